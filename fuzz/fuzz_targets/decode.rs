@@ -0,0 +1,8 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|input: (usize, &[u8])| {
+    let (split, data) = input;
+    redis_rs::fuzz::decode(data, split);
+});
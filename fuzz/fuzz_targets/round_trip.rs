@@ -0,0 +1,9 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use redis_rs::{Protocol, RedisType};
+
+fuzz_target!(|input: (RedisType, Protocol)| {
+    let (value, protocol) = input;
+    redis_rs::fuzz::round_trip(&value, protocol);
+});
@@ -0,0 +1,143 @@
+// #[derive(ToRedisHash, FromRedisHash)] for structs with named fields, to store them as hashes with
+// redis_rs::client::Client's hset_struct and hget_struct
+// Each field is a hash field of the same name, unless it has #[redis(rename = "name")], or is left
+// out with #[redis(skip)]. Field types have to implement redis_rs::client::HashValue.
+
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::quote;
+use syn::{parse_macro_input, Data, DeriveInput, Fields, Ident, LitStr};
+
+#[proc_macro_derive(ToRedisHash, attributes(redis))]
+pub fn derive_to_redis_hash(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    to_redis_hash(&input)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+#[proc_macro_derive(FromRedisHash, attributes(redis))]
+pub fn derive_from_redis_hash(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    from_redis_hash(&input)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+// A struct field and what it's called in the hash
+struct Field {
+    ident: Ident,
+    name: String,
+    skip: bool,
+}
+
+fn to_redis_hash(input: &DeriveInput) -> syn::Result<TokenStream2> {
+    let fields = fields(input, "ToRedisHash")?;
+    let (idents, names): (Vec<_>, Vec<_>) = fields
+        .iter()
+        .filter(|field| !field.skip)
+        .map(|field| (&field.ident, &field.name))
+        .unzip();
+
+    let ty = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    Ok(quote! {
+        impl #impl_generics ::redis_rs::client::ToRedisHash for #ty #ty_generics #where_clause {
+            fn to_redis_hash(&self) -> ::std::vec::Vec<(::std::vec::Vec<u8>, ::std::vec::Vec<u8>)> {
+                let mut fields = ::std::vec::Vec::new();
+                #(
+                    if let ::std::option::Option::Some(value) =
+                        ::redis_rs::client::HashValue::to_hash_value(&self.#idents)
+                    {
+                        fields.push((#names.as_bytes().to_vec(), value));
+                    }
+                )*
+                fields
+            }
+        }
+    })
+}
+
+fn from_redis_hash(input: &DeriveInput) -> syn::Result<TokenStream2> {
+    let fields = fields(input, "FromRedisHash")?;
+    let values = fields.iter().map(|Field { ident, name, skip }| {
+        if *skip {
+            return quote! { #ident: ::std::default::Default::default() };
+        }
+        quote! {
+            #ident: {
+                let value = fields.remove(#name.as_bytes());
+                match ::redis_rs::client::HashValue::from_hash_value(value.as_deref()) {
+                    ::std::option::Option::Some(value) => value,
+                    ::std::option::Option::None => {
+                        return ::std::result::Result::Err(
+                            ::redis_rs::client::RedisError::InvalidField {
+                                field: ::std::string::String::from(#name),
+                                value,
+                            },
+                        )
+                    }
+                }
+            }
+        }
+    });
+
+    let ty = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    Ok(quote! {
+        impl #impl_generics ::redis_rs::client::FromRedisHash for #ty #ty_generics #where_clause {
+            fn from_redis_hash(
+                fields: &mut ::std::collections::HashMap<::std::vec::Vec<u8>, ::std::vec::Vec<u8>>,
+            ) -> ::std::result::Result<Self, ::redis_rs::client::RedisError> {
+                ::std::result::Result::Ok(#ty { #(#values,)* })
+            }
+        }
+    })
+}
+
+fn fields(input: &DeriveInput, derive: &str) -> syn::Result<Vec<Field>> {
+    let named = match &input.data {
+        Data::Struct(data) => match &data.fields {
+            Fields::Named(fields) => &fields.named,
+            _ => return Err(named_only(input, derive)),
+        },
+        _ => return Err(named_only(input, derive)),
+    };
+
+    let mut fields = Vec::new();
+    for field in named {
+        let ident = field.ident.clone().expect("named fields have names");
+        let mut name = ident.to_string();
+        // Raw identifiers, such as r#type, are stored without the r#
+        if let Some(stripped) = name.strip_prefix("r#") {
+            name = stripped.to_string();
+        }
+        let mut skip = false;
+        for attr in field
+            .attrs
+            .iter()
+            .filter(|attr| attr.path().is_ident("redis"))
+        {
+            attr.parse_nested_meta(|meta| {
+                if meta.path.is_ident("rename") {
+                    name = meta.value()?.parse::<LitStr>()?.value();
+                    Ok(())
+                } else if meta.path.is_ident("skip") {
+                    skip = true;
+                    Ok(())
+                } else {
+                    Err(meta.error("expected rename = \"...\" or skip"))
+                }
+            })?;
+        }
+        fields.push(Field { ident, name, skip });
+    }
+    Ok(fields)
+}
+
+fn named_only(input: &DeriveInput, derive: &str) -> syn::Error {
+    syn::Error::new_spanned(
+        &input.ident,
+        format!("{derive} can only be derived for structs with named fields"),
+    )
+}
@@ -0,0 +1,194 @@
+// Reading the append only file, shared by the server and redis-rs-check
+use crate::{RedisType, RedisTypeParseError};
+use std::fmt::Display;
+use std::fs::OpenOptions;
+use std::path::Path;
+
+// Where an append only file stops making sense, everything before offset is still usable
+#[derive(Debug, PartialEq)]
+pub struct ParseError {
+    pub offset: usize,
+    pub message: String,
+}
+
+impl Display for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} at offset {}", self.message, self.offset)
+    }
+}
+
+// A command's name and arguments, as they were written
+pub type Argv = Vec<Vec<u8>>;
+
+// Parse commands from data, returning them and how many bytes they took up
+// A command cut off at the end is not an error, it just isn't included
+pub fn parse(data: &[u8]) -> Result<(Vec<Argv>, usize), ParseError> {
+    let mut commands = Vec::new();
+    let mut consumed = 0;
+    let error = |offset, message| Err(ParseError { offset, message });
+
+    while consumed < data.len() {
+        let (value, len) = match RedisType::parse_prefix(&data[consumed..]) {
+            Ok(parsed) => parsed,
+            Err(RedisTypeParseError::Incomplete) => break,
+            Err(e) => return error(consumed, format!("Bad file format ({e:?})")),
+        };
+
+        let argv = match value {
+            RedisType::Array { value } if !value.is_empty() => value
+                .into_iter()
+                .map(|arg| match arg {
+                    RedisType::String { value } => Some(value.into_bytes()),
+                    RedisType::Bulk { value } => Some(value.to_vec()),
+                    _ => None,
+                })
+                .collect::<Option<Vec<_>>>(),
+            _ => None,
+        };
+        match argv {
+            Some(argv) => commands.push(argv),
+            None => return error(consumed, String::from("Expected an array of bulk strings")),
+        }
+        consumed += len;
+    }
+
+    Ok((commands, consumed))
+}
+
+// What's wrong with the end of an append only file
+#[derive(Debug, PartialEq)]
+pub enum Damage {
+    // The last command is cut off, as by a crash part way through writing it
+    Truncated,
+    // Bytes that aren't a command with none after them, such as the zeroes some filesystems leave
+    // after a crash, so only they are lost by truncating
+    CorruptTail(ParseError),
+    // Bytes that aren't a command with more commands after them (the first starting at
+    // resumes_at), which truncating would lose too
+    Corrupt {
+        error: ParseError,
+        resumes_at: usize,
+    },
+}
+
+impl Damage {
+    // Whether truncating to the last valid command only loses what couldn't be read anyway
+    pub fn is_tail(&self) -> bool {
+        !matches!(self, Damage::Corrupt { .. })
+    }
+}
+
+impl Display for Damage {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Damage::Truncated => write!(f, "Ends part way through a command"),
+            Damage::CorruptTail(error) => write!(f, "{error}, with no commands after it"),
+            Damage::Corrupt { error, resumes_at } => write!(
+                f,
+                "{error}, with more commands from offset {resumes_at} that truncating would lose"
+            ),
+        }
+    }
+}
+
+// An append only file's commands, as far as they can be read
+#[derive(Debug, PartialEq)]
+pub struct Check {
+    pub commands: Vec<Argv>,
+    // Bytes taken up by the commands, everything after them is damaged
+    pub valid: usize,
+    pub len: usize,
+    pub damage: Option<Damage>,
+}
+
+// Read the commands in data, and whatever is wrong after the last one that can be read
+pub fn check(data: &[u8]) -> Check {
+    let (commands, valid, damage) = match parse(data) {
+        Ok((commands, valid)) if valid == data.len() => (commands, valid, None),
+        Ok((commands, valid)) => (commands, valid, Some(Damage::Truncated)),
+        Err(error) => {
+            let (commands, _) = parse(&data[..error.offset]).unwrap_or_default();
+            let valid = error.offset;
+            let damage = match resumes_at(data, valid) {
+                Some(resumes_at) => Damage::Corrupt { error, resumes_at },
+                None => Damage::CorruptTail(error),
+            };
+            (commands, valid, Some(damage))
+        }
+    };
+    Check {
+        commands,
+        valid,
+        len: data.len(),
+        damage,
+    }
+}
+
+// Where the first complete command after the damage at offset starts, if there is one
+fn resumes_at(data: &[u8], offset: usize) -> Option<usize> {
+    (offset + 1..data.len()).find(|&start| {
+        data[start] == b'*'
+            && matches!(
+                RedisType::parse_prefix(&data[start..]),
+                Ok((RedisType::Array { value }, _)) if !value.is_empty()
+            )
+    })
+}
+
+// Truncate the append only file at path to the commands check found in it, returning how many
+// bytes were cut off
+pub fn repair(path: &Path, check: &Check) -> std::io::Result<usize> {
+    let file = OpenOptions::new().write(true).open(path)?;
+    file.set_len(check.valid as u64)?;
+    file.sync_all()?;
+    Ok(check.len - check.valid)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_error_offset() {
+        let mut data = b"*1\r\n$4\r\nPING\r\n".to_vec();
+        let valid = data.len();
+
+        data.extend_from_slice(b":1\r\n*1\r\n$4\r\nPING\r\n");
+        assert_eq!(parse(&data).unwrap_err().offset, valid);
+
+        // Arguments are binary safe
+        data.truncate(valid);
+        data.extend_from_slice(b"*1\r\n$4\r\nP\xFFNG\r\n");
+        let (commands, _) = parse(&data).unwrap();
+        assert_eq!(commands[1], vec![b"P\xFFNG".to_vec()]);
+    }
+
+    #[test]
+    fn test_check() {
+        let command = b"*2\r\n$4\r\nINCR\r\n$1\r\nn\r\n";
+        let mut data = command.repeat(2);
+        let valid = data.len();
+        assert_eq!(check(&data).damage, None);
+
+        data.extend_from_slice(&command[..10]);
+        let checked = check(&data);
+        assert_eq!(checked.damage, Some(Damage::Truncated));
+        assert_eq!((checked.commands.len(), checked.valid), (2, valid));
+
+        // Zeroes left by a crash
+        data.truncate(valid);
+        data.extend_from_slice(&[0; 64]);
+        let checked = check(&data);
+        assert!(matches!(checked.damage, Some(Damage::CorruptTail(_))));
+        assert!(checked.damage.unwrap().is_tail());
+        assert_eq!((checked.commands.len(), checked.valid), (2, valid));
+
+        // Commands after the damage would be lost
+        data.extend_from_slice(command);
+        let checked = check(&data);
+        assert!(matches!(
+            checked.damage,
+            Some(Damage::Corrupt { resumes_at, .. }) if resumes_at == valid + 64
+        ));
+    }
+}
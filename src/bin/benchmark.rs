@@ -0,0 +1,318 @@
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::process::ExitCode;
+use std::time::{Duration, Instant};
+
+use clap::Parser;
+use redis_rs::value::Value;
+use redis_rs::{Protocol, RedisType, RedisTypeParseError};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+const TESTS: [&str; 5] = ["ping", "set", "get", "incr", "mset"];
+
+// Keys MSET sets in each command, as redis-benchmark does
+const MSET_KEYS: usize = 10;
+
+// Load a server with many clients at once and report throughput and latency, along the lines of
+// redis-benchmark
+#[derive(Parser, Debug)]
+#[command(
+    version,
+    about = "Measure how fast a redis-rs (or Redis) server answers commands"
+)]
+struct Args {
+    /// Server to connect to
+    #[arg(long, default_value = "127.0.0.1")]
+    host: String,
+
+    /// Port the server is listening on
+    #[arg(short, long, default_value_t = 6379)]
+    port: u16,
+
+    /// Password to AUTH with, if the server requires one
+    #[arg(short = 'a', long)]
+    password: Option<String>,
+
+    /// Connections to run commands on in parallel
+    #[arg(short, long, default_value_t = 50)]
+    clients: usize,
+
+    /// Commands to send for each test
+    #[arg(short = 'n', long, default_value_t = 100000)]
+    requests: usize,
+
+    /// Commands each client sends before waiting for the replies
+    #[arg(short = 'P', long, default_value_t = 1)]
+    pipeline: usize,
+
+    /// Keys are picked at random from this many, 1 uses the same key for every command
+    #[arg(short = 'r', long, default_value_t = 10000)]
+    keyspace: u64,
+
+    /// Size of the values written by SET and MSET, in bytes
+    #[arg(short, long, default_value_t = 3)]
+    data_size: usize,
+
+    /// Comma separated tests to run, out of ping, set, get, incr and mset
+    #[arg(short, long, default_value = "ping,set,get,incr,mset")]
+    tests: String,
+
+    /// Only print one line per test
+    #[arg(short, long)]
+    quiet: bool,
+}
+
+// What one client saw: how long each command took to get its reply, and how many of them
+// were errors
+#[derive(Default)]
+struct Results {
+    latencies: Vec<Duration>,
+    errors: usize,
+}
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    let args = Args::parse();
+
+    let tests = args
+        .tests
+        .split(',')
+        .map(|test| test.trim().to_ascii_lowercase())
+        .collect::<Vec<_>>();
+    if let Some(test) = tests.iter().find(|test| !TESTS.contains(&test.as_str())) {
+        eprintln!(
+            "Unknown test '{test}', expected some of {}",
+            TESTS.join(",")
+        );
+        return ExitCode::FAILURE;
+    }
+    if args.clients == 0 || args.pipeline == 0 || args.keyspace == 0 {
+        eprintln!("--clients, --pipeline and --keyspace must be at least 1");
+        return ExitCode::FAILURE;
+    }
+
+    for test in &tests {
+        match run(&args, test).await {
+            Ok((results, elapsed)) => report(&args, test, results, elapsed),
+            Err(e) => {
+                eprintln!("{test} against {}:{} failed: {e}", args.host, args.port);
+                return ExitCode::FAILURE;
+            }
+        }
+    }
+
+    ExitCode::SUCCESS
+}
+
+// Run a single test, splitting its requests across the clients
+async fn run(args: &Args, test: &str) -> std::io::Result<(Results, Duration)> {
+    let mut streams = Vec::new();
+    for _ in 0..args.clients {
+        let mut stream = TcpStream::connect((args.host.as_str(), args.port)).await?;
+        stream.set_nodelay(true)?;
+        if let Some(password) = &args.password {
+            let auth = command(&["AUTH", password]);
+            let mut input = Vec::new();
+            let results = round_trip(&mut stream, &auth, 1, &mut input).await?;
+            if results.errors > 0 {
+                return Err(std::io::Error::other("AUTH failed"));
+            }
+        }
+        streams.push(stream);
+    }
+
+    let start = Instant::now();
+    let mut tasks = Vec::new();
+    for (i, stream) in streams.into_iter().enumerate() {
+        // Spread the remainder over the first few clients
+        let requests = args.requests / args.clients + usize::from(i < args.requests % args.clients);
+        let generator = Generator::new(test, args.keyspace, args.data_size);
+        tasks.push(tokio::spawn(client(
+            stream,
+            generator,
+            requests,
+            args.pipeline,
+        )));
+    }
+
+    let mut results = Results::default();
+    for task in tasks {
+        let client = task.await.expect("client panicked")?;
+        results.latencies.extend(client.latencies);
+        results.errors += client.errors;
+    }
+    Ok((results, start.elapsed()))
+}
+
+async fn client(
+    mut stream: TcpStream,
+    mut generator: Generator,
+    requests: usize,
+    pipeline: usize,
+) -> std::io::Result<Results> {
+    let mut results = Results::default();
+    let mut input = Vec::new();
+    let mut sent = 0;
+
+    while sent < requests {
+        let batch = pipeline.min(requests - sent);
+        let mut output = Vec::new();
+        for _ in 0..batch {
+            output.extend_from_slice(&generator.next());
+        }
+
+        let replies = round_trip(&mut stream, &output, batch, &mut input).await?;
+        results.latencies.extend(replies.latencies);
+        results.errors += replies.errors;
+        sent += batch;
+    }
+
+    Ok(results)
+}
+
+// Send output and wait for count replies, timing each from when the commands were sent
+async fn round_trip(
+    stream: &mut TcpStream,
+    output: &[u8],
+    count: usize,
+    input: &mut Vec<u8>,
+) -> std::io::Result<Results> {
+    let mut results = Results::default();
+    let start = Instant::now();
+    stream.write_all(output).await?;
+
+    while results.latencies.len() < count {
+        // Replies to the commands sent here are all ASCII, so lengths in text match input
+        let text = String::from_utf8_lossy(input).into_owned();
+        let mut consumed = 0;
+        loop {
+            match RedisType::parse_prefix(&text[consumed..]) {
+                Ok((reply, len)) => {
+                    if matches!(reply, RedisType::Error { .. }) {
+                        results.errors += 1;
+                    }
+                    results.latencies.push(start.elapsed());
+                    consumed += len;
+                    if results.latencies.len() == count {
+                        break;
+                    }
+                }
+                Err(RedisTypeParseError::Incomplete) | Err(RedisTypeParseError::MissingPrefix) => {
+                    break
+                }
+                Err(e) => {
+                    return Err(std::io::Error::other(format!("invalid reply: {e:?}")));
+                }
+            }
+        }
+        input.drain(..consumed);
+
+        if results.latencies.len() < count {
+            input.reserve(16 * 1024);
+            if stream.read_buf(input).await? == 0 {
+                return Err(std::io::ErrorKind::UnexpectedEof.into());
+            }
+        }
+    }
+
+    Ok(results)
+}
+
+fn report(args: &Args, test: &str, mut results: Results, elapsed: Duration) {
+    results.latencies.sort();
+    let percentile = |p: f64| {
+        let index = ((results.latencies.len() as f64 - 1.0) * p / 100.0).round() as usize;
+        results
+            .latencies
+            .get(index)
+            .map_or(0.0, |latency| latency.as_secs_f64() * 1000.0)
+    };
+    let throughput = results.latencies.len() as f64 / elapsed.as_secs_f64();
+    let name = test.to_ascii_uppercase();
+
+    if args.quiet {
+        println!(
+            "{name}: {throughput:.2} requests per second, p50={:.3} msec",
+            percentile(50.0)
+        );
+        return;
+    }
+
+    println!("====== {name} ======");
+    println!(
+        "  {} requests completed in {:.2} seconds",
+        results.latencies.len(),
+        elapsed.as_secs_f64()
+    );
+    println!("  {} parallel clients", args.clients);
+    println!("  {} bytes payload", args.data_size);
+    println!("  pipeline of {}", args.pipeline);
+    if results.errors > 0 {
+        println!("  {} error replies", results.errors);
+    }
+    println!();
+    println!("Latency by percentile (msec):");
+    for p in [50.0, 95.0, 99.0, 99.9, 100.0] {
+        println!("  {p:>5.1}% <= {:.3}", percentile(p));
+    }
+    println!();
+    println!("Throughput: {throughput:.2} requests per second");
+    println!();
+}
+
+// Builds the commands for a test, each with its own random keys
+struct Generator {
+    test: String,
+    keyspace: u64,
+    value: String,
+    random: u64,
+}
+
+impl Generator {
+    fn new(test: &str, keyspace: u64, data_size: usize) -> Generator {
+        Generator {
+            test: test.to_owned(),
+            keyspace,
+            value: "x".repeat(data_size),
+            random: RandomState::new().build_hasher().finish() | 1,
+        }
+    }
+
+    fn key(&mut self, prefix: &str) -> String {
+        self.random ^= self.random << 13;
+        self.random ^= self.random >> 7;
+        self.random ^= self.random << 17;
+        format!("{prefix}:{:012}", self.random % self.keyspace)
+    }
+
+    fn next(&mut self) -> Vec<u8> {
+        match self.test.as_str() {
+            "ping" => command(&["PING"]),
+            "set" => {
+                let key = self.key("key");
+                command(&["SET", &key, &self.value])
+            }
+            "get" => command(&["GET", &self.key("key")]),
+            "incr" => command(&["INCR", &self.key("counter")]),
+            "mset" => {
+                let keys = (0..MSET_KEYS).map(|_| self.key("key")).collect::<Vec<_>>();
+                let mut args = vec!["MSET"];
+                for key in &keys {
+                    args.push(key);
+                    args.push(&self.value);
+                }
+                command(&args)
+            }
+            _ => unreachable!("tests are checked before they're run"),
+        }
+    }
+}
+
+fn command(args: &[&str]) -> Vec<u8> {
+    let args = args
+        .iter()
+        .map(|arg| RedisType::from(Value::from(*arg)))
+        .collect::<Vec<_>>();
+    RedisType::from(args).encode(Protocol::Resp2)
+}
@@ -0,0 +1,159 @@
+// --bigkeys and --memkeys: scan every key to find the largest of each type, and how much there is
+// of each type overall, printed the way redis-cli does
+
+use std::collections::BTreeMap;
+use std::io::{stdout, Write};
+use std::process::ExitCode;
+
+use redis_rs::RedisType;
+
+use crate::connection::Connection;
+use crate::format::quote;
+use crate::scan::Scan;
+
+// The command that gives a key's size for each type, and what it counts
+const SIZES: [(&str, &str, &str); 6] = [
+    ("string", "STRLEN", "bytes"),
+    ("list", "LLEN", "items"),
+    ("set", "SCARD", "members"),
+    ("zset", "ZCARD", "members"),
+    ("hash", "HLEN", "fields"),
+    ("stream", "XLEN", "entries"),
+];
+
+#[derive(Default)]
+struct Totals {
+    keys: u64,
+    size: u64,
+    biggest: Option<(Vec<u8>, u64)>,
+}
+
+// With memory, sizes are what MEMORY USAGE reports rather than the length of each value
+pub async fn run(connection: &mut Connection, memory: bool) -> std::io::Result<ExitCode> {
+    let mut out = stdout();
+    let total = match query(connection, vec!["DBSIZE"]).await? {
+        RedisType::Integer { value } => value.max(0) as u64,
+        reply => return Err(unexpected("DBSIZE", &reply)),
+    };
+
+    writeln!(out)?;
+    writeln!(
+        out,
+        "# Scanning the entire keyspace to find biggest keys as well as"
+    )?;
+    writeln!(out, "# average sizes per key type.")?;
+    writeln!(out)?;
+
+    let mut types = BTreeMap::<String, Totals>::new();
+    let mut sampled = 0;
+    let mut key_bytes = 0;
+    let mut scan = Scan::new(None, None);
+    while let Some(keys) = scan.next(connection).await? {
+        for key in keys {
+            let key_type = match query(connection, vec![b"TYPE".to_vec(), key.clone()]).await? {
+                RedisType::String { value } => value,
+                reply => return Err(unexpected("TYPE", &reply)),
+            };
+            let (command, unit) = match SIZES.iter().find(|(name, _, _)| *name == key_type) {
+                _ if memory => ("MEMORY USAGE", "bytes"),
+                Some((_, command, unit)) => (*command, *unit),
+                // Deleted since the scan found it, or a type there's no way to measure
+                None => continue,
+            };
+
+            let mut args = command
+                .split(' ')
+                .map(|word| word.as_bytes().to_vec())
+                .collect::<Vec<_>>();
+            args.push(key.clone());
+            let size = match query(connection, args).await? {
+                RedisType::Integer { value } => value.max(0) as u64,
+                // Deleted in the meantime
+                RedisType::NullString | RedisType::NullArray => continue,
+                reply => return Err(unexpected(command, &reply)),
+            };
+
+            sampled += 1;
+            key_bytes += key.len() as u64;
+            let totals = types.entry(key_type.clone()).or_default();
+            totals.keys += 1;
+            totals.size += size;
+            if totals
+                .biggest
+                .as_ref()
+                .is_none_or(|(_, biggest)| size > *biggest)
+            {
+                let progress = 100.0 * sampled as f64 / total.max(1) as f64;
+                writeln!(
+                    out,
+                    "[{progress:05.2}%] Biggest {key_type:<6} found so far '{}' with {size} {unit}",
+                    quote(&key)
+                )?;
+                totals.biggest = Some((key, size));
+            }
+        }
+    }
+
+    writeln!(out)?;
+    writeln!(out, "-------- summary -------")?;
+    writeln!(out)?;
+    writeln!(out, "Sampled {sampled} keys in the keyspace!")?;
+    writeln!(
+        out,
+        "Total key length in bytes is {key_bytes} (avg len {:.2})",
+        key_bytes as f64 / sampled.max(1) as f64
+    )?;
+    writeln!(out)?;
+
+    for (key_type, totals) in &types {
+        if let Some((key, size)) = &totals.biggest {
+            writeln!(
+                out,
+                "Biggest {key_type:>6} found '{}' has {size} {}",
+                quote(key),
+                unit(key_type, memory)
+            )?;
+        }
+    }
+    writeln!(out)?;
+
+    for (key_type, _, _) in SIZES {
+        let totals = types.remove(key_type).unwrap_or_default();
+        writeln!(
+            out,
+            "{} {key_type}s with {} {} ({:05.2}% of keys, avg size {:.2})",
+            totals.keys,
+            totals.size,
+            unit(key_type, memory),
+            100.0 * totals.keys as f64 / sampled.max(1) as f64,
+            totals.size as f64 / totals.keys.max(1) as f64
+        )?;
+    }
+
+    Ok(ExitCode::SUCCESS)
+}
+
+fn unit(key_type: &str, memory: bool) -> &'static str {
+    match SIZES.iter().find(|(name, _, _)| *name == key_type) {
+        Some((_, _, unit)) if !memory => unit,
+        _ => "bytes",
+    }
+}
+
+// Send a command, treating an error reply as a failure
+async fn query(
+    connection: &mut Connection,
+    command: Vec<impl Into<Vec<u8>>>,
+) -> std::io::Result<RedisType> {
+    match connection.send(command).await? {
+        RedisType::Error { value } => Err(std::io::Error::other(value)),
+        reply => Ok(reply),
+    }
+}
+
+fn unexpected(command: &str, reply: &RedisType) -> std::io::Error {
+    std::io::Error::new(
+        std::io::ErrorKind::InvalidData,
+        format!("unexpected reply to {command}: {reply:?}"),
+    )
+}
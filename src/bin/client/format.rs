@@ -0,0 +1,225 @@
+// How replies are printed, following redis-cli: quoted and labelled at a terminal, or just the
+// values, one per line, when the output is going somewhere else (such as a script)
+// --json and --csv print each reply on a single line in those formats instead.
+
+use redis_rs::RedisType;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Format {
+    Human,
+    Raw,
+    Json,
+    Csv,
+}
+
+impl Format {
+    // A reply as it's printed, ending with a newline
+    pub fn reply(&self, reply: &RedisType) -> Vec<u8> {
+        match self {
+            Format::Human => human(reply).into_bytes(),
+            Format::Raw => raw(reply),
+            Format::Json => format!("{}\n", json(reply)).into_bytes(),
+            Format::Csv => format!("{}\n", csv(reply)).into_bytes(),
+        }
+    }
+}
+
+fn human(reply: &RedisType) -> String {
+    let mut out = String::new();
+    write_human(&mut out, reply, 0);
+    out
+}
+
+// Elements after the first line of an array are indented to line up under it
+fn write_human(out: &mut String, reply: &RedisType, indent: usize) {
+    match reply {
+        RedisType::NullString | RedisType::NullArray => out.push_str("(nil)\n"),
+        RedisType::String { value } => out.push_str(&format!("{}\n", quote(value.as_bytes()))),
+        RedisType::Bulk { value } => out.push_str(&format!("{}\n", quote(value))),
+        RedisType::Error { value } => out.push_str(&format!("(error) {value}\n")),
+        RedisType::Integer { value } => out.push_str(&format!("(integer) {value}\n")),
+        RedisType::Double { value } => out.push_str(&format!("(double) {value}\n")),
+        RedisType::Boolean { value } => out.push_str(&format!("({value})\n")),
+        RedisType::Array { value } | RedisType::Push { value } => {
+            let elements = value.iter().map(|element| (None, element)).collect();
+            write_elements(out, elements, ')', "(empty array)", indent)
+        }
+        RedisType::Set { value } => {
+            let elements = value.iter().map(|element| (None, element)).collect();
+            write_elements(out, elements, '~', "(empty set)", indent)
+        }
+        // Each key and value on one line, as redis-cli shows RESP3 maps
+        RedisType::Map { value } => {
+            let elements = value.iter().map(|(k, v)| (Some(k), v)).collect();
+            write_elements(out, elements, '#', "(empty hash)", indent)
+        }
+    }
+}
+
+// Numbered with marker after the number, which says what kind of collection they're in
+fn write_elements(
+    out: &mut String,
+    elements: Vec<(Option<&RedisType>, &RedisType)>,
+    marker: char,
+    empty: &str,
+    indent: usize,
+) {
+    if elements.is_empty() {
+        out.push_str(empty);
+        out.push('\n');
+        return;
+    }
+
+    let width = elements.len().to_string().len();
+    for (i, (key, element)) in elements.into_iter().enumerate() {
+        if i > 0 {
+            out.push_str(&" ".repeat(indent));
+        }
+        out.push_str(&format!("{:>width$}{marker} ", i + 1));
+        if let Some(key) = key {
+            let mut line = String::new();
+            write_human(&mut line, key, indent + width + 2);
+            out.push_str(line.trim_end());
+            out.push_str(" => ");
+        }
+        write_human(out, element, indent + width + 2);
+    }
+}
+
+// Double quoted, with anything that isn't printable escaped
+pub fn quote(value: &[u8]) -> String {
+    let mut quoted = String::from("\"");
+    for byte in value {
+        match byte {
+            b'\\' => quoted.push_str("\\\\"),
+            b'"' => quoted.push_str("\\\""),
+            b'\n' => quoted.push_str("\\n"),
+            b'\r' => quoted.push_str("\\r"),
+            b'\t' => quoted.push_str("\\t"),
+            byte if byte.is_ascii_graphic() || *byte == b' ' => quoted.push(*byte as char),
+            byte => quoted.push_str(&format!("\\x{byte:02x}")),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+fn raw(reply: &RedisType) -> Vec<u8> {
+    let mut out = Vec::new();
+    write_raw(&mut out, reply);
+    out.push(b'\n');
+    out
+}
+
+fn write_raw(out: &mut Vec<u8>, reply: &RedisType) {
+    match reply {
+        RedisType::NullString | RedisType::NullArray => {}
+        RedisType::String { value } | RedisType::Error { value } => {
+            out.extend_from_slice(value.as_bytes())
+        }
+        RedisType::Bulk { value } => out.extend_from_slice(value),
+        RedisType::Integer { value } => out.extend_from_slice(value.to_string().as_bytes()),
+        RedisType::Double { value } => out.extend_from_slice(value.as_bytes()),
+        RedisType::Boolean { value } => out.extend_from_slice(format!("({value})").as_bytes()),
+        RedisType::Array { value } | RedisType::Push { value } | RedisType::Set { value } => {
+            for (i, element) in value.iter().enumerate() {
+                if i > 0 {
+                    out.push(b'\n');
+                }
+                write_raw(out, element);
+            }
+        }
+        RedisType::Map { value } => {
+            for (i, (k, v)) in value.iter().enumerate() {
+                if i > 0 {
+                    out.push(b'\n');
+                }
+                write_raw(out, k);
+                out.push(b'\n');
+                write_raw(out, v);
+            }
+        }
+    }
+}
+
+// Nil is null and errors are objects with an error field, so they can be told apart from strings
+fn json(reply: &RedisType) -> String {
+    match reply {
+        RedisType::NullString | RedisType::NullArray => String::from("null"),
+        RedisType::String { value } => json_string(value.as_bytes()),
+        RedisType::Bulk { value } => json_string(value),
+        RedisType::Error { value } => format!("{{\"error\":{}}}", json_string(value.as_bytes())),
+        RedisType::Integer { value } => value.to_string(),
+        // JSON has no infinity or NaN
+        RedisType::Double { value } => match value.parse::<f64>() {
+            Ok(number) if number.is_finite() => value.clone(),
+            _ => json_string(value.as_bytes()),
+        },
+        RedisType::Boolean { value } => value.to_string(),
+        RedisType::Array { value } | RedisType::Push { value } | RedisType::Set { value } => {
+            let elements = value.iter().map(json).collect::<Vec<_>>();
+            format!("[{}]", elements.join(","))
+        }
+        // Object keys have to be strings, so they're written as they would be with --raw
+        RedisType::Map { value } => {
+            let fields = value
+                .iter()
+                .map(|(k, v)| {
+                    let mut key = Vec::new();
+                    write_raw(&mut key, k);
+                    let key = json_string(&key);
+                    format!("{key}:{}", json(v))
+                })
+                .collect::<Vec<_>>();
+            format!("{{{}}}", fields.join(","))
+        }
+    }
+}
+
+// Bytes that aren't valid UTF-8 are each escaped as the character with that code, as redis-cli
+// does
+fn json_string(value: &[u8]) -> String {
+    let text = std::str::from_utf8(value);
+    let chars: Vec<char> = match text {
+        Ok(text) => text.chars().collect(),
+        Err(_) => value.iter().map(|byte| *byte as char).collect(),
+    };
+
+    let mut quoted = String::from("\"");
+    for c in chars {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            '\t' => quoted.push_str("\\t"),
+            c if c.is_control() || (text.is_err() && !c.is_ascii()) => {
+                quoted.push_str(&format!("\\u{:04x}", c as u32))
+            }
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+// Elements separated by commas, with strings quoted, nil as NULL and errors starting with ERROR
+fn csv(reply: &RedisType) -> String {
+    match reply {
+        RedisType::NullString | RedisType::NullArray => String::from("NULL"),
+        RedisType::String { value } => quote(value.as_bytes()),
+        RedisType::Bulk { value } => quote(value),
+        RedisType::Error { value } => format!("ERROR,{}", quote(value.as_bytes())),
+        RedisType::Integer { value } => value.to_string(),
+        RedisType::Double { value } => value.clone(),
+        RedisType::Boolean { value } => value.to_string(),
+        RedisType::Array { value } | RedisType::Push { value } | RedisType::Set { value } => {
+            value.iter().map(csv).collect::<Vec<_>>().join(",")
+        }
+        RedisType::Map { value } => value
+            .iter()
+            .map(|(k, v)| format!("{},{}", csv(k), csv(v)))
+            .collect::<Vec<_>>()
+            .join(","),
+    }
+}
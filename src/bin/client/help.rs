@@ -0,0 +1,108 @@
+// HELP at the prompt: how to use a command, or every command in a @group, from the commands the
+// client was built with, laid out as redis-cli does
+
+use redis_rs::server::CommandDocs;
+
+// With no topic, what can be asked for
+pub fn overview() -> String {
+    format!(
+        "redis-rs client {}\n\
+         To get help about commands type:\n      \
+         \"help @<group>\" to get a list of commands in <group>\n      \
+         \"help <command>\" for help on <command>\n      \
+         \"help <tab>\" to get a list of possible help topics\n      \
+         \"quit\" to exit\n",
+        env!("CARGO_PKG_VERSION")
+    )
+}
+
+// Help for a command (or some of a container command's subcommands, such as CLIENT KILL) with its
+// description, or for each command in a group given as @group, with colours for a terminal
+pub fn topic(commands: &[CommandDocs], topic: &[String], color: bool) -> String {
+    let Some(first) = topic.first() else {
+        return overview();
+    };
+
+    let mut out = String::new();
+    if let Some(group) = first.strip_prefix('@') {
+        for command in commands {
+            if command.group.eq_ignore_ascii_case(group) {
+                write_command(&mut out, command, command.usage.iter(), color);
+            }
+        }
+    } else if let Some(command) = commands
+        .iter()
+        .find(|command| command.name.eq_ignore_ascii_case(first))
+    {
+        // The forms that start with the subcommand that was asked about, if any
+        let usage = command.usage.iter().filter(|usage| {
+            let mut words = usage.split_whitespace();
+            topic.iter().all(|word| {
+                words
+                    .next()
+                    .is_some_and(|name| name.eq_ignore_ascii_case(word))
+            })
+        });
+        write_command(&mut out, command, usage, color);
+        if !out.is_empty() && !command.description.is_empty() {
+            out.push('\n');
+            for line in command.description.lines() {
+                out.push_str(format!("  {line}").trim_end());
+                out.push('\n');
+            }
+        }
+    }
+
+    if out.is_empty() {
+        out = format!("No help for '{}'\n", topic.join(" "));
+    }
+    out
+}
+
+// The name in bold and its arguments in grey, then what it does, as redis-cli shows it
+fn write_command<'a>(
+    out: &mut String,
+    command: &CommandDocs,
+    usage: impl Iterator<Item = &'a String>,
+    color: bool,
+) {
+    let paint = |code: &str, text: &str| {
+        if color {
+            format!("\x1b[{code}m{text}\x1b[0m")
+        } else {
+            text.to_string()
+        }
+    };
+
+    let start = out.len();
+    out.push('\n');
+    // The name is the first word, or the first two for a container command such as CLIENT KILL
+    let name_words = if command.subcommands.is_empty() { 1 } else { 2 };
+    for line in usage {
+        let mut words = line.splitn(name_words + 1, ' ').collect::<Vec<_>>();
+        let args = if words.len() > name_words {
+            words.pop().unwrap()
+        } else {
+            ""
+        };
+        let name = paint("1", &words.join(" "));
+        if args.is_empty() {
+            out.push_str(&format!("  {name}\n"));
+        } else {
+            out.push_str(&format!("  {name} {}\n", paint("90", args)));
+        }
+    }
+    // None of the forms matched
+    if out.len() == start + 1 {
+        out.truncate(start);
+        return;
+    }
+
+    out.push_str(&format!(
+        "  {} {}\n",
+        paint("33", "summary:"),
+        command.summary
+    ));
+    out.push_str(&format!("  {} {}\n", paint("33", "since:"), command.since));
+    out.push_str(&format!("  {} {}\n", paint("33", "group:"), command.group));
+}
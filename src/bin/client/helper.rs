@@ -0,0 +1,287 @@
+// Tab completion and argument hints at the prompt, from the commands the server knows
+
+use std::borrow::Cow;
+
+use redis_rs::server::{command_docs, CommandDocs};
+use rustyline::completion::{Completer, Pair};
+use rustyline::highlight::Highlighter;
+use rustyline::hint::{Hint, Hinter};
+use rustyline::validate::Validator;
+use rustyline::{Context, Helper};
+
+use crate::input;
+
+pub struct ReplHelper {
+    pub commands: Vec<CommandDocs>,
+    // Each group of commands as @group, for completing HELP
+    groups: Vec<String>,
+    // Set while reading the rest of a command that carries on over more lines, when the line
+    // being typed doesn't start with a command name
+    pub continuing: bool,
+}
+
+impl ReplHelper {
+    pub fn new() -> Self {
+        let commands = command_docs();
+        let mut groups = commands
+            .iter()
+            .map(|command| format!("@{}", command.group))
+            .collect::<Vec<_>>();
+        groups.sort();
+        groups.dedup();
+        ReplHelper {
+            commands,
+            groups,
+            continuing: false,
+        }
+    }
+
+    fn find(&self, name: &str) -> Option<&CommandDocs> {
+        self.commands
+            .iter()
+            .find(|command| command.name.eq_ignore_ascii_case(name))
+    }
+}
+
+// A completion in the case that's been typed so far, as redis-cli does
+fn matching_case(word: &str, typed: &str) -> String {
+    if typed.chars().any(|c| c.is_ascii_lowercase()) {
+        word.to_ascii_lowercase()
+    } else {
+        word.to_string()
+    }
+}
+
+impl Completer for ReplHelper {
+    type Candidate = Pair;
+
+    // Command names for the first word, and subcommands for the second after a container
+    // command such as CLIENT or CONFIG
+    fn complete(
+        &self,
+        line: &str,
+        pos: usize,
+        _ctx: &Context<'_>,
+    ) -> rustyline::Result<(usize, Vec<Pair>)> {
+        if self.continuing {
+            return Ok((pos, vec![]));
+        }
+        let before = &line[..pos];
+        let start = before.rfind(char::is_whitespace).map_or(0, |i| i + 1);
+        let typed = &before[start..];
+
+        let words = before[..start].split_whitespace().collect::<Vec<_>>();
+        let candidates = match words.as_slice() {
+            [] => self.commands.iter().map(|command| command.name).collect(),
+            // What there's help for: commands, and @groups of them
+            [help] if help.eq_ignore_ascii_case("HELP") => self
+                .commands
+                .iter()
+                .map(|command| command.name)
+                .chain(self.groups.iter().map(String::as_str))
+                .collect(),
+            [name] => match self.find(name) {
+                Some(command) => command.subcommands.iter().map(String::as_str).collect(),
+                None => vec![],
+            },
+            _ => vec![],
+        };
+
+        let pairs = candidates
+            .into_iter()
+            .filter(|candidate| {
+                candidate
+                    .get(..typed.len())
+                    .is_some_and(|prefix| prefix.eq_ignore_ascii_case(typed))
+            })
+            .map(|candidate| Pair {
+                display: candidate.to_string(),
+                replacement: matching_case(candidate, typed),
+            })
+            .collect();
+        Ok((start, pairs))
+    }
+}
+
+// The arguments a command takes, shown after it but never inserted into the line
+pub struct ArgumentHint(String);
+
+impl Hint for ArgumentHint {
+    fn display(&self) -> &str {
+        &self.0
+    }
+
+    fn completion(&self) -> Option<&str> {
+        None
+    }
+}
+
+impl Hinter for ReplHelper {
+    type Hint = ArgumentHint;
+
+    // Once a command (and its subcommand, for a container) has been typed, how it's used, less the
+    // arguments that have been typed since
+    fn hint(&self, line: &str, pos: usize, _ctx: &Context<'_>) -> Option<ArgumentHint> {
+        if pos < line.len() || self.continuing {
+            return None;
+        }
+
+        // Nothing while inside quotes
+        let words = input::split(line.as_bytes())
+            .ok()?
+            .iter()
+            .map(|word| String::from_utf8_lossy(word).into_owned())
+            .collect::<Vec<_>>();
+        let command = self.find(words.first()?)?;
+        let name_words = if command.subcommands.is_empty() { 1 } else { 2 };
+        if words.len() < name_words {
+            return None;
+        }
+        let (name, typed) = words.split_at(name_words);
+
+        // The first form of the command that starts with what's been typed
+        let usage = command.usage.iter().find(|usage| {
+            let mut usage = usage.split_whitespace();
+            name.iter().all(|word| {
+                usage
+                    .next()
+                    .is_some_and(|name| name.eq_ignore_ascii_case(word))
+            })
+        })?;
+        let args = usage.splitn(name_words + 1, ' ').nth(name_words)?;
+        let hint = remaining(args, typed).join(" ");
+        if hint.is_empty() {
+            return None;
+        }
+
+        Some(ArgumentHint(if line.ends_with(char::is_whitespace) {
+            hint
+        } else {
+            format!(" {hint}")
+        }))
+    }
+}
+
+// The parts of a command's arguments still to be typed, given those that have been: each typed
+// argument fills the next required one, or an optional one it's the keyword of (leaving what goes
+// with that keyword, such as seconds after EX), or an optional one that doesn't start with a keyword
+fn remaining(args: &str, typed: &[String]) -> Vec<String> {
+    let mut remaining = tokens(args);
+    for arg in typed {
+        let filled = remaining.iter().enumerate().find_map(|(i, token)| {
+            let Some(group) = token.strip_prefix('[').and_then(|t| t.strip_suffix(']')) else {
+                return Some((i, ""));
+            };
+            split_top_level(group, " | ")
+                .into_iter()
+                .find_map(|alternative| {
+                    let (first, rest) = alternative.split_once(' ').unwrap_or((alternative, ""));
+                    let keyword = first
+                        .split('|')
+                        .any(|keyword| is_keyword(keyword) && keyword.eq_ignore_ascii_case(arg));
+                    let placeholder = first.starts_with(|c: char| c.is_ascii_lowercase());
+                    (keyword || placeholder).then_some((i, rest))
+                })
+        });
+
+        // Arguments that can be repeated (such as [key ...]) stay in the hint, and what goes with
+        // a keyword comes next
+        if let Some((i, rest)) = filled {
+            if !remaining[i].ends_with("...]") {
+                let rest = tokens(rest);
+                remaining.remove(i);
+                remaining.splice(0..0, rest);
+            }
+        }
+    }
+    remaining
+}
+
+// Arguments (or optional groups of them, in brackets) separated by spaces
+fn tokens(args: &str) -> Vec<String> {
+    let mut tokens: Vec<String> = vec![];
+    for token in split_top_level(args, " ") {
+        match tokens.last_mut() {
+            // Alternatives such as ON | OFF are one argument
+            Some(last) if token == "|" || last.ends_with(" |") => {
+                last.push(' ');
+                last.push_str(token);
+            }
+            _ if token.is_empty() => {}
+            _ => tokens.push(token.to_string()),
+        }
+    }
+    tokens
+}
+
+// Split on separator where it isn't inside brackets
+fn split_top_level<'a>(text: &'a str, separator: &str) -> Vec<&'a str> {
+    let mut parts = vec![];
+    let mut depth = 0;
+    let mut start = 0;
+    for (i, c) in text.char_indices() {
+        match c {
+            '[' => depth += 1,
+            ']' => depth -= 1,
+            _ if depth == 0 && i >= start && text[i..].starts_with(separator) => {
+                parts.push(&text[start..i]);
+                start = i + separator.len();
+            }
+            _ => {}
+        }
+    }
+    parts.push(&text[start..]);
+    parts
+}
+
+// Such as NX or NO-EVICT, rather than the name of a value to give (such as key)
+fn is_keyword(word: &str) -> bool {
+    word.starts_with(|c: char| c.is_ascii_uppercase())
+        && word
+            .chars()
+            .all(|c| c.is_ascii_uppercase() || c.is_ascii_digit() || c == '-')
+}
+
+impl Highlighter for ReplHelper {
+    // Hints are grey, so they can't be mistaken for what's been typed
+    fn highlight_hint<'h>(&self, hint: &'h str) -> Cow<'h, str> {
+        Cow::Owned(format!("\x1b[90m{hint}\x1b[0m"))
+    }
+}
+
+impl Validator for ReplHelper {}
+
+impl Helper for ReplHelper {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hint(args: &str, typed: &[&str]) -> String {
+        let typed = typed.iter().map(|arg| arg.to_string()).collect::<Vec<_>>();
+        remaining(args, &typed).join(" ")
+    }
+
+    #[test]
+    fn test_remaining() {
+        let set = "key value [NX | XX] [GET] [EX seconds | PX milliseconds | KEEPTTL]";
+        assert_eq!(hint(set, &[]), set);
+        assert_eq!(
+            hint(set, &["k"]),
+            "value [NX | XX] [GET] [EX seconds | PX milliseconds | KEEPTTL]"
+        );
+        assert_eq!(hint(set, &["k", "v", "px"]), "milliseconds [NX | XX] [GET]");
+        assert_eq!(hint(set, &["k", "v", "PX", "10", "NX", "GET"]), "");
+
+        assert_eq!(hint("key [key ...]", &["a", "b", "c"]), "[key ...]");
+        assert_eq!(hint("ON|OFF [NOLOOP]", &["on"]), "[NOLOOP]");
+        assert_eq!(
+            hint(
+                "[protover [AUTH username password] [SETNAME clientname]]",
+                &["3"]
+            ),
+            "[AUTH username password] [SETNAME clientname]"
+        );
+        assert_eq!(hint("[WRITE|ALL]", &["write"]), "");
+    }
+}
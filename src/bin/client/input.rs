@@ -0,0 +1,193 @@
+// Splitting what's typed at the prompt (or read from a file) into arguments, the way redis-cli
+// does: words are separated by whitespace, and can be "double quoted" (with escapes such as \n and
+// \x00) or 'single quoted' (where only \' is special) to include spaces or any byte
+// This is redis_rs::split_args_bytes with a way to tell input that isn't finished yet apart from
+// input that's wrong, and lines joined by a backslash.
+
+type Bytes<'a> = std::iter::Peekable<std::iter::Copied<std::slice::Iter<'a, u8>>>;
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum SplitError {
+    // Inside quotes, or after a backslash that continues onto the next line
+    Incomplete,
+    // A closing quote followed by something other than whitespace
+    Invalid,
+}
+
+pub fn split(input: &[u8]) -> Result<Vec<Vec<u8>>, SplitError> {
+    let mut args = vec![];
+    let mut bytes = input.iter().copied().peekable();
+    loop {
+        while bytes.next_if(u8::is_ascii_whitespace).is_some() {}
+        let mut ahead = bytes.clone();
+        match (ahead.next(), ahead.next()) {
+            (None, _) => return Ok(args),
+            (Some(b'\\'), None) => return Err(SplitError::Incomplete),
+            (Some(b'\\'), Some(b'\n')) => {
+                bytes.nth(1);
+                continue;
+            }
+            _ => {}
+        }
+
+        let mut arg = vec![];
+        while let Some(byte) = bytes.next_if(|byte| !byte.is_ascii_whitespace()) {
+            match byte {
+                // A backslash at the end of a line joins it to the next one, and is otherwise
+                // just a backslash outside quotes
+                b'\\' => match bytes.peek() {
+                    None => return Err(SplitError::Incomplete),
+                    Some(b'\n') => {
+                        bytes.next();
+                    }
+                    _ => arg.push(b'\\'),
+                },
+                b'"' | b'\'' => {
+                    if byte == b'"' {
+                        double_quoted(&mut bytes, &mut arg)?;
+                    } else {
+                        single_quoted(&mut bytes, &mut arg)?;
+                    }
+                    // As in redis-cli, a quoted string has to be a whole argument
+                    if bytes.peek().is_some_and(|byte| !byte.is_ascii_whitespace()) {
+                        return Err(SplitError::Invalid);
+                    }
+                }
+                byte => arg.push(byte),
+            }
+        }
+        args.push(arg);
+    }
+}
+
+// Up to and including the closing quote
+fn double_quoted(bytes: &mut Bytes, arg: &mut Vec<u8>) -> Result<(), SplitError> {
+    loop {
+        match bytes.next().ok_or(SplitError::Incomplete)? {
+            b'"' => return Ok(()),
+            b'\\' => match bytes.next().ok_or(SplitError::Incomplete)? {
+                b'n' => arg.push(b'\n'),
+                b'r' => arg.push(b'\r'),
+                b't' => arg.push(b'\t'),
+                b'b' => arg.push(0x08),
+                b'a' => arg.push(0x07),
+                b'\n' => {}
+                b'x' => {
+                    let mut ahead = bytes.clone();
+                    match [ahead.next(), ahead.next()]
+                        .map(|digit| digit.and_then(|digit| (digit as char).to_digit(16)))
+                    {
+                        [Some(high), Some(low)] => {
+                            bytes.nth(1);
+                            arg.push((high * 16 + low) as u8);
+                        }
+                        _ => arg.push(b'x'),
+                    }
+                }
+                byte => arg.push(byte),
+            },
+            byte => arg.push(byte),
+        }
+    }
+}
+
+fn single_quoted(bytes: &mut Bytes, arg: &mut Vec<u8>) -> Result<(), SplitError> {
+    loop {
+        match bytes.next().ok_or(SplitError::Incomplete)? {
+            b'\'' => return Ok(()),
+            b'\\' if bytes.next_if_eq(&b'\'').is_some() => arg.push(b'\''),
+            byte => arg.push(byte),
+        }
+    }
+}
+
+// Everything typed (for the history) and the arguments in it
+pub type Typed = (String, Result<Vec<Vec<u8>>, SplitError>);
+
+// A command typed at the prompt, starting with first and carrying on over the lines next reads for
+// as long as it's Incomplete
+pub fn continued<E>(
+    first: String,
+    mut next: impl FnMut() -> Result<String, E>,
+) -> Result<Typed, E> {
+    let mut input = first;
+    loop {
+        match split(input.as_bytes()) {
+            Err(SplitError::Incomplete) => {
+                let line = next()?;
+                input.push('\n');
+                input.push_str(&line);
+            }
+            args => return Ok((input, args)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(input: &str) -> Vec<String> {
+        split(input.as_bytes())
+            .unwrap()
+            .into_iter()
+            .map(|arg| String::from_utf8(arg).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_split() {
+        assert_eq!(args("  SET  foo bar "), ["SET", "foo", "bar"]);
+        assert_eq!(args(""), Vec::<String>::new());
+        assert_eq!(args(r#"SET "foo bar" 'it\'s'"#), ["SET", "foo bar", "it's"]);
+        assert_eq!(args(r#"SET k "a\tb\x41\"""#), ["SET", "k", "a\tbA\""]);
+        assert_eq!(args(r"SET k a\b"), ["SET", "k", r"a\b"]);
+        assert_eq!(args("SET k \"\""), ["SET", "k", ""]);
+        assert_eq!(split(br#"GET "\x00""#), Ok(vec![b"GET".to_vec(), vec![0]]));
+        assert_eq!(split(br#"GET "foo"bar"#), Err(SplitError::Invalid));
+    }
+
+    #[test]
+    fn test_split_continued() {
+        assert_eq!(split(b"SET foo \\"), Err(SplitError::Incomplete));
+        assert_eq!(split(b"SET foo \"bar"), Err(SplitError::Incomplete));
+        assert_eq!(split(b"SET foo 'bar"), Err(SplitError::Incomplete));
+        assert_eq!(args("SET foo \\\nbar"), ["SET", "foo", "bar"]);
+        assert_eq!(args("SET foo ba\\\nr"), ["SET", "foo", "bar"]);
+        assert_eq!(args("SET foo \"one\ntwo\""), ["SET", "foo", "one\ntwo"]);
+        assert_eq!(args("SET foo \"one \\\ntwo\""), ["SET", "foo", "one two"]);
+    }
+
+    // Each line the prompt would read next, failing once they've run out as Ctrl-D would
+    fn typed(first: &str, lines: &[&str]) -> Result<Typed, ()> {
+        let mut lines = lines.iter();
+        continued(first.to_string(), || {
+            lines.next().map(|line| line.to_string()).ok_or(())
+        })
+    }
+
+    #[test]
+    fn test_continued() {
+        let (input, args) = typed("GET foo", &["unread"]).unwrap();
+        assert_eq!(input, "GET foo");
+        assert_eq!(args, Ok(vec![b"GET".to_vec(), b"foo".to_vec()]));
+
+        let (input, args) = typed("SET foo \\", &["bar \"one", "two\""]).unwrap();
+        assert_eq!(input, "SET foo \\\nbar \"one\ntwo\"");
+        assert_eq!(
+            args,
+            Ok(vec![
+                b"SET".to_vec(),
+                b"foo".to_vec(),
+                b"bar".to_vec(),
+                b"one\ntwo".to_vec()
+            ])
+        );
+
+        let (_, args) = typed("SET 'a", &["'b"]).unwrap();
+        assert_eq!(args, Err(SplitError::Invalid));
+
+        // Stopping partway through gives up on the command
+        assert_eq!(typed("SET foo \"bar", &["baz"]), Err(()));
+    }
+}
@@ -0,0 +1,89 @@
+// --latency and --latency-history: PING the server over and over and report how long it takes to
+// answer, until interrupted
+
+use std::io::{stdout, IsTerminal, Write};
+use std::process::ExitCode;
+use std::time::{Duration, Instant};
+
+use redis_rs::RedisType;
+
+use crate::connection::Connection;
+
+// How long to wait between PINGs, as redis-cli does
+const SAMPLE_INTERVAL: Duration = Duration::from_millis(10);
+
+// How often the numbers are printed when they can't be updated in place
+const REPORT_INTERVAL: Duration = Duration::from_secs(1);
+
+#[derive(Default)]
+struct Samples {
+    count: u64,
+    min: Duration,
+    max: Duration,
+    total: Duration,
+}
+
+impl Samples {
+    fn add(&mut self, latency: Duration) {
+        if self.count == 0 || latency < self.min {
+            self.min = latency;
+        }
+        self.max = self.max.max(latency);
+        self.total += latency;
+        self.count += 1;
+    }
+
+    // In milliseconds, since a server on the same machine usually answers in well under one
+    fn summary(&self) -> String {
+        let ms = |latency: Duration| latency.as_secs_f64() * 1000.0;
+        format!(
+            "min: {:.2}, max: {:.2}, avg: {:.2} ({} samples)",
+            ms(self.min),
+            ms(self.max),
+            ms(self.total) / self.count.max(1) as f64,
+            self.count
+        )
+    }
+}
+
+// With history, the numbers start over each time that much time has passed, leaving a line for
+// each window behind
+pub async fn run(
+    connection: &mut Connection,
+    history: Option<Duration>,
+) -> std::io::Result<ExitCode> {
+    let mut out = stdout();
+    let terminal = out.is_terminal();
+
+    let mut samples = Samples::default();
+    let mut window_start = Instant::now();
+    let mut last_report = Instant::now();
+    loop {
+        let start = Instant::now();
+        if let RedisType::Error { value } = connection.send(vec!["PING"]).await? {
+            return Err(std::io::Error::other(value));
+        }
+        samples.add(start.elapsed());
+
+        // At a terminal the line is rewritten after every sample
+        if terminal {
+            write!(out, "\x1b[0G\x1b[2K{}", samples.summary())?;
+            out.flush()?;
+        }
+
+        let window = window_start.elapsed();
+        if history.is_some_and(|history| window >= history) {
+            if !terminal {
+                write!(out, "{}", samples.summary())?;
+            }
+            writeln!(out, " -- {:.2} seconds range", window.as_secs_f64())?;
+            samples = Samples::default();
+            window_start = Instant::now();
+        } else if history.is_none() && !terminal && last_report.elapsed() >= REPORT_INTERVAL {
+            writeln!(out, "{}", samples.summary())?;
+            last_report = Instant::now();
+        }
+
+        tokio::time::sleep(SAMPLE_INTERVAL).await;
+    }
+}
@@ -0,0 +1,466 @@
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::io::{stdin, stdout, BufRead, BufReader, ErrorKind, IsTerminal, Write};
+use std::path::PathBuf;
+use std::process::ExitCode;
+use std::time::Duration;
+
+use clap::{ArgAction, ArgGroup, Parser};
+use connection::{Connection, Options};
+use format::Format;
+use redis_rs::RedisType;
+use rustyline::error::ReadlineError;
+use rustyline::history::DefaultHistory;
+use rustyline::{CompletionType, Config, Editor};
+use tracing_subscriber::filter::LevelFilter;
+
+mod bigkeys;
+mod connection;
+mod format;
+mod help;
+mod helper;
+mod input;
+mod latency;
+mod password;
+mod scan;
+
+// Run commands against a server, either from a prompt or one given on the command line, along the
+// lines of redis-cli
+#[derive(Parser, Debug)]
+#[command(
+    version,
+    about = "Run commands against a redis-rs (or Redis) server",
+    // -h is the host, as it is for redis-cli
+    disable_help_flag = true,
+    // What -r repeats
+    group(ArgGroup::new("runs").args(["command", "eval"]).multiple(true))
+)]
+struct Args {
+    #[command(flatten)]
+    connection: Options,
+
+    /// Print each reply as JSON, with errors as objects with an error field
+    #[arg(long, conflicts_with = "csv")]
+    json: bool,
+
+    /// Print each reply as a line of comma separated values
+    #[arg(long)]
+    csv: bool,
+
+    /// List every key (or those matching --pattern), one per line, using SCAN
+    #[arg(long, conflicts_with = "command")]
+    scan: bool,
+
+    /// Only list keys matching this glob-style pattern with --scan
+    #[arg(long, requires = "scan")]
+    pattern: Option<String>,
+
+    /// How many keys each SCAN looks at with --scan
+    #[arg(long, requires = "scan")]
+    count: Option<u64>,
+
+    /// Find the largest key of each type, and how many keys and how much data there is of each
+    #[arg(long, conflicts_with_all = ["command", "scan", "memkeys"])]
+    bigkeys: bool,
+
+    /// The same as --bigkeys, but sizing keys by how much memory they use
+    #[arg(long, conflicts_with_all = ["command", "scan"])]
+    memkeys: bool,
+
+    /// Keep sending PINGs and show the minimum, maximum and average time to answer, in
+    /// milliseconds
+    #[arg(long, conflicts_with_all = ["command", "scan", "bigkeys", "memkeys"])]
+    latency: bool,
+
+    /// The same as --latency, starting over every 15 seconds (or -i) and leaving a line for each
+    #[arg(long, conflicts_with_all = ["command", "scan", "bigkeys", "memkeys", "latency"])]
+    latency_history: bool,
+
+    /// Send EVAL with the Lua script in this file, taking keys and then arguments from those
+    /// given after it, separated by a comma (such as --eval script.lua key1 key2 , arg1 arg2)
+    #[arg(long, conflicts_with_all = ["scan", "bigkeys", "memkeys", "latency", "latency_history"])]
+    eval: Option<PathBuf>,
+
+    /// Run the commands in this file, one per line, instead of starting a prompt (as is done with
+    /// stdin when it isn't a terminal)
+    #[arg(long, conflicts_with_all = ["command", "scan", "bigkeys", "memkeys", "latency", "latency_history", "eval"])]
+    file: Option<PathBuf>,
+
+    /// Stop at the first command from a file (or stdin) whose reply is an error
+    #[arg(long)]
+    abort_on_error: bool,
+
+    /// Run the command this many times (or forever, if it's negative), with __rand_int__ in
+    /// its arguments replaced by a different random number each time
+    #[arg(short = 'r', requires = "runs", allow_negative_numbers = true)]
+    repeat: Option<i64>,
+
+    /// Seconds to wait between runs with -r, or for each --latency-history window, such as 0.5
+    #[arg(short = 'i', value_parser = seconds)]
+    interval: Option<Duration>,
+
+    /// Print help
+    #[arg(long, action = ArgAction::Help)]
+    help: Option<bool>,
+
+    /// A command to run instead of starting a prompt, such as SET foo bar; the client exits
+    /// once it's answered, with status 1 if the reply is an error
+    #[arg(trailing_var_arg = true, allow_hyphen_values = true)]
+    command: Vec<String>,
+}
+
+// How long each --latency-history window is unless -i says otherwise, as in redis-cli
+const LATENCY_HISTORY_INTERVAL: u64 = 15;
+
+// Replaced with a random number in each run of a command, as it is by redis-benchmark
+const RAND_INT: &str = "__rand_int__";
+
+// A positive number of seconds, which can be fractional
+fn seconds(arg: &str) -> Result<Duration, String> {
+    match arg.parse::<f64>() {
+        Ok(seconds) if seconds > 0.0 && seconds.is_finite() => Ok(Duration::from_secs_f64(seconds)),
+        _ => Err(String::from("expected a positive number of seconds")),
+    }
+}
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    // Logs go to stderr so they don't get mixed up with replies, and only warnings unless RUST_LOG
+    // asks for more (such as RUST_LOG=debug to see what's sent and received)
+    let level = std::env::var("RUST_LOG")
+        .ok()
+        .and_then(|level| level.parse().ok())
+        .unwrap_or(LevelFilter::WARN);
+    tracing_subscriber::fmt()
+        .with_writer(std::io::stderr)
+        .with_max_level(level)
+        .init();
+    let mut args = Args::parse();
+    args.connection.apply_url();
+
+    let password = &mut args.connection.password;
+    let env = std::env::var("REDISCLI_AUTH").ok();
+    match password::source(password.is_some(), env, args.connection.askpass) {
+        password::Source::Flag => eprintln!(
+            "Warning: Using a password with '-a', '--pass' or '-u' on the command line may not be safe."
+        ),
+        password::Source::Env(env) => *password = Some(env),
+        password::Source::Prompt => match password::ask() {
+            Ok(entered) => *password = Some(entered),
+            Err(e) => {
+                eprintln!("Could not read the password: {e}");
+                return ExitCode::FAILURE;
+            }
+        },
+        password::Source::None => {}
+    }
+
+    let addr = args.connection.addr();
+    tracing::info!("Connecting to {addr}");
+    let mut connection = match Connection::open(&args.connection).await {
+        Ok(connection) => connection,
+        Err(e) => {
+            eprintln!("Could not connect to {addr}: {e}");
+            return ExitCode::FAILURE;
+        }
+    };
+
+    // Quoted and labelled for a person at a terminal, otherwise as the values themselves
+    let format = if args.json {
+        Format::Json
+    } else if args.csv {
+        Format::Csv
+    } else if stdout().is_terminal() {
+        Format::Human
+    } else {
+        Format::Raw
+    };
+
+    let result = if args.latency || args.latency_history {
+        let history = args.latency_history.then(|| {
+            args.interval
+                .unwrap_or(Duration::from_secs(LATENCY_HISTORY_INTERVAL))
+        });
+        latency::run(&mut connection, history).await
+    } else if args.bigkeys || args.memkeys {
+        bigkeys::run(&mut connection, args.memkeys).await
+    } else if args.scan {
+        scan(&mut connection, args.pattern, args.count).await
+    } else if let Some(path) = &args.eval {
+        let script = match std::fs::read_to_string(path) {
+            Ok(script) => script,
+            Err(e) => {
+                eprintln!("Can't open file '{}': {e}", path.display());
+                return ExitCode::FAILURE;
+            }
+        };
+        let command = eval(script, args.command);
+        let repeat = args.repeat.unwrap_or(1);
+        one_shot(&mut connection, command, format, repeat, args.interval).await
+    } else if let Some(path) = &args.file {
+        let file = match std::fs::File::open(path) {
+            Ok(file) => file,
+            Err(e) => {
+                eprintln!("Can't open file '{}': {e}", path.display());
+                return ExitCode::FAILURE;
+            }
+        };
+        batch(
+            &mut connection,
+            BufReader::new(file),
+            format,
+            args.abort_on_error,
+        )
+        .await
+    } else if args.command.is_empty() && !stdin().is_terminal() {
+        batch(&mut connection, stdin().lock(), format, args.abort_on_error).await
+    } else if args.command.is_empty() {
+        repl(&mut connection, format).await
+    } else {
+        let repeat = args.repeat.unwrap_or(1);
+        one_shot(&mut connection, args.command, format, repeat, args.interval).await
+    };
+    result.unwrap_or_else(|e| {
+        eprintln!("Error talking to {addr}: {e}");
+        ExitCode::FAILURE
+    })
+}
+
+// Send a command and print its reply, repeat times (forever if it's negative) with interval in
+// between
+async fn one_shot(
+    connection: &mut Connection,
+    command: Vec<String>,
+    format: Format,
+    repeat: i64,
+    interval: Option<Duration>,
+) -> std::io::Result<ExitCode> {
+    let mut random = RandomState::new().build_hasher().finish() | 1;
+    let mut failed = false;
+    let mut run = 0;
+    while repeat < 0 || run < repeat {
+        if run > 0 {
+            if let Some(interval) = interval {
+                tokio::time::sleep(interval).await;
+            }
+        }
+        run += 1;
+
+        // Each placeholder gets its own number, so a command can name several random keys
+        let args = command
+            .iter()
+            .map(|arg| {
+                let mut arg = arg.clone();
+                while arg.contains(RAND_INT) {
+                    random ^= random << 13;
+                    random ^= random >> 7;
+                    random ^= random << 17;
+                    arg = arg.replacen(RAND_INT, &format!("{:012}", random % 1_000_000_000_000), 1);
+                }
+                arg
+            })
+            .collect::<Vec<_>>();
+
+        let reply = connection.send(args).await?;
+        print(connection, &reply, format)?;
+        failed |= matches!(reply, RedisType::Error { .. });
+    }
+
+    Ok(if failed {
+        ExitCode::FAILURE
+    } else {
+        ExitCode::SUCCESS
+    })
+}
+
+// Run each command read from input in turn, printing its reply, with commands carrying on over
+// more lines as they do at the prompt; the client exits with status 1 if any reply is an error,
+// straight away with abort_on_error
+async fn batch(
+    connection: &mut Connection,
+    mut input: impl BufRead,
+    format: Format,
+    abort_on_error: bool,
+) -> std::io::Result<ExitCode> {
+    let mut failed = false;
+    let mut line = 0;
+    let mut command = Vec::new();
+    loop {
+        // The line a command starts on, for errors
+        let start = line + 1;
+        command.clear();
+        let args = loop {
+            let read = input.read_until(b'\n', &mut command)?;
+            if read == 0 && command.is_empty() {
+                return Ok(ExitCode::from(u8::from(failed)));
+            }
+            line += 1;
+            // Files written on Windows end lines with \r\n
+            if command.ends_with(b"\r\n") {
+                command.truncate(command.len() - 2);
+                command.push(b'\n');
+            }
+            // Without the last newline, so a backslash before it carries on to the next line
+            let text = command.strip_suffix(b"\n").unwrap_or(&command);
+            match input::split(text) {
+                Err(input::SplitError::Incomplete) if read > 0 => continue,
+                result => break result,
+            }
+        };
+
+        let args = match args {
+            Ok(args) if args.is_empty() => continue,
+            Ok(args) => args,
+            Err(e) => {
+                let problem = match e {
+                    input::SplitError::Incomplete => "unexpected end of file",
+                    input::SplitError::Invalid => "invalid argument(s)",
+                };
+                eprintln!("Line {start}: {problem}");
+                if abort_on_error {
+                    return Ok(ExitCode::FAILURE);
+                }
+                failed = true;
+                continue;
+            }
+        };
+        if ["QUIT", "EXIT"]
+            .iter()
+            .any(|quit| args[0].eq_ignore_ascii_case(quit.as_bytes()))
+        {
+            return Ok(ExitCode::from(u8::from(failed)));
+        }
+
+        let reply = connection.send(args).await?;
+        print(connection, &reply, format)?;
+        if matches!(reply, RedisType::Error { .. }) {
+            if abort_on_error {
+                return Ok(ExitCode::FAILURE);
+            }
+            failed = true;
+        }
+    }
+}
+
+// The EVAL for a script, with the keys before a lone comma and the arguments after it (everything
+// is a key if there's no comma)
+fn eval(script: String, mut keys: Vec<String>) -> Vec<String> {
+    let args = match keys.iter().position(|arg| arg == ",") {
+        Some(comma) => keys.split_off(comma).split_off(1),
+        None => vec![],
+    };
+
+    let mut command = vec![String::from("EVAL"), script, keys.len().to_string()];
+    command.extend(keys);
+    command.extend(args);
+    command
+}
+
+// Print every key SCAN returns, as it is without quotes even at a terminal
+async fn scan(
+    connection: &mut Connection,
+    pattern: Option<String>,
+    count: Option<u64>,
+) -> std::io::Result<ExitCode> {
+    let mut scan = scan::Scan::new(pattern, count);
+    let mut stdout = stdout();
+    while let Some(keys) = scan.next(connection).await? {
+        for key in keys {
+            stdout.write_all(&key)?;
+            stdout.write_all(b"\n")?;
+        }
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+// Read commands from a prompt until the end of input, quit or exit, or the server can't be
+// reconnected to
+async fn repl(connection: &mut Connection, format: Format) -> std::io::Result<ExitCode> {
+    // Tab lists every completion when there's more than one, as bash does
+    let config = Config::builder()
+        .completion_type(CompletionType::List)
+        .build();
+    let mut editor = Editor::<helper::ReplHelper, DefaultHistory>::with_config(config)
+        .map_err(std::io::Error::other)?;
+    editor.set_helper(Some(helper::ReplHelper::new()));
+
+    loop {
+        let command = match read_command(&mut editor) {
+            Ok(Some(command)) => command,
+            Ok(None) => continue,
+            // Ctrl-C abandons the command being typed, as it does in redis-cli
+            Err(ReadlineError::Interrupted) => continue,
+            Err(ReadlineError::Eof) => {
+                tracing::info!("Reached end of stdin");
+                break;
+            }
+            Err(e) => {
+                tracing::warn!("Error reading from stdin: {e:?}");
+                break;
+            }
+        };
+
+        // Otherwise QUIT would only lead to reconnecting for the next command
+        if ["QUIT", "EXIT"]
+            .iter()
+            .any(|quit| command[0].eq_ignore_ascii_case(quit.as_bytes()))
+        {
+            break;
+        }
+        // Answered from the commands the client knows, without asking the server
+        if command[0].eq_ignore_ascii_case(b"HELP") {
+            let topic = command[1..]
+                .iter()
+                .map(|word| String::from_utf8_lossy(word).into_owned())
+                .collect::<Vec<_>>();
+            let commands = &editor.helper().unwrap().commands;
+            print!("{}", help::topic(commands, &topic, format == Format::Human));
+            continue;
+        }
+        match connection.send(command).await {
+            Ok(reply) => print(connection, &reply, format)?,
+            Err(e) if matches!(e.kind(), ErrorKind::InvalidData | ErrorKind::TimedOut) => {
+                tracing::warn!("{e}")
+            }
+            Err(e) => return Err(e),
+        }
+    }
+
+    Ok(ExitCode::SUCCESS)
+}
+
+// The arguments of the next command typed at the prompt, which carries on over more lines while
+// the last one ends with a backslash or inside quotes, or None if there's nothing to run
+fn read_command(
+    editor: &mut Editor<helper::ReplHelper, DefaultHistory>,
+) -> rustyline::Result<Option<Vec<Vec<u8>>>> {
+    let first = editor.readline("redis-rs> ")?;
+    let (input, args) = input::continued(first, || {
+        editor.helper_mut().unwrap().continuing = true;
+        let line = editor.readline("...> ");
+        editor.helper_mut().unwrap().continuing = false;
+        line
+    })?;
+    let args = match args {
+        Ok(args) => Some(args),
+        Err(_) => {
+            println!("Invalid argument(s)");
+            None
+        }
+    };
+    tracing::debug!("Input read: {input}");
+
+    if !input.trim().is_empty() {
+        let _ = editor.add_history_entry(input.as_str());
+    }
+    Ok(args.filter(|args| !args.is_empty()))
+}
+
+// A reply, after any push messages the server sent before it
+fn print(connection: &mut Connection, reply: &RedisType, format: Format) -> std::io::Result<()> {
+    let mut stdout = stdout();
+    for push in connection.take_pushes() {
+        stdout.write_all(&format.reply(&push))?;
+    }
+    stdout.write_all(&format.reply(reply))
+}
@@ -0,0 +1,89 @@
+// Where the password comes from, and --askpass: reading it at a prompt that shows a * for each
+// character instead
+
+use std::borrow::Cow;
+
+use rustyline::completion::Completer;
+use rustyline::config::Configurer;
+use rustyline::highlight::{CmdKind, Highlighter};
+use rustyline::hint::Hinter;
+use rustyline::history::DefaultHistory;
+use rustyline::validate::Validator;
+use rustyline::{ColorMode, Editor, Helper};
+
+struct Masked;
+
+impl Highlighter for Masked {
+    fn highlight<'l>(&self, line: &'l str, _pos: usize) -> Cow<'l, str> {
+        Cow::Owned("*".repeat(line.chars().count()))
+    }
+
+    // Redrawn on every change, so what's shown is never the line itself
+    fn highlight_char(&self, _line: &str, _pos: usize, kind: CmdKind) -> bool {
+        kind != CmdKind::MoveCursor
+    }
+}
+
+impl Completer for Masked {
+    type Candidate = String;
+}
+
+impl Hinter for Masked {
+    type Hint = String;
+}
+
+impl Validator for Masked {}
+
+impl Helper for Masked {}
+
+// The prompt is the one redis-cli uses
+pub fn ask() -> rustyline::Result<String> {
+    let mut editor = Editor::<Masked, DefaultHistory>::new()?;
+    editor.set_helper(Some(Masked));
+    // Highlighting (and so masking) is otherwise skipped when the terminal doesn't do colours
+    editor.set_color_mode(ColorMode::Forced);
+    editor.set_auto_add_history(false);
+    editor.readline("Please input password: ")
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Source {
+    // -a, --pass or -u
+    Flag,
+    // REDISCLI_AUTH
+    Env(String),
+    // --askpass
+    Prompt,
+    None,
+}
+
+// The first of these that's there: the command line, the environment, then asking for it
+pub fn source(flag: bool, env: Option<String>, askpass: bool) -> Source {
+    if flag {
+        Source::Flag
+    } else if let Some(password) = env {
+        Source::Env(password)
+    } else if askpass {
+        Source::Prompt
+    } else {
+        Source::None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_source() {
+        let env = || Some(String::from("secret"));
+        assert_eq!(source(true, env(), true), Source::Flag);
+        assert_eq!(source(true, None, false), Source::Flag);
+        assert_eq!(
+            source(false, env(), true),
+            Source::Env(String::from("secret"))
+        );
+        assert_eq!(source(false, None, true), Source::Prompt);
+        assert_eq!(source(false, None, false), Source::None);
+    }
+}
@@ -0,0 +1,59 @@
+// Walking the keyspace with SCAN, a batch of keys at a time
+
+use redis_rs::RedisType;
+
+use crate::connection::Connection;
+
+pub struct Scan {
+    cursor: String,
+    pattern: Option<String>,
+    count: Option<u64>,
+    done: bool,
+}
+
+impl Scan {
+    pub fn new(pattern: Option<String>, count: Option<u64>) -> Self {
+        Scan {
+            cursor: String::from("0"),
+            pattern,
+            count,
+            done: false,
+        }
+    }
+
+    // The next batch of keys, which can be empty when a pattern is given, or None once the cursor
+    // has come back around to 0
+    pub async fn next(
+        &mut self,
+        connection: &mut Connection,
+    ) -> std::io::Result<Option<Vec<Vec<u8>>>> {
+        if self.done {
+            return Ok(None);
+        }
+
+        let mut command = vec![String::from("SCAN"), self.cursor.clone()];
+        if let Some(pattern) = &self.pattern {
+            command.extend([String::from("MATCH"), pattern.clone()]);
+        }
+        if let Some(count) = self.count {
+            command.extend([String::from("COUNT"), count.to_string()]);
+        }
+
+        let (cursor, keys) = match connection.send(command).await? {
+            RedisType::Array { value } => match <[RedisType; 2]>::try_from(value) {
+                Ok([cursor, RedisType::Array { value: keys }]) => (cursor, keys),
+                _ => return Err(invalid("unexpected reply to SCAN")),
+            },
+            RedisType::Error { value } => return Err(std::io::Error::other(value)),
+            _ => return Err(invalid("unexpected reply to SCAN")),
+        };
+
+        self.cursor = String::from_utf8_lossy(&cursor.to_bytes()).into_owned();
+        self.done = self.cursor == "0";
+        Ok(Some(keys.iter().map(RedisType::to_bytes).collect()))
+    }
+}
+
+fn invalid(message: &str) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::InvalidData, message)
+}
@@ -0,0 +1,141 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::PathBuf;
+use std::process::ExitCode;
+
+use clap::Parser;
+use redis_rs::{aof, rdb};
+
+// Verify the files the server persists to, along the lines of redis-check-rdb and redis-check-aof
+#[derive(Parser, Debug)]
+#[command(
+    version,
+    about = "Check redis-rs snapshot (RDB) and append only (AOF) files"
+)]
+struct Args {
+    /// The file to check, snapshots are recognized by their header
+    file: PathBuf,
+
+    /// Truncate an append only file to its last valid command
+    #[arg(long)]
+    fix: bool,
+}
+
+fn main() -> ExitCode {
+    let args = Args::parse();
+
+    let data = match fs::read(&args.file) {
+        Ok(data) => data,
+        Err(e) => {
+            eprintln!("Cannot open {}: {e}", args.file.display());
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let ok = if data.starts_with(rdb::MAGIC) {
+        if args.fix {
+            eprintln!("--fix only applies to append only files");
+            return ExitCode::FAILURE;
+        }
+        check_rdb(&args, &data)
+    } else {
+        check_aof(&args, &data)
+    };
+
+    if ok {
+        ExitCode::SUCCESS
+    } else {
+        ExitCode::FAILURE
+    }
+}
+
+fn check_rdb(args: &Args, data: &[u8]) -> bool {
+    println!("Checking RDB file {}", args.file.display());
+
+    let entries = match rdb::parse(data) {
+        Ok(entries) => entries,
+        Err(e) => {
+            println!("--- RDB ERROR DETECTED ---");
+            println!("{e}");
+            return false;
+        }
+    };
+
+    // parse has already checked the version and verified the checksum if there is one
+    let version = std::str::from_utf8(&data[rdb::MAGIC.len()..rdb::MAGIC.len() + 4])
+        .unwrap()
+        .parse::<u32>()
+        .unwrap();
+    println!("RDB version {version}");
+    if version >= 5 {
+        let checksum = u64::from_le_bytes(data[data.len() - 8..].try_into().unwrap());
+        if checksum == 0 {
+            println!("Checksum disabled when saving, not verified");
+        } else {
+            println!("CRC64 checksum is OK ({checksum:#018x})");
+        }
+    }
+
+    let mut types = BTreeMap::new();
+    for entry in &entries {
+        *types.entry(entry.value.type_name()).or_insert(0) += 1;
+    }
+    let expires = entries.iter().filter(|e| e.expires_at.is_some()).count();
+
+    println!("{} keys read, {expires} with an expiration", entries.len());
+    for (kind, count) in types {
+        println!("  {kind}: {count}");
+    }
+
+    println!("RDB looks OK");
+    true
+}
+
+fn check_aof(args: &Args, data: &[u8]) -> bool {
+    println!("Checking AOF file {}", args.file.display());
+
+    let checked = aof::check(data);
+    let mut names = BTreeMap::new();
+    for argv in &checked.commands {
+        *names
+            .entry(String::from_utf8_lossy(&argv[0]).to_ascii_lowercase())
+            .or_insert(0) += 1;
+    }
+
+    println!(
+        "{} valid commands in {} of {} bytes",
+        checked.commands.len(),
+        checked.valid,
+        checked.len
+    );
+    for (name, count) in names {
+        println!("  {name}: {count}");
+    }
+
+    let Some(damage) = &checked.damage else {
+        println!("AOF is valid");
+        return true;
+    };
+    println!("--- AOF ERROR DETECTED ---\n{damage}");
+
+    if !args.fix {
+        println!("AOF is not valid. Use the --fix option to try fixing it.");
+        return false;
+    }
+
+    println!(
+        "Truncating AOF to {} bytes, {} bytes will be lost",
+        checked.valid,
+        checked.len - checked.valid
+    );
+    match aof::repair(&args.file, &checked) {
+        Ok(_) => {
+            println!("Successfully truncated AOF");
+            true
+        }
+        Err(e) => {
+            println!("Failed to truncate AOF: {e}");
+            false
+        }
+    }
+}
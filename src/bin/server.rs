@@ -1,822 +1,179 @@
-use lazy_static::lazy_static;
-use priority_queue::PriorityQueue;
-use redis_rs::RedisType;
-use std::collections::HashMap;
-use std::net::SocketAddr;
-use std::str::FromStr;
-use std::sync::Arc;
-use std::time::{Duration, SystemTime, UNIX_EPOCH};
-use tokio::io::{AsyncReadExt, AsyncWriteExt};
-use tokio::net::{TcpListener, TcpStream};
-use tokio::sync::Mutex;
-use tracing_subscriber;
-
-#[tokio::main]
-async fn main() -> std::io::Result<()> {
-    tracing_subscriber::fmt::init();
-
-    let addr = "0.0.0.0:6379";
-
-    let listener = TcpListener::bind(addr).await?;
-    tracing::info!("Listening on {addr}");
-
-    let state = Arc::new(Mutex::new(State::default()));
-
-    let ttl_state = state.clone();
-    tokio::spawn(async move {
-        loop {
-            let now = SystemTime::now();
-            loop {
-                let evict = match ttl_state.lock().await.ttl.peek() {
-                    Some((_, eviction_time)) => *eviction_time < now,
-                    None => false,
-                };
-
-                if evict {
-                    let mut ttl_state = ttl_state.lock().await;
-                    let (key, _) = ttl_state.ttl.pop().unwrap();
-                    tracing::debug!("Evicting {key} from keystore");
-                    ttl_state.keystore.remove(&key);
-                } else {
-                    break;
-                }
-            }
-            tokio::time::sleep(Duration::from_secs(1)).await;
-        }
-    });
-
-    loop {
-        let (stream, addr) = listener.accept().await?;
-        let thread_state = state.clone();
-
-        tracing::debug!("Accepted connection from {addr:?}");
-        tokio::spawn(async move {
-            if let Err(e) = handle(stream, addr, thread_state).await {
-                tracing::warn!("An error occurred: {e:?}");
-            }
-        });
-    }
+use std::path::PathBuf;
+
+use clap::Parser;
+use redis_rs::server::config::{self, Config};
+use redis_rs::server::{logging, systemd, Server, SENTINEL_PORT};
+
+// Command line flags, these override both the config file and environment variables
+#[derive(Parser, Debug)]
+#[command(version, about = "A Redis compatible server")]
+struct Args {
+    /// Load configuration from a redis.conf style file
+    #[arg(long)]
+    config: Option<PathBuf>,
+
+    /// Port to listen on
+    #[arg(long)]
+    port: Option<u16>,
+
+    /// Addresses to listen on
+    #[arg(long, num_args = 1..)]
+    bind: Option<Vec<String>>,
+
+    /// Working directory, snapshots are written here
+    #[arg(long)]
+    dir: Option<PathBuf>,
+
+    /// Password required for the default user
+    #[arg(long)]
+    requirepass: Option<String>,
+
+    /// Maximum memory to use for data (e.g. 100mb, 1gb)
+    #[arg(long)]
+    maxmemory: Option<String>,
+
+    /// Worker threads to spread connections across, 0 for one per CPU
+    #[arg(long)]
+    io_threads: Option<usize>,
+
+    /// One of debug, verbose, notice, warning or nothing
+    #[arg(long)]
+    loglevel: Option<String>,
+
+    /// Only accept loopback connections if no password is set (yes or no)
+    #[arg(long)]
+    protected_mode: Option<String>,
+
+    /// Write the process id to this file while running
+    #[arg(long)]
+    pidfile: Option<PathBuf>,
+
+    /// Log to this file instead of stdout
+    #[arg(long)]
+    logfile: Option<PathBuf>,
+
+    /// Run as a sentinel, monitoring the masters in the config file's sentinel directives
+    #[arg(long)]
+    sentinel: bool,
 }
 
-async fn handle(
-    mut stream: TcpStream,
-    addr: SocketAddr,
-    state: Arc<Mutex<State>>,
-) -> std::io::Result<()> {
-    tracing::info!("[{addr}] Accepted connection");
-
-    let mut buf = [0; 1024];
+impl Args {
+    // Pairs of parameter name and value for each flag that was set
+    fn overrides(&self) -> Vec<(&'static str, String)> {
+        let mut overrides = Vec::new();
 
-    loop {
-        let bytes_read = stream.read(&mut buf).await?;
-        if bytes_read == 0 {
-            break;
+        if let Some(port) = self.port {
+            overrides.push(("port", port.to_string()));
         }
-        tracing::debug!("[{addr}] Received {bytes_read} bytes");
-
-        let string = String::from_utf8_lossy(&buf[0..bytes_read]);
-        let command = match RedisType::from_str(&string) {
-            Ok(RedisType::Array { value }) => value,
-            Ok(data) => {
-                tracing::warn!("[{addr}] Error, input should be array, got: {data:?}");
-                continue;
-            }
-            Err(err) => {
-                tracing::warn!("[{addr}] Error parsing input: {err:?}");
-                continue;
-            }
-        };
-
-        if command.len() < 1 {
-            tracing::warn!("[{addr}] Input command was empty");
-            continue;
+        if let Some(bind) = &self.bind {
+            overrides.push(("bind", bind.join(" ")));
         }
-
-        let args = &command[1..];
-        let command = match &command[0] {
-            RedisType::String { value } => value.to_ascii_uppercase().to_owned(),
-            _ => {
-                tracing::warn!(
-                    "[{addr}] Input command must be a string, got {:?}",
-                    command[0]
-                );
-                continue;
-            }
-        };
-        tracing::debug!("[{addr} Received: {command} {args:?}");
-
-        match COMMANDS.get(command.as_str()) {
-            Some(command) => {
-                let mut command_state = state.lock().await;
-                let response = match command.f.as_ref()(&mut command_state, args) {
-                    Ok(value) => value,
-                    Err(value) => RedisType::Error { value },
-                };
-                stream.write_all(response.to_string().as_bytes()).await?;
-            }
-            None => {
-                tracing::warn!("[{addr}] Unimplemented command: {command} {args:?}");
-                stream
-                    .write_all(
-                        RedisType::Error {
-                            value: format!("Unimplemented command: {command}").to_owned(),
-                        }
-                        .to_string()
-                        .as_bytes(),
-                    )
-                    .await?;
-                continue;
-            }
+        if let Some(dir) = &self.dir {
+            overrides.push(("dir", dir.display().to_string()));
         }
-    }
-
-    tracing::info!("[{addr}] Ending connection");
-
-    Ok(())
-}
-
-#[derive(Debug, Default)]
-pub struct State {
-    keystore: HashMap<String, String>,
-    ttl: PriorityQueue<String, SystemTime>,
-}
-
-#[derive()]
-pub struct Command {
-    #[allow(dead_code)]
-    help: String,
-    f: Box<fn(&mut State, &[RedisType]) -> Result<RedisType, String>>,
-}
-
-lazy_static! {
-    static ref COMMANDS: HashMap<&'static str, Command> = {
-        let mut m = HashMap::new();
-
-        macro_rules! assert_n_args {
-            ($args:ident, $n:literal) => {
-                if $args.len() != $n {
-                    return Err(String::from(format!("Expected {} args, got {}", $n, $args.len())));
-                }
-            }
+        if let Some(requirepass) = &self.requirepass {
+            overrides.push(("requirepass", requirepass.clone()));
         }
-
-        macro_rules! assert_n_or_more_args {
-            ($args:ident, $n:literal) => {
-                if $args.len() < $n {
-                    return Err(String::from(format!("Expected at least {} args, got {}", $n, $args.len())));
-                }
-            }
+        if let Some(maxmemory) = &self.maxmemory {
+            overrides.push(("maxmemory", maxmemory.clone()));
         }
-
-        macro_rules! get_string_arg {
-            ($args:ident, $index:expr) => {
-                {
-                    if $index >= $args.len() {
-                        return Err(String::from("Not enough args"));
-                    }
-
-                    match $args[$index].clone() {
-                        RedisType::String{value} => value,
-                        RedisType::Integer{value} => value.to_string(),
-                        _ => return Err(String::from(format!("Attempted to use {} as a string", $args[$index]))),
-
-                    }
-                }
-            }
+        if let Some(io_threads) = self.io_threads {
+            overrides.push(("io-threads", io_threads.to_string()));
         }
-
-        // TODO: should this be case insensitive?
-        macro_rules! is_string_eq {
-            ($args:ident, $index:expr, $value:literal) => {
-               get_string_arg!($args, $index).to_ascii_uppercase() == $value.to_ascii_uppercase()
-            }
+        if let Some(loglevel) = &self.loglevel {
+            overrides.push(("loglevel", loglevel.clone()));
         }
-
-        macro_rules! get_integer_arg {
-            ($args:ident, $index:expr) => {
-                {
-                    if $index >= $args.len() {
-                        return Err(String::from("Not enough args"));
-                    }
-
-                    match $args[$index].clone() {
-                        RedisType::String{value} => {
-                            match value.parse() {
-                                Ok(value) => value,
-                                Err(_) => return Err(String::from(format!("Attempted to use {} as an integer", $args[$index]))),
-                            }
-                        },
-                        RedisType::Integer{value} => value,
-                        _ => return Err(String::from(format!("Attempted to use {} as an integer", $args[$index]))),
-                    }
-                }
-            }
+        if let Some(protected_mode) = &self.protected_mode {
+            overrides.push(("protected-mode", protected_mode.clone()));
         }
-
-        macro_rules! get_float_arg {
-            ($args:ident, $index:expr) => {
-                {
-                    if $index >= $args.len() {
-                        return Err(String::from("Not enough args"));
-                    }
-
-                    match $args[$index].clone() {
-                        RedisType::String{value} => {
-                            match value.parse() {
-                                Ok(value) => value,
-                                Err(_) => return Err(String::from(format!("Attempted to use {} as a float", $args[$index]))),
-                            }
-                        },
-                        RedisType::Integer{value} => value as f64,
-                        _ => return Err(String::from(format!("Attempted to use {} as a float", $args[$index]))),
-                    }
-                }
-            }
+        if let Some(pidfile) = &self.pidfile {
+            overrides.push(("pidfile", pidfile.display().to_string()));
         }
-
-        macro_rules! get_expiration {
-            ($args:ident, $index:expr) => {
-                if is_string_eq!($args, $index, "EX") {
-                    // Seconds from now
-                    let value = get_integer_arg!($args, $index + 1);
-                    Some((
-                        SystemTime::now()
-                        + Duration::from_secs(value as u64)
-                    ))
-                } else if is_string_eq!($args, $index, "PX") {
-                    // Milliseconds from now
-                    let value = get_integer_arg!($args, $index + 1);
-                    Some((
-                        SystemTime::now()
-                        + Duration::from_millis(value as u64)
-                    ))
-                } else if is_string_eq!($args, $index, "EXAT") {
-                    // Seconds since epoch
-                    let value = get_integer_arg!($args, $index + 1);
-                    Some(UNIX_EPOCH + Duration::from_secs(value as u64))
-                } else if is_string_eq!($args, $index, "PXAT") {
-                    // Milliseconds since epoch
-                    let value = get_integer_arg!($args, $index + 1);
-                    Some(UNIX_EPOCH + Duration::from_millis(value as u64))
-                } else {
-                    None
-                }
-            }
+        if let Some(logfile) = &self.logfile {
+            overrides.push(("logfile", logfile.display().to_string()));
         }
 
-        m.insert("COMMAND", Command {
-            help: String::from("Return an array with details about every Redis command"),
-            f: Box::new(|_state, args| {
-                assert_n_args!(args, 1);
-                if !is_string_eq!(args, 0, "DOCS") {
-                    return Err(String::from("Only DOCS is supported"));
-                }
-
-                // TODO: Eventually we'll want to serialize and send `COMMANDS` back
-                Ok(RedisType::Array { value: vec![] })
-            })
-        });
-
-        m.insert("APPEND", Command {
-            help: String::from("\
-APPEND key value
-
-Append value to the string stored at key. If key is not set, SET it now. 
-            "),
-            f: Box::new(|state, args| {
-                assert_n_args!{args, 2};
-                let key = get_string_arg!(args, 0);
-                let value = get_string_arg!(args, 1);
-
-                if let Some(current) = state.keystore.get_mut(&key) {
-                    current.push_str(&value);
-                } else {
-                    state.keystore.insert(key.clone(), value);
-                }
-
-                Ok(RedisType::Integer{ value: state.keystore.get(&key).unwrap().to_string().len() as i64 })
-            })
-        });
-
-        m.insert("DECR", Command {
-            help: String::from("\
-DECR key
-
-Decrement the number stored at key by one.
-
-If the key does not exist, it is set to 0 before performing the operation. An error is returned if the key contains a value of the wrong type or contains a string that can not be represented as integer. This operation is limited to 64 bit signed integers. 
-            "),
-            f: Box::new(|state, args| {
-                assert_n_args!{args, 1};
-                let key = get_string_arg!(args, 0);
-
-                if let Some(current) = state.keystore.get_mut(&key) {
-                    match current.parse::<i64>() {
-                        Ok(value) => {
-                            *current = (value - 1).to_string();
-                            Ok(RedisType::Integer{ value: value - 1 })
-                        },
-                        Err(_) => Err(String::from("Value is not an integer or out of range")),
-                    }
-                } else {
-                    state.keystore.insert(key.clone(), "-1".to_owned());
-                    Ok(RedisType::Integer{ value: -1 })
-                }
-            })
-        });
-
-        m.insert("DECRBY", Command {
-            help: String::from("\
-DECRBY key decrement
-
-Decrement the number stored at key by decrement.
-
-If the key does not exist, it is set to 0 before performing the operation. An error is returned if the key contains a value of the wrong type or contains a string that can not be represented as integer. This operation is limited to 64 bit signed integers. 
-            "),
-            f: Box::new(|state, args| {
-                assert_n_args!{args, 2};
-                let key = get_string_arg!(args, 0);
-                let decrement = get_integer_arg!(args, 1);
-
-                if let Some(current) = state.keystore.get_mut(&key) {
-                    match current.parse::<i64>() {
-                        Ok(value) => {
-                            *current = (value - decrement).to_string();
-                            Ok(RedisType::Integer{ value: value - decrement })
-                        },
-                        Err(_) => Err(String::from("Value is not an integer or out of range")),
-                    }
-                } else {
-                    state.keystore.insert(key.clone(), (0 - decrement).to_string());
-                    Ok(RedisType::Integer{ value: 0 - decrement })
-                }
-            })
-        });
-
-        m.insert("GET", Command {
-            help: String::from(""),
-            f: Box::new(|state, args| {
-                assert_n_args!(args, 1);
-                let key = get_string_arg!(args, 0);
-
-                Ok(match state.keystore.get(&key) {
-                    Some(value) => RedisType::String { value: value.to_owned() },
-                    None => RedisType::NullString,
-                })
-            })
-        });
-
-        m.insert("GETDEL", Command {
-            help: String::from("\
-GETDEL key
-
-Get the value of key and delete it. 
-            "),
-            f: Box::new(|state, args| {
-                assert_n_args!(args, 1);
-                let key = get_string_arg!(args, 0);
-
-                Ok(match state.keystore.remove(&key) {
-                    Some(value) => RedisType::String { value: value.to_owned() },
-                    None => RedisType::NullString,
-                })
-            })
-        });
-
-        m.insert("GETEX", Command {
-            help: String::from("\
-GETEX key [EX seconds | PX milliseconds | EXAT unix-time-seconds | PXAT unix-time-milliseconds | PERSIST]
-
-Get the value of key and set its expiration time. 
-            "),
-            f: Box::new(|state, args| {
-                assert_n_or_more_args!(args, 1);
-                let key = get_string_arg!(args, 0);
-
-                let mut persist = false;
-                let mut expiration = None;
-
-                if args.len() > 1 {
-                    if is_string_eq!(args, 1, "PERSIST") {
-                        persist = true;
-                    } else if let Some(ex) = get_expiration!(args, 1) {
-                        expiration = Some(ex);
-                    } else {
-                        return Err(String::from("Invalid argument"));
-                    }
-                }
-
-                if persist && expiration.is_some() {
-                    return Err(String::from("Cannot set multiple of PERSIST, EX, PX, EXAT, PXAT"));
-                }
-
-                if expiration.is_some() {
-                    tracing::debug!("Setting expiration for key {} to {:?}", key, expiration);
-                    state.ttl.push(key.clone(), expiration.unwrap());
-                } else if persist {
-                    state.ttl.remove(&key);
-                }
-
-                Ok(match state.keystore.remove(&key) {
-                    Some(value) => RedisType::String { value: value.to_owned() },
-                    None => RedisType::NullString,
-                })
-            })
-        });
-
-        m.insert("GETRANGE", Command {
-            help: String::from("\
-GETRANGE key start end
-
-Get a substring of the string stored at a key."
-            ),
-            f: Box::new(|state, args| {
-                assert_n_args!(args, 3);
-                let key = get_string_arg!(args, 0);
-                let mut start = get_integer_arg!(args, 1);
-                let mut end = get_integer_arg!(args, 2);
-
-                Ok(match state.keystore.get(&key) {
-                    Some(value) => {
-                        start = start.max(0).min(value.len() as i64 - 1);
-                        end = end.max(0).min(value.len() as i64 - 1);
-
-                        if start > end {
-                            RedisType::String { value: String::new() }
-                        } else {
-                            RedisType::String { value: value[start as usize..end as usize].to_owned() }
-                        }
-                    },
-                    None => RedisType::NullString,
-                })
-            })
-        });
-
-        m.insert("GETSET", Command {
-            help: String::from("\
-GETSET key value
-
-Set key to hold the string value and return its old value. 
-            "),
-            f: Box::new(|state, args| {
-                assert_n_args!(args, 2);
-                let key = get_string_arg!(args, 0);
-                let value = get_string_arg!(args, 1);
-
-                Ok(match state.keystore.insert(key.clone(), value.clone()) {
-                    Some(old_value) => RedisType::String { value: old_value },
-                    None => RedisType::NullString,
-                })
-            })
-        });
-
-        m.insert("INCR", Command {
-            help: String::from("\
-INCR key
-
-Increment the number stored at key by one.
-
-If the key does not exist, it is set to 0 before performing the operation. An error is returned if the key contains a value of the wrong type or contains a string that can not be represented as integer. This operation is limited to 64 bit signed integers. 
-            "),
-            f: Box::new(|state, args| {
-                assert_n_args!{args, 1};
-                let key = get_string_arg!(args, 0);
-
-                if let Some(current) = state.keystore.get_mut(&key) {
-                    match current.parse::<i64>() {
-                        Ok(value) => {
-                            *current = (value + 1).to_string();
-                            Ok(RedisType::Integer{ value: value + 1 })
-                        },
-                        Err(_) => Err(String::from("Value is not an integer or out of range")),
-                    }
-                } else {
-                    state.keystore.insert(key.clone(), "1".to_owned());
-                    Ok(RedisType::Integer{ value: 1 })
-                }
-            })
-        });
-
-        m.insert("INCRBY", Command {
-            help: String::from("\
-INCRBY key increment
-
-Increment the number stored at key by increment.
-"),
-            f: Box::new(|state, args| {
-                assert_n_args!{args, 2};
-                let key = get_string_arg!(args, 0);
-                let increment = get_integer_arg!(args, 1);
-
-                if let Some(current) = state.keystore.get_mut(&key) {
-                    match current.parse::<i64>() {
-                        Ok(value) => {
-                            *current = (value + increment).to_string();
-                            Ok(RedisType::Integer{ value: value + increment })
-                        },
-                        Err(_) => Err(String::from("Value is not an integer or out of range")),
-                    }
-                } else {
-                    state.keystore.insert(key.clone(), increment.to_string());
-                    Ok(RedisType::Integer{ value: increment })
-                }
-            })
-        });
-
-        m.insert("INCRBYFLOAT", Command {
-            help: String::from("\
-INCRBYFLOAT key increment
-
-Increment the string representing a floating point number stored at key by the specified increment. 
-            "),
-            f: Box::new(|state, args| {
-                assert_n_args!{args, 2};
-                let key = get_string_arg!(args, 0);
-                let increment = get_float_arg!(args, 1);
-
-                if let Some(current) = state.keystore.get_mut(&key) {
-                    match current.parse::<f64>() {
-                        Ok(value) => {
-                            *current = (value + increment).to_string();
-                            Ok(RedisType::String{ value: (value + increment).to_string() })
-                        },
-                        Err(_) => Err(String::from("Value is not a float")),
-                    }
-                } else {
-                    state.keystore.insert(key.clone(), increment.to_string());
-                    Ok(RedisType::String{ value: increment.to_string() })
-                }
-            })
-        });
-
-        m.insert("MGET", Command {
-            help: String::from("\
-MGET key [key ...]
-
-Get the values of all the given keys.
-
-For every key that does not hold a string value or does not exist, the special value nil is returned.
-            "),
-            f: Box::new(|state, args| {
-                assert_n_or_more_args!(args, 1);
-
-                let mut values = Vec::new();
-
-                for i in 0..args.len() {
-                    let key = get_string_arg!(args, i);
-                    match state.keystore.get(&key) {
-                        Some(value) => values.push(RedisType::String { value: value.to_owned() }),
-                        None => values.push(RedisType::NullString),
-                    }
-                }
-
-                Ok(RedisType::Array { value: values })
-            })
-        });
-
-        m.insert("MSET", Command {
-            help: String::from("\
-MSET key value [key value ...]
-
-Set multiple keys to multiple values.
-            "),
-            f: Box::new(|state, args| {
-                assert_n_or_more_args!(args, 2);
-
-                for i in (0..args.len()).step_by(2) {
-                    let key = get_string_arg!(args, i);
-                    let value = get_string_arg!(args, i + 1);
-                    state.keystore.insert(key, value);
-                }
-
-                Ok(RedisType::String { value: "OK".to_owned() })
-            })
-        });
-        
-        m.insert("MSETNX", Command {
-            help: String::from("\
-MSETNX key value [key value ...]
-
-Set multiple keys to multiple values, only if none of the keys exist.
-            "),
-            f: Box::new(|state, args| {
-                assert_n_or_more_args!(args, 2);
-
-                for i in (0..args.len()).step_by(2) {
-                    let key = get_string_arg!(args, i);
-                    if state.keystore.contains_key(&key) {
-                        return Ok(RedisType::Integer { value: 0 });
-                    }
-                }
-
-                for i in (0..args.len()).step_by(2) {
-                    let key = get_string_arg!(args, i);
-                    let value = get_string_arg!(args, i + 1);
-                    state.keystore.insert(key, value);
-                }
-
-                Ok(RedisType::Integer { value: 1 })
-            })
-        });
-
-        m.insert("PSETEX", Command {
-            help: String::from("\
-PSETEX key milliseconds value
-
-Set the value and expiration in milliseconds of a key.
-            "),
-            f: Box::new(|state, args| {
-                assert_n_args!{args, 3};
-                let key = get_string_arg!(args, 0);
-                let milliseconds = get_integer_arg!(args, 1);
-                let value = get_string_arg!(args, 2);
-
-                let expiration = SystemTime::now() + Duration::from_millis(milliseconds as u64);
-
-                state.ttl.push(key.clone(), expiration);
-                state.keystore.insert(key, value);
-                
-                Ok(RedisType::String { value: "OK".to_owned() })
-            })
-        });
-
-        m.insert("SET", Command {
-            help: String::from("\
-SET key value [NX | XX] [GET] [EX seconds | PX milliseconds | EXAT unix-time-seconds | PXAT unix-time-milliseconds | KEEPTTL]
-
-Sets key to a given value.
-
-NX|XX - only set if the key does not / does already exist.
-EX|PX|EXAT|PXAT - key expires after seconds/milliseconds or at a Unix timestamp in seconds/milliseconds
-KEEPTTL - retain the previously set TTL
-GET - return the previous value, returns NIL and doesn't return if the key wasn't set
-
-Returns OK if SET succeeded, nil if SET was not performed for NX|XX or because of GET, the old value if GET was specified. 
-            "),
-            f: Box::new(|state, args| {
-                assert_n_or_more_args!(args, 2);
-                let key = get_string_arg!(args, 0);
-                let value = get_string_arg!(args, 1);
-
-                let mut nx = false;
-                let mut xx = false;
-                let mut keepttl = false;
-                let mut get = false;
-
-                let mut expiration = None;
-
-                let mut i = 2;
-                loop {
-                    if i >= args.len() {
-                        break;
-                    } else if is_string_eq!(args, i, "NX") {
-                        nx = true;
-                        i += 1;
-                    } else if is_string_eq!(args, i, "XX") {
-                        xx = true;
-                        i += 1;
-                    } else if is_string_eq!(args, i, "KEEPTTL") {
-                        keepttl = true;
-                        i += 1;
-                    } else if is_string_eq!(args, i, "GET") {
-                        get = true;
-                        i += 1;
-                    } else if let Some(ex) = get_expiration!(args, i) {
-                        expiration = Some(ex);
-                        i+= 2;
-                    } else {
-                        return Err(String::from(format!("Unexpected parameter: {:?}", args[i])));
-                    }
-                }
-
-                if nx && xx {
-                    return Err(String::from("SET: Cannot set both NX and XX"));
-                }
-
-                if keepttl && expiration.is_some() {
-                    return Err(String::from("SET: Cannot set more than one of EX/PX/EXAT/PXAT/KEEPTTL"));
-                }
-
-                if expiration.is_some() {
-                    tracing::debug!("Setting expiration for key {} to {:?}", key, expiration);
-                    state.ttl.push(key.clone(), expiration.unwrap());
-                } else if keepttl {
-                    // do nothing
-                } else {
-                    state.ttl.remove(&key);
-                }
-
-                if nx && state.keystore.contains_key(&key) {
-                    return Ok(RedisType::NullString);
-                }
-
-                if xx && !state.keystore.contains_key(&key) {
-                    return Ok(RedisType::NullString);
-                }
-
-                let result = if get {
-                    Ok(match state.keystore.get(&key) {
-                        Some(value) => RedisType::String { value: value.to_owned() },
-                        None => RedisType::NullString,
-                    })
-                } else {
-                    Ok(RedisType::String { value: "OK".to_owned() })
-                };
-
-                state.keystore.insert(key, value);
-                result
-            })
-        });
-
-        m.insert("SETEX", Command {
-            help: String::from("\
-SETEX key seconds value
-
-Set the value and expiration of a key.
-            "),
-            f: Box::new(|state, args| {
-                assert_n_args!{args, 3};
-                let key = get_string_arg!(args, 0);
-                let seconds = get_integer_arg!(args, 1);
-                let value = get_string_arg!(args, 2);
-
-                let expiration = SystemTime::now() + Duration::from_secs(seconds as u64);
-
-                state.ttl.push(key.clone(), expiration);
-                state.keystore.insert(key, value);
-                
-                Ok(RedisType::String { value: "OK".to_owned() })
-            })
-        }); 
-
-        m.insert("SETNX", Command {
-            help: String::from("\
-SETNX key value
-
-Set the value of a key, only if the key does not exist.
-            "),
-            f: Box::new(|state, args| {
-                assert_n_args!{args, 2};
-                let key = get_string_arg!(args, 0);
-                let value = get_string_arg!(args, 1);
-
-                if state.keystore.contains_key(&key) {
-                    Ok(RedisType::Integer { value: 0 })
-                } else {
-                    state.keystore.insert(key, value);
-                    Ok(RedisType::Integer { value: 1 })
-                }
-            })
-        });
-
-        m.insert("SETRANGE", Command {
-            help: String::from("\
-SETRANGE key offset value
-
-Overwrite part of a string at key starting at the specified offset.
-            "),
-            f: Box::new(|state, args| {
-                assert_n_args!{args, 3};
-                let key = get_string_arg!(args, 0);
-                let offset = get_integer_arg!(args, 1);
-                let value = get_string_arg!(args, 2);
+        overrides
+    }
+}
 
-                let mut current_value = match state.keystore.get(&key) {
-                    Some(value) => value.to_owned(),
-                    None => String::new(),
-                };
+// Build the config from (in increasing order of precedence) defaults, file, environment and flags
+fn load_config(args: &Args) -> Result<Config, String> {
+    let mut config = Config::default();
 
-                if offset > current_value.len() as i64 {
-                    current_value.push_str(&" ".repeat((offset - current_value.len() as i64) as usize));
-                }
+    // Sentinels listen on their own port unless told otherwise
+    if args.sentinel {
+        config.port = SENTINEL_PORT;
+    }
 
-                current_value.replace_range(offset as usize.., &value);
+    if let Some(path) = &args.config {
+        // Resolve now, since the working directory changes to dir later
+        let path = path
+            .canonicalize()
+            .map_err(|e| format!("Unable to read config file {}: {e}", path.display()))?;
+        config.load_file(&path)?;
+    }
 
-                state.keystore.insert(key, current_value.clone());
+    config.apply_env()?;
 
-                Ok(RedisType::Integer { value: current_value.len() as i64 })
-            })
-        });
+    for (name, value) in args.overrides() {
+        let parameter = config::find_parameter(name).unwrap();
+        config
+            .set_override(parameter, &value)
+            .map_err(|e| format!("Invalid --{name}: {e}"))?;
+    }
 
-        m.insert("STRLEN", Command {
-            help: String::from("\
-STRLEN key
+    config.apply()?;
+    Ok(config)
+}
 
-Get the length of the value stored in a key.
-            "),
-            f: Box::new(|state, args| {
-                assert_n_args!{args, 1};
-                let key = get_string_arg!(args, 0);
+fn main() {
+    let args = Args::parse();
 
-                let value = match state.keystore.get(&key) {
-                    Some(value) => value,
-                    None => return Ok(RedisType::Integer { value: 0 }),
-                };
+    // Logging isn't set up until we know where the logs go
+    let config = match load_config(&args) {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("Invalid configuration: {e}");
+            std::process::exit(1);
+        }
+    };
 
-                Ok(RedisType::Integer { value: value.len() as i64 })
-            })
-        });
+    if let Err(e) = logging::init(config.loglevel.filter(), config.logfile.as_deref()) {
+        eprintln!("Unable to open log file: {e}");
+        std::process::exit(1);
+    }
 
-        m
+    // Sized from io-threads, so the config has to be loaded before the runtime exists
+    // With io_uring, connections get threads of their own and the runtime only runs the rest
+    let worker_threads = if config.io_uring {
+        1
+    } else {
+        config.worker_threads()
+    };
+    tracing::info!("Starting with {} io threads", config.worker_threads());
+
+    // Environment variables can only be cleared safely while there's just the one thread
+    let listeners = match systemd::take_listeners() {
+        Ok(listeners) => listeners,
+        Err(e) => {
+            tracing::error!("{e}");
+            std::process::exit(1);
+        }
     };
+
+    let mut server = Server::new(config).handle_signals().systemd(listeners);
+    if args.sentinel {
+        server = server.sentinel();
+    }
+    let result = tokio::runtime::Builder::new_multi_thread()
+        .worker_threads(worker_threads)
+        .enable_all()
+        .build()
+        .and_then(|runtime| runtime.block_on(server.run()));
+
+    if let Err(e) = result {
+        tracing::error!("{e}");
+        std::process::exit(1);
+    }
 }
@@ -0,0 +1,382 @@
+// A client for a Redis Cluster, which learns which node serves each slot from CLUSTER SLOTS on
+// one of the seed nodes it's given, then sends each command to the node serving its keys
+// For example:
+//
+//     let seeds = ["redis://10.0.0.1:7000".parse()?, "redis://10.0.0.2:7000".parse()?];
+//     let mut cluster = ClusterClient::open(&seeds).await?;
+//     cluster.set("greeting", "hello").await?;
+//
+// A MOVED redirect fetches the slots again, since a slot moving usually means others have too,
+// while an ASK redirect (for a slot part way through moving) is followed for that command only.
+// MGET, MSET, DEL, UNLINK, EXISTS and TOUCH with keys in more than one slot are split into a
+// command for each slot, so MSET is no longer atomic when they are. Other commands with keys in
+// more than one slot are sent as they are, for the server to refuse with CROSSSLOT.
+// Every node is logged in to as the first seed says.
+
+use std::collections::{BTreeMap, HashMap};
+
+use crate::client::{arg, Client, ConnectionInfo, FromRedisType, RedisError};
+use crate::cluster::key_slot;
+use crate::server::command_keys;
+use crate::RedisType;
+
+// Redirects to follow for one command before giving up, as the cluster is likely reconfiguring
+const MAX_REDIRECTS: usize = 16;
+
+type Node = (String, u16);
+
+pub struct ClusterClient {
+    // How to connect to each node, other than where it is
+    info: ConnectionInfo,
+    seeds: Vec<Node>,
+    // The first slot of each range to the last slot and the node serving them
+    slots: BTreeMap<u16, (u16, Node)>,
+    nodes: HashMap<Node, Client>,
+}
+
+impl ClusterClient {
+    // Fetch the slots from the first seed that gives them
+    pub async fn open(seeds: &[ConnectionInfo]) -> Result<ClusterClient, RedisError> {
+        let Some(info) = seeds.first() else {
+            return Err(RedisError::Io(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "no seed nodes given",
+            )));
+        };
+        let mut cluster = ClusterClient {
+            info: info.clone(),
+            seeds: seeds
+                .iter()
+                .map(|seed| (seed.host.clone(), seed.port))
+                .collect(),
+            slots: BTreeMap::new(),
+            nodes: HashMap::new(),
+        };
+        cluster.refresh(None).await?;
+        Ok(cluster)
+    }
+
+    // Fetch which node serves each slot again, such as after adding nodes to the cluster
+    pub async fn refresh_slots(&mut self) -> Result<(), RedisError> {
+        self.refresh(None).await
+    }
+
+    // Send any command to the node serving its keys (or any node if it has none), and wait for
+    // its reply
+    pub async fn command(&mut self, args: &[impl AsRef<[u8]>]) -> Result<RedisType, RedisError> {
+        self.query(args).await
+    }
+
+    pub async fn query<T: FromRedisType>(
+        &mut self,
+        args: &[impl AsRef<[u8]>],
+    ) -> Result<T, RedisError> {
+        self.call(args.iter().map(arg).collect()).await
+    }
+
+    pub async fn get(&mut self, key: impl AsRef<[u8]>) -> Result<Option<Vec<u8>>, RedisError> {
+        self.call(vec![b"GET".to_vec(), arg(key)]).await
+    }
+
+    pub async fn set(
+        &mut self,
+        key: impl AsRef<[u8]>,
+        value: impl AsRef<[u8]>,
+    ) -> Result<(), RedisError> {
+        self.call(vec![b"SET".to_vec(), arg(key), arg(value)]).await
+    }
+
+    pub async fn mget<K: AsRef<[u8]>>(
+        &mut self,
+        keys: &[K],
+    ) -> Result<Vec<Option<Vec<u8>>>, RedisError> {
+        let mut command = vec![b"MGET".to_vec()];
+        command.extend(keys.iter().map(arg));
+        self.call(command).await
+    }
+
+    pub async fn mset<K: AsRef<[u8]>, V: AsRef<[u8]>>(
+        &mut self,
+        pairs: &[(K, V)],
+    ) -> Result<(), RedisError> {
+        let mut command = vec![b"MSET".to_vec()];
+        for (key, value) in pairs {
+            command.extend([arg(key), arg(value)]);
+        }
+        self.call(command).await
+    }
+
+    pub async fn del<K: AsRef<[u8]>>(&mut self, keys: &[K]) -> Result<i64, RedisError> {
+        let mut command = vec![b"DEL".to_vec()];
+        command.extend(keys.iter().map(arg));
+        self.call(command).await
+    }
+
+    pub async fn exists<K: AsRef<[u8]>>(&mut self, keys: &[K]) -> Result<i64, RedisError> {
+        let mut command = vec![b"EXISTS".to_vec()];
+        command.extend(keys.iter().map(arg));
+        self.call(command).await
+    }
+
+    async fn call<T: FromRedisType>(&mut self, command: Vec<Vec<u8>>) -> Result<T, RedisError> {
+        let argv = command
+            .iter()
+            .map(|arg| RedisType::from(arg.clone()))
+            .collect::<Vec<_>>();
+        let mut slots = command_keys(&argv).iter().map(key_slot).collect::<Vec<_>>();
+        slots.sort_unstable();
+        slots.dedup();
+
+        if slots.len() > 1 {
+            let name = String::from_utf8_lossy(&command[0]).to_ascii_uppercase();
+            if let Some(step) = split_step(&name) {
+                return T::from_redis_type(self.fan_out(&name, &command, step).await?);
+            }
+        }
+        T::from_redis_type(self.run(slots.first().copied(), &command).await?)
+    }
+
+    // Send a command split into one for each slot its keys are in, each key taking step
+    // arguments (two for MSET's key and value), then put the replies back together as one
+    async fn fan_out(
+        &mut self,
+        name: &str,
+        command: &[Vec<u8>],
+        step: usize,
+    ) -> Result<RedisType, RedisError> {
+        // Each slot's arguments, and where its keys were in the command for MGET's reply
+        let mut parts: BTreeMap<u16, (Vec<Vec<u8>>, Vec<usize>)> = BTreeMap::new();
+        for (index, args) in command[1..].chunks(step).enumerate() {
+            let (part, indexes) = parts.entry(key_slot(&args[0])).or_default();
+            part.extend(args.iter().cloned());
+            indexes.push(index);
+        }
+
+        let mut values = vec![RedisType::NullString; (command.len() - 1) / step];
+        let mut total = 0;
+        for (slot, (args, indexes)) in parts {
+            let mut part = vec![command[0].clone()];
+            part.extend(args);
+            let reply = self.run(Some(slot), &part).await?;
+            match name {
+                "MGET" => {
+                    let replies = Vec::<RedisType>::from_redis_type(reply)?;
+                    if replies.len() != indexes.len() {
+                        return Err(RedisError::UnexpectedReply(RedisType::from(replies)));
+                    }
+                    for (index, value) in indexes.into_iter().zip(replies) {
+                        values[index] = value;
+                    }
+                }
+                "MSET" => {}
+                _ => total += i64::from_redis_type(reply)?,
+            }
+        }
+
+        Ok(match name {
+            "MGET" => RedisType::from(values),
+            "MSET" => RedisType::from(String::from("OK")),
+            _ => RedisType::from(total),
+        })
+    }
+
+    // Send a command to the node serving slot, following any redirects
+    async fn run(
+        &mut self,
+        slot: Option<u16>,
+        command: &[Vec<u8>],
+    ) -> Result<RedisType, RedisError> {
+        let mut node = self.node_for(slot);
+        let mut asking = false;
+        let mut redirects = 0;
+        loop {
+            let result = self.send_to(&node, command, asking).await;
+            self.drop_if_broken(&node);
+            let error = match result {
+                Ok(reply) => return Ok(reply),
+                Err(error) => error,
+            };
+            let Some((_, host, port)) = error.redirect() else {
+                return Err(error);
+            };
+            if redirects == MAX_REDIRECTS {
+                return Err(error);
+            }
+            redirects += 1;
+
+            // An empty host is the one the redirect came from
+            if !host.is_empty() {
+                node.0 = host.to_string();
+            }
+            node.1 = port;
+            asking = error.code() == Some("ASK");
+            if !asking {
+                self.refresh(Some(node.clone())).await?;
+            }
+        }
+    }
+
+    async fn send_to(
+        &mut self,
+        node: &Node,
+        command: &[Vec<u8>],
+        asking: bool,
+    ) -> Result<RedisType, RedisError> {
+        let client = self.client(node).await?;
+        // ASKING only lets the next command through, so it's sent again for each one
+        if asking {
+            client.call_once::<()>(&[b"ASKING".to_vec()]).await?;
+        }
+        client.call_once(command).await
+    }
+
+    // The node serving slot as far as is known, or any node for commands without keys
+    fn node_for(&self, slot: Option<u16>) -> Node {
+        let serving = slot.and_then(|slot| {
+            let (_, (end, node)) = self.slots.range(..=slot).next_back()?;
+            (slot <= *end).then(|| node.clone())
+        });
+        serving
+            .or_else(|| self.slots.values().next().map(|(_, node)| node.clone()))
+            .unwrap_or_else(|| self.seeds[0].clone())
+    }
+
+    // The connection to node, connecting if there isn't one yet
+    async fn client(&mut self, node: &Node) -> Result<&mut Client, RedisError> {
+        if !self.nodes.contains_key(node) {
+            let info = ConnectionInfo {
+                host: node.0.clone(),
+                port: node.1,
+                ..self.info.clone()
+            };
+            let client = Client::open(&info).await?;
+            self.nodes.insert(node.clone(), client);
+        }
+        Ok(self.nodes.get_mut(node).expect("just connected"))
+    }
+
+    // Drop the connection to node if a reply on it failed part way, to connect again next time
+    fn drop_if_broken(&mut self, node: &Node) {
+        if self
+            .nodes
+            .get(node)
+            .is_some_and(|client| !client.is_in_sync())
+        {
+            self.nodes.remove(node);
+        }
+    }
+
+    // Fetch the slots from first, or if it can't give them any other node known
+    async fn refresh(&mut self, first: Option<Node>) -> Result<(), RedisError> {
+        let mut candidates = Vec::new();
+        for node in first
+            .into_iter()
+            .chain(self.nodes.keys().cloned())
+            .chain(self.seeds.iter().cloned())
+        {
+            if !candidates.contains(&node) {
+                candidates.push(node);
+            }
+        }
+
+        let mut error = None;
+        for node in candidates {
+            let command = [b"CLUSTER".to_vec(), b"SLOTS".to_vec()];
+            let reply = match self.client(&node).await {
+                Ok(client) => client.call_once(&command).await,
+                Err(e) => Err(e),
+            };
+            self.drop_if_broken(&node);
+            match reply.and_then(|reply| slot_ranges(reply, &node.0)) {
+                Ok(slots) => {
+                    self.slots = slots;
+                    // Nodes that no longer serve any slots won't be needed again
+                    let serving = self
+                        .slots
+                        .values()
+                        .map(|(_, node)| node)
+                        .collect::<Vec<_>>();
+                    self.nodes.retain(|node, _| serving.contains(&node));
+                    return Ok(());
+                }
+                Err(e) => error = Some(e),
+            }
+        }
+        Err(error.expect("there is always a seed"))
+    }
+}
+
+// Multi-key commands that can be split up by slot, and how many arguments go with each key
+fn split_step(name: &str) -> Option<usize> {
+    match name {
+        "MGET" | "DEL" | "UNLINK" | "EXISTS" | "TOUCH" => Some(1),
+        "MSET" => Some(2),
+        _ => None,
+    }
+}
+
+// The ranges of slots in a CLUSTER SLOTS reply from a node on host, each with the node serving
+// them (its first node, the rest being replicas)
+fn slot_ranges(
+    reply: Vec<Vec<RedisType>>,
+    host: &str,
+) -> Result<BTreeMap<u16, (u16, Node)>, RedisError> {
+    let mut slots = BTreeMap::new();
+    for range in reply {
+        let [start, end, serving, ..] = &range[..] else {
+            return Err(RedisError::UnexpectedReply(RedisType::from(range)));
+        };
+        let serving = Vec::<RedisType>::from_redis_type(serving.clone())?;
+        let [ip, port, ..] = &serving[..] else {
+            return Err(RedisError::UnexpectedReply(RedisType::from(serving)));
+        };
+
+        let number = |reply: &RedisType| {
+            let number = i64::from_redis_type(reply.clone())?;
+            u16::try_from(number).map_err(|_| RedisError::UnexpectedReply(reply.clone()))
+        };
+        let mut ip = String::from_redis_type(ip.clone())?;
+        // As Redis does for a node that doesn't know its own address
+        if ip.is_empty() || ip == "?" {
+            ip = host.to_string();
+        }
+        slots.insert(number(start)?, (number(end)?, (ip, number(port)?)));
+    }
+    Ok(slots)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::slot_ranges;
+    use crate::RedisType;
+
+    #[test]
+    fn test_slot_ranges() {
+        let range = |start: i64, end: i64, ip: &str, port: i64| {
+            vec![
+                RedisType::from(start),
+                RedisType::from(end),
+                RedisType::from(vec![
+                    RedisType::from(String::from(ip)),
+                    RedisType::from(port),
+                    RedisType::from(String::from("id")),
+                ]),
+            ]
+        };
+        let slots = slot_ranges(
+            vec![range(0, 99, "10.0.0.1", 7000), range(100, 16383, "", 7001)],
+            "10.0.0.2",
+        )
+        .unwrap();
+        assert_eq!(
+            slots.into_iter().collect::<Vec<_>>(),
+            [
+                (0, (99, (String::from("10.0.0.1"), 7000))),
+                (100, (16383, (String::from("10.0.0.2"), 7001))),
+            ]
+        );
+
+        assert!(slot_ranges(vec![vec![RedisType::from(0)]], "host").is_err());
+        assert!(slot_ranges(vec![range(0, 99999, "host", 7000)], "host").is_err());
+    }
+}
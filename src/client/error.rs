@@ -0,0 +1,84 @@
+// What can go wrong sending a command: the connection, the server's reply, or the server refusing
+// the command
+
+use std::fmt;
+
+use crate::{RedisType, RedisTypeParseError};
+
+#[derive(Debug)]
+pub enum RedisError {
+    // Connecting, or the connection failing (including the server closing it)
+    Io(std::io::Error),
+    // What the server sent isn't valid RESP, so the connection can't be used any more
+    Parse(RedisTypeParseError),
+    // An error reply, such as "WRONGTYPE Operation against a key holding the wrong kind of value"
+    Server(String),
+    // A reply that isn't what the command returns, or can't be turned into the type asked for
+    UnexpectedReply(RedisType),
+    // A hash field missing (value is None) or holding something that can't be turned into the type
+    // of the struct field it's read into
+    InvalidField {
+        field: String,
+        value: Option<Vec<u8>>,
+    },
+}
+
+impl RedisError {
+    // The first word of an error reply, such as WRONGTYPE or MOVED, which says what went wrong
+    pub fn code(&self) -> Option<&str> {
+        match self {
+            RedisError::Server(message) => message.split_whitespace().next(),
+            _ => None,
+        }
+    }
+
+    // Where a MOVED or ASK error says the key's slot now is: the slot, and the node's host and port
+    // The host is empty if it's the same as the one the error came from.
+    pub fn redirect(&self) -> Option<(u16, &str, u16)> {
+        let RedisError::Server(message) = self else {
+            return None;
+        };
+        let mut words = message.split_whitespace();
+        if !matches!(words.next()?, "MOVED" | "ASK") {
+            return None;
+        }
+        let slot = words.next()?.parse().ok()?;
+        let (host, port) = words.next()?.rsplit_once(':')?;
+        Some((slot, host, port.parse().ok()?))
+    }
+}
+
+impl fmt::Display for RedisError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RedisError::Io(e) => write!(f, "{e}"),
+            RedisError::Parse(e) => write!(f, "Error parsing reply from server: {e:?}"),
+            RedisError::Server(message) => write!(f, "{message}"),
+            RedisError::UnexpectedReply(reply) => write!(f, "Unexpected reply: {reply:?}"),
+            RedisError::InvalidField { field, value: None } => write!(f, "Missing field: {field}"),
+            RedisError::InvalidField {
+                field,
+                value: Some(value),
+            } => write!(
+                f,
+                "Invalid value for field {field}: {}",
+                String::from_utf8_lossy(value)
+            ),
+        }
+    }
+}
+
+impl std::error::Error for RedisError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            RedisError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for RedisError {
+    fn from(e: std::io::Error) -> Self {
+        RedisError::Io(e)
+    }
+}
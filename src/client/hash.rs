@@ -0,0 +1,162 @@
+// Storing a struct as a hash, a field for each of its fields, which the ToRedisHash and
+// FromRedisHash derives in redis_rs_derive write for it
+// For example:
+//
+//     #[derive(ToRedisHash, FromRedisHash)]
+//     struct User {
+//         name: String,
+//         age: u32,
+//         #[redis(rename = "mail")]
+//         email: Option<String>,
+//     }
+//
+//     client.hset_struct("user:1", &user).await?;
+//     let user: Option<User> = client.hget_struct("user:1").await?;
+//
+// Fields that are None aren't written (so any value already in the hash is kept), and are None if
+// they're missing when read. #[redis(skip)] leaves a field out, taking Default::default() when read.
+
+use std::collections::HashMap;
+
+use crate::client::{arg, Client, RedisError};
+
+pub trait ToRedisHash {
+    // Each field's name and value, leaving out those without a value
+    fn to_redis_hash(&self) -> Vec<(Vec<u8>, Vec<u8>)>;
+}
+
+pub trait FromRedisHash: Sized {
+    // Fields that are used are taken out of fields, anything left over is ignored
+    fn from_redis_hash(fields: &mut HashMap<Vec<u8>, Vec<u8>>) -> Result<Self, RedisError>;
+}
+
+// A type that can be a field of a struct stored as a hash
+pub trait HashValue: Sized {
+    // None to leave the field out
+    fn to_hash_value(&self) -> Option<Vec<u8>>;
+
+    // None if the value (or lack of one) can't be this type
+    fn from_hash_value(value: Option<&[u8]>) -> Option<Self>;
+}
+
+impl HashValue for Vec<u8> {
+    fn to_hash_value(&self) -> Option<Vec<u8>> {
+        Some(self.clone())
+    }
+
+    fn from_hash_value(value: Option<&[u8]>) -> Option<Self> {
+        value.map(<[u8]>::to_vec)
+    }
+}
+
+impl HashValue for String {
+    fn to_hash_value(&self) -> Option<Vec<u8>> {
+        Some(self.as_bytes().to_vec())
+    }
+
+    fn from_hash_value(value: Option<&[u8]>) -> Option<Self> {
+        String::from_utf8(value?.to_vec()).ok()
+    }
+}
+
+// Written as 1 or 0, as Redis does, but true and false can be read too
+impl HashValue for bool {
+    fn to_hash_value(&self) -> Option<Vec<u8>> {
+        Some(if *self { b"1" } else { b"0" }.to_vec())
+    }
+
+    fn from_hash_value(value: Option<&[u8]>) -> Option<Self> {
+        match value? {
+            b"1" | b"true" => Some(true),
+            b"0" | b"false" => Some(false),
+            _ => None,
+        }
+    }
+}
+
+impl<T: HashValue> HashValue for Option<T> {
+    fn to_hash_value(&self) -> Option<Vec<u8>> {
+        self.as_ref()?.to_hash_value()
+    }
+
+    fn from_hash_value(value: Option<&[u8]>) -> Option<Self> {
+        match value {
+            Some(value) => T::from_hash_value(Some(value)).map(Some),
+            None => Some(None),
+        }
+    }
+}
+
+// Numbers are written as text, so the hash can be read (and changed with HINCRBY) by anything else
+macro_rules! number_hash_value {
+    ($($t:ty),*) => {
+        $(
+            impl HashValue for $t {
+                fn to_hash_value(&self) -> Option<Vec<u8>> {
+                    Some(self.to_string().into_bytes())
+                }
+
+                fn from_hash_value(value: Option<&[u8]>) -> Option<Self> {
+                    std::str::from_utf8(value?).ok()?.parse().ok()
+                }
+            }
+        )*
+    };
+}
+
+number_hash_value!(i8, i16, i32, i64, i128, isize, u8, u16, u32, u64, u128, usize, f32, f64);
+
+impl Client {
+    // HSET each of value's fields, returning how many weren't in the hash before
+    pub async fn hset_struct<T: ToRedisHash>(
+        &mut self,
+        key: impl AsRef<[u8]>,
+        value: &T,
+    ) -> Result<i64, RedisError> {
+        let fields = value.to_redis_hash();
+        // HSET needs at least one field
+        if fields.is_empty() {
+            return Ok(0);
+        }
+        let mut command = vec![b"HSET".to_vec(), arg(key)];
+        for (field, value) in fields {
+            command.extend([field, value]);
+        }
+        self.call(command).await
+    }
+
+    // HGETALL, as a T; None if the key doesn't exist
+    pub async fn hget_struct<T: FromRedisHash>(
+        &mut self,
+        key: impl AsRef<[u8]>,
+    ) -> Result<Option<T>, RedisError> {
+        let mut fields: HashMap<Vec<u8>, Vec<u8>> =
+            self.call(vec![b"HGETALL".to_vec(), arg(key)]).await?;
+        if fields.is_empty() {
+            return Ok(None);
+        }
+        T::from_redis_hash(&mut fields).map(Some)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::HashValue;
+
+    #[test]
+    fn test_hash_value() {
+        assert_eq!(42u32.to_hash_value(), Some(b"42".to_vec()));
+        assert_eq!(u32::from_hash_value(Some(b"42")), Some(42));
+        assert_eq!(u8::from_hash_value(Some(b"300")), None);
+        assert_eq!(f64::from_hash_value(Some(b"1.5")), Some(1.5));
+        assert_eq!(i64::from_hash_value(None), None);
+
+        assert_eq!(true.to_hash_value(), Some(b"1".to_vec()));
+        assert_eq!(bool::from_hash_value(Some(b"false")), Some(false));
+        assert_eq!(String::from_hash_value(Some(b"\xff")), None);
+
+        assert_eq!(None::<String>.to_hash_value(), None);
+        assert_eq!(Option::<String>::from_hash_value(None), Some(None));
+        assert_eq!(Option::<u32>::from_hash_value(Some(b"x")), None);
+    }
+}
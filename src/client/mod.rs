@@ -0,0 +1,422 @@
+// A client for programs to talk to a Redis (or redis-rs) server with, which has a method for each
+// common command that gives back its reply as the Rust type it stands for
+// For example:
+//
+//     let mut client = Client::connect("127.0.0.1:6379").await?;
+//     client.set("greeting", "hello").await?;
+//     let greeting: Option<Vec<u8>> = client.get("greeting").await?;
+//
+// Any other command can be sent with command(), which returns the reply as it was sent.
+
+mod cluster;
+mod error;
+mod hash;
+mod info;
+mod pool;
+mod pubsub;
+mod reply;
+mod retry;
+mod scan;
+mod tls;
+mod transaction;
+
+pub use cluster::ClusterClient;
+pub use error::RedisError;
+pub use hash::{FromRedisHash, HashValue, ToRedisHash};
+pub use info::ConnectionInfo;
+pub use pool::{Pool, PoolBuilder, PoolStats, PooledClient};
+pub use pubsub::{Message, PubSub};
+#[cfg(feature = "derive")]
+pub use redis_rs_derive::{FromRedisHash, ToRedisHash};
+pub use reply::FromRedisType;
+pub use retry::{Retry, RetryOn, RetryPolicy};
+pub use scan::Scan;
+pub use tls::start_tls;
+pub use transaction::Transaction;
+
+use std::future::Future;
+use std::time::Duration;
+
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::net::{TcpStream, ToSocketAddrs};
+
+use crate::value::Value;
+use crate::{Protocol, RedisType, RedisTypeParseError};
+
+trait Stream: AsyncRead + AsyncWrite + Unpin + Send {}
+
+impl<T: AsyncRead + AsyncWrite + Unpin + Send> Stream for T {}
+
+pub struct Client {
+    stream: Box<dyn Stream>,
+    // Where the server is and how to log in, to connect again with if the connection is lost
+    info: ConnectionInfo,
+    // Read from the server but not yet parsed into a complete reply
+    buffer: Vec<u8>,
+    // Set while a command is waiting for its reply, so if that's given up on (or fails part way
+    // through) it's known that the next reply read might not be for the next command
+    pending: bool,
+    retry: RetryPolicy,
+}
+
+impl Client {
+    pub async fn connect(addr: impl ToSocketAddrs) -> Result<Client, RedisError> {
+        let stream = TcpStream::connect(addr).await?;
+        stream.set_nodelay(true)?;
+        let addr = stream.peer_addr()?;
+        let info = ConnectionInfo {
+            host: addr.ip().to_string(),
+            port: addr.port(),
+            ..ConnectionInfo::default()
+        };
+        Ok(Client::new(Box::new(stream), info))
+    }
+
+    // Connect as info says, such as to a URL parsed into one, over TLS if it says to, then log in
+    // and select its database
+    pub async fn open(info: &ConnectionInfo) -> Result<Client, RedisError> {
+        let connecting = async {
+            let tcp = TcpStream::connect((info.host.as_str(), info.port)).await?;
+            tcp.set_nodelay(true)?;
+            let stream: Box<dyn Stream> = if info.tls {
+                Box::new(start_tls(tcp, &info.host, None).await?)
+            } else {
+                Box::new(tcp)
+            };
+            Ok::<_, RedisError>(stream)
+        };
+        let stream = within(info.connect_timeout, "connecting", connecting).await?;
+        let mut client = Client::new(stream, info.clone());
+
+        if let Some(password) = &info.password {
+            let mut auth = vec![b"AUTH".to_vec()];
+            auth.extend(info.username.clone().map(String::into_bytes));
+            auth.push(password.clone().into_bytes());
+            client.call_once::<()>(&auth).await?;
+        }
+        if info.db != 0 {
+            let select = vec![b"SELECT".to_vec(), arg(info.db.to_string())];
+            client.call_once::<()>(&select).await?;
+        }
+        Ok(client)
+    }
+
+    fn new(stream: Box<dyn Stream>, info: ConnectionInfo) -> Client {
+        Client {
+            stream,
+            info,
+            buffer: Vec::new(),
+            pending: false,
+            retry: RetryPolicy::default(),
+        }
+    }
+
+    // When to try commands again after they fail, and how long to wait first; by default they
+    // aren't
+    pub fn set_retry_policy(&mut self, retry: RetryPolicy) {
+        self.retry = retry;
+    }
+
+    // Whether every command sent has had its whole reply, so the next reply will be for the next
+    // command; if not the connection has to be dropped
+    pub fn is_in_sync(&self) -> bool {
+        !self.pending
+    }
+
+    // Send any command, such as ["CONFIG", "GET", "port"], and wait for its reply
+    // An error reply is returned as RedisError::Server.
+    pub async fn command(&mut self, args: &[impl AsRef<[u8]>]) -> Result<RedisType, RedisError> {
+        self.query(args).await
+    }
+
+    // The same as command(), but turning the reply into T, such as a HashMap<String, String> from
+    // ["CONFIG", "GET", "*"]
+    pub async fn query<T: FromRedisType>(
+        &mut self,
+        args: &[impl AsRef<[u8]>],
+    ) -> Result<T, RedisError> {
+        self.call(args.iter().map(arg).collect()).await
+    }
+
+    pub async fn ping(&mut self) -> Result<(), RedisError> {
+        self.call(vec![b"PING".to_vec()]).await
+    }
+
+    // None if the key doesn't exist
+    pub async fn get(&mut self, key: impl AsRef<[u8]>) -> Result<Option<Vec<u8>>, RedisError> {
+        self.call(vec![b"GET".to_vec(), arg(key)]).await
+    }
+
+    pub async fn set(
+        &mut self,
+        key: impl AsRef<[u8]>,
+        value: impl AsRef<[u8]>,
+    ) -> Result<(), RedisError> {
+        self.call(vec![b"SET".to_vec(), arg(key), arg(value)]).await
+    }
+
+    // Set a key that expires after the given number of seconds
+    pub async fn set_ex(
+        &mut self,
+        key: impl AsRef<[u8]>,
+        value: impl AsRef<[u8]>,
+        seconds: u64,
+    ) -> Result<(), RedisError> {
+        let command = vec![
+            b"SET".to_vec(),
+            arg(key),
+            arg(value),
+            b"EX".to_vec(),
+            arg(seconds.to_string()),
+        ];
+        self.call(command).await
+    }
+
+    // Set a key only if it doesn't exist yet, returning whether it was set
+    pub async fn set_nx(
+        &mut self,
+        key: impl AsRef<[u8]>,
+        value: impl AsRef<[u8]>,
+    ) -> Result<bool, RedisError> {
+        self.call(vec![b"SETNX".to_vec(), arg(key), arg(value)])
+            .await
+    }
+
+    // Get a key's value and delete it
+    pub async fn get_del(&mut self, key: impl AsRef<[u8]>) -> Result<Option<Vec<u8>>, RedisError> {
+        self.call(vec![b"GETDEL".to_vec(), arg(key)]).await
+    }
+
+    // The value of each key, in order, with None for those that don't exist
+    pub async fn mget<K: AsRef<[u8]>>(
+        &mut self,
+        keys: &[K],
+    ) -> Result<Vec<Option<Vec<u8>>>, RedisError> {
+        let mut command = vec![b"MGET".to_vec()];
+        command.extend(keys.iter().map(arg));
+        self.call(command).await
+    }
+
+    pub async fn mset<K: AsRef<[u8]>, V: AsRef<[u8]>>(
+        &mut self,
+        pairs: &[(K, V)],
+    ) -> Result<(), RedisError> {
+        let mut command = vec![b"MSET".to_vec()];
+        for (key, value) in pairs {
+            command.extend([arg(key), arg(value)]);
+        }
+        self.call(command).await
+    }
+
+    // The value after adding one, treating a key that doesn't exist as 0
+    pub async fn incr(&mut self, key: impl AsRef<[u8]>) -> Result<i64, RedisError> {
+        self.call(vec![b"INCR".to_vec(), arg(key)]).await
+    }
+
+    pub async fn incr_by(&mut self, key: impl AsRef<[u8]>, by: i64) -> Result<i64, RedisError> {
+        self.call(vec![b"INCRBY".to_vec(), arg(key), arg(by.to_string())])
+            .await
+    }
+
+    pub async fn decr(&mut self, key: impl AsRef<[u8]>) -> Result<i64, RedisError> {
+        self.call(vec![b"DECR".to_vec(), arg(key)]).await
+    }
+
+    // The length of the value after appending
+    pub async fn append(
+        &mut self,
+        key: impl AsRef<[u8]>,
+        value: impl AsRef<[u8]>,
+    ) -> Result<i64, RedisError> {
+        self.call(vec![b"APPEND".to_vec(), arg(key), arg(value)])
+            .await
+    }
+
+    pub async fn strlen(&mut self, key: impl AsRef<[u8]>) -> Result<i64, RedisError> {
+        self.call(vec![b"STRLEN".to_vec(), arg(key)]).await
+    }
+
+    // How many of the keys there were to delete
+    pub async fn del<K: AsRef<[u8]>>(&mut self, keys: &[K]) -> Result<i64, RedisError> {
+        let mut command = vec![b"DEL".to_vec()];
+        command.extend(keys.iter().map(arg));
+        self.call(command).await
+    }
+
+    // How many of the keys exist, counting a key given twice twice
+    pub async fn exists<K: AsRef<[u8]>>(&mut self, keys: &[K]) -> Result<i64, RedisError> {
+        let mut command = vec![b"EXISTS".to_vec()];
+        command.extend(keys.iter().map(arg));
+        self.call(command).await
+    }
+
+    // Whether the key exists to be given the timeout
+    pub async fn expire(
+        &mut self,
+        key: impl AsRef<[u8]>,
+        seconds: i64,
+    ) -> Result<bool, RedisError> {
+        self.call(vec![b"EXPIRE".to_vec(), arg(key), arg(seconds.to_string())])
+            .await
+    }
+
+    // Whether the key had a timeout to remove
+    pub async fn persist(&mut self, key: impl AsRef<[u8]>) -> Result<bool, RedisError> {
+        self.call(vec![b"PERSIST".to_vec(), arg(key)]).await
+    }
+
+    // Seconds until the key expires, -1 if it never does, or -2 if it doesn't exist, as TTL
+    // replies
+    pub async fn ttl(&mut self, key: impl AsRef<[u8]>) -> Result<i64, RedisError> {
+        self.call(vec![b"TTL".to_vec(), arg(key)]).await
+    }
+
+    // Send a command and turn its reply into T, trying again as the retry policy says
+    async fn call<T: FromRedisType>(&mut self, command: Vec<Vec<u8>>) -> Result<T, RedisError> {
+        let mut attempt = 1;
+        let mut result = self.call_once(&command).await;
+        loop {
+            let error = match result {
+                Ok(reply) => return Ok(reply),
+                Err(error) => error,
+            };
+            let Some(delay) = self.retry.delay(&error, attempt) else {
+                return Err(error);
+            };
+            self.retry.notify(&Retry {
+                attempt,
+                delay,
+                error: &error,
+            });
+            tokio::time::sleep(delay).await;
+            attempt += 1;
+
+            result = match self.recover(&error).await {
+                Ok(()) => self.call_once(&command).await,
+                Err(error) => Err(error),
+            };
+        }
+    }
+
+    // Get ready to retry after error: connecting again if the connection failed (since a reply
+    // could still be on its way), or to the node a MOVED redirect names
+    async fn recover(&mut self, error: &RedisError) -> Result<(), RedisError> {
+        match RetryOn::of(error) {
+            Some(RetryOn::Connection) => {}
+            Some(RetryOn::Moved) => {
+                let Some((_, host, port)) = error.redirect() else {
+                    return Ok(());
+                };
+                if !host.is_empty() {
+                    self.info.host = host.to_string();
+                }
+                self.info.port = port;
+            }
+            _ => return Ok(()),
+        }
+        let mut client = Client::open(&self.info).await?;
+        client.retry = std::mem::take(&mut self.retry);
+        *self = client;
+        Ok(())
+    }
+
+    // If the reply times out it could still come, so the connection is left out of step
+    async fn call_once<T: FromRedisType>(&mut self, command: &[Vec<u8>]) -> Result<T, RedisError> {
+        let timeout = self.info.response_timeout;
+        let exchange = async {
+            self.pending = true;
+            self.send(command.to_vec()).await?;
+            self.read().await
+        };
+        let reply = within(timeout, "waiting for a reply", exchange).await?;
+        self.pending = false;
+
+        match reply {
+            RedisType::Error { value } => Err(RedisError::Server(value)),
+            reply => T::from_redis_type(reply),
+        }
+    }
+
+    async fn send(&mut self, command: Vec<Vec<u8>>) -> Result<(), RedisError> {
+        // Always bulk strings, as servers expect
+        let array = RedisType::from(
+            command
+                .into_iter()
+                .map(|arg| RedisType::Bulk {
+                    value: Value::from(arg),
+                })
+                .collect::<Vec<_>>(),
+        );
+        for segment in array.encode_segments(Protocol::Resp2) {
+            self.stream.write_all(&segment).await?;
+        }
+        Ok(())
+    }
+
+    // The next reply from the server
+    // Push messages (which only come after the client asks for them, such as with CLIENT TRACKING)
+    // are skipped.
+    async fn read(&mut self) -> Result<RedisType, RedisError> {
+        loop {
+            match self.read_frame().await? {
+                RedisType::Push { .. } => continue,
+                reply => return Ok(reply),
+            }
+        }
+    }
+
+    // The next thing the server sent, push messages included
+    async fn read_frame(&mut self) -> Result<RedisType, RedisError> {
+        read_value(&mut self.stream, &mut self.buffer).await
+    }
+}
+
+// The next value read from stream, reading into buffer as much as it takes
+// Anything after it stays buffered for the next call.
+pub async fn read_value(
+    stream: &mut (impl AsyncRead + Unpin),
+    buffer: &mut Vec<u8>,
+) -> Result<RedisType, RedisError> {
+    loop {
+        if !buffer.is_empty() {
+            match RedisType::parse_prefix(&*buffer) {
+                Ok((reply, len)) => {
+                    buffer.drain(..len);
+                    return Ok(reply);
+                }
+                Err(RedisTypeParseError::Incomplete) => {}
+                Err(e) => return Err(RedisError::Parse(e)),
+            }
+        }
+
+        buffer.reserve(16 * 1024);
+        if stream.read_buf(&mut *buffer).await? == 0 {
+            return Err(RedisError::Io(std::io::Error::new(
+                std::io::ErrorKind::UnexpectedEof,
+                "server closed the connection",
+            )));
+        }
+    }
+}
+
+// What future gives, or a TimedOut error saying what was taking too long if it takes longer than
+// timeout
+pub async fn within<T, E: From<std::io::Error>>(
+    timeout: Option<Duration>,
+    doing: &str,
+    future: impl Future<Output = Result<T, E>>,
+) -> Result<T, E> {
+    match timeout {
+        Some(timeout) => tokio::time::timeout(timeout, future).await.map_err(|_| {
+            E::from(std::io::Error::new(
+                std::io::ErrorKind::TimedOut,
+                format!("timed out {doing}"),
+            ))
+        })?,
+        None => future.await,
+    }
+}
+
+fn arg(arg: impl AsRef<[u8]>) -> Vec<u8> {
+    arg.as_ref().to_vec()
+}
@@ -0,0 +1,228 @@
+// Connections shared between tasks, such as the handlers of a web service, so each doesn't have to
+// connect for itself
+// For example:
+//
+//     let pool = Pool::builder("127.0.0.1:6379").max_size(16).min_idle(2).build().await?;
+//     let mut client = pool.get().await?;
+//     client.incr("hits").await?;
+//
+// The client goes back to the pool when it's dropped.
+
+use std::net::SocketAddr;
+use std::ops::{Deref, DerefMut};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use tokio::net::{lookup_host, ToSocketAddrs};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+use crate::client::{within, Client, RedisError};
+
+const DEFAULT_MAX_SIZE: usize = 10;
+const DEFAULT_CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Clone)]
+pub struct Pool {
+    inner: Arc<Inner>,
+}
+
+struct Inner {
+    addrs: Vec<SocketAddr>,
+    connect_timeout: Duration,
+    // Connected and not in use, most recently returned last
+    idle: Mutex<Vec<Client>>,
+    // One permit for each connection that can be open, idle or not
+    permits: Arc<Semaphore>,
+    max_size: usize,
+    connections_opened: AtomicU64,
+    connections_closed: AtomicU64,
+    checkouts: AtomicU64,
+    failed_checks: AtomicU64,
+    // Tasks waiting in get() for a connection to be returned
+    waiting: AtomicU64,
+}
+
+pub struct PoolBuilder<A> {
+    addr: A,
+    max_size: usize,
+    min_idle: usize,
+    connect_timeout: Duration,
+}
+
+impl<A: ToSocketAddrs> PoolBuilder<A> {
+    // The most connections open at once; get() waits for one to be returned after that
+    pub fn max_size(mut self, max_size: usize) -> Self {
+        self.max_size = max_size.max(1);
+        self
+    }
+
+    // How many connections to open straight away, so the first requests don't have to
+    pub fn min_idle(mut self, min_idle: usize) -> Self {
+        self.min_idle = min_idle;
+        self
+    }
+
+    // How long to wait for the server to accept a connection before giving up
+    pub fn connect_timeout(mut self, timeout: Duration) -> Self {
+        self.connect_timeout = timeout;
+        self
+    }
+
+    // Look up the address and open the first min_idle connections
+    pub async fn build(self) -> Result<Pool, RedisError> {
+        let addrs = lookup_host(self.addr).await?.collect::<Vec<_>>();
+        let pool = Pool {
+            inner: Arc::new(Inner {
+                addrs,
+                connect_timeout: self.connect_timeout,
+                idle: Mutex::new(Vec::new()),
+                permits: Arc::new(Semaphore::new(self.max_size)),
+                max_size: self.max_size,
+                connections_opened: AtomicU64::new(0),
+                connections_closed: AtomicU64::new(0),
+                checkouts: AtomicU64::new(0),
+                failed_checks: AtomicU64::new(0),
+                waiting: AtomicU64::new(0),
+            }),
+        };
+
+        let mut idle = Vec::new();
+        for _ in 0..self.min_idle.min(self.max_size) {
+            idle.push(pool.connect().await?);
+        }
+        *pool.inner.idle.lock().unwrap() = idle;
+        Ok(pool)
+    }
+}
+
+// How the pool is being used, such as to report as metrics
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct PoolStats {
+    pub max_size: usize,
+    // Open connections, in use or idle
+    pub connections: usize,
+    pub idle: usize,
+    pub in_use: usize,
+    // Tasks waiting for a connection because all of them are in use
+    pub waiting: u64,
+    pub connections_opened: u64,
+    pub connections_closed: u64,
+    pub checkouts: u64,
+    // Idle connections that didn't answer PING when checked out, and were closed
+    pub failed_checks: u64,
+}
+
+impl Pool {
+    pub fn builder<A: ToSocketAddrs>(addr: A) -> PoolBuilder<A> {
+        PoolBuilder {
+            addr,
+            max_size: DEFAULT_MAX_SIZE,
+            min_idle: 0,
+            connect_timeout: DEFAULT_CONNECT_TIMEOUT,
+        }
+    }
+
+    // A connection to use, which goes back to the pool when it's dropped
+    // An idle connection is checked with a PING first, and replaced if it doesn't answer. If every
+    // connection is in use, this waits for one to be returned.
+    pub async fn get(&self) -> Result<PooledClient, RedisError> {
+        let permit = match self.inner.permits.clone().try_acquire_owned() {
+            Ok(permit) => permit,
+            Err(_) => {
+                self.inner.waiting.fetch_add(1, Ordering::Relaxed);
+                let permit = self.inner.permits.clone().acquire_owned().await;
+                self.inner.waiting.fetch_sub(1, Ordering::Relaxed);
+                permit.expect("the semaphore is never closed")
+            }
+        };
+        self.inner.checkouts.fetch_add(1, Ordering::Relaxed);
+
+        loop {
+            let idle = self.inner.idle.lock().unwrap().pop();
+            let Some(mut client) = idle else {
+                break;
+            };
+            if client.ping().await.is_ok() {
+                return Ok(self.pooled(client, permit));
+            }
+            self.inner.failed_checks.fetch_add(1, Ordering::Relaxed);
+            self.inner
+                .connections_closed
+                .fetch_add(1, Ordering::Relaxed);
+        }
+
+        let client = self.connect().await?;
+        Ok(self.pooled(client, permit))
+    }
+
+    pub fn stats(&self) -> PoolStats {
+        let inner = &self.inner;
+        let idle = inner.idle.lock().unwrap().len();
+        let in_use = inner.max_size - inner.permits.available_permits();
+        PoolStats {
+            max_size: inner.max_size,
+            connections: idle + in_use,
+            idle,
+            in_use,
+            waiting: inner.waiting.load(Ordering::Relaxed),
+            connections_opened: inner.connections_opened.load(Ordering::Relaxed),
+            connections_closed: inner.connections_closed.load(Ordering::Relaxed),
+            checkouts: inner.checkouts.load(Ordering::Relaxed),
+            failed_checks: inner.failed_checks.load(Ordering::Relaxed),
+        }
+    }
+
+    async fn connect(&self) -> Result<Client, RedisError> {
+        let connecting = Client::connect(&self.inner.addrs[..]);
+        let client = within(Some(self.inner.connect_timeout), "connecting", connecting).await?;
+        self.inner
+            .connections_opened
+            .fetch_add(1, Ordering::Relaxed);
+        Ok(client)
+    }
+
+    fn pooled(&self, client: Client, permit: OwnedSemaphorePermit) -> PooledClient {
+        PooledClient {
+            client: Some(client),
+            pool: self.inner.clone(),
+            _permit: permit,
+        }
+    }
+}
+
+// A client checked out of a pool, used as a Client
+pub struct PooledClient {
+    // Only taken when it's dropped
+    client: Option<Client>,
+    pool: Arc<Inner>,
+    // Released after the client is back in the pool, for the next get() to find
+    _permit: OwnedSemaphorePermit,
+}
+
+impl Deref for PooledClient {
+    type Target = Client;
+
+    fn deref(&self) -> &Client {
+        self.client.as_ref().unwrap()
+    }
+}
+
+impl DerefMut for PooledClient {
+    fn deref_mut(&mut self) -> &mut Client {
+        self.client.as_mut().unwrap()
+    }
+}
+
+// A connection left waiting for a reply (because a command was cancelled or failed part way) is
+// closed rather than returned, since its next reply could be the wrong one
+impl Drop for PooledClient {
+    fn drop(&mut self) {
+        let client = self.client.take().unwrap();
+        if client.is_in_sync() {
+            self.pool.idle.lock().unwrap().push(client);
+        } else {
+            self.pool.connections_closed.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+}
@@ -0,0 +1,265 @@
+// Listening for messages published to channels (or channels matching patterns), on a connection
+// given over to that
+// For example:
+//
+//     let mut pubsub = Client::connect("127.0.0.1:6379").await?.into_pubsub();
+//     pubsub.subscribe(&["news"]).await?;
+//     pubsub.psubscribe(&["sport.*"]).await?;
+//     while let Ok(message) = pubsub.next().await {
+//         println!("{:?}: {:?}", message.channel, message.payload);
+//     }
+//
+// If the connection is lost, next() connects again and subscribes to everything it was subscribed
+// to before carrying on. Anything published in between is missed.
+
+use std::collections::{BTreeSet, VecDeque};
+
+use crate::client::{Client, FromRedisType, RedisError};
+use crate::RedisType;
+
+pub struct PubSub {
+    client: Client,
+    channels: BTreeSet<Vec<u8>>,
+    patterns: BTreeSet<Vec<u8>>,
+    // Messages that came while waiting for a subscription to be confirmed
+    queued: VecDeque<Message>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Message {
+    pub channel: Vec<u8>,
+    // The pattern the channel matched, for messages from psubscribe()
+    pub pattern: Option<Vec<u8>>,
+    pub payload: Vec<u8>,
+}
+
+// What a subscribed connection can be sent (as push messages with RESP3, or arrays before that)
+#[derive(Debug, PartialEq)]
+enum Event {
+    Message(Message),
+    // Replies to SUBSCRIBE and the like, one for each channel, such as subscribe or punsubscribe
+    Confirmed(String),
+    // Such as the reply to PING
+    Other,
+}
+
+impl Client {
+    // Use the connection for Pub/Sub, after which it can't be used for other commands
+    pub fn into_pubsub(self) -> PubSub {
+        PubSub {
+            client: self,
+            channels: BTreeSet::new(),
+            patterns: BTreeSet::new(),
+            queued: VecDeque::new(),
+        }
+    }
+}
+
+impl PubSub {
+    pub async fn subscribe<C: AsRef<[u8]>>(&mut self, channels: &[C]) -> Result<(), RedisError> {
+        let channels = names(channels);
+        self.channels.extend(channels.iter().cloned());
+        self.request("SUBSCRIBE", channels).await
+    }
+
+    // Patterns are globs, such as news.* or user:[0-9]*
+    pub async fn psubscribe<P: AsRef<[u8]>>(&mut self, patterns: &[P]) -> Result<(), RedisError> {
+        let patterns = names(patterns);
+        self.patterns.extend(patterns.iter().cloned());
+        self.request("PSUBSCRIBE", patterns).await
+    }
+
+    // No channels unsubscribes from all of them
+    pub async fn unsubscribe<C: AsRef<[u8]>>(&mut self, channels: &[C]) -> Result<(), RedisError> {
+        let channels = names(channels);
+        let replies = unsubscribed(&mut self.channels, &channels);
+        self.send("UNSUBSCRIBE", channels, replies).await
+    }
+
+    // No patterns unsubscribes from all of them
+    pub async fn punsubscribe<P: AsRef<[u8]>>(&mut self, patterns: &[P]) -> Result<(), RedisError> {
+        let patterns = names(patterns);
+        let replies = unsubscribed(&mut self.patterns, &patterns);
+        self.send("PUNSUBSCRIBE", patterns, replies).await
+    }
+
+    pub fn channels(&self) -> impl Iterator<Item = &[u8]> {
+        self.channels.iter().map(Vec::as_slice)
+    }
+
+    pub fn patterns(&self) -> impl Iterator<Item = &[u8]> {
+        self.patterns.iter().map(Vec::as_slice)
+    }
+
+    // The next message published to any of the channels subscribed to, waiting until there is one
+    // An error means the connection was lost and couldn't be made again; calling this again tries
+    // again.
+    pub async fn next(&mut self) -> Result<Message, RedisError> {
+        let mut reconnected = false;
+        loop {
+            if let Some(message) = self.queued.pop_front() {
+                return Ok(message);
+            }
+            let frame = match self.client.read_frame().await {
+                Ok(frame) => frame,
+                // Only once, so a server that keeps closing the connection isn't retried forever
+                Err(RedisError::Io(_) | RedisError::Parse(_)) if !reconnected => {
+                    self.reconnect().await?;
+                    reconnected = true;
+                    continue;
+                }
+                Err(e) => return Err(e),
+            };
+            if let Event::Message(message) = event(frame)? {
+                return Ok(message);
+            }
+        }
+    }
+
+    // SUBSCRIBE or PSUBSCRIBE, which are an error without anything to subscribe to
+    async fn request(&mut self, command: &str, names: Vec<Vec<u8>>) -> Result<(), RedisError> {
+        if names.is_empty() {
+            return Ok(());
+        }
+        let replies = names.len();
+        self.send(command, names, replies).await
+    }
+
+    // Send the command and wait for the server to confirm it, once for each of the names
+    async fn send(
+        &mut self,
+        command: &str,
+        names: Vec<Vec<u8>>,
+        mut replies: usize,
+    ) -> Result<(), RedisError> {
+        let mut message = vec![command.as_bytes().to_vec()];
+        message.extend(names);
+        self.client.send(message).await?;
+
+        // Messages that come first are kept for next()
+        let kind = command.to_lowercase();
+        while replies > 0 {
+            match event(self.client.read_frame().await?)? {
+                Event::Message(message) => self.queued.push_back(message),
+                Event::Confirmed(confirmed) if confirmed == kind => replies -= 1,
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    async fn reconnect(&mut self) -> Result<(), RedisError> {
+        self.client = Client::open(&self.client.info).await?;
+        let channels = self.channels.iter().cloned().collect();
+        self.request("SUBSCRIBE", channels).await?;
+        let patterns = self.patterns.iter().cloned().collect();
+        self.request("PSUBSCRIBE", patterns).await
+    }
+}
+
+fn names<N: AsRef<[u8]>>(names: &[N]) -> Vec<Vec<u8>> {
+    names.iter().map(|name| name.as_ref().to_vec()).collect()
+}
+
+// Forget what's being unsubscribed from (everything, if nothing is given), returning how many
+// confirmations the server will send: one for each, or one if there's nothing to unsubscribe from
+fn unsubscribed(subscribed: &mut BTreeSet<Vec<u8>>, names: &[Vec<u8>]) -> usize {
+    if names.is_empty() {
+        let replies = subscribed.len().max(1);
+        subscribed.clear();
+        return replies;
+    }
+    for name in names {
+        subscribed.remove(name);
+    }
+    names.len()
+}
+
+fn event(frame: RedisType) -> Result<Event, RedisError> {
+    let parts = match frame {
+        RedisType::Array { value } | RedisType::Push { value } => value,
+        RedisType::Error { value } => return Err(RedisError::Server(value)),
+        _ => return Ok(Event::Other),
+    };
+    let mut parts = parts.into_iter();
+    let kind = match parts.next() {
+        Some(kind) => String::from_redis_type(kind)?.to_lowercase(),
+        None => return Ok(Event::Other),
+    };
+    let mut next = || -> Result<Vec<u8>, RedisError> {
+        match parts.next() {
+            Some(part) => Vec::<u8>::from_redis_type(part),
+            None => Err(RedisError::UnexpectedReply(RedisType::NullArray)),
+        }
+    };
+
+    Ok(match kind.as_str() {
+        "message" => Event::Message(Message {
+            channel: next()?,
+            pattern: None,
+            payload: next()?,
+        }),
+        "pmessage" => Event::Message(Message {
+            pattern: Some(next()?),
+            channel: next()?,
+            payload: next()?,
+        }),
+        "subscribe" | "psubscribe" | "unsubscribe" | "punsubscribe" => Event::Confirmed(kind),
+        _ => Event::Other,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{event, Event, Message};
+    use crate::RedisType;
+
+    fn frame(parts: &[&str]) -> RedisType {
+        RedisType::from(
+            parts
+                .iter()
+                .map(|part| RedisType::from(part.as_bytes().to_vec()))
+                .collect::<Vec<_>>(),
+        )
+    }
+
+    #[test]
+    fn test_event() {
+        assert_eq!(
+            event(frame(&["message", "news", "hello"])).unwrap(),
+            Event::Message(Message {
+                channel: b"news".to_vec(),
+                pattern: None,
+                payload: b"hello".to_vec(),
+            })
+        );
+        let push = RedisType::Push {
+            value: vec![
+                RedisType::from(b"pmessage".to_vec()),
+                RedisType::from(b"sport.*".to_vec()),
+                RedisType::from(b"sport.tennis".to_vec()),
+                RedisType::from(b"\xff".to_vec()),
+            ],
+        };
+        assert_eq!(
+            event(push).unwrap(),
+            Event::Message(Message {
+                channel: b"sport.tennis".to_vec(),
+                pattern: Some(b"sport.*".to_vec()),
+                payload: b"\xff".to_vec(),
+            })
+        );
+
+        let confirmed = RedisType::from(vec![
+            RedisType::from(b"unsubscribe".to_vec()),
+            RedisType::NullString,
+            RedisType::from(0),
+        ]);
+        assert_eq!(
+            event(confirmed).unwrap(),
+            Event::Confirmed(String::from("unsubscribe"))
+        );
+        assert_eq!(event(frame(&["pong", ""])).unwrap(), Event::Other);
+        assert!(event(frame(&["message", "news"])).is_err());
+    }
+}
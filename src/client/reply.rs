@@ -0,0 +1,251 @@
+// Turning replies into the Rust types they stand for, such as an integer reply into an i64 or a
+// bulk string (or nil) into an Option<Vec<u8>>
+
+use std::collections::HashMap;
+use std::hash::Hash;
+
+use crate::client::RedisError;
+use crate::RedisType;
+
+pub trait FromRedisType: Sized {
+    fn from_redis_type(reply: RedisType) -> Result<Self, RedisError>;
+}
+
+// An error reply inside an array (or any other reply) that can't be turned into the type asked for
+fn unexpected<T>(reply: RedisType) -> Result<T, RedisError> {
+    match reply {
+        RedisType::Error { value } => Err(RedisError::Server(value)),
+        reply => Err(RedisError::UnexpectedReply(reply)),
+    }
+}
+
+impl FromRedisType for RedisType {
+    fn from_redis_type(reply: RedisType) -> Result<Self, RedisError> {
+        Ok(reply)
+    }
+}
+
+// For commands that only reply OK
+impl FromRedisType for () {
+    fn from_redis_type(reply: RedisType) -> Result<Self, RedisError> {
+        match reply {
+            RedisType::Error { .. } => unexpected(reply),
+            _ => Ok(()),
+        }
+    }
+}
+
+impl FromRedisType for i64 {
+    fn from_redis_type(reply: RedisType) -> Result<Self, RedisError> {
+        match reply {
+            RedisType::Integer { value } => Ok(value),
+            RedisType::String { ref value } => match value.parse() {
+                Ok(value) => Ok(value),
+                Err(_) => unexpected(reply),
+            },
+            reply => unexpected(reply),
+        }
+    }
+}
+
+impl FromRedisType for f64 {
+    fn from_redis_type(reply: RedisType) -> Result<Self, RedisError> {
+        match reply {
+            RedisType::Integer { value } => Ok(value as f64),
+            RedisType::String { ref value } | RedisType::Double { ref value } => {
+                match value.parse() {
+                    Ok(value) => Ok(value),
+                    Err(_) => unexpected(reply),
+                }
+            }
+            reply => unexpected(reply),
+        }
+    }
+}
+
+// Integer replies that are 1 for yes and 0 for no, such as from EXPIRE and SETNX
+impl FromRedisType for bool {
+    fn from_redis_type(reply: RedisType) -> Result<Self, RedisError> {
+        match reply {
+            RedisType::Integer { value } => Ok(value != 0),
+            RedisType::Boolean { value } => Ok(value),
+            reply => unexpected(reply),
+        }
+    }
+}
+
+impl FromRedisType for Vec<u8> {
+    fn from_redis_type(reply: RedisType) -> Result<Self, RedisError> {
+        match reply {
+            RedisType::String { value } => Ok(value.into_bytes()),
+            RedisType::Bulk { value } => Ok(value.to_vec()),
+            RedisType::Integer { value } => Ok(value.to_string().into_bytes()),
+            RedisType::Double { value } => Ok(value.into_bytes()),
+            reply => unexpected(reply),
+        }
+    }
+}
+
+impl FromRedisType for String {
+    fn from_redis_type(reply: RedisType) -> Result<Self, RedisError> {
+        match reply {
+            RedisType::String { value } | RedisType::Double { value } => Ok(value),
+            // Only bulk strings that aren't UTF-8 are kept as bytes
+            RedisType::Bulk { ref value } => match std::str::from_utf8(value) {
+                Ok(value) => Ok(value.to_string()),
+                Err(_) => unexpected(reply),
+            },
+            RedisType::Integer { value } => Ok(value.to_string()),
+            reply => unexpected(reply),
+        }
+    }
+}
+
+// None for nil, such as GET on a key that doesn't exist
+impl<T: FromRedisType> FromRedisType for Option<T> {
+    fn from_redis_type(reply: RedisType) -> Result<Self, RedisError> {
+        match reply {
+            RedisType::NullString | RedisType::NullArray => Ok(None),
+            reply => T::from_redis_type(reply).map(Some),
+        }
+    }
+}
+
+impl<T: FromRedisType> FromRedisType for Vec<T> {
+    fn from_redis_type(reply: RedisType) -> Result<Self, RedisError> {
+        match reply {
+            RedisType::Array { value } | RedisType::Set { value } => {
+                value.into_iter().map(T::from_redis_type).collect()
+            }
+            reply => unexpected(reply),
+        }
+    }
+}
+
+// From an array of two, such as SCAN's cursor and keys
+impl<A: FromRedisType, B: FromRedisType> FromRedisType for (A, B) {
+    fn from_redis_type(reply: RedisType) -> Result<Self, RedisError> {
+        match reply {
+            RedisType::Array { value } if value.len() == 2 => {
+                let mut value = value.into_iter();
+                let (a, b) = (value.next().unwrap(), value.next().unwrap());
+                Ok((A::from_redis_type(a)?, B::from_redis_type(b)?))
+            }
+            reply => unexpected(reply),
+        }
+    }
+}
+
+// From a map, or an array of alternating keys and values before RESP3, such as from HGETALL
+impl<K, V> FromRedisType for HashMap<K, V>
+where
+    K: FromRedisType + Eq + Hash,
+    V: FromRedisType,
+{
+    fn from_redis_type(reply: RedisType) -> Result<Self, RedisError> {
+        match reply {
+            RedisType::Map { value } => value
+                .into_iter()
+                .map(|(k, v)| Ok((K::from_redis_type(k)?, V::from_redis_type(v)?)))
+                .collect(),
+            RedisType::Array { value } if value.len() % 2 == 0 => {
+                let mut map = HashMap::with_capacity(value.len() / 2);
+                let mut value = value.into_iter();
+                while let (Some(k), Some(v)) = (value.next(), value.next()) {
+                    map.insert(K::from_redis_type(k)?, V::from_redis_type(v)?);
+                }
+                Ok(map)
+            }
+            reply => unexpected(reply),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::FromRedisType;
+    use crate::client::RedisError;
+    use crate::RedisType;
+
+    fn from<T: FromRedisType>(reply: RedisType) -> Result<T, RedisError> {
+        T::from_redis_type(reply)
+    }
+
+    #[test]
+    fn test_from_redis_type() {
+        assert_eq!(from::<i64>(RedisType::from(5)).unwrap(), 5);
+        assert_eq!(
+            from::<i64>(RedisType::from(String::from("-3"))).unwrap(),
+            -3
+        );
+        assert!(from::<bool>(RedisType::from(1)).unwrap());
+        assert_eq!(
+            from::<f64>(RedisType::Double {
+                value: String::from("1.5")
+            })
+            .unwrap(),
+            1.5
+        );
+        assert_eq!(
+            from::<Vec<u8>>(RedisType::from(b"\xff".to_vec())).unwrap(),
+            b"\xff"
+        );
+        assert_eq!(
+            from::<String>(RedisType::from(String::from("OK"))).unwrap(),
+            "OK"
+        );
+        assert_eq!(
+            from::<Option<Vec<u8>>>(RedisType::NullString).unwrap(),
+            None
+        );
+        assert_eq!(
+            from::<Vec<Option<String>>>(RedisType::from(vec![
+                RedisType::from(String::from("a")),
+                RedisType::NullString,
+            ]))
+            .unwrap(),
+            vec![Some(String::from("a")), None]
+        );
+        from::<()>(RedisType::from(String::from("OK"))).unwrap();
+
+        let pairs = RedisType::from(vec![
+            RedisType::from(String::from("name")),
+            RedisType::from(String::from("alice")),
+        ]);
+        let map = from::<HashMap<String, String>>(pairs).unwrap();
+        assert_eq!(map["name"], "alice");
+        let map = RedisType::Map {
+            value: vec![(RedisType::from(String::from("age")), RedisType::from(30))],
+        };
+        assert_eq!(from::<HashMap<String, i64>>(map).unwrap()["age"], 30);
+
+        let scan = RedisType::from(vec![
+            RedisType::from(String::from("17")),
+            RedisType::from(vec![RedisType::from(String::from("key"))]),
+        ]);
+        assert_eq!(
+            from::<(i64, Vec<String>)>(scan).unwrap(),
+            (17, vec![String::from("key")])
+        );
+    }
+
+    #[test]
+    fn test_from_redis_type_errors() {
+        let error = RedisType::Error {
+            value: String::from(
+                "WRONGTYPE Operation against a key holding the wrong kind of value",
+            ),
+        };
+        let e = from::<Vec<i64>>(RedisType::from(vec![error])).unwrap_err();
+        assert_eq!(e.code(), Some("WRONGTYPE"));
+
+        assert!(matches!(
+            from::<i64>(RedisType::from(String::from("nope"))),
+            Err(RedisError::UnexpectedReply(_))
+        ));
+        assert!(from::<String>(RedisType::from(b"\xff".to_vec())).is_err());
+        assert!(from::<Vec<u8>>(RedisType::NullString).is_err());
+    }
+}
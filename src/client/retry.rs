@@ -0,0 +1,196 @@
+// Trying a command again when it fails in a way that could go away by itself, such as the
+// connection dropping or the server still loading its data
+// For example:
+//
+//     client.set_retry_policy(
+//         RetryPolicy::new()
+//             .max_attempts(5)
+//             .backoff(Duration::from_millis(50), Duration::from_secs(2))
+//             .retry_on(&[RetryOn::Connection, RetryOn::Loading])
+//             .on_retry(|retry| eprintln!("Retrying after {:?}: {}", retry.delay, retry.error)),
+//     );
+//
+// Commands can run more than once this way: one whose connection dropped after it was sent may
+// have been run before the reply was lost.
+
+use std::collections::hash_map::RandomState;
+use std::fmt;
+use std::hash::{BuildHasher, Hasher};
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::client::RedisError;
+
+const DEFAULT_BASE_DELAY: Duration = Duration::from_millis(100);
+const DEFAULT_MAX_DELAY: Duration = Duration::from_secs(3);
+
+// The kinds of failure that can be retried
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RetryOn {
+    // The connection failing, being closed or timing out, after which the client connects again
+    Connection,
+    // LOADING errors, while the server loads its data after starting
+    Loading,
+    // MOVED redirects, after which the client connects to the node it names
+    Moved,
+}
+
+impl RetryOn {
+    pub fn of(error: &RedisError) -> Option<RetryOn> {
+        match error {
+            RedisError::Io(_) => Some(RetryOn::Connection),
+            _ if error.code() == Some("LOADING") => Some(RetryOn::Loading),
+            _ if error.code() == Some("MOVED") => Some(RetryOn::Moved),
+            _ => None,
+        }
+    }
+}
+
+// What's about to be retried, for on_retry
+pub struct Retry<'a> {
+    // Of the attempt that failed, starting from 1
+    pub attempt: u32,
+    pub delay: Duration,
+    pub error: &'a RedisError,
+}
+
+type OnRetry = dyn Fn(&Retry) + Send + Sync;
+
+// By default nothing is retried
+#[derive(Clone)]
+pub struct RetryPolicy {
+    max_attempts: u32,
+    base_delay: Duration,
+    max_delay: Duration,
+    jitter: bool,
+    retry_on: Vec<RetryOn>,
+    on_retry: Option<Arc<OnRetry>>,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            max_attempts: 1,
+            base_delay: DEFAULT_BASE_DELAY,
+            max_delay: DEFAULT_MAX_DELAY,
+            jitter: true,
+            retry_on: vec![RetryOn::Connection, RetryOn::Loading, RetryOn::Moved],
+            on_retry: None,
+        }
+    }
+}
+
+impl fmt::Debug for RetryPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RetryPolicy")
+            .field("max_attempts", &self.max_attempts)
+            .field("base_delay", &self.base_delay)
+            .field("max_delay", &self.max_delay)
+            .field("jitter", &self.jitter)
+            .field("retry_on", &self.retry_on)
+            .finish_non_exhaustive()
+    }
+}
+
+impl RetryPolicy {
+    pub fn new() -> RetryPolicy {
+        RetryPolicy::default()
+    }
+
+    // How many times to try a command in all, including the first
+    pub fn max_attempts(mut self, max_attempts: u32) -> Self {
+        self.max_attempts = max_attempts.max(1);
+        self
+    }
+
+    // How long to wait before the first retry, doubling for each after that up to max
+    pub fn backoff(mut self, base: Duration, max: Duration) -> Self {
+        self.base_delay = base;
+        self.max_delay = max.max(base);
+        self
+    }
+
+    // Whether to wait a random amount between half the delay and all of it, so clients that failed
+    // together don't all retry together (on by default)
+    pub fn jitter(mut self, jitter: bool) -> Self {
+        self.jitter = jitter;
+        self
+    }
+
+    // Which kinds of failure to retry, all of them by default
+    pub fn retry_on(mut self, retry_on: &[RetryOn]) -> Self {
+        self.retry_on = retry_on.to_vec();
+        self
+    }
+
+    // Called before waiting to retry, such as to log it
+    pub fn on_retry(mut self, on_retry: impl Fn(&Retry) + Send + Sync + 'static) -> Self {
+        self.on_retry = Some(Arc::new(on_retry));
+        self
+    }
+
+    // How long to wait before trying again after the given attempt failed, if it should be
+    pub(crate) fn delay(&self, error: &RedisError, attempt: u32) -> Option<Duration> {
+        let retry_on = RetryOn::of(error)?;
+        if attempt >= self.max_attempts || !self.retry_on.contains(&retry_on) {
+            return None;
+        }
+
+        let doublings = (attempt - 1).min(31);
+        let delay = self
+            .base_delay
+            .saturating_mul(1 << doublings)
+            .min(self.max_delay);
+        if !self.jitter {
+            return Some(delay);
+        }
+        let random = RandomState::new().build_hasher().finish();
+        let half = delay / 2;
+        Some(half + half.mul_f64(random as f64 / u64::MAX as f64))
+    }
+
+    pub(crate) fn notify(&self, retry: &Retry) {
+        if let Some(on_retry) = &self.on_retry {
+            on_retry(retry);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::{RetryOn, RetryPolicy};
+    use crate::client::RedisError;
+
+    #[test]
+    fn test_delay() {
+        let eof = RedisError::Io(std::io::ErrorKind::UnexpectedEof.into());
+        let loading = RedisError::Server(String::from(
+            "LOADING Redis is loading the dataset in memory",
+        ));
+        let wrong = RedisError::Server(String::from("WRONGTYPE Operation against a key"));
+
+        assert_eq!(RetryPolicy::new().delay(&eof, 1), None);
+
+        let ms = Duration::from_millis;
+        let policy = RetryPolicy::new()
+            .max_attempts(5)
+            .backoff(ms(10), ms(25))
+            .jitter(false)
+            .retry_on(&[RetryOn::Connection]);
+        let delays = (1..=5)
+            .map(|attempt| policy.delay(&eof, attempt))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            delays,
+            [Some(ms(10)), Some(ms(20)), Some(ms(25)), Some(ms(25)), None]
+        );
+        assert_eq!(policy.delay(&loading, 1), None);
+        assert_eq!(policy.delay(&wrong, 1), None);
+
+        let policy = RetryPolicy::new().max_attempts(2).backoff(ms(100), ms(100));
+        let delay = policy.delay(&loading, 1).unwrap();
+        assert!(delay >= ms(50) && delay <= ms(100));
+    }
+}
@@ -0,0 +1,139 @@
+// Going through every key (or every field of a hash, member of a set or sorted set) matching a
+// pattern, with SCAN, HSCAN, SSCAN or ZSCAN, sending the cursor back each time until it's done
+// For example:
+//
+//     let mut keys = client.scan_match("user:*").count(100);
+//     while let Some(key) = keys.next().await? {
+//         println!("{}", String::from_utf8_lossy(&key));
+//     }
+//
+// As with the commands themselves, something can come up more than once if the keys change while
+// they're being scanned.
+
+use std::collections::VecDeque;
+
+use crate::client::{arg, Client, FromRedisType, RedisError};
+use crate::RedisType;
+
+pub struct Scan<'a, T> {
+    client: &'a mut Client,
+    // Such as ["HSCAN", key], to follow with the cursor and options
+    command: Vec<Vec<u8>>,
+    pattern: Vec<u8>,
+    count: Option<u64>,
+    // None once the server has said it's done, by sending back 0
+    cursor: Option<Vec<u8>>,
+    batch: VecDeque<T>,
+    items: fn(Vec<RedisType>) -> Result<Vec<T>, RedisError>,
+}
+
+impl<T> Scan<'_, T> {
+    // A hint for how much each command looks at, 10 otherwise
+    pub fn count(mut self, count: u64) -> Self {
+        self.count = Some(count);
+        self
+    }
+
+    // The next one, sending the next command when the last one's have all been returned; None
+    // once there are no more
+    pub async fn next(&mut self) -> Result<Option<T>, RedisError> {
+        loop {
+            if let Some(item) = self.batch.pop_front() {
+                return Ok(Some(item));
+            }
+            let Some(cursor) = self.cursor.take() else {
+                return Ok(None);
+            };
+
+            let mut command = self.command.clone();
+            command.extend([cursor, b"MATCH".to_vec(), self.pattern.clone()]);
+            if let Some(count) = self.count {
+                command.extend([b"COUNT".to_vec(), arg(count.to_string())]);
+            }
+            let (cursor, items): (Vec<u8>, Vec<RedisType>) = self.client.call(command).await?;
+            if cursor != b"0" {
+                self.cursor = Some(cursor);
+            }
+            self.batch.extend((self.items)(items)?);
+        }
+    }
+
+    // All the rest of them
+    pub async fn collect(mut self) -> Result<Vec<T>, RedisError> {
+        let mut all = Vec::new();
+        while let Some(item) = self.next().await? {
+            all.push(item);
+        }
+        Ok(all)
+    }
+}
+
+impl Client {
+    // Keys matching a glob-style pattern, such as user:*
+    pub fn scan_match(&mut self, pattern: impl AsRef<[u8]>) -> Scan<'_, Vec<u8>> {
+        self.scanner(vec![b"SCAN".to_vec()], pattern, each)
+    }
+
+    // The fields of a hash matching the pattern, with their values
+    pub fn hscan_match(
+        &mut self,
+        key: impl AsRef<[u8]>,
+        pattern: impl AsRef<[u8]>,
+    ) -> Scan<'_, (Vec<u8>, Vec<u8>)> {
+        self.scanner(vec![b"HSCAN".to_vec(), arg(key)], pattern, pairs)
+    }
+
+    pub fn sscan_match(
+        &mut self,
+        key: impl AsRef<[u8]>,
+        pattern: impl AsRef<[u8]>,
+    ) -> Scan<'_, Vec<u8>> {
+        self.scanner(vec![b"SSCAN".to_vec(), arg(key)], pattern, each)
+    }
+
+    // The members of a sorted set matching the pattern, with their scores
+    pub fn zscan_match(
+        &mut self,
+        key: impl AsRef<[u8]>,
+        pattern: impl AsRef<[u8]>,
+    ) -> Scan<'_, (Vec<u8>, f64)> {
+        self.scanner(vec![b"ZSCAN".to_vec(), arg(key)], pattern, pairs)
+    }
+
+    fn scanner<T>(
+        &mut self,
+        command: Vec<Vec<u8>>,
+        pattern: impl AsRef<[u8]>,
+        items: fn(Vec<RedisType>) -> Result<Vec<T>, RedisError>,
+    ) -> Scan<'_, T> {
+        Scan {
+            client: self,
+            command,
+            pattern: arg(pattern),
+            count: None,
+            cursor: Some(b"0".to_vec()),
+            batch: VecDeque::new(),
+            items,
+        }
+    }
+}
+
+// From the SCAN (or SSCAN) reply's list of keys
+fn each<T: FromRedisType>(items: Vec<RedisType>) -> Result<Vec<T>, RedisError> {
+    items.into_iter().map(T::from_redis_type).collect()
+}
+
+// From a list that alternates between fields (or members) and their values (or scores)
+fn pairs<A: FromRedisType, B: FromRedisType>(
+    items: Vec<RedisType>,
+) -> Result<Vec<(A, B)>, RedisError> {
+    let mut pairs = Vec::with_capacity(items.len() / 2);
+    let mut items = items.into_iter();
+    while let Some(a) = items.next() {
+        let b = items
+            .next()
+            .ok_or(RedisError::UnexpectedReply(RedisType::NullString))?;
+        pairs.push((A::from_redis_type(a)?, B::from_redis_type(b)?));
+    }
+    Ok(pairs)
+}
@@ -0,0 +1,54 @@
+// Starting TLS over a connection, for rediss:// URLs here and --tls in the client
+
+use std::io::ErrorKind;
+use std::path::Path;
+use std::sync::Arc;
+
+use tokio::net::TcpStream;
+use tokio_rustls::client::TlsStream;
+use tokio_rustls::rustls::pki_types::pem::PemObject;
+use tokio_rustls::rustls::pki_types::{CertificateDer, ServerName};
+use tokio_rustls::rustls::{self, ClientConfig, RootCertStore};
+use tokio_rustls::TlsConnector;
+
+// Start TLS over a connection, checking the server's certificate is for name against the given
+// certificate authority (a PEM file) or the system's
+pub async fn start_tls(
+    tcp: TcpStream,
+    name: &str,
+    cacert: Option<&Path>,
+) -> std::io::Result<TlsStream<TcpStream>> {
+    let mut roots = RootCertStore::empty();
+    match cacert {
+        Some(path) => {
+            let certs = CertificateDer::pem_file_iter(path).map_err(|e| {
+                std::io::Error::other(format!("Unable to read {}: {e}", path.display()))
+            })?;
+            for cert in certs {
+                roots
+                    .add(cert.map_err(std::io::Error::other)?)
+                    .map_err(std::io::Error::other)?;
+            }
+        }
+        None => {
+            let native = rustls_native_certs::load_native_certs();
+            for error in native.errors {
+                tracing::warn!("Unable to load a system certificate: {error}");
+            }
+            roots.add_parsable_certificates(native.certs);
+        }
+    }
+
+    let provider = Arc::new(rustls::crypto::ring::default_provider());
+    let config = ClientConfig::builder_with_provider(provider)
+        .with_safe_default_protocol_versions()
+        .map_err(std::io::Error::other)?
+        .with_root_certificates(roots)
+        .with_no_client_auth();
+
+    let name = ServerName::try_from(name.to_string())
+        .map_err(|e| std::io::Error::new(ErrorKind::InvalidInput, e))?;
+    TlsConnector::from(Arc::new(config))
+        .connect(name, tcp)
+        .await
+}
@@ -0,0 +1,109 @@
+// Changing keys based on what they hold without another client changing them in between, with
+// WATCH, MULTI and EXEC
+// For example, to copy a key:
+//
+//     let replies: Vec<RedisType> = client
+//         .transaction(&["source"], async |client, tx| {
+//             if let Some(value) = client.get("source").await? {
+//                 tx.command(&[b"SET".as_slice(), b"copy", &value]);
+//             }
+//             Ok(())
+//         })
+//         .await?;
+//
+// If a watched key changes before EXEC, nothing is run and the closure is called again with the
+// new values, as many times as it takes.
+
+use crate::client::{within, Client, FromRedisType, RedisError};
+use crate::RedisType;
+
+// The commands to run together between MULTI and EXEC
+#[derive(Clone, Debug, Default)]
+pub struct Transaction {
+    commands: Vec<Vec<Vec<u8>>>,
+}
+
+impl Transaction {
+    pub fn new() -> Transaction {
+        Transaction::default()
+    }
+
+    pub fn command(&mut self, args: &[impl AsRef<[u8]>]) -> &mut Transaction {
+        self.commands
+            .push(args.iter().map(|arg| arg.as_ref().to_vec()).collect());
+        self
+    }
+
+    pub fn len(&self) -> usize {
+        self.commands.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.commands.is_empty()
+    }
+}
+
+impl Client {
+    // WATCH the keys, let build read what it needs and add the commands to run, then run them with
+    // MULTI and EXEC, starting again if EXEC says a watched key changed
+    // The reply is EXEC's, with one reply for each command, such as a Vec<RedisType>. If build
+    // fails the keys are unwatched and its error returned.
+    pub async fn transaction<K, T, F>(&mut self, keys: &[K], mut build: F) -> Result<T, RedisError>
+    where
+        K: AsRef<[u8]>,
+        T: FromRedisType,
+        F: AsyncFnMut(&mut Client, &mut Transaction) -> Result<(), RedisError>,
+    {
+        loop {
+            let mut watch = vec![b"WATCH".to_vec()];
+            watch.extend(keys.iter().map(|key| key.as_ref().to_vec()));
+            self.call::<()>(watch).await?;
+
+            let mut tx = Transaction::new();
+            if let Err(e) = build(self, &mut tx).await {
+                // Unless it was the connection that failed, leave it as it was found
+                if self.is_in_sync() {
+                    self.call::<()>(vec![b"UNWATCH".to_vec()]).await?;
+                }
+                return Err(e);
+            }
+
+            match self.exec(tx).await? {
+                // A watched key was changed
+                RedisType::NullArray | RedisType::NullString => continue,
+                reply => return T::from_redis_type(reply),
+            }
+        }
+    }
+
+    // Send MULTI, the commands and EXEC all at once, then read all their replies
+    async fn exec(&mut self, tx: Transaction) -> Result<RedisType, RedisError> {
+        let timeout = self.info.response_timeout;
+        let exchange = async {
+            self.pending = true;
+            let queued = tx.commands.len();
+            self.send(vec![b"MULTI".to_vec()]).await?;
+            for command in tx.commands {
+                self.send(command).await?;
+            }
+            self.send(vec![b"EXEC".to_vec()]).await?;
+
+            // A command refused while queueing (such as for the wrong number of arguments) makes
+            // EXEC fail with EXECABORT, but the refusal says why
+            let mut refused = None;
+            for _ in 0..1 + queued {
+                if let RedisType::Error { value } = self.read().await? {
+                    refused.get_or_insert(value);
+                }
+            }
+            Ok::<_, RedisError>((refused, self.read().await?))
+        };
+        let (refused, reply) = within(timeout, "waiting for a reply", exchange).await?;
+        self.pending = false;
+
+        match (refused, reply) {
+            (Some(value), _) | (None, RedisType::Error { value }) => Err(RedisError::Server(value)),
+            (None, reply) => Ok(reply),
+        }
+    }
+}
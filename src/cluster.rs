@@ -0,0 +1,64 @@
+// Hash slots for Redis Cluster, shared by the server and anything that needs to route keys
+// Keys are partitioned into this many hash slots, each served by one node
+pub const SLOTS: u16 = 16384;
+
+// The slot for a key, CRC16 of the key modulo the number of slots
+// If the key has a non-empty {hashtag}, only that part is hashed, so related keys can be kept
+// in the same slot (and used together by multi-key commands)
+pub fn key_slot(key: impl AsRef<[u8]>) -> u16 {
+    crc16(hash_tag(key.as_ref())) % SLOTS
+}
+
+// The part of key between the first { and the } after it, or all of it if that is empty
+fn hash_tag(key: &[u8]) -> &[u8] {
+    let start = match key.iter().position(|&byte| byte == b'{') {
+        Some(start) => start + 1,
+        None => return key,
+    };
+    match key[start..].iter().position(|&byte| byte == b'}') {
+        Some(0) | None => key,
+        Some(len) => &key[start..start + len],
+    }
+}
+
+// CRC16-CCITT (XModem), as used by Redis Cluster
+fn crc16(data: &[u8]) -> u16 {
+    let mut crc: u16 = 0;
+    for byte in data {
+        crc ^= (*byte as u16) << 8;
+        for _ in 0..8 {
+            crc = if crc & 0x8000 != 0 {
+                (crc << 1) ^ 0x1021
+            } else {
+                crc << 1
+            };
+        }
+    }
+    crc
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_key_slot() {
+        // Values from the Redis Cluster specification and CLUSTER KEYSLOT
+        assert_eq!(crc16(b"123456789"), 0x31C3);
+        assert_eq!(key_slot("foo"), 12182);
+        assert_eq!(key_slot("bar"), 5061);
+        assert_eq!(key_slot(""), 0);
+        assert_eq!(key_slot("somekey"), 11058);
+    }
+
+    #[test]
+    fn test_hash_tag() {
+        assert_eq!(key_slot("{user1000}.following"), key_slot("user1000"));
+        assert_eq!(key_slot("{user1000}.followers"), key_slot("user1000"));
+        // Only the first { counts, and an empty tag means the whole key is hashed
+        assert_eq!(key_slot("foo{{bar}}zap"), key_slot("{bar"));
+        assert_eq!(key_slot("foo{bar}{zap}"), key_slot("bar"));
+        assert_eq!(hash_tag(b"foo{}{bar}"), b"foo{}{bar}");
+        assert_eq!(hash_tag(b"foo{bar"), b"foo{bar");
+    }
+}
@@ -0,0 +1,126 @@
+// Properties checked by the cargo-fuzz targets in fuzz/, which should hold for any input at all
+// Each panics if its property doesn't hold, which is what the fuzzer is looking for.
+
+use crate::{Protocol, RedisType, RedisTypeParseError, MAX_NESTING};
+
+// Parsing arbitrary input never panics, and what parse_prefix accepts is a value on its own
+pub fn parse(data: &[u8]) {
+    if let Ok((value, len)) = RedisType::parse_prefix(data) {
+        assert_eq!(RedisType::parse_prefix(&data[..len]), Ok((value, len)));
+    }
+}
+
+// Reading a stream in two pieces, split anywhere, gives the same values as reading it at once
+pub fn decode(data: &[u8], split: usize) {
+    let (first, second) = data.split_at(split % (data.len() + 1));
+    assert_eq!(decode_stream(&[first, second]), decode_stream(&[data]));
+}
+
+// The values read from chunks arriving one after another, and the error that stopped reading
+// if there was one
+fn decode_stream(chunks: &[&[u8]]) -> (Vec<RedisType>, Option<RedisTypeParseError>) {
+    let mut buffer = Vec::new();
+    let mut values = Vec::new();
+
+    for chunk in chunks {
+        buffer.extend_from_slice(chunk);
+        loop {
+            match RedisType::parse_prefix(&buffer) {
+                Ok((value, len)) => {
+                    values.push(value);
+                    buffer.drain(..len);
+                }
+                Err(RedisTypeParseError::Incomplete | RedisTypeParseError::MissingPrefix) => break,
+                Err(e) => return (values, Some(e)),
+            }
+        }
+    }
+
+    (values, None)
+}
+
+// Encoding a value and parsing it back gives the same value, give or take what the protocol
+// can't express, and encode_segments writes exactly what encode does
+pub fn round_trip(value: &RedisType, protocol: Protocol) {
+    let encoded = value.encode_segments(protocol).concat();
+
+    let parsed = RedisType::parse_prefix(&encoded);
+    if nesting(value, protocol) > MAX_NESTING {
+        assert_eq!(parsed, Err(RedisTypeParseError::NestingTooDeep));
+    } else {
+        assert_eq!(parsed, Ok((as_parsed(value, protocol), encoded.len())));
+    }
+
+    assert_eq!(value.encode(protocol), encoded);
+}
+
+// The value that parsing what value encodes to should give
+fn as_parsed(value: &RedisType, protocol: Protocol) -> RedisType {
+    match value {
+        RedisType::NullArray if protocol == Protocol::Resp3 => RedisType::NullString,
+        RedisType::Bulk { value } => RedisType::from(value.to_vec()),
+        RedisType::Error { value } => RedisType::Error {
+            value: value.replace(['\r', '\n'], " "),
+        },
+        RedisType::Array { value } => RedisType::Array {
+            value: value.iter().map(|el| as_parsed(el, protocol)).collect(),
+        },
+        RedisType::Push { value } if protocol == Protocol::Resp2 => RedisType::Array {
+            value: value.iter().map(|el| as_parsed(el, protocol)).collect(),
+        },
+        RedisType::Push { value } => RedisType::Push {
+            value: value.iter().map(|el| as_parsed(el, protocol)).collect(),
+        },
+        RedisType::Set { value } if protocol == Protocol::Resp2 => RedisType::Array {
+            value: value.iter().map(|el| as_parsed(el, protocol)).collect(),
+        },
+        RedisType::Set { value } => RedisType::Set {
+            value: value.iter().map(|el| as_parsed(el, protocol)).collect(),
+        },
+        RedisType::Double { value } if protocol == Protocol::Resp2 => RedisType::String {
+            value: value.clone(),
+        },
+        RedisType::Double { value } => RedisType::Double {
+            value: value.replace(['\r', '\n'], " "),
+        },
+        RedisType::Boolean { value } if protocol == Protocol::Resp2 => RedisType::Integer {
+            value: *value as i64,
+        },
+        RedisType::Map { value } if protocol == Protocol::Resp2 => RedisType::Array {
+            value: value
+                .iter()
+                .flat_map(|(k, v)| [as_parsed(k, protocol), as_parsed(v, protocol)])
+                .collect(),
+        },
+        RedisType::Map { value } => RedisType::Map {
+            value: value
+                .iter()
+                .map(|(k, v)| (as_parsed(k, protocol), as_parsed(v, protocol)))
+                .collect(),
+        },
+        value => value.clone(),
+    }
+}
+
+// How many arrays, maps, pushes or sets deep value goes once encoded (counting RESP2's null array, which is
+// written as one)
+fn nesting(value: &RedisType, protocol: Protocol) -> usize {
+    match value {
+        RedisType::NullArray if protocol == Protocol::Resp2 => 1,
+        RedisType::Array { value } | RedisType::Push { value } | RedisType::Set { value } => {
+            1 + value
+                .iter()
+                .map(|el| nesting(el, protocol))
+                .max()
+                .unwrap_or(0)
+        }
+        RedisType::Map { value } => {
+            1 + value
+                .iter()
+                .map(|(k, v)| nesting(k, protocol).max(nesting(v, protocol)))
+                .max()
+                .unwrap_or(0)
+        }
+        _ => 0,
+    }
+}
@@ -1,16 +1,75 @@
+pub mod aof;
+pub mod client;
+pub mod cluster;
+#[cfg(feature = "fuzz")]
+pub mod fuzz;
+pub mod rdb;
+pub mod server;
+pub mod value;
+
+use bytes::Bytes;
 use std::{fmt::Display, str::FromStr};
+use value::Value;
+
+// Bulk values at least this long are written from their own buffer by encode_segments
+const SHARED_BULK_MIN_LEN: usize = 16 * 1024;
+
+// The version of RESP used to serialize values, negotiated per connection with HELLO
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq, Hash)]
+#[cfg_attr(feature = "fuzz", derive(arbitrary::Arbitrary))]
+pub enum Protocol {
+    #[default]
+    Resp2,
+    Resp3,
+}
+
+impl Protocol {
+    pub fn version(&self) -> i64 {
+        match self {
+            Protocol::Resp2 => 2,
+            Protocol::Resp3 => 3,
+        }
+    }
+}
 
-// Force output as bulk string rather than simple string
-pub static mut ALWAYS_USE_BULK_STRING: bool = true;
+// How values are serialized for one connection: the RESP version it negotiated, and whether
+// strings are always sent as bulk strings (as Redis replies to GET, and as commands have to be
+// sent) rather than as simple strings where they can be
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq, Hash)]
+pub struct Encoding {
+    pub protocol: Protocol,
+    pub bulk_strings: bool,
+}
+
+impl From<Protocol> for Encoding {
+    fn from(protocol: Protocol) -> Self {
+        Encoding {
+            protocol,
+            bulk_strings: false,
+        }
+    }
+}
 
 #[derive(Clone, Debug, Eq, PartialEq, Hash)]
+#[cfg_attr(feature = "fuzz", derive(arbitrary::Arbitrary))]
 pub enum RedisType {
     NullString,
     NullArray,
     String { value: String },
+    // Always a bulk string, sharing its buffer (such as a stored value) instead of copying it
+    Bulk { value: Value },
     Error { value: String },
     Integer { value: i64 },
     Array { value: Vec<RedisType> },
+    Map { value: Vec<(RedisType, RedisType)> },
+    // Sent by the server outside of any reply, such as an invalidation for client-side caching
+    Push { value: Vec<RedisType> },
+    // The rest are RESP3 types sent as the nearest RESP2 one (an array, a bulk string and an
+    // integer) to RESP2 connections
+    Set { value: Vec<RedisType> },
+    // As the server wrote it, such as 1.5, inf or nan
+    Double { value: String },
+    Boolean { value: bool },
 }
 
 impl From<Option<String>> for RedisType {
@@ -32,6 +91,25 @@ impl From<String> for RedisType {
     }
 }
 
+// A bulk string as it was sent: a String if it's valid UTF-8 (as almost everything is), otherwise
+// the raw bytes
+impl From<Vec<u8>> for RedisType {
+    fn from(value: Vec<u8>) -> Self {
+        match String::from_utf8(value) {
+            Ok(value) => RedisType::String { value },
+            Err(e) => RedisType::Bulk {
+                value: Value::from(e.into_bytes()),
+            },
+        }
+    }
+}
+
+impl From<Value> for RedisType {
+    fn from(value: Value) -> Self {
+        RedisType::Bulk { value }
+    }
+}
+
 impl From<i64> for RedisType {
     fn from(value: i64) -> Self {
         RedisType::Integer { value }
@@ -44,113 +122,326 @@ impl From<Vec<RedisType>> for RedisType {
     }
 }
 
-#[derive(Copy, Clone, Debug)]
+impl From<Vec<(RedisType, RedisType)>> for RedisType {
+    fn from(value: Vec<(RedisType, RedisType)>) -> Self {
+        RedisType::Map { value }
+    }
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
 pub enum RedisTypeParseError {
     MissingPrefix,
     InvalidPrefix,
     InvalidSuffix,
     InvalidArrayLength,
+    InvalidBulkLength,
+    InvalidInteger,
+    InvalidBoolean,
+    // Arrays and maps nested more than MAX_NESTING deep
+    NestingTooDeep,
     LeftOverData,
+    // More data is needed before a complete value can be parsed
+    Incomplete,
+}
+
+// How deeply arrays and maps can be nested inside each other, so hostile input can't parse its
+// way through the stack
+pub(crate) const MAX_NESTING: usize = 128;
+
+// Lines holding a length or an integer are refused once they get this long without ending, as in
+// Redis, rather than searched again for their end each time more of them arrives
+pub(crate) const MAX_NUMBER_LINE: usize = 64 * 1024;
+
+// The bytes each type starts with, anything else isn't RESP
+pub(crate) const TYPE_BYTES: &[u8] = b"+-:*%>~_$,#=!";
+
+impl RedisType {
+    // Parse a single value from the start of data, returning it and the number of bytes consumed
+    // Any data after the first value is left alone, so this can be used on a buffered stream
+    pub fn parse_prefix(data: impl AsRef<[u8]>) -> Result<(RedisType, usize), RedisTypeParseError> {
+        let data = data.as_ref();
+        let (rest, result) = parse(data, 0)?;
+        Ok((result, data.len() - rest.len()))
+    }
 }
 
 impl FromStr for RedisType {
     type Err = RedisTypeParseError;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        fn parse(s: &str) -> Result<(&str, RedisType), RedisTypeParseError> {
-            let bytes = s.as_bytes();
+        match parse(s.as_bytes(), 0) {
+            Ok((b"", result)) => Ok(result),
+            Ok(_) => Err(RedisTypeParseError::LeftOverData),
+            Err(e) => Err(e),
+        }
+    }
+}
 
-            if s.len() == 0 {
-                return Err(RedisTypeParseError::MissingPrefix);
+// Parse a single value from the start of data, nested inside depth arrays or maps
+fn parse(data: &[u8], depth: usize) -> Result<(&[u8], RedisType), RedisTypeParseError> {
+    if data.is_empty() {
+        return Err(RedisTypeParseError::MissingPrefix);
+    }
+
+    if !TYPE_BYTES.contains(&data[0]) {
+        return Err(RedisTypeParseError::InvalidPrefix);
+    }
+
+    let too_long = match data[0] {
+        b'*' | b'%' | b'>' | b'~' => Some(RedisTypeParseError::InvalidArrayLength),
+        b'$' | b'=' | b'!' => Some(RedisTypeParseError::InvalidBulkLength),
+        b':' => Some(RedisTypeParseError::InvalidInteger),
+        _ => None,
+    };
+    let line = match too_long {
+        Some(_) => &data[..data.len().min(MAX_NUMBER_LINE + 2)],
+        None => data,
+    };
+    let Some(crlf) = line.windows(2).position(|window| window == b"\r\n") else {
+        return match too_long {
+            Some(e) if data.len() >= MAX_NUMBER_LINE + 2 => Err(e),
+            _ => Err(RedisTypeParseError::Incomplete),
+        };
+    };
+    // The prefix is a single byte, so this can only fail for an empty line
+    let payload = data.get(1..crlf).unwrap_or_default();
+    let mut rest = &data[crlf + 2..];
+
+    // Lengths and integers both have to be entirely a number
+    let number = |error| {
+        std::str::from_utf8(payload)
+            .ok()
+            .and_then(|payload| payload.parse::<i64>().ok())
+            .ok_or(error)
+    };
+
+    if matches!(data[0], b'*' | b'%' | b'>' | b'~') && depth >= MAX_NESTING {
+        return Err(RedisTypeParseError::NestingTooDeep);
+    }
+
+    match data[0] {
+        b'+' => Ok((
+            rest,
+            RedisType::String {
+                value: String::from_utf8_lossy(payload).into_owned(),
+            },
+        )),
+        b'-' => Ok((
+            rest,
+            RedisType::Error {
+                value: String::from_utf8_lossy(payload).into_owned(),
+            },
+        )),
+        b':' => Ok((
+            rest,
+            RedisType::Integer {
+                value: number(RedisTypeParseError::InvalidInteger)?,
+            },
+        )),
+        b'*' => {
+            let len = number(RedisTypeParseError::InvalidArrayLength)?;
+
+            // Special case: bulk string with -1 length is actually a 'null' array
+            // This is historical
+            if len < 0 {
+                Ok((rest, RedisType::NullArray))
+            } else {
+                let mut value = Vec::new();
+
+                for _ in 0..len {
+                    // The rest of the array hasn't been received yet
+                    if rest.is_empty() {
+                        return Err(RedisTypeParseError::Incomplete);
+                    }
+
+                    let (next, el) = parse(rest, depth + 1)?;
+                    value.push(el);
+                    rest = next;
+                }
+
+                Ok((rest, RedisType::Array { value }))
             }
+        }
+        b'%' => {
+            let len = number(RedisTypeParseError::InvalidArrayLength)?;
+            let mut value = Vec::new();
+
+            for _ in 0..len {
+                if rest.is_empty() {
+                    return Err(RedisTypeParseError::Incomplete);
+                }
 
-            if !s.contains("\r\n") {
-                return Err(RedisTypeParseError::InvalidSuffix);
+                let (next, k) = parse(rest, depth + 1)?;
+                if next.is_empty() {
+                    return Err(RedisTypeParseError::Incomplete);
+                }
+
+                let (next, v) = parse(next, depth + 1)?;
+                value.push((k, v));
+                rest = next;
             }
 
-            let crlf = s.find("\r\n").unwrap();
-            let payload = &s[1..crlf];
-            let mut rest = &s[crlf + 2..];
+            Ok((rest, RedisType::Map { value }))
+        }
+        b'>' | b'~' => {
+            let len = number(RedisTypeParseError::InvalidArrayLength)?;
+            let mut value = Vec::new();
 
-            match bytes[0] as char {
-                '+' => Ok((
-                    rest,
-                    RedisType::String {
-                        value: String::from(payload),
-                    },
-                )),
-                '-' => Ok((
-                    rest,
-                    RedisType::Error {
-                        value: String::from(payload),
-                    },
-                )),
-                // TODO: Better error handling for failing to parse
-                ':' => Ok((
-                    rest,
-                    RedisType::Integer {
-                        value: String::from(payload).parse::<i64>().unwrap(),
-                    },
-                )),
-                '*' => {
-                    // TODO: Validate that array length parsed correctly
-                    let len = String::from(payload).parse::<i64>().unwrap();
-
-                    // Special case: bulk string with -1 length is actually a 'null' array
-                    // This is historical
-                    if len < 0 {
-                        Ok((rest, RedisType::NullArray))
-                    } else {
-                        let mut value = Vec::new();
-
-                        for _ in 0..len {
-                            let (next, el) = parse(rest)?;
-                            value.push(el);
-                            rest = next;
-                        }
+            for _ in 0..len {
+                if rest.is_empty() {
+                    return Err(RedisTypeParseError::Incomplete);
+                }
 
-                        Ok((rest, RedisType::Array { value }))
-                    }
+                let (next, el) = parse(rest, depth + 1)?;
+                value.push(el);
+                rest = next;
+            }
+
+            if data[0] == b'>' {
+                Ok((rest, RedisType::Push { value }))
+            } else {
+                Ok((rest, RedisType::Set { value }))
+            }
+        }
+        // RESP3 has a single null type, treat it as the RESP2 null string
+        b'_' => Ok((rest, RedisType::NullString)),
+        b',' => Ok((
+            rest,
+            RedisType::Double {
+                value: String::from_utf8_lossy(payload).into_owned(),
+            },
+        )),
+        b'#' => match payload {
+            b"t" => Ok((rest, RedisType::Boolean { value: true })),
+            b"f" => Ok((rest, RedisType::Boolean { value: false })),
+            _ => Err(RedisTypeParseError::InvalidBoolean),
+        },
+        // Bulk strings, plus RESP3's verbatim strings (which start with their format, such as
+        // txt:) and errors that are sent the same way
+        b'$' | b'=' | b'!' => {
+            let len = number(RedisTypeParseError::InvalidBulkLength)?;
+
+            // Special case: bulk string with -1 length is actually a 'null' value
+            // I'm just treating any negative as this case
+            if len < 0 {
+                Ok((rest, RedisType::NullString))
+            } else {
+                let len = len as usize;
+                if rest.len() < len + 2 {
+                    return Err(RedisTypeParseError::Incomplete);
                 }
-                '$' => {
-                    let len = String::from(payload).parse::<i64>().unwrap(); // TODO: Validate
-
-                    // Special case: bulk string with -1 length is actually a 'null' value
-                    // I'm just treating any negative as this case
-                    if len < 0 {
-                        Ok((rest, RedisType::NullString))
-                    } else {
-                        let len = len as usize;
-                        let value = String::from(&rest[0..len]);
-                        rest = &rest[len + 2..];
-
-                        Ok((rest, RedisType::String { value }))
-                    }
+
+                if !rest[len..].starts_with(b"\r\n") {
+                    return Err(RedisTypeParseError::InvalidSuffix);
                 }
-                _ => Err(RedisTypeParseError::InvalidPrefix),
+                let bytes = &rest[..len];
+                let value = match data[0] {
+                    b'=' if bytes.get(3) == Some(&b':') => RedisType::from(bytes[4..].to_vec()),
+                    b'!' => RedisType::Error {
+                        value: String::from_utf8_lossy(bytes).into_owned(),
+                    },
+                    _ => RedisType::from(bytes.to_vec()),
+                };
+                rest = &rest[len + 2..];
+
+                Ok((rest, value))
             }
         }
+        _ => Err(RedisTypeParseError::InvalidPrefix),
+    }
+}
 
-        match parse(s) {
-            Ok((rest, result)) if rest.len() == 0 => Ok(result),
-            Ok(_) => Err(RedisTypeParseError::LeftOverData),
-            Err(e) => Err(e),
+impl RedisType {
+    // An argument as the bytes that were sent, other values as they'd be encoded
+    pub fn to_bytes(&self) -> Vec<u8> {
+        match self {
+            RedisType::String { value } => value.clone().into_bytes(),
+            RedisType::Bulk { value } => value.to_vec(),
+            RedisType::Integer { value } => value.to_string().into_bytes(),
+            RedisType::Double { value } => value.clone().into_bytes(),
+            value => value.encode(Protocol::Resp2),
         }
     }
-}
 
-impl Display for RedisType {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    // Serialize using the given protocol version (or Encoding), Display always uses RESP2 with
+    // simple strings, and can only show bulk values that aren't valid UTF-8 approximately
+    pub fn encode(&self, encoding: impl Into<Encoding>) -> Vec<u8> {
+        self.encode_segments(encoding).concat()
+    }
+
+    // Serialize as a series of buffers to be written one after the other
+    // Large bulk values are included as they are rather than copied, everything else is encoded
+    // into buffers around them
+    pub fn encode_segments(&self, encoding: impl Into<Encoding>) -> Vec<Bytes> {
+        let mut segments = Vec::new();
+        let mut rest = Vec::new();
+        self.write_segments(&mut segments, &mut rest, encoding.into());
+        if !rest.is_empty() {
+            segments.push(Bytes::from(rest));
+        }
+        segments
+    }
+
+    fn write_segments(&self, segments: &mut Vec<Bytes>, rest: &mut Vec<u8>, encoding: Encoding) {
+        let protocol = encoding.protocol;
+        match self {
+            RedisType::Bulk { value } => {
+                rest.extend_from_slice(format!("${}\r\n", value.len()).as_bytes());
+                // Copying small values is cheaper than writing them separately
+                if value.len() < SHARED_BULK_MIN_LEN {
+                    rest.extend_from_slice(value);
+                } else {
+                    segments.push(Bytes::from(std::mem::take(rest)));
+                    segments.push(value.to_bytes());
+                }
+                rest.extend_from_slice(b"\r\n");
+            }
+            RedisType::Array { value } | RedisType::Push { value } | RedisType::Set { value } => {
+                let header = match (self, protocol) {
+                    (RedisType::Push { .. }, Protocol::Resp3) => format!(">{}\r\n", value.len()),
+                    (RedisType::Set { .. }, Protocol::Resp3) => format!("~{}\r\n", value.len()),
+                    _ => format!("*{}\r\n", value.len()),
+                };
+                rest.extend_from_slice(header.as_bytes());
+                for el in value {
+                    el.write_segments(segments, rest, encoding);
+                }
+            }
+            RedisType::Map { value } => {
+                let header = match protocol {
+                    Protocol::Resp2 => format!("*{}\r\n", value.len() * 2),
+                    Protocol::Resp3 => format!("%{}\r\n", value.len()),
+                };
+                rest.extend_from_slice(header.as_bytes());
+                for (k, v) in value {
+                    k.write_segments(segments, rest, encoding);
+                    v.write_segments(segments, rest, encoding);
+                }
+            }
+            value => {
+                let mut encoded = String::new();
+                value
+                    .write_resp(&mut encoded, encoding)
+                    .expect("writing to a String cannot fail");
+                rest.extend_from_slice(encoded.as_bytes());
+            }
+        }
+    }
+
+    fn write_resp(&self, f: &mut impl std::fmt::Write, encoding: Encoding) -> std::fmt::Result {
         let crlf = "\r\n";
+        let protocol = encoding.protocol;
 
         match self {
+            RedisType::NullString if protocol == Protocol::Resp3 => write!(f, "_{}", crlf),
+            RedisType::NullArray if protocol == Protocol::Resp3 => write!(f, "_{}", crlf),
             RedisType::NullString => write!(f, "$-1{}", crlf),
             RedisType::NullArray => write!(f, "*-1{}", crlf),
             RedisType::String { value } => {
-                if value.len() == 0 {
+                if value.is_empty() {
                     // Empty strings
                     write!(f, "$0{}{}", crlf, crlf)
-                } else if unsafe { ALWAYS_USE_BULK_STRING }
+                } else if encoding.bulk_strings
                     || (value
                         .chars()
                         .any(|c| c.is_control() || c == '\r' || c == '\n'))
@@ -163,13 +454,59 @@ impl Display for RedisType {
                     write!(f, "+{}{}", value, crlf)
                 }
             }
+            // Only for Display, encode writes the bytes as they are
+            RedisType::Bulk { value } => {
+                let value = String::from_utf8_lossy(value);
+                write!(f, "${}{}{}{}", value.len(), crlf, value, crlf)
+            }
+            // Errors can't be sent as bulk strings, so line breaks (say, in an argument quoted
+            // back to the client) become spaces as they do in Redis
+            RedisType::Error { value } if value.contains(['\r', '\n']) => {
+                write!(f, "-{}{}", value.replace(['\r', '\n'], " "), crlf)
+            }
             RedisType::Error { value } => write!(f, "-{}{}", value, crlf),
             RedisType::Integer { value } => write!(f, ":{}{}", value, crlf),
-            RedisType::Array { value } => {
-                write!(f, "*{}{}", value.len(), crlf)?;
+            RedisType::Double { value } if protocol == Protocol::Resp2 => {
+                write!(f, "${}{}{}{}", value.len(), crlf, value, crlf)
+            }
+            RedisType::Double { value } => {
+                write!(f, ",{}{}", value.replace(['\r', '\n'], " "), crlf)
+            }
+            RedisType::Boolean { value } if protocol == Protocol::Resp2 => {
+                write!(f, ":{}{}", *value as i64, crlf)
+            }
+            RedisType::Boolean { value } => {
+                write!(f, "#{}{}", if *value { 't' } else { 'f' }, crlf)
+            }
+            RedisType::Array { value } | RedisType::Push { value } | RedisType::Set { value } => {
+                // RESP2 has no push or set types, so they're sent as arrays (as Redis does for
+                // pub/sub)
+                match (self, protocol) {
+                    (RedisType::Push { .. }, Protocol::Resp3) => {
+                        write!(f, ">{}{}", value.len(), crlf)?
+                    }
+                    (RedisType::Set { .. }, Protocol::Resp3) => {
+                        write!(f, "~{}{}", value.len(), crlf)?
+                    }
+                    _ => write!(f, "*{}{}", value.len(), crlf)?,
+                }
 
                 for el in value {
-                    write!(f, "{}", el)?;
+                    el.write_resp(f, encoding)?;
+                }
+
+                Ok(())
+            }
+            RedisType::Map { value } => {
+                // RESP2 has no map type, so flatten into an array of alternating keys and values
+                match protocol {
+                    Protocol::Resp2 => write!(f, "*{}{}", value.len() * 2, crlf)?,
+                    Protocol::Resp3 => write!(f, "%{}{}", value.len(), crlf)?,
+                }
+
+                for (k, v) in value {
+                    k.write_resp(f, encoding)?;
+                    v.write_resp(f, encoding)?;
                 }
 
                 Ok(())
@@ -178,11 +515,107 @@ impl Display for RedisType {
     }
 }
 
+impl Display for RedisType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.write_resp(f, Encoding::from(Protocol::Resp2))
+    }
+}
+
+// Split a line into arguments the same way redis.conf and redis-cli do
+// Arguments are separated by whitespace and can be "double quoted" (with \n, \r, \t, \xHH
+// style escapes) or 'single quoted' (where only \' is special)
+pub fn split_args(line: &str) -> Result<Vec<String>, String> {
+    let args = split_args_bytes(line.as_bytes())?;
+    Ok(args
+        .into_iter()
+        .map(|arg| String::from_utf8_lossy(&arg).into_owned())
+        .collect())
+}
+
+// split_args for a line that can be any bytes (such as an inline command), where \xHH escapes can
+// make any byte as well
+pub fn split_args_bytes(line: &[u8]) -> Result<Vec<Vec<u8>>, String> {
+    let mut args = Vec::new();
+    let mut bytes = line.iter().copied().peekable();
+
+    loop {
+        while bytes.next_if(u8::is_ascii_whitespace).is_some() {}
+
+        let mut arg = Vec::new();
+        match bytes.peek() {
+            None => return Ok(args),
+            Some(b'"') => {
+                bytes.next();
+                loop {
+                    match bytes.next() {
+                        None => return Err(String::from("unbalanced quotes")),
+                        Some(b'"') => break,
+                        Some(b'\\') => match bytes.next() {
+                            Some(b'n') => arg.push(b'\n'),
+                            Some(b'r') => arg.push(b'\r'),
+                            Some(b't') => arg.push(b'\t'),
+                            Some(b'b') => arg.push(0x08),
+                            Some(b'a') => arg.push(0x07),
+                            Some(b'x') => {
+                                let hex = bytes.by_ref().take(2).collect::<Vec<_>>();
+                                let byte = std::str::from_utf8(&hex)
+                                    .ok()
+                                    .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+                                match byte {
+                                    Some(byte) if hex.len() == 2 => arg.push(byte),
+                                    _ => {
+                                        arg.push(b'x');
+                                        arg.extend(hex);
+                                    }
+                                }
+                            }
+                            Some(byte) => arg.push(byte),
+                            None => return Err(String::from("unbalanced quotes")),
+                        },
+                        Some(byte) => arg.push(byte),
+                    }
+                }
+            }
+            Some(b'\'') => {
+                bytes.next();
+                loop {
+                    match bytes.next() {
+                        None => return Err(String::from("unbalanced quotes")),
+                        Some(b'\'') => break,
+                        Some(b'\\') if bytes.peek() == Some(&b'\'') => {
+                            bytes.next();
+                            arg.push(b'\'');
+                        }
+                        Some(byte) => arg.push(byte),
+                    }
+                }
+            }
+            Some(_) => {
+                while let Some(byte) = bytes.next_if(|byte| !byte.is_ascii_whitespace()) {
+                    arg.push(byte);
+                }
+                args.push(arg);
+                continue;
+            }
+        }
+
+        // A closing quote must be followed by whitespace or the end of the line
+        if bytes.peek().is_some_and(|byte| !byte.is_ascii_whitespace()) {
+            return Err(String::from("closing quote must be followed by a space"));
+        }
+        args.push(arg);
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use std::str::FromStr;
 
-    use crate::RedisType;
+    use crate::value::Value;
+    use crate::{
+        split_args, split_args_bytes, Encoding, Protocol, RedisType, RedisTypeParseError,
+        MAX_NESTING, MAX_NUMBER_LINE,
+    };
 
     macro_rules! make_tests {
         ($name:tt, $string:expr, $redis:expr) => {
@@ -315,4 +748,205 @@ mod tests {
             ]
         }
     );
+
+    #[test]
+    fn test_map_from_str() {
+        assert_eq!(
+            RedisType::from_str("%2\r\n+proto\r\n:3\r\n+modules\r\n*0\r\n").unwrap(),
+            RedisType::Map {
+                value: vec![
+                    (
+                        RedisType::String {
+                            value: "proto".to_owned()
+                        },
+                        RedisType::Integer { value: 3 }
+                    ),
+                    (
+                        RedisType::String {
+                            value: "modules".to_owned()
+                        },
+                        RedisType::Array { value: vec![] }
+                    ),
+                ]
+            }
+        );
+    }
+
+    #[test]
+    fn test_bulk_encode_segments() {
+        let small = Value::from("value");
+        let large = Value::from(vec![b'x'; 100 * 1024]);
+        let reply = RedisType::Array {
+            value: vec![
+                RedisType::Bulk {
+                    value: small.clone(),
+                },
+                RedisType::Bulk {
+                    value: large.clone(),
+                },
+                RedisType::Integer { value: 1 },
+            ],
+        };
+
+        let segments = reply.encode_segments(Protocol::Resp2);
+        assert_eq!(segments.len(), 3);
+        assert_eq!(&segments[0][..], b"*3\r\n$5\r\nvalue\r\n$102400\r\n");
+        // The large value is written from the same buffer, not a copy of it
+        assert_eq!(segments[1].as_ptr(), large.as_ptr());
+        assert_eq!(&segments[2][..], b"\r\n:1\r\n");
+        assert_eq!(segments.concat(), reply.encode(Protocol::Resp2));
+    }
+
+    #[test]
+    fn test_map_encode() {
+        let map = RedisType::Map {
+            value: vec![(
+                RedisType::String {
+                    value: "proto".to_owned(),
+                },
+                RedisType::Integer { value: 3 },
+            )],
+        };
+
+        assert_eq!(map.encode(Protocol::Resp2), b"*2\r\n+proto\r\n:3\r\n");
+        assert_eq!(map.encode(Protocol::Resp3), b"%1\r\n+proto\r\n:3\r\n");
+        assert_eq!(map.to_string().into_bytes(), map.encode(Protocol::Resp2));
+    }
+
+    #[test]
+    fn test_bulk_strings_encode() {
+        let reply = RedisType::from(vec![RedisType::from(String::from("OK"))]);
+        let bulk = Encoding {
+            protocol: Protocol::Resp3,
+            bulk_strings: true,
+        };
+        assert_eq!(reply.encode(Protocol::Resp3), b"*1\r\n+OK\r\n");
+        assert_eq!(reply.encode(bulk), b"*1\r\n$2\r\nOK\r\n");
+        assert_eq!(reply.encode_segments(bulk).concat(), b"*1\r\n$2\r\nOK\r\n");
+        assert_eq!(
+            reply.to_string().into_bytes(),
+            reply.encode(Protocol::Resp2)
+        );
+    }
+
+    #[test]
+    fn test_null_encode() {
+        assert_eq!(RedisType::NullString.encode(Protocol::Resp3), b"_\r\n");
+        assert_eq!(RedisType::NullArray.encode(Protocol::Resp3), b"_\r\n");
+        assert_eq!(RedisType::from_str("_\r\n").unwrap(), RedisType::NullString);
+    }
+
+    #[test]
+    fn test_binary_encode() {
+        let binary = vec![0xff, 0xfe, b'\r', b'\n', 0, 0x80];
+        let reply = RedisType::from(vec![RedisType::Bulk {
+            value: Value::from(binary.clone()),
+        }]);
+
+        let encoded = reply.encode(Protocol::Resp2);
+        assert_eq!(encoded, b"*1\r\n$6\r\n\xff\xfe\r\n\x00\x80\r\n");
+        assert_eq!(
+            RedisType::parse_prefix(&encoded),
+            Ok((
+                RedisType::from(vec![RedisType::from(binary)]),
+                encoded.len()
+            ))
+        );
+    }
+
+    #[test]
+    fn test_parse_prefix() {
+        let input = "*1\r\n$4\r\nPING\r\n*2\r\n$3\r\nGET";
+        let (value, len) = RedisType::parse_prefix(input).unwrap();
+
+        assert_eq!(
+            value,
+            RedisType::Array {
+                value: vec![RedisType::String {
+                    value: "PING".to_owned()
+                }]
+            }
+        );
+        assert_eq!(len, 14);
+        assert!(matches!(
+            RedisType::parse_prefix(&input[len..]),
+            Err(RedisTypeParseError::Incomplete)
+        ));
+        assert!(matches!(
+            RedisType::parse_prefix("$5\r\nab"),
+            Err(RedisTypeParseError::Incomplete)
+        ));
+        assert!(matches!(
+            RedisType::parse_prefix("*2\r\n$3\r\nGET\r\n"),
+            Err(RedisTypeParseError::Incomplete)
+        ));
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        for (input, error) in [
+            (":abc\r\n", RedisTypeParseError::InvalidInteger),
+            ("*x\r\n", RedisTypeParseError::InvalidArrayLength),
+            ("%1.5\r\n", RedisTypeParseError::InvalidArrayLength),
+            ("$\r\n", RedisTypeParseError::InvalidBulkLength),
+            ("\r\n", RedisTypeParseError::InvalidPrefix),
+            ("é\r\n", RedisTypeParseError::InvalidPrefix),
+        ] {
+            assert_eq!(RedisType::from_str(input), Err(error), "{input:?}");
+        }
+
+        let deep = "*1\r\n".repeat(MAX_NESTING + 1);
+        assert_eq!(
+            RedisType::parse_prefix(&deep),
+            Err(RedisTypeParseError::NestingTooDeep)
+        );
+
+        // A length that never ends is refused rather than waited for
+        let long = format!("${}", "1".repeat(MAX_NUMBER_LINE + 1));
+        assert_eq!(
+            RedisType::parse_prefix(&long),
+            Err(RedisTypeParseError::InvalidBulkLength)
+        );
+        assert_eq!(
+            RedisType::parse_prefix(&long[..MAX_NUMBER_LINE]),
+            Err(RedisTypeParseError::Incomplete)
+        );
+    }
+
+    #[test]
+    fn test_error_encode() {
+        let error = RedisType::Error {
+            value: "ERR unknown command 'a\r\nb'".to_owned(),
+        };
+        assert_eq!(error.to_string(), "-ERR unknown command 'a  b'\r\n");
+    }
+
+    #[test]
+    fn test_split_args() {
+        assert_eq!(split_args("  set a   b ").unwrap(), vec!["set", "a", "b"]);
+        assert_eq!(
+            split_args("set \"hello world\" 'it\\'s'").unwrap(),
+            vec!["set", "hello world", "it's"]
+        );
+        assert_eq!(split_args("\"a\\r\\nb\\x41\"").unwrap(), vec!["a\r\nbA"]);
+        assert_eq!(split_args("save \"\"").unwrap(), vec!["save", ""]);
+        assert!(split_args("\"unterminated").is_err());
+        assert!(split_args("\"a\"b").is_err());
+    }
+
+    #[test]
+    fn test_split_args_bytes() {
+        // Line breaks, NUL and bytes that aren't UTF-8, both escaped and as they are
+        assert_eq!(
+            split_args_bytes(b"set \"a\\r\\nb\\x00\\xff\" \xfe\x00\x01 '\xff\r\n'").unwrap(),
+            vec![
+                b"set".to_vec(),
+                b"a\r\nb\x00\xff".to_vec(),
+                b"\xfe\x00\x01".to_vec(),
+                b"\xff\r\n".to_vec()
+            ]
+        );
+        assert_eq!(split_args("\"\\xc3\\xa9\"").unwrap(), vec!["\u{e9}"]);
+        assert!(split_args_bytes(b"\"\xff").is_err());
+    }
 }
@@ -0,0 +1,449 @@
+// Reading the Redis RDB snapshot format, shared by the server and redis-rs-check
+mod encoding;
+
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+// Snapshots use the Redis RDB format, so files can be exchanged with a real Redis server
+pub const MAGIC: &[u8] = b"REDIS";
+pub const VERSION: u32 = 9;
+// Newer versions can still be read, as long as they only use the types listed below
+const MAX_LOAD_VERSION: u32 = 12;
+
+const OPCODE_SLOT_INFO: u8 = 0xF4;
+const OPCODE_FUNCTION: u8 = 0xF5;
+const OPCODE_IDLE: u8 = 0xF8;
+const OPCODE_FREQ: u8 = 0xF9;
+pub const OPCODE_AUX: u8 = 0xFA;
+pub const OPCODE_RESIZEDB: u8 = 0xFB;
+pub const OPCODE_EXPIRETIME_MS: u8 = 0xFC;
+const OPCODE_EXPIRETIME: u8 = 0xFD;
+pub const OPCODE_SELECTDB: u8 = 0xFE;
+pub const OPCODE_EOF: u8 = 0xFF;
+
+pub const TYPE_STRING: u8 = 0;
+const TYPE_LIST: u8 = 1;
+const TYPE_SET: u8 = 2;
+const TYPE_ZSET: u8 = 3;
+const TYPE_HASH: u8 = 4;
+const TYPE_ZSET_2: u8 = 5;
+pub const TYPE_MODULE_2: u8 = 7;
+const TYPE_HASH_ZIPMAP: u8 = 9;
+const TYPE_LIST_ZIPLIST: u8 = 10;
+const TYPE_SET_INTSET: u8 = 11;
+const TYPE_ZSET_ZIPLIST: u8 = 12;
+const TYPE_HASH_ZIPLIST: u8 = 13;
+const TYPE_LIST_QUICKLIST: u8 = 14;
+const TYPE_HASH_LISTPACK: u8 = 16;
+const TYPE_ZSET_LISTPACK: u8 = 17;
+const TYPE_LIST_QUICKLIST_2: u8 = 18;
+const TYPE_SET_LISTPACK: u8 = 20;
+
+const ENCODING_INT8: u8 = 0xC0;
+const ENCODING_INT16: u8 = 0xC1;
+const ENCODING_INT32: u8 = 0xC2;
+const ENCODING_LZF: u8 = 0xC3;
+
+// Module values are a series of these, each followed by what it says
+pub const MODULE_OPCODE_EOF: u64 = 0;
+pub const MODULE_OPCODE_STRING: u64 = 5;
+
+// RedisJSON's documents, as its type name and encoding version 3 (the document as JSON text)
+pub const JSON_MODULE_ID: u64 = module_id(b"ReJSON-RL", 3);
+
+// Quicklist 2 nodes are either a single element or a listpack of them
+const QUICKLIST_NODE_PLAIN: u64 = 1;
+
+// A hash field and value, or a sorted set member and score before it is parsed
+pub type Pair = (Vec<u8>, Vec<u8>);
+
+// A key read back from a snapshot
+#[derive(Debug, PartialEq)]
+pub struct LoadedEntry {
+    pub key: Vec<u8>,
+    pub value: LoadedValue,
+    pub expires_at: Option<SystemTime>,
+}
+
+// Snapshots written by a real Redis server can contain any of its types, even though only
+// strings and JSON documents (as RedisJSON saves them) can be stored here so far
+#[derive(Debug, PartialEq)]
+pub enum LoadedValue {
+    String(Vec<u8>),
+    List(Vec<Vec<u8>>),
+    Set(Vec<Vec<u8>>),
+    SortedSet(Vec<(Vec<u8>, f64)>),
+    Hash(Vec<Pair>),
+    // The document's JSON text
+    Json(Vec<u8>),
+}
+
+impl LoadedValue {
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            LoadedValue::String(_) => "string",
+            LoadedValue::List(_) => "list",
+            LoadedValue::Set(_) => "set",
+            LoadedValue::SortedSet(_) => "zset",
+            LoadedValue::Hash(_) => "hash",
+            LoadedValue::Json(_) => "ReJSON-RL",
+        }
+    }
+}
+
+// Parse a complete RDB file, returning every key in it
+pub fn parse(data: &[u8]) -> Result<Vec<LoadedEntry>, String> {
+    let mut reader = Reader { data, pos: 0 };
+
+    if reader.bytes(MAGIC.len())? != MAGIC {
+        return Err(String::from("Wrong signature trying to load DB from file"));
+    }
+    let version = std::str::from_utf8(reader.bytes(4)?)
+        .ok()
+        .and_then(|version| version.parse::<u32>().ok())
+        .ok_or("Invalid RDB version")?;
+    if version > MAX_LOAD_VERSION {
+        return Err(format!("Can't handle RDB format version {version}"));
+    }
+
+    let mut entries = Vec::new();
+    let mut expires_at = None;
+
+    loop {
+        match reader.byte()? {
+            OPCODE_EOF => break,
+            OPCODE_AUX => {
+                reader.string()?;
+                reader.string()?;
+            }
+            OPCODE_SELECTDB => {
+                let db = reader.length()?;
+                if db != 0 {
+                    return Err(format!("Only database 0 is supported, found {db}"));
+                }
+            }
+            OPCODE_RESIZEDB => {
+                reader.length()?;
+                reader.length()?;
+            }
+            OPCODE_SLOT_INFO => {
+                reader.length()?;
+                reader.length()?;
+                reader.length()?;
+            }
+            OPCODE_FUNCTION => {
+                reader.string()?;
+            }
+            // Eviction hints for the next key, not used here
+            OPCODE_IDLE => {
+                reader.length()?;
+            }
+            OPCODE_FREQ => {
+                reader.byte()?;
+            }
+            OPCODE_EXPIRETIME_MS => {
+                let millis = u64::from_le_bytes(reader.bytes(8)?.try_into().unwrap());
+                expires_at = Some(UNIX_EPOCH + Duration::from_millis(millis));
+            }
+            OPCODE_EXPIRETIME => {
+                let seconds = u32::from_le_bytes(reader.bytes(4)?.try_into().unwrap());
+                expires_at = Some(UNIX_EPOCH + Duration::from_secs(seconds as u64));
+            }
+            kind => {
+                let key = reader.string()?;
+                let value = reader.value(kind)?;
+                entries.push(LoadedEntry {
+                    key,
+                    value,
+                    expires_at: expires_at.take(),
+                });
+            }
+        }
+    }
+
+    // Version 5 added the checksum, a checksum of 0 means it was disabled when saving
+    if version >= 5 {
+        let body = &data[..reader.pos];
+        let checksum = u64::from_le_bytes(reader.bytes(8)?.try_into().unwrap());
+        if checksum != 0 && checksum != crc64(0, body) {
+            return Err(String::from("Wrong RDB checksum"));
+        }
+    }
+
+    Ok(entries)
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn bytes(&mut self, n: usize) -> Result<&'a [u8], String> {
+        match self.data.get(self.pos..self.pos + n) {
+            Some(bytes) => {
+                self.pos += n;
+                Ok(bytes)
+            }
+            None => Err(String::from("Unexpected end of RDB file")),
+        }
+    }
+
+    fn byte(&mut self) -> Result<u8, String> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn length(&mut self) -> Result<u64, String> {
+        let first = self.byte()?;
+        match first >> 6 {
+            0 => Ok((first & 0x3F) as u64),
+            1 => Ok((((first & 0x3F) as u64) << 8) | self.byte()? as u64),
+            2 if first == 0x80 => Ok(u32::from_be_bytes(self.bytes(4)?.try_into().unwrap()) as u64),
+            2 if first == 0x81 => Ok(u64::from_be_bytes(self.bytes(8)?.try_into().unwrap())),
+            _ => Err(format!("Unsupported RDB length encoding {first:#x}")),
+        }
+    }
+
+    // Strings can also be stored as integers or LZF compressed, marked by a length with the top
+    // two bits set
+    fn string(&mut self) -> Result<Vec<u8>, String> {
+        let int = |value: i64| Ok(value.to_string().into_bytes());
+
+        match self.data.get(self.pos) {
+            Some(&ENCODING_INT8) => {
+                self.pos += 1;
+                int(self.byte()? as i8 as i64)
+            }
+            Some(&ENCODING_INT16) => {
+                self.pos += 1;
+                int(i16::from_le_bytes(self.bytes(2)?.try_into().unwrap()) as i64)
+            }
+            Some(&ENCODING_INT32) => {
+                self.pos += 1;
+                int(i32::from_le_bytes(self.bytes(4)?.try_into().unwrap()) as i64)
+            }
+            Some(&ENCODING_LZF) => {
+                self.pos += 1;
+                let compressed_length = self.length()?;
+                let length = self.length()?;
+                let compressed = self.bytes(compressed_length as usize)?;
+                encoding::lzf_decompress(compressed, length as usize)
+            }
+            _ => {
+                let length = self.length()?;
+                Ok(self.bytes(length as usize)?.to_vec())
+            }
+        }
+    }
+
+    fn strings(&mut self) -> Result<Vec<Vec<u8>>, String> {
+        let length = self.length()?;
+        (0..length).map(|_| self.string()).collect()
+    }
+
+    // Scores in the original sorted set type are written as text, with a length byte that has
+    // three special values
+    fn text_double(&mut self) -> Result<f64, String> {
+        match self.byte()? {
+            253 => Ok(f64::NAN),
+            254 => Ok(f64::INFINITY),
+            255 => Ok(f64::NEG_INFINITY),
+            length => std::str::from_utf8(self.bytes(length as usize)?)
+                .ok()
+                .and_then(|score| score.parse().ok())
+                .ok_or_else(|| String::from("Invalid sorted set score")),
+        }
+    }
+
+    fn value(&mut self, kind: u8) -> Result<LoadedValue, String> {
+        Ok(match kind {
+            TYPE_STRING => LoadedValue::String(self.string()?),
+            TYPE_LIST => LoadedValue::List(self.strings()?),
+            TYPE_SET => LoadedValue::Set(self.strings()?),
+            TYPE_ZSET | TYPE_ZSET_2 => {
+                let length = self.length()?;
+                let members = (0..length)
+                    .map(|_| {
+                        let member = self.string()?;
+                        let score = if kind == TYPE_ZSET {
+                            self.text_double()?
+                        } else {
+                            f64::from_le_bytes(self.bytes(8)?.try_into().unwrap())
+                        };
+                        Ok((member, score))
+                    })
+                    .collect::<Result<_, String>>()?;
+                LoadedValue::SortedSet(members)
+            }
+            TYPE_HASH => {
+                let length = self.length()?;
+                let pairs = (0..length)
+                    .map(|_| Ok((self.string()?, self.string()?)))
+                    .collect::<Result<_, String>>()?;
+                LoadedValue::Hash(pairs)
+            }
+            TYPE_HASH_ZIPMAP => LoadedValue::Hash(encoding::zipmap(&self.string()?)?),
+            TYPE_LIST_ZIPLIST => LoadedValue::List(encoding::ziplist(&self.string()?)?),
+            TYPE_SET_INTSET => LoadedValue::Set(encoding::intset(&self.string()?)?),
+            TYPE_SET_LISTPACK => LoadedValue::Set(encoding::listpack(&self.string()?)?),
+            TYPE_ZSET_ZIPLIST | TYPE_ZSET_LISTPACK => {
+                let blob = self.string()?;
+                let elements = if kind == TYPE_ZSET_ZIPLIST {
+                    encoding::ziplist(&blob)?
+                } else {
+                    encoding::listpack(&blob)?
+                };
+                let members = pairs(elements)?
+                    .into_iter()
+                    .map(|(member, score)| {
+                        std::str::from_utf8(&score)
+                            .ok()
+                            .and_then(|score| score.parse().ok())
+                            .map(|score| (member, score))
+                            .ok_or_else(|| String::from("Invalid sorted set score"))
+                    })
+                    .collect::<Result<_, String>>()?;
+                LoadedValue::SortedSet(members)
+            }
+            TYPE_HASH_ZIPLIST => LoadedValue::Hash(pairs(encoding::ziplist(&self.string()?)?)?),
+            TYPE_HASH_LISTPACK => LoadedValue::Hash(pairs(encoding::listpack(&self.string()?)?)?),
+            TYPE_LIST_QUICKLIST => {
+                let mut elements = Vec::new();
+                for node in self.strings()? {
+                    elements.extend(encoding::ziplist(&node)?);
+                }
+                LoadedValue::List(elements)
+            }
+            TYPE_LIST_QUICKLIST_2 => {
+                let mut elements = Vec::new();
+                for _ in 0..self.length()? {
+                    let container = self.length()?;
+                    let node = self.string()?;
+                    if container == QUICKLIST_NODE_PLAIN {
+                        elements.push(node);
+                    } else {
+                        elements.extend(encoding::listpack(&node)?);
+                    }
+                }
+                LoadedValue::List(elements)
+            }
+            TYPE_MODULE_2 => {
+                let id = self.length()?;
+                if id != JSON_MODULE_ID {
+                    return Err(format!("Unsupported RDB module type {id:#x}"));
+                }
+                if self.length()? != MODULE_OPCODE_STRING {
+                    return Err(String::from("Invalid RedisJSON value"));
+                }
+                let document = self.string()?;
+                if self.length()? != MODULE_OPCODE_EOF {
+                    return Err(String::from("Invalid RedisJSON value"));
+                }
+                LoadedValue::Json(document)
+            }
+            kind => return Err(format!("Unsupported RDB value type {kind}")),
+        })
+    }
+}
+
+// A module type's id, its 9 character name (6 bits for each) followed by 10 bits of version
+const fn module_id(name: &[u8; 9], version: u64) -> u64 {
+    const CHARSET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789-_";
+
+    let mut id = 0;
+    let mut i = 0;
+    while i < name.len() {
+        let mut c = 0;
+        while CHARSET[c] != name[i] {
+            c += 1;
+        }
+        id = (id << 6) | c as u64;
+        i += 1;
+    }
+    (id << 10) | version
+}
+
+// Hashes and sorted sets in ziplists and listpacks alternate between fields and values
+fn pairs(elements: Vec<Vec<u8>>) -> Result<Vec<Pair>, String> {
+    if !elements.len().is_multiple_of(2) {
+        return Err(String::from(
+            "Odd number of elements in a hash or sorted set",
+        ));
+    }
+
+    let mut elements = elements.into_iter();
+    let mut pairs = Vec::new();
+    while let (Some(field), Some(value)) = (elements.next(), elements.next()) {
+        pairs.push((field, value));
+    }
+    Ok(pairs)
+}
+
+// CRC-64/Jones as used by Redis (reflected, polynomial 0xad93d23594c935a9)
+pub fn crc64(mut crc: u64, data: &[u8]) -> u64 {
+    const POLY: u64 = 0x95ac_9329_ac4b_c9b5;
+
+    for byte in data {
+        crc ^= *byte as u64;
+        for _ in 0..8 {
+            crc = if crc & 1 == 1 {
+                (crc >> 1) ^ POLY
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    crc
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_crc64() {
+        // The check value from the Redis test suite
+        assert_eq!(crc64(0, b"123456789"), 0xe9c6d914c4b8d9ca);
+    }
+
+    #[test]
+    fn test_parse_redis_types() {
+        // Written the way a Redis 7 server would, with a checksum of 0 (disabled)
+        let mut data = b"REDIS0011".to_vec();
+        data.extend_from_slice(b"\xFA\x0Aredis-bits\xC0\x40");
+        data.extend_from_slice(b"\xFE\x00\xFB\x04\x00");
+        // An integer encoded string and an LZF compressed one
+        data.extend_from_slice(b"\x00\x03int\xC1\x39\x30");
+        data.extend_from_slice(b"\x00\x03lzf\xC3\x05\x0A\x00a\xE0\x00\x00");
+        // A set as an intset and a hash as a listpack
+        data.extend_from_slice(b"\x0B\x03set\x0A\x02\x00\x00\x00\x01\x00\x00\x00\x07\x00");
+        data.extend_from_slice(b"\x10\x04hash\x0D\x00\x00\x00\x00\x02\x00\x81f\x02\x81v\x02\xFF");
+        // A sorted set with binary scores
+        data.extend_from_slice(b"\x05\x04zset\x01\x01m");
+        data.extend_from_slice(&1.5f64.to_le_bytes());
+        data.extend_from_slice(b"\xFF");
+        data.extend_from_slice(&[0; 8]);
+
+        let values = parse(&data)
+            .unwrap()
+            .into_iter()
+            .map(|entry| (entry.key, entry.value))
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            values,
+            vec![
+                (b"int".to_vec(), LoadedValue::String(b"12345".to_vec())),
+                (b"lzf".to_vec(), LoadedValue::String(b"a".repeat(10))),
+                (b"set".to_vec(), LoadedValue::Set(vec![b"7".to_vec()])),
+                (
+                    b"hash".to_vec(),
+                    LoadedValue::Hash(vec![(b"f".to_vec(), b"v".to_vec())])
+                ),
+                (
+                    b"zset".to_vec(),
+                    LoadedValue::SortedSet(vec![(b"m".to_vec(), 1.5)])
+                ),
+            ]
+        );
+    }
+}
@@ -0,0 +1,307 @@
+// Decoders for the compact encodings real Redis servers use inside RDB files: LZF compressed
+// strings and the ziplist, listpack, intset and zipmap blobs that hold small collections
+
+use super::Pair;
+
+// Decompress an LZF compressed string that should come out to len bytes
+pub fn lzf_decompress(input: &[u8], len: usize) -> Result<Vec<u8>, String> {
+    let invalid = || String::from("Invalid LZF compressed string");
+    let mut output = Vec::with_capacity(len);
+    let mut i = 0;
+
+    while i < input.len() {
+        let control = input[i] as usize;
+        i += 1;
+
+        if control < 32 {
+            // A run of control + 1 literal bytes
+            let literal = input.get(i..i + control + 1).ok_or_else(invalid)?;
+            output.extend_from_slice(literal);
+            i += control + 1;
+        } else {
+            // A back reference into what has already been written
+            let mut length = control >> 5;
+            if length == 7 {
+                length += *input.get(i).ok_or_else(invalid)? as usize;
+                i += 1;
+            }
+            let offset = ((control & 0x1F) << 8) + *input.get(i).ok_or_else(invalid)? as usize + 1;
+            i += 1;
+
+            let start = output.len().checked_sub(offset).ok_or_else(invalid)?;
+            // Byte at a time, the reference may overlap what it is writing
+            for j in start..start + length + 2 {
+                output.push(output[j]);
+            }
+        }
+    }
+
+    if output.len() != len {
+        return Err(invalid());
+    }
+    Ok(output)
+}
+
+// Ziplists (RDB version 9 and before) are a header, entries each prefixed with the length of
+// the previous one, and a 0xFF terminator
+pub fn ziplist(data: &[u8]) -> Result<Vec<Vec<u8>>, String> {
+    let invalid = || String::from("Invalid ziplist");
+    let mut reader = Bytes { data, pos: 10 };
+    let mut elements = Vec::new();
+
+    loop {
+        let first = reader.byte().ok_or_else(invalid)?;
+        if first == 0xFF {
+            break;
+        }
+        if first == 0xFE {
+            reader.take(4).ok_or_else(invalid)?;
+        }
+
+        let encoding = reader.byte().ok_or_else(invalid)?;
+        let element = match encoding >> 6 {
+            0 => reader.take((encoding & 0x3F) as usize).map(<[u8]>::to_vec),
+            1 => {
+                let low = reader.byte().ok_or_else(invalid)?;
+                reader
+                    .take((((encoding & 0x3F) as usize) << 8) | low as usize)
+                    .map(<[u8]>::to_vec)
+            }
+            2 => {
+                let length = u32::from_be_bytes(reader.array().ok_or_else(invalid)?);
+                reader.take(length as usize).map(<[u8]>::to_vec)
+            }
+            _ => {
+                let value = match encoding {
+                    0xC0 => reader.array().map(i16::from_le_bytes).map(i64::from),
+                    0xD0 => reader.array().map(i32::from_le_bytes).map(i64::from),
+                    0xE0 => reader.array().map(i64::from_le_bytes),
+                    0xF0 => reader
+                        .array::<3>()
+                        .map(|b| i32::from_le_bytes([0, b[0], b[1], b[2]]) as i64 >> 8),
+                    0xFE => reader.array().map(i8::from_le_bytes).map(i64::from),
+                    0xF1..=0xFD => Some((encoding & 0x0F) as i64 - 1),
+                    _ => None,
+                };
+                value.map(|value| value.to_string().into_bytes())
+            }
+        };
+
+        elements.push(element.ok_or_else(invalid)?);
+    }
+
+    Ok(elements)
+}
+
+// Listpacks (RDB version 10 and later) replace ziplists, entries instead end with their own
+// length so they can be walked backwards
+pub fn listpack(data: &[u8]) -> Result<Vec<Vec<u8>>, String> {
+    let invalid = || String::from("Invalid listpack");
+    let mut reader = Bytes { data, pos: 6 };
+    let mut elements = Vec::new();
+
+    loop {
+        let start = reader.pos;
+        let encoding = reader.byte().ok_or_else(invalid)?;
+        if encoding == 0xFF {
+            break;
+        }
+
+        let int = |value: i64| Some(value.to_string().into_bytes());
+        let element = if encoding & 0x80 == 0 {
+            int((encoding & 0x7F) as i64)
+        } else if encoding & 0xC0 == 0x80 {
+            reader.take((encoding & 0x3F) as usize).map(<[u8]>::to_vec)
+        } else if encoding & 0xE0 == 0xC0 {
+            let low = reader.byte().ok_or_else(invalid)?;
+            let value = (((encoding & 0x1F) as i64) << 8) | low as i64;
+            int(if value >= 1 << 12 {
+                value - (1 << 13)
+            } else {
+                value
+            })
+        } else if encoding & 0xF0 == 0xE0 {
+            let low = reader.byte().ok_or_else(invalid)?;
+            reader
+                .take((((encoding & 0x0F) as usize) << 8) | low as usize)
+                .map(<[u8]>::to_vec)
+        } else {
+            match encoding {
+                0xF0 => {
+                    let length = u32::from_le_bytes(reader.array().ok_or_else(invalid)?);
+                    reader.take(length as usize).map(<[u8]>::to_vec)
+                }
+                0xF1 => reader
+                    .array()
+                    .map(i16::from_le_bytes)
+                    .and_then(|v| int(v as i64)),
+                0xF2 => reader
+                    .array::<3>()
+                    .and_then(|b| int(i32::from_le_bytes([0, b[0], b[1], b[2]]) as i64 >> 8)),
+                0xF3 => reader
+                    .array()
+                    .map(i32::from_le_bytes)
+                    .and_then(|v| int(v as i64)),
+                0xF4 => reader.array().map(i64::from_le_bytes).and_then(int),
+                _ => None,
+            }
+        };
+        elements.push(element.ok_or_else(invalid)?);
+
+        // Skip the back length, 7 bits of the entry length per byte
+        let mut entry_length = reader.pos - start;
+        loop {
+            reader.byte().ok_or_else(invalid)?;
+            entry_length >>= 7;
+            if entry_length == 0 {
+                break;
+            }
+        }
+    }
+
+    Ok(elements)
+}
+
+// Intsets are sorted integers all stored with the same width
+pub fn intset(data: &[u8]) -> Result<Vec<Vec<u8>>, String> {
+    let invalid = || String::from("Invalid intset");
+    let mut reader = Bytes { data, pos: 0 };
+    let width = u32::from_le_bytes(reader.array().ok_or_else(invalid)?);
+    let length = u32::from_le_bytes(reader.array().ok_or_else(invalid)?);
+
+    (0..length)
+        .map(|_| {
+            let value = match width {
+                2 => reader.array().map(i16::from_le_bytes).map(i64::from),
+                4 => reader.array().map(i32::from_le_bytes).map(i64::from),
+                8 => reader.array().map(i64::from_le_bytes),
+                _ => None,
+            };
+            value
+                .map(|value| value.to_string().into_bytes())
+                .ok_or_else(invalid)
+        })
+        .collect()
+}
+
+// Zipmaps are the hash encoding from before Redis 2.6, field value pairs with room for the
+// value to grow in place
+pub fn zipmap(data: &[u8]) -> Result<Vec<Pair>, String> {
+    let invalid = || String::from("Invalid zipmap");
+    let mut reader = Bytes { data, pos: 1 };
+    let mut pairs = Vec::new();
+
+    let length = |reader: &mut Bytes| match reader.byte()? {
+        length @ 0..=253 => Some(Some(length as usize)),
+        254 => reader.array().map(|b| Some(u32::from_le_bytes(b) as usize)),
+        _ => Some(None),
+    };
+
+    while let Some(field_length) = length(&mut reader).ok_or_else(invalid)? {
+        let field = reader.take(field_length).ok_or_else(invalid)?.to_vec();
+        let value_length = length(&mut reader).flatten().ok_or_else(invalid)?;
+        let free = reader.byte().ok_or_else(invalid)? as usize;
+        let value = reader.take(value_length).ok_or_else(invalid)?.to_vec();
+        reader.take(free).ok_or_else(invalid)?;
+        pairs.push((field, value));
+    }
+
+    Ok(pairs)
+}
+
+struct Bytes<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Bytes<'a> {
+    fn take(&mut self, n: usize) -> Option<&'a [u8]> {
+        let bytes = self.data.get(self.pos..self.pos.checked_add(n)?)?;
+        self.pos += n;
+        Some(bytes)
+    }
+
+    fn byte(&mut self) -> Option<u8> {
+        Some(self.take(1)?[0])
+    }
+
+    fn array<const N: usize>(&mut self) -> Option<[u8; N]> {
+        self.take(N)?.try_into().ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn strings(elements: &[&str]) -> Vec<Vec<u8>> {
+        elements.iter().map(|e| e.as_bytes().to_vec()).collect()
+    }
+
+    #[test]
+    fn test_lzf_decompress() {
+        // One literal 'a' then a back reference repeating it 9 more times
+        let compressed = [0x00, b'a', 0xE0, 0x00, 0x00];
+        assert_eq!(lzf_decompress(&compressed, 10).unwrap(), b"aaaaaaaaaa");
+
+        assert!(lzf_decompress(&compressed, 11).is_err());
+        assert!(lzf_decompress(&[0x20, 0x05], 2).is_err());
+    }
+
+    #[test]
+    fn test_ziplist() {
+        let mut data = vec![0; 10];
+        data.extend_from_slice(&[0x00, 0x03, b'a', b'b', b'c']);
+        data.extend_from_slice(&[0x05, 0xF3]); // immediate 2
+        data.extend_from_slice(&[0x02, 0xC0, 0x18, 0xFC]); // int16 -1000
+        data.extend_from_slice(&[0x04, 0xF0, 0x00, 0x00, 0x80]); // int24 -8388608
+        data.push(0xFF);
+
+        assert_eq!(
+            ziplist(&data).unwrap(),
+            strings(&["abc", "2", "-1000", "-8388608"])
+        );
+        assert!(ziplist(&data[..data.len() - 1]).is_err());
+    }
+
+    #[test]
+    fn test_listpack() {
+        let mut data = vec![0; 6];
+        data.extend_from_slice(&[0x05, 0x01]); // 7 bit uint 5
+        data.extend_from_slice(&[0x83, b'f', b'o', b'o', 0x04]);
+        data.extend_from_slice(&[0xDF, 0xFF, 0x02]); // 13 bit int -1
+        data.extend_from_slice(&[0xF1, 0x30, 0x75, 0x03]); // int16 30000
+        data.push(0xFF);
+
+        assert_eq!(
+            listpack(&data).unwrap(),
+            strings(&["5", "foo", "-1", "30000"])
+        );
+        assert!(listpack(&data[..8]).is_err());
+    }
+
+    #[test]
+    fn test_intset() {
+        let mut data = vec![2, 0, 0, 0, 2, 0, 0, 0];
+        data.extend_from_slice(&(-3i16).to_le_bytes());
+        data.extend_from_slice(&7i16.to_le_bytes());
+
+        assert_eq!(intset(&data).unwrap(), strings(&["-3", "7"]));
+        assert!(intset(&data[..10]).is_err());
+    }
+
+    #[test]
+    fn test_zipmap() {
+        let data = [
+            2, 3, b'f', b'o', b'o', 3, 1, b'b', b'a', b'r', 0, 1, b'x', 1, 0, b'y', 0xFF,
+        ];
+
+        assert_eq!(
+            zipmap(&data).unwrap(),
+            vec![
+                (b"foo".to_vec(), b"bar".to_vec()),
+                (b"x".to_vec(), b"y".to_vec()),
+            ]
+        );
+    }
+}
@@ -0,0 +1,125 @@
+// The allocator the server was built with
+// The system allocator is used unless the jemalloc or mimalloc feature is enabled, and only those
+// two can report how much memory they're holding on to or be asked to give it back.
+
+use crate::server::ServerError;
+
+#[cfg(feature = "jemalloc")]
+#[global_allocator]
+static GLOBAL: tikv_jemallocator::Jemalloc = tikv_jemallocator::Jemalloc;
+
+#[cfg(all(feature = "mimalloc", not(feature = "jemalloc")))]
+#[global_allocator]
+static GLOBAL: mimalloc::MiMalloc = mimalloc::MiMalloc;
+
+// What the allocator itself reports, in bytes
+#[derive(Debug, Default)]
+pub struct AllocatorStats {
+    // Handed out to the server (mimalloc doesn't track this, so it's left at 0)
+    pub allocated: usize,
+    // In pages that the allocator is using for those allocations
+    pub active: usize,
+    // Physical memory the allocator has mapped, including pages it hasn't returned yet
+    pub resident: usize,
+}
+
+// As reported by INFO's mem_allocator
+#[cfg(feature = "jemalloc")]
+pub fn name() -> String {
+    let version = tikv_jemalloc_ctl::version::read().unwrap_or("unknown");
+    // Drop the git describe suffix, 5.3.0-1-g... is 5.3.0
+    format!("jemalloc-{}", version.split('-').next().unwrap_or(version))
+}
+
+#[cfg(all(feature = "mimalloc", not(feature = "jemalloc")))]
+pub fn name() -> String {
+    // Two digits each for the minor version and patch, 30302 is 3.3.2
+    let version = unsafe { libmimalloc_sys::mi_version() };
+    format!(
+        "mimalloc-{}.{}.{}",
+        version / 10000,
+        version / 100 % 100,
+        version % 100
+    )
+}
+
+#[cfg(not(any(feature = "jemalloc", feature = "mimalloc")))]
+pub fn name() -> String {
+    String::from("libc")
+}
+
+#[cfg(feature = "jemalloc")]
+pub fn stats() -> Option<AllocatorStats> {
+    use tikv_jemalloc_ctl::{epoch, stats};
+
+    // jemalloc only refreshes its statistics when the epoch moves on
+    epoch::advance().ok()?;
+    Some(AllocatorStats {
+        allocated: stats::allocated::read().ok()?,
+        active: stats::active::read().ok()?,
+        resident: stats::resident::read().ok()?,
+    })
+}
+
+#[cfg(all(feature = "mimalloc", not(feature = "jemalloc")))]
+pub fn stats() -> Option<AllocatorStats> {
+    let (mut elapsed, mut user, mut system, mut rss, mut peak_rss) = (0, 0, 0, 0, 0);
+    let (mut commit, mut peak_commit, mut page_faults) = (0, 0, 0);
+    unsafe {
+        libmimalloc_sys::mi_process_info(
+            &mut elapsed,
+            &mut user,
+            &mut system,
+            &mut rss,
+            &mut peak_rss,
+            &mut commit,
+            &mut peak_commit,
+            &mut page_faults,
+        );
+    }
+
+    Some(AllocatorStats {
+        allocated: 0,
+        active: commit,
+        resident: rss,
+    })
+}
+
+#[cfg(not(any(feature = "jemalloc", feature = "mimalloc")))]
+pub fn stats() -> Option<AllocatorStats> {
+    None
+}
+
+// Ask the allocator to return pages it's holding on to but not using, for MEMORY PURGE
+#[cfg(feature = "jemalloc")]
+pub fn purge() -> Result<(), ServerError> {
+    // MALLCTL_ARENAS_ALL, every arena at once
+    let name = b"arena.4096.purge\0";
+    let result = unsafe {
+        tikv_jemalloc_sys::mallctl(
+            name.as_ptr() as *const _,
+            std::ptr::null_mut(),
+            std::ptr::null_mut(),
+            std::ptr::null_mut(),
+            0,
+        )
+    };
+
+    if result == 0 {
+        Ok(())
+    } else {
+        Err(ServerError::Err(String::from("Error purging dirty pages")))
+    }
+}
+
+#[cfg(all(feature = "mimalloc", not(feature = "jemalloc")))]
+pub fn purge() -> Result<(), ServerError> {
+    unsafe { libmimalloc_sys::mi_collect(true) };
+    Ok(())
+}
+
+// Like Redis built with libc, there's nothing to ask the system allocator to do
+#[cfg(not(any(feature = "jemalloc", feature = "mimalloc")))]
+pub fn purge() -> Result<(), ServerError> {
+    Ok(())
+}
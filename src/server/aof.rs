@@ -0,0 +1,293 @@
+use crate::aof::{check, repair, Argv};
+use crate::server::config::AppendFsync;
+use crate::server::rdb;
+use crate::server::State;
+use std::fs::{self, File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+// Every successful write command, appended in RESP form so it can be replayed on startup
+#[derive(Debug)]
+pub struct Aof {
+    file: File,
+    path: PathBuf,
+    pub fsync: AppendFsync,
+    last_fsync: Instant,
+    unsynced: bool,
+}
+
+impl Aof {
+    pub fn open(path: &Path, fsync: AppendFsync) -> std::io::Result<Aof> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+
+        Ok(Aof {
+            file,
+            path: path.to_owned(),
+            fsync,
+            last_fsync: Instant::now(),
+            unsynced: false,
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    // Written straight to the OS before the reply goes out, only the fsync depends on the policy
+    pub fn append(&mut self, argv: &[Vec<u8>]) -> std::io::Result<()> {
+        self.file.write_all(&encode(argv))?;
+
+        if self.fsync == AppendFsync::Always {
+            self.file.sync_data()?;
+        } else {
+            self.unsynced = true;
+        }
+        Ok(())
+    }
+
+    // Called every second, for appendfsync everysec
+    pub fn fsync_if_due(&mut self) -> std::io::Result<()> {
+        if self.fsync == AppendFsync::EverySec
+            && self.last_fsync.elapsed() >= Duration::from_secs(1)
+        {
+            self.sync()?;
+        }
+        Ok(())
+    }
+
+    pub fn sync(&mut self) -> std::io::Result<()> {
+        if self.unsynced {
+            self.file.sync_data()?;
+            self.unsynced = false;
+        }
+        self.last_fsync = Instant::now();
+        Ok(())
+    }
+}
+
+// A command as an array of bulk strings, the same way clients send them
+pub fn encode(argv: &[impl AsRef<[u8]>]) -> Vec<u8> {
+    let mut out = format!("*{}\r\n", argv.len()).into_bytes();
+    for arg in argv {
+        let arg = arg.as_ref();
+        out.extend_from_slice(format!("${}\r\n", arg.len()).as_bytes());
+        out.extend_from_slice(arg);
+        out.extend_from_slice(b"\r\n");
+    }
+    out
+}
+
+// The command to log for a write, with relative expirations made absolute so that
+// replaying the file later doesn't extend them
+pub fn propagated(command: &str, args: &[Vec<u8>]) -> Vec<Vec<u8>> {
+    let now = SystemTime::now();
+    let at = |offset: Duration| -> Vec<u8> {
+        (now + offset)
+            .duration_since(UNIX_EPOCH)
+            .map(|at| at.as_millis())
+            .unwrap_or_default()
+            .to_string()
+            .into_bytes()
+    };
+
+    match command {
+        "SETEX" | "PSETEX" if args.len() == 3 => {
+            let offset = if command == "SETEX" {
+                Duration::from_secs(number(&args[1]))
+            } else {
+                Duration::from_millis(number(&args[1]))
+            };
+            vec![
+                b"SET".to_vec(),
+                args[0].clone(),
+                args[2].clone(),
+                b"PXAT".to_vec(),
+                at(offset),
+            ]
+        }
+        "SET" | "GETEX" => {
+            let mut argv = vec![command.as_bytes().to_vec()];
+            let mut i = 0;
+            while i < args.len() {
+                if i + 1 < args.len() && args[i].eq_ignore_ascii_case(b"EX") {
+                    argv.extend([
+                        b"PXAT".to_vec(),
+                        at(Duration::from_secs(number(&args[i + 1]))),
+                    ]);
+                    i += 2;
+                } else if i + 1 < args.len() && args[i].eq_ignore_ascii_case(b"PX") {
+                    argv.extend([
+                        b"PXAT".to_vec(),
+                        at(Duration::from_millis(number(&args[i + 1]))),
+                    ]);
+                    i += 2;
+                } else {
+                    argv.push(args[i].clone());
+                    i += 1;
+                }
+            }
+            argv
+        }
+        "EXPIRE" | "PEXPIRE" | "EXPIREAT" if args.len() >= 2 => {
+            let value = number::<i64>(&args[1]);
+            let now = now
+                .duration_since(UNIX_EPOCH)
+                .map(|now| now.as_millis() as i64)
+                .unwrap_or_default();
+            let at = match command {
+                "EXPIRE" => now.saturating_add(value.saturating_mul(1000)),
+                "PEXPIRE" => now.saturating_add(value),
+                _ => value.saturating_mul(1000),
+            };
+            [
+                b"PEXPIREAT".to_vec(),
+                args[0].clone(),
+                at.to_string().into_bytes(),
+            ]
+            .into_iter()
+            .chain(args[2..].iter().cloned())
+            .collect()
+        }
+        _ => std::iter::once(command.as_bytes().to_vec())
+            .chain(args.iter().cloned())
+            .collect(),
+    }
+}
+
+// An argument the command has already checked is a number
+fn number<T: FromStr + Default>(value: &[u8]) -> T {
+    std::str::from_utf8(value)
+        .ok()
+        .and_then(|value| value.parse().ok())
+        .unwrap_or_default()
+}
+
+// Read back every complete command in the file at path, returns Ok(None) if there is no file yet
+// A file that ends part way through a command (from a crash part way through a write), or in bytes
+// that aren't commands with none after them, is truncated to the last complete command if
+// load_truncated, and refused otherwise. Damage with commands after it is always refused, as
+// truncating would lose them.
+pub fn load(path: &Path, load_truncated: bool) -> Result<Option<Vec<Argv>>, String> {
+    let data = match fs::read(path) {
+        Ok(data) => data,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e.to_string()),
+    };
+
+    let checked = check(&data);
+    let Some(damage) = &checked.damage else {
+        return Ok(Some(checked.commands));
+    };
+    let report = format!(
+        "{damage} (after {} valid commands in {} of {} bytes)",
+        checked.commands.len(),
+        checked.valid,
+        checked.len
+    );
+    if !damage.is_tail() {
+        return Err(format!(
+            "Bad file format reading the append only file: {report}. Make a backup of it, then \
+             use redis-rs-check --fix to truncate it"
+        ));
+    }
+    if !load_truncated {
+        return Err(format!(
+            "Unexpected end of the append only file: {report}. Use redis-rs-check --fix to \
+             truncate it, or set aof-load-truncated yes to load it anyway"
+        ));
+    }
+
+    tracing::warn!(
+        "!!! Warning: damaged end of the AOF file {}!!! {report}",
+        path.display()
+    );
+    let removed = repair(path, &checked).map_err(|e| e.to_string())?;
+    tracing::warn!("AOF truncated to its last valid command, {removed} bytes removed");
+    Ok(Some(checked.commands))
+}
+
+// Replace the file at path with the smallest set of commands that recreates the dataset
+pub fn rewrite(state: &State, path: &Path) -> std::io::Result<()> {
+    let mut out = Vec::new();
+    for entry in rdb::Snapshot::of(state).entries() {
+        let at = entry.expires_at.map(|expires_at| {
+            let at = expires_at
+                .duration_since(UNIX_EPOCH)
+                .map(|at| at.as_millis())
+                .unwrap_or_default();
+            at.to_string().into_bytes()
+        });
+        // JSON.SET has no expiration time of its own
+        if entry.json {
+            let argv = [
+                b"JSON.SET".to_vec(),
+                entry.key.to_vec(),
+                b"$".to_vec(),
+                entry.value.to_vec(),
+            ];
+            out.extend(encode(&argv));
+            if let Some(at) = at {
+                out.extend(encode(&[b"PEXPIREAT".to_vec(), entry.key.to_vec(), at]));
+            }
+            continue;
+        }
+        let mut argv = vec![b"SET".to_vec(), entry.key.to_vec(), entry.value.to_vec()];
+        if let Some(at) = at {
+            argv.extend([b"PXAT".to_vec(), at]);
+        }
+        out.extend(encode(&argv));
+    }
+
+    rdb::write_atomically(path, &out)
+}
+
+// Start a new append only file from the current dataset, as when appendonly is turned on
+pub fn start(state: &State, path: &Path, fsync: AppendFsync) -> std::io::Result<Aof> {
+    rewrite(state, path)?;
+    Aof::open(path, fsync)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::aof::parse;
+
+    fn argv(args: &[&str]) -> Vec<Vec<u8>> {
+        args.iter().map(|arg| arg.as_bytes().to_vec()).collect()
+    }
+
+    #[test]
+    fn test_encode_parse() {
+        let commands = vec![argv(&["SET", "key", "two words"]), argv(&["INCR", "n"])];
+        let mut data = commands
+            .iter()
+            .flat_map(|argv| encode(argv))
+            .collect::<Vec<_>>();
+        let complete = data.len();
+
+        assert_eq!(parse(&data), Ok((commands.clone(), complete)));
+
+        // A command cut off part way through is left out
+        data.extend_from_slice(b"*2\r\n$4\r\nINCR\r\n$1");
+        assert_eq!(parse(&data), Ok((commands, complete)));
+
+        assert!(parse(b"+OK\r\n").is_err());
+    }
+
+    #[test]
+    fn test_propagated() {
+        let setex = propagated("SETEX", &argv(&["key", "10", "value"]));
+        assert_eq!(setex[..4], argv(&["SET", "key", "value", "PXAT"]));
+        assert!(number::<u128>(&setex[4]) > 0);
+
+        let set = propagated("SET", &argv(&["key", "value", "NX", "px", "100"]));
+        assert_eq!(set[..5], argv(&["SET", "key", "value", "NX", "PXAT"]));
+
+        assert_eq!(
+            propagated("APPEND", &argv(&["key", "value"])),
+            argv(&["APPEND", "key", "value"])
+        );
+    }
+}
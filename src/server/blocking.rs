@@ -0,0 +1,309 @@
+// Commands that wait for something to happen before they reply, such as WAIT for replicas to
+// acknowledge writes (and BLPOP for a key to be pushed to, once there are lists)
+//
+// A blocking command that can't reply yet sets client.blocked instead. The connection then waits
+// without holding the lock until one of the events it's waiting for is signalled, its deadline
+// passes or the client goes away, and runs the command again, which either replies or blocks
+// again. A client keeps its place in each queue across runs, so that clients are served in the
+// order they blocked as they are in Redis.
+
+use crate::server::clients::Client;
+use crate::server::connection::Reader;
+use crate::server::lifecycle::ShutdownListener;
+use crate::server::State;
+use crate::RedisType;
+use std::collections::{HashMap, VecDeque};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::{Mutex, Notify};
+
+// Something a blocked client can be waiting for
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Event {
+    // A write to the key
+    Key(Vec<u8>),
+    // A replica acknowledging how much of the replication stream it has applied
+    ReplicaAck,
+}
+
+impl Event {
+    // Whether only the first client waiting is woken
+    // What was written to a key may be consumed by the first client (as BLPOP does), which passes
+    // the event on to the next once it's done, while every WAIT can be satisfied by the same ack.
+    fn wakes_one(&self) -> bool {
+        matches!(self, Event::Key(_))
+    }
+}
+
+// Set by a blocking command that can't reply yet
+#[derive(Debug)]
+pub struct Block {
+    events: Vec<Event>,
+    // The reply once it times out, such as nil for BLPOP or how many replicas there are for WAIT
+    timeout_reply: RedisType,
+    // From when the command first blocked, None to wait forever
+    deadline: Option<Instant>,
+    // Set once the client is parked
+    notify: Option<Arc<Notify>>,
+}
+
+impl Block {
+    pub fn new(events: Vec<Event>, timeout: Option<Duration>, timeout_reply: RedisType) -> Block {
+        Block {
+            events,
+            timeout_reply,
+            deadline: timeout.map(|timeout| Instant::now() + timeout),
+            notify: None,
+        }
+    }
+}
+
+// The clients waiting for each event, in the order they blocked
+#[derive(Debug, Default)]
+pub struct Waiters {
+    queues: HashMap<Event, VecDeque<u64>>,
+    clients: HashMap<u64, Waiter>,
+}
+
+#[derive(Debug)]
+struct Waiter {
+    events: Vec<Event>,
+    notify: Arc<Notify>,
+    // Signalled and not yet run again
+    woken: bool,
+}
+
+impl Waiters {
+    // Wait for any of events, returning what is notified when one is signalled
+    // A client that was already waiting for the same events keeps its place.
+    pub fn park(&mut self, client: u64, events: Vec<Event>) -> Arc<Notify> {
+        if let Some(waiter) = self.clients.get_mut(&client) {
+            if waiter.events == events {
+                waiter.woken = false;
+                return waiter.notify.clone();
+            }
+            self.unpark(client);
+        }
+
+        for event in &events {
+            self.queues
+                .entry(event.clone())
+                .or_default()
+                .push_back(client);
+        }
+        let notify = Arc::new(Notify::new());
+        self.clients.insert(
+            client,
+            Waiter {
+                events,
+                notify: notify.clone(),
+                woken: false,
+            },
+        );
+        notify
+    }
+
+    // Stop waiting, once the client has been served, timed out or gone
+    pub fn unpark(&mut self, client: u64) {
+        let Some(waiter) = self.clients.remove(&client) else {
+            return;
+        };
+
+        for event in &waiter.events {
+            if let Some(queue) = self.queues.get_mut(event) {
+                queue.retain(|id| *id != client);
+                if queue.is_empty() {
+                    self.queues.remove(event);
+                }
+            }
+        }
+
+        // Whatever it was woken for might be enough for the next one in line
+        if waiter.woken {
+            for event in &waiter.events {
+                self.signal(event);
+            }
+        }
+    }
+
+    // Wake the clients waiting for event that haven't been already
+    pub fn signal(&mut self, event: &Event) {
+        let Some(queue) = self.queues.get(event) else {
+            return;
+        };
+
+        for id in queue {
+            let waiter = self
+                .clients
+                .get_mut(id)
+                .expect("every queued client is waiting");
+            if waiter.woken {
+                continue;
+            }
+
+            waiter.woken = true;
+            waiter.notify.notify_one();
+            if event.wakes_one() {
+                break;
+            }
+        }
+    }
+
+    pub fn signal_keys(&mut self, keys: &[Vec<u8>]) {
+        if self.queues.is_empty() {
+            return;
+        }
+        for key in keys {
+            self.signal(&Event::Key(key.clone()));
+        }
+    }
+
+    // How many clients are blocked, for INFO
+    pub fn len(&self) -> usize {
+        self.clients.len()
+    }
+}
+
+// After a command has run, park the client if it blocked (keeping the deadline from when it first
+// did, if it was woken and blocked again), otherwise it's done waiting
+// Returns if it's blocked.
+pub fn park(state: &mut State, client: &mut Client, previous: Option<Block>) -> bool {
+    let Some(block) = client.blocked.as_mut() else {
+        if previous.is_some() {
+            state.waiters.unpark(client.id);
+        }
+        return false;
+    };
+
+    if let Some(previous) = previous {
+        block.deadline = previous.deadline;
+    }
+    block.notify = Some(state.waiters.park(client.id, block.events.clone()));
+
+    // Shown as blocked by CLIENT LIST until it replies
+    state.clients.insert(client.id, client.info());
+    true
+}
+
+pub enum Woken {
+    // Signalled, the command should be run again
+    Ready,
+    TimedOut(RedisType),
+    // Given up on, since the server is shutting down
+    ShuttingDown,
+    // The client went away or was killed
+    Closed,
+}
+
+// Wait for a parked client to be woken, reading anything else it sends onto the end of input
+// until then (to be run once it's been answered)
+pub async fn wait(
+    state: &Arc<Mutex<State>>,
+    client: &mut Client,
+    reader: &mut impl Reader,
+    input: &mut Vec<u8>,
+    shutdown: &mut ShutdownListener,
+) -> Woken {
+    let block = client.blocked.as_ref().expect("only blocked clients wait");
+    let notify = block.notify.clone().expect("blocked clients are parked");
+    let deadline = block.deadline;
+    let mut killed = client.kill.subscribe();
+
+    let woken = loop {
+        let timeout = async {
+            match deadline {
+                Some(deadline) => tokio::time::sleep_until(deadline.into()).await,
+                None => std::future::pending().await,
+            }
+        };
+
+        // A signal before notified is polled isn't lost, it's kept until then
+        tokio::select! {
+            _ = notify.notified() => return Woken::Ready,
+            _ = timeout => {
+                let block = client.blocked.take().expect("only blocked clients wait");
+                break Woken::TimedOut(block.timeout_reply);
+            }
+            _ = killed.wait() => break Woken::Closed,
+            _ = shutdown.wait() => {
+                client.blocked = None;
+                break Woken::ShuttingDown;
+            }
+            result = reader.read(input) => {
+                if !matches!(result, Ok(len) if len > 0) {
+                    break Woken::Closed;
+                }
+            }
+        }
+    };
+
+    state.lock().await.waiters.unpark(client.id);
+    woken
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Event, Waiters};
+
+    fn key(key: &str) -> Event {
+        Event::Key(key.as_bytes().to_vec())
+    }
+
+    #[test]
+    fn test_key_wakes_in_order() {
+        let mut waiters = Waiters::default();
+        waiters.park(1, vec![key("a")]);
+        waiters.park(2, vec![key("a"), key("b")]);
+        assert_eq!(waiters.len(), 2);
+
+        // Only the first in line, until it's done
+        waiters.signal(&key("a"));
+        assert!(waiters.clients[&1].woken);
+        assert!(!waiters.clients[&2].woken);
+
+        // A second write wakes the next
+        waiters.signal(&key("a"));
+        assert!(waiters.clients[&2].woken);
+
+        // Running again without being served keeps the first in line
+        waiters.park(1, vec![key("a")]);
+        waiters.park(2, vec![key("a"), key("b")]);
+        waiters.signal(&key("a"));
+        assert!(waiters.clients[&1].woken);
+        assert!(!waiters.clients[&2].woken);
+    }
+
+    #[test]
+    fn test_unpark_passes_wakeup_on() {
+        let mut waiters = Waiters::default();
+        waiters.park(1, vec![key("a")]);
+        waiters.park(2, vec![key("a")]);
+
+        waiters.signal(&key("a"));
+        waiters.unpark(1);
+        assert!(waiters.clients[&2].woken);
+
+        waiters.unpark(2);
+        assert_eq!(waiters.len(), 0);
+        assert!(waiters.queues.is_empty());
+
+        // Nothing to pass on if it wasn't woken
+        waiters.park(3, vec![key("a")]);
+        waiters.park(4, vec![key("a")]);
+        waiters.unpark(3);
+        assert!(!waiters.clients[&4].woken);
+    }
+
+    #[test]
+    fn test_replica_ack_wakes_everyone() {
+        let mut waiters = Waiters::default();
+        waiters.park(1, vec![Event::ReplicaAck]);
+        waiters.park(2, vec![Event::ReplicaAck]);
+        waiters.park(3, vec![key("a")]);
+
+        waiters.signal(&Event::ReplicaAck);
+        assert!(waiters.clients[&1].woken);
+        assert!(waiters.clients[&2].woken);
+        assert!(!waiters.clients[&3].woken);
+    }
+}
@@ -0,0 +1,314 @@
+use crate::server::blocking::Block;
+use crate::server::lifecycle::Shutdown;
+use crate::server::replication::FullSync;
+use crate::{Encoding, Protocol, RedisType};
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::mpsc;
+
+static NEXT_CLIENT_ID: AtomicU64 = AtomicU64::new(1);
+
+// Per-connection state, owned by the task handling that connection
+#[derive(Debug)]
+pub struct Client {
+    pub id: u64,
+    pub addr: SocketAddr,
+    pub laddr: SocketAddr,
+    pub name: Option<String>,
+    pub protocol: Protocol,
+    // Whether replies such as GET's are sent as bulk strings, as Redis does, even where a simple
+    // string would do
+    pub bulk_strings: bool,
+    pub authenticated: bool,
+    pub created: Instant,
+    pub last_interaction: Instant,
+    pub last_command: Option<String>,
+    // Bytes waiting in the query buffer and the output buffer, as of the last command
+    pub query_buffer: usize,
+    pub output_memory: usize,
+    pub kill: Arc<Shutdown>,
+    // Messages from other connections to send outside of any reply, such as CLIENT TRACKING's
+    // invalidations
+    pub pushes: mpsc::UnboundedSender<RedisType>,
+    // Set by QUIT, the connection closes once the current reply is sent
+    pub close_after_reply: bool,
+    // Set by CLIENT NO-EVICT, never disconnected for output buffer limits
+    pub no_evict: bool,
+    // Set by CLIENT NO-TOUCH, commands don't update when keys were last accessed
+    pub no_touch: bool,
+    // Sent by a replica with REPLCONF listening-port before it asks to sync
+    pub listening_port: Option<u16>,
+    // Set by PSYNC and SYNC, the connection becomes a replica once the reply is sent
+    pub full_sync: Option<FullSync>,
+    pub replica: bool,
+    // The connection to our master, when we are a replica
+    pub master: bool,
+    // Set by ASKING, the next command may use a slot this node is importing
+    pub asking: bool,
+    // Set by commands that don't reply, like REPLCONF ACK
+    pub no_reply: bool,
+    // Set by blocking commands that can't reply yet, such as WAIT
+    pub blocked: Option<Block>,
+    // The replication offset just after this client's last write, which WAIT waits for
+    pub write_offset: u64,
+    // Set by CLIENT TRACKING ON, the keys it reads are remembered
+    pub tracking: bool,
+    // How many channels and patterns it's subscribed to, see pubsub.rs
+    pub channels: usize,
+    pub patterns: usize,
+    // Sent before the reply a command returns, by commands that reply more than once (such as
+    // SUBSCRIBE with several channels)
+    pub replies: Vec<RedisType>,
+}
+
+impl Client {
+    pub fn new(addr: SocketAddr, laddr: SocketAddr) -> Self {
+        let now = Instant::now();
+
+        Client {
+            id: NEXT_CLIENT_ID.fetch_add(1, Ordering::Relaxed),
+            addr,
+            laddr,
+            name: None,
+            protocol: Protocol::default(),
+            bulk_strings: true,
+            authenticated: false,
+            created: now,
+            last_interaction: now,
+            last_command: None,
+            query_buffer: 0,
+            output_memory: 0,
+            kill: Arc::default(),
+            // A connection replaces this with one it reads from, anything else (such as the AOF
+            // being loaded) has nowhere to send them
+            pushes: mpsc::unbounded_channel().0,
+            close_after_reply: false,
+            no_evict: false,
+            no_touch: false,
+            listening_port: None,
+            full_sync: None,
+            replica: false,
+            master: false,
+            asking: false,
+            no_reply: false,
+            blocked: None,
+            write_offset: 0,
+            tracking: false,
+            channels: 0,
+            patterns: 0,
+            replies: Vec::new(),
+        }
+    }
+
+    pub fn subscribed(&self) -> bool {
+        self.channels + self.patterns > 0
+    }
+
+    // How replies to this connection are serialized
+    pub fn encoding(&self) -> Encoding {
+        Encoding {
+            protocol: self.protocol,
+            bulk_strings: self.bulk_strings,
+        }
+    }
+
+    // Snapshot for the shared client registry
+    pub fn info(&self) -> ClientInfo {
+        ClientInfo {
+            id: self.id,
+            addr: self.addr,
+            laddr: self.laddr,
+            name: self.name.clone(),
+            protocol: self.protocol,
+            created: self.created,
+            last_interaction: self.last_interaction,
+            last_command: self.last_command.clone(),
+            query_buffer: self.query_buffer,
+            output_memory: self.output_memory,
+            kill: self.kill.clone(),
+            pushes: self.pushes.clone(),
+            no_evict: self.no_evict,
+            no_touch: self.no_touch,
+            replica: self.replica,
+            master: self.master,
+            blocked: self.blocked.is_some(),
+            tracking: self.tracking,
+            channels: self.channels,
+            patterns: self.patterns,
+        }
+    }
+}
+
+// What other connections can see about a client, refreshed around each command it runs
+#[derive(Clone, Debug)]
+pub struct ClientInfo {
+    pub id: u64,
+    pub addr: SocketAddr,
+    pub laddr: SocketAddr,
+    pub name: Option<String>,
+    pub protocol: Protocol,
+    pub created: Instant,
+    pub last_interaction: Instant,
+    pub last_command: Option<String>,
+    pub query_buffer: usize,
+    pub output_memory: usize,
+    pub kill: Arc<Shutdown>,
+    pub pushes: mpsc::UnboundedSender<RedisType>,
+    pub no_evict: bool,
+    pub no_touch: bool,
+    pub replica: bool,
+    pub master: bool,
+    pub blocked: bool,
+    pub tracking: bool,
+    pub channels: usize,
+    pub patterns: usize,
+}
+
+// One condition of a CLIENT KILL, a client must match all of them to be killed
+pub type ClientFilter = Box<dyn Fn(&ClientInfo) -> bool>;
+
+impl ClientInfo {
+    // A single line in the format used by CLIENT LIST and CLIENT INFO
+    pub fn describe(&self) -> String {
+        let mut flags = String::new();
+        if self.no_evict {
+            flags.push('e');
+        }
+        if self.no_touch {
+            flags.push('T');
+        }
+        if self.replica {
+            flags.push('S');
+        }
+        if self.master {
+            flags.push('M');
+        }
+        if self.blocked {
+            flags.push('b');
+        }
+        if self.tracking {
+            flags.push('t');
+        }
+        if self.channels + self.patterns > 0 {
+            flags.push('P');
+        }
+        if flags.is_empty() {
+            flags.push('N');
+        }
+
+        format!(
+            "id={} addr={} laddr={} fd=-1 name={} age={} idle={} flags={flags} db=0 sub={} psub={} multi=-1 qbuf={} omem={} cmd={} user=default resp={}",
+            self.id,
+            self.addr,
+            self.laddr,
+            self.name.as_deref().unwrap_or(""),
+            self.created.elapsed().as_secs(),
+            self.last_interaction.elapsed().as_secs(),
+            self.channels,
+            self.patterns,
+            self.query_buffer,
+            self.output_memory,
+            self.last_command.as_deref().unwrap_or("NULL"),
+            self.protocol.version(),
+        )
+    }
+}
+
+// Names are shown in CLIENT LIST, so they can't contain anything that would break its format
+pub fn is_valid_name(name: &str) -> bool {
+    name.chars().all(|c| c.is_ascii_graphic())
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PauseMode {
+    All,
+    Write,
+}
+
+// Set by CLIENT PAUSE, commands it applies to wait until it ends or CLIENT UNPAUSE
+#[derive(Clone, Copy, Debug)]
+pub struct Pause {
+    pub mode: PauseMode,
+    pub until: Instant,
+}
+
+impl Pause {
+    // A new pause never shortens an existing one and ALL wins over WRITE
+    pub fn merge(self, other: Option<Pause>) -> Pause {
+        match other {
+            Some(other) if other.until > Instant::now() => Pause {
+                mode: if self.mode == PauseMode::All || other.mode == PauseMode::All {
+                    PauseMode::All
+                } else {
+                    PauseMode::Write
+                },
+                until: self.until.max(other.until),
+            },
+            _ => self,
+        }
+    }
+
+    // How much longer a command has to wait, if this pause applies to it at all
+    pub fn remaining(&self, write: bool) -> Option<Duration> {
+        if self.mode == PauseMode::Write && !write {
+            return None;
+        }
+
+        let remaining = self.until.saturating_duration_since(Instant::now());
+        if remaining.is_zero() {
+            None
+        } else {
+            Some(remaining)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pause_remaining() {
+        let pause = Pause {
+            mode: PauseMode::Write,
+            until: Instant::now() + Duration::from_secs(10),
+        };
+        assert!(pause.remaining(true).is_some());
+        assert!(pause.remaining(false).is_none());
+
+        let expired = Pause {
+            mode: PauseMode::All,
+            until: Instant::now(),
+        };
+        assert!(expired.remaining(true).is_none());
+    }
+
+    #[test]
+    fn test_pause_merge() {
+        let now = Instant::now();
+        let long_write = Pause {
+            mode: PauseMode::Write,
+            until: now + Duration::from_secs(10),
+        };
+        let short_all = Pause {
+            mode: PauseMode::All,
+            until: now + Duration::from_secs(1),
+        };
+
+        let merged = short_all.merge(Some(long_write));
+        assert_eq!(merged.mode, PauseMode::All);
+        assert_eq!(merged.until, long_write.until);
+
+        assert_eq!(long_write.merge(None).mode, PauseMode::Write);
+    }
+
+    #[test]
+    fn test_valid_name() {
+        assert!(is_valid_name("worker-1"));
+        assert!(is_valid_name(""));
+        assert!(!is_valid_name("two words"));
+        assert!(!is_valid_name("line\nbreak"));
+    }
+}
@@ -0,0 +1,461 @@
+use crate::cluster::SLOTS;
+use crate::server::rdb;
+use crate::server::replication::new_replid;
+use crate::server::{aof, ServerError, State};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Write;
+use std::ops::RangeInclusive;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::TcpStream;
+use tokio::sync::Mutex;
+
+// The cluster bus port is always the client port plus this, although there's no bus (yet)
+const BUS_PORT_OFFSET: u16 = 10000;
+
+const MEET_TIMEOUT: Duration = Duration::from_secs(5);
+
+// What this node knows about the cluster: the other nodes (added with CLUSTER MEET) and which of
+// us serves each slot (set with CLUSTER ADDSLOTS and CLUSTER SETSLOT)
+#[derive(Debug)]
+pub struct Cluster {
+    pub myid: String,
+    pub nodes: BTreeMap<String, Node>,
+    // Slots being moved away from us, and to us, with the node on the other end
+    pub migrating: BTreeMap<u16, String>,
+    pub importing: BTreeMap<u16, String>,
+    // Addresses from CLUSTER MEET, connected to by the cron
+    meets: Vec<(String, u16)>,
+}
+
+#[derive(Debug, Default)]
+pub struct Node {
+    pub id: String,
+    // Empty for ourselves, clients are told the address they connected to
+    pub ip: String,
+    pub port: u16,
+    pub slots: BTreeSet<u16>,
+}
+
+impl Default for Cluster {
+    fn default() -> Self {
+        let myid = new_replid();
+        let mut nodes = BTreeMap::new();
+        nodes.insert(
+            myid.clone(),
+            Node {
+                id: myid.clone(),
+                ..Node::default()
+            },
+        );
+
+        Cluster {
+            myid,
+            nodes,
+            migrating: BTreeMap::new(),
+            importing: BTreeMap::new(),
+            meets: Vec::new(),
+        }
+    }
+}
+
+impl Node {
+    // Contiguous runs of slots, as used by CLUSTER SLOTS, CLUSTER SHARDS and nodes.conf
+    pub fn slot_ranges(&self) -> Vec<RangeInclusive<u16>> {
+        let mut ranges: Vec<RangeInclusive<u16>> = Vec::new();
+        for &slot in &self.slots {
+            match ranges.last_mut() {
+                Some(range) if *range.end() + 1 == slot => *range = *range.start()..=slot,
+                _ => ranges.push(slot..=slot),
+            }
+        }
+        ranges
+    }
+
+    pub fn endpoint(&self) -> String {
+        format!("{}:{}", self.ip, self.port)
+    }
+}
+
+impl Cluster {
+    pub fn myself(&self) -> &Node {
+        &self.nodes[&self.myid]
+    }
+
+    pub fn owner(&self, slot: u16) -> Option<&Node> {
+        self.nodes.values().find(|node| node.slots.contains(&slot))
+    }
+
+    // Give slot to node (which must be known), taking it from whoever had it before
+    pub fn assign(&mut self, slot: u16, id: &str) {
+        for node in self.nodes.values_mut() {
+            node.slots.remove(&slot);
+        }
+        if let Some(node) = self.nodes.get_mut(id) {
+            node.slots.insert(slot);
+        }
+    }
+
+    pub fn unassign(&mut self, slot: u16) {
+        for node in self.nodes.values_mut() {
+            node.slots.remove(&slot);
+        }
+    }
+
+    pub fn slots_assigned(&self) -> usize {
+        self.nodes.values().map(|node| node.slots.len()).sum()
+    }
+
+    // The cluster only accepts commands with keys once every slot is served by someone
+    pub fn is_ok(&self) -> bool {
+        self.slots_assigned() == SLOTS as usize
+    }
+
+    // Whether a command for keys in slot can run here, or the error redirecting the client
+    // missing_keys is whether any of them don't exist here, which matters while migrating
+    // asking is whether the client sent ASKING first, to use a slot we are importing
+    pub fn route(&self, slot: u16, missing_keys: bool, asking: bool) -> Result<(), ServerError> {
+        if !self.is_ok() {
+            return Err(ServerError::ClusterDown("The cluster is down"));
+        }
+
+        let owner = match self.owner(slot) {
+            Some(owner) => owner,
+            None => return Err(ServerError::ClusterDown("Hash slot not served")),
+        };
+
+        if owner.id == self.myid {
+            match self.migrating.get(&slot).and_then(|id| self.nodes.get(id)) {
+                Some(target) if missing_keys => Err(ServerError::Ask(slot, target.endpoint())),
+                _ => Ok(()),
+            }
+        } else if asking && self.importing.contains_key(&slot) {
+            Ok(())
+        } else {
+            Err(ServerError::Moved(slot, owner.endpoint()))
+        }
+    }
+
+    // CLUSTER MEET, the connection is made by the cron
+    pub fn meet(&mut self, ip: String, port: u16) {
+        let known = self
+            .nodes
+            .values()
+            .any(|node| node.ip == ip && node.port == port);
+        if !known && !self.meets.contains(&(ip.clone(), port)) {
+            self.meets.push((ip, port));
+        }
+    }
+
+    pub fn take_meets(&mut self) -> Vec<(String, u16)> {
+        std::mem::take(&mut self.meets)
+    }
+
+    // In the format of nodes.conf and CLUSTER NODES, our own ip is the one the reader sees
+    pub fn describe_nodes(&self, my_ip: &str, my_port: u16) -> String {
+        let mut out = String::new();
+        for node in self.nodes.values() {
+            let myself = node.id == self.myid;
+            let (ip, port) = if myself {
+                (my_ip, my_port)
+            } else {
+                (node.ip.as_str(), node.port)
+            };
+            let flags = if myself { "myself,master" } else { "master" };
+
+            let _ = write!(
+                out,
+                "{} {ip}:{port}@{} {flags} - 0 0 0 connected",
+                node.id,
+                port as u32 + BUS_PORT_OFFSET as u32,
+            );
+            for range in node.slot_ranges() {
+                if range.start() == range.end() {
+                    let _ = write!(out, " {}", range.start());
+                } else {
+                    let _ = write!(out, " {}-{}", range.start(), range.end());
+                }
+            }
+            if myself {
+                for (slot, id) in &self.migrating {
+                    let _ = write!(out, " [{slot}->-{id}]");
+                }
+                for (slot, id) in &self.importing {
+                    let _ = write!(out, " [{slot}-<-{id}]");
+                }
+            }
+            out.push('\n');
+        }
+        out
+    }
+}
+
+// A slot given to a CLUSTER subcommand
+pub fn parse_slot(s: &str) -> Result<u16, ServerError> {
+    match s.parse::<u16>() {
+        Ok(slot) if slot < SLOTS => Ok(slot),
+        _ => Err(ServerError::Err(String::from(
+            "Invalid or out of range slot",
+        ))),
+    }
+}
+
+// Parse a slot number or an inclusive range of them as written in nodes.conf
+fn parse_slots(s: &str) -> Option<RangeInclusive<u16>> {
+    let (start, end) = s.split_once('-').unwrap_or((s, s));
+    let (start, end) = (start.parse::<u16>().ok()?, end.parse::<u16>().ok()?);
+    (start <= end && end < SLOTS).then_some(start..=end)
+}
+
+// Load the cluster configuration written by save, None if there isn't one yet
+pub fn load(path: &Path) -> Result<Option<Cluster>, String> {
+    let contents = match std::fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e.to_string()),
+    };
+
+    parse(&contents).map(Some)
+}
+
+// Parse nodes.conf, in the same format as CLUSTER NODES
+fn parse(contents: &str) -> Result<Cluster, String> {
+    let mut cluster = Cluster {
+        myid: String::new(),
+        nodes: BTreeMap::new(),
+        migrating: BTreeMap::new(),
+        importing: BTreeMap::new(),
+        meets: Vec::new(),
+    };
+
+    for (number, line) in contents.lines().enumerate() {
+        let error = |message: &str| format!("{message} at line {}: {line}", number + 1);
+
+        let parts = line.split_ascii_whitespace().collect::<Vec<_>>();
+        if parts.is_empty() || parts[0] == "vars" {
+            continue;
+        }
+        if parts.len() < 8 {
+            return Err(error("Not enough fields"));
+        }
+
+        let address = parts[1].split('@').next().unwrap();
+        let (ip, port) = address
+            .rsplit_once(':')
+            .ok_or_else(|| error("Invalid address"))?;
+        let port = port.parse().map_err(|_| error("Invalid port"))?;
+
+        let mut node = Node {
+            id: parts[0].to_owned(),
+            ip: ip.to_owned(),
+            port,
+            slots: BTreeSet::new(),
+        };
+        let myself = parts[2].split(',').any(|flag| flag == "myself");
+        if myself {
+            cluster.myid = node.id.clone();
+            node.ip = String::new();
+        }
+
+        for part in &parts[8..] {
+            // Migrating and importing slots, only written for ourselves
+            if let Some(part) = part.strip_prefix('[').and_then(|p| p.strip_suffix(']')) {
+                if let Some((slot, id)) = part.split_once("->-") {
+                    let slot = slot.parse().map_err(|_| error("Invalid slot"))?;
+                    cluster.migrating.insert(slot, id.to_owned());
+                } else if let Some((slot, id)) = part.split_once("-<-") {
+                    let slot = slot.parse().map_err(|_| error("Invalid slot"))?;
+                    cluster.importing.insert(slot, id.to_owned());
+                }
+                continue;
+            }
+
+            let range = parse_slots(part).ok_or_else(|| error("Invalid slot"))?;
+            node.slots.extend(range);
+        }
+
+        cluster.nodes.insert(node.id.clone(), node);
+    }
+
+    if cluster.myid.is_empty() {
+        return Err(String::from("Missing the myself node"));
+    }
+    Ok(cluster)
+}
+
+// Write the cluster configuration so that our id and slots survive a restart
+pub fn save(state: &State) {
+    let path = Path::new(&state.config.cluster_config_file);
+    let mut contents = state.cluster.describe_nodes("", state.config.port);
+    contents.push_str("vars currentEpoch 0 lastVoteEpoch 0\n");
+
+    if let Err(e) = rdb::write_atomically(path, contents.as_bytes()) {
+        tracing::warn!("Unable to save the cluster config {}: {e}", path.display());
+    }
+}
+
+// Learn the id of the node at ip and port for CLUSTER MEET, then have it meet us in turn
+pub async fn meet(state: Arc<Mutex<State>>, ip: String, port: u16) {
+    let result = tokio::time::timeout(MEET_TIMEOUT, ask_id(&ip, port)).await;
+    let (id, mut stream, local_ip) = match result {
+        Ok(Ok(result)) => result,
+        Ok(Err(e)) => {
+            tracing::warn!("Unable to meet cluster node {ip}:{port}: {e}");
+            return;
+        }
+        Err(_) => {
+            tracing::warn!("Unable to meet cluster node {ip}:{port}: timed out");
+            return;
+        }
+    };
+
+    let my_port = {
+        let mut state = state.lock().await;
+        if state.cluster.nodes.contains_key(&id) {
+            return;
+        }
+
+        tracing::info!("Cluster node {id} met at {ip}:{port}");
+        state.cluster.nodes.insert(
+            id.clone(),
+            Node {
+                id,
+                ip,
+                port,
+                slots: BTreeSet::new(),
+            },
+        );
+        save(&state);
+        state.config.port
+    };
+
+    // Its reply doesn't matter, it will connect back to us to learn our id, but wait for it so
+    // that the connection isn't reset under the command
+    let argv = [
+        String::from("CLUSTER"),
+        String::from("MEET"),
+        local_ip,
+        my_port.to_string(),
+    ];
+    if stream.write_all(&aof::encode(&argv)).await.is_ok() {
+        let mut reader = BufReader::new(&mut stream);
+        let mut line = String::new();
+        let _ = tokio::time::timeout(MEET_TIMEOUT, reader.read_line(&mut line)).await;
+    }
+}
+
+// Connect and ask for CLUSTER MYID, keeping the connection and our address on it
+async fn ask_id(ip: &str, port: u16) -> Result<(String, TcpStream, String), String> {
+    let mut stream = TcpStream::connect((ip, port))
+        .await
+        .map_err(|e| e.to_string())?;
+    let local_ip = stream
+        .local_addr()
+        .map_err(|e| e.to_string())?
+        .ip()
+        .to_string();
+
+    let argv = [String::from("CLUSTER"), String::from("MYID")];
+    stream
+        .write_all(&aof::encode(&argv))
+        .await
+        .map_err(|e| e.to_string())?;
+
+    // Either a simple string or, as this server sends it, a bulk string
+    let mut reader = BufReader::new(&mut stream);
+    let mut line = String::new();
+    reader
+        .read_line(&mut line)
+        .await
+        .map_err(|e| e.to_string())?;
+    if line.starts_with('$') {
+        line.clear();
+        reader
+            .read_line(&mut line)
+            .await
+            .map_err(|e| e.to_string())?;
+    } else if let Some(error) = line.strip_prefix('-') {
+        return Err(error.trim_end().to_owned());
+    } else {
+        line.remove(0);
+    }
+
+    let id = line.trim_end().to_owned();
+    if id.len() != 40 {
+        return Err(format!("Unexpected reply to CLUSTER MYID: {id}"));
+    }
+    Ok((id, stream, local_ip))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_route() {
+        let mut cluster = Cluster::default();
+        let myid = cluster.myid.clone();
+        assert_eq!(
+            cluster.route(0, false, false),
+            Err(ServerError::ClusterDown("The cluster is down"))
+        );
+
+        let other = String::from("b").repeat(40);
+        cluster.nodes.insert(
+            other.clone(),
+            Node {
+                id: other.clone(),
+                ip: String::from("10.0.0.2"),
+                port: 7000,
+                slots: BTreeSet::new(),
+            },
+        );
+        for slot in 0..SLOTS {
+            cluster.assign(slot, if slot < 100 { &myid } else { &other });
+        }
+        assert!(cluster.is_ok());
+        assert_eq!(cluster.myself().slot_ranges(), vec![0..=99]);
+
+        assert_eq!(cluster.route(5, true, false), Ok(()));
+        assert_eq!(
+            cluster.route(500, false, false),
+            Err(ServerError::Moved(500, String::from("10.0.0.2:7000")))
+        );
+
+        // Missing keys in a migrating slot are asked for on the target
+        cluster.migrating.insert(5, other.clone());
+        assert_eq!(cluster.route(5, false, false), Ok(()));
+        assert_eq!(
+            cluster.route(5, true, false),
+            Err(ServerError::Ask(5, String::from("10.0.0.2:7000")))
+        );
+
+        // An importing slot is only used after ASKING
+        cluster.importing.insert(500, other.clone());
+        assert!(cluster.route(500, false, false).is_err());
+        assert_eq!(cluster.route(500, false, true), Ok(()));
+    }
+
+    #[test]
+    fn test_nodes_round_trip() {
+        let mut cluster = Cluster::default();
+        let myid = cluster.myid.clone();
+        for slot in (0..10).chain([20]) {
+            cluster.assign(slot, &myid);
+        }
+        cluster.importing.insert(30, String::from("c").repeat(40));
+
+        let contents = cluster.describe_nodes("", 6379);
+        assert!(contents.contains(" 0-9 20 [30-<-"));
+
+        let loaded = parse(&contents).unwrap();
+        assert_eq!(loaded.myid, myid);
+        assert_eq!(loaded.myself().slots, cluster.myself().slots);
+        assert_eq!(loaded.importing, cluster.importing);
+
+        assert!(parse("").is_err());
+        assert!(parse(&contents.replace(" 0-9 ", " 9-0 ")).is_err());
+    }
+}
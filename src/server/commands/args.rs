@@ -0,0 +1,211 @@
+// Reading a command's arguments, with the errors Redis gives when they're missing or malformed
+
+use crate::server::ServerError;
+use crate::RedisType;
+use std::str::FromStr;
+
+// The arguments to a command (not including its name), read from front to back
+// For example, for SET key value [NX | XX] [EX seconds]:
+//
+//     let mut args = ArgParser::new(args);
+//     let key = args.string()?;
+//     let value = args.string()?;
+//     let mut condition = Exclusive::default();
+//     while let Some(option) = args.option() {
+//         match option.as_str() {
+//             "NX" | "XX" => condition.set(&option)?,
+//             "EX" => seconds = Some(args.integer()?),
+//             _ => return Err(ServerError::Syntax),
+//         }
+//     }
+//
+// An option missing its value (such as a trailing EX) is a syntax error, as is anything left over
+// once finish is called.
+pub(super) struct ArgParser<'a> {
+    args: &'a [RedisType],
+    next: usize,
+}
+
+impl<'a> ArgParser<'a> {
+    pub(super) fn new(args: &'a [RedisType]) -> ArgParser<'a> {
+        ArgParser { args, next: 0 }
+    }
+
+    // How many arguments haven't been read yet
+    pub(super) fn remaining(&self) -> usize {
+        self.args.len() - self.next
+    }
+
+    pub(super) fn is_empty(&self) -> bool {
+        self.remaining() == 0
+    }
+
+    // The next argument as it was sent, for keys and values
+    pub(super) fn bytes(&mut self) -> Result<Vec<u8>, ServerError> {
+        let arg = self.args.get(self.next).ok_or(ServerError::Syntax)?;
+        self.next += 1;
+        Ok(arg.to_bytes())
+    }
+
+    // The next argument as text, for names, options and numbers (where anything that isn't valid
+    // UTF-8 wouldn't be valid anyway)
+    pub(super) fn string(&mut self) -> Result<String, ServerError> {
+        let arg = self.args.get(self.next).ok_or(ServerError::Syntax)?;
+        self.next += 1;
+
+        Ok(match arg {
+            RedisType::String { value } => value.clone(),
+            RedisType::Bulk { value } => String::from_utf8_lossy(value).into_owned(),
+            arg => arg.to_string(),
+        })
+    }
+
+    pub(super) fn integer(&mut self) -> Result<i64, ServerError> {
+        self.parse(ServerError::NotAnInteger)
+    }
+
+    pub(super) fn float(&mut self) -> Result<f64, ServerError> {
+        match self.parse::<f64>(ServerError::NotAFloat)? {
+            value if value.is_nan() => Err(ServerError::NotAFloat),
+            value => Ok(value),
+        }
+    }
+
+    // The next argument as any type that can be parsed from a string (such as a u16 port), with
+    // error if it doesn't parse
+    pub(super) fn parse<T: FromStr>(&mut self, error: ServerError) -> Result<T, ServerError> {
+        self.string()?.parse().map_err(|_| error)
+    }
+
+    // The next argument in upper case, for matching against option and subcommand names, or None
+    // if they've all been read
+    pub(super) fn option(&mut self) -> Option<String> {
+        self.string().ok().map(|option| option.to_ascii_uppercase())
+    }
+
+    // Reads the next argument only if it's keyword, ignoring case
+    pub(super) fn keyword(&mut self, keyword: &str) -> bool {
+        match self.args.get(self.next) {
+            Some(RedisType::String { value }) if value.eq_ignore_ascii_case(keyword) => {
+                self.next += 1;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    // Every argument that hasn't been read yet
+    pub(super) fn rest(&mut self) -> Vec<String> {
+        let mut rest = Vec::with_capacity(self.remaining());
+        while let Ok(arg) = self.string() {
+            rest.push(arg);
+        }
+        rest
+    }
+
+    // Every argument that hasn't been read yet as it was sent, for lists of keys
+    pub(super) fn rest_bytes(&mut self) -> Vec<Vec<u8>> {
+        let mut rest = Vec::with_capacity(self.remaining());
+        while let Ok(arg) = self.bytes() {
+            rest.push(arg);
+        }
+        rest
+    }
+
+    // A syntax error if there are arguments that haven't been read
+    pub(super) fn finish(&self) -> Result<(), ServerError> {
+        if self.is_empty() {
+            Ok(())
+        } else {
+            Err(ServerError::Syntax)
+        }
+    }
+
+    // Check the number of arguments to a subcommand such as "client|setname", which (as with a
+    // command's arity) counts the command name and is negative for at least that many
+    pub(super) fn arity(&self, command: &str, arity: i64) -> Result<(), ServerError> {
+        let argc = self.args.len() as i64 + 1;
+        if (arity < 0 && argc < -arity) || (arity >= 0 && argc != arity) {
+            return Err(ServerError::WrongArity(String::from(command)));
+        }
+        Ok(())
+    }
+}
+
+// Which of a group of mutually exclusive options was given, such as SET's NX and XX
+// Giving the same one again is allowed (as Redis allows it), a different one is a syntax error.
+#[derive(Default)]
+pub(super) struct Exclusive(Option<String>);
+
+impl Exclusive {
+    pub(super) fn set(&mut self, option: &str) -> Result<(), ServerError> {
+        match &self.0 {
+            Some(given) if given != option => Err(ServerError::Syntax),
+            _ => {
+                self.0 = Some(option.to_owned());
+                Ok(())
+            }
+        }
+    }
+
+    pub(super) fn is(&self, option: &str) -> bool {
+        self.0.as_deref() == Some(option)
+    }
+
+    pub(super) fn is_set(&self) -> bool {
+        self.0.is_some()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ArgParser, Exclusive};
+    use crate::server::ServerError;
+    use crate::RedisType;
+
+    fn args(args: &[&str]) -> Vec<RedisType> {
+        args.iter()
+            .map(|arg| RedisType::from(String::from(*arg)))
+            .collect()
+    }
+
+    #[test]
+    fn test_arg_parser() {
+        let argv = args(&["key", "10", "ex", "ten"]);
+        let mut args = ArgParser::new(&argv);
+        assert_eq!(args.string(), Ok(String::from("key")));
+        assert_eq!(args.integer(), Ok(10));
+        assert!(!args.keyword("PX"));
+        assert!(args.keyword("EX"));
+        assert_eq!(args.remaining(), 1);
+        assert_eq!(args.finish(), Err(ServerError::Syntax));
+        assert_eq!(args.integer(), Err(ServerError::NotAnInteger));
+
+        // An option without its value
+        assert_eq!(args.integer(), Err(ServerError::Syntax));
+        assert_eq!(args.option(), None);
+        assert_eq!(args.finish(), Ok(()));
+    }
+
+    #[test]
+    fn test_arity() {
+        let argv = args(&["SETNAME", "name"]);
+        let args = ArgParser::new(&argv);
+        assert!(args.arity("client|setname", 3).is_ok());
+        assert!(args.arity("client|kill", -3).is_ok());
+        assert_eq!(
+            args.arity("client|getname", 2),
+            Err(ServerError::WrongArity(String::from("client|getname")))
+        );
+    }
+
+    #[test]
+    fn test_exclusive() {
+        let mut condition = Exclusive::default();
+        assert!(!condition.is_set());
+        assert!(condition.set("NX").is_ok());
+        assert!(condition.set("NX").is_ok());
+        assert!(condition.set("XX").is_err());
+        assert!(condition.is("NX"));
+    }
+}
@@ -0,0 +1,268 @@
+use super::args::ArgParser;
+use crate::cluster::key_slot;
+use crate::server::{cluster, Command, KeySpec, ServerError};
+use crate::RedisType;
+use std::collections::HashMap;
+
+#[rustfmt::skip]
+pub(super) fn register(m: &mut HashMap<&'static str, Command>) {
+    m.insert("ASKING", Command {
+        summary: "Signal that the next command is for a slot being imported",
+        group: "cluster",
+        since: "3.0.0",
+        arity: 1,
+        flags: &["fast"],
+        keys: KeySpec::None,
+        help: String::from("\
+ASKING
+
+Sent after an ASK redirection: the next command may use keys in a slot this node is importing,
+which would otherwise be redirected to the slot's owner with MOVED.
+        "),
+        f: Box::new(|_state, client, _args| {
+            client.asking = true;
+            Ok(RedisType::String { value: "OK".to_owned() })
+        })
+    });
+
+    m.insert("CLUSTER", Command {
+        summary: "A container for Redis Cluster commands",
+        group: "cluster",
+        since: "3.0.0",
+        arity: -2,
+        flags: &["stale"],
+        keys: KeySpec::None,
+        help: String::from("\
+CLUSTER INFO
+CLUSTER MYID
+CLUSTER KEYSLOT key
+CLUSTER NODES
+CLUSTER SLOTS
+CLUSTER SHARDS
+CLUSTER MEET ip port
+CLUSTER ADDSLOTS slot [slot ...]
+CLUSTER ADDSLOTSRANGE start end [start end ...]
+CLUSTER DELSLOTS slot [slot ...]
+CLUSTER DELSLOTSRANGE start end [start end ...]
+CLUSTER SETSLOT slot IMPORTING|MIGRATING|NODE node-id
+CLUSTER SETSLOT slot STABLE
+
+Only available with cluster-enabled. Keys are split into 16384 hash slots, each served by one
+node. A key's slot (as returned by KEYSLOT) is a hash of the key, or of just the part in {braces}
+if there is one, so that related keys can be kept together; commands using keys from more than
+one slot are refused with CROSSSLOT. Commands for keys in another node's slot are answered with a
+MOVED error naming it, or ASK while the slot is being migrated and the keys are no longer here.
+The nodes and their slots are saved to cluster-config-file.
+        "),
+        f: Box::new(|state, client, args| {
+            if !state.config.cluster_enabled {
+                return Err(ServerError::Err(String::from("This instance has cluster support disabled")));
+            }
+
+            // Other nodes are reached at their own address, we are where the client connected
+            let my_ip = client.laddr.ip().to_string();
+            let node_ip = |node: &cluster::Node| {
+                if node.id == state.cluster.myid { my_ip.clone() } else { node.ip.clone() }
+            };
+            let node_port = |node: &cluster::Node| {
+                if node.id == state.cluster.myid { state.config.port } else { node.port }
+            };
+
+            let mut args = ArgParser::new(args);
+            let subcommand = args.string()?;
+            let command = format!("cluster|{}", subcommand.to_ascii_lowercase());
+
+            match subcommand.to_ascii_uppercase().as_str() {
+                "INFO" => {
+                    args.arity(&command, 2)?;
+                    let cluster = &state.cluster;
+                    let slots = cluster.slots_assigned();
+                    let size = cluster.nodes.values().filter(|node| !node.slots.is_empty()).count();
+                    let info = [
+                        ("cluster_enabled", String::from("1")),
+                        ("cluster_state", String::from(if cluster.is_ok() { "ok" } else { "fail" })),
+                        ("cluster_slots_assigned", slots.to_string()),
+                        ("cluster_slots_ok", slots.to_string()),
+                        ("cluster_slots_pfail", String::from("0")),
+                        ("cluster_slots_fail", String::from("0")),
+                        ("cluster_known_nodes", cluster.nodes.len().to_string()),
+                        ("cluster_size", size.to_string()),
+                        ("cluster_current_epoch", String::from("0")),
+                        ("cluster_my_epoch", String::from("0")),
+                    ];
+                    let info = info.iter().map(|(key, value)| format!("{key}:{value}\r\n")).collect::<String>();
+                    Ok(RedisType::from(info))
+                }
+                "MYID" => {
+                    args.arity(&command, 2)?;
+                    Ok(RedisType::from(state.cluster.myid.clone()))
+                }
+                "KEYSLOT" => {
+                    args.arity(&command, 3)?;
+                    Ok(RedisType::from(key_slot(args.bytes()?) as i64))
+                }
+                "NODES" => {
+                    args.arity(&command, 2)?;
+                    Ok(RedisType::from(state.cluster.describe_nodes(&my_ip, state.config.port)))
+                }
+                "SLOTS" => {
+                    args.arity(&command, 2)?;
+                    let mut slots = Vec::new();
+                    for node in state.cluster.nodes.values() {
+                        for range in node.slot_ranges() {
+                            slots.push((*range.start(), RedisType::from(vec![
+                                RedisType::from(*range.start() as i64),
+                                RedisType::from(*range.end() as i64),
+                                RedisType::from(vec![
+                                    RedisType::from(node_ip(node)),
+                                    RedisType::from(node_port(node) as i64),
+                                    RedisType::from(node.id.clone()),
+                                    RedisType::from(Vec::<(RedisType, RedisType)>::new()),
+                                ]),
+                            ])));
+                        }
+                    }
+                    slots.sort_by_key(|(start, _)| *start);
+                    Ok(RedisType::from(slots.into_iter().map(|(_, slot)| slot).collect::<Vec<_>>()))
+                }
+                "SHARDS" => {
+                    args.arity(&command, 2)?;
+                    let shards = state.cluster.nodes.values().map(|node| {
+                        let slots = node.slot_ranges().iter()
+                            .flat_map(|range| [*range.start(), *range.end()])
+                            .map(|slot| RedisType::from(slot as i64))
+                            .collect::<Vec<_>>();
+                        let ip = node_ip(node);
+                        let port = node_port(node);
+                        let offset = if node.id == state.cluster.myid { state.replication.offset as i64 } else { 0 };
+                        RedisType::from(vec![
+                            (RedisType::from(String::from("slots")), RedisType::from(slots)),
+                            (RedisType::from(String::from("nodes")), RedisType::from(vec![RedisType::from(vec![
+                                (RedisType::from(String::from("id")), RedisType::from(node.id.clone())),
+                                (RedisType::from(String::from("port")), RedisType::from(port as i64)),
+                                (RedisType::from(String::from("ip")), RedisType::from(ip.clone())),
+                                (RedisType::from(String::from("endpoint")), RedisType::from(ip)),
+                                (RedisType::from(String::from("role")), RedisType::from(String::from("master"))),
+                                (RedisType::from(String::from("replication-offset")), RedisType::from(offset)),
+                                (RedisType::from(String::from("health")), RedisType::from(String::from("online"))),
+                            ])])),
+                        ])
+                    }).collect::<Vec<_>>();
+                    Ok(RedisType::from(shards))
+                }
+                "MEET" => {
+                    // The cluster bus port is accepted but unused, nodes meet over the client port
+                    if args.remaining() != 2 && args.remaining() != 3 {
+                        return Err(ServerError::WrongArity(String::from("cluster|meet")));
+                    }
+                    let ip = args.string()?;
+                    let port = args.integer()?;
+                    let port = u16::try_from(port).ok().filter(|_| ip.parse::<std::net::IpAddr>().is_ok())
+                        .ok_or_else(|| ServerError::Err(format!("Invalid node address specified: {ip}:{port}")))?;
+                    state.cluster.meet(ip, port);
+                    Ok(RedisType::String { value: "OK".to_owned() })
+                }
+                "ADDSLOTS" | "DELSLOTS" | "ADDSLOTSRANGE" | "DELSLOTSRANGE" => {
+                    let subcommand = subcommand.to_ascii_uppercase();
+                    let add = subcommand.starts_with("ADD");
+
+                    let mut slots = Vec::new();
+                    if subcommand.ends_with("RANGE") {
+                        if args.remaining() < 2 || !args.remaining().is_multiple_of(2) {
+                            return Err(ServerError::WrongArity(command));
+                        }
+                        while !args.is_empty() {
+                            let start = cluster::parse_slot(&args.string()?)?;
+                            let end = cluster::parse_slot(&args.string()?)?;
+                            if start > end {
+                                return Err(ServerError::Err(format!("start slot number {start} is greater than end slot number {end}")));
+                            }
+                            slots.extend(start..=end);
+                        }
+                    } else {
+                        args.arity(&command, -3)?;
+                        while !args.is_empty() {
+                            slots.push(cluster::parse_slot(&args.string()?)?);
+                        }
+                    }
+
+                    // Nothing changes unless every slot is valid
+                    let mut seen = std::collections::BTreeSet::new();
+                    for &slot in &slots {
+                        if !seen.insert(slot) {
+                            return Err(ServerError::Err(format!("Slot {slot} specified multiple times")));
+                        }
+                        match state.cluster.owner(slot) {
+                            Some(_) if add => return Err(ServerError::Err(format!("Slot {slot} is already busy"))),
+                            None if !add => return Err(ServerError::Err(format!("Slot {slot} is already unassigned"))),
+                            _ => {}
+                        }
+                    }
+
+                    let myid = state.cluster.myid.clone();
+                    for slot in slots {
+                        if add {
+                            state.cluster.assign(slot, &myid);
+                            state.cluster.importing.remove(&slot);
+                        } else {
+                            state.cluster.unassign(slot);
+                            state.cluster.migrating.remove(&slot);
+                        }
+                    }
+                    cluster::save(state);
+                    Ok(RedisType::String { value: "OK".to_owned() })
+                }
+                "SETSLOT" => {
+                    args.arity(&command, -4)?;
+                    let slot = cluster::parse_slot(&args.string()?)?;
+                    let action = args.string()?.to_ascii_uppercase();
+                    let mine = state.cluster.myself().slots.contains(&slot);
+
+                    if action == "STABLE" {
+                        args.arity(&command, 4)?;
+                        state.cluster.migrating.remove(&slot);
+                        state.cluster.importing.remove(&slot);
+                        cluster::save(state);
+                        return Ok(RedisType::String { value: "OK".to_owned() });
+                    }
+
+                    args.arity(&command, 5)?;
+                    let id = args.string()?;
+                    if !state.cluster.nodes.contains_key(&id) {
+                        return Err(ServerError::Err(format!("Unknown node {id}")));
+                    }
+
+                    match action.as_str() {
+                        "MIGRATING" => {
+                            if !mine {
+                                return Err(ServerError::Err(format!("I'm not the owner of hash slot {slot}")));
+                            }
+                            if id == state.cluster.myid {
+                                return Err(ServerError::Err(String::from("I can't migrate to myself")));
+                            }
+                            state.cluster.migrating.insert(slot, id);
+                        }
+                        "IMPORTING" => {
+                            if mine {
+                                return Err(ServerError::Err(format!("I'm already the owner of hash slot {slot}")));
+                            }
+                            if id == state.cluster.myid {
+                                return Err(ServerError::Err(String::from("I can't import from myself")));
+                            }
+                            state.cluster.importing.insert(slot, id);
+                        }
+                        "NODE" => {
+                            state.cluster.assign(slot, &id);
+                            state.cluster.migrating.remove(&slot);
+                            state.cluster.importing.remove(&slot);
+                        }
+                        _ => return Err(ServerError::Err(String::from("Invalid CLUSTER SETSLOT action or number of arguments"))),
+                    }
+                    cluster::save(state);
+                    Ok(RedisType::String { value: "OK".to_owned() })
+                }
+                _ => Err(ServerError::UnknownSubcommand(subcommand)),
+            }
+        })
+    });
+}
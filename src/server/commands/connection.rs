@@ -0,0 +1,498 @@
+use super::args::ArgParser;
+use crate::server::clients::{self, ClientFilter, Pause, PauseMode};
+use crate::server::{pubsub, tracking};
+use crate::server::{Command, KeySpec, ServerError, State, REDIS_VERSION};
+use crate::{Protocol, RedisType};
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+#[rustfmt::skip]
+pub(super) fn register(m: &mut HashMap<&'static str, Command>) {
+    m.insert("CLIENT", Command {
+        summary: "A container for client connection commands",
+        group: "connection",
+        since: "2.4.0",
+        arity: -2,
+        flags: &["noscript", "loading", "stale"],
+        keys: KeySpec::None,
+        help: String::from("\
+CLIENT ID
+CLIENT INFO
+CLIENT LIST [TYPE normal] [ID client-id [client-id ...]]
+CLIENT SETNAME connection-name
+CLIENT GETNAME
+CLIENT KILL ip:port
+CLIENT KILL [ID client-id] [TYPE normal|master|replica|pubsub] [USER username] [ADDR ip:port] [LADDR ip:port] [SKIPME yes|no]
+CLIENT PAUSE timeout [WRITE|ALL]
+CLIENT UNPAUSE
+CLIENT NO-EVICT ON|OFF
+CLIENT NO-TOUCH ON|OFF
+CLIENT TRACKING ON|OFF [REDIRECT client-id] [PREFIX prefix [PREFIX prefix ...]] [BCAST] [NOLOOP]
+CLIENT GETREDIR
+CLIENT TRACKINGINFO
+
+Inspect connected clients, name the current connection, close connections and pause command processing.
+NO-EVICT exempts this connection from output buffer limits.
+NO-TOUCH stops this connection's commands from changing when keys were last accessed.
+TRACKING sends an invalidate push (over RESP3, or as a message on __redis__:invalidate to a RESP2 connection subscribed to it) when keys this connection has read change, or with BCAST when any key starting with one of the prefixes does.
+REDIRECT sends them to another connection instead, NOLOOP leaves out this connection's own writes.
+        "),
+        f: Box::new(|state, client, args| {
+            let mut args = ArgParser::new(args);
+            let subcommand = args.string()?;
+            let command = format!("client|{}", subcommand.to_ascii_lowercase());
+
+            match subcommand.to_ascii_uppercase().as_str() {
+                "ID" => {
+                    args.arity(&command, 2)?;
+                    Ok(RedisType::from(client.id as i64))
+                }
+                "INFO" => {
+                    args.arity(&command, 2)?;
+                    Ok(RedisType::from(format!("{}\n", client.info().describe())))
+                }
+                "LIST" => {
+                    let mut ids = None;
+                    while let Some(option) = args.option() {
+                        match option.as_str() {
+                            "TYPE" => {
+                                let kind = args.string()?;
+                                if !kind.eq_ignore_ascii_case("normal") {
+                                    return Err(ServerError::Err(format!("Unknown client type '{kind}'")));
+                                }
+                            }
+                            "ID" => {
+                                let mut requested = Vec::new();
+                                while !args.is_empty() {
+                                    match args.integer() {
+                                        Ok(id) if id > 0 => requested.push(id as u64),
+                                        _ => return Err(ServerError::Err(String::from("Invalid client ID"))),
+                                    }
+                                }
+                                ids = Some(requested);
+                            }
+                            _ => return Err(ServerError::Syntax),
+                        }
+                    }
+
+                    let mut lines = String::new();
+                    for info in state.clients.values() {
+                        if ids.as_ref().is_some_and(|ids| !ids.contains(&info.id)) {
+                            continue;
+                        }
+                        lines.push_str(&info.describe());
+                        lines.push('\n');
+                    }
+                    Ok(RedisType::from(lines))
+                }
+                "SETNAME" => {
+                    args.arity(&command, 3)?;
+                    let name = args.string()?;
+
+                    if !clients::is_valid_name(&name) {
+                        return Err(ServerError::Err(String::from("Client names cannot contain spaces, newlines or special characters.")));
+                    }
+
+                    client.name = if name.is_empty() { None } else { Some(name) };
+                    Ok(RedisType::String { value: "OK".to_owned() })
+                }
+                "GETNAME" => {
+                    args.arity(&command, 2)?;
+                    Ok(RedisType::from(client.name.clone()))
+                }
+                "KILL" if args.remaining() == 1 => {
+                    // Old form, a single address that must match a client
+                    let addr = args.string()?;
+                    match state.clients.values().find(|info| info.addr.to_string() == addr) {
+                        Some(info) => {
+                            info.kill.trigger();
+                            Ok(RedisType::String { value: "OK".to_owned() })
+                        }
+                        None => Err(ServerError::Err(String::from("No such client"))),
+                    }
+                }
+                "KILL" => {
+                    args.arity(&command, -3)?;
+                    if !args.remaining().is_multiple_of(2) {
+                        return Err(ServerError::Syntax);
+                    }
+
+                    let mut filters: Vec<ClientFilter> = Vec::new();
+                    let mut skip_me = true;
+
+                    while let Some(filter) = args.option() {
+                        let value = args.string()?;
+
+                        match filter.as_str() {
+                            "ID" => match value.parse::<u64>() {
+                                Ok(id) if id > 0 => filters.push(Box::new(move |info| info.id == id)),
+                                _ => return Err(ServerError::Err(String::from("client-id should be greater than 0"))),
+                            },
+                            "TYPE" => match value.to_ascii_lowercase().as_str() {
+                                "normal" => filters.push(Box::new(|info| !info.master && !info.replica && info.channels + info.patterns == 0)),
+                                "master" => filters.push(Box::new(|info| info.master)),
+                                "slave" | "replica" => filters.push(Box::new(|info| info.replica)),
+                                "pubsub" => filters.push(Box::new(|info| info.channels + info.patterns > 0)),
+                                _ => return Err(ServerError::Err(format!("Unknown client type '{value}'"))),
+                            },
+                            "USER" => {
+                                // Every connection is the default user
+                                if value != "default" {
+                                    return Err(ServerError::Err(format!("No such user '{value}'")));
+                                }
+                            }
+                            "ADDR" => filters.push(Box::new(move |info| info.addr.to_string() == value)),
+                            "LADDR" => filters.push(Box::new(move |info| info.laddr.to_string() == value)),
+                            "SKIPME" => {
+                                skip_me = match value.to_ascii_lowercase().as_str() {
+                                    "yes" => true,
+                                    "no" => false,
+                                    _ => return Err(ServerError::Syntax),
+                                };
+                            }
+                            _ => return Err(ServerError::Syntax),
+                        }
+                    }
+
+                    let mut killed = 0;
+                    for info in state.clients.values() {
+                        if skip_me && info.id == client.id {
+                            continue;
+                        }
+                        if filters.iter().all(|filter| filter(info)) {
+                            info.kill.trigger();
+                            killed += 1;
+                        }
+                    }
+                    Ok(RedisType::from(killed))
+                }
+                "PAUSE" => {
+                    args.arity(&command, -3)?;
+                    let timeout = args.integer()?;
+                    if timeout < 0 {
+                        return Err(ServerError::Err(String::from("timeout is negative")));
+                    }
+
+                    let mode = match args.option().as_deref() {
+                        None | Some("ALL") => PauseMode::All,
+                        Some("WRITE") => PauseMode::Write,
+                        Some(_) => return Err(ServerError::Syntax),
+                    };
+                    args.finish()?;
+
+                    let pause = Pause {
+                        mode,
+                        until: Instant::now() + Duration::from_millis(timeout as u64),
+                    };
+                    state.pause = Some(pause.merge(state.pause));
+                    Ok(RedisType::String { value: "OK".to_owned() })
+                }
+                "NO-EVICT" | "NO-TOUCH" => {
+                    args.arity(&command, 3)?;
+                    let on = match args.option().as_deref() {
+                        Some("ON") => true,
+                        Some("OFF") => false,
+                        _ => return Err(ServerError::Syntax),
+                    };
+
+                    if subcommand.eq_ignore_ascii_case("NO-EVICT") {
+                        client.no_evict = on;
+                    } else {
+                        client.no_touch = on;
+                    }
+                    Ok(RedisType::String { value: "OK".to_owned() })
+                }
+                "TRACKING" => {
+                    args.arity(&command, -3)?;
+                    let on = match args.option().as_deref() {
+                        Some("ON") => true,
+                        Some("OFF") => false,
+                        _ => return Err(ServerError::Syntax),
+                    };
+
+                    let mut options = tracking::Options::default();
+                    while let Some(option) = args.option() {
+                        match option.as_str() {
+                            "REDIRECT" => {
+                                if options.redirect.is_some() {
+                                    return Err(ServerError::Err(String::from("A client can only redirect to a single other client")));
+                                }
+                                options.redirect = Some(args.integer()? as u64);
+                            }
+                            "BCAST" => options.bcast = true,
+                            "PREFIX" => options.prefixes.push(args.bytes()?),
+                            "NOLOOP" => options.noloop = true,
+                            _ => return Err(ServerError::Syntax),
+                        }
+                    }
+
+                    if on {
+                        if options.redirect.is_some_and(|id| !state.clients.contains_key(&id)) {
+                            return Err(ServerError::Err(String::from("The client ID you want redirect to does not exist")));
+                        }
+                        state.tracking.enable(client.id, options).map_err(ServerError::Err)?;
+                    } else {
+                        state.tracking.disable(client.id);
+                    }
+                    client.tracking = on;
+                    Ok(RedisType::String { value: "OK".to_owned() })
+                }
+                "GETREDIR" => {
+                    args.arity(&command, 2)?;
+                    let redirect = match state.tracking.options(client.id) {
+                        Some(options) => options.redirect.map_or(0, |id| id as i64),
+                        None => -1,
+                    };
+                    Ok(RedisType::from(redirect))
+                }
+                "TRACKINGINFO" => {
+                    args.arity(&command, 2)?;
+                    let (flags, redirect, prefixes) = match state.tracking.options(client.id) {
+                        Some(options) => {
+                            let mut flags = vec!["on"];
+                            if options.bcast {
+                                flags.push("bcast");
+                            }
+                            if options.noloop {
+                                flags.push("noloop");
+                            }
+                            if options.redirect.is_some_and(|id| !state.clients.contains_key(&id)) {
+                                flags.push("broken_redirect");
+                            }
+                            let redirect = options.redirect.map_or(0, |id| id as i64);
+                            (flags, redirect, options.prefixes.clone())
+                        }
+                        None => (vec!["off"], -1, Vec::new()),
+                    };
+
+                    let flags = flags.into_iter().map(|flag| RedisType::from(String::from(flag))).collect::<Vec<_>>();
+                    let prefixes = prefixes.into_iter().map(RedisType::from).collect::<Vec<_>>();
+                    Ok(RedisType::Map {
+                        value: vec![
+                            (RedisType::from(String::from("flags")), RedisType::from(flags)),
+                            (RedisType::from(String::from("redirect")), RedisType::from(redirect)),
+                            (RedisType::from(String::from("prefixes")), RedisType::from(prefixes)),
+                        ],
+                    })
+                }
+                "UNPAUSE" => {
+                    args.arity(&command, 2)?;
+                    state.pause = None;
+                    state.unpaused.notify_waiters();
+                    Ok(RedisType::String { value: "OK".to_owned() })
+                }
+                _ => Err(ServerError::UnknownSubcommand(subcommand)),
+            }
+        })
+    });
+
+    m.insert("HELLO", Command {
+        summary: "Handshake with Redis",
+        group: "connection",
+        since: "6.0.0",
+        arity: -1,
+        flags: &["noscript", "loading", "stale", "fast", "no_auth", "allow_busy"],
+        keys: KeySpec::None,
+        help: String::from("\
+HELLO [protover [AUTH username password] [SETNAME clientname]]
+
+Handshake with the server, optionally switching the connection to a different protocol version.
+
+Returns a map of server and connection properties.
+        "),
+        f: Box::new(|state, client, args| {
+            let mut args = ArgParser::new(args);
+            let mut protocol = client.protocol;
+            let mut name = None;
+            let mut authenticated = client.authenticated;
+
+            if !args.is_empty() {
+                protocol = match args.integer() {
+                    Ok(2) => Protocol::Resp2,
+                    Ok(3) => Protocol::Resp3,
+                    Ok(_) => return Err(ServerError::NoProto),
+                    Err(_) => return Err(ServerError::Err(String::from("Protocol version is not an integer or out of range"))),
+                };
+            }
+
+            while !args.is_empty() {
+                let option = args.string()?;
+                match option.to_ascii_uppercase().as_str() {
+                    "AUTH" => {
+                        let username = args.string()?;
+                        let password = args.string()?;
+
+                        if !check_password(state, &username, &password) {
+                            return Err(ServerError::WrongPass);
+                        }
+                        authenticated = true;
+                    }
+                    "SETNAME" => {
+                        let value = args.string()?;
+                        if !clients::is_valid_name(&value) {
+                            return Err(ServerError::Err(String::from("Client names cannot contain spaces, newlines or special characters.")));
+                        }
+                        name = Some(value);
+                    }
+                    _ => return Err(ServerError::Err(format!("Syntax error in HELLO option '{option}'"))),
+                }
+            }
+
+            if !authenticated {
+                return Err(ServerError::NoAuth("HELLO must be called with the client already authenticated, otherwise the HELLO <proto> AUTH <user> <pass> option can be used to authenticate the client and select the RESP protocol version at the same time"));
+            }
+
+            client.authenticated = true;
+            client.protocol = protocol;
+            if name.is_some() {
+                client.name = name;
+            }
+
+            Ok(RedisType::from(vec![
+                (RedisType::from(String::from("server")), RedisType::from(String::from("redis"))),
+                (RedisType::from(String::from("version")), RedisType::from(String::from(REDIS_VERSION))),
+                (RedisType::from(String::from("proto")), RedisType::from(client.protocol.version())),
+                (RedisType::from(String::from("id")), RedisType::from(client.id as i64)),
+                (RedisType::from(String::from("mode")), RedisType::from(String::from("standalone"))),
+                (RedisType::from(String::from("role")), RedisType::from(String::from("master"))),
+                (RedisType::from(String::from("modules")), RedisType::Array { value: vec![] }),
+            ]))
+        })
+    });
+
+    m.insert("AUTH", Command {
+        summary: "Authenticate to the server",
+        group: "connection",
+        since: "1.0.0",
+        arity: -2,
+        flags: &["noscript", "loading", "stale", "fast", "no_auth", "allow_busy"],
+        keys: KeySpec::None,
+        help: String::from("\
+AUTH [username] password
+
+Authenticate the current connection.
+
+Only the default user exists, its password is set with requirepass.
+        "),
+        f: Box::new(|state, client, args| {
+            let mut args = ArgParser::new(args);
+            let legacy = args.remaining() == 1;
+            let (username, password) = match args.remaining() {
+                1 => (String::from("default"), args.string()?),
+                2 => (args.string()?, args.string()?),
+                _ => return Err(ServerError::Syntax),
+            };
+
+            if legacy && state.config.requirepass.is_none() {
+                return Err(ServerError::Err(String::from("AUTH <password> called without any password configured for the default user. Are you sure your configuration is correct?")));
+            }
+
+            if !check_password(state, &username, &password) {
+                return Err(ServerError::WrongPass);
+            }
+
+            client.authenticated = true;
+            Ok(RedisType::String { value: "OK".to_owned() })
+        })
+    });
+
+    m.insert("PING", Command {
+        summary: "Returns the server's liveliness response",
+        group: "connection",
+        since: "1.0.0",
+        arity: -1,
+        flags: &["fast"],
+        keys: KeySpec::None,
+        help: String::from("\
+PING [message]
+
+Returns PONG, or message if one is given.
+A RESP2 connection with subscriptions is sent an array of pong and the message (or an empty string) instead, which can't be mistaken for a published message.
+        "),
+        f: Box::new(|_state, client, args| {
+            if client.subscribed() && client.protocol == Protocol::Resp2 {
+                return match args.len() {
+                    0 | 1 => Ok(RedisType::from(vec![
+                        RedisType::from(String::from("pong")),
+                        RedisType::from(args.first().map(RedisType::to_bytes).unwrap_or_default()),
+                    ])),
+                    _ => Err(ServerError::WrongArity(String::from("ping"))),
+                };
+            }
+            match args.len() {
+                0 => Ok(RedisType::String { value: "PONG".to_owned() }),
+                1 => Ok(RedisType::String { value: ArgParser::new(args).string()? }),
+                _ => Err(ServerError::WrongArity(String::from("ping"))),
+            }
+        })
+    });
+
+    m.insert("ECHO", Command {
+        summary: "Returns the given string",
+        group: "connection",
+        since: "1.0.0",
+        arity: 2,
+        flags: &["fast"],
+        keys: KeySpec::None,
+        help: String::from("\
+ECHO message
+        "),
+        f: Box::new(|_state, _client, args| {
+            Ok(RedisType::String { value: ArgParser::new(args).string()? })
+        })
+    });
+
+    m.insert("QUIT", Command {
+        summary: "Closes the connection",
+        group: "connection",
+        since: "1.0.0",
+        arity: -1,
+        flags: &["noscript", "loading", "stale", "fast", "no_auth", "allow_busy"],
+        keys: KeySpec::None,
+        help: String::from("\
+QUIT
+
+Close the connection once all pending replies (including this one) have been sent.
+        "),
+        f: Box::new(|_state, client, _args| {
+            client.close_after_reply = true;
+            Ok(RedisType::String { value: "OK".to_owned() })
+        })
+    });
+
+    m.insert("RESET", Command {
+        summary: "Resets the connection",
+        group: "connection",
+        since: "6.2.0",
+        arity: 1,
+        flags: &["noscript", "loading", "stale", "fast", "no_auth", "allow_busy"],
+        keys: KeySpec::None,
+        help: String::from("\
+RESET
+
+Puts the connection back as it was when it was opened: unsubscribed from every channel and pattern, without CLIENT TRACKING, NO-EVICT or NO-TOUCH, back on RESP2, and unauthenticated if there's a password.
+        "),
+        f: Box::new(|state, client, _args| {
+            for kind in [pubsub::Kind::Channel, pubsub::Kind::Pattern] {
+                pubsub::unsubscribe(state, client, kind, Vec::new());
+            }
+            state.tracking.disable(client.id);
+            client.tracking = false;
+            client.no_evict = false;
+            client.no_touch = false;
+            client.asking = false;
+            client.protocol = Protocol::Resp2;
+            client.authenticated = state.config.requirepass.is_none();
+            Ok(RedisType::String { value: "RESET".to_owned() })
+        })
+    });
+}
+
+// Only the default user exists, without requirepass it accepts any password
+fn check_password(state: &State, username: &str, password: &str) -> bool {
+    username == "default"
+        && match &state.config.requirepass {
+            Some(requirepass) => requirepass == password,
+            None => true,
+        }
+}
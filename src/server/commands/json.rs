@@ -0,0 +1,327 @@
+use super::args::{ArgParser, Exclusive};
+use crate::server::json::Path;
+use crate::server::{Command, CommandFn, KeySpec, ServerError, State};
+use crate::value::Value;
+use crate::RedisType;
+use serde_json::Map;
+use std::collections::HashMap;
+
+#[rustfmt::skip]
+pub(super) fn register(m: &mut HashMap<&'static str, Command>) {
+    // JSON.DEL and JSON.FORGET
+    let del: CommandFn = |state, _client, args| {
+        let mut args = ArgParser::new(args);
+        let key = args.bytes()?;
+        let path = match args.is_empty() {
+            true => String::from("$"),
+            false => args.string()?,
+        };
+        args.finish()?;
+        let path = parse_path(&path)?;
+
+        let Some(document) = document(state, &key)? else {
+            return Ok(RedisType::from(0));
+        };
+        if path.is_root() {
+            state.keystore.remove(&key);
+            state.ttl.remove(&key);
+            state.last_access.remove(&key);
+            return Ok(RedisType::from(1));
+        }
+
+        let mut document = document.clone();
+        let deleted = path.delete(&mut document);
+        if deleted > 0 {
+            state.keystore.insert(key, Value::json(document));
+        }
+        Ok(RedisType::from(deleted as i64))
+    };
+
+    m.insert("JSON.DEL", Command {
+        summary: "Deletes a value",
+        group: "json",
+        since: "1.0.0",
+        arity: -2,
+        flags: &["write"],
+        keys: KeySpec::FIRST,
+        help: String::from("\
+JSON.DEL key [path]
+
+Delete every value path matches in the JSON document at key, or the whole key if path is the root
+(which it is by default). Returns how many values were deleted.
+        "),
+        f: Box::new(del),
+    });
+
+    m.insert("JSON.FORGET", Command {
+        summary: "Deletes a value",
+        group: "json",
+        since: "1.0.0",
+        arity: -2,
+        flags: &["write"],
+        keys: KeySpec::FIRST,
+        help: String::from("\
+JSON.FORGET key [path]
+
+The same as JSON.DEL.
+        "),
+        f: Box::new(del),
+    });
+
+    m.insert("JSON.GET", Command {
+        summary: "Gets the value at one or more paths in JSON serialized form",
+        group: "json",
+        since: "1.0.0",
+        arity: -2,
+        flags: &["readonly"],
+        keys: KeySpec::FIRST,
+        help: String::from("\
+JSON.GET key [INDENT indent] [NEWLINE newline] [SPACE space] [path [path ...]]
+
+Return the values paths match in the JSON document at key, serialized as JSON, or nil if key
+doesn't exist. A JSONPath (starting with $) gives a list of every value it matches, a legacy path
+(such as .a.b) the first one, which has to be there. With more than one path, the reply is an
+object with each path's reply, as lists if any of them is a JSONPath. Without a path, it's the
+whole document. INDENT, NEWLINE and SPACE are put before each nested level, after each element,
+and after each name in an object, which are all empty by default.
+        "),
+        f: Box::new(|state, _client, args| {
+            let mut args = ArgParser::new(args);
+            let key = args.bytes()?;
+            let mut format = Format::default();
+            let mut paths = Vec::new();
+            while let Ok(arg) = args.string() {
+                match arg.to_ascii_uppercase().as_str() {
+                    "INDENT" => format.indent = args.string()?,
+                    "NEWLINE" => format.newline = args.string()?,
+                    "SPACE" => format.space = args.string()?,
+                    _ => paths.push(arg),
+                }
+            }
+            if paths.is_empty() {
+                paths.push(String::from("."));
+            }
+            let parsed = paths.iter().map(|path| parse_path(path)).collect::<Result<Vec<_>, _>>()?;
+
+            let Some(document) = document(state, &key)? else {
+                return Ok(RedisType::NullString);
+            };
+            // Once there's a JSONPath, they're all treated as JSONPaths
+            let legacy = parsed.iter().all(|path| path.legacy);
+            let reply = |path: &Path, name: &str| {
+                let found = path.find(document);
+                match legacy {
+                    true => found.first().map(|value| (*value).clone()).ok_or_else(|| missing(name)),
+                    false => Ok(serde_json::Value::from(found.into_iter().cloned().collect::<Vec<_>>())),
+                }
+            };
+
+            let value = match &parsed[..] {
+                [path] => reply(path, &paths[0])?,
+                _ => {
+                    let mut object = Map::new();
+                    for (path, name) in parsed.iter().zip(&paths) {
+                        object.insert(name.clone(), reply(path, name)?);
+                    }
+                    serde_json::Value::Object(object)
+                }
+            };
+            Ok(RedisType::from(format.serialize(&value)))
+        })
+    });
+
+    m.insert("JSON.NUMINCRBY", Command {
+        summary: "Increments the numeric value at path by a value",
+        group: "json",
+        since: "1.0.0",
+        arity: 4,
+        flags: &["write", "denyoom"],
+        keys: KeySpec::FIRST,
+        help: String::from("\
+JSON.NUMINCRBY key path value
+
+Add value to every number path matches in the JSON document at key. Integers stay integers unless
+the sum is too large for one. For a JSONPath, returns a list of the new values (serialized as JSON)
+with null for anything that isn't a number, for a legacy path the new value, which has to be a
+number.
+        "),
+        f: Box::new(|state, _client, args| {
+            let mut args = ArgParser::new(args);
+            let key = args.bytes()?;
+            let name = args.string()?;
+            let path = parse_path(&name)?;
+            let by = match serde_json::from_str(&args.string()?) {
+                Ok(serde_json::Value::Number(by)) => by,
+                _ => return Err(ServerError::NotAFloat),
+            };
+
+            let Some(document) = document(state, &key)? else {
+                return Err(ServerError::Err(String::from(
+                    "could not perform this operation on a key that doesn't exist",
+                )));
+            };
+            let mut document = document.clone();
+            let results = path.increment(&mut document, &by).map_err(ServerError::Err)?;
+
+            let reply = match path.legacy {
+                true => match results.first() {
+                    Some(Some(result)) => result.to_string(),
+                    Some(None) => return Err(ServerError::Err(format!("Path '{name}' does not hold a number"))),
+                    None => return Err(missing(&name)),
+                },
+                false => {
+                    let results = results.iter().cloned().map(|result| result.map_or(serde_json::Value::Null, serde_json::Value::Number));
+                    serde_json::Value::from(results.collect::<Vec<_>>()).to_string()
+                }
+            };
+            if results.iter().any(Option::is_some) {
+                state.keystore.insert(key, Value::json(document));
+            }
+            Ok(RedisType::from(reply))
+        })
+    });
+
+    m.insert("JSON.SET", Command {
+        summary: "Sets or updates the JSON value at a path",
+        group: "json",
+        since: "1.0.0",
+        arity: -4,
+        flags: &["write", "denyoom"],
+        keys: KeySpec::FIRST,
+        help: String::from("\
+JSON.SET key path value [NX | XX]
+
+Set every value path matches in the JSON document at key to value (which is JSON). If there are
+none and path ends with a name, it's added to the objects the rest of the path matches. A new key
+has to be set at the root ($ or .). NX only sets values that aren't there yet, XX only those that
+are. Returns OK, or nil if nothing was set.
+        "),
+        f: Box::new(|state, _client, args| {
+            let mut args = ArgParser::new(args);
+            let key = args.bytes()?;
+            let path = parse_path(&args.string()?)?;
+            let value = serde_json::from_slice::<serde_json::Value>(&args.bytes()?)
+                .map_err(|e| ServerError::Err(e.to_string()))?;
+            let mut condition = Exclusive::default();
+            while let Some(option) = args.option() {
+                match option.as_str() {
+                    "NX" | "XX" => condition.set(&option)?,
+                    _ => return Err(ServerError::Syntax),
+                }
+            }
+            let nx = condition.is("NX");
+            let xx = condition.is("XX");
+
+            let document = match document(state, &key)? {
+                Some(_) if path.is_root() && nx => return Ok(RedisType::NullString),
+                Some(_) if path.is_root() => value,
+                Some(document) => {
+                    let mut document = document.clone();
+                    if path.set(&mut document, &value, nx, xx) == 0 {
+                        return Ok(RedisType::NullString);
+                    }
+                    document
+                }
+                None if !path.is_root() => {
+                    return Err(ServerError::Err(String::from("new objects must be created at the root")));
+                }
+                None if xx => return Ok(RedisType::NullString),
+                None => value,
+            };
+            state.keystore.insert(key, Value::json(document));
+            Ok(RedisType::String { value: "OK".to_owned() })
+        })
+    });
+}
+
+// The JSON document at key, WRONGTYPE if it holds a string
+fn document<'a>(
+    state: &'a State,
+    key: &[u8],
+) -> Result<Option<&'a serde_json::Value>, ServerError> {
+    match state.keystore.get(key) {
+        Some(value) => value.as_json().map(Some).ok_or(ServerError::WrongType),
+        None => Ok(None),
+    }
+}
+
+fn parse_path(path: &str) -> Result<Path, ServerError> {
+    Path::parse(path).map_err(ServerError::Err)
+}
+
+fn missing(path: &str) -> ServerError {
+    ServerError::Err(format!("Path '{path}' does not exist"))
+}
+
+// How JSON.GET lays out what it returns
+#[derive(Default)]
+struct Format {
+    indent: String,
+    newline: String,
+    space: String,
+}
+
+impl Format {
+    fn serialize(&self, value: &serde_json::Value) -> String {
+        let mut out = String::new();
+        self.write(&mut out, value, 0);
+        out
+    }
+
+    fn write(&self, out: &mut String, value: &serde_json::Value, depth: usize) {
+        let (open, close, elements): (_, _, Vec<(Option<&String>, _)>) = match value {
+            serde_json::Value::Array(array) if !array.is_empty() => {
+                ('[', ']', array.iter().map(|value| (None, value)).collect())
+            }
+            serde_json::Value::Object(object) if !object.is_empty() => (
+                '{',
+                '}',
+                object
+                    .iter()
+                    .map(|(name, value)| (Some(name), value))
+                    .collect(),
+            ),
+            value => return out.push_str(&value.to_string()),
+        };
+
+        out.push(open);
+        for (i, (name, value)) in elements.into_iter().enumerate() {
+            if i > 0 {
+                out.push(',');
+            }
+            out.push_str(&self.newline);
+            out.push_str(&self.indent.repeat(depth + 1));
+            if let Some(name) = name {
+                out.push_str(&serde_json::Value::from(name.as_str()).to_string());
+                out.push(':');
+                out.push_str(&self.space);
+            }
+            self.write(out, value, depth + 1);
+        }
+        out.push_str(&self.newline);
+        out.push_str(&self.indent.repeat(depth));
+        out.push(close);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Format;
+    use serde_json::json;
+
+    #[test]
+    fn test_format() {
+        let value = json!({"a": [1, {}], "b": "x"});
+        assert_eq!(Format::default().serialize(&value), value.to_string());
+
+        let format = Format {
+            indent: String::from("  "),
+            newline: String::from("\n"),
+            space: String::from(" "),
+        };
+        assert_eq!(
+            format.serialize(&value),
+            "{\n  \"a\": [\n    1,\n    {}\n  ],\n  \"b\": \"x\"\n}"
+        );
+    }
+}
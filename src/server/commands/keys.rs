@@ -0,0 +1,495 @@
+use super::args::ArgParser;
+use crate::server::blocking::{Block, Event};
+use crate::server::glob;
+use crate::server::{refcount, Command, CommandFn, KeySpec, ServerError, State};
+use crate::RedisType;
+use std::collections::HashMap;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+#[rustfmt::skip]
+pub(super) fn register(m: &mut HashMap<&'static str, Command>) {
+    // DEL and UNLINK
+    let del: CommandFn = |state, _client, args| {
+        let mut deleted = 0;
+        for key in ArgParser::new(args).rest_bytes() {
+            if state.keystore.remove(&key).is_some() {
+                deleted += 1;
+            }
+            state.ttl.remove(&key);
+            state.last_access.remove(&key);
+        }
+
+        Ok(RedisType::from(deleted))
+    };
+
+    m.insert("DEL", Command {
+        summary: "Delete one or more keys",
+        group: "generic",
+        since: "1.0.0",
+        arity: -2,
+        flags: &["write"],
+        keys: KeySpec::Range { first: 1, last: -1, step: 1 },
+        help: String::from("\
+DEL key [key ...]
+
+Removes the specified keys. A key is ignored if it does not exist. Returns the number of keys
+that were removed. This is also what a master sends its replicas when a key expires.
+        "),
+        f: Box::new(del),
+    });
+
+    m.insert("UNLINK", Command {
+        summary: "Delete one or more keys without blocking",
+        group: "generic",
+        since: "4.0.0",
+        arity: -2,
+        flags: &["write", "fast"],
+        keys: KeySpec::Range { first: 1, last: -1, step: 1 },
+        help: String::from("\
+UNLINK key [key ...]
+
+The same as DEL, values are always small enough that there's nothing to free in the background.
+        "),
+        f: Box::new(del),
+    });
+
+    m.insert("EXISTS", Command {
+        summary: "Determine how many of the given keys exist",
+        group: "generic",
+        since: "1.0.0",
+        arity: -2,
+        flags: &["readonly", "fast"],
+        keys: KeySpec::Range { first: 1, last: -1, step: 1 },
+        help: String::from("\
+EXISTS key [key ...]
+
+Returns how many of the given keys exist. A key given more than once is counted each time.
+        "),
+        f: Box::new(|state, _client, args| {
+            let mut count = 0;
+            for key in ArgParser::new(args).rest_bytes() {
+                if state.keystore.contains_key(&key) {
+                    count += 1;
+                }
+            }
+
+            Ok(RedisType::from(count))
+        })
+    });
+
+    // EXPIRE, PEXPIRE, EXPIREAT and PEXPIREAT, with the given time in units of milliseconds
+    macro_rules! expire_command {
+        ($unit:expr, $absolute:expr, $name:literal) => {
+            |state, _client, args| {
+                let mut args = ArgParser::new(args);
+                let key = args.bytes()?;
+                let value = args.integer()?;
+                let condition = args.string().ok();
+                args.finish()?;
+
+                let invalid = || ServerError::Err(String::from(concat!("invalid expire time in '", $name, "' command")));
+                let mut at = value.checked_mul($unit).ok_or_else(invalid)?;
+                if !$absolute {
+                    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_millis() as i64;
+                    at = at.checked_add(now).ok_or_else(invalid)?;
+                }
+
+                let set = expire_at(state, &key, at, condition.as_deref())?;
+                Ok(RedisType::from(set as i64))
+            }
+        }
+    }
+
+    m.insert("EXPIRE", Command {
+        summary: "Set a key's time to live in seconds",
+        group: "generic",
+        since: "1.0.0",
+        arity: -3,
+        flags: &["write", "fast"],
+        keys: KeySpec::FIRST,
+        help: String::from("\
+EXPIRE key seconds [NX | XX | GT | LT]
+
+Set key to expire after the given number of seconds, a time that isn't positive deletes it.
+
+NX - only if the key has no expiration time
+XX - only if the key already has an expiration time
+GT|LT - only if the new time is later / earlier than the current one (no expiration time counts
+    as later than any other)
+
+Returns 1 if the expiration time was set, 0 if the key doesn't exist or the condition failed.
+        "),
+        f: Box::new(expire_command!(1000, false, "expire")),
+    });
+
+    m.insert("PEXPIRE", Command {
+        summary: "Set a key's time to live in milliseconds",
+        group: "generic",
+        since: "2.6.0",
+        arity: -3,
+        flags: &["write", "fast"],
+        keys: KeySpec::FIRST,
+        help: String::from("\
+PEXPIRE key milliseconds [NX | XX | GT | LT]
+
+The same as EXPIRE, in milliseconds.
+        "),
+        f: Box::new(expire_command!(1, false, "pexpire")),
+    });
+
+    m.insert("EXPIREAT", Command {
+        summary: "Set the expiration for a key as a Unix timestamp",
+        group: "generic",
+        since: "1.2.0",
+        arity: -3,
+        flags: &["write", "fast"],
+        keys: KeySpec::FIRST,
+        help: String::from("\
+EXPIREAT key unix-time-seconds [NX | XX | GT | LT]
+
+The same as EXPIRE, at a Unix timestamp in seconds. A time in the past deletes the key.
+        "),
+        f: Box::new(expire_command!(1000, true, "expireat")),
+    });
+
+    m.insert("PEXPIREAT", Command {
+        summary: "Set the expiration for a key as a Unix timestamp specified in milliseconds",
+        group: "generic",
+        since: "2.6.0",
+        arity: -3,
+        flags: &["write", "fast"],
+        keys: KeySpec::FIRST,
+        help: String::from("\
+PEXPIREAT key unix-time-milliseconds [NX | XX | GT | LT]
+
+The same as EXPIRE, at a Unix timestamp in milliseconds. A time in the past deletes the key.
+        "),
+        f: Box::new(expire_command!(1, true, "pexpireat")),
+    });
+
+    // TTL and PTTL
+    macro_rules! ttl_command {
+        ($unit:expr) => {
+            |state, _client, args| {
+                let key = ArgParser::new(args).bytes()?;
+
+                if !state.keystore.contains_key(&key) {
+                    return Ok(RedisType::from(-2));
+                }
+                Ok(match state.ttl.get(&key) {
+                    Some(expires_at) => {
+                        let remaining = expires_at.duration_since(SystemTime::now()).unwrap_or_default().as_millis() as i64;
+                        RedisType::from((remaining + $unit / 2) / $unit)
+                    },
+                    None => RedisType::from(-1),
+                })
+            }
+        }
+    }
+
+    m.insert("TTL", Command {
+        summary: "Get the time to live for a key in seconds",
+        group: "generic",
+        since: "1.0.0",
+        arity: 2,
+        flags: &["readonly", "fast"],
+        keys: KeySpec::FIRST,
+        help: String::from("\
+TTL key
+
+Returns the number of seconds until key expires, -1 if it doesn't have an expiration time or -2
+if it doesn't exist.
+        "),
+        f: Box::new(ttl_command!(1000)),
+    });
+
+    m.insert("PTTL", Command {
+        summary: "Get the time to live for a key in milliseconds",
+        group: "generic",
+        since: "2.6.0",
+        arity: 2,
+        flags: &["readonly", "fast"],
+        keys: KeySpec::FIRST,
+        help: String::from("\
+PTTL key
+
+The same as TTL, in milliseconds.
+        "),
+        f: Box::new(ttl_command!(1)),
+    });
+
+    m.insert("PERSIST", Command {
+        summary: "Remove the expiration from a key",
+        group: "generic",
+        since: "2.2.0",
+        arity: 2,
+        flags: &["write", "fast"],
+        keys: KeySpec::FIRST,
+        help: String::from("\
+PERSIST key
+
+Remove the expiration time from key, so that it's kept until deleted. Returns 1 if the key had
+an expiration time, 0 if it didn't or doesn't exist.
+        "),
+        f: Box::new(|state, _client, args| {
+            let key = ArgParser::new(args).bytes()?;
+
+            let removed = state.keystore.contains_key(&key) && state.ttl.remove(&key);
+            Ok(RedisType::from(removed as i64))
+        })
+    });
+
+    m.insert("RENAME", Command {
+        summary: "Rename a key",
+        group: "generic",
+        since: "1.0.0",
+        arity: 3,
+        flags: &["write"],
+        keys: KeySpec::Range { first: 1, last: 2, step: 1 },
+        help: String::from("\
+RENAME key newkey
+
+Move the value of key to newkey, along with its expiration time. Anything already at newkey is
+replaced. An error if key doesn't exist.
+        "),
+        f: Box::new(|state, _client, args| {
+            let mut args = ArgParser::new(args);
+            let key = args.bytes()?;
+            let new_key = args.bytes()?;
+
+            let value = match state.keystore.remove(&key) {
+                Some(value) => value,
+                None => return Err(ServerError::Err(String::from("no such key"))),
+            };
+            let expires_at = state.ttl.get(&key).copied();
+            let last_access = state.last_access.remove(&key);
+            state.ttl.remove(&key);
+
+            state.ttl.remove(&new_key);
+            state.last_access.remove(&new_key);
+            if let Some(expires_at) = expires_at {
+                state.ttl.push(new_key.clone(), expires_at);
+            }
+            if let Some(last_access) = last_access {
+                state.last_access.insert(new_key.clone(), last_access);
+            }
+            state.keystore.insert(new_key, value);
+
+            Ok(RedisType::String { value: "OK".to_owned() })
+        })
+    });
+
+    m.insert("OBJECT", Command {
+        summary: "A container for object introspection commands",
+        group: "generic",
+        since: "2.2.3",
+        arity: -2,
+        flags: &["readonly"],
+        keys: KeySpec::Range { first: 2, last: 2, step: 1 },
+        help: String::from("\
+OBJECT ENCODING key
+OBJECT REFCOUNT key
+OBJECT IDLETIME key
+
+ENCODING - how the value is stored: int for integers (0 to 9999 are shared between every key
+       holding them), embstr for strings short enough to be kept inline, raw for the rest
+REFCOUNT - 2147483647 for shared integers, otherwise 1
+IDLETIME - seconds since the key was last read or written
+
+Returns nil if the key doesn't exist.
+        "),
+        f: Box::new(|state, _client, args| {
+            let mut args = ArgParser::new(args);
+            let subcommand = args.string()?;
+            let name = subcommand.to_ascii_uppercase();
+            if !["ENCODING", "REFCOUNT", "IDLETIME"].contains(&name.as_str()) {
+                return Err(ServerError::UnknownSubcommand(subcommand));
+            }
+            args.arity(&format!("object|{}", subcommand.to_ascii_lowercase()), 3)?;
+            let key = args.bytes()?;
+
+            let value = match state.keystore.get(&key) {
+                Some(value) => value,
+                None => return Ok(RedisType::NullString),
+            };
+            match name.as_str() {
+                "ENCODING" => Ok(RedisType::from(value.encoding().to_owned())),
+                "REFCOUNT" => Ok(RedisType::from(refcount(value))),
+                _ => {
+                    let idle = state.last_access.get(&key).map(|at| at.elapsed().as_secs()).unwrap_or_default();
+                    Ok(RedisType::from(idle as i64))
+                }
+            }
+        })
+    });
+
+    m.insert("SCAN", Command {
+        summary: "Incrementally iterate the keys space",
+        group: "generic",
+        since: "2.8.0",
+        arity: -2,
+        flags: &["readonly"],
+        keys: KeySpec::None,
+        help: String::from("\
+SCAN cursor [MATCH pattern] [COUNT count] [TYPE type]
+
+Returns the next cursor and a batch of keys, starting from cursor 0 and carrying on until the
+cursor returned is 0 again.
+
+MATCH - only keys matching the glob-style pattern (checked after they're read, so a batch can
+    be empty even if there are more to come)
+COUNT - how many keys to look at for each call (10 by default)
+TYPE - only keys holding values of the given type (string or ReJSON-RL)
+
+Each key that's there for the whole of the scan is returned once, however the keyspace changes
+in the meantime: the scan goes through a snapshot of the keyspace taken at cursor 0, leaving out
+keys that have since been deleted. Keys added during the scan aren't returned. Snapshots of scans
+that go unfinished are dropped after a minute without being continued (or when there are too
+many), after which the cursor goes on through the keyspace as it is and can skip or repeat keys.
+        "),
+        f: Box::new(|state, _client, args| {
+            let mut args = ArgParser::new(args);
+            let cursor = args.parse::<u64>(ServerError::Err(String::from("invalid cursor")))?;
+            let mut pattern = None;
+            let mut count = 10;
+            let mut value_type = None;
+            while let Some(option) = args.option() {
+                match option.as_str() {
+                    "MATCH" => pattern = Some(args.string()?),
+                    "COUNT" => {
+                        count = args.integer()?;
+                        if count < 1 {
+                            return Err(ServerError::Syntax);
+                        }
+                    }
+                    "TYPE" => value_type = Some(args.string()?),
+                    _ => return Err(ServerError::Syntax),
+                }
+            }
+
+            let now = SystemTime::now();
+            let ttl = &state.ttl;
+            let keystore = &state.keystore;
+            let (next, keys) = state.scans.scan(keystore, cursor, count as usize, |key| {
+                if ttl.get(key).is_some_and(|expires_at| *expires_at <= now) {
+                    return false;
+                }
+                if pattern.as_ref().is_some_and(|pattern| !glob::matches(pattern, &String::from_utf8_lossy(key), false)) {
+                    return false;
+                }
+                value_type.as_ref().is_none_or(|value_type| {
+                    keystore.get(key).is_some_and(|value| value_type.eq_ignore_ascii_case(value.type_name()))
+                })
+            });
+            let keys = keys.into_iter().map(RedisType::from).collect::<Vec<_>>();
+
+            Ok(RedisType::from(vec![
+                RedisType::from(next.to_string()),
+                RedisType::from(keys),
+            ]))
+        })
+    });
+
+    m.insert("TYPE", Command {
+        summary: "Determine the type stored at key",
+        group: "generic",
+        since: "1.0.0",
+        arity: 2,
+        flags: &["readonly", "fast"],
+        keys: KeySpec::FIRST,
+        help: String::from("\
+TYPE key
+
+Returns the type of the value stored at key, string or ReJSON-RL (for JSON documents), or none if
+it doesn't exist.
+        "),
+        f: Box::new(|state, _client, args| {
+            let mut args = ArgParser::new(args);
+            let key = args.bytes()?;
+            args.finish()?;
+
+            let value_type = state.keystore.get(&key).map_or("none", |value| value.type_name());
+            Ok(RedisType::from(String::from(value_type)))
+        })
+    });
+
+    m.insert("WAIT", Command {
+        summary: "Wait for the synchronous replication of all the write commands sent in the context of the current connection",
+        group: "generic",
+        since: "3.0.0",
+        arity: 3,
+        flags: &["noscript", "blocking"],
+        keys: KeySpec::None,
+        help: String::from("\
+WAIT numreplicas timeout
+
+Block until at least numreplicas replicas have acknowledged every write this connection has
+made, or timeout milliseconds have passed (0 waits forever). Returns how many replicas have.
+        "),
+        f: Box::new(|state, client, args| {
+            let mut args = ArgParser::new(args);
+            let needed = args.integer()?;
+            let timeout = args.integer()?;
+            if timeout < 0 {
+                return Err(ServerError::Err(String::from("timeout is negative")));
+            }
+            if state.replication.master.is_some() {
+                return Err(ServerError::Err(String::from("WAIT cannot be used with replica instances. Please also note that since Redis 4.0 if a replica is configured to be writable (which is not the default) writes to replicas are just local and are not propagated.")));
+            }
+
+            let acknowledged = state.replication.acknowledged(client.write_offset) as i64;
+            if acknowledged < needed {
+                state.replication.request_acks();
+                let timeout = (timeout > 0).then(|| Duration::from_millis(timeout as u64));
+                client.blocked = Some(Block::new(vec![Event::ReplicaAck], timeout, RedisType::from(acknowledged)));
+            }
+            Ok(RedisType::from(acknowledged))
+        })
+    });
+}
+
+// EXPIRE and friends, with the time as milliseconds since the epoch
+// A time that has already passed deletes the key, returns if the key exists and the condition
+// (NX, XX, GT or LT, if any) held
+fn expire_at(
+    state: &mut State,
+    key: &[u8],
+    at: i64,
+    condition: Option<&str>,
+) -> Result<bool, ServerError> {
+    if !state.keystore.contains_key(key) {
+        return Ok(false);
+    }
+
+    let current = state.ttl.get(key).map(|current| {
+        current
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis() as i64
+    });
+    let allowed = match condition
+        .map(|condition| condition.to_ascii_uppercase())
+        .as_deref()
+    {
+        None => true,
+        Some("NX") => current.is_none(),
+        Some("XX") => current.is_some(),
+        // No expiration time counts as never expiring
+        Some("GT") => current.is_some_and(|current| at > current),
+        Some("LT") => current.is_none_or(|current| at < current),
+        Some(condition) => return Err(ServerError::Err(format!("Unsupported option {condition}"))),
+    };
+    if !allowed {
+        return Ok(false);
+    }
+
+    let expires_at = UNIX_EPOCH + Duration::from_millis(at.max(0) as u64);
+    if expires_at <= SystemTime::now() {
+        state.keystore.remove(key);
+        state.ttl.remove(key);
+        state.last_access.remove(key);
+    } else {
+        state.ttl.push(key.to_vec(), expires_at);
+    }
+    Ok(true)
+}
@@ -0,0 +1,50 @@
+// The command table, split into one module per command group (as COMMAND DOCS reports them)
+// Each module has a register function that adds its commands to the table, so a new command goes
+// in the module for its group and a new group is one more entry in REGISTER.
+
+use super::Command;
+use lazy_static::lazy_static;
+use std::collections::HashMap;
+
+mod args;
+mod cluster;
+mod connection;
+mod json;
+mod keys;
+mod pubsub;
+mod replication;
+mod sentinel;
+mod server;
+mod string;
+
+// Adds a module's commands to the table
+type Register = fn(&mut HashMap<&'static str, Command>);
+
+const REGISTER: &[Register] = &[
+    connection::register,
+    server::register,
+    replication::register,
+    cluster::register,
+    sentinel::register,
+    keys::register,
+    string::register,
+    json::register,
+    pubsub::register,
+];
+
+lazy_static! {
+    pub(super) static ref COMMANDS: HashMap<&'static str, Command> = {
+        let mut m = HashMap::new();
+        for register in REGISTER {
+            let mut group = HashMap::new();
+            register(&mut group);
+            for (name, command) in group {
+                assert!(
+                    m.insert(name, command).is_none(),
+                    "{name} is registered twice"
+                );
+            }
+        }
+        m
+    };
+}
@@ -0,0 +1,166 @@
+use super::args::ArgParser;
+use crate::server::clients::Client;
+use crate::server::pubsub::{self, Kind};
+use crate::server::{glob, Command, KeySpec, ServerError};
+use crate::RedisType;
+use std::collections::HashMap;
+
+#[rustfmt::skip]
+pub(super) fn register(m: &mut HashMap<&'static str, Command>) {
+    m.insert("SUBSCRIBE", Command {
+        summary: "Listens for messages published to channels",
+        group: "pubsub",
+        since: "2.0.0",
+        arity: -2,
+        flags: &["pubsub", "noscript", "loading", "stale"],
+        keys: KeySpec::None,
+        help: String::from("\
+SUBSCRIBE channel [channel ...]
+
+Sends each message published to the channels from then on, as a message push (an array under RESP2) of the channel and the message.
+Under RESP2 the connection can then only run SUBSCRIBE, UNSUBSCRIBE, PSUBSCRIBE, PUNSUBSCRIBE, PING, QUIT and RESET until it unsubscribes from everything.
+        "),
+        f: Box::new(|state, client, args| {
+            let channels = ArgParser::new(args).rest_bytes();
+            let replies = pubsub::subscribe(state, client, Kind::Channel, channels);
+            Ok(reply(client, replies))
+        })
+    });
+
+    m.insert("UNSUBSCRIBE", Command {
+        summary: "Stops listening to messages posted to channels",
+        group: "pubsub",
+        since: "2.0.0",
+        arity: -1,
+        flags: &["pubsub", "noscript", "loading", "stale"],
+        keys: KeySpec::None,
+        help: String::from("\
+UNSUBSCRIBE [channel [channel ...]]
+
+Unsubscribes from the channels, or from every channel without any.
+        "),
+        f: Box::new(|state, client, args| {
+            let channels = ArgParser::new(args).rest_bytes();
+            let replies = pubsub::unsubscribe(state, client, Kind::Channel, channels);
+            Ok(reply(client, replies))
+        })
+    });
+
+    m.insert("PSUBSCRIBE", Command {
+        summary: "Listens for messages published to channels that match one or more patterns",
+        group: "pubsub",
+        since: "2.0.0",
+        arity: -2,
+        flags: &["pubsub", "noscript", "loading", "stale"],
+        keys: KeySpec::None,
+        help: String::from("\
+PSUBSCRIBE pattern [pattern ...]
+
+As SUBSCRIBE, for every channel matching the glob-style patterns, with pmessage pushes of the pattern, the channel and the message.
+        "),
+        f: Box::new(|state, client, args| {
+            let patterns = ArgParser::new(args).rest_bytes();
+            let replies = pubsub::subscribe(state, client, Kind::Pattern, patterns);
+            Ok(reply(client, replies))
+        })
+    });
+
+    m.insert("PUNSUBSCRIBE", Command {
+        summary: "Stops listening to messages published to channels that match one or more patterns",
+        group: "pubsub",
+        since: "2.0.0",
+        arity: -1,
+        flags: &["pubsub", "noscript", "loading", "stale"],
+        keys: KeySpec::None,
+        help: String::from("\
+PUNSUBSCRIBE [pattern [pattern ...]]
+
+Unsubscribes from the patterns, or from every pattern without any.
+        "),
+        f: Box::new(|state, client, args| {
+            let patterns = ArgParser::new(args).rest_bytes();
+            let replies = pubsub::unsubscribe(state, client, Kind::Pattern, patterns);
+            Ok(reply(client, replies))
+        })
+    });
+
+    m.insert("PUBLISH", Command {
+        summary: "Posts a message to a channel",
+        group: "pubsub",
+        since: "2.0.0",
+        arity: 3,
+        flags: &["pubsub", "loading", "stale", "fast"],
+        keys: KeySpec::None,
+        help: String::from("\
+PUBLISH channel message
+
+Returns how many subscribers the message was sent to, counting a connection once for the channel and once for each of its patterns that match it.
+        "),
+        f: Box::new(|state, _client, args| {
+            let mut args = ArgParser::new(args);
+            let channel = args.bytes()?;
+            let message = args.bytes()?;
+            Ok(RedisType::from(pubsub::publish(state, &channel, &message) as i64))
+        })
+    });
+
+    m.insert("PUBSUB", Command {
+        summary: "A container for Pub/Sub commands",
+        group: "pubsub",
+        since: "2.8.0",
+        arity: -2,
+        flags: &["pubsub", "loading", "stale"],
+        keys: KeySpec::None,
+        help: String::from("\
+PUBSUB CHANNELS [pattern]
+PUBSUB NUMSUB [channel [channel ...]]
+PUBSUB NUMPAT
+
+CHANNELS lists the channels with at least one subscriber (not counting patterns), or those matching pattern.
+NUMSUB returns each channel with its number of subscribers, NUMPAT how many patterns are subscribed to.
+        "),
+        f: Box::new(|state, _client, args| {
+            let mut args = ArgParser::new(args);
+            let subcommand = args.string()?;
+            let command = format!("pubsub|{}", subcommand.to_ascii_lowercase());
+
+            match subcommand.to_ascii_uppercase().as_str() {
+                "CHANNELS" => {
+                    args.arity(&command, -2)?;
+                    let pattern = match args.is_empty() {
+                        true => None,
+                        false => Some(args.string()?),
+                    };
+                    args.finish().map_err(|_| ServerError::WrongArity(command))?;
+
+                    let channels = state.pubsub.channels().filter(|channel| {
+                        pattern.as_ref().is_none_or(|pattern| glob::matches(pattern, &String::from_utf8_lossy(channel), false))
+                    });
+                    Ok(RedisType::from(channels.cloned().map(RedisType::from).collect::<Vec<_>>()))
+                }
+                "NUMSUB" => {
+                    let mut counts = Vec::new();
+                    for channel in args.rest_bytes() {
+                        let subscribers = state.pubsub.subscribers(&channel);
+                        counts.push(RedisType::from(channel));
+                        counts.push(RedisType::from(subscribers as i64));
+                    }
+                    Ok(RedisType::from(counts))
+                }
+                "NUMPAT" => {
+                    args.arity(&command, 2)?;
+                    Ok(RedisType::from(state.pubsub.patterns() as i64))
+                }
+                _ => Err(ServerError::UnknownSubcommand(subcommand)),
+            }
+        })
+    });
+}
+
+// A command that replies once for each channel or pattern returns the last of them, the others go
+// out before it
+fn reply(client: &mut Client, mut replies: Vec<RedisType>) -> RedisType {
+    let last = replies.pop().unwrap_or(RedisType::NullArray);
+    client.replies.extend(replies);
+    last
+}
@@ -0,0 +1,346 @@
+use super::args::ArgParser;
+use crate::server::blocking::Event;
+use crate::server::replication::{self, LinkStatus};
+use crate::server::{rdb, Command, CommandFn, KeySpec, ServerError};
+use crate::RedisType;
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+#[rustfmt::skip]
+pub(super) fn register(m: &mut HashMap<&'static str, Command>) {
+    m.insert("PSYNC", Command {
+        summary: "An internal command used in replication",
+        group: "server",
+        since: "2.8.0",
+        arity: -3,
+        flags: &["admin", "noscript", "no_async_loading", "no_multi"],
+        keys: KeySpec::None,
+        help: String::from("\
+PSYNC replicationid offset [FAILOVER]
+
+Sent by a replica to start replicating. This always does a full resync: the reply is
++FULLRESYNC with the replication id and offset, followed by a snapshot in RDB format and then
+every write from that point on.
+
+With FAILOVER, sent by our master once it has handed over to us: we are promoted to a master
+first, as long as replicationid is the one we were replicating.
+        "),
+        f: Box::new(|state, client, args| {
+            let mut args = ArgParser::new(args);
+            let replid = args.string()?;
+            let _offset = args.integer()?;
+            let failover = args.keyword("FAILOVER");
+            args.finish()?;
+
+            if client.replica {
+                return Err(ServerError::Err(String::from("Replica already connected")));
+            }
+
+            if failover {
+                if state.replication.master.is_none() || replid != state.replication.replid {
+                    return Err(ServerError::Err(String::from("PSYNC FAILOVER replid must match my replid.")));
+                }
+                tracing::info!("Failover request received for replid {replid}");
+                state.replication.promote();
+                state.config.replicaof = None;
+            }
+
+            // Chained replicas get our copy of the master's data, so it has to be complete
+            if state.replication.master.as_ref().is_some_and(|link| link.status != LinkStatus::Connected) {
+                return Err(ServerError::NoMasterLink);
+            }
+
+            let snapshot = rdb::Snapshot::of(state);
+            client.full_sync = Some(state.replication.attach(client.id, client.addr, client.listening_port, snapshot, true));
+            client.replica = true;
+            Ok(RedisType::NullString)
+        })
+    });
+
+    m.insert("SYNC", Command {
+        summary: "An internal command used in replication",
+        group: "server",
+        since: "1.0.0",
+        arity: 1,
+        flags: &["admin", "noscript", "no_async_loading", "no_multi"],
+        keys: KeySpec::None,
+        help: String::from("\
+SYNC
+
+The replication handshake from before PSYNC, the same as a full resync without the +FULLRESYNC
+line.
+        "),
+        f: Box::new(|state, client, _args| {
+
+            if client.replica {
+                return Err(ServerError::Err(String::from("Replica already connected")));
+            }
+            if state.replication.master.as_ref().is_some_and(|link| link.status != LinkStatus::Connected) {
+                return Err(ServerError::NoMasterLink);
+            }
+
+            let snapshot = rdb::Snapshot::of(state);
+            client.full_sync = Some(state.replication.attach(client.id, client.addr, client.listening_port, snapshot, false));
+            client.replica = true;
+            Ok(RedisType::NullString)
+        })
+    });
+
+    m.insert("ROLE", Command {
+        summary: "Return the replication role",
+        group: "server",
+        since: "2.8.12",
+        arity: 1,
+        flags: &["noscript", "loading", "stale", "fast"],
+        keys: KeySpec::None,
+        help: String::from("\
+ROLE
+
+As a master: master, the replication offset, and the ip, port and acknowledged offset of each
+connected replica.
+
+As a replica: slave, the master's host and port, the state of the link (connect, connecting,
+sync or connected) and how much of the master's stream has been applied.
+
+As a sentinel: sentinel and the names of the monitored masters.
+        "),
+        f: Box::new(|state, _client, _args| {
+
+            if let Some(sentinel) = &state.sentinel {
+                let names = sentinel.masters.keys().map(|name| RedisType::from(name.clone())).collect::<Vec<_>>();
+                return Ok(RedisType::from(vec![
+                    RedisType::from(String::from("sentinel")),
+                    RedisType::from(names),
+                ]));
+            }
+
+            if let Some(link) = &state.replication.master {
+                let offset = if link.status == LinkStatus::Connected {
+                    state.replication.offset as i64
+                } else {
+                    -1
+                };
+                return Ok(RedisType::from(vec![
+                    RedisType::from(String::from("slave")),
+                    RedisType::from(link.host.clone()),
+                    RedisType::from(link.port as i64),
+                    RedisType::from(link.status.to_string()),
+                    RedisType::from(offset),
+                ]));
+            }
+
+            let replicas = state.replication.replicas.values()
+                .filter(|replica| replica.online)
+                .map(|replica| RedisType::from(vec![
+                    RedisType::from(replica.endpoint().0),
+                    RedisType::from(replica.endpoint().1.to_string()),
+                    RedisType::from(replica.ack_offset.to_string()),
+                ]))
+                .collect::<Vec<_>>();
+
+            Ok(RedisType::from(vec![
+                RedisType::from(String::from("master")),
+                RedisType::from(state.replication.offset as i64),
+                RedisType::from(replicas),
+            ]))
+        })
+    });
+
+    // REPLICAOF and its older name SLAVEOF
+    let replicaof: CommandFn = |state, _client, args| {
+        let mut args = ArgParser::new(args);
+        let host = args.string()?;
+
+        if host.eq_ignore_ascii_case("NO") && args.keyword("ONE") {
+            if state.replication.master.is_some() {
+                state.replication.promote();
+                state.config.replicaof = None;
+                tracing::info!("MASTER MODE enabled");
+            }
+            return Ok(RedisType::String { value: "OK".to_owned() });
+        }
+
+        let port = args.integer()?;
+        let port = u16::try_from(port).map_err(|_| ServerError::Err(String::from("Invalid master port")))?;
+
+        if let Some(link) = &state.replication.master {
+            if link.host == host && link.port == port {
+                return Ok(RedisType::String { value: "OK Already connected to specified master".to_owned() });
+            }
+        }
+
+        tracing::info!("REPLICAOF {host}:{port} enabled");
+        state.replication.replicate_from(host.clone(), port);
+        state.config.replicaof = Some((host, port));
+        Ok(RedisType::String { value: "OK".to_owned() })
+    };
+
+    m.insert("REPLICAOF", Command {
+        summary: "Configure a server as replica of another, or promote it to a master",
+        group: "server",
+        since: "5.0.0",
+        arity: 3,
+        flags: &["admin", "noscript", "stale", "no_async_loading"],
+        keys: KeySpec::None,
+        help: String::from("\
+REPLICAOF host port
+REPLICAOF NO ONE
+
+Start replicating from the master at host and port: its snapshot replaces all of our data, then
+every write it makes is applied here as well. The connection is made in the background and
+retried if it is lost. NO ONE stops replicating and keeps the current data as a master.
+        "),
+        f: Box::new(replicaof),
+    });
+
+    m.insert("SLAVEOF", Command {
+        summary: "Configure a server as replica of another, or promote it to a master",
+        group: "server",
+        since: "1.0.0",
+        arity: 3,
+        flags: &["admin", "noscript", "stale", "no_async_loading"],
+        keys: KeySpec::None,
+        help: String::from("\
+SLAVEOF host port
+SLAVEOF NO ONE
+
+The older name for REPLICAOF.
+        "),
+        f: Box::new(replicaof),
+    });
+
+    m.insert("FAILOVER", Command {
+        summary: "Start a coordinated failover between this server and one of its replicas",
+        group: "server",
+        since: "6.2.0",
+        arity: -1,
+        flags: &["admin", "noscript", "stale"],
+        keys: KeySpec::None,
+        help: String::from("\
+FAILOVER [TO host port [FORCE]] [TIMEOUT milliseconds]
+FAILOVER ABORT
+
+Hand over to a replica without losing writes: writes are paused until a replica (the one at
+host and port, if given) has acknowledged everything, then this server becomes its replica and
+asks it to take over. Without FORCE the failover is aborted if no replica catches up within
+TIMEOUT, with FORCE it goes ahead with the target anyway. ABORT stops a failover in progress.
+The progress is shown by master_failover_state in INFO replication.
+        "),
+        f: Box::new(|state, _client, args| {
+            let mut target = None;
+            let mut force = false;
+            let mut abort = false;
+            let mut timeout = None;
+
+            let mut args = ArgParser::new(args);
+            while let Some(option) = args.option() {
+                match option.as_str() {
+                    "TO" if target.is_none() => {
+                        let host = args.string()?;
+                        let port = args.integer()?;
+                        let port = u16::try_from(port).map_err(|_| ServerError::Err(String::from("Invalid port")))?;
+                        target = Some((host, port));
+                    }
+                    "FORCE" if !force => force = true,
+                    "ABORT" if !abort => abort = true,
+                    "TIMEOUT" if timeout.is_none() => {
+                        let milliseconds = args.integer()?;
+                        if milliseconds <= 0 {
+                            return Err(ServerError::Err(String::from("FAILOVER timeout must be greater than 0")));
+                        }
+                        timeout = Some(Duration::from_millis(milliseconds as u64));
+                    }
+                    _ => return Err(ServerError::Syntax),
+                }
+            }
+
+            if abort {
+                if target.is_some() || force || timeout.is_some() {
+                    return Err(ServerError::Err(String::from("FAILOVER ABORT cannot be combined with other arguments")));
+                }
+                if state.replication.failover.is_none() {
+                    return Err(ServerError::Err(String::from("No failover in progress.")));
+                }
+                tracing::warn!("FAILOVER manually aborted");
+                replication::abort_failover(state);
+                return Ok(RedisType::String { value: "OK".to_owned() });
+            }
+
+            if state.replication.master.is_some() {
+                return Err(ServerError::Err(String::from("FAILOVER is not valid when server is a replica.")));
+            }
+            if state.replication.replicas.is_empty() {
+                return Err(ServerError::Err(String::from("FAILOVER requires connected replicas.")));
+            }
+            if state.replication.failover.is_some() {
+                return Err(ServerError::Err(String::from("FAILOVER already in progress.")));
+            }
+            if force && (target.is_none() || timeout.is_none()) {
+                return Err(ServerError::Err(String::from("FAILOVER with force option requires both a timeout and target HOST and IP.")));
+            }
+            if let Some(target) = &target {
+                let replica = state.replication.replicas.values().find(|replica| replica.endpoint() == *target);
+                match replica {
+                    None => return Err(ServerError::Err(String::from("FAILOVER target HOST and PORT is not a replica."))),
+                    Some(replica) if !replica.online => return Err(ServerError::Err(String::from("FAILOVER target replica is not online."))),
+                    Some(_) => {}
+                }
+            }
+
+            tracing::info!("FAILOVER requested to {}", target.as_ref().map_or(String::from("any replica"), |(host, port)| format!("{host}:{port}")));
+            state.replication.failover = Some(replication::Failover {
+                target,
+                deadline: timeout.map(|timeout| Instant::now() + timeout),
+                force,
+                state: replication::FailoverState::WaitingForSync,
+            });
+            replication::update_failover(state);
+            Ok(RedisType::String { value: "OK".to_owned() })
+        })
+    });
+
+    m.insert("REPLCONF", Command {
+        summary: "An internal command for configuring the replication stream",
+        group: "server",
+        since: "3.0.0",
+        arity: -1,
+        flags: &["admin", "noscript", "loading", "stale", "allow_busy"],
+        keys: KeySpec::None,
+        help: String::from("\
+REPLCONF option value [option value ...]
+
+Sent by replicas to describe themselves before PSYNC (listening-port, ip-address, capa) and to
+report how much of the replication stream they have processed (ACK offset), which gets no reply.
+        "),
+        f: Box::new(|state, client, args| {
+            if args.len() % 2 != 0 {
+                return Err(ServerError::Syntax);
+            }
+
+            let mut args = ArgParser::new(args);
+            while !args.is_empty() {
+                let option = args.string()?.to_ascii_lowercase();
+                match option.as_str() {
+                    "listening-port" => {
+                        let port = args.integer()?;
+                        client.listening_port = Some(u16::try_from(port).map_err(|_| ServerError::Err(String::from("invalid port")))?);
+                    }
+                    "ack" => {
+                        let offset = args.integer()?;
+                        state.replication.ack(client.id, offset.max(0) as u64);
+                        state.waiters.signal(&Event::ReplicaAck);
+                        replication::update_failover(state);
+                        client.no_reply = true;
+                    }
+                    // Only the master sends GETACK, and other capabilities don't change anything yet
+                    "ip-address" | "capa" | "fack" | "getack" | "rdb-only" | "rdb-filter-only" => {
+                        args.string()?;
+                    }
+                    _ => return Err(ServerError::Err(format!("Unrecognized REPLCONF option: {option}"))),
+                }
+            }
+
+            Ok(RedisType::String { value: "OK".to_owned() })
+        })
+    });
+}
@@ -0,0 +1,150 @@
+use super::args::ArgParser;
+use crate::server::{sentinel, Command, KeySpec, ServerError};
+use crate::RedisType;
+use std::collections::HashMap;
+
+#[rustfmt::skip]
+pub(super) fn register(m: &mut HashMap<&'static str, Command>) {
+    m.insert("SENTINEL", Command {
+        summary: "A container for Redis Sentinel commands",
+        group: "sentinel",
+        since: "2.8.4",
+        arity: -2,
+        flags: &["admin", "sentinel", "only_sentinel"],
+        keys: KeySpec::None,
+        help: String::from("\
+SENTINEL MASTERS
+SENTINEL MASTER name
+SENTINEL REPLICAS name
+SENTINEL GET-MASTER-ADDR-BY-NAME name
+SENTINEL MONITOR name ip port quorum
+SENTINEL REMOVE name
+SENTINEL SET name option value [option value ...]
+SENTINEL FAILOVER name
+SENTINEL CKQUORUM name
+SENTINEL MYID
+
+Only available when started with --sentinel. Each monitored master is pinged every second and
+asked for INFO replication every ten to find its replicas. Once it hasn't answered for
+down-after-milliseconds it is subjectively down (s_down), and with a quorum of 1 that is enough
+for the sentinel to promote the replica furthest along and point the others at it. FAILOVER does
+the same on demand, using the FAILOVER command if the master is still up so that no writes are
+lost. Options for SET are down-after-milliseconds, failover-timeout, quorum and auth-pass.
+        "),
+        f: Box::new(|state, _client, args| {
+            let sentinel = match state.sentinel.as_mut() {
+                Some(sentinel) => sentinel,
+                None => return Err(ServerError::Err(String::from("unknown command 'SENTINEL', only available in sentinel mode"))),
+            };
+            let mut args = ArgParser::new(args);
+            let subcommand = args.string()?;
+            let command = format!("sentinel|{}", subcommand.to_ascii_lowercase());
+            let upper = subcommand.to_ascii_uppercase();
+            let fields = |fields: Vec<(String, String)>| {
+                RedisType::from(fields.into_iter().map(|(field, value)| (RedisType::from(field), RedisType::from(value))).collect::<Vec<_>>())
+            };
+
+            // Subcommands other than these are about one master, given by name
+            match upper.as_str() {
+                "MASTERS" => {
+                    args.arity(&command, 2)?;
+                    return Ok(RedisType::from(sentinel.masters.values().map(|master| fields(master.fields())).collect::<Vec<_>>()));
+                }
+                "MYID" => {
+                    args.arity(&command, 2)?;
+                    return Ok(RedisType::from(sentinel.myid.clone()));
+                }
+                "MONITOR" => {
+                    args.arity(&command, 6)?;
+                    let name = args.string()?;
+                    let host = args.string()?;
+                    let port = args.integer()?;
+                    let port = u16::try_from(port).ok().filter(|port| *port > 0).ok_or_else(|| ServerError::Err(String::from("Invalid port number")))?;
+                    let quorum = args.integer()?;
+                    let quorum = u32::try_from(quorum).unwrap_or(0);
+                    sentinel.monitor(&name, &host, port, quorum).map_err(ServerError::Err)?;
+                    tracing::warn!("+monitor master {name} {host} {port} quorum {quorum}");
+                    sentinel::save(state);
+                    return Ok(RedisType::String { value: "OK".to_owned() });
+                }
+                _ => {}
+            }
+
+            let name = args.string()?;
+            if upper == "GET-MASTER-ADDR-BY-NAME" {
+                args.arity(&command, 3)?;
+                return Ok(match sentinel.masters.get(&name) {
+                    Some(master) => RedisType::from(vec![
+                        RedisType::from(master.host.clone()),
+                        RedisType::from(master.port.to_string()),
+                    ]),
+                    None => RedisType::NullArray,
+                });
+            }
+            if upper == "REMOVE" {
+                args.arity(&command, 3)?;
+                if !sentinel.remove(&name) {
+                    return Err(ServerError::Err(String::from("No such master with that name")));
+                }
+                tracing::warn!("-monitor master {name}");
+                sentinel::save(state);
+                return Ok(RedisType::String { value: "OK".to_owned() });
+            }
+
+            let master = match sentinel.masters.get_mut(&name) {
+                Some(master) => master,
+                None => return Err(ServerError::Err(String::from("No such master with that name"))),
+            };
+            match upper.as_str() {
+                "MASTER" => {
+                    args.arity(&command, 3)?;
+                    Ok(fields(master.fields()))
+                }
+                "REPLICAS" | "SLAVES" => {
+                    args.arity(&command, 3)?;
+                    let replicas = master.replicas.iter().map(|replica| fields(vec![
+                        (String::from("name"), format!("{}:{}", replica.ip, replica.port)),
+                        (String::from("ip"), replica.ip.clone()),
+                        (String::from("port"), replica.port.to_string()),
+                        (String::from("flags"), String::from("slave")),
+                        (String::from("role-reported"), String::from("slave")),
+                        (String::from("master-link-status"), String::from(if replica.online { "ok" } else { "err" })),
+                        (String::from("master-host"), master.host.clone()),
+                        (String::from("master-port"), master.port.to_string()),
+                        (String::from("slave-repl-offset"), replica.offset.to_string()),
+                    ])).collect::<Vec<_>>();
+                    Ok(RedisType::from(replicas))
+                }
+                "SET" => {
+                    if args.remaining() < 2 || !args.remaining().is_multiple_of(2) {
+                        return Err(ServerError::WrongArity(command));
+                    }
+                    while !args.is_empty() {
+                        master.set(&args.string()?, &args.string()?).map_err(ServerError::Err)?;
+                    }
+                    sentinel::save(state);
+                    Ok(RedisType::String { value: "OK".to_owned() })
+                }
+                "FAILOVER" => {
+                    args.arity(&command, 3)?;
+                    if master.failover_since.is_some() || master.failover_requested {
+                        return Err(ServerError::InProgress);
+                    }
+                    if master.choose_replica().is_none() {
+                        return Err(ServerError::NoGoodReplica);
+                    }
+                    master.failover_requested = true;
+                    Ok(RedisType::String { value: "OK".to_owned() })
+                }
+                "CKQUORUM" => {
+                    args.arity(&command, 3)?;
+                    if master.quorum > 1 {
+                        return Err(ServerError::NoQuorum(master.quorum));
+                    }
+                    Ok(RedisType::String { value: "OK 1 usable Sentinels. Quorum and failover authorization can be reached".to_owned() })
+                }
+                _ => Err(ServerError::UnknownSubcommand(subcommand)),
+            }
+        })
+    });
+}
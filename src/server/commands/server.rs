@@ -0,0 +1,719 @@
+use super::args::{ArgParser, Exclusive};
+use super::COMMANDS;
+use crate::server::{
+    allocator, aof, config, glob, info, memory, rdb, refcount, Command, KeySpec, ServerError,
+};
+use crate::RedisType;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+#[rustfmt::skip]
+pub(super) fn register(m: &mut HashMap<&'static str, Command>) {
+    m.insert("COMMAND", Command {
+        summary: "Get array of Redis command details",
+        group: "server",
+        since: "2.8.13",
+        arity: -1,
+        flags: &["loading", "stale"],
+        keys: KeySpec::None,
+        help: String::from("\
+COMMAND
+COMMAND COUNT
+COMMAND LIST
+COMMAND INFO [command-name ...]
+COMMAND DOCS [command-name ...]
+COMMAND GETKEYS command [arg ...]
+
+Return details about Redis commands, either all of them or only the ones named.
+GETKEYS returns the keys that the given full command would access.
+        "),
+        f: Box::new(|_state, _client, args| {
+            // Sorted so that the output is stable
+            let mut names = COMMANDS.keys().copied().collect::<Vec<_>>();
+            names.sort();
+
+            if args.is_empty() {
+                return Ok(RedisType::from(
+                    names.iter().map(|name| COMMANDS[name].info(name)).collect::<Vec<_>>(),
+                ));
+            }
+
+            // For GETKEYS, the full command to find the keys of
+            let argv = &args[1..];
+            let mut args = ArgParser::new(args);
+            let subcommand = args.string()?;
+            let command = format!("command|{}", subcommand.to_ascii_lowercase());
+
+            match subcommand.to_ascii_uppercase().as_str() {
+                "COUNT" => {
+                    args.arity(&command, 2)?;
+                    Ok(RedisType::from(COMMANDS.len() as i64))
+                }
+                "LIST" => {
+                    args.arity(&command, 2)?;
+                    Ok(RedisType::from(
+                        names
+                            .iter()
+                            .map(|name| RedisType::from(name.to_ascii_lowercase()))
+                            .collect::<Vec<_>>(),
+                    ))
+                }
+                "INFO" => {
+                    if args.is_empty() {
+                        return Ok(RedisType::from(
+                            names.iter().map(|name| COMMANDS[name].info(name)).collect::<Vec<_>>(),
+                        ));
+                    }
+
+                    let mut value = Vec::new();
+                    for name in args.rest() {
+                        let name = name.to_ascii_uppercase();
+                        value.push(match COMMANDS.get(name.as_str()) {
+                            Some(command) => command.info(&name),
+                            None => RedisType::NullArray,
+                        });
+                    }
+                    Ok(RedisType::from(value))
+                }
+                "DOCS" => {
+                    let requested = if args.is_empty() {
+                        names.iter().map(|name| name.to_string()).collect::<Vec<_>>()
+                    } else {
+                        args.rest().iter().map(|name| name.to_ascii_uppercase()).collect()
+                    };
+
+                    // Unknown commands are left out rather than returned as null
+                    let mut value = Vec::new();
+                    for name in requested {
+                        if let Some(command) = COMMANDS.get(name.as_str()) {
+                            value.push((RedisType::from(name.to_ascii_lowercase()), command.docs()));
+                        }
+                    }
+                    Ok(RedisType::Map { value })
+                }
+                "GETKEYS" => {
+                    args.arity(&command, -3)?;
+
+                    let command = match COMMANDS.get(args.string()?.to_ascii_uppercase().as_str()) {
+                        Some(command) => command,
+                        None => return Err(ServerError::Err(String::from("Invalid command specified"))),
+                    };
+
+                    if !command.check_arity(argv.len()) {
+                        return Err(ServerError::Err(String::from("Invalid number of arguments specified for command")));
+                    }
+
+                    let keys = command.keys(argv);
+                    if keys.is_empty() {
+                        return Err(ServerError::Err(String::from("The command has no key arguments")));
+                    }
+                    Ok(RedisType::from(keys))
+                }
+                _ => Err(ServerError::UnknownSubcommand(subcommand)),
+            }
+        })
+    });
+
+    m.insert("CONFIG", Command {
+        summary: "Get or set configuration parameters",
+        group: "server",
+        since: "2.0.0",
+        arity: -2,
+        flags: &["admin", "noscript", "loading", "stale"],
+        keys: KeySpec::None,
+        help: String::from("\
+CONFIG GET parameter [parameter ...]
+CONFIG SET parameter value [parameter value ...]
+CONFIG REWRITE
+CONFIG RESETSTAT
+
+Get or set configuration parameters. GET accepts glob-style patterns.
+SET is atomic: if any value is invalid, none of them are changed.
+REWRITE saves the current configuration back to the config file the server was started with.
+RESETSTAT clears the statistics reported by INFO.
+        "),
+        f: Box::new(|state, _client, args| {
+            let mut args = ArgParser::new(args);
+            let subcommand = args.string()?;
+            let command = format!("config|{}", subcommand.to_ascii_lowercase());
+
+            match subcommand.to_ascii_uppercase().as_str() {
+                "GET" => {
+                    args.arity(&command, -3)?;
+                    let patterns = args.rest();
+
+                    let mut value = Vec::new();
+                    for parameter in config::PARAMETERS.iter() {
+                        if patterns.iter().any(|pattern| glob::matches(pattern, parameter.name, true)) {
+                            value.push((
+                                RedisType::from(String::from(parameter.name)),
+                                RedisType::from((parameter.get)(&state.config)),
+                            ));
+                        }
+                    }
+
+                    Ok(RedisType::Map { value })
+                }
+                "SET" => {
+                    if args.remaining() < 2 || !args.remaining().is_multiple_of(2) {
+                        return Err(ServerError::WrongArity(String::from("config|set")));
+                    }
+
+                    // Apply everything to a copy so a failure part way through doesn't leave a partial update
+                    let mut config = state.config.clone();
+                    while !args.is_empty() {
+                        let name = args.string()?;
+                        let value = args.string()?;
+
+                        let parameter = match config::find_parameter(&name) {
+                            Some(parameter) => parameter,
+                            None => return Err(ServerError::Err(format!("Unknown option or number of arguments for CONFIG SET - '{name}'"))),
+                        };
+
+                        let set = match parameter.set {
+                            Some(set) => set,
+                            None => return Err(ServerError::Err(format!("CONFIG SET failed (possibly related to argument '{}') - can't set immutable config", parameter.name))),
+                        };
+
+                        if let Err(e) = set(&mut config, &value) {
+                            return Err(ServerError::Err(format!("CONFIG SET failed (possibly related to argument '{}') - {e}", parameter.name)));
+                        }
+                    }
+
+                    if let Err(e) = config.apply() {
+                        return Err(ServerError::Err(format!("CONFIG SET failed - {e}")));
+                    }
+
+                    // Turning on appendonly starts the file off with the current dataset
+                    if config.appendonly && state.aof.is_none() {
+                        let path = PathBuf::from(&config.appendfilename);
+                        match aof::start(state, &path, config.appendfsync) {
+                            Ok(aof) => state.aof = Some(aof),
+                            Err(e) => return Err(ServerError::Err(format!("CONFIG SET failed (possibly related to argument 'appendonly') - {e}"))),
+                        }
+                    } else if !config.appendonly {
+                        if let Some(mut aof) = state.aof.take() {
+                            if let Err(e) = aof.sync() {
+                                tracing::warn!("Error syncing the append only file: {e}");
+                            }
+                        }
+                    }
+                    if let Some(aof) = state.aof.as_mut() {
+                        aof.fsync = config.appendfsync;
+                    }
+
+                    // Samples from before sampling was turned off would otherwise come back with it
+                    if config.hotkeys_sample_rate == 0 {
+                        state.hotkeys.reset();
+                    }
+
+                    state.config = config;
+                    Ok(RedisType::String { value: "OK".to_owned() })
+                }
+                "REWRITE" => {
+                    args.arity(&command, 2)?;
+
+                    match state.config.rewrite() {
+                        Ok(()) => Ok(RedisType::String { value: "OK".to_owned() }),
+                        Err(e) => {
+                            tracing::warn!("CONFIG REWRITE failed: {e}");
+                            Err(ServerError::Err(e))
+                        }
+                    }
+                }
+                "RESETSTAT" => {
+                    args.arity(&command, 2)?;
+                    state.stats.reset();
+                    Ok(RedisType::String { value: "OK".to_owned() })
+                }
+                _ => Err(ServerError::UnknownSubcommand(subcommand)),
+            }
+        })
+    });
+
+    m.insert("DBSIZE", Command {
+        summary: "Return the number of keys in the selected database",
+        group: "server",
+        since: "1.0.0",
+        arity: 1,
+        flags: &["readonly", "fast"],
+        keys: KeySpec::None,
+        help: String::from("\
+DBSIZE
+
+Returns the number of keys, including any that have expired but haven't been deleted yet.
+        "),
+        f: Box::new(|state, _client, _args| {
+            Ok(RedisType::from(state.keystore.len() as i64))
+        })
+    });
+
+    m.insert("DEBUG", Command {
+        summary: "A container for debugging commands",
+        group: "server",
+        since: "1.0.0",
+        arity: -2,
+        flags: &["admin", "noscript", "loading", "stale", "protected"],
+        keys: KeySpec::None,
+        help: String::from("\
+DEBUG SLEEP seconds
+DEBUG OBJECT key
+DEBUG SET-ACTIVE-EXPIRE 0|1
+DEBUG HOTKEYS [count]
+DEBUG BIGKEYS [count]
+DEBUG JMAP
+
+Commands used by test suites. SLEEP blocks the whole server, not just this connection.
+SET-ACTIVE-EXPIRE 0 stops the background removal of expired keys, 1 starts it again.
+HOTKEYS returns the count (10 by default) most accessed keys over the last minute with roughly
+how many times each was accessed, and BIGKEYS the largest keys accessed with their memory usage in
+bytes, both from the accesses sampled when hotkeys-sample-rate is set.
+Other subcommands that only make sense for the C implementation are accepted and ignored.
+        "),
+        f: Box::new(|state, _client, args| {
+            let mut args = ArgParser::new(args);
+            let subcommand = args.string()?;
+            let command = format!("debug|{}", subcommand.to_ascii_lowercase());
+
+            match subcommand.to_ascii_uppercase().as_str() {
+                "SLEEP" => {
+                    args.arity(&command, 3)?;
+                    let seconds = args.float()?;
+                    if !seconds.is_finite() || seconds < 0.0 {
+                        return Err(ServerError::Err(String::from("value is out of range")));
+                    }
+
+                    // Deliberately blocking while holding the state, to simulate a slow command
+                    std::thread::sleep(Duration::from_secs_f64(seconds));
+                    Ok(RedisType::String { value: "OK".to_owned() })
+                }
+                "OBJECT" => {
+                    args.arity(&command, 3)?;
+                    let key = args.bytes()?;
+
+                    let idle = state.last_access.get(&key).map(|at| at.elapsed().as_secs()).unwrap_or_default();
+                    match state.keystore.get(&key) {
+                        Some(value) => Ok(RedisType::String {
+                            value: format!(
+                                "Value at:{:p} refcount:{} encoding:{} serializedlength:{} lru:0 lru_seconds_idle:{idle}",
+                                value.as_ptr(),
+                                refcount(value),
+                                value.encoding(),
+                                value.len(),
+                            ),
+                        }),
+                        None => Err(ServerError::Err(String::from("no such key"))),
+                    }
+                }
+                "SET-ACTIVE-EXPIRE" => {
+                    args.arity(&command, 3)?;
+                    state.active_expire_disabled = args.integer()? == 0;
+                    Ok(RedisType::String { value: "OK".to_owned() })
+                }
+                "HOTKEYS" | "BIGKEYS" => {
+                    if args.remaining() > 1 {
+                        return Err(ServerError::WrongArity(command));
+                    }
+                    let count = match args.is_empty() {
+                        true => 10,
+                        false => args.parse::<usize>(ServerError::NotAnInteger)?,
+                    };
+                    if state.config.hotkeys_sample_rate == 0 {
+                        return Err(ServerError::Err(String::from("Key sampling is disabled, set hotkeys-sample-rate to enable it")));
+                    }
+
+                    let now = Instant::now();
+                    let value = if subcommand.eq_ignore_ascii_case("HOTKEYS") {
+                        state.hotkeys.hottest(count, now).into_iter()
+                            .map(|(key, accesses)| (RedisType::from(key), RedisType::from(accesses as i64)))
+                            .collect()
+                    } else {
+                        state.hotkeys.biggest(count, &state.keystore, now).into_iter()
+                            .map(|(key, size)| (RedisType::from(key), RedisType::from(size as i64)))
+                            .collect()
+                    };
+                    Ok(RedisType::Map { value })
+                }
+                "JMAP" | "CHANGE-REPL-ID" | "QUICKLIST-PACKED-THRESHOLD" | "SET-SKIP-CHECKSUM-VALIDATION" => {
+                    Ok(RedisType::String { value: "OK".to_owned() })
+                }
+                _ => Err(ServerError::UnknownSubcommand(subcommand)),
+            }
+        })
+    });
+
+    m.insert("HELP", Command {
+        summary: "Show the help for a command",
+        group: "server",
+        since: env!("CARGO_PKG_VERSION"),
+        arity: 2,
+        flags: &["loading", "stale", "fast"],
+        keys: KeySpec::None,
+        help: String::from("\
+HELP command
+
+Not a Redis command. Returns how the command is used and what it does, one line per reply, or its
+summary if there's nothing more to say. Commands with subcommands also answer <command> HELP with
+a list of them, as they do in Redis.
+        "),
+        f: Box::new(|_state, _client, args| {
+            let name = ArgParser::new(args).string()?.to_ascii_uppercase();
+            let command = match COMMANDS.get(name.as_str()) {
+                Some(command) => command,
+                None => return Err(ServerError::Err(String::from("Invalid command specified"))),
+            };
+
+            let help = command.help.trim();
+            let lines = if help.is_empty() { command.summary } else { help }
+                .lines()
+                .map(|line| RedisType::from(line.trim_end().to_owned()))
+                .collect::<Vec<_>>();
+            Ok(RedisType::from(lines))
+        })
+    });
+
+    m.insert("INFO", Command {
+        summary: "Get information and statistics about the server",
+        group: "server",
+        since: "1.0.0",
+        arity: -1,
+        flags: &["loading", "stale"],
+        keys: KeySpec::None,
+        help: String::from("\
+INFO [section [section ...]]
+
+Sections are server, clients, memory, stats, commandstats, latencystats, hotkeys, and keyspace.
+Without a section (or with default) everything but commandstats, latencystats and hotkeys is included, all includes everything.
+        "),
+        f: Box::new(|state, _client, args| {
+            let sections = ArgParser::new(args).rest();
+            Ok(RedisType::from(info::render(state, &sections)))
+        })
+    });
+
+    m.insert("LATENCY", Command {
+        summary: "A container for latency diagnostics commands",
+        group: "server",
+        since: "2.8.13",
+        arity: -2,
+        flags: &["admin", "noscript", "loading", "stale"],
+        keys: KeySpec::None,
+        help: String::from("\
+LATENCY LATEST
+LATENCY HISTORY event
+LATENCY RESET [event ...]
+
+Report latency spikes over latency-monitor-threshold milliseconds, by event.
+Events are command, fast-command, and expire-cycle.
+        "),
+        f: Box::new(|state, _client, args| {
+            let mut args = ArgParser::new(args);
+            let subcommand = args.string()?;
+            let command = format!("latency|{}", subcommand.to_ascii_lowercase());
+
+            match subcommand.to_ascii_uppercase().as_str() {
+                "LATEST" => {
+                    args.arity(&command, 2)?;
+                    Ok(RedisType::from(
+                        state
+                            .latency
+                            .events()
+                            .map(|(event, history)| {
+                                let (timestamp, latest) = history.samples.back().copied().unwrap_or_default();
+                                RedisType::from(vec![
+                                    RedisType::from(event.to_owned()),
+                                    RedisType::from(timestamp as i64),
+                                    RedisType::from(latest as i64),
+                                    RedisType::from(history.max as i64),
+                                ])
+                            })
+                            .collect::<Vec<_>>(),
+                    ))
+                }
+                "HISTORY" => {
+                    args.arity(&command, 3)?;
+                    let event = args.string()?;
+                    let samples = match state.latency.history(&event) {
+                        Some(history) => history
+                            .samples
+                            .iter()
+                            .map(|(timestamp, latency)| {
+                                RedisType::from(vec![
+                                    RedisType::from(*timestamp as i64),
+                                    RedisType::from(*latency as i64),
+                                ])
+                            })
+                            .collect(),
+                        None => vec![],
+                    };
+                    Ok(RedisType::Array { value: samples })
+                }
+                "RESET" => {
+                    let events = args.rest();
+                    Ok(RedisType::from(state.latency.reset(&events) as i64))
+                }
+                _ => Err(ServerError::UnknownSubcommand(subcommand)),
+            }
+        })
+    });
+
+    m.insert("MEMORY", Command {
+        summary: "A container for memory diagnostics commands",
+        group: "server",
+        since: "4.0.0",
+        arity: -2,
+        flags: &["readonly"],
+        keys: KeySpec::Custom(|argv| {
+            if argv.len() > 2 && matches!(&argv[1], RedisType::String { value } if value.eq_ignore_ascii_case("USAGE")) {
+                vec![2]
+            } else {
+                vec![]
+            }
+        }),
+        help: String::from("\
+MEMORY USAGE key [SAMPLES count]
+MEMORY STATS
+MEMORY DOCTOR
+MEMORY PURGE
+
+Estimate the memory used by a key or by the server as a whole, or ask the allocator to return unused memory.
+Estimates are based on how Redis itself lays out data, so they are comparable with a real server.
+        "),
+        f: Box::new(|state, _client, args| {
+            let mut args = ArgParser::new(args);
+            let subcommand = args.string()?;
+            let command = format!("memory|{}", subcommand.to_ascii_lowercase());
+
+            match subcommand.to_ascii_uppercase().as_str() {
+                "USAGE" => {
+                    args.arity(&command, -3)?;
+                    let key = args.bytes()?;
+
+                    // Strings are measured exactly, SAMPLES only matters for aggregate types
+                    if args.keyword("SAMPLES") && args.integer()? < 0 {
+                        return Err(ServerError::Err(String::from("value is out of range, must be positive")));
+                    }
+                    args.finish()?;
+
+                    Ok(match state.keystore.get(&key) {
+                        Some(value) => RedisType::from(memory::key_usage(&key, value) as i64),
+                        None => RedisType::NullString,
+                    })
+                }
+                "STATS" => {
+                    args.arity(&command, 2)?;
+
+                    let keys = state.keystore.len();
+                    let dataset = memory::dataset_usage(state);
+                    let overhead = memory::keyspace_overhead(state);
+                    let clients = memory::clients_usage(state);
+                    let total = dataset + overhead + clients;
+                    let allocator = allocator::stats().unwrap_or_default();
+                    let allocated = memory::allocated(state, &allocator);
+                    let rss = memory::rss();
+
+                    let string = |value: &str| RedisType::from(String::from(value));
+                    let integer = |value: usize| RedisType::from(value as i64);
+
+                    Ok(RedisType::from(vec![
+                        (string("total.allocated"), integer(total)),
+                        (string("clients.normal"), integer(clients)),
+                        (string("overhead.hashtable.main"), integer(overhead)),
+                        (string("overhead.total"), integer(overhead + clients)),
+                        (string("keys.count"), integer(keys)),
+                        (string("keys.bytes-per-key"), integer(total.checked_div(keys).unwrap_or(0))),
+                        (string("dataset.bytes"), integer(dataset)),
+                        (string("dataset.percentage"), string(&format!("{:.2}", if total == 0 { 0.0 } else { dataset as f64 * 100.0 / total as f64 }))),
+                        (string("allocator.allocated"), integer(allocator.allocated)),
+                        (string("allocator.active"), integer(allocator.active)),
+                        (string("allocator.resident"), integer(allocator.resident)),
+                        (string("allocator-fragmentation.ratio"), string(&memory::ratio(allocator.active, allocator.allocated))),
+                        (string("allocator.rss-ratio"), string(&memory::ratio(allocator.resident, allocator.active))),
+                        (string("fragmentation"), string(&memory::ratio(rss, allocated))),
+                        (string("fragmentation.bytes"), RedisType::from(rss as i64 - allocated as i64)),
+                    ]))
+                }
+                "DOCTOR" => {
+                    args.arity(&command, 2)?;
+                    Ok(RedisType::from(memory::doctor(state)))
+                }
+                "PURGE" => {
+                    args.arity(&command, 2)?;
+                    allocator::purge()?;
+                    Ok(RedisType::String { value: "OK".to_owned() })
+                }
+                _ => Err(ServerError::UnknownSubcommand(subcommand)),
+            }
+        })
+    });
+
+    m.insert("BGSAVE", Command {
+        summary: "Asynchronously save the dataset to disk",
+        group: "server",
+        since: "1.0.0",
+        arity: -1,
+        flags: &["admin", "noscript", "no_async_loading"],
+        keys: KeySpec::None,
+        help: String::from("\
+BGSAVE [SCHEDULE]
+
+Write a snapshot of the dataset to dbfilename in dir in the background.
+With SCHEDULE, a save that is already running isn't an error, another one is started once it's done.
+        "),
+        f: Box::new(|state, _client, args| {
+            let mut args = ArgParser::new(args);
+            let schedule = args.keyword("SCHEDULE");
+            args.finish()?;
+
+            if schedule && state.saves.lock().unwrap().in_progress_since.is_some() {
+                state.saves.lock().unwrap().scheduled = true;
+                return Ok(RedisType::String { value: "Background saving scheduled".to_owned() });
+            }
+
+            match rdb::start_bgsave(state, PathBuf::from(&state.config.dbfilename)) {
+                Ok(()) => {
+                    tracing::info!("Background saving started");
+                    Ok(RedisType::String { value: "Background saving started".to_owned() })
+                }
+                Err(e) => Err(ServerError::Err(e)),
+            }
+        })
+    });
+
+    m.insert("LASTSAVE", Command {
+        summary: "Get the Unix timestamp of the last successful save to disk",
+        group: "server",
+        since: "1.0.0",
+        arity: 1,
+        flags: &["loading", "stale", "fast"],
+        keys: KeySpec::None,
+        help: String::from("\
+LASTSAVE
+
+Unix time of the last successful SAVE or BGSAVE (or server start, if there hasn't been one).
+        "),
+        f: Box::new(|state, _client, _args| {
+            let last_save = state.saves.lock().unwrap().last_save;
+            let seconds = last_save.duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or_default();
+            Ok(RedisType::from(seconds as i64))
+        })
+    });
+
+    m.insert("SAVE", Command {
+        summary: "Synchronously save the dataset to disk",
+        group: "server",
+        since: "1.0.0",
+        arity: 1,
+        flags: &["admin", "noscript", "no_async_loading", "no_multi"],
+        keys: KeySpec::None,
+        help: String::from("\
+SAVE
+
+Write a snapshot of the dataset to dbfilename in dir, blocking the server until it's done.
+        "),
+        f: Box::new(|state, _client, _args| {
+            if state.saves.lock().unwrap().in_progress_since.is_some() {
+                return Err(ServerError::Err(String::from("Background save already in progress")));
+            }
+
+            match rdb::save(state, Path::new(&state.config.dbfilename)) {
+                Ok(()) => {
+                    tracing::info!("DB saved on disk");
+                    Ok(RedisType::String { value: "OK".to_owned() })
+                }
+                Err(e) => {
+                    tracing::warn!("Failed saving the DB: {e}");
+                    Err(ServerError::Err(String::new()))
+                }
+            }
+        })
+    });
+
+    m.insert("SHUTDOWN", Command {
+        summary: "Synchronously save the dataset to disk and then shut down the server",
+        group: "server",
+        since: "1.0.0",
+        arity: -1,
+        flags: &["admin", "noscript", "loading", "stale", "no_multi", "allow_busy"],
+        keys: KeySpec::None,
+        help: String::from("\
+SHUTDOWN [NOSAVE|SAVE] [NOW] [FORCE] [ABORT]
+
+Stop accepting connections, send any queued replies and exit.
+A snapshot is saved first if SAVE is given, or if save points are configured and NOSAVE isn't.
+If saving fails the server keeps running, unless FORCE is given.
+NOW would skip waiting for replicas, there aren't any so it has no effect.
+        "),
+        f: Box::new(|state, client, args| {
+            let count = args.len();
+            let mut args = ArgParser::new(args);
+            let mut save = Exclusive::default();
+            let mut force = false;
+            let mut abort = false;
+
+            while let Some(option) = args.option() {
+                match option.as_str() {
+                    "SAVE" | "NOSAVE" => save.set(&option)?,
+                    "NOW" => {}
+                    "FORCE" => force = true,
+                    "ABORT" => abort = true,
+                    _ => return Err(ServerError::Syntax),
+                }
+            }
+
+            if abort {
+                // Shutdown doesn't wait for anything that could be aborted
+                if count > 1 {
+                    return Err(ServerError::Syntax);
+                }
+                return Err(ServerError::Err(String::from("No shutdown in progress.")));
+            }
+
+            tracing::warn!("[{}] User requested shutdown...", client.addr);
+
+            // Sentinels have no data to save
+            let save = if save.is_set() { save.is("SAVE") } else { !state.config.save.is_empty() };
+            if save && state.sentinel.is_none() {
+                tracing::info!("Saving the final RDB snapshot before exiting.");
+                match rdb::save(state, Path::new(&state.config.dbfilename)) {
+                    Ok(()) => tracing::info!("DB saved on disk"),
+                    Err(e) if force => tracing::warn!("Error trying to save the DB, exiting anyway (FORCE): {e}"),
+                    Err(e) => {
+                        tracing::warn!("Error trying to save the DB, can't exit: {e}");
+                        return Err(ServerError::Err(String::from("Errors trying to SHUTDOWN. Check logs.")));
+                    }
+                }
+            }
+
+            state.shutdown_save_handled = true;
+            state.shutdown.trigger();
+            Ok(RedisType::String { value: "OK".to_owned() })
+        })
+    });
+
+    m.insert("TIME", Command {
+        summary: "Returns the server time",
+        group: "server",
+        since: "2.6.0",
+        arity: 1,
+        flags: &["loading", "stale", "fast"],
+        keys: KeySpec::None,
+        help: String::from("\
+TIME
+
+Returns the current unix time as two strings: seconds and microseconds within the current second.
+        "),
+        f: Box::new(|_state, _client, _args| {
+            let now = match SystemTime::now().duration_since(UNIX_EPOCH) {
+                Ok(now) => now,
+                Err(_) => return Err(ServerError::Err(String::from("system clock is before the unix epoch"))),
+            };
+
+            Ok(RedisType::from(vec![
+                RedisType::from(now.as_secs().to_string()),
+                RedisType::from(now.subsec_micros().to_string()),
+            ]))
+        })
+    });
+}
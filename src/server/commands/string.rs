@@ -0,0 +1,738 @@
+use super::args::{ArgParser, Exclusive};
+use crate::server::{Command, KeySpec, ServerError, State};
+use crate::value::Value;
+use crate::RedisType;
+use std::collections::HashMap;
+use std::ops::RangeInclusive;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+#[rustfmt::skip]
+pub(super) fn register(m: &mut HashMap<&'static str, Command>) {
+    m.insert("APPEND", Command {
+        summary: "Append a value to a key",
+        group: "string",
+        since: "2.0.0",
+        arity: 3,
+        flags: &["write", "denyoom", "fast"],
+        keys: KeySpec::FIRST,
+        help: String::from("\
+APPEND key value
+
+Append value to the string stored at key. If key is not set, SET it now. 
+        "),
+        f: Box::new(|state, _client, args| {
+            let mut args = ArgParser::new(args);
+            let key = args.bytes()?;
+            let value = args.bytes()?;
+
+            let value = match string(state, &key)? {
+                Some(current) => [&current[..], &value[..]].concat(),
+                None => value,
+            };
+            let len = value.len();
+            state.keystore.insert(key, Value::from(value));
+
+            Ok(RedisType::Integer{ value: len as i64 })
+        })
+    });
+
+    m.insert("DECR", Command {
+        summary: "Decrement the integer value of a key by one",
+        group: "string",
+        since: "1.0.0",
+        arity: 2,
+        flags: &["write", "denyoom", "fast"],
+        keys: KeySpec::FIRST,
+        help: String::from("\
+DECR key
+
+Decrement the number stored at key by one.
+
+If the key does not exist, it is set to 0 before performing the operation. An error is returned if the key contains a value of the wrong type or contains a string that can not be represented as integer. This operation is limited to 64 bit signed integers. 
+        "),
+        f: Box::new(|state, _client, args| {
+            let mut args = ArgParser::new(args);
+            let key = args.bytes()?;
+
+            string(state, &key)?;
+            if let Some(current) = state.keystore.get_mut(&key) {
+                match parse_value::<i64>(current) {
+                    Some(value) => {
+                        *current = Value::integer(value - 1);
+                        Ok(RedisType::Integer{ value: value - 1 })
+                    },
+                    None => Err(ServerError::NotAnInteger),
+                }
+            } else {
+                state.keystore.insert(key.clone(), Value::integer(-1));
+                Ok(RedisType::Integer{ value: -1 })
+            }
+        })
+    });
+
+    m.insert("DECRBY", Command {
+        summary: "Decrement the integer value of a key by the given number",
+        group: "string",
+        since: "1.0.0",
+        arity: 3,
+        flags: &["write", "denyoom", "fast"],
+        keys: KeySpec::FIRST,
+        help: String::from("\
+DECRBY key decrement
+
+Decrement the number stored at key by decrement.
+
+If the key does not exist, it is set to 0 before performing the operation. An error is returned if the key contains a value of the wrong type or contains a string that can not be represented as integer. This operation is limited to 64 bit signed integers. 
+        "),
+        f: Box::new(|state, _client, args| {
+            let mut args = ArgParser::new(args);
+            let key = args.bytes()?;
+            let decrement = args.integer()?;
+
+            string(state, &key)?;
+            if let Some(current) = state.keystore.get_mut(&key) {
+                match parse_value::<i64>(current) {
+                    Some(value) => {
+                        *current = Value::integer(value - decrement);
+                        Ok(RedisType::Integer{ value: value - decrement })
+                    },
+                    None => Err(ServerError::NotAnInteger),
+                }
+            } else {
+                state.keystore.insert(key.clone(), Value::integer(0 - decrement));
+                Ok(RedisType::Integer{ value: 0 - decrement })
+            }
+        })
+    });
+
+    m.insert("GET", Command {
+        summary: "Get the value of a key",
+        group: "string",
+        since: "1.0.0",
+        arity: 2,
+        flags: &["readonly", "fast"],
+        keys: KeySpec::FIRST,
+        help: String::from(""),
+        f: Box::new(|state, _client, args| {
+            let mut args = ArgParser::new(args);
+            let key = args.bytes()?;
+
+            Ok(match string(state, &key)? {
+                Some(value) => RedisType::Bulk { value: value.clone() },
+                None => RedisType::NullString,
+            })
+        })
+    });
+
+    m.insert("GETDEL", Command {
+        summary: "Get the value of a key and delete the key",
+        group: "string",
+        since: "6.2.0",
+        arity: 2,
+        flags: &["write", "fast"],
+        keys: KeySpec::FIRST,
+        help: String::from("\
+GETDEL key
+
+Get the value of key and delete it. 
+        "),
+        f: Box::new(|state, _client, args| {
+            let mut args = ArgParser::new(args);
+            let key = args.bytes()?;
+
+            string(state, &key)?;
+            state.ttl.remove(&key);
+            state.last_access.remove(&key);
+            Ok(match state.keystore.remove(&key) {
+                Some(value) => RedisType::Bulk { value },
+                None => RedisType::NullString,
+            })
+        })
+    });
+
+    m.insert("GETEX", Command {
+        summary: "Get the value of a key and optionally set its expiration",
+        group: "string",
+        since: "6.2.0",
+        arity: -2,
+        flags: &["write", "fast"],
+        keys: KeySpec::FIRST,
+        help: String::from("\
+GETEX key [EX seconds | PX milliseconds | EXAT unix-time-seconds | PXAT unix-time-milliseconds | PERSIST]
+
+Get the value of key and set its expiration time. 
+        "),
+        f: Box::new(|state, _client, args| {
+            let mut args = ArgParser::new(args);
+            let key = args.bytes()?;
+
+            // Only one of EX, PX, EXAT, PXAT and PERSIST
+            let mut option = Exclusive::default();
+            let mut expiration = None;
+            while let Some(given) = args.option() {
+                option.set(&given)?;
+                match given.as_str() {
+                    "EX" | "PX" | "EXAT" | "PXAT" => expiration = Some(expire_time(&given, args.integer()?, "getex")?),
+                    "PERSIST" => {}
+                    _ => return Err(ServerError::Syntax),
+                }
+            }
+            let persist = option.is("PERSIST");
+
+            let value = match string(state, &key)? {
+                Some(value) => value.clone(),
+                None => return Ok(RedisType::NullString),
+            };
+
+            if let Some(expiration) = expiration {
+                tracing::debug!("Setting expiration for key {} to {:?}", key.escape_ascii(), expiration);
+                state.ttl.push(key.clone(), expiration);
+            } else if persist {
+                state.ttl.remove(&key);
+            }
+
+            Ok(RedisType::Bulk { value })
+        })
+    });
+
+    m.insert("GETRANGE", Command {
+        summary: "Get a substring of the string stored at a key",
+        group: "string",
+        since: "2.4.0",
+        arity: 4,
+        flags: &["readonly"],
+        keys: KeySpec::FIRST,
+        help: String::from("\
+GETRANGE key start end
+
+Get the bytes of the string stored at key from start to end, both included. Negative offsets
+count back from the end, so -1 is the last byte. Offsets past either end are clamped to the
+string, a key that doesn't exist is the empty string.
+        "),
+        f: Box::new(|state, _client, args| {
+            let mut args = ArgParser::new(args);
+            let key = args.bytes()?;
+            let start = args.integer()?;
+            let end = args.integer()?;
+
+            let value = match string(state, &key)? {
+                Some(value) => match byte_range(value.len(), start, end) {
+                    Some(range) => value.slice(range),
+                    None => Value::from(&b""[..]),
+                },
+                None => Value::from(&b""[..]),
+            };
+            Ok(RedisType::Bulk { value })
+        })
+    });
+
+    m.insert("GETSET", Command {
+        summary: "Set the string value of a key and return its old value",
+        group: "string",
+        since: "1.0.0",
+        arity: 3,
+        flags: &["write", "denyoom", "fast"],
+        keys: KeySpec::FIRST,
+        help: String::from("\
+GETSET key value
+
+Set key to hold the string value and return its old value.
+        "),
+        f: Box::new(|state, _client, args| {
+            let mut args = ArgParser::new(args);
+            let key = args.bytes()?;
+            let value = args.bytes()?;
+
+            string(state, &key)?;
+            state.ttl.remove(&key);
+            Ok(match state.keystore.insert(key.clone(), Value::from(value)) {
+                Some(old_value) => RedisType::Bulk { value: old_value },
+                None => RedisType::NullString,
+            })
+        })
+    });
+
+    m.insert("INCR", Command {
+        summary: "Increment the integer value of a key by one",
+        group: "string",
+        since: "1.0.0",
+        arity: 2,
+        flags: &["write", "denyoom", "fast"],
+        keys: KeySpec::FIRST,
+        help: String::from("\
+INCR key
+
+Increment the number stored at key by one.
+
+If the key does not exist, it is set to 0 before performing the operation. An error is returned if the key contains a value of the wrong type or contains a string that can not be represented as integer. This operation is limited to 64 bit signed integers. 
+        "),
+        f: Box::new(|state, _client, args| {
+            let mut args = ArgParser::new(args);
+            let key = args.bytes()?;
+
+            string(state, &key)?;
+            if let Some(current) = state.keystore.get_mut(&key) {
+                match parse_value::<i64>(current) {
+                    Some(value) => {
+                        *current = Value::integer(value + 1);
+                        Ok(RedisType::Integer{ value: value + 1 })
+                    },
+                    None => Err(ServerError::NotAnInteger),
+                }
+            } else {
+                state.keystore.insert(key.clone(), Value::integer(1));
+                Ok(RedisType::Integer{ value: 1 })
+            }
+        })
+    });
+
+    m.insert("INCRBY", Command {
+        summary: "Increment the integer value of a key by the given amount",
+        group: "string",
+        since: "1.0.0",
+        arity: 3,
+        flags: &["write", "denyoom", "fast"],
+        keys: KeySpec::FIRST,
+        help: String::from("\
+INCRBY key increment
+
+Increment the number stored at key by increment.
+"),
+        f: Box::new(|state, _client, args| {
+            let mut args = ArgParser::new(args);
+            let key = args.bytes()?;
+            let increment = args.integer()?;
+
+            string(state, &key)?;
+            if let Some(current) = state.keystore.get_mut(&key) {
+                match parse_value::<i64>(current) {
+                    Some(value) => {
+                        *current = Value::integer(value + increment);
+                        Ok(RedisType::Integer{ value: value + increment })
+                    },
+                    None => Err(ServerError::NotAnInteger),
+                }
+            } else {
+                state.keystore.insert(key.clone(), Value::integer(increment));
+                Ok(RedisType::Integer{ value: increment })
+            }
+        })
+    });
+
+    m.insert("INCRBYFLOAT", Command {
+        summary: "Increment the float value of a key by the given amount",
+        group: "string",
+        since: "2.6.0",
+        arity: 3,
+        flags: &["write", "denyoom", "fast"],
+        keys: KeySpec::FIRST,
+        help: String::from("\
+INCRBYFLOAT key increment
+
+Increment the string representing a floating point number stored at key by the specified increment. 
+        "),
+        f: Box::new(|state, _client, args| {
+            let mut args = ArgParser::new(args);
+            let key = args.bytes()?;
+            let increment = args.float()?;
+
+            string(state, &key)?;
+            if let Some(current) = state.keystore.get_mut(&key) {
+                match parse_value::<f64>(current) {
+                    Some(value) => {
+                        *current = Value::from((value + increment).to_string());
+                        Ok(RedisType::String{ value: (value + increment).to_string() })
+                    },
+                    None => Err(ServerError::NotAFloat),
+                }
+            } else {
+                state.keystore.insert(key.clone(), Value::from(increment.to_string()));
+                Ok(RedisType::String{ value: increment.to_string() })
+            }
+        })
+    });
+
+    m.insert("MGET", Command {
+        summary: "Get the values of all the given keys",
+        group: "string",
+        since: "1.0.0",
+        arity: -2,
+        flags: &["readonly", "fast"],
+        keys: KeySpec::Range { first: 1, last: -1, step: 1 },
+        help: String::from("\
+MGET key [key ...]
+
+Get the values of all the given keys.
+
+For every key that does not hold a string value or does not exist, the special value nil is returned.
+        "),
+        f: Box::new(|state, _client, args| {
+            let mut values = Vec::new();
+
+            for key in ArgParser::new(args).rest_bytes() {
+                match string(state, &key) {
+                    Ok(Some(value)) => values.push(RedisType::Bulk { value: value.clone() }),
+                    _ => values.push(RedisType::NullString),
+                }
+            }
+
+            Ok(RedisType::Array { value: values })
+        })
+    });
+
+    m.insert("MSET", Command {
+        summary: "Set multiple keys to multiple values",
+        group: "string",
+        since: "1.0.1",
+        arity: -3,
+        flags: &["write", "denyoom"],
+        keys: KeySpec::Range { first: 1, last: -1, step: 2 },
+        help: String::from("\
+MSET key value [key value ...]
+
+Set multiple keys to multiple values.
+        "),
+        f: Box::new(|state, _client, args| {
+            if args.len() % 2 != 0 {
+                return Err(ServerError::WrongArity(String::from("mset")));
+            }
+
+            let mut args = ArgParser::new(args);
+            while !args.is_empty() {
+                let key = args.bytes()?;
+                let value = args.bytes()?;
+                state.ttl.remove(&key);
+                state.keystore.insert(key, Value::from(value));
+            }
+
+            Ok(RedisType::String { value: "OK".to_owned() })
+        })
+    });
+
+    m.insert("MSETNX", Command {
+        summary: "Set multiple keys to multiple values, only if none of the keys exist",
+        group: "string",
+        since: "1.0.1",
+        arity: -3,
+        flags: &["write", "denyoom"],
+        keys: KeySpec::Range { first: 1, last: -1, step: 2 },
+        help: String::from("\
+MSETNX key value [key value ...]
+
+Set multiple keys to multiple values, only if none of the keys exist.
+        "),
+        f: Box::new(|state, _client, args| {
+            if args.len() % 2 != 0 {
+                return Err(ServerError::WrongArity(String::from("msetnx")));
+            }
+
+            let mut pairs = Vec::new();
+            let mut args = ArgParser::new(args);
+            while !args.is_empty() {
+                pairs.push((args.bytes()?, args.bytes()?));
+            }
+
+            if pairs.iter().any(|(key, _)| state.keystore.contains_key(key)) {
+                return Ok(RedisType::Integer { value: 0 });
+            }
+
+            for (key, value) in pairs {
+                state.keystore.insert(key, Value::from(value));
+            }
+
+            Ok(RedisType::Integer { value: 1 })
+        })
+    });
+
+    m.insert("PSETEX", Command {
+        summary: "Set the value and expiration in milliseconds of a key",
+        group: "string",
+        since: "2.6.0",
+        arity: 4,
+        flags: &["write", "denyoom"],
+        keys: KeySpec::FIRST,
+        help: String::from("\
+PSETEX key milliseconds value
+
+Set the value and expiration in milliseconds of a key.
+        "),
+        f: Box::new(|state, _client, args| {
+            let mut args = ArgParser::new(args);
+            let key = args.bytes()?;
+            let expiration = expire_time("PX", args.integer()?, "psetex")?;
+            let value = args.bytes()?;
+
+            state.ttl.push(key.clone(), expiration);
+            state.keystore.insert(key, Value::from(value));
+
+            Ok(RedisType::String { value: "OK".to_owned() })
+        })
+    });
+
+    m.insert("SET", Command {
+        summary: "Set the string value of a key",
+        group: "string",
+        since: "1.0.0",
+        arity: -3,
+        flags: &["write", "denyoom"],
+        keys: KeySpec::FIRST,
+        help: String::from("\
+SET key value [NX | XX] [GET] [EX seconds | PX milliseconds | EXAT unix-time-seconds | PXAT unix-time-milliseconds | KEEPTTL]
+
+Sets key to a given value.
+
+NX|XX - only set if the key does not / does already exist.
+EX|PX|EXAT|PXAT - key expires after seconds/milliseconds or at a Unix timestamp in seconds/milliseconds
+KEEPTTL - retain the previously set TTL
+GET - return the previous value, returns NIL and doesn't return if the key wasn't set
+
+Returns OK if SET succeeded, nil if SET was not performed for NX|XX or because of GET, the old value if GET was specified. 
+        "),
+        f: Box::new(|state, _client, args| {
+            let mut args = ArgParser::new(args);
+            let key = args.bytes()?;
+            let value = args.bytes()?;
+
+            let mut condition = Exclusive::default();
+            let mut expiry = Exclusive::default();
+            let mut expiration = None;
+            let mut get = false;
+
+            while let Some(option) = args.option() {
+                match option.as_str() {
+                    "NX" | "XX" => condition.set(&option)?,
+                    "KEEPTTL" => expiry.set(&option)?,
+                    "EX" | "PX" | "EXAT" | "PXAT" => {
+                        expiry.set(&option)?;
+                        expiration = Some(expire_time(&option, args.integer()?, "set")?);
+                    }
+                    "GET" => get = true,
+                    _ => return Err(ServerError::Syntax),
+                }
+            }
+            let nx = condition.is("NX");
+            let xx = condition.is("XX");
+            let keepttl = expiry.is("KEEPTTL");
+            if get {
+                string(state, &key)?;
+            }
+
+            if nx && state.keystore.contains_key(&key) {
+                return Ok(RedisType::NullString);
+            }
+
+            if xx && !state.keystore.contains_key(&key) {
+                return Ok(RedisType::NullString);
+            }
+
+            if let Some(expiration) = expiration {
+                tracing::debug!("Setting expiration for key {} to {:?}", key.escape_ascii(), expiration);
+                state.ttl.push(key.clone(), expiration);
+            } else if keepttl {
+                // do nothing
+            } else {
+                state.ttl.remove(&key);
+            }
+
+            let result = if get {
+                Ok(match string(state, &key)? {
+                    Some(value) => RedisType::Bulk { value: value.clone() },
+                    None => RedisType::NullString,
+                })
+            } else {
+                Ok(RedisType::String { value: "OK".to_owned() })
+            };
+
+            state.keystore.insert(key, Value::from(value));
+            result
+        })
+    });
+
+    m.insert("SETEX", Command {
+        summary: "Set the value and expiration of a key",
+        group: "string",
+        since: "2.0.0",
+        arity: 4,
+        flags: &["write", "denyoom"],
+        keys: KeySpec::FIRST,
+        help: String::from("\
+SETEX key seconds value
+
+Set the value and expiration of a key.
+        "),
+        f: Box::new(|state, _client, args| {
+            let mut args = ArgParser::new(args);
+            let key = args.bytes()?;
+            let expiration = expire_time("EX", args.integer()?, "setex")?;
+            let value = args.bytes()?;
+
+            state.ttl.push(key.clone(), expiration);
+            state.keystore.insert(key, Value::from(value));
+
+            Ok(RedisType::String { value: "OK".to_owned() })
+        })
+    });
+
+    m.insert("SETNX", Command {
+        summary: "Set the value of a key, only if the key does not exist",
+        group: "string",
+        since: "1.0.0",
+        arity: 3,
+        flags: &["write", "denyoom", "fast"],
+        keys: KeySpec::FIRST,
+        help: String::from("\
+SETNX key value
+
+Set the value of a key, only if the key does not exist.
+        "),
+        f: Box::new(|state, _client, args| {
+            let mut args = ArgParser::new(args);
+            let key = args.bytes()?;
+            let value = args.bytes()?;
+
+            if let im::hashmap::Entry::Vacant(entry) = state.keystore.entry(key) {
+                entry.insert(Value::from(value));
+                Ok(RedisType::Integer { value: 1 })
+            } else {
+                Ok(RedisType::Integer { value: 0 })
+            }
+        })
+    });
+
+    m.insert("SETRANGE", Command {
+        summary: "Overwrite part of a string at key starting at the specified offset",
+        group: "string",
+        since: "2.2.0",
+        arity: 4,
+        flags: &["write", "denyoom"],
+        keys: KeySpec::FIRST,
+        help: String::from("\
+SETRANGE key offset value
+
+Overwrite the bytes of the string stored at key starting at offset with value, padding it with
+zero bytes if offset is past its end. A key that doesn't exist is treated as the empty string.
+Returns the length of the string afterwards.
+        "),
+        f: Box::new(|state, _client, args| {
+            let mut args = ArgParser::new(args);
+            let key = args.bytes()?;
+            let offset = args.integer()?;
+            let value = args.bytes()?;
+
+            if offset < 0 {
+                return Err(ServerError::Err(String::from("offset is out of range")));
+            }
+            let offset = offset as usize;
+
+            let current = string(state, &key)?;
+            // Nothing to write doesn't create the key (or pad it)
+            if value.is_empty() {
+                return Ok(RedisType::from(current.map_or(0, |current| current.len()) as i64));
+            }
+            if offset.saturating_add(value.len()) > MAX_STRING_LENGTH {
+                return Err(ServerError::Err(String::from("string exceeds maximum allowed size (proto-max-bulk-len)")));
+            }
+
+            let mut current = current.map(|current| current.to_vec()).unwrap_or_default();
+            if current.len() < offset + value.len() {
+                current.resize(offset + value.len(), 0);
+            }
+            current[offset..offset + value.len()].copy_from_slice(&value);
+
+            let len = current.len();
+            state.keystore.insert(key, Value::from(current));
+
+            Ok(RedisType::from(len as i64))
+        })
+    });
+
+    m.insert("STRLEN", Command {
+        summary: "Get the length of the value stored in a key",
+        group: "string",
+        since: "2.2.0",
+        arity: 2,
+        flags: &["readonly", "fast"],
+        keys: KeySpec::FIRST,
+        help: String::from("\
+STRLEN key
+
+Get the length of the value stored in a key.
+        "),
+        f: Box::new(|state, _client, args| {
+            let mut args = ArgParser::new(args);
+            let key = args.bytes()?;
+
+            let value = match string(state, &key)? {
+                Some(value) => value,
+                None => return Ok(RedisType::Integer { value: 0 }),
+            };
+
+            Ok(RedisType::Integer { value: value.len() as i64 })
+        })
+    });
+}
+
+// The value at key if it's a string, WRONGTYPE if it's a JSON document
+fn string<'a>(state: &'a State, key: &[u8]) -> Result<Option<&'a Value>, ServerError> {
+    match state.keystore.get(key) {
+        Some(value) if value.is_json() => Err(ServerError::WrongType),
+        value => Ok(value),
+    }
+}
+
+// The largest string SETRANGE will make, as in Redis
+const MAX_STRING_LENGTH: usize = 512 * 1024 * 1024;
+
+// The bytes GETRANGE returns from a string of len bytes, with start and end included and
+// negative offsets counting back from the end, or None if that's nothing
+fn byte_range(len: usize, start: i64, end: i64) -> Option<RangeInclusive<usize>> {
+    let len = len as i64;
+    let start = if start < 0 { len + start } else { start }.max(0);
+    let end = if end < 0 { len + end } else { end }.min(len - 1);
+
+    if start > end {
+        None
+    } else {
+        Some(start as usize..=end as usize)
+    }
+}
+
+// Stored values that hold a number, as used by INCR and friends
+fn parse_value<T: std::str::FromStr>(value: &[u8]) -> Option<T> {
+    std::str::from_utf8(value).ok()?.parse().ok()
+}
+
+// When a key given value for option (EX, PX, EXAT or PXAT) expires
+// Times that aren't positive, or are too far away to represent, are refused as they are by Redis.
+fn expire_time(option: &str, value: i64, command: &str) -> Result<SystemTime, ServerError> {
+    let invalid = || ServerError::Err(format!("invalid expire time in '{command}' command"));
+    if value <= 0 {
+        return Err(invalid());
+    }
+
+    let value = value as u64;
+    match option {
+        "EX" => SystemTime::now().checked_add(Duration::from_secs(value)),
+        "PX" => SystemTime::now().checked_add(Duration::from_millis(value)),
+        "EXAT" => UNIX_EPOCH.checked_add(Duration::from_secs(value)),
+        _ => UNIX_EPOCH.checked_add(Duration::from_millis(value)),
+    }
+    .ok_or_else(invalid)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::byte_range;
+
+    #[test]
+    fn test_byte_range() {
+        assert_eq!(byte_range(5, 0, -1), Some(0..=4));
+        assert_eq!(byte_range(5, 1, 2), Some(1..=2));
+        assert_eq!(byte_range(5, -3, -2), Some(2..=3));
+        assert_eq!(byte_range(5, -100, 100), Some(0..=4));
+        assert_eq!(byte_range(5, 3, 1), None);
+        assert_eq!(byte_range(5, 5, 10), None);
+        assert_eq!(byte_range(5, -1, -3), None);
+        assert_eq!(byte_range(0, 0, -1), None);
+    }
+}
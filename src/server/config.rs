@@ -0,0 +1,1450 @@
+use crate::server::logging;
+use crate::split_args;
+use std::env;
+use std::fmt::Display;
+use std::fs;
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use tracing_subscriber::filter::LevelFilter;
+
+// Server configuration, stored in the shared state so it can be changed at runtime
+#[derive(Clone, Debug)]
+pub struct Config {
+    pub bind: Vec<String>,
+    pub port: u16,
+    pub protected_mode: bool,
+    pub requirepass: Option<String>,
+    pub client_output_buffer_limit: BufferLimits,
+    pub client_query_buffer_limit: usize,
+    // The longest argument a client can send
+    pub proto_max_bulk_len: usize,
+    pub maxmemory: usize,
+    pub maxmemory_policy: MaxmemoryPolicy,
+    pub timeout: u64,
+    pub tcp_keepalive: u64,
+    // Seconds a shutdown waits for clients to get their last replies before closing on them
+    pub shutdown_drain_timeout: u64,
+    // Runtime worker threads that connections (and their socket reads and writes) are spread
+    // across, 0 for one per CPU
+    pub io_threads: usize,
+    // Read and write sockets with io_uring, only available when built with the io-uring feature
+    pub io_uring: bool,
+    pub latency_monitor_threshold: u64,
+    // Key accesses are sampled 1 in this many times to find hot and big keys, 0 disables
+    pub hotkeys_sample_rate: u64,
+    // Commands slower than this many microseconds are logged as warnings, negative disables
+    pub slow_command_threshold: i64,
+    pub save: Vec<SaveRule>,
+    pub dir: PathBuf,
+    pub dbfilename: String,
+    pub appendonly: bool,
+    pub appendfilename: String,
+    pub appendfsync: AppendFsync,
+    // Whether an append only file that ends part way through a command (or in bytes that aren't
+    // one) is truncated to load it on startup, rather than refusing to start
+    pub aof_load_truncated: bool,
+    // Whether keys of types that can't be stored (hashes, lists and so on) are left out when
+    // loading a snapshot, rather than refusing to load it (and then saving over them)
+    pub rdb_skip_unsupported: bool,
+    // The master to replicate from, set by REPLICAOF at runtime
+    pub replicaof: Option<(String, u16)>,
+    pub masterauth: Option<String>,
+    // Whether normal clients are refused writes while we are a replica
+    pub replica_read_only: bool,
+    pub cluster_enabled: bool,
+    // Where the cluster's nodes and slots are saved, in dir
+    pub cluster_config_file: String,
+    // The sentinel directives (such as sentinel monitor), only used in sentinel mode
+    pub sentinel: Vec<Vec<String>>,
+    pub loglevel: LogLevel,
+    pub logfile: Option<PathBuf>,
+    pub pidfile: Option<PathBuf>,
+    // Where the config was loaded from, used by CONFIG REWRITE
+    pub config_file: Option<PathBuf>,
+    // What the file said when it was last read, so that a reload only acts on what's changed in it
+    pub file_values: Vec<(&'static str, String)>,
+    // Set by environment variables or flags, which take precedence over the file even once it's
+    // reloaded
+    pub overridden: Vec<&'static str>,
+}
+
+// Output buffer limits for one class of clients, a limit of 0 disables that check
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub struct BufferLimit {
+    pub hard: usize,
+    pub soft: usize,
+    pub soft_seconds: u64,
+}
+
+impl FromStr for BufferLimit {
+    type Err = String;
+
+    // Parsed from <hard> <soft> <soft seconds>, as in redis.conf
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parts = s.split_ascii_whitespace().collect::<Vec<_>>();
+        if parts.len() != 3 {
+            return Err(format!("Invalid buffer limit: {s}"));
+        }
+
+        Ok(BufferLimit {
+            hard: parse_memory(parts[0])
+                .ok_or_else(|| format!("Invalid hard limit: {}", parts[0]))?,
+            soft: parse_memory(parts[1])
+                .ok_or_else(|| format!("Invalid soft limit: {}", parts[1]))?,
+            soft_seconds: parts[2]
+                .parse()
+                .map_err(|_| format!("Invalid soft seconds: {}", parts[2]))?,
+        })
+    }
+}
+
+impl Display for BufferLimit {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} {} {}", self.hard, self.soft, self.soft_seconds)
+    }
+}
+
+// Output buffer limits for each class of clients, as client-output-buffer-limit sets them
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct BufferLimits {
+    pub normal: BufferLimit,
+    // For the writes streamed to replicas once they have the snapshot
+    pub replica: BufferLimit,
+    // For connections subscribed to any channels or patterns
+    pub pubsub: BufferLimit,
+}
+
+// Redis' defaults
+impl Default for BufferLimits {
+    fn default() -> Self {
+        BufferLimits {
+            normal: BufferLimit::default(),
+            replica: BufferLimit {
+                hard: 256 * 1024 * 1024,
+                soft: 64 * 1024 * 1024,
+                soft_seconds: 60,
+            },
+            pubsub: BufferLimit {
+                hard: 32 * 1024 * 1024,
+                soft: 8 * 1024 * 1024,
+                soft_seconds: 60,
+            },
+        }
+    }
+}
+
+impl BufferLimits {
+    // The limit for a class as redis.conf names it, where slave is the old name for replica
+    fn class_mut(&mut self, class: &str) -> Option<&mut BufferLimit> {
+        match class.to_ascii_lowercase().as_str() {
+            "normal" => Some(&mut self.normal),
+            "replica" | "slave" => Some(&mut self.replica),
+            "pubsub" => Some(&mut self.pubsub),
+            _ => None,
+        }
+    }
+}
+
+impl Display for BufferLimits {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "normal {} replica {} pubsub {}",
+            self.normal, self.replica, self.pubsub
+        )
+    }
+}
+
+// What to do when maxmemory is reached
+// Keys can't be evicted yet, so refusing commands that could use more memory is the only policy.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub enum MaxmemoryPolicy {
+    #[default]
+    NoEviction,
+}
+
+const MAXMEMORY_POLICIES: [(&str, MaxmemoryPolicy); 1] =
+    [("noeviction", MaxmemoryPolicy::NoEviction)];
+
+impl FromStr for MaxmemoryPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        MAXMEMORY_POLICIES
+            .iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case(s))
+            .map(|(_, policy)| *policy)
+            .ok_or_else(|| String::from("argument(s) must be one of the following: noeviction"))
+    }
+}
+
+impl Display for MaxmemoryPolicy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let (name, _) = MAXMEMORY_POLICIES
+            .iter()
+            .find(|(_, policy)| policy == self)
+            .unwrap();
+        write!(f, "{name}")
+    }
+}
+
+// How often the append only file is flushed to disk
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub enum AppendFsync {
+    Always,
+    #[default]
+    EverySec,
+    No,
+}
+
+impl FromStr for AppendFsync {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "always" => Ok(AppendFsync::Always),
+            "everysec" => Ok(AppendFsync::EverySec),
+            "no" => Ok(AppendFsync::No),
+            _ => Err(String::from(
+                "argument(s) must be one of the following: always, everysec, no",
+            )),
+        }
+    }
+}
+
+impl Display for AppendFsync {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            AppendFsync::Always => "always",
+            AppendFsync::EverySec => "everysec",
+            AppendFsync::No => "no",
+        };
+        write!(f, "{name}")
+    }
+}
+
+// Redis log levels, each mapped onto the closest tracing level
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub enum LogLevel {
+    Debug,
+    Verbose,
+    #[default]
+    Notice,
+    Warning,
+    Nothing,
+}
+
+const LOG_LEVELS: [(&str, LogLevel); 5] = [
+    ("debug", LogLevel::Debug),
+    ("verbose", LogLevel::Verbose),
+    ("notice", LogLevel::Notice),
+    ("warning", LogLevel::Warning),
+    ("nothing", LogLevel::Nothing),
+];
+
+impl LogLevel {
+    pub fn filter(&self) -> LevelFilter {
+        match self {
+            LogLevel::Debug => LevelFilter::TRACE,
+            LogLevel::Verbose => LevelFilter::DEBUG,
+            LogLevel::Notice => LevelFilter::INFO,
+            LogLevel::Warning => LevelFilter::WARN,
+            LogLevel::Nothing => LevelFilter::OFF,
+        }
+    }
+}
+
+impl FromStr for LogLevel {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        LOG_LEVELS
+            .iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case(s))
+            .map(|(_, level)| *level)
+            .ok_or_else(|| {
+                String::from("argument(s) must be one of the following: debug, verbose, notice, warning, nothing")
+            })
+    }
+}
+
+impl Display for LogLevel {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let (name, _) = LOG_LEVELS.iter().find(|(_, level)| level == self).unwrap();
+        write!(f, "{name}")
+    }
+}
+
+// Snapshot after this many seconds if at least this many changes were made
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct SaveRule {
+    pub seconds: u64,
+    pub changes: u64,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            bind: vec![String::from("0.0.0.0")],
+            port: 6379,
+            protected_mode: true,
+            requirepass: None,
+            client_output_buffer_limit: BufferLimits::default(),
+            client_query_buffer_limit: 1024 * 1024 * 1024,
+            proto_max_bulk_len: 512 * 1024 * 1024,
+            maxmemory: 0,
+            maxmemory_policy: MaxmemoryPolicy::default(),
+            timeout: 0,
+            tcp_keepalive: 300,
+            shutdown_drain_timeout: 10,
+            io_threads: 0,
+            io_uring: false,
+            latency_monitor_threshold: 0,
+            hotkeys_sample_rate: 0,
+            slow_command_threshold: 10000,
+            save: vec![
+                SaveRule {
+                    seconds: 3600,
+                    changes: 1,
+                },
+                SaveRule {
+                    seconds: 300,
+                    changes: 100,
+                },
+                SaveRule {
+                    seconds: 60,
+                    changes: 10000,
+                },
+            ],
+            dir: PathBuf::from("."),
+            dbfilename: String::from("dump.rdb"),
+            appendonly: false,
+            appendfilename: String::from("appendonly.aof"),
+            appendfsync: AppendFsync::default(),
+            aof_load_truncated: true,
+            rdb_skip_unsupported: false,
+            replicaof: None,
+            masterauth: None,
+            replica_read_only: true,
+            cluster_enabled: false,
+            cluster_config_file: String::from("nodes.conf"),
+            sentinel: Vec::new(),
+            loglevel: LogLevel::default(),
+            logfile: None,
+            pidfile: None,
+            config_file: None,
+            file_values: Vec::new(),
+            overridden: Vec::new(),
+        }
+    }
+}
+
+type Setter = fn(&mut Config, &str) -> Result<(), String>;
+
+// A single named configuration parameter, as seen by CONFIG GET/SET
+pub struct Parameter {
+    pub name: &'static str,
+    pub get: fn(&Config) -> String,
+    // None if the parameter can only be set at startup
+    pub set: Option<Setter>,
+}
+
+// What a reload picks up from the config file, other changes wait for a restart
+const RELOADABLE: &[&str] = &["loglevel", "save", "maxmemory", "requirepass"];
+
+// The directives a reload found changed in the config file
+#[derive(Debug, Default, PartialEq)]
+pub struct Reload {
+    pub applied: Vec<&'static str>,
+    pub deferred: Vec<&'static str>,
+    // With why the value was refused
+    pub rejected: Vec<(&'static str, String)>,
+}
+
+pub static PARAMETERS: &[Parameter] = &[
+    Parameter {
+        name: "bind",
+        get: |config| config.bind.join(" "),
+        set: None,
+    },
+    Parameter {
+        name: "port",
+        get: |config| config.port.to_string(),
+        set: None,
+    },
+    Parameter {
+        name: "protected-mode",
+        get: |config| yes_no(config.protected_mode),
+        set: Some(|config, value| {
+            config.protected_mode = parse_yes_no(value).ok_or("argument must be 'yes' or 'no'")?;
+            Ok(())
+        }),
+    },
+    Parameter {
+        name: "requirepass",
+        get: |config| config.requirepass.clone().unwrap_or_default(),
+        set: Some(|config, value| {
+            config.requirepass = if value.is_empty() {
+                None
+            } else {
+                Some(String::from(value))
+            };
+            Ok(())
+        }),
+    },
+    Parameter {
+        name: "client-output-buffer-limit",
+        get: |config| config.client_output_buffer_limit.to_string(),
+        set: Some(|config, value| {
+            // <class> <hard> <soft> <soft seconds>, for any number of classes, where the classes
+            // left out keep their limits
+            let parts = value.split_ascii_whitespace().collect::<Vec<_>>();
+            if parts.is_empty() || parts.len() % 4 != 0 {
+                return Err(String::from("wrong number of arguments"));
+            }
+
+            let mut limits = config.client_output_buffer_limit;
+            for chunk in parts.chunks(4) {
+                let limit = limits
+                    .class_mut(chunk[0])
+                    .ok_or_else(|| format!("unknown client class '{}'", chunk[0]))?;
+                *limit = chunk[1..].join(" ").parse()?;
+            }
+            config.client_output_buffer_limit = limits;
+            Ok(())
+        }),
+    },
+    Parameter {
+        name: "client-query-buffer-limit",
+        get: |config| config.client_query_buffer_limit.to_string(),
+        set: Some(|config, value| {
+            config.client_query_buffer_limit =
+                parse_memory(value).ok_or("argument must be a memory value")?;
+            Ok(())
+        }),
+    },
+    Parameter {
+        name: "proto-max-bulk-len",
+        get: |config| config.proto_max_bulk_len.to_string(),
+        set: Some(|config, value| {
+            config.proto_max_bulk_len =
+                parse_memory(value).ok_or("argument must be a memory value")?;
+            Ok(())
+        }),
+    },
+    Parameter {
+        name: "maxmemory",
+        get: |config| config.maxmemory.to_string(),
+        set: Some(|config, value| {
+            config.maxmemory = parse_memory(value).ok_or("argument must be a memory value")?;
+            Ok(())
+        }),
+    },
+    Parameter {
+        name: "maxmemory-policy",
+        get: |config| config.maxmemory_policy.to_string(),
+        set: Some(|config, value| {
+            config.maxmemory_policy = value.parse()?;
+            Ok(())
+        }),
+    },
+    Parameter {
+        name: "timeout",
+        get: |config| config.timeout.to_string(),
+        set: Some(|config, value| {
+            config.timeout = value
+                .parse()
+                .map_err(|_| "argument couldn't be parsed into an integer")?;
+            Ok(())
+        }),
+    },
+    Parameter {
+        name: "tcp-keepalive",
+        get: |config| config.tcp_keepalive.to_string(),
+        set: Some(|config, value| {
+            config.tcp_keepalive = value
+                .parse()
+                .map_err(|_| "argument couldn't be parsed into an integer")?;
+            Ok(())
+        }),
+    },
+    Parameter {
+        name: "shutdown-drain-timeout",
+        get: |config| config.shutdown_drain_timeout.to_string(),
+        set: Some(|config, value| {
+            config.shutdown_drain_timeout = value
+                .parse()
+                .map_err(|_| "argument couldn't be parsed into an integer")?;
+            Ok(())
+        }),
+    },
+    Parameter {
+        name: "io-threads",
+        get: |config| config.io_threads.to_string(),
+        set: None,
+    },
+    Parameter {
+        name: "io-uring",
+        get: |config| yes_no(config.io_uring),
+        set: None,
+    },
+    Parameter {
+        name: "latency-monitor-threshold",
+        get: |config| config.latency_monitor_threshold.to_string(),
+        set: Some(|config, value| {
+            config.latency_monitor_threshold = value
+                .parse()
+                .map_err(|_| "argument couldn't be parsed into an integer")?;
+            Ok(())
+        }),
+    },
+    Parameter {
+        name: "hotkeys-sample-rate",
+        get: |config| config.hotkeys_sample_rate.to_string(),
+        set: Some(|config, value| {
+            config.hotkeys_sample_rate = value
+                .parse()
+                .map_err(|_| "argument couldn't be parsed into an integer")?;
+            Ok(())
+        }),
+    },
+    Parameter {
+        name: "slow-command-threshold",
+        get: |config| config.slow_command_threshold.to_string(),
+        set: Some(|config, value| {
+            config.slow_command_threshold = value
+                .parse()
+                .map_err(|_| "argument couldn't be parsed into an integer")?;
+            Ok(())
+        }),
+    },
+    Parameter {
+        name: "save",
+        get: |config| {
+            config
+                .save
+                .iter()
+                .map(|rule| format!("{} {}", rule.seconds, rule.changes))
+                .collect::<Vec<_>>()
+                .join(" ")
+        },
+        set: Some(|config, value| {
+            let parts = value.split_ascii_whitespace().collect::<Vec<_>>();
+            if parts.len() % 2 != 0 {
+                return Err(String::from("Invalid save parameters"));
+            }
+
+            let mut save = Vec::new();
+            for chunk in parts.chunks(2) {
+                match (chunk[0].parse(), chunk[1].parse()) {
+                    (Ok(seconds), Ok(changes)) => save.push(SaveRule { seconds, changes }),
+                    _ => return Err(String::from("Invalid save parameters")),
+                }
+            }
+
+            config.save = save;
+            Ok(())
+        }),
+    },
+    Parameter {
+        name: "dir",
+        get: |config| config.dir.display().to_string(),
+        set: Some(|config, value| {
+            let dir = PathBuf::from(value);
+            if !dir.is_dir() {
+                return Err(format!("No such directory: {value}"));
+            }
+            config.dir = dir;
+            Ok(())
+        }),
+    },
+    Parameter {
+        name: "dbfilename",
+        get: |config| config.dbfilename.clone(),
+        set: Some(|config, value| {
+            // Snapshots always go in dir
+            if value.is_empty() || value.contains('/') || value.contains('\\') {
+                return Err(String::from("dbfilename can't be a path, just a filename"));
+            }
+            config.dbfilename = String::from(value);
+            Ok(())
+        }),
+    },
+    Parameter {
+        name: "appendonly",
+        get: |config| yes_no(config.appendonly),
+        set: Some(|config, value| {
+            config.appendonly = parse_yes_no(value).ok_or("argument must be 'yes' or 'no'")?;
+            Ok(())
+        }),
+    },
+    Parameter {
+        name: "appendfilename",
+        get: |config| config.appendfilename.clone(),
+        set: None,
+    },
+    Parameter {
+        name: "appendfsync",
+        get: |config| config.appendfsync.to_string(),
+        set: Some(|config, value| {
+            config.appendfsync = value.parse()?;
+            Ok(())
+        }),
+    },
+    Parameter {
+        name: "aof-load-truncated",
+        get: |config| yes_no(config.aof_load_truncated),
+        set: Some(|config, value| {
+            config.aof_load_truncated =
+                parse_yes_no(value).ok_or("argument must be 'yes' or 'no'")?;
+            Ok(())
+        }),
+    },
+    Parameter {
+        name: "rdb-skip-unsupported",
+        get: |config| yes_no(config.rdb_skip_unsupported),
+        set: Some(|config, value| {
+            config.rdb_skip_unsupported =
+                parse_yes_no(value).ok_or("argument must be 'yes' or 'no'")?;
+            Ok(())
+        }),
+    },
+    Parameter {
+        name: "replicaof",
+        get: |config| {
+            config
+                .replicaof
+                .as_ref()
+                .map(|(host, port)| format!("{host} {port}"))
+                .unwrap_or_default()
+        },
+        set: None,
+    },
+    Parameter {
+        name: "masterauth",
+        get: |config| config.masterauth.clone().unwrap_or_default(),
+        set: Some(|config, value| {
+            config.masterauth = if value.is_empty() {
+                None
+            } else {
+                Some(String::from(value))
+            };
+            Ok(())
+        }),
+    },
+    Parameter {
+        name: "replica-read-only",
+        get: |config| yes_no(config.replica_read_only),
+        set: Some(|config, value| {
+            config.replica_read_only =
+                parse_yes_no(value).ok_or("argument must be 'yes' or 'no'")?;
+            Ok(())
+        }),
+    },
+    Parameter {
+        name: "cluster-enabled",
+        get: |config| yes_no(config.cluster_enabled),
+        set: None,
+    },
+    Parameter {
+        name: "cluster-config-file",
+        get: |config| config.cluster_config_file.clone(),
+        set: None,
+    },
+    Parameter {
+        name: "loglevel",
+        get: |config| config.loglevel.to_string(),
+        set: Some(|config, value| {
+            config.loglevel = value.parse()?;
+            Ok(())
+        }),
+    },
+    Parameter {
+        name: "logfile",
+        get: |config| path_or_empty(&config.logfile),
+        set: None,
+    },
+    Parameter {
+        name: "pidfile",
+        get: |config| path_or_empty(&config.pidfile),
+        set: None,
+    },
+];
+
+// The parameters set in a redis.conf style file with their values, and its sentinel directives
+// Directives such as save that can be repeated are combined, otherwise the last one wins
+type Directives = (Vec<(&'static Parameter, String)>, Vec<Vec<String>>);
+
+fn directives(contents: &str) -> Result<Directives, String> {
+    let mut values: Vec<(&Parameter, Vec<String>)> = Vec::new();
+    let mut sentinel = Vec::new();
+
+    for (number, line) in contents.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let error =
+            |message: &str| format!("Bad directive at line {}: {line} ({message})", number + 1);
+
+        let mut args = split_args(line).map_err(|e| error(&e))?;
+        if args.len() < 2 {
+            return Err(error("wrong number of arguments"));
+        }
+
+        let name = args.remove(0);
+        if name.eq_ignore_ascii_case("sentinel") {
+            sentinel.push(args);
+            continue;
+        }
+
+        let parameter = match find_parameter(&name) {
+            Some(parameter) => parameter,
+            None => return Err(error("unknown directive")),
+        };
+
+        let repeatable = matches!(parameter.name, "save" | "client-output-buffer-limit");
+        let multiple = matches!(parameter.name, "bind" | "replicaof");
+        if !repeatable && !multiple && args.len() != 1 {
+            return Err(error("wrong number of arguments"));
+        }
+
+        match values.iter_mut().find(|(p, _)| p.name == parameter.name) {
+            Some((_, existing)) if repeatable => existing.extend(args),
+            Some((_, existing)) => *existing = args,
+            None => values.push((parameter, args)),
+        }
+    }
+
+    let values = values
+        .into_iter()
+        .map(|(parameter, args)| {
+            let value = args
+                .iter()
+                .filter(|arg| !arg.is_empty())
+                .cloned()
+                .collect::<Vec<_>>()
+                .join(" ");
+            (parameter, value)
+        })
+        .collect();
+
+    Ok((values, sentinel))
+}
+
+pub fn find_parameter(name: &str) -> Option<&'static Parameter> {
+    PARAMETERS
+        .iter()
+        .find(|p| p.name.eq_ignore_ascii_case(name))
+}
+
+impl Config {
+    // Load a redis.conf style file on top of the current values
+    pub fn load_file(&mut self, path: &Path) -> Result<(), String> {
+        let contents = fs::read_to_string(path)
+            .map_err(|e| format!("Unable to read config file {}: {e}", path.display()))?;
+
+        self.load(&contents)?;
+        self.config_file = Some(path.to_path_buf());
+        Ok(())
+    }
+
+    // Apply the directives in a redis.conf style file
+    pub fn load(&mut self, contents: &str) -> Result<(), String> {
+        let (values, sentinel) = directives(contents)?;
+        self.sentinel.extend(sentinel);
+        for (parameter, value) in values {
+            self.set_initial(parameter, &value)
+                .map_err(|e| format!("Invalid value for {}: {e}", parameter.name))?;
+            self.file_value(parameter.name, value);
+        }
+        Ok(())
+    }
+
+    // Set a parameter from an environment variable or flag, which a reload of the file leaves alone
+    pub fn set_override(
+        &mut self,
+        parameter: &'static Parameter,
+        value: &str,
+    ) -> Result<(), String> {
+        self.set_initial(parameter, value)?;
+        if !self.overridden.contains(&parameter.name) {
+            self.overridden.push(parameter.name);
+        }
+        Ok(())
+    }
+
+    fn file_value(&mut self, name: &'static str, value: String) {
+        match self
+            .file_values
+            .iter_mut()
+            .find(|(existing, _)| *existing == name)
+        {
+            Some((_, existing)) => *existing = value,
+            None => self.file_values.push((name, value)),
+        }
+    }
+
+    // Re-read the config file (on SIGHUP), applying the directives that have changed if they're
+    // safe to change while running and leaving the rest for the next restart
+    // Nothing is applied if the file can't be read or parsed.
+    pub fn reload(&mut self) -> Result<Reload, String> {
+        let path = match &self.config_file {
+            Some(path) => path,
+            None => return Err(String::from("The server is running without a config file")),
+        };
+        let contents = fs::read_to_string(path)
+            .map_err(|e| format!("Unable to read config file {}: {e}", path.display()))?;
+        self.reload_contents(&contents)
+    }
+
+    // Only directives whose value in the file has changed since it was last read are looked at, so
+    // that neither flags and environment variables nor CONFIG SET are undone by an unchanged line
+    fn reload_contents(&mut self, contents: &str) -> Result<Reload, String> {
+        let (values, _) = directives(contents)?;
+        let mut reload = Reload::default();
+        for (parameter, value) in values {
+            let previous = self
+                .file_values
+                .iter()
+                .find(|(name, _)| *name == parameter.name)
+                .map(|(_, previous)| previous);
+            if previous == Some(&value) || self.overridden.contains(&parameter.name) {
+                continue;
+            }
+
+            let mut changed = self.clone();
+            // Left as it was, so that it's rejected again until it's fixed
+            if let Err(e) = changed.set_initial(parameter, &value) {
+                reload.rejected.push((parameter.name, e));
+                continue;
+            }
+            self.file_value(parameter.name, value);
+            if (parameter.get)(&changed) == (parameter.get)(self) {
+                continue;
+            }
+
+            if RELOADABLE.contains(&parameter.name) {
+                changed.file_values = self.file_values.clone();
+                *self = changed;
+                reload.applied.push(parameter.name);
+            } else {
+                reload.deferred.push(parameter.name);
+            }
+        }
+        Ok(reload)
+    }
+
+    // Load overrides from environment variables named after each parameter
+    // For example, client-query-buffer-limit is read from REDIS_CLIENT_QUERY_BUFFER_LIMIT
+    pub fn apply_env(&mut self) -> Result<(), String> {
+        for parameter in PARAMETERS {
+            let var = format!(
+                "REDIS_{}",
+                parameter.name.to_ascii_uppercase().replace('-', "_")
+            );
+
+            if let Ok(value) = env::var(&var) {
+                self.set_override(parameter, &value)
+                    .map_err(|e| format!("Invalid {var}: {e}"))?;
+            }
+        }
+
+        Ok(())
+    }
+
+    // Write the current configuration back to the file it was loaded from
+    pub fn rewrite(&self) -> Result<(), String> {
+        let path = match &self.config_file {
+            Some(path) => path,
+            None => return Err(String::from("The server is running without a config file")),
+        };
+
+        let existing = match fs::read_to_string(path) {
+            Ok(existing) => existing,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => String::new(),
+            Err(e) => return Err(format!("Rewriting config file: {e}")),
+        };
+
+        // Write to a temporary file first so a failure can't leave a half written config
+        let tmp = path.with_extension("tmp");
+        fs::write(&tmp, self.rewrite_contents(&existing))
+            .and_then(|_| fs::rename(&tmp, path))
+            .map_err(|e| format!("Rewriting config file: {e}"))
+    }
+
+    // Update each directive in place (keeping comments and ordering), then append any
+    // parameters that aren't in the file yet but have been changed from their defaults
+    fn rewrite_contents(&self, existing: &str) -> String {
+        let defaults = Config::default();
+        let mut written = Vec::new();
+        let mut lines = Vec::new();
+        let mut sentinel_written = false;
+
+        for line in existing.lines() {
+            let trimmed = line.trim();
+            let parameter = match split_args(trimmed) {
+                Ok(args) if !trimmed.starts_with('#') && !args.is_empty() => {
+                    // The sentinel directives are all replaced at the first one
+                    if args[0].eq_ignore_ascii_case("sentinel") {
+                        if !std::mem::replace(&mut sentinel_written, true) {
+                            lines.extend(self.sentinel_lines());
+                        }
+                        continue;
+                    }
+                    find_parameter(&args[0])
+                }
+                _ => None,
+            };
+
+            match parameter {
+                // Repeated directives are all written at the first occurrence
+                Some(parameter) if written.contains(&parameter.name) => {}
+                Some(parameter) => {
+                    written.push(parameter.name);
+                    lines.extend(self.directive_lines(parameter));
+                }
+                None => lines.push(String::from(line)),
+            }
+        }
+
+        let mut appended = false;
+        for parameter in PARAMETERS {
+            let current = self.directive_lines(parameter);
+            if written.contains(&parameter.name) || current == defaults.directive_lines(parameter) {
+                continue;
+            }
+
+            if !appended {
+                lines.push(String::from("# Generated by CONFIG REWRITE"));
+                appended = true;
+            }
+            lines.extend(current);
+        }
+        if !sentinel_written && !self.sentinel.is_empty() {
+            if !appended {
+                lines.push(String::from("# Generated by CONFIG REWRITE"));
+            }
+            lines.extend(self.sentinel_lines());
+        }
+
+        let mut contents = lines.join("\n");
+        contents.push('\n');
+        contents
+    }
+
+    fn sentinel_lines(&self) -> Vec<String> {
+        self.sentinel
+            .iter()
+            .map(|args| {
+                let args = args.iter().map(|arg| quote(arg)).collect::<Vec<_>>();
+                format!("sentinel {}", args.join(" "))
+            })
+            .collect()
+    }
+
+    // The config file lines representing the current value of a parameter
+    fn directive_lines(&self, parameter: &Parameter) -> Vec<String> {
+        let value = (parameter.get)(self);
+
+        match parameter.name {
+            "bind" => vec![format!("bind {value}")],
+            // Not replicating anyone is the absence of the directive
+            "replicaof" if value.is_empty() => vec![],
+            "replicaof" => vec![format!("replicaof {value}")],
+            "save" | "client-output-buffer-limit" => {
+                let width = if parameter.name == "save" { 2 } else { 4 };
+                let parts = value.split_ascii_whitespace().collect::<Vec<_>>();
+                if parts.is_empty() {
+                    return vec![format!("{} \"\"", parameter.name)];
+                }
+
+                parts
+                    .chunks(width)
+                    .map(|chunk| format!("{} {}", parameter.name, chunk.join(" ")))
+                    .collect()
+            }
+            _ => vec![format!("{} {}", parameter.name, quote(&value))],
+        }
+    }
+
+    // Set a parameter at startup, when parameters without a runtime setter can still be changed
+    pub fn set_initial(&mut self, parameter: &Parameter, value: &str) -> Result<(), String> {
+        match parameter.name {
+            "bind" => {
+                self.bind = value.split_ascii_whitespace().map(String::from).collect();
+                Ok(())
+            }
+            "port" => {
+                self.port = value
+                    .parse()
+                    .map_err(|_| "argument must be a port number")?;
+                Ok(())
+            }
+            "io-threads" => {
+                self.io_threads = value
+                    .parse()
+                    .map_err(|_| "argument couldn't be parsed into an integer")?;
+                Ok(())
+            }
+            "io-uring" => {
+                self.io_uring = parse_yes_no(value).ok_or("argument must be 'yes' or 'no'")?;
+                if self.io_uring && !cfg!(feature = "io-uring") {
+                    return Err(String::from(
+                        "the server was built without the io-uring feature",
+                    ));
+                }
+                Ok(())
+            }
+            "logfile" => {
+                self.logfile = (!value.is_empty()).then(|| PathBuf::from(value));
+                Ok(())
+            }
+            "pidfile" => {
+                self.pidfile = (!value.is_empty()).then(|| PathBuf::from(value));
+                Ok(())
+            }
+            "appendfilename" => {
+                if value.is_empty() || value.contains('/') || value.contains('\\') {
+                    return Err(String::from(
+                        "appendfilename can't be a path, just a filename",
+                    ));
+                }
+                self.appendfilename = String::from(value);
+                Ok(())
+            }
+            "cluster-enabled" => {
+                self.cluster_enabled =
+                    parse_yes_no(value).ok_or("argument must be 'yes' or 'no'")?;
+                Ok(())
+            }
+            "cluster-config-file" => {
+                if value.is_empty() || value.contains('/') || value.contains('\\') {
+                    return Err(String::from(
+                        "cluster-config-file can't be a path, just a filename",
+                    ));
+                }
+                self.cluster_config_file = String::from(value);
+                Ok(())
+            }
+            "replicaof" => {
+                let parts = value.split_ascii_whitespace().collect::<Vec<_>>();
+                self.replicaof = match parts[..] {
+                    [] => None,
+                    [host, port] => Some((
+                        String::from(host),
+                        port.parse().map_err(|_| "argument must be a port number")?,
+                    )),
+                    _ => return Err(String::from("argument must be <host> <port>")),
+                };
+                Ok(())
+            }
+            _ => (parameter.set.unwrap())(self, value),
+        }
+    }
+
+    // Apply settings that live outside of the config itself, after startup or a CONFIG SET
+    pub fn apply(&self) -> Result<(), String> {
+        logging::set_level(self.loglevel.filter());
+
+        env::set_current_dir(&self.dir)
+            .map_err(|e| format!("Can't chdir to '{}': {e}", self.dir.display()))
+    }
+
+    // How many worker threads the runtime is started with
+    pub fn worker_threads(&self) -> usize {
+        match self.io_threads {
+            0 => std::thread::available_parallelism().map_or(1, |n| n.get()),
+            n => n,
+        }
+    }
+
+    // Protected mode only kicks in if we're reachable from outside and nobody needs a password
+    pub fn is_protected(&self) -> bool {
+        self.protected_mode
+            && self.requirepass.is_none()
+            && self.bind.iter().any(|addr| !is_loopback(addr))
+    }
+}
+
+// Quote a config file argument if it wouldn't survive split_args otherwise
+fn quote(value: &str) -> String {
+    if !value.is_empty()
+        && !value.contains(|c: char| c.is_whitespace() || c == '"' || c == '\'' || c == '\\')
+    {
+        return String::from(value);
+    }
+
+    let mut quoted = String::from("\"");
+    for c in value.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            '\t' => quoted.push_str("\\t"),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+fn path_or_empty(path: &Option<PathBuf>) -> String {
+    path.as_ref()
+        .map(|path| path.display().to_string())
+        .unwrap_or_default()
+}
+
+fn yes_no(value: bool) -> String {
+    String::from(if value { "yes" } else { "no" })
+}
+
+pub fn parse_yes_no(value: &str) -> Option<bool> {
+    if value.eq_ignore_ascii_case("yes") {
+        Some(true)
+    } else if value.eq_ignore_ascii_case("no") {
+        Some(false)
+    } else {
+        None
+    }
+}
+
+// Parse a memory size such as 100, 1k, 1kb, 5mb or 1gb (k is 1000, kb is 1024)
+pub fn parse_memory(value: &str) -> Option<usize> {
+    let value = value.to_ascii_lowercase();
+    let split = value
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(value.len());
+    let (number, unit) = value.split_at(split);
+
+    let multiplier = match unit {
+        "" | "b" => 1,
+        "k" => 1000,
+        "kb" => 1024,
+        "m" => 1000 * 1000,
+        "mb" => 1024 * 1024,
+        "g" => 1000 * 1000 * 1000,
+        "gb" => 1024 * 1024 * 1024,
+        _ => return None,
+    };
+
+    number.parse::<usize>().ok()?.checked_mul(multiplier)
+}
+
+fn is_loopback(addr: &str) -> bool {
+    if addr.eq_ignore_ascii_case("localhost") {
+        return true;
+    }
+
+    match addr.parse::<IpAddr>() {
+        Ok(ip) => ip.is_loopback(),
+        Err(_) => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        find_parameter, parse_memory, BufferLimit, BufferLimits, Config, LogLevel, Reload, SaveRule,
+    };
+
+    #[test]
+    fn test_load() {
+        let mut config = Config::default();
+        config
+            .load(
+                "# A comment\n\
+                 bind 127.0.0.1 ::1\n\
+                 port 6380\n\
+                 save 900 1\n\
+                 save 60 1000\n\
+                 requirepass \"correct horse\"\n\
+                 maxmemory 100mb\n\
+                 replicaof 10.0.0.1 6379\n\
+                 cluster-enabled yes\n",
+            )
+            .unwrap();
+
+        assert_eq!(config.bind, vec!["127.0.0.1", "::1"]);
+        assert_eq!(config.port, 6380);
+        assert_eq!(
+            config.save,
+            vec![
+                SaveRule {
+                    seconds: 900,
+                    changes: 1
+                },
+                SaveRule {
+                    seconds: 60,
+                    changes: 1000
+                },
+            ]
+        );
+        assert_eq!(config.requirepass.as_deref(), Some("correct horse"));
+        assert_eq!(config.maxmemory, 100 * 1024 * 1024);
+        assert_eq!(config.replicaof, Some((String::from("10.0.0.1"), 6379)));
+        assert!(config.cluster_enabled);
+
+        config.load("save \"\"\n").unwrap();
+        assert!(config.save.is_empty());
+
+        assert!(Config::default().load("not-a-directive 1\n").is_err());
+        assert!(Config::default().load("port\n").is_err());
+        assert!(Config::default().load("replicaof 10.0.0.1\n").is_err());
+    }
+
+    #[test]
+    fn test_reload() {
+        let mut config = Config::default();
+        config
+            .load("port 6380\nsave 900 1\nmaxmemory 100mb\nappendonly no\n")
+            .unwrap();
+
+        let reload = config
+            .reload_contents(
+                "port 6381\nsave 60 1000\nmaxmemory lots\nappendonly no\nrequirepass secret\n",
+            )
+            .unwrap();
+        assert_eq!(reload.applied, vec!["save", "requirepass"]);
+        assert_eq!(reload.deferred, vec!["port"]);
+        assert_eq!(reload.rejected.len(), 1);
+        assert_eq!(reload.rejected[0].0, "maxmemory");
+
+        assert_eq!(config.port, 6380);
+        assert_eq!(
+            config.save,
+            vec![SaveRule {
+                seconds: 60,
+                changes: 1000
+            }]
+        );
+        assert_eq!(config.maxmemory, 100 * 1024 * 1024);
+        assert_eq!(config.requirepass.as_deref(), Some("secret"));
+
+        // Nothing is applied from a file that doesn't parse
+        assert!(config.reload_contents("maxmemory 1mb\nport\n").is_err());
+        assert_eq!(config.maxmemory, 100 * 1024 * 1024);
+
+        // Unchanged since the last reload, other than the rejected value
+        let reload = config
+            .reload_contents(
+                "port 6381\nsave 60 1000\nmaxmemory lots\nappendonly no\nrequirepass secret\n",
+            )
+            .unwrap();
+        assert!(reload.applied.is_empty() && reload.deferred.is_empty());
+        assert_eq!(reload.rejected.len(), 1);
+    }
+
+    #[test]
+    fn test_reload_overrides() {
+        let mut config = Config::default();
+        config
+            .load("port 6380\nloglevel notice\nmaxmemory 100mb\n")
+            .unwrap();
+        for (name, value) in [("port", "7000"), ("loglevel", "debug")] {
+            config
+                .set_override(find_parameter(name).unwrap(), value)
+                .unwrap();
+        }
+
+        // Flags and environment variables still win, whether or not the file changed
+        let reload = config
+            .reload_contents("port 6380\nloglevel warning\nmaxmemory 200mb\n")
+            .unwrap();
+        assert_eq!(reload.applied, vec!["maxmemory"]);
+        assert!(reload.deferred.is_empty());
+        assert_eq!(config.port, 7000);
+        assert_eq!(config.loglevel, LogLevel::Debug);
+        assert_eq!(config.maxmemory, 200 * 1024 * 1024);
+
+        // And values set at runtime aren't undone by lines that haven't changed
+        config.maxmemory = 0;
+        let reload = config
+            .reload_contents("port 6380\nloglevel warning\nmaxmemory 200mb\n")
+            .unwrap();
+        assert_eq!(reload, Reload::default());
+        assert_eq!(config.maxmemory, 0);
+    }
+
+    #[test]
+    fn test_rewrite_contents() {
+        let existing = "# Network\nport 6380\n\n# Snapshots\nsave 900 1\nsave 60 1000\n";
+        let mut config = Config::default();
+        config.load(existing).unwrap();
+
+        config.save = vec![SaveRule {
+            seconds: 10,
+            changes: 5,
+        }];
+        config.maxmemory = 1024;
+
+        assert_eq!(
+            config.rewrite_contents(existing),
+            "# Network\nport 6380\n\n# Snapshots\nsave 10 5\n# Generated by CONFIG REWRITE\nmaxmemory 1024\n"
+        );
+    }
+
+    #[test]
+    fn test_sentinel_directives() {
+        let existing = "port 26379\nsentinel monitor mymaster 127.0.0.1 6379 1\n# Kept\nsentinel down-after-milliseconds mymaster 5000\n";
+        let mut config = Config::default();
+        config.load(existing).unwrap();
+        assert_eq!(
+            config.sentinel,
+            vec![
+                vec!["monitor", "mymaster", "127.0.0.1", "6379", "1"],
+                vec!["down-after-milliseconds", "mymaster", "5000"],
+            ]
+        );
+
+        // After a failover the whole block is replaced
+        config.sentinel = vec![vec![
+            String::from("monitor"),
+            String::from("mymaster"),
+            String::from("127.0.0.1"),
+            String::from("6380"),
+            String::from("1"),
+        ]];
+        assert_eq!(
+            config.rewrite_contents(existing),
+            "port 26379\nsentinel monitor mymaster 127.0.0.1 6380 1\n# Kept\n"
+        );
+        assert_eq!(
+            config.rewrite_contents("port 26379\n"),
+            "port 26379\n# Generated by CONFIG REWRITE\nsentinel monitor mymaster 127.0.0.1 6380 1\n"
+        );
+    }
+
+    #[test]
+    fn test_parameter_round_trip() {
+        let mut config = Config::default();
+
+        for (name, value) in [
+            ("maxmemory", "1048576"),
+            ("proto-max-bulk-len", "1048576"),
+            ("maxmemory-policy", "noeviction"),
+            ("timeout", "30"),
+            ("tcp-keepalive", "60"),
+            ("shutdown-drain-timeout", "5"),
+            ("latency-monitor-threshold", "100"),
+            ("hotkeys-sample-rate", "10"),
+            ("slow-command-threshold", "-1"),
+            ("save", "900 1 60 100"),
+            (
+                "client-output-buffer-limit",
+                "normal 1024 512 10 replica 0 0 0 pubsub 4096 2048 5",
+            ),
+            ("protected-mode", "no"),
+            ("appendonly", "yes"),
+            ("appendfsync", "always"),
+            ("aof-load-truncated", "no"),
+            ("rdb-skip-unsupported", "yes"),
+            ("masterauth", "hunter2"),
+            ("replica-read-only", "no"),
+        ] {
+            let parameter = find_parameter(name).unwrap();
+            (parameter.set.unwrap())(&mut config, value).unwrap();
+            assert_eq!((parameter.get)(&config), value);
+        }
+    }
+
+    #[test]
+    fn test_parameter_validation() {
+        let mut config = Config::default();
+
+        for (name, value) in [
+            ("maxmemory", "lots"),
+            ("maxmemory-policy", "sometimes-lru"),
+            ("maxmemory-policy", "allkeys-lru"),
+            ("save", "900"),
+            ("client-output-buffer-limit", "readers 1 1 1"),
+            ("client-output-buffer-limit", "normal 1 1"),
+            ("dbfilename", "data/dump.rdb"),
+        ] {
+            let parameter = find_parameter(name).unwrap();
+            assert!((parameter.set.unwrap())(&mut config, value).is_err());
+        }
+
+        assert!(find_parameter("port").unwrap().set.is_none());
+        assert!(find_parameter("io-threads").unwrap().set.is_none());
+        let io_threads = find_parameter("io-threads").unwrap();
+        assert!(config.set_initial(io_threads, "many").is_err());
+        config.set_initial(io_threads, "4").unwrap();
+        assert_eq!(config.worker_threads(), 4);
+    }
+
+    #[test]
+    fn test_parse_memory() {
+        assert_eq!(parse_memory("100"), Some(100));
+        assert_eq!(parse_memory("1k"), Some(1000));
+        assert_eq!(parse_memory("1KB"), Some(1024));
+        assert_eq!(parse_memory("256mb"), Some(256 * 1024 * 1024));
+        assert_eq!(parse_memory("1gb"), Some(1024 * 1024 * 1024));
+        assert_eq!(parse_memory("1tb"), None);
+        assert_eq!(parse_memory("mb"), None);
+    }
+
+    #[test]
+    fn test_parse_buffer_limit() {
+        assert_eq!(
+            "32mb 8mb 60".parse::<BufferLimit>(),
+            Ok(BufferLimit {
+                hard: 32 * 1024 * 1024,
+                soft: 8 * 1024 * 1024,
+                soft_seconds: 60,
+            })
+        );
+        assert!("32mb 8mb".parse::<BufferLimit>().is_err());
+    }
+
+    #[test]
+    fn test_buffer_limit_classes() {
+        let mut config = Config::default();
+        let parameter = find_parameter("client-output-buffer-limit").unwrap();
+        (parameter.set.unwrap())(&mut config, "slave 1mb 512kb 10 normal 100 50 1").unwrap();
+
+        let limits = config.client_output_buffer_limit;
+        assert_eq!(limits.normal, "100 50 1".parse().unwrap());
+        assert_eq!(limits.replica, "1mb 512kb 10".parse().unwrap());
+        assert_eq!(limits.pubsub, BufferLimits::default().pubsub);
+
+        // Nothing changes if any class is wrong
+        assert!((parameter.set.unwrap())(&mut config, "pubsub 1 1 1 nobody 1 1 1").is_err());
+        assert_eq!(config.client_output_buffer_limit, limits);
+    }
+
+    #[test]
+    fn test_protected_default() {
+        assert!(Config::default().is_protected());
+    }
+
+    #[test]
+    fn test_protected_loopback_only() {
+        let config = Config {
+            bind: vec![String::from("127.0.0.1"), String::from("::1")],
+            ..Config::default()
+        };
+        assert!(!config.is_protected());
+    }
+
+    #[test]
+    fn test_protected_with_password() {
+        let config = Config {
+            requirepass: Some(String::from("hunter2")),
+            ..Config::default()
+        };
+        assert!(!config.is_protected());
+    }
+
+    #[test]
+    fn test_protected_disabled() {
+        let config = Config {
+            protected_mode: false,
+            ..Config::default()
+        };
+        assert!(!config.is_protected());
+    }
+}
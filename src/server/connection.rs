@@ -0,0 +1,40 @@
+use crate::server::output::OutputBuffer;
+use std::io;
+use std::os::fd::AsRawFd;
+use tokio::io::AsyncReadExt;
+use tokio::net::tcp::OwnedReadHalf;
+use tokio::net::TcpStream;
+
+// How much is read from a socket at a time, enough for a good sized pipeline
+pub const READ_SIZE: usize = 16 * 1024;
+
+// A client's socket, either one of tokio's or (with the io-uring feature) one driven by io_uring
+pub trait Stream: AsRawFd {
+    type Reader: Reader;
+
+    // Separate the socket into where commands are read from and where replies are queued
+    fn split(self) -> (Self::Reader, OutputBuffer);
+}
+
+pub trait Reader {
+    // Read more from the client onto the end of input, returning how many bytes that was (0 once
+    // the client has closed the connection)
+    // Nothing is lost if this is cancelled part way through, so it can be used in select!
+    async fn read(&mut self, input: &mut Vec<u8>) -> io::Result<usize>;
+}
+
+impl Stream for TcpStream {
+    type Reader = OwnedReadHalf;
+
+    fn split(self) -> (OwnedReadHalf, OutputBuffer) {
+        let (reader, writer) = self.into_split();
+        (reader, OutputBuffer::new(writer))
+    }
+}
+
+impl Reader for OwnedReadHalf {
+    async fn read(&mut self, input: &mut Vec<u8>) -> io::Result<usize> {
+        input.reserve(READ_SIZE);
+        self.read_buf(input).await
+    }
+}
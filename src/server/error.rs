@@ -0,0 +1,196 @@
+// Errors a command can reply with, each written with the code Redis starts that error with
+// Client libraries go by that first word to tell what went wrong (and whether to follow a
+// redirect, retry, or give up), so it has to be the one Redis would send.
+
+use crate::RedisType;
+use std::fmt;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum ServerError {
+    // ERR, the general error, with the rest of the message
+    Err(String),
+    Syntax,
+    NotAnInteger,
+    NotAFloat,
+    // The command (or subcommand, such as "client|setname") given the wrong number of arguments
+    WrongArity(String),
+    UnknownCommand {
+        name: String,
+        args: Vec<String>,
+    },
+    UnknownSubcommand(String),
+    WrongType,
+    // Why authentication is needed first
+    NoAuth(&'static str),
+    WrongPass,
+    NoProto,
+    ReadOnly,
+    // A command that could use more memory, while over maxmemory
+    Oom,
+    // Not sent yet, there are no transactions to abort
+    #[allow(dead_code)]
+    ExecAbort,
+    // Not sent yet, there's no command that refuses to replace a key
+    #[allow(dead_code)]
+    BusyKey,
+    NoMasterLink,
+    // Redirects to the node serving a slot, as its address
+    Moved(u16, String),
+    Ask(u16, String),
+    CrossSlot,
+    ClusterDown(&'static str),
+    InProgress,
+    NoGoodReplica,
+    // How many sentinels the quorum needs
+    NoQuorum(u32),
+    // A command sent once the server has started shutting down
+    ShuttingDown,
+    // Input that isn't a valid request, after which the connection is closed
+    Protocol(String),
+}
+
+impl ServerError {
+    // For a command that doesn't exist, given its full argv
+    pub fn unknown_command(argv: &[RedisType]) -> ServerError {
+        let mut argv = argv.iter().map(|arg| match arg {
+            RedisType::String { value } => value.clone(),
+            arg => arg.to_string(),
+        });
+        ServerError::UnknownCommand {
+            name: argv.next().unwrap_or_default(),
+            args: argv.collect(),
+        }
+    }
+
+    // The code at the start of the error, which is what clients match on
+    pub fn code(&self) -> &'static str {
+        match self {
+            ServerError::Err(_)
+            | ServerError::Syntax
+            | ServerError::NotAnInteger
+            | ServerError::NotAFloat
+            | ServerError::WrongArity(_)
+            | ServerError::UnknownCommand { .. }
+            | ServerError::UnknownSubcommand(_)
+            | ServerError::ShuttingDown
+            | ServerError::Protocol(_) => "ERR",
+            ServerError::WrongType => "WRONGTYPE",
+            ServerError::NoAuth(_) => "NOAUTH",
+            ServerError::WrongPass => "WRONGPASS",
+            ServerError::NoProto => "NOPROTO",
+            ServerError::ReadOnly => "READONLY",
+            ServerError::Oom => "OOM",
+            ServerError::ExecAbort => "EXECABORT",
+            ServerError::BusyKey => "BUSYKEY",
+            ServerError::NoMasterLink => "NOMASTERLINK",
+            ServerError::Moved(..) => "MOVED",
+            ServerError::Ask(..) => "ASK",
+            ServerError::CrossSlot => "CROSSSLOT",
+            ServerError::ClusterDown(_) => "CLUSTERDOWN",
+            ServerError::InProgress => "INPROG",
+            ServerError::NoGoodReplica => "NOGOODSLAVE",
+            ServerError::NoQuorum(_) => "NOQUORUM",
+        }
+    }
+}
+
+impl fmt::Display for ServerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let message = match self {
+            ServerError::Err(message) => message.clone(),
+            ServerError::Syntax => String::from("syntax error"),
+            ServerError::NotAnInteger => String::from("value is not an integer or out of range"),
+            ServerError::NotAFloat => String::from("value is not a valid float"),
+            ServerError::WrongArity(command) => {
+                format!("wrong number of arguments for '{command}' command")
+            }
+            ServerError::UnknownCommand { name, args } => {
+                let args = args.iter().map(|arg| format!("'{arg}' ")).collect::<String>();
+                format!("unknown command '{name}', with args beginning with: {args}")
+            }
+            ServerError::UnknownSubcommand(subcommand) => {
+                format!("unknown subcommand '{subcommand}'")
+            }
+            ServerError::WrongType => {
+                String::from("Operation against a key holding the wrong kind of value")
+            }
+            ServerError::NoAuth(reason) | ServerError::ClusterDown(reason) => {
+                String::from(*reason)
+            }
+            ServerError::WrongPass => {
+                String::from("invalid username-password pair or user is disabled.")
+            }
+            ServerError::NoProto => String::from("unsupported protocol version"),
+            ServerError::ReadOnly => String::from("You can't write against a read only replica."),
+            ServerError::Oom => String::from("command not allowed when used memory > 'maxmemory'."),
+            ServerError::ExecAbort => {
+                String::from("Transaction discarded because of previous errors.")
+            }
+            ServerError::BusyKey => String::from("Target key name already exists."),
+            ServerError::NoMasterLink => {
+                String::from("Can't SYNC while not connected with my master")
+            }
+            ServerError::Moved(slot, endpoint) | ServerError::Ask(slot, endpoint) => {
+                format!("{slot} {endpoint}")
+            }
+            ServerError::CrossSlot => String::from("Keys in request don't hash to the same slot"),
+            ServerError::InProgress => String::from("Failover already in progress"),
+            ServerError::NoGoodReplica => String::from("No suitable replica to promote"),
+            ServerError::ShuttingDown => String::from("The server is shutting down"),
+            ServerError::Protocol(detail) => format!("Protocol error: {detail}"),
+            ServerError::NoQuorum(needed) => format!(
+                "1 usable Sentinels. Not enough available Sentinels to reach the specified quorum for this master ({needed} needed)"
+            ),
+        };
+
+        // SAVE fails with nothing but the code
+        if message.is_empty() {
+            write!(f, "{}", self.code())
+        } else {
+            write!(f, "{} {message}", self.code())
+        }
+    }
+}
+
+impl From<ServerError> for RedisType {
+    fn from(error: ServerError) -> Self {
+        RedisType::Error {
+            value: error.to_string(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ServerError;
+
+    #[test]
+    fn test_error_codes() {
+        assert_eq!(ServerError::Syntax.to_string(), "ERR syntax error");
+        assert_eq!(
+            ServerError::Err(String::from("no such key")).to_string(),
+            "ERR no such key"
+        );
+        assert_eq!(
+            ServerError::WrongArity(String::from("client|setname")).to_string(),
+            "ERR wrong number of arguments for 'client|setname' command"
+        );
+        assert_eq!(
+            ServerError::UnknownCommand {
+                name: String::from("foo"),
+                args: vec![String::from("a"), String::from("b")],
+            }
+            .to_string(),
+            "ERR unknown command 'foo', with args beginning with: 'a' 'b' "
+        );
+        assert_eq!(
+            ServerError::Moved(3999, String::from("127.0.0.1:6381")).to_string(),
+            "MOVED 3999 127.0.0.1:6381"
+        );
+        assert!(ServerError::WrongType.to_string().starts_with("WRONGTYPE "));
+        assert!(ServerError::ReadOnly.to_string().starts_with("READONLY "));
+        assert!(ServerError::Oom.to_string().starts_with("OOM "));
+        assert!(ServerError::ExecAbort.to_string().starts_with("EXECABORT "));
+        assert!(ServerError::BusyKey.to_string().starts_with("BUSYKEY "));
+    }
+}
@@ -0,0 +1,299 @@
+use crate::server::clients::Client;
+use crate::server::State;
+use crate::value::Value;
+use std::collections::hash_map::RandomState;
+use std::collections::HashMap;
+use std::hash::{BuildHasher, Hasher};
+use std::time::{Duration, Instant, SystemTime};
+use tokio::sync::Mutex;
+
+// How often the active expire cycle runs, and how much of that time it may spend removing keys
+pub const CYCLE_PERIOD: Duration = Duration::from_millis(100);
+const CYCLE_TIME_LIMIT: Duration = Duration::from_millis(25);
+
+// Keys the cycle samples at a time, it keeps sampling while more than ACCEPTABLE_STALE percent of
+// them turn out to have expired, since there are probably a lot more like them
+const KEYS_PER_LOOP: usize = 20;
+const ACCEPTABLE_STALE: usize = 10;
+
+// When keys expire: persistently so that snapshots and lock-free readers can take a copy of it
+// without copying every entry, and as a list of keys so the active expire cycle can sample it
+#[derive(Debug, Default)]
+pub struct Ttl {
+    expires: im::HashMap<Vec<u8>, SystemTime>,
+    keys: Vec<Vec<u8>>,
+    positions: HashMap<Vec<u8>, usize>,
+    random: u64,
+}
+
+impl Ttl {
+    pub fn push(&mut self, key: Vec<u8>, expires_at: SystemTime) {
+        if self.expires.insert(key.clone(), expires_at).is_none() {
+            self.positions.insert(key.clone(), self.keys.len());
+            self.keys.push(key);
+        }
+    }
+
+    // Returns if key had an expiration time
+    pub fn remove(&mut self, key: &[u8]) -> bool {
+        if self.expires.remove(key).is_none() {
+            return false;
+        }
+
+        let position = self.positions.remove(key).unwrap();
+        self.keys.swap_remove(position);
+        if let Some(moved) = self.keys.get(position) {
+            self.positions.insert(moved.clone(), position);
+        }
+        true
+    }
+
+    pub fn get(&self, key: &[u8]) -> Option<&SystemTime> {
+        self.expires.get(key)
+    }
+
+    // Up to count keys picked at random (possibly more than once), or all of them if there are
+    // no more than that
+    pub fn sample(&mut self, count: usize) -> Vec<Vec<u8>> {
+        if self.keys.len() <= count {
+            return self.keys.clone();
+        }
+
+        (0..count)
+            .map(|_| {
+                let index = self.next_random() % self.keys.len() as u64;
+                self.keys[index as usize].clone()
+            })
+            .collect()
+    }
+
+    // Xorshift, which is plenty for picking keys to check
+    fn next_random(&mut self) -> u64 {
+        if self.random == 0 {
+            self.random = RandomState::new().build_hasher().finish() | 1;
+        }
+        self.random ^= self.random << 13;
+        self.random ^= self.random >> 7;
+        self.random ^= self.random << 17;
+        self.random
+    }
+
+    pub fn clear(&mut self) {
+        self.expires.clear();
+        self.keys.clear();
+        self.positions.clear();
+    }
+
+    pub fn len(&self) -> usize {
+        self.expires.len()
+    }
+
+    pub fn iter(&self) -> im::hashmap::Iter<'_, Vec<u8>, SystemTime> {
+        self.expires.iter()
+    }
+
+    pub fn expires(&self) -> &im::HashMap<Vec<u8>, SystemTime> {
+        &self.expires
+    }
+}
+
+// Remove a key that has expired, telling the AOF, any replicas and clients tracking it
+fn delete_expired(state: &mut State, key: &[u8]) {
+    tracing::debug!("Evicting {} from keystore", key.escape_ascii());
+    state.keystore.remove(key);
+    state.ttl.remove(key);
+    state.last_access.remove(key);
+    state.stats.expired_keys += 1;
+    crate::server::propagate(state, &[b"DEL".to_vec(), key.to_vec()]);
+    crate::server::tracking::invalidate(state, &[key.to_vec()], None);
+}
+
+// Remove expired keys that nothing has used since, the same way Redis' activeExpireCycle does.
+// Rather than looking at every key with an expiration time, sample a few at a time and stop once
+// few enough of them have expired or the cycle has taken long enough. The lock is released
+// between samples so that clients aren't held up for the whole cycle.
+pub async fn active_expire_cycle(state: &Mutex<State>) {
+    let start = Instant::now();
+
+    loop {
+        let mut state = state.lock().await;
+        // Replicas wait for the master to send a DEL instead
+        if state.active_expire_disabled || state.replication.master.is_some() {
+            return;
+        }
+
+        let now = SystemTime::now();
+        let sampled = state.ttl.sample(KEYS_PER_LOOP);
+        let mut expired = 0;
+        for key in sampled.iter() {
+            if state.ttl.get(key).is_some_and(|at| *at <= now) {
+                delete_expired(&mut state, key);
+                expired += 1;
+            }
+        }
+
+        let done =
+            sampled.len() < KEYS_PER_LOOP || expired * 100 <= sampled.len() * ACCEPTABLE_STALE;
+        let elapsed = start.elapsed();
+        if done || elapsed >= CYCLE_TIME_LIMIT {
+            if !done {
+                state.stats.expired_time_cap_reached_count += 1;
+            }
+            let threshold = state.config.latency_monitor_threshold;
+            state.latency.record("expire-cycle", elapsed, threshold);
+            crate::server::reads::publish(&state);
+            return;
+        }
+
+        drop(state);
+        tokio::task::yield_now().await;
+    }
+}
+
+// An expired key a replica keeps from its own clients until the master's DEL arrives
+#[derive(Debug)]
+pub struct Hidden {
+    key: Vec<u8>,
+    value: Value,
+    expires_at: SystemTime,
+}
+
+// Deal with any of keys that have expired before a command uses them
+// A master deletes them and sends the DEL on, so that replicas never have to decide for themselves.
+// A replica only hides them from its clients, but the master itself still sees them so that its
+// writes apply the same way they did on the master.
+pub fn expire_keys(state: &mut State, client: &Client, keys: &[Vec<u8>]) -> Vec<Hidden> {
+    let now = SystemTime::now();
+    let mut hidden = Vec::new();
+
+    for key in keys {
+        let expires_at = match state.ttl.get(key) {
+            Some(expires_at) if *expires_at <= now => *expires_at,
+            _ => continue,
+        };
+
+        if state.replication.master.is_none() {
+            delete_expired(state, key);
+        } else if !client.master {
+            if let Some(value) = state.keystore.remove(key) {
+                state.ttl.remove(key);
+                hidden.push(Hidden {
+                    key: key.clone(),
+                    value,
+                    expires_at,
+                });
+            }
+        }
+    }
+
+    hidden
+}
+
+// Put hidden keys back once the command is done, unless it wrote them itself (on a writable replica)
+pub fn restore(state: &mut State, hidden: Vec<Hidden>) {
+    for Hidden {
+        key,
+        value,
+        expires_at,
+    } in hidden
+    {
+        if !state.keystore.contains_key(&key) {
+            state.keystore.insert(key.clone(), value);
+            state.ttl.push(key, expires_at);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::SocketAddr;
+    use std::time::Duration;
+
+    #[test]
+    fn test_expire_keys() {
+        let mut state = State::default();
+        let addr = SocketAddr::from(([127, 0, 0, 1], 1234));
+        let mut client = Client::new(addr, addr);
+        let keys = vec![b"expired".to_vec(), b"live".to_vec()];
+
+        let past = SystemTime::now() - Duration::from_secs(1);
+        let future = SystemTime::now() + Duration::from_secs(100);
+        let reset = |state: &mut State| {
+            state.keystore.insert(b"expired".to_vec(), Value::from("1"));
+            state.ttl.push(b"expired".to_vec(), past);
+            state.keystore.insert(b"live".to_vec(), Value::from("2"));
+            state.ttl.push(b"live".to_vec(), future);
+        };
+
+        // A master deletes expired keys for good
+        reset(&mut state);
+        assert!(expire_keys(&mut state, &client, &keys).is_empty());
+        assert!(!state.keystore.contains_key(&b"expired"[..]));
+        assert!(state.keystore.contains_key(&b"live"[..]));
+
+        // A replica only hides them while a command runs
+        state
+            .replication
+            .replicate_from(String::from("localhost"), 6379);
+        reset(&mut state);
+        let hidden = expire_keys(&mut state, &client, &keys);
+        assert!(!state.keystore.contains_key(&b"expired"[..]));
+        restore(&mut state, hidden);
+        assert_eq!(state.keystore.get(&b"expired"[..]), Some(&Value::from("1")));
+        assert_eq!(state.ttl.get(b"expired"), Some(&past));
+
+        // And the master's own commands still see them
+        client.master = true;
+        assert!(expire_keys(&mut state, &client, &keys).is_empty());
+        assert!(state.keystore.contains_key(&b"expired"[..]));
+    }
+
+    #[test]
+    fn test_ttl() {
+        let mut ttl = Ttl::default();
+        let at = SystemTime::now();
+        for key in ["a", "b", "c"] {
+            ttl.push(key.as_bytes().to_vec(), at);
+        }
+        ttl.push(b"a".to_vec(), at + Duration::from_secs(1));
+        assert_eq!(ttl.len(), 3);
+        assert_eq!(ttl.get(b"a"), Some(&(at + Duration::from_secs(1))));
+
+        // Removing moves the last key into the gap, which has to stay findable
+        assert!(ttl.remove(b"a"));
+        assert!(!ttl.remove(b"a"));
+        assert!(ttl.remove(b"c"));
+        assert_eq!(ttl.sample(20), vec![b"b".to_vec()]);
+
+        for i in 0..100 {
+            ttl.push(format!("key{i}").into_bytes(), at);
+        }
+        let sampled = ttl.sample(20);
+        assert_eq!(sampled.len(), 20);
+        assert!(sampled.iter().all(|key| ttl.get(key).is_some()));
+    }
+
+    #[tokio::test]
+    async fn test_active_expire_cycle() {
+        let mut state = State::default();
+        let past = SystemTime::now() - Duration::from_secs(1);
+        let future = SystemTime::now() + Duration::from_secs(100);
+        for i in 0..1000 {
+            let key = format!("key{i}").into_bytes();
+            state.keystore.insert(key.clone(), Value::from("1"));
+            state.ttl.push(key, if i % 10 == 0 { future } else { past });
+        }
+        state.keystore.insert(b"forever".to_vec(), Value::from("1"));
+        let state = Mutex::new(state);
+
+        // Keeps going while most of what it samples has expired
+        active_expire_cycle(&state).await;
+        let state = state.into_inner();
+        assert!(state.stats.expired_keys > 500);
+        assert_eq!(state.keystore.len() as u64 + state.stats.expired_keys, 1001);
+        assert_eq!(state.ttl.len(), state.keystore.len() - 1);
+        assert!(state.keystore.contains_key(&b"key0"[..]));
+        assert!(state.keystore.contains_key(&b"forever"[..]));
+    }
+}
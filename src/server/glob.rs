@@ -0,0 +1,154 @@
+// Glob-style pattern matching as used by KEYS, SCAN MATCH and CONFIG GET
+//
+// Supports * (any run of characters), ? (any single character), [abc] / [a-z] / [^abc]
+// character classes, and \ to escape the next character.
+pub fn matches(pattern: &str, string: &str, nocase: bool) -> bool {
+    let pattern = pattern.chars().collect::<Vec<_>>();
+    let string = string.chars().collect::<Vec<_>>();
+    matches_from(&pattern, &string, nocase)
+}
+
+fn matches_from(pattern: &[char], string: &[char], nocase: bool) -> bool {
+    let eq = |a: char, b: char| {
+        if nocase {
+            a.to_lowercase().eq(b.to_lowercase())
+        } else {
+            a == b
+        }
+    };
+
+    let mut p = 0;
+    let mut s = 0;
+
+    while p < pattern.len() {
+        match pattern[p] {
+            '*' => {
+                // Collapse runs of stars, then try every possible tail
+                while p < pattern.len() && pattern[p] == '*' {
+                    p += 1;
+                }
+                if p == pattern.len() {
+                    return true;
+                }
+                return (s..=string.len())
+                    .any(|i| matches_from(&pattern[p..], &string[i..], nocase));
+            }
+            '?' => {
+                if s >= string.len() {
+                    return false;
+                }
+                s += 1;
+                p += 1;
+            }
+            '[' => {
+                if s >= string.len() {
+                    return false;
+                }
+
+                p += 1;
+                let negate = p < pattern.len() && pattern[p] == '^';
+                if negate {
+                    p += 1;
+                }
+
+                let mut matched = false;
+                while p < pattern.len() && pattern[p] != ']' {
+                    if pattern[p] == '\\' && p + 1 < pattern.len() {
+                        p += 1;
+                        matched |= eq(pattern[p], string[s]);
+                        p += 1;
+                    } else if p + 2 < pattern.len()
+                        && pattern[p + 1] == '-'
+                        && pattern[p + 2] != ']'
+                    {
+                        let (mut lo, mut hi) = (pattern[p], pattern[p + 2]);
+                        if lo > hi {
+                            std::mem::swap(&mut lo, &mut hi);
+                        }
+                        let c = string[s];
+                        matched |= if nocase {
+                            let c = c.to_ascii_lowercase();
+                            c >= lo.to_ascii_lowercase() && c <= hi.to_ascii_lowercase()
+                        } else {
+                            c >= lo && c <= hi
+                        };
+                        p += 3;
+                    } else {
+                        matched |= eq(pattern[p], string[s]);
+                        p += 1;
+                    }
+                }
+
+                // Skip the closing bracket (an unterminated class just runs to the end)
+                if p < pattern.len() {
+                    p += 1;
+                }
+
+                if matched == negate {
+                    return false;
+                }
+                s += 1;
+            }
+            c => {
+                let c = if c == '\\' && p + 1 < pattern.len() {
+                    p += 1;
+                    pattern[p]
+                } else {
+                    c
+                };
+
+                if s >= string.len() || !eq(c, string[s]) {
+                    return false;
+                }
+                s += 1;
+                p += 1;
+            }
+        }
+    }
+
+    s == string.len()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::matches;
+
+    #[test]
+    fn test_literal() {
+        assert!(matches("hello", "hello", false));
+        assert!(!matches("hello", "hell", false));
+        assert!(!matches("hello", "Hello", false));
+        assert!(matches("hello", "HeLLo", true));
+    }
+
+    #[test]
+    fn test_star() {
+        assert!(matches("*", "", false));
+        assert!(matches("h*llo", "heeeello", false));
+        assert!(matches("h*llo", "hllo", false));
+        assert!(matches("max*", "maxmemory-policy", false));
+        assert!(!matches("h*llo", "hello world", false));
+    }
+
+    #[test]
+    fn test_question() {
+        assert!(matches("h?llo", "hallo", false));
+        assert!(!matches("h?llo", "hllo", false));
+    }
+
+    #[test]
+    fn test_class() {
+        assert!(matches("h[ae]llo", "hello", false));
+        assert!(!matches("h[ae]llo", "hillo", false));
+        assert!(matches("h[^e]llo", "hallo", false));
+        assert!(!matches("h[^e]llo", "hello", false));
+        assert!(matches("h[a-b]llo", "hbllo", false));
+        assert!(!matches("h[a-b]llo", "hcllo", false));
+    }
+
+    #[test]
+    fn test_escape() {
+        assert!(matches("h\\*llo", "h*llo", false));
+        assert!(!matches("h\\*llo", "hello", false));
+    }
+}
@@ -0,0 +1,238 @@
+use crate::server::memory;
+use crate::value::Value;
+use std::collections::{HashMap, VecDeque};
+use std::time::{Duration, Instant};
+
+// Samples are kept for a minute, in buckets so that the oldest can be dropped as time moves on
+const BUCKET: Duration = Duration::from_secs(10);
+const BUCKETS: usize = 6;
+
+// Keys each bucket keeps track of, for each of accesses and sizes
+const MAX_KEYS: usize = 1024;
+
+// The most accessed and the largest keys over the last minute, from 1 in hotkeys-sample-rate key
+// accesses (by commands that hold the state's lock, and reads once they're flushed)
+// Accesses are counted with the space-saving algorithm: once a bucket is full, a new key takes the
+// place of the least accessed one and inherits its count. Counts can be overestimates for that
+// reason, and since each sample stands for sample-rate accesses, but a key that gets a large share
+// of the accesses is never dropped. Sizes are the key's memory usage when it was last sampled.
+#[derive(Debug, Default)]
+pub struct HotKeys {
+    // Oldest first
+    buckets: VecDeque<Bucket>,
+    // Accesses to skip before the next sample
+    skip: u64,
+}
+
+#[derive(Debug)]
+struct Bucket {
+    started: Instant,
+    sampled: u64,
+    accesses: HashMap<Vec<u8>, u64>,
+    sizes: HashMap<Vec<u8>, usize>,
+}
+
+impl HotKeys {
+    // Note an access to key, which holds value, if it's one to sample. A rate of 0 disables
+    // tracking.
+    pub fn record(&mut self, key: &[u8], value: &Value, rate: u64, now: Instant) {
+        if rate == 0 {
+            return;
+        }
+        // In case the rate has come down since the last sample
+        self.skip = self.skip.min(rate - 1);
+        if self.skip > 0 {
+            self.skip -= 1;
+            return;
+        }
+        self.skip = rate - 1;
+
+        let bucket = self.bucket(now);
+        bucket.sampled += 1;
+
+        if let Some(count) = bucket.accesses.get_mut(key) {
+            *count += rate;
+        } else if bucket.accesses.len() < MAX_KEYS {
+            bucket.accesses.insert(key.to_vec(), rate);
+        } else {
+            let (least, count) = smallest(&bucket.accesses);
+            bucket.accesses.remove(&least);
+            bucket.accesses.insert(key.to_vec(), count + rate);
+        }
+
+        let size = memory::key_usage(key, value);
+        if let Some(existing) = bucket.sizes.get_mut(key) {
+            *existing = size;
+        } else if bucket.sizes.len() < MAX_KEYS {
+            bucket.sizes.insert(key.to_vec(), size);
+        } else {
+            let (least, smallest) = smallest(&bucket.sizes);
+            if size > smallest {
+                bucket.sizes.remove(&least);
+                bucket.sizes.insert(key.to_vec(), size);
+            }
+        }
+    }
+
+    // How many accesses were sampled over the last minute
+    pub fn sampled(&self, now: Instant) -> u64 {
+        self.live(now).map(|bucket| bucket.sampled).sum()
+    }
+
+    // Up to count of the most accessed keys, with roughly how many times each was accessed
+    pub fn hottest(&self, count: usize, now: Instant) -> Vec<(Vec<u8>, u64)> {
+        let mut totals: HashMap<&[u8], u64> = HashMap::new();
+        for bucket in self.live(now) {
+            for (key, accesses) in &bucket.accesses {
+                *totals.entry(key).or_default() += accesses;
+            }
+        }
+        top(totals, count)
+    }
+
+    // Up to count of the largest keys seen that are still there, with their size in bytes now
+    pub fn biggest(
+        &self,
+        count: usize,
+        keystore: &im::HashMap<Vec<u8>, Value>,
+        now: Instant,
+    ) -> Vec<(Vec<u8>, usize)> {
+        let mut sizes: HashMap<&[u8], usize> = HashMap::new();
+        for bucket in self.live(now) {
+            for key in bucket.sizes.keys() {
+                if let Some(value) = keystore.get(key) {
+                    sizes.insert(key, memory::key_usage(key, value));
+                }
+            }
+        }
+        top(sizes, count)
+    }
+
+    pub fn reset(&mut self) {
+        self.buckets.clear();
+    }
+
+    // The bucket that samples taken now go in, dropping any that have aged out of the window
+    fn bucket(&mut self, now: Instant) -> &mut Bucket {
+        let current = self
+            .buckets
+            .back()
+            .is_some_and(|bucket| now.duration_since(bucket.started) < BUCKET);
+        if !current {
+            self.buckets.push_back(Bucket {
+                started: now,
+                sampled: 0,
+                accesses: HashMap::new(),
+                sizes: HashMap::new(),
+            });
+        }
+        while self.buckets.len() > BUCKETS
+            || self
+                .buckets
+                .front()
+                .is_some_and(|bucket| now.duration_since(bucket.started) >= BUCKET * BUCKETS as u32)
+        {
+            self.buckets.pop_front();
+        }
+        self.buckets.back_mut().expect("a bucket was just added")
+    }
+
+    fn live(&self, now: Instant) -> impl Iterator<Item = &Bucket> {
+        self.buckets
+            .iter()
+            .filter(move |bucket| now.duration_since(bucket.started) < BUCKET * BUCKETS as u32)
+    }
+}
+
+// The entry with the smallest number, to make way for another
+fn smallest<T: Copy + Ord>(entries: &HashMap<Vec<u8>, T>) -> (Vec<u8>, T) {
+    entries
+        .iter()
+        .min_by_key(|(_, n)| **n)
+        .map(|(key, n)| (key.clone(), *n))
+        .expect("only called when full")
+}
+
+// The count entries with the largest numbers, largest first (then by key, to be predictable)
+fn top<T: Copy + Ord>(entries: HashMap<&[u8], T>, count: usize) -> Vec<(Vec<u8>, T)> {
+    let mut entries = entries
+        .into_iter()
+        .map(|(key, n)| (key.to_vec(), n))
+        .collect::<Vec<_>>();
+    entries.sort_by(|(a, x), (b, y)| y.cmp(x).then_with(|| a.cmp(b)));
+    entries.truncate(count);
+    entries
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{HotKeys, BUCKET, BUCKETS, MAX_KEYS};
+    use crate::value::Value;
+    use std::time::Instant;
+
+    #[test]
+    fn test_hottest() {
+        let value = Value::from(b"value".to_vec());
+        let mut hotkeys = HotKeys::default();
+        let now = Instant::now();
+
+        // One key gets half of the accesses, spread among far more keys than can be tracked
+        for n in 0..MAX_KEYS * 4 {
+            hotkeys.record(b"hot", &value, 1, now);
+            hotkeys.record(format!("key:{n}").as_bytes(), &value, 1, now);
+        }
+
+        let hottest = hotkeys.hottest(2, now);
+        assert_eq!(hottest[0], (b"hot".to_vec(), MAX_KEYS as u64 * 4));
+        assert!(hottest[1].1 < MAX_KEYS as u64 * 4);
+        assert_eq!(hotkeys.sampled(now), MAX_KEYS as u64 * 8);
+
+        // Until it ages out
+        let later = now + BUCKET * BUCKETS as u32;
+        assert!(hotkeys.hottest(1, later).is_empty());
+        assert_eq!(hotkeys.sampled(later), 0);
+    }
+
+    #[test]
+    fn test_sample_rate() {
+        let value = Value::from(b"value".to_vec());
+        let mut hotkeys = HotKeys::default();
+        let now = Instant::now();
+        for _ in 0..100 {
+            hotkeys.record(b"key", &value, 10, now);
+        }
+        assert_eq!(hotkeys.sampled(now), 10);
+        assert_eq!(hotkeys.hottest(1, now), vec![(b"key".to_vec(), 100)]);
+
+        hotkeys.record(b"key", &value, 0, now);
+        assert_eq!(hotkeys.sampled(now), 10);
+    }
+
+    #[test]
+    fn test_biggest() {
+        // A few big keys among more small ones than can be tracked
+        let mut keystore = im::HashMap::new();
+        for n in 0..MAX_KEYS * 2 {
+            let value = Value::from(vec![b'x'; n % 100 + 1]);
+            keystore.insert(format!("key:{n}").into_bytes(), value);
+        }
+        for (n, size) in [1_000, 10_000, 100_000].into_iter().enumerate() {
+            keystore.insert(
+                format!("big:{n}").into_bytes(),
+                Value::from(vec![b'x'; size]),
+            );
+        }
+
+        let mut hotkeys = HotKeys::default();
+        let now = Instant::now();
+        for (key, value) in &keystore {
+            hotkeys.record(key, value, 1, now);
+        }
+        keystore.remove(&b"big:2"[..]);
+
+        let biggest = hotkeys.biggest(2, &keystore, now);
+        let keys = biggest.iter().map(|(key, _)| &key[..]).collect::<Vec<_>>();
+        assert_eq!(keys, vec![&b"big:1"[..], &b"big:0"[..]]);
+        assert!(biggest[0].1 > 10_000);
+    }
+}
@@ -0,0 +1,487 @@
+use crate::server::replication::LinkStatus;
+use crate::server::{allocator, memory, State, REDIS_VERSION};
+use std::fmt::Write;
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+type Section = fn(&State) -> Vec<(String, String)>;
+
+// Sections reported by INFO, in order, and whether they are included by default
+const SECTIONS: &[(&str, bool, Section)] = &[
+    ("server", true, server),
+    ("clients", true, clients),
+    ("memory", true, memory),
+    ("persistence", true, persistence),
+    ("stats", true, stats),
+    ("replication", true, replication),
+    ("cluster", true, cluster),
+    ("sentinel", true, sentinel),
+    ("commandstats", false, commandstats),
+    ("latencystats", false, latencystats),
+    ("hotkeys", false, hotkeys),
+    ("keyspace", true, keyspace),
+];
+
+// Render the requested sections (or the defaults), as returned by INFO
+pub fn render(state: &State, requested: &[String]) -> String {
+    let requested = requested
+        .iter()
+        .map(|section| section.to_ascii_lowercase())
+        .collect::<Vec<_>>();
+
+    let mut output = String::new();
+    for (name, default, section) in SECTIONS {
+        // Sentinels don't hold data, and only they have the sentinel section
+        let available = match state.sentinel {
+            Some(_) => matches!(*name, "server" | "clients" | "stats" | "sentinel"),
+            None => *name != "sentinel",
+        };
+        if !available {
+            continue;
+        }
+
+        let included = if requested.is_empty() {
+            *default
+        } else {
+            requested.iter().any(|requested| match requested.as_str() {
+                "all" | "everything" => true,
+                "default" => *default,
+                requested => requested == *name,
+            })
+        };
+        if !included {
+            continue;
+        }
+
+        if !output.is_empty() {
+            output.push_str("\r\n");
+        }
+
+        let mut title = name.to_string();
+        title[..1].make_ascii_uppercase();
+        let _ = write!(output, "# {title}\r\n");
+
+        for (key, value) in section(state) {
+            let _ = write!(output, "{key}:{value}\r\n");
+        }
+    }
+
+    output
+}
+
+fn server(state: &State) -> Vec<(String, String)> {
+    vec![
+        ("redis_version".into(), REDIS_VERSION.into()),
+        (
+            "redis_mode".into(),
+            if state.sentinel.is_some() {
+                "sentinel"
+            } else if state.config.cluster_enabled {
+                "cluster"
+            } else {
+                "standalone"
+            }
+            .into(),
+        ),
+        ("os".into(), std::env::consts::OS.into()),
+        ("process_id".into(), std::process::id().to_string()),
+        ("tcp_port".into(), state.config.port.to_string()),
+        (
+            "io_threads".into(),
+            state.config.worker_threads().to_string(),
+        ),
+        (
+            "uptime_in_seconds".into(),
+            state.stats.started.elapsed().as_secs().to_string(),
+        ),
+        (
+            "uptime_in_days".into(),
+            (state.stats.started.elapsed().as_secs() / 86400).to_string(),
+        ),
+        (
+            "config_file".into(),
+            state
+                .config
+                .config_file
+                .as_ref()
+                .map(|path| path.display().to_string())
+                .unwrap_or_default(),
+        ),
+    ]
+}
+
+fn clients(state: &State) -> Vec<(String, String)> {
+    vec![
+        ("connected_clients".into(), state.clients.len().to_string()),
+        ("blocked_clients".into(), state.waiters.len().to_string()),
+        (
+            "tracking_clients".into(),
+            state.tracking.clients().to_string(),
+        ),
+    ]
+}
+
+fn memory(state: &State) -> Vec<(String, String)> {
+    let used = memory::used_memory(state);
+    let rss = memory::rss();
+    let maxmemory = state.config.maxmemory;
+    let allocator = allocator::stats().unwrap_or_default();
+    let allocated = memory::allocated(state, &allocator);
+
+    vec![
+        ("used_memory".into(), used.to_string()),
+        ("used_memory_human".into(), memory::human(used)),
+        ("used_memory_rss".into(), rss.to_string()),
+        ("used_memory_rss_human".into(), memory::human(rss)),
+        (
+            "used_memory_dataset".into(),
+            memory::dataset_usage(state).to_string(),
+        ),
+        (
+            "allocator_allocated".into(),
+            allocator.allocated.to_string(),
+        ),
+        ("allocator_active".into(), allocator.active.to_string()),
+        ("allocator_resident".into(), allocator.resident.to_string()),
+        (
+            "allocator_frag_ratio".into(),
+            memory::ratio(allocator.active, allocator.allocated),
+        ),
+        (
+            "allocator_rss_ratio".into(),
+            memory::ratio(allocator.resident, allocator.active),
+        ),
+        (
+            "mem_fragmentation_ratio".into(),
+            memory::ratio(rss, allocated),
+        ),
+        (
+            "mem_fragmentation_bytes".into(),
+            (rss as i64 - allocated as i64).to_string(),
+        ),
+        ("mem_allocator".into(), allocator::name()),
+        ("maxmemory".into(), maxmemory.to_string()),
+        ("maxmemory_human".into(), memory::human(maxmemory)),
+        (
+            "maxmemory_policy".into(),
+            state.config.maxmemory_policy.to_string(),
+        ),
+    ]
+}
+
+fn persistence(state: &State) -> Vec<(String, String)> {
+    let saves = state.saves.lock().unwrap();
+    let seconds = |duration: Option<std::time::Duration>| {
+        duration.map_or(-1, |duration| duration.as_secs() as i64)
+    };
+
+    vec![
+        ("loading".into(), "0".into()),
+        (
+            "rdb_changes_since_last_save".into(),
+            saves.changes_since_save.to_string(),
+        ),
+        (
+            "rdb_bgsave_in_progress".into(),
+            (saves.in_progress_since.is_some() as u8).to_string(),
+        ),
+        (
+            "rdb_last_save_time".into(),
+            saves
+                .last_save
+                .duration_since(UNIX_EPOCH)
+                .map(|duration| duration.as_secs())
+                .unwrap_or_default()
+                .to_string(),
+        ),
+        (
+            "rdb_last_bgsave_status".into(),
+            if saves.last_bgsave_ok { "ok" } else { "err" }.into(),
+        ),
+        (
+            "rdb_last_bgsave_time_sec".into(),
+            seconds(saves.last_bgsave_duration).to_string(),
+        ),
+        (
+            "rdb_current_bgsave_time_sec".into(),
+            seconds(saves.in_progress_since.map(|since| since.elapsed())).to_string(),
+        ),
+        (
+            "aof_enabled".into(),
+            (state.aof.is_some() as u8).to_string(),
+        ),
+    ]
+}
+
+fn stats(state: &State) -> Vec<(String, String)> {
+    vec![
+        (
+            "total_connections_received".into(),
+            state.stats.total_connections_received.to_string(),
+        ),
+        (
+            "total_commands_processed".into(),
+            state.stats.total_commands_processed.to_string(),
+        ),
+        ("expired_keys".into(), state.stats.expired_keys.to_string()),
+        (
+            "expired_time_cap_reached_count".into(),
+            state.stats.expired_time_cap_reached_count.to_string(),
+        ),
+        (
+            "tracking_total_keys".into(),
+            state.tracking.keys().to_string(),
+        ),
+        (
+            "tracking_total_prefixes".into(),
+            state.tracking.prefixes().to_string(),
+        ),
+    ]
+}
+
+// Field names and order follow Redis, since monitoring and failover tools parse them exactly
+fn replication(state: &State) -> Vec<(String, String)> {
+    let replication = &state.replication;
+    let mut fields = Vec::new();
+
+    match &replication.master {
+        Some(link) => {
+            let up = link.status == LinkStatus::Connected;
+            fields.extend([
+                ("role".into(), "slave".into()),
+                ("master_host".into(), link.host.clone()),
+                ("master_port".into(), link.port.to_string()),
+                (
+                    "master_link_status".into(),
+                    if up { "up" } else { "down" }.into(),
+                ),
+                (
+                    "master_last_io_seconds_ago".into(),
+                    if up {
+                        link.last_io.elapsed().as_secs().to_string()
+                    } else {
+                        "-1".into()
+                    },
+                ),
+                (
+                    "master_sync_in_progress".into(),
+                    ((link.status == LinkStatus::Sync) as u8).to_string(),
+                ),
+                (
+                    "slave_read_repl_offset".into(),
+                    replication.offset.to_string(),
+                ),
+                ("slave_repl_offset".into(), replication.offset.to_string()),
+            ]);
+            if !up {
+                let down_since = link
+                    .down_since
+                    .map_or(-1, |since| since.elapsed().as_secs() as i64);
+                fields.push((
+                    "master_link_down_since_seconds".into(),
+                    down_since.to_string(),
+                ));
+            }
+            fields.extend([
+                ("slave_priority".into(), "100".into()),
+                (
+                    "slave_read_only".into(),
+                    (state.config.replica_read_only as u8).to_string(),
+                ),
+                ("replica_announced".into(), "1".into()),
+            ]);
+        }
+        None => fields.push(("role".into(), "master".into())),
+    }
+
+    fields.push((
+        "connected_slaves".into(),
+        replication.replicas.len().to_string(),
+    ));
+    for (i, replica) in replication.replicas.values().enumerate() {
+        let (ip, port) = replica.endpoint();
+        fields.push((
+            format!("slave{i}"),
+            format!(
+                "ip={ip},port={port},state={},offset={},lag={}",
+                if replica.online {
+                    "online"
+                } else {
+                    "send_bulk"
+                },
+                replica.ack_offset,
+                replica.last_ack.elapsed().as_secs(),
+            ),
+        ));
+    }
+
+    fields.push((
+        "master_failover_state".into(),
+        replication
+            .failover
+            .as_ref()
+            .map_or(String::from("no-failover"), |failover| {
+                failover.state.to_string()
+            }),
+    ));
+
+    // There is no backlog for partial resyncs, so no second replid either
+    fields.extend([
+        ("master_replid".into(), replication.replid.clone()),
+        ("master_replid2".into(), "0".repeat(40)),
+        ("master_repl_offset".into(), replication.offset.to_string()),
+        ("second_repl_offset".into(), "-1".into()),
+        ("repl_backlog_active".into(), "0".into()),
+        ("repl_backlog_size".into(), "0".into()),
+        ("repl_backlog_first_byte_offset".into(), "0".into()),
+        ("repl_backlog_histlen".into(), "0".into()),
+    ]);
+    fields
+}
+
+fn cluster(state: &State) -> Vec<(String, String)> {
+    vec![(
+        "cluster_enabled".into(),
+        (state.config.cluster_enabled as u8).to_string(),
+    )]
+}
+
+fn sentinel(state: &State) -> Vec<(String, String)> {
+    let masters = match &state.sentinel {
+        Some(sentinel) => &sentinel.masters,
+        None => return vec![],
+    };
+
+    let mut fields = vec![
+        ("sentinel_masters".into(), masters.len().to_string()),
+        ("sentinel_tilt".into(), "0".into()),
+        ("sentinel_running_scripts".into(), "0".into()),
+        ("sentinel_scripts_queue_length".into(), "0".into()),
+        ("sentinel_simulate_failure_flags".into(), "0".into()),
+    ];
+    for (i, master) in masters.values().enumerate() {
+        let status = if master.odown() {
+            "odown"
+        } else if master.sdown_since.is_some() {
+            "sdown"
+        } else {
+            "ok"
+        };
+        fields.push((
+            format!("master{i}"),
+            format!(
+                "name={},status={status},address={}:{},slaves={},sentinels=1",
+                master.name,
+                master.host,
+                master.port,
+                master.replicas.len()
+            ),
+        ));
+    }
+    fields
+}
+
+fn commandstats(state: &State) -> Vec<(String, String)> {
+    state
+        .stats
+        .commands
+        .iter()
+        .filter(|(_, stats)| stats.calls > 0 || stats.rejected_calls > 0)
+        .map(|(name, stats)| {
+            let per_call = if stats.calls == 0 {
+                0.0
+            } else {
+                stats.usec as f64 / stats.calls as f64
+            };
+
+            (
+                format!("cmdstat_{name}"),
+                format!(
+                    "calls={},usec={},usec_per_call={per_call:.2},rejected_calls={},failed_calls={}",
+                    stats.calls, stats.usec, stats.rejected_calls, stats.failed_calls
+                ),
+            )
+        })
+        .collect()
+}
+
+fn latencystats(state: &State) -> Vec<(String, String)> {
+    state
+        .stats
+        .commands
+        .iter()
+        .filter(|(_, stats)| !stats.latency.is_empty())
+        .map(|(name, stats)| {
+            let percentiles = [(50.0, "p50"), (99.0, "p99"), (99.9, "p99.900")]
+                .iter()
+                .map(|(percentile, label)| {
+                    format!(
+                        "{label}={:.3}",
+                        stats.latency.percentile(*percentile) as f64
+                    )
+                })
+                .collect::<Vec<_>>()
+                .join(",");
+
+            (format!("latency_percentiles_usec_{name}"), percentiles)
+        })
+        .collect()
+}
+
+// The keys sampled most often and the largest, as DEBUG HOTKEYS and BIGKEYS list them
+fn hotkeys(state: &State) -> Vec<(String, String)> {
+    let now = Instant::now();
+    let mut fields = vec![
+        (
+            "hotkeys_sample_rate".into(),
+            state.config.hotkeys_sample_rate.to_string(),
+        ),
+        (
+            "hotkeys_sampled".into(),
+            state.hotkeys.sampled(now).to_string(),
+        ),
+    ];
+    for (n, (key, accesses)) in state.hotkeys.hottest(5, now).iter().enumerate() {
+        fields.push((
+            format!("hotkey_{n}"),
+            format!("key={},accesses={accesses}", key.escape_ascii()),
+        ));
+    }
+    for (n, (key, size)) in state
+        .hotkeys
+        .biggest(5, &state.keystore, now)
+        .iter()
+        .enumerate()
+    {
+        fields.push((
+            format!("bigkey_{n}"),
+            format!("key={},bytes={size}", key.escape_ascii()),
+        ));
+    }
+    fields
+}
+
+fn keyspace(state: &State) -> Vec<(String, String)> {
+    if state.keystore.is_empty() {
+        return vec![];
+    }
+
+    let now = SystemTime::now();
+    let remaining = state
+        .ttl
+        .iter()
+        .filter_map(|(_, at)| at.duration_since(now).ok())
+        .collect::<Vec<_>>();
+    let expires = remaining.len();
+    let avg_ttl = match expires {
+        0 => 0,
+        _ => remaining.iter().map(|ttl| ttl.as_millis()).sum::<u128>() / expires as u128,
+    };
+
+    vec![(
+        "db0".into(),
+        format!(
+            "keys={},expires={expires},avg_ttl={avg_ttl}",
+            state.keystore.len()
+        ),
+    )]
+}
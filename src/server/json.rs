@@ -0,0 +1,370 @@
+use serde_json::{Number, Value};
+
+// A path into a JSON document, in either of the syntaxes RedisJSON accepts
+// JSONPath starts with $ and matches any number of values, which commands reply with as a list.
+// The legacy syntax (., .a.b or just a.b) matches at most one and replies with it alone. Only
+// child names, array indexes (negative ones counting from the end) and wildcards are supported,
+// not recursive descent, filters, slices or unions.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Path {
+    pub legacy: bool,
+    steps: Vec<Step>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Step {
+    Key(String),
+    Index(i64),
+    Wildcard,
+}
+
+// Where a value is in a document, as a step in each object or array on the way to it
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+enum Location {
+    Key(String),
+    Index(usize),
+}
+
+impl Path {
+    pub fn parse(path: &str) -> Result<Path, String> {
+        let invalid = || format!("Invalid or unsupported path '{path}'");
+        let dotted;
+        let (legacy, mut rest) = match path.strip_prefix('$') {
+            Some(rest) => (false, rest),
+            None if path == "." => (true, ""),
+            None if path.starts_with(['.', '[']) => (true, path),
+            // A legacy path can leave out the leading dot
+            None => {
+                dotted = format!(".{path}");
+                (true, dotted.as_str())
+            }
+        };
+
+        let mut steps = Vec::new();
+        while !rest.is_empty() {
+            if let Some(after) = rest.strip_prefix('.') {
+                let (name, after) = split_name(after);
+                steps.push(name_step(name).ok_or_else(invalid)?);
+                rest = after;
+            } else if let Some(after) = rest.strip_prefix('[') {
+                let (step, after) = bracket(after).ok_or_else(invalid)?;
+                steps.push(step);
+                rest = after;
+            } else {
+                return Err(invalid());
+            }
+        }
+        Ok(Path { legacy, steps })
+    }
+
+    pub fn is_root(&self) -> bool {
+        self.steps.is_empty()
+    }
+
+    // Every value the path matches, in document order
+    pub fn find<'a>(&self, document: &'a Value) -> Vec<&'a Value> {
+        self.locate(document)
+            .iter()
+            .filter_map(|location| get(document, location))
+            .collect()
+    }
+
+    // Set every value the path matches to value, or if there aren't any and the path ends with a
+    // name, add it to the objects the rest of the path matches
+    // Returns how many values were set or added, either of which can be ruled out.
+    pub fn set(&self, document: &mut Value, value: &Value, nx: bool, xx: bool) -> usize {
+        let found = self.locate(document);
+        if !found.is_empty() {
+            if nx {
+                return 0;
+            }
+            for location in &found {
+                if let Some(target) = get_mut(document, location) {
+                    *target = value.clone();
+                }
+            }
+            return found.len();
+        }
+
+        let Some((Step::Key(name), parent)) = self.steps.split_last() else {
+            return 0;
+        };
+        if xx {
+            return 0;
+        }
+        let parent = Path {
+            legacy: self.legacy,
+            steps: parent.to_vec(),
+        };
+        let mut added = 0;
+        for location in parent.locate(document) {
+            if let Some(Value::Object(object)) = get_mut(document, &location) {
+                object.insert(name.clone(), value.clone());
+                added += 1;
+            }
+        }
+        added
+    }
+
+    // Remove every value the path matches (other than the whole document), returning how many
+    pub fn delete(&self, document: &mut Value) -> usize {
+        let mut found = self.locate(document);
+        // Later array elements first, so that removing one doesn't move the rest
+        found.sort_by(|a, b| b.cmp(a));
+        let mut deleted = 0;
+        for location in found {
+            let Some((last, parent)) = location.split_last() else {
+                continue;
+            };
+            match (get_mut(document, parent), last) {
+                (Some(Value::Object(object)), Location::Key(key)) => {
+                    // Keeping the order of the rest of the keys
+                    deleted += object.shift_remove(key).is_some() as usize;
+                }
+                (Some(Value::Array(array)), Location::Index(index)) if *index < array.len() => {
+                    array.remove(*index);
+                    deleted += 1;
+                }
+                _ => {}
+            }
+        }
+        deleted
+    }
+
+    // Add by to every number the path matches, returning each one's new value, or None for
+    // values that aren't numbers
+    pub fn increment(
+        &self,
+        document: &mut Value,
+        by: &Number,
+    ) -> Result<Vec<Option<Number>>, String> {
+        let mut results = Vec::new();
+        for location in self.locate(document) {
+            let target = get_mut(document, &location);
+            let Some(Value::Number(current)) = target else {
+                results.push(None);
+                continue;
+            };
+            let sum = add(current, by)?;
+            *current = sum.clone();
+            results.push(Some(sum));
+        }
+        Ok(results)
+    }
+
+    fn locate(&self, document: &Value) -> Vec<Vec<Location>> {
+        let mut found = vec![(Vec::new(), document)];
+        for step in &self.steps {
+            let mut next = Vec::new();
+            for (location, value) in found {
+                let mut child = |step: Location, value| {
+                    let mut location = location.clone();
+                    location.push(step);
+                    next.push((location, value));
+                };
+                match (step, value) {
+                    (Step::Key(key), Value::Object(object)) => {
+                        if let Some(value) = object.get(key) {
+                            child(Location::Key(key.clone()), value);
+                        }
+                    }
+                    (Step::Index(index), Value::Array(array)) => {
+                        let index = match *index < 0 {
+                            true => array.len().checked_sub(index.unsigned_abs() as usize),
+                            false => Some(*index as usize),
+                        };
+                        if let Some((index, value)) =
+                            index.and_then(|index| Some((index, array.get(index)?)))
+                        {
+                            child(Location::Index(index), value);
+                        }
+                    }
+                    (Step::Wildcard, Value::Object(object)) => {
+                        for (key, value) in object {
+                            child(Location::Key(key.clone()), value);
+                        }
+                    }
+                    (Step::Wildcard, Value::Array(array)) => {
+                        for (index, value) in array.iter().enumerate() {
+                            child(Location::Index(index), value);
+                        }
+                    }
+                    _ => {}
+                }
+            }
+            found = next;
+        }
+
+        let mut locations = found
+            .into_iter()
+            .map(|(location, _)| location)
+            .collect::<Vec<_>>();
+        if self.legacy {
+            locations.truncate(1);
+        }
+        locations
+    }
+}
+
+// A name up to the next step
+fn split_name(path: &str) -> (&str, &str) {
+    let end = path.find(['.', '[']).unwrap_or(path.len());
+    path.split_at(end)
+}
+
+fn name_step(name: &str) -> Option<Step> {
+    match name {
+        "" => None,
+        "*" => Some(Step::Wildcard),
+        name => Some(Step::Key(name.to_string())),
+    }
+}
+
+// A bracketed step, ["name"], ['name'], [0] or [*], from after its [
+fn bracket(path: &str) -> Option<(Step, &str)> {
+    if let Some(quote) = path.chars().next().filter(|c| *c == '"' || *c == '\'') {
+        let mut name = String::new();
+        let mut chars = path[1..].char_indices();
+        while let Some((i, c)) = chars.next() {
+            match c {
+                '\\' => name.push(chars.next()?.1),
+                c if c == quote => {
+                    let rest = path[1 + i + 1..].strip_prefix(']')?;
+                    return Some((Step::Key(name), rest));
+                }
+                c => name.push(c),
+            }
+        }
+        return None;
+    }
+
+    let (inside, rest) = path.split_once(']')?;
+    let step = match inside.trim() {
+        "*" => Step::Wildcard,
+        index => Step::Index(index.parse().ok()?),
+    };
+    Some((step, rest))
+}
+
+fn get<'a>(document: &'a Value, location: &[Location]) -> Option<&'a Value> {
+    location
+        .iter()
+        .try_fold(document, |value, step| match step {
+            Location::Key(key) => value.get(key),
+            Location::Index(index) => value.get(index),
+        })
+}
+
+fn get_mut<'a>(document: &'a mut Value, location: &[Location]) -> Option<&'a mut Value> {
+    location
+        .iter()
+        .try_fold(document, |value, step| match step {
+            Location::Key(key) => value.get_mut(key),
+            Location::Index(index) => value.get_mut(index),
+        })
+}
+
+// Integers stay integers while they fit, otherwise the sum is a float
+fn add(a: &Number, b: &Number) -> Result<Number, String> {
+    if let Some(sum) = a
+        .as_i64()
+        .zip(b.as_i64())
+        .and_then(|(a, b)| a.checked_add(b))
+    {
+        return Ok(Number::from(sum));
+    }
+    let sum = a.as_f64().unwrap_or_default() + b.as_f64().unwrap_or_default();
+    Number::from_f64(sum).ok_or_else(|| format!("result {sum} is not a number or is out of range"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Path, Step};
+    use serde_json::{json, Number};
+
+    #[test]
+    fn test_parse() {
+        let key = |name: &str| Step::Key(name.to_string());
+        for (path, legacy, steps) in [
+            ("$", false, vec![]),
+            (".", true, vec![]),
+            ("$.a.b", false, vec![key("a"), key("b")]),
+            (".a[0]", true, vec![key("a"), Step::Index(0)]),
+            ("a.b", true, vec![key("a"), key("b")]),
+            ("$[\"a.b\"]['c']", false, vec![key("a.b"), key("c")]),
+            ("$.*[-1]", false, vec![Step::Wildcard, Step::Index(-1)]),
+            ("$[*]", false, vec![Step::Wildcard]),
+        ] {
+            assert_eq!(Path::parse(path), Ok(Path { legacy, steps }), "{path}");
+        }
+
+        for path in ["$..a", "$.a[1:2]", "$[?(@.a)]", "$.", "$a", "$[\"a]"] {
+            assert!(Path::parse(path).is_err(), "{path}");
+        }
+    }
+
+    #[test]
+    fn test_find() {
+        let document = json!({"a": [1, {"b": 2}], "c": {"b": 3}});
+        let find = |path| Path::parse(path).unwrap().find(&document);
+
+        assert_eq!(find("$"), vec![&document]);
+        assert_eq!(find("$.a[-1].b"), vec![&json!(2)]);
+        assert_eq!(find("$.*.b"), vec![&json!(3)]);
+        assert_eq!(find("$.a[*]"), vec![&json!(1), &json!({"b": 2})]);
+        assert!(find("$.a[2]").is_empty());
+        // Legacy paths match the first value only
+        assert_eq!(find(".a[*]"), vec![&json!(1)]);
+    }
+
+    #[test]
+    fn test_set() {
+        let mut document = json!({"a": {"x": 1}, "b": {}, "c": {}});
+        let set = |document: &mut _, path, nx, xx| {
+            Path::parse(path)
+                .unwrap()
+                .set(document, &json!("v"), nx, xx)
+        };
+
+        // Only values that are there already are set, if there are any
+        assert_eq!(set(&mut document, "$.*.x", false, false), 1);
+        assert_eq!(document, json!({"a": {"x": "v"}, "b": {}, "c": {}}));
+        assert_eq!(set(&mut document, "$.*.y", false, false), 3);
+        assert_eq!(document["b"], json!({"y": "v"}));
+
+        assert_eq!(set(&mut document, "$.a.x", true, false), 0);
+        assert_eq!(set(&mut document, "$.a.z", false, true), 0);
+        assert_eq!(set(&mut document, "$.a.z.z", false, false), 0);
+        assert_eq!(set(&mut document, "$.a[0]", false, false), 0);
+        assert_eq!(set(&mut document, "$.a.z", false, false), 1);
+        assert_eq!(document["a"], json!({"x": "v", "y": "v", "z": "v"}));
+    }
+
+    #[test]
+    fn test_delete() {
+        let mut document = json!({"a": [0, 1, 2, 3], "b": 1});
+        assert_eq!(Path::parse("$.a[*]").unwrap().delete(&mut document), 4);
+        assert_eq!(Path::parse("$.b").unwrap().delete(&mut document), 1);
+        assert_eq!(Path::parse("$.c").unwrap().delete(&mut document), 0);
+        assert_eq!(document, json!({"a": []}));
+    }
+
+    #[test]
+    fn test_increment() {
+        let mut document = json!({"a": 1, "b": 1.5, "c": "x", "d": i64::MAX});
+        let results = Path::parse("$.*")
+            .unwrap()
+            .increment(&mut document, &Number::from(2))
+            .unwrap();
+        let results = results
+            .into_iter()
+            .map(|result| result.map(|n| n.to_string()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            results,
+            [Some("3"), Some("3.5"), None, Some("9.223372036854776e+18")]
+                .map(|result| result.map(String::from))
+        );
+        assert_eq!(document["c"], "x");
+    }
+}
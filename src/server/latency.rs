@@ -0,0 +1,114 @@
+use std::collections::{BTreeMap, VecDeque};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+// How many samples are kept for each event, as in Redis
+const HISTORY_LENGTH: usize = 160;
+
+// Latency spikes over latency-monitor-threshold, grouped by event class
+#[derive(Debug, Default)]
+pub struct LatencyMonitor {
+    events: BTreeMap<&'static str, EventHistory>,
+}
+
+#[derive(Debug, Default)]
+pub struct EventHistory {
+    // (unix timestamp in seconds, latency in milliseconds), oldest first
+    pub samples: VecDeque<(u64, u64)>,
+    // Worst latency seen since the last reset, even if it's no longer in samples
+    pub max: u64,
+}
+
+impl LatencyMonitor {
+    // Record a sample if it's over the threshold, a threshold of 0 disables monitoring
+    pub fn record(&mut self, event: &'static str, latency: Duration, threshold: u64) {
+        let latency = latency.as_millis() as u64;
+        if threshold == 0 || latency < threshold {
+            return;
+        }
+
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|now| now.as_secs())
+            .unwrap_or_default();
+
+        let history = self.events.entry(event).or_default();
+        history.max = history.max.max(latency);
+
+        // Only one sample per second, keeping the worst
+        match history.samples.back_mut() {
+            Some((last, worst)) if *last == timestamp => *worst = (*worst).max(latency),
+            _ => {
+                history.samples.push_back((timestamp, latency));
+                if history.samples.len() > HISTORY_LENGTH {
+                    history.samples.pop_front();
+                }
+            }
+        }
+    }
+
+    pub fn events(&self) -> impl Iterator<Item = (&'static str, &EventHistory)> {
+        self.events.iter().map(|(event, history)| (*event, history))
+    }
+
+    pub fn history(&self, event: &str) -> Option<&EventHistory> {
+        self.events.get(event)
+    }
+
+    // Reset the given events (or all of them if none are given), returning how many were reset
+    pub fn reset(&mut self, events: &[String]) -> usize {
+        if events.is_empty() {
+            let count = self.events.len();
+            self.events.clear();
+            return count;
+        }
+
+        events
+            .iter()
+            .filter(|event| self.events.remove(event.as_str()).is_some())
+            .count()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_threshold() {
+        let mut monitor = LatencyMonitor::default();
+        monitor.record("command", Duration::from_millis(50), 0);
+        monitor.record("command", Duration::from_millis(5), 10);
+        assert!(monitor.history("command").is_none());
+
+        monitor.record("command", Duration::from_millis(50), 10);
+        assert_eq!(monitor.history("command").unwrap().max, 50);
+    }
+
+    #[test]
+    fn test_one_sample_per_second() {
+        let mut monitor = LatencyMonitor::default();
+        monitor.record("command", Duration::from_millis(20), 10);
+        monitor.record("command", Duration::from_millis(30), 10);
+        monitor.record("command", Duration::from_millis(15), 10);
+
+        // Two samples at most, in case the second ticked over in between
+        let history = monitor.history("command").unwrap();
+        assert!(history.samples.len() <= 2);
+        assert_eq!(history.max, 30);
+    }
+
+    #[test]
+    fn test_reset() {
+        let mut monitor = LatencyMonitor::default();
+        monitor.record("command", Duration::from_millis(20), 10);
+        monitor.record("expire-cycle", Duration::from_millis(20), 10);
+
+        assert_eq!(
+            monitor.reset(&[String::from("command"), String::from("fork")]),
+            1
+        );
+        assert!(monitor.history("command").is_none());
+        assert_eq!(monitor.reset(&[]), 1);
+        assert_eq!(monitor.events().count(), 0);
+    }
+}
@@ -0,0 +1,131 @@
+use super::State;
+use std::fs;
+use std::path::Path;
+use std::sync::Arc;
+use tokio::sync::{watch, Mutex};
+
+// Broadcast to the accept loops and every connection when the server starts shutting down
+// Each client also has one of its own, triggered by CLIENT KILL
+#[derive(Debug)]
+pub struct Shutdown {
+    sender: watch::Sender<bool>,
+}
+
+impl Default for Shutdown {
+    fn default() -> Self {
+        let (sender, _) = watch::channel(false);
+        Shutdown { sender }
+    }
+}
+
+impl Shutdown {
+    pub fn trigger(&self) {
+        self.sender.send_replace(true);
+    }
+
+    pub fn subscribe(&self) -> ShutdownListener {
+        ShutdownListener {
+            receiver: self.sender.subscribe(),
+        }
+    }
+}
+
+pub struct ShutdownListener {
+    receiver: watch::Receiver<bool>,
+}
+
+impl ShutdownListener {
+    pub fn is_triggered(&self) -> bool {
+        *self.receiver.borrow()
+    }
+
+    // Resolves once shutdown has been triggered (immediately if it already was)
+    pub async fn wait(&mut self) {
+        while !*self.receiver.borrow() {
+            if self.receiver.changed().await.is_err() {
+                return;
+            }
+        }
+    }
+}
+
+// Resolves on SIGINT (Ctrl-C) or SIGTERM
+pub async fn wait_for_signal() {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+
+        let mut terminate = match signal(SignalKind::terminate()) {
+            Ok(terminate) => terminate,
+            Err(e) => {
+                tracing::warn!("Unable to listen for SIGTERM: {e}");
+                let _ = tokio::signal::ctrl_c().await;
+                return;
+            }
+        };
+
+        tokio::select! {
+            _ = tokio::signal::ctrl_c() => tracing::info!("Received SIGINT, scheduling shutdown"),
+            _ = terminate.recv() => tracing::info!("Received SIGTERM, scheduling shutdown"),
+        }
+    }
+
+    #[cfg(not(unix))]
+    {
+        let _ = tokio::signal::ctrl_c().await;
+        tracing::info!("Received Ctrl-C, scheduling shutdown");
+    }
+}
+
+// Reload the config file each time the process is sent SIGHUP, logging what became of each
+// directive that changed
+#[cfg(unix)]
+pub async fn reload_on_hangup(state: Arc<Mutex<State>>) {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let mut hangup = match signal(SignalKind::hangup()) {
+        Ok(hangup) => hangup,
+        Err(e) => {
+            tracing::warn!("Unable to listen for SIGHUP: {e}");
+            return;
+        }
+    };
+
+    while hangup.recv().await.is_some() {
+        tracing::info!("Received SIGHUP, reloading the config file");
+        let mut state = state.lock().await;
+        let reload = match state.config.reload() {
+            Ok(reload) => reload,
+            Err(e) => {
+                tracing::warn!("Config file not reloaded: {e}");
+                continue;
+            }
+        };
+
+        for name in &reload.applied {
+            tracing::info!("Applied {name} from the config file");
+        }
+        for name in &reload.deferred {
+            tracing::warn!("Not applying {name} from the config file until restart");
+        }
+        for (name, e) in &reload.rejected {
+            tracing::warn!("Rejected {name} from the config file: {e}");
+        }
+        if reload.applied.is_empty() && reload.deferred.is_empty() && reload.rejected.is_empty() {
+            tracing::info!("Nothing has changed in the config file");
+        }
+        if let Err(e) = state.config.apply() {
+            tracing::warn!("{e}");
+        }
+    }
+}
+
+pub fn write_pidfile(path: &Path) -> std::io::Result<()> {
+    fs::write(path, format!("{}\n", std::process::id()))
+}
+
+pub fn remove_pidfile(path: &Path) {
+    if let Err(e) = fs::remove_file(path) {
+        tracing::warn!("Unable to remove pidfile {}: {e}", path.display());
+    }
+}
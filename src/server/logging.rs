@@ -0,0 +1,43 @@
+use std::fs::OpenOptions;
+use std::path::Path;
+use std::sync::{Mutex, OnceLock};
+use tracing_subscriber::filter::LevelFilter;
+use tracing_subscriber::fmt::writer::BoxMakeWriter;
+use tracing_subscriber::prelude::*;
+use tracing_subscriber::{reload, Registry};
+
+// Kept so that the log level can be changed at runtime with CONFIG SET loglevel
+static LEVEL: OnceLock<reload::Handle<LevelFilter, Registry>> = OnceLock::new();
+
+// Log to stdout, or append to logfile if one is given
+pub fn init(level: LevelFilter, logfile: Option<&Path>) -> std::io::Result<()> {
+    let (filter, handle) = reload::Layer::new(level);
+
+    let (writer, ansi) = match logfile {
+        Some(path) => {
+            let file = OpenOptions::new().create(true).append(true).open(path)?;
+            (BoxMakeWriter::new(Mutex::new(file)), false)
+        }
+        None => (BoxMakeWriter::new(std::io::stdout), true),
+    };
+
+    tracing_subscriber::registry()
+        .with(filter)
+        .with(
+            tracing_subscriber::fmt::layer()
+                .with_writer(writer)
+                .with_ansi(ansi),
+        )
+        .init();
+
+    LEVEL.set(handle).expect("logging initialized twice");
+    Ok(())
+}
+
+pub fn set_level(level: LevelFilter) {
+    if let Some(handle) = LEVEL.get() {
+        if let Err(e) = handle.reload(level) {
+            tracing::warn!("Unable to change log level: {e}");
+        }
+    }
+}
@@ -0,0 +1,231 @@
+use crate::server::allocator::AllocatorStats;
+use crate::server::State;
+use std::time::{Duration, Instant};
+
+// How long a measurement of used memory is trusted for when checking maxmemory, since measuring
+// goes through every key
+const USED_MEMORY_TTL: Duration = Duration::from_secs(1);
+
+// Estimates of what Redis itself would allocate on a 64 bit build, so the numbers reported
+// here are comparable with a real server (and with maxmemory settings tuned for one)
+const DICT_ENTRY: usize = 24;
+const OBJECT_HEADER: usize = 16;
+const EMBSTR_LIMIT: usize = 44;
+
+// Round an allocation up to the jemalloc size class it would be served from
+pub fn allocation_size(size: usize) -> usize {
+    if size <= 8 {
+        8
+    } else if size <= 128 {
+        size.div_ceil(16) * 16
+    } else {
+        // Each power of two range is split into four classes
+        let spacing = size.next_power_of_two() / 8;
+        size.div_ceil(spacing) * spacing
+    }
+}
+
+// Size of an sds string holding len bytes: header, contents and the trailing nul
+fn sds_size(len: usize) -> usize {
+    let header = match len {
+        0..=31 => 1,
+        32..=255 => 3,
+        256..=65535 => 5,
+        _ => 9,
+    };
+    header + len + 1
+}
+
+// How Redis would store a string: as an integer in the object itself, embedded in the same
+// allocation as the object, or as a separate string
+pub fn string_encoding(value: &[u8]) -> &'static str {
+    let integer = std::str::from_utf8(value).is_ok_and(|value| value.parse::<i64>().is_ok());
+    if value.len() <= 20 && integer {
+        "int"
+    } else if value.len() <= EMBSTR_LIMIT {
+        "embstr"
+    } else {
+        "raw"
+    }
+}
+
+// Memory used by a string value, including its object header
+pub fn string_usage(value: &[u8]) -> usize {
+    match string_encoding(value) {
+        "int" => allocation_size(OBJECT_HEADER),
+        "embstr" => allocation_size(OBJECT_HEADER + sds_size(value.len())),
+        _ => allocation_size(OBJECT_HEADER) + allocation_size(sds_size(value.len())),
+    }
+}
+
+// Memory used by a single key: its entry in the keyspace, the key itself and its value
+pub fn key_usage(key: &[u8], value: &[u8]) -> usize {
+    allocation_size(DICT_ENTRY) + allocation_size(sds_size(key.len())) + string_usage(value)
+}
+
+// Size of the keyspace hash table itself, one pointer per bucket in a table sized the way
+// Redis sizes its dict
+pub fn keyspace_overhead(state: &State) -> usize {
+    state.keystore.len().next_power_of_two() * std::mem::size_of::<usize>()
+        + state.ttl.len() * allocation_size(DICT_ENTRY)
+}
+
+pub fn dataset_usage(state: &State) -> usize {
+    state
+        .keystore
+        .iter()
+        .map(|(key, value)| key_usage(key, value))
+        .sum()
+}
+
+// Bytes sitting in client query and output buffers
+pub fn clients_usage(state: &State) -> usize {
+    state
+        .clients
+        .values()
+        .map(|info| info.query_buffer + info.output_memory)
+        .sum()
+}
+
+// Estimated total memory in use, what maxmemory is compared against
+pub fn used_memory(state: &State) -> usize {
+    dataset_usage(state) + keyspace_overhead(state) + clients_usage(state)
+}
+
+// Whether more memory is in use than maxmemory allows, for refusing commands that could add to it
+pub fn over_maxmemory(state: &mut State) -> bool {
+    let maxmemory = state.config.maxmemory;
+    if maxmemory == 0 {
+        return false;
+    }
+    let used = match state.used_memory_sample {
+        Some((at, used)) if at.elapsed() < USED_MEMORY_TTL => used,
+        _ => {
+            let used = used_memory(state);
+            state.used_memory_sample = Some((Instant::now(), used));
+            used
+        }
+    };
+    used > maxmemory
+}
+
+// Physical memory the process is using, as the kernel sees it (0 where that isn't known)
+pub fn rss() -> usize {
+    std::fs::read_to_string("/proc/self/status")
+        .ok()
+        .and_then(|status| {
+            let line = status.lines().find(|line| line.starts_with("VmRSS:"))?;
+            let kb = line.split_whitespace().nth(1)?.parse::<usize>().ok()?;
+            Some(kb * 1024)
+        })
+        .unwrap_or(0)
+}
+
+// What RSS is compared against for the fragmentation ratio: what the allocator has actually
+// handed out if it can say, otherwise the estimate
+pub fn allocated(state: &State, allocator: &AllocatorStats) -> usize {
+    if allocator.allocated > 0 {
+        allocator.allocated
+    } else {
+        used_memory(state)
+    }
+}
+
+// How many times larger a is than b, formatted as INFO does ratios
+pub fn ratio(a: usize, b: usize) -> String {
+    if b == 0 {
+        String::from("0.00")
+    } else {
+        format!("{:.2}", a as f64 / b as f64)
+    }
+}
+
+// Format a byte count the way INFO does, for example 1.50M
+pub fn human(bytes: usize) -> String {
+    const UNITS: [&str; 5] = ["K", "M", "G", "T", "P"];
+
+    if bytes < 1024 {
+        return format!("{bytes}B");
+    }
+
+    let mut value = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    format!("{value:.2}{}", UNITS[unit])
+}
+
+// Plain language advice for MEMORY DOCTOR
+pub fn doctor(state: &State) -> String {
+    if state.keystore.is_empty() {
+        return String::from(
+            "Hi Sam, this instance is empty or is using very little memory, my issues detector can't be used in these conditions. Please, leave for your mission on Earth and fill it with some data. The new Sam and I will be back to our programming as soon as I finished rebooting.",
+        );
+    }
+
+    let mut issues = Vec::new();
+
+    let maxmemory = state.config.maxmemory;
+    let used = used_memory(state);
+    if maxmemory > 0 && used * 10 > maxmemory * 9 {
+        issues.push(format!(
+            " * High memory usage: {} of the {} maxmemory limit is in use. Consider raising maxmemory or configuring an eviction policy.",
+            human(used),
+            human(maxmemory)
+        ));
+    }
+
+    let clients = state.clients.len().max(1);
+    if clients_usage(state) / clients > 200 * 1024 {
+        issues.push(String::from(
+            " * Big client buffers: the average client is using more than 200k of query and output buffers. Check CLIENT LIST for slow readers or very large pipelines.",
+        ));
+    }
+
+    if issues.is_empty() {
+        String::from("Hi Sam, I can't find any memory issue in your instance. I can only account for what occurs on this base.")
+    } else {
+        format!(
+            "Sam, I detected a few issues in this Redis instance memory implants:\n\n{}\n\nI'm here to keep you safe, Sam. I want to help you.",
+            issues.join("\n\n")
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_allocation_size() {
+        assert_eq!(allocation_size(1), 8);
+        assert_eq!(allocation_size(17), 32);
+        assert_eq!(allocation_size(128), 128);
+        assert_eq!(allocation_size(129), 160);
+        assert_eq!(allocation_size(257), 320);
+        assert_eq!(allocation_size(1000), 1024);
+    }
+
+    #[test]
+    fn test_string_usage() {
+        // Integers live in the object, short strings share its allocation
+        assert_eq!(string_usage(b"12345"), 16);
+        assert_eq!(string_usage(b"hello"), 32);
+        assert!(string_usage(&[b'x'; 100]) > 100);
+    }
+
+    #[test]
+    fn test_human() {
+        assert_eq!(human(512), "512B");
+        assert_eq!(human(1536), "1.50K");
+        assert_eq!(human(3 * 1024 * 1024), "3.00M");
+    }
+
+    #[test]
+    fn test_ratio() {
+        assert_eq!(ratio(150, 100), "1.50");
+        assert_eq!(ratio(150, 0), "0.00");
+    }
+}
@@ -0,0 +1,1923 @@
+mod allocator;
+mod aof;
+mod blocking;
+mod clients;
+mod cluster;
+mod commands;
+pub mod config;
+mod connection;
+mod error;
+mod expire;
+mod glob;
+mod hotkeys;
+mod info;
+mod json;
+mod latency;
+mod lifecycle;
+pub mod logging;
+mod memory;
+mod output;
+mod plugin;
+mod pubsub;
+mod rdb;
+mod reads;
+mod replication;
+mod request;
+mod scan;
+mod sentinel;
+mod stats;
+pub mod systemd;
+mod tracking;
+#[cfg(feature = "io-uring")]
+mod uring;
+
+use crate::cluster::key_slot;
+use crate::value::Value;
+use crate::{Protocol, RedisType};
+use bytes::Bytes;
+use clients::{Client, ClientInfo, Pause};
+use commands::COMMANDS;
+use config::BufferLimit;
+pub use config::Config;
+use connection::{Reader, Stream};
+use error::ServerError;
+use latency::LatencyMonitor;
+use lifecycle::{Shutdown, ShutdownListener};
+use output::OutputBuffer;
+pub use plugin::{CommandHandler, CustomCommand, Db, Keys, Reply};
+use replication::{FullSync, Replication};
+pub use sentinel::DEFAULT_PORT as SENTINEL_PORT;
+use socket2::{SockRef, TcpKeepalive};
+use stats::Stats;
+use std::collections::{BTreeMap, HashMap};
+use std::net::{SocketAddr, ToSocketAddrs};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::net::TcpListener;
+use tokio::sync::{mpsc, Mutex, Notify};
+use tokio::task::JoinHandle;
+use tracing::Instrument;
+
+// Reported by HELLO, clients use this to decide which features they can rely on
+const REDIS_VERSION: &str = "7.0.0";
+
+const PROTECTED_MODE_ERROR: &str = "DENIED Redis is running in protected mode because protected mode is enabled and no password is set for the default user. In this mode connections are only accepted from the loopback interface. If you want to connect from external computers to Redis you may adopt one of the following solutions: 1) Just disable protected mode sending the command 'CONFIG SET protected-mode no' from the loopback interface by connecting to Redis from the same host the server is running, however MAKE SURE Redis is not publicly accessible from internet if you do so. 2) Alternatively you can just disable the protected mode by editing the Redis configuration file, and setting the protected mode option to 'no', and then restarting the server. 3) If you started the server manually just for testing, restart it with the '--protected-mode no' option. 4) Set up an authentication password for the default user. NOTE: You only need to do one of the above things in order for the server to start accepting connections from the outside.";
+
+// A server to run in this process, configured the same way as the redis-rs binary
+// For example, in a test:
+//
+//     let server = Server::bind("127.0.0.1:0").spawn().await?;
+//     let stream = TcpStream::connect(("127.0.0.1", server.port())).await?;
+//     ...
+//     server.shutdown().await?;
+//
+// Unlike the binary, an embedded server doesn't save snapshots unless it's given save points
+// and leaves signals to the rest of the program. Changing dir moves the whole process.
+pub struct Server {
+    config: Result<Config, String>,
+    // Whether the config still needs to be applied to the process (its dir and log level)
+    apply: bool,
+    sentinel: bool,
+    signals: bool,
+    systemd: bool,
+    activated: Vec<std::net::TcpListener>,
+    commands: Vec<CustomCommand>,
+}
+
+impl Server {
+    // Serve with a config that's already been loaded and applied, as the binary does
+    pub fn new(config: Config) -> Server {
+        Server {
+            config: Ok(config),
+            apply: false,
+            sentinel: false,
+            signals: false,
+            systemd: false,
+            activated: Vec::new(),
+            commands: Vec::new(),
+        }
+    }
+
+    // Listen on addr, which can have port 0 to be given any free port
+    pub fn bind(addr: impl ToSocketAddrs) -> Server {
+        let mut config = Config {
+            save: Vec::new(),
+            ..Config::default()
+        };
+        let result = addr.to_socket_addrs().map(|mut addrs| addrs.next());
+        let config = match result {
+            Ok(Some(addr)) => {
+                config.bind = vec![addr.ip().to_string()];
+                config.port = addr.port();
+                Ok(config)
+            }
+            Ok(None) => Err(String::from("No address to bind to")),
+            Err(e) => Err(format!("Invalid address to bind to: {e}")),
+        };
+
+        Server {
+            config,
+            apply: true,
+            sentinel: false,
+            signals: false,
+            systemd: false,
+            activated: Vec::new(),
+            commands: Vec::new(),
+        }
+    }
+
+    // Set a parameter as it would be set in redis.conf, for example config("maxmemory", "10mb")
+    pub fn config(mut self, name: &str, value: &str) -> Server {
+        self.config = self.config.and_then(|mut config| {
+            let parameter = config::find_parameter(name)
+                .ok_or_else(|| format!("Unknown parameter '{name}'"))?;
+            config
+                .set_initial(parameter, value)
+                .map_err(|e| format!("Invalid {name}: {e}"))?;
+            Ok(config)
+        });
+        self
+    }
+
+    // Run as a sentinel, monitoring the masters in the config's sentinel directives
+    pub fn sentinel(mut self) -> Server {
+        self.sentinel = true;
+        self
+    }
+
+    // Shut down on SIGINT and SIGTERM
+    pub fn handle_signals(mut self) -> Server {
+        self.signals = true;
+        self
+    }
+
+    // When started by systemd, serve on the sockets it passed (with socket activation, from
+    // systemd::take_listeners) instead of binding if there are any, and tell it when the server is
+    // ready and stopping, and that it's still running if its watchdog is on
+    pub fn systemd(mut self, listeners: Vec<std::net::TcpListener>) -> Server {
+        self.systemd = true;
+        self.activated = listeners;
+        self
+    }
+
+    // Add a command of the program's own, see plugin.rs
+    pub fn command(mut self, command: CustomCommand) -> Server {
+        self.commands.push(command);
+        self
+    }
+
+    // Load the data, start listening, and serve clients in the background
+    pub async fn spawn(self) -> std::io::Result<ServerHandle> {
+        let config = self.config.map_err(std::io::Error::other)?;
+        let commands = plugin::Commands::new(self.commands).map_err(std::io::Error::other)?;
+        if self.apply {
+            config.apply().map_err(std::io::Error::other)?;
+        }
+
+        start(
+            config,
+            commands,
+            self.sentinel,
+            self.signals,
+            self.systemd,
+            self.activated,
+        )
+        .await
+    }
+
+    // Serve until the server shuts down, from SHUTDOWN or a signal
+    pub async fn run(self) -> std::io::Result<()> {
+        self.spawn().await?.wait().await
+    }
+}
+
+// A server running in the background, which keeps running if this is dropped
+pub struct ServerHandle {
+    addrs: Vec<SocketAddr>,
+    state: Arc<Mutex<State>>,
+    task: JoinHandle<std::io::Result<()>>,
+}
+
+impl ServerHandle {
+    // The first address the server is listening on, with the port it was given
+    pub fn addr(&self) -> SocketAddr {
+        self.addrs[0]
+    }
+
+    pub fn addrs(&self) -> &[SocketAddr] {
+        &self.addrs
+    }
+
+    pub fn port(&self) -> u16 {
+        self.addr().port()
+    }
+
+    // Shut down as a signal would: clients get their last replies, and a snapshot is saved if
+    // there are save points
+    pub async fn shutdown(self) -> std::io::Result<()> {
+        self.state.lock().await.shutdown.trigger();
+        self.wait().await
+    }
+
+    // Wait for the server to shut down by itself
+    pub async fn wait(self) -> std::io::Result<()> {
+        self.task.await?
+    }
+}
+
+// Load the data and start serving, returning once the server is listening
+async fn start(
+    config: Config,
+    commands: plugin::Commands,
+    sentinel: bool,
+    signals: bool,
+    systemd: bool,
+    activated: Vec<std::net::TcpListener>,
+) -> std::io::Result<ServerHandle> {
+    let pidfile = config.pidfile.clone();
+    if let Some(path) = &pidfile {
+        if let Err(e) = lifecycle::write_pidfile(path) {
+            tracing::warn!("Unable to write pidfile {}: {e}", path.display());
+        }
+    }
+
+    let mut config = config;
+    let mut listeners = Vec::new();
+    let mut addrs = Vec::new();
+    if !activated.is_empty() {
+        if config.io_uring {
+            return Err(std::io::Error::other(
+                "io-uring can't use sockets passed by systemd",
+            ));
+        }
+        for listener in activated {
+            let listener = TcpListener::from_std(listener)?;
+            tracing::info!(
+                "Listening on {} (passed by systemd)",
+                listener.local_addr()?
+            );
+            addrs.push(listener.local_addr()?);
+            listeners.push(listener);
+        }
+        config.port = addrs[0].port();
+    } else if config.io_uring {
+        // Each io_uring thread binds for itself, which only works if they agree on the port
+        if config.port == 0 {
+            return Err(std::io::Error::other("io-uring needs a port other than 0"));
+        }
+        for bind in &config.bind {
+            addrs.extend((bind.as_str(), config.port).to_socket_addrs()?);
+        }
+    } else {
+        for bind in &config.bind {
+            let listener = TcpListener::bind((bind.as_str(), config.port)).await?;
+            tracing::info!("Listening on {}", listener.local_addr()?);
+            addrs.push(listener.local_addr()?);
+            listeners.push(listener);
+        }
+        // Report the port that was picked, rather than 0
+        if let Some(addr) = addrs.first() {
+            config.port = addr.port();
+        }
+    }
+
+    if config.is_protected() {
+        tracing::warn!("Protected mode is enabled, only loopback connections will be accepted");
+    }
+
+    let mut state = State {
+        config,
+        commands,
+        ..State::default()
+    };
+
+    // Restore the last snapshot before accepting any connections, the AOF is more up to date
+    // so it takes precedence when enabled
+    let start = Instant::now();
+    if sentinel {
+        match sentinel::Sentinel::from_directives(&state.config.sentinel) {
+            Ok(sentinel) => {
+                tracing::info!("Sentinel ID is {}", sentinel.myid);
+                state.sentinel = Some(sentinel);
+            }
+            Err(e) => {
+                return Err(std::io::Error::other(format!(
+                    "Invalid sentinel configuration: {e}"
+                )))
+            }
+        }
+        if state.config.config_file.is_none() {
+            tracing::warn!("Sentinel started without a config file, failovers won't be saved");
+        }
+        sentinel::save(&mut state);
+    } else if state.config.appendonly {
+        let appendfilename = PathBuf::from(&state.config.appendfilename);
+        match load_aof(&mut state, &appendfilename) {
+            Ok(Some(commands)) => tracing::info!(
+                "DB loaded from append only file: {commands} commands in {:.3} seconds",
+                start.elapsed().as_secs_f64()
+            ),
+            Ok(None) => {
+                tracing::info!("No append only file found, starting with an empty database")
+            }
+            Err(e) => {
+                return Err(std::io::Error::other(format!(
+                    "Error loading {}: {e}",
+                    appendfilename.display()
+                )))
+            }
+        }
+
+        match aof::Aof::open(&appendfilename, state.config.appendfsync) {
+            Ok(aof) => state.aof = Some(aof),
+            Err(e) => {
+                return Err(std::io::Error::other(format!(
+                    "Can't open the append-only file {}: {e}",
+                    appendfilename.display()
+                )))
+            }
+        }
+    } else {
+        let dbfilename = PathBuf::from(&state.config.dbfilename);
+        match rdb::load(&mut state, &dbfilename) {
+            Ok(Some(keys)) => tracing::info!(
+                "DB loaded from disk: {keys} keys in {:.3} seconds",
+                start.elapsed().as_secs_f64()
+            ),
+            Ok(None) => tracing::info!("No snapshot found, starting with an empty database"),
+            Err(e) => {
+                return Err(std::io::Error::other(format!(
+                    "Error loading {}: {e}",
+                    dbfilename.display()
+                )))
+            }
+        }
+    }
+
+    if let Some((host, port)) = state.config.replicaof.clone() {
+        state.replication.replicate_from(host, port);
+    }
+
+    if state.config.cluster_enabled {
+        let path = PathBuf::from(&state.config.cluster_config_file);
+        match cluster::load(&path) {
+            Ok(Some(cluster)) => state.cluster = cluster,
+            Ok(None) => cluster::save(&state),
+            Err(e) => {
+                return Err(std::io::Error::other(format!(
+                    "Error loading the cluster config {}: {e}",
+                    path.display()
+                )))
+            }
+        }
+        tracing::info!("Cluster node id {}", state.cluster.myid);
+    }
+
+    reads::publish(&state);
+    let state = Arc::new(Mutex::new(state));
+
+    // Stopped when the server starts shutting down, and waited for before the final save, so
+    // they don't expire keys or start a background save behind it
+    let mut loops = Vec::new();
+    let expire_state = state.clone();
+    let mut shutdown = state.lock().await.shutdown.subscribe();
+    loops.push(tokio::spawn(async move {
+        loop {
+            tokio::select! {
+                _ = tokio::time::sleep(expire::CYCLE_PERIOD) => {}
+                _ = shutdown.wait() => return,
+            }
+            expire::active_expire_cycle(&expire_state).await;
+        }
+    }));
+
+    let cron_state = state.clone();
+    let mut shutdown = state.lock().await.shutdown.subscribe();
+    loops.push(tokio::spawn(async move {
+        loop {
+            let link_state = cron_state.clone();
+            let mut cron_state = cron_state.lock().await;
+            reads::flush(&mut cron_state);
+            reads::publish(&cron_state);
+
+            // Background saves for save points and BGSAVE SCHEDULE
+            let start_save = {
+                let saves = cron_state.saves.lock().unwrap();
+                cron_state.sentinel.is_none()
+                    && saves.in_progress_since.is_none()
+                    && (saves.scheduled || saves.save_point_reached(&cron_state.config.save))
+            };
+            if start_save {
+                tracing::info!("Starting background save");
+                let path = PathBuf::from(&cron_state.config.dbfilename);
+                if let Err(e) = rdb::start_bgsave(&cron_state, path) {
+                    tracing::warn!("Unable to start background save: {e}");
+                }
+            }
+
+            cron_state.replication.cron();
+            replication::update_failover(&mut cron_state);
+            if let Some(sentinel) = cron_state.sentinel.as_mut() {
+                for (name, cancel) in sentinel.unstarted() {
+                    tokio::spawn(sentinel::monitor(link_state.clone(), name, cancel));
+                }
+            }
+            for (ip, port) in cron_state.cluster.take_meets() {
+                tokio::spawn(cluster::meet(link_state.clone(), ip, port));
+            }
+            if let Some((host, port, cancel)) = cron_state.replication.connect() {
+                tokio::spawn(replication::run_link(link_state, host, port, cancel));
+            }
+            if let Some(aof) = cron_state.aof.as_mut() {
+                if let Err(e) = aof.fsync_if_due() {
+                    tracing::warn!("Error syncing the append only file: {e}");
+                }
+            }
+            drop(cron_state);
+
+            tokio::select! {
+                _ = tokio::time::sleep(Duration::from_secs(1)) => {}
+                _ = shutdown.wait() => return,
+            }
+        }
+    }));
+
+    if signals {
+        let signal_state = state.clone();
+        tokio::spawn(async move {
+            lifecycle::wait_for_signal().await;
+            signal_state.lock().await.shutdown.trigger();
+        });
+        #[cfg(unix)]
+        tokio::spawn(lifecycle::reload_on_hangup(state.clone()));
+    }
+
+    // Each connection holds a clone of this sender, so once they are all dropped we know
+    // every connection has finished
+    let (connections, connections_done) = mpsc::channel::<()>(1);
+
+    let mut accept_tasks = Vec::new();
+    for listener in listeners {
+        accept_tasks.push(tokio::spawn(accept(
+            listener,
+            state.clone(),
+            connections.clone(),
+        )));
+    }
+    #[cfg(feature = "io-uring")]
+    if state.lock().await.config.io_uring {
+        accept_tasks.extend(uring::listen(&state, &connections).await?);
+    }
+    drop(connections);
+
+    if systemd {
+        systemd::notify("READY=1\nSTATUS=Ready to accept connections");
+        if let Some(interval) = systemd::watchdog_interval() {
+            let watchdog_state = state.clone();
+            tokio::spawn(async move {
+                loop {
+                    tokio::time::sleep(interval / 2).await;
+                    // Only while the state can be locked, so a server that's stuck gets restarted
+                    drop(watchdog_state.lock().await);
+                    systemd::notify("WATCHDOG=1");
+                }
+            });
+        }
+    }
+
+    let task = tokio::spawn(finish(
+        state.clone(),
+        accept_tasks,
+        loops,
+        connections_done,
+        pidfile,
+        systemd,
+    ));
+    Ok(ServerHandle { addrs, state, task })
+}
+
+// Wait for the server to stop accepting connections, then for its clients to finish
+async fn finish(
+    state: Arc<Mutex<State>>,
+    accept_tasks: Vec<JoinHandle<std::io::Result<()>>>,
+    loops: Vec<JoinHandle<()>>,
+    mut connections_done: mpsc::Receiver<()>,
+    pidfile: Option<PathBuf>,
+    systemd: bool,
+) -> std::io::Result<()> {
+    let mut result = Ok(());
+    for task in accept_tasks {
+        if let Err(e) = task.await? {
+            tracing::error!("Error accepting connections: {e}");
+            state.lock().await.shutdown.trigger();
+            result = Err(e);
+        }
+    }
+
+    tracing::info!("No longer accepting connections, waiting for clients to finish");
+    if systemd {
+        systemd::notify("STOPPING=1");
+    }
+    let drain_timeout = state.lock().await.config.shutdown_drain_timeout;
+    if tokio::time::timeout(Duration::from_secs(drain_timeout), connections_done.recv())
+        .await
+        .is_err()
+    {
+        tracing::warn!("Timed out waiting for clients to disconnect");
+    }
+    for task in loops {
+        task.await?;
+    }
+
+    // Shutting down because of a signal, save if there are save points like Redis does
+    {
+        let mut state = state.lock().await;
+        if let Some(aof) = state.aof.as_mut() {
+            tracing::info!("Calling fsync() on the AOF file.");
+            if let Err(e) = aof.sync() {
+                tracing::error!("Error syncing the append only file: {e}");
+            }
+        }
+        if !state.shutdown_save_handled && !state.config.save.is_empty() && state.sentinel.is_none()
+        {
+            tracing::info!("Saving the final RDB snapshot before exiting.");
+            match rdb::save(&state, Path::new(&state.config.dbfilename)) {
+                Ok(()) => tracing::info!("DB saved on disk"),
+                Err(e) => tracing::error!("Error trying to save the DB: {e}"),
+            }
+        }
+    }
+
+    if let Some(path) = &pidfile {
+        lifecycle::remove_pidfile(path);
+    }
+
+    tracing::info!("Redis is now ready to exit, bye bye...");
+    result
+}
+
+async fn accept(
+    listener: TcpListener,
+    state: Arc<Mutex<State>>,
+    connections: mpsc::Sender<()>,
+) -> std::io::Result<()> {
+    let mut shutdown = state.lock().await.shutdown.subscribe();
+
+    loop {
+        let (stream, addr) = tokio::select! {
+            result = listener.accept() => result?,
+            _ = shutdown.wait() => return Ok(()),
+        };
+        let thread_state = state.clone();
+        let connection = connections.clone();
+
+        tracing::debug!("Accepted connection from {addr:?}");
+        tokio::spawn(async move {
+            if let Err(e) = handle(stream, addr, thread_state).await {
+                tracing::warn!("An error occurred: {e:?}");
+            }
+            drop(connection);
+        });
+    }
+}
+
+async fn handle<S: Stream>(
+    stream: S,
+    addr: SocketAddr,
+    state: Arc<Mutex<State>>,
+) -> std::io::Result<()> {
+    tracing::info!("[{addr}] Accepted connection");
+
+    let (protected, requirepass, keepalive, mut shutdown, reads) = {
+        let state = state.lock().await;
+        (
+            state.config.is_protected(),
+            state.config.requirepass.is_some(),
+            state.config.tcp_keepalive,
+            state.shutdown.subscribe(),
+            state.reads.clone(),
+        )
+    };
+
+    // Lets the OS notice peers that have gone away without closing the connection
+    if keepalive > 0 {
+        let keepalive = TcpKeepalive::new().with_time(Duration::from_secs(keepalive));
+        if let Err(e) = SockRef::from(&stream).set_tcp_keepalive(&keepalive) {
+            tracing::warn!("[{addr}] Unable to enable TCP keepalive: {e}");
+        }
+    }
+
+    let local_addr = SockRef::from(&stream)
+        .local_addr()?
+        .as_socket()
+        .ok_or_else(|| std::io::Error::other("not a TCP socket"))?;
+    let (reader, mut output) = stream.split();
+
+    if protected && !addr.ip().is_loopback() {
+        tracing::warn!("[{addr}] Refusing connection from non-loopback address in protected mode");
+        let error = RedisType::Error {
+            value: String::from(PROTECTED_MODE_ERROR),
+        };
+        let _ = output.push(
+            vec![Bytes::from(error.to_string())],
+            &BufferLimit::default(),
+        );
+        return output.close().await;
+    }
+
+    let mut client = Client::new(addr, local_addr);
+    client.authenticated = !requirepass;
+    let (pushes, pushed) = tokio::sync::mpsc::unbounded_channel();
+    client.pushes = pushes;
+    {
+        let mut state = state.lock().await;
+        state.clients.insert(client.id, client.info());
+        state.stats.total_connections_received += 1;
+    }
+
+    let result = serve(
+        reader,
+        output,
+        &mut client,
+        pushed,
+        &state,
+        &reads,
+        &mut shutdown,
+    )
+    .await;
+
+    {
+        let mut state = state.lock().await;
+        state.clients.remove(&client.id);
+        state.tracking.disable(client.id);
+        state.pubsub.remove(client.id);
+        state.replication.detach(client.id);
+    }
+    tracing::info!("[{addr}] Ending connection");
+
+    result
+}
+
+// Read and run commands until the client disconnects or has to be disconnected
+async fn serve(
+    mut reader: impl Reader,
+    mut output: OutputBuffer,
+    client: &mut Client,
+    mut pushed: tokio::sync::mpsc::UnboundedReceiver<RedisType>,
+    state: &Arc<Mutex<State>>,
+    reads: &reads::Reads,
+    shutdown: &mut ShutdownListener,
+) -> std::io::Result<()> {
+    let addr = client.addr;
+    let mut input = Vec::new();
+    let mut killed = client.kill.subscribe();
+
+    loop {
+        let reads::Limits {
+            output: output_limit,
+            query: query_limit,
+            bulk: bulk_limit,
+            timeout,
+            drain_timeout,
+        } = reads.limits();
+
+        // A timeout of 0 means connections may stay idle forever, and subscribers are waiting for
+        // messages rather than idle
+        let idle = async {
+            if timeout == 0 || client.subscribed() {
+                std::future::pending::<()>().await;
+            } else {
+                tokio::time::sleep(Duration::from_secs(timeout)).await;
+            }
+        };
+
+        let limit = output_limit_for(client, &output_limit);
+        let soft_limit_deadline = output.soft_limit_deadline(&limit);
+        let soft_limit_expired = async {
+            match soft_limit_deadline {
+                Some(deadline) => tokio::time::sleep_until(deadline.into()).await,
+                None => std::future::pending::<()>().await,
+            }
+        };
+
+        // On shutdown, stop reading new commands but still flush replies that are already queued
+        let drain_timeout = Duration::from_secs(drain_timeout);
+        if shutdown.is_triggered() {
+            return drain(reader, output, client, &mut input, drain_timeout).await;
+        }
+        let bytes_read = tokio::select! {
+            result = reader.read(&mut input) => result?,
+            // Sent by other connections, between replies
+            Some(push) = pushed.recv() => {
+                let encoded = push.encode_segments(client.encoding());
+                if let Err(reason) = output.push(encoded, &limit).and_then(|_| output.flush()) {
+                    tracing::warn!("[{addr}] Closing client: {reason}");
+                    output.abort();
+                    return Ok(());
+                }
+                continue;
+            }
+            _ = shutdown.wait() => {
+                return drain(reader, output, client, &mut input, drain_timeout).await;
+            }
+            _ = killed.wait() => {
+                tracing::info!("[{addr}] Closing connection killed by CLIENT KILL");
+                break;
+            }
+            _ = idle => {
+                tracing::info!("[{addr}] Closing idle client");
+                break;
+            }
+            // Still over the soft limit without anything more being sent to it
+            _ = soft_limit_expired => {
+                if let Err(reason) = output.check(&limit) {
+                    tracing::warn!("[{addr}] Closing client: {reason}");
+                    output.abort();
+                    return Ok(());
+                }
+                continue;
+            }
+        };
+        if bytes_read == 0 {
+            break;
+        }
+        tracing::debug!("[{addr}] Received {bytes_read} bytes");
+
+        if input.len() > query_limit {
+            tracing::warn!(
+                "[{addr}] Closing client that reached max query buffer length ({} bytes)",
+                input.len()
+            );
+            output.abort();
+            return Ok(());
+        }
+
+        let request_limits = request::Limits {
+            bulk: bulk_limit,
+            request: query_limit,
+        };
+        let (commands, error) = request::parse(&mut input, &request_limits);
+
+        client.query_buffer = input.len();
+        'commands: for command in commands {
+            client.output_memory = output.pending();
+            // The rest of a pipeline that was still running when shutdown started
+            if shutdown.is_triggered() {
+                let response = RedisType::from(ServerError::ShuttingDown);
+                let _ = output.push(
+                    response.encode_segments(client.encoding()),
+                    &BufferLimit::default(),
+                );
+                continue;
+            }
+            let response = match reads.execute(client, &command) {
+                Some(response) => {
+                    reads::flush_if_full(state, reads);
+                    response
+                }
+                // Blocking commands are run again each time they're woken, until they reply
+                None => loop {
+                    if let Some(response) = execute(state, client, &command).await {
+                        // Refused before it could run again (say, with MOVED), so no longer waiting
+                        if client.blocked.take().is_some() {
+                            state.lock().await.waiters.unpark(client.id);
+                        }
+                        break response;
+                    }
+                    if client.blocked.is_none() {
+                        continue 'commands;
+                    }
+
+                    // Replies to the commands before it go out while it waits
+                    if let Err(reason) = output.flush() {
+                        tracing::warn!("[{addr}] Closing client: {reason}");
+                        state.lock().await.waiters.unpark(client.id);
+                        output.abort();
+                        return Ok(());
+                    }
+                    match blocking::wait(state, client, &mut reader, &mut input, shutdown).await {
+                        blocking::Woken::Ready => {}
+                        blocking::Woken::TimedOut(reply) => break reply,
+                        blocking::Woken::ShuttingDown => break ServerError::ShuttingDown.into(),
+                        blocking::Woken::Closed => return output.close().await,
+                    }
+                },
+            };
+
+            // PSYNC and SYNC reply with the snapshot, sent once the connection becomes a replica
+            if let Some(sync) = client.full_sync.take() {
+                return serve_replica(reader, output, client, state, reads, shutdown, sync).await;
+            }
+
+            let limit = output_limit_for(client, &output_limit);
+            let replies = std::mem::take(&mut client.replies)
+                .into_iter()
+                .chain([response]);
+            for reply in replies {
+                if let Err(reason) = output.push(reply.encode_segments(client.encoding()), &limit) {
+                    tracing::warn!("[{addr}] Closing client: {reason}");
+                    output.abort();
+                    return Ok(());
+                }
+            }
+
+            // Don't run the rest of a pipeline once killed (including by this client itself)
+            if killed.is_triggered() {
+                break;
+            }
+
+            if client.close_after_reply {
+                tracing::debug!("[{addr}] Closing connection after QUIT");
+                return output.close().await;
+            }
+        }
+
+        // The commands before it have run, but there's no telling where the next one starts
+        if let Some(error) = error.filter(|_| !killed.is_triggered()) {
+            tracing::warn!("[{addr}] Closing client after protocol error: {error}");
+            let reply = RedisType::from(ServerError::Protocol(error.to_string()));
+            let _ = output.push(reply.encode_segments(client.encoding()), &limit);
+            return output.close().await;
+        }
+
+        // Replies to everything that was read go out together
+        if let Err(reason) = output.flush() {
+            tracing::warn!("[{addr}] Closing client: {reason}");
+            output.abort();
+            return Ok(());
+        }
+    }
+
+    output.close().await
+}
+
+// The output buffer limit for client's class, which is different once it's subscribed to anything
+fn output_limit_for(client: &Client, limits: &config::BufferLimits) -> BufferLimit {
+    if client.no_evict {
+        BufferLimit::default()
+    } else if client.subscribed() {
+        limits.pubsub
+    } else {
+        limits.normal
+    }
+}
+
+// Once the server is shutting down, answer anything the client has already sent with an error
+// rather than running it, then close the connection once every reply has been written (or the
+// drain timeout is up, so a client that doesn't read can't hold up the shutdown)
+async fn drain(
+    mut reader: impl Reader,
+    mut output: OutputBuffer,
+    client: &Client,
+    input: &mut Vec<u8>,
+    timeout: Duration,
+) -> std::io::Result<()> {
+    let addr = client.addr;
+    tracing::debug!("[{addr}] Closing connection for shutdown");
+
+    // Polled once, so this only takes what has already arrived
+    let _ = tokio::time::timeout(Duration::ZERO, reader.read(input)).await;
+    let (commands, _) = request::parse(input, &request::Limits::NONE);
+    for _ in commands {
+        let response = RedisType::from(ServerError::ShuttingDown);
+        let _ = output.push(
+            response.encode_segments(client.encoding()),
+            &BufferLimit::default(),
+        );
+    }
+
+    output.close_within(timeout).await
+}
+
+// After PSYNC or SYNC, send the connection a snapshot and then every write from then on
+// Replicas only send REPLCONF ACK back, which doesn't get a reply
+async fn serve_replica(
+    mut reader: impl Reader,
+    mut output: OutputBuffer,
+    client: &mut Client,
+    state: &Arc<Mutex<State>>,
+    reads: &reads::Reads,
+    shutdown: &mut ShutdownListener,
+    sync: FullSync,
+) -> std::io::Result<()> {
+    let addr = client.addr;
+    tracing::info!(
+        "[{addr}] Replica asks for synchronization, starting full resync with replid {} offset {}",
+        sync.replid,
+        sync.offset
+    );
+
+    // The snapshot was taken by PSYNC, so writes from then on are already queued in the stream
+    let snapshot = sync.snapshot;
+    let data = tokio::task::spawn_blocking(move || rdb::dump(&snapshot.entries()))
+        .await
+        .map_err(std::io::Error::other)?;
+
+    let mut payload = Vec::new();
+    if sync.psync {
+        payload.extend(format!("+FULLRESYNC {} {}\r\n", sync.replid, sync.offset).into_bytes());
+    }
+    payload.extend(format!("${}\r\n", data.len()).into_bytes());
+    payload.extend(data);
+
+    // The snapshot isn't held to the replica limit, only the writes after it, as in Redis
+    if let Err(reason) = output
+        .push(vec![Bytes::from(payload)], &BufferLimit::default())
+        .and_then(|_| output.flush())
+    {
+        tracing::warn!("[{addr}] Closing replica: {reason}");
+        output.abort();
+        return Ok(());
+    }
+    state.lock().await.replication.online(client.id);
+    tracing::info!("[{addr}] Synchronization with replica succeeded");
+
+    let mut stream = sync.stream;
+    let mut input = Vec::new();
+    let mut killed = client.kill.subscribe();
+
+    loop {
+        let limit = match client.no_evict {
+            true => BufferLimit::default(),
+            false => reads.limits().output.replica,
+        };
+        let soft_limit_deadline = output.soft_limit_deadline(&limit);
+        let soft_limit_expired = async {
+            match soft_limit_deadline {
+                Some(deadline) => tokio::time::sleep_until(deadline.into()).await,
+                None => std::future::pending::<()>().await,
+            }
+        };
+
+        tokio::select! {
+            data = stream.recv() => {
+                // Closed if the replica was detached from the master's side
+                let Some(data) = data else { break };
+                if let Err(reason) = output.push(vec![Bytes::from(data)], &limit).and_then(|_| output.flush()) {
+                    tracing::warn!("[{addr}] Closing replica: {reason}");
+                    output.abort();
+                    return Ok(());
+                }
+            }
+            result = reader.read(&mut input) => {
+                let bytes_read = result?;
+                if bytes_read == 0 {
+                    break;
+                }
+
+                let (commands, error) = request::parse(&mut input, &request::Limits::NONE);
+                if let Some(error) = error {
+                    tracing::warn!("[{addr}] Error parsing input from replica: {error}");
+                }
+                for command in commands {
+                    execute(state, client, &command).await;
+                }
+            }
+            _ = shutdown.wait() => break,
+            _ = killed.wait() => {
+                tracing::info!("[{addr}] Closing replica killed by CLIENT KILL");
+                break;
+            }
+            _ = soft_limit_expired => {
+                if let Err(reason) = output.check(&limit) {
+                    tracing::warn!("[{addr}] Closing replica: {reason}");
+                    output.abort();
+                    return Ok(());
+                }
+            }
+        }
+    }
+
+    tracing::info!("[{addr}] Connection with replica lost");
+    output.close().await
+}
+
+// Run a single command, returning the reply to send (if any)
+async fn execute(
+    state: &Arc<Mutex<State>>,
+    client: &mut Client,
+    command: &RedisType,
+) -> Option<RedisType> {
+    let addr = client.addr;
+
+    // Neither can come from the request parser, but if they did there'd be no telling what the
+    // client meant, so it's closed as for any other protocol error
+    let protocol_error = |client: &mut Client, error: &str| {
+        tracing::warn!("[{addr}] Closing client after protocol error: {error}");
+        client.close_after_reply = true;
+        Some(ServerError::Protocol(error.to_string()).into())
+    };
+
+    let command = match command {
+        RedisType::Array { value } => value,
+        data => {
+            tracing::warn!("[{addr}] Error, input should be array, got: {data:?}");
+            return protocol_error(client, "expected '*'");
+        }
+    };
+
+    if command.is_empty() {
+        tracing::warn!("[{addr}] Input command was empty");
+        return None;
+    }
+
+    let argv = &command;
+    let args = &command[1..];
+    let command = match &command[0] {
+        RedisType::String { value } => value.to_ascii_uppercase(),
+        _ => {
+            tracing::warn!(
+                "[{addr}] Input command must be a string, got {:?}",
+                command[0]
+            );
+            return protocol_error(client, "invalid command name");
+        }
+    };
+    let custom = match COMMANDS.contains_key(command.as_str()) {
+        true => None,
+        false => state.lock().await.commands.get(&command),
+    };
+    let definition = COMMANDS
+        .get(command.as_str())
+        .or(custom.as_ref().map(|custom| &custom.definition));
+
+    let keys = definition
+        .map(|definition| definition.keys(argv))
+        .unwrap_or_default()
+        .iter()
+        .map(RedisType::to_bytes)
+        .collect::<Vec<_>>();
+
+    let span = tracing::info_span!(
+        "command",
+        client = client.id,
+        %addr,
+        name = client.name.as_deref().unwrap_or(""),
+        %command,
+        keys = ?LoggedKeys(&keys),
+        duration_us = tracing::field::Empty,
+        outcome = tracing::field::Empty,
+    );
+
+    async {
+        tracing::debug!("Received: {args:?}");
+
+        Some(match definition {
+            Some(definition) if !definition.check_arity(argv.len()) => {
+                tracing::Span::current().record("outcome", "rejected");
+                state.lock().await.stats.record_rejected(&command);
+                ServerError::WrongArity(command.to_ascii_lowercase()).into()
+            }
+            Some(definition) if !client.authenticated && !definition.has_flag("no_auth") => {
+                tracing::Span::current().record("outcome", "rejected");
+                state.lock().await.stats.record_rejected(&command);
+                ServerError::NoAuth("Authentication required.").into()
+            }
+            Some(_)
+                if client.protocol == Protocol::Resp2
+                    && client.subscribed()
+                    && !pubsub::ALLOWED.contains(&command.as_str()) =>
+            {
+                tracing::Span::current().record("outcome", "rejected");
+                state.lock().await.stats.record_rejected(&command);
+                ServerError::Err(format!("Can't execute '{}': only (P|S)SUBSCRIBE / (P|S)UNSUBSCRIBE / PING / QUIT / RESET are allowed in this context", command.to_ascii_lowercase())).into()
+            }
+            Some(definition) => {
+                let mut command_state = wait_while_paused(state, client, definition).await?;
+                reads::flush(&mut command_state);
+
+                if command_state.sentinel.is_some()
+                    && !sentinel::COMMANDS.contains(&command.as_str())
+                {
+                    tracing::Span::current().record("outcome", "unknown");
+                    return Some(ServerError::unknown_command(argv).into());
+                }
+
+                // In a cluster, the node serving the keys' slot runs the command, and they all
+                // have to be in the same one
+                let asking = std::mem::take(&mut client.asking);
+                if command_state.config.cluster_enabled && !keys.is_empty() && !client.master {
+                    let slot = key_slot(&keys[0]);
+                    let missing_keys = keys
+                        .iter()
+                        .any(|key| !command_state.keystore.contains_key(key));
+                    let routed = if keys.iter().any(|key| key_slot(key) != slot) {
+                        Err(ServerError::CrossSlot)
+                    } else {
+                        command_state.cluster.route(slot, missing_keys, asking)
+                    };
+                    if let Err(error) = routed {
+                        tracing::Span::current().record("outcome", "rejected");
+                        command_state.stats.record_rejected(&command);
+                        return Some(error.into());
+                    }
+                }
+
+                // Replicas only take writes from their master
+                if definition.has_flag("write")
+                    && !client.master
+                    && command_state.replication.master.is_some()
+                    && command_state.config.replica_read_only
+                {
+                    tracing::Span::current().record("outcome", "rejected");
+                    command_state.stats.record_rejected(&command);
+                    return Some(ServerError::ReadOnly.into());
+                }
+
+                // Commands that could use more memory are refused once it's all used, other than
+                // writes from our master, which has already run them
+                if definition.has_flag("denyoom")
+                    && !client.master
+                    && memory::over_maxmemory(&mut command_state)
+                {
+                    tracing::Span::current().record("outcome", "rejected");
+                    command_state.stats.record_rejected(&command);
+                    return Some(ServerError::Oom.into());
+                }
+
+                // Keep the registry up to date both before (so that CLIENT LIST sees this command)
+                // and after (so that changes such as CLIENT SETNAME are visible)
+                client.last_interaction = Instant::now();
+                client.last_command = Some(command.to_ascii_lowercase());
+                command_state.clients.insert(client.id, client.info());
+
+                let mut hidden = expire::expire_keys(&mut command_state, client, &keys);
+
+                // Set again if it's still blocked after being woken
+                let previous = client.blocked.take();
+                let start = Instant::now();
+                let result = match (definition.subcommand_help(&command, args), &custom) {
+                    (Some(help), _) => help,
+                    // Without the lock, which the handler takes for each update it makes
+                    (None, Some(custom)) => {
+                        // Put back before the lock is let go, since the master's DEL for one of
+                        // them could arrive in the meantime (the handler's Db hides them anyway)
+                        expire::restore(&mut command_state, std::mem::take(&mut hidden));
+                        drop(command_state);
+                        let db = Db::new(state.clone(), client.id);
+                        let args = args.iter().map(RedisType::to_bytes).collect();
+                        let reply = custom.call(db, args).await;
+                        command_state = state.lock().await;
+                        reply.map_err(ServerError::Err)
+                    }
+                    (None, None) => definition.f.as_ref()(&mut command_state, client, args),
+                };
+                let elapsed = start.elapsed();
+
+                expire::restore(&mut command_state, hidden);
+
+                // The connection waits for it to be woken, without a reply for now
+                if result.is_err() {
+                    client.blocked = None;
+                }
+                if blocking::park(&mut command_state, client, previous) {
+                    tracing::Span::current().record("outcome", "blocked");
+                    return None;
+                }
+
+                command_state
+                    .stats
+                    .record_call(&command, elapsed, result.is_err());
+
+                // Custom commands have already passed on each change they made
+                if definition.has_flag("write") && result.is_ok() && custom.is_none() {
+                    command_state.saves.lock().unwrap().changes_since_save += 1;
+                    if command_state.aof.is_some() || command_state.replication.is_streaming() {
+                        let args = args.iter().map(RedisType::to_bytes).collect::<Vec<_>>();
+                        let argv = aof::propagated(&command, &args);
+                        propagate(&mut command_state, &argv);
+                    }
+                    command_state.waiters.signal_keys(&keys);
+                    tracking::invalidate(&mut command_state, &keys, Some(client.id));
+                }
+                if definition.has_flag("write") && result.is_ok() {
+                    client.write_offset = command_state.replication.offset;
+                }
+
+                // The client is told when what it has read changes
+                if client.tracking && definition.has_flag("readonly") && result.is_ok() {
+                    command_state.tracking.remember(client.id, &keys);
+                }
+
+                let event = if definition.has_flag("fast") {
+                    "fast-command"
+                } else {
+                    "command"
+                };
+                let threshold = command_state.config.latency_monitor_threshold;
+                command_state.latency.record(event, elapsed, threshold);
+
+                let span = tracing::Span::current();
+                span.record("duration_us", elapsed.as_micros() as u64);
+                span.record("outcome", if result.is_ok() { "ok" } else { "error" });
+
+                let slow = command_state.config.slow_command_threshold;
+                if slow >= 0 && elapsed.as_micros() > slow as u128 {
+                    tracing::warn!("Slow command");
+                } else {
+                    tracing::debug!("Command finished");
+                }
+
+                if !client.no_touch || command == "TOUCH" {
+                    let now = Instant::now();
+                    let state = &mut *command_state;
+                    for key in keys.iter() {
+                        if let Some(value) = state.keystore.get(key) {
+                            let rate = state.config.hotkeys_sample_rate;
+                            state.hotkeys.record(key, value, rate, now);
+                            state.last_access.insert(key.clone(), now);
+                        } else {
+                            state.last_access.remove(key);
+                        }
+                    }
+                }
+
+                command_state.clients.insert(client.id, client.info());
+                reads::publish(&command_state);
+
+                if std::mem::take(&mut client.no_reply) {
+                    return None;
+                }
+
+                match result {
+                    Ok(value) => value,
+                    Err(error) => error.into(),
+                }
+            }
+            None => {
+                tracing::Span::current().record("outcome", "unknown");
+                tracing::warn!("Unimplemented command: {command} {args:?}");
+                ServerError::unknown_command(argv).into()
+            }
+        })
+    }
+    .instrument(span)
+    .await
+}
+
+// Lock the state once no CLIENT PAUSE applies to this command, writes also wait out a FAILOVER
+// Returns None if the client was killed while waiting
+async fn wait_while_paused<'a>(
+    state: &'a Arc<Mutex<State>>,
+    client: &Client,
+    definition: &Command,
+) -> Option<tokio::sync::MutexGuard<'a, State>> {
+    let write = definition.has_flag("write");
+    let mut killed = client.kill.subscribe();
+
+    loop {
+        let guard = state.lock().await;
+        let failover = write && !client.master && guard.replication.failover.is_some();
+        let remaining = match guard.pause.and_then(|pause| pause.remaining(write)) {
+            Some(remaining) => remaining,
+            // A failover notifies unpaused once it's over, this is just in case it's missed
+            None if failover => Duration::from_secs(1),
+            None => return Some(guard),
+        };
+
+        // Created before unlocking so that an UNPAUSE can't be missed
+        let unpaused = guard.unpaused.clone();
+        let notified = unpaused.notified();
+        drop(guard);
+
+        tokio::select! {
+            _ = tokio::time::sleep(remaining) => {}
+            _ = notified => {}
+            _ = killed.wait() => return None,
+        }
+    }
+}
+
+// Everything the server knows, behind a single lock that each command holds while it runs
+// The exception is plain reads (see reads.rs), which are answered from the keystore as published
+// by the last command.
+#[derive(Debug, Default)]
+pub struct State {
+    config: Config,
+    shutdown: Shutdown,
+    clients: BTreeMap<u64, ClientInfo>,
+    pause: Option<Pause>,
+    unpaused: Arc<Notify>,
+    // Clients running a blocking command, waiting for what it needs to happen
+    waiters: blocking::Waiters,
+    // The keys each client with CLIENT TRACKING on may have cached
+    tracking: tracking::Tracking,
+    pubsub: pubsub::PubSub,
+    latency: LatencyMonitor,
+    stats: Stats,
+    // When each key was last used by a command, for idle times and LRU eviction
+    last_access: HashMap<Vec<u8>, Instant>,
+    // Sampled accesses, for DEBUG HOTKEYS and BIGKEYS
+    hotkeys: hotkeys::HotKeys,
+    // When used memory was last measured for maxmemory, and what it was
+    used_memory_sample: Option<(Instant, usize)>,
+    saves: Arc<std::sync::Mutex<rdb::SaveStatus>>,
+    // Set by SHUTDOWN once it has saved (or decided not to), so exiting doesn't save again
+    shutdown_save_handled: bool,
+    // Set by DEBUG SET-ACTIVE-EXPIRE 0, expired keys then stay around until it's turned back on
+    active_expire_disabled: bool,
+    // Open while appendonly is enabled
+    aof: Option<aof::Aof>,
+    replication: Replication,
+    cluster: cluster::Cluster,
+    // Only in sentinel mode, which watches other servers instead of holding data
+    sentinel: Option<sentinel::Sentinel>,
+    // Added by the program embedding the server
+    commands: plugin::Commands,
+    // Persistent so that snapshots can be taken without copying every key
+    keystore: im::HashMap<Vec<u8>, Value>,
+    // Snapshots of the keystore for SCANs in progress
+    scans: scan::Scans,
+    ttl: expire::Ttl,
+    // The keys as last published for GET, MGET and EXISTS, which don't need the lock to read them
+    reads: Arc<reads::Reads>,
+}
+
+// Log a write to the append only file, if there is one, and send it to any replicas
+fn propagate(state: &mut State, argv: &[Vec<u8>]) {
+    if let Some(aof) = state.aof.as_mut() {
+        if let Err(e) = aof.append(argv) {
+            tracing::error!(
+                "Error writing to the AOF file {}: {e}",
+                aof.path().display()
+            );
+        }
+    }
+    state.replication.propagate(argv);
+}
+
+// Replay every command in the append only file at path, returning how many there were
+fn load_aof(state: &mut State, path: &Path) -> Result<Option<usize>, String> {
+    let commands = match aof::load(path, state.config.aof_load_truncated)? {
+        Some(commands) => commands,
+        None => return Ok(None),
+    };
+
+    let unspecified = SocketAddr::from(([0, 0, 0, 0], 0));
+    let mut client = Client::new(unspecified, unspecified);
+    client.authenticated = true;
+
+    for argv in &commands {
+        let name = String::from_utf8_lossy(&argv[0]);
+        let definition = COMMANDS
+            .get(name.to_ascii_uppercase().as_str())
+            .ok_or_else(|| format!("Unknown command '{name}' reading the append only file"))?;
+        let args = argv[1..]
+            .iter()
+            .map(|arg| RedisType::from(arg.clone()))
+            .collect::<Vec<_>>();
+
+        if !definition.check_arity(argv.len()) {
+            return Err(format!(
+                "Wrong number of arguments for '{name}' in the append only file"
+            ));
+        }
+        definition.f.as_ref()(state, &mut client, &args)
+            .map_err(|e| format!("Error replaying '{name}' from the append only file: {e}"))?;
+    }
+
+    Ok(Some(commands.len()))
+}
+
+// Keys as they're logged, with anything that isn't printable ASCII escaped
+struct LoggedKeys<'a, K>(&'a [K]);
+
+impl<K: AsRef<[u8]>> std::fmt::Debug for LoggedKeys<'_, K> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_list()
+            .entries(
+                self.0
+                    .iter()
+                    .map(|key| key.as_ref().escape_ascii().to_string()),
+            )
+            .finish()
+    }
+}
+
+// Shared integers are never freed, which Redis reports with the largest refcount there is
+fn refcount(value: &Value) -> i64 {
+    if value.is_shared_integer() {
+        i32::MAX as i64
+    } else {
+        1
+    }
+}
+
+// What a command does and how it's used, for tools such as the client's tab completion
+#[derive(Clone, Debug, PartialEq)]
+pub struct CommandDocs {
+    pub name: &'static str,
+    pub summary: &'static str,
+    pub group: &'static str,
+    pub since: &'static str,
+    // One line per form, each starting with the name (such as "OBJECT ENCODING key")
+    pub usage: Vec<String>,
+    // For container commands such as CLIENT, otherwise empty
+    pub subcommands: Vec<String>,
+    // What the help text says after the usage lines, which can be empty
+    pub description: String,
+}
+
+// Every command the server knows, sorted by name
+pub fn command_docs() -> Vec<CommandDocs> {
+    let mut docs = COMMANDS
+        .iter()
+        .map(|(name, command)| CommandDocs {
+            name,
+            summary: command.summary,
+            group: command.group,
+            since: command.since,
+            usage: command.usage().map(String::from).collect(),
+            subcommands: command
+                .subcommands(name)
+                .into_iter()
+                .map(String::from)
+                .collect(),
+            description: command.description().to_string(),
+        })
+        .collect::<Vec<_>>();
+    docs.sort_by_key(|docs| docs.name);
+    docs
+}
+
+// The keys a command accesses, given its full argv, as the server would find them (none if the
+// command isn't known or has the wrong number of arguments), for clients picking a cluster node
+pub fn command_keys(argv: &[RedisType]) -> Vec<Vec<u8>> {
+    let Some(name) = argv.first() else {
+        return vec![];
+    };
+    let name = String::from_utf8_lossy(&name.to_bytes()).to_ascii_uppercase();
+    match COMMANDS.get(name.as_str()) {
+        Some(command) if command.check_arity(argv.len()) => {
+            command.keys(argv).iter().map(RedisType::to_bytes).collect()
+        }
+        _ => vec![],
+    }
+}
+
+type CommandFn = fn(&mut State, &mut Client, &[RedisType]) -> Result<RedisType, ServerError>;
+
+// Where the keys are in a command's arguments, counting the command name as 0
+#[derive(Copy, Clone, Debug)]
+pub enum KeySpec {
+    None,
+    // A negative last counts back from the end (-1 being the last argument)
+    Range {
+        first: i64,
+        last: i64,
+        step: i64,
+    },
+    // Keys that can't be found from fixed positions, such as those after a numkeys argument
+    #[allow(dead_code)]
+    Custom(fn(&[RedisType]) -> Vec<usize>),
+}
+
+impl KeySpec {
+    const FIRST: KeySpec = KeySpec::Range {
+        first: 1,
+        last: 1,
+        step: 1,
+    };
+
+    // Indexes of the keys in argv (which includes the command name)
+    fn positions(&self, argv: &[RedisType]) -> Vec<usize> {
+        match *self {
+            KeySpec::None => vec![],
+            KeySpec::Range { first, last, step } => {
+                let last = if last < 0 {
+                    argv.len() as i64 + last
+                } else {
+                    last
+                };
+
+                (first..=last.min(argv.len() as i64 - 1))
+                    .step_by(step.max(1) as usize)
+                    .map(|i| i as usize)
+                    .collect()
+            }
+            KeySpec::Custom(f) => f(argv),
+        }
+    }
+}
+
+// The flags a command can have, with Redis' names, reported by COMMAND INFO
+// The server acts on write (refused on read only replicas), readonly (tracked for client side
+// caching), denyoom (refused over maxmemory), fast (for the latency monitor) and no_auth. The rest
+// describe the command for clients and tools.
+const FLAGS: &[&str] = &[
+    "write",
+    "readonly",
+    "denyoom",
+    "admin",
+    "pubsub",
+    "noscript",
+    "blocking",
+    "loading",
+    "stale",
+    "fast",
+    "no_auth",
+    "no_multi",
+    "no_async_loading",
+    "allow_busy",
+    "protected",
+    "sentinel",
+    "only_sentinel",
+];
+
+pub struct Command {
+    summary: &'static str,
+    group: &'static str,
+    since: &'static str,
+    // Number of arguments including the command name, negative means at least that many
+    arity: i64,
+    flags: &'static [&'static str],
+    keys: KeySpec,
+    // Starts with how the command is used, one line per form (such as "OBJECT ENCODING key"), then
+    // a blank line and a description
+    help: String,
+    f: Box<CommandFn>,
+}
+
+impl Command {
+    fn has_flag(&self, flag: &str) -> bool {
+        debug_assert!(FLAGS.contains(&flag), "unknown command flag {flag}");
+        self.flags.contains(&flag)
+    }
+
+    // argc includes the command name
+    fn check_arity(&self, argc: usize) -> bool {
+        if self.arity < 0 {
+            argc as i64 >= -self.arity
+        } else {
+            argc as i64 == self.arity
+        }
+    }
+
+    // The keys this command will access, given the full argv including the command name
+    fn keys(&self, argv: &[RedisType]) -> Vec<RedisType> {
+        self.keys
+            .positions(argv)
+            .into_iter()
+            .map(|i| argv[i].clone())
+            .collect()
+    }
+
+    // The usage lines at the start of the help text
+    fn usage(&self) -> impl Iterator<Item = &str> {
+        self.help
+            .lines()
+            .map(str::trim_end)
+            .skip_while(|line| line.is_empty())
+            .take_while(|line| !line.is_empty())
+    }
+
+    // The rest of the help text, after the usage lines
+    fn description(&self) -> &str {
+        let help = self.help.trim();
+        help.split_once("\n\n").map_or("", |(_, rest)| rest.trim())
+    }
+
+    // The subcommands of a container command such as OBJECT or CLIENT, from its usage lines
+    // Empty if any of those lines go on with an argument or option rather than a subcommand.
+    fn subcommands(&self, name: &str) -> Vec<&str> {
+        let mut subcommands = Vec::new();
+        for line in self.usage() {
+            let Some(rest) = line.strip_prefix(name) else {
+                return vec![];
+            };
+            let Some(subcommand) = rest.split_whitespace().next() else {
+                continue;
+            };
+            if !subcommand.starts_with(|c: char| c.is_ascii_uppercase())
+                || !subcommand
+                    .chars()
+                    .all(|c| c.is_ascii_uppercase() || c.is_ascii_digit() || c == '-')
+            {
+                return vec![];
+            }
+            if !subcommands.contains(&subcommand) {
+                subcommands.push(subcommand);
+            }
+        }
+        subcommands
+    }
+
+    // Reply for <command> HELP on a container command, in the format Redis uses, or None if this
+    // isn't one
+    fn subcommand_help(
+        &self,
+        name: &str,
+        args: &[RedisType],
+    ) -> Option<Result<RedisType, ServerError>> {
+        match args.first() {
+            Some(RedisType::String { value }) if value.eq_ignore_ascii_case("HELP") => {}
+            _ => return None,
+        }
+        if self.subcommands(name).is_empty() {
+            return None;
+        }
+        if args.len() != 1 {
+            let command = format!("{}|help", name.to_ascii_lowercase());
+            return Some(Err(ServerError::WrongArity(command)));
+        }
+
+        let mut lines = vec![format!(
+            "{name} <subcommand> [<arg> [value] [opt] ...]. Subcommands are:"
+        )];
+        lines.extend(
+            self.usage()
+                .map(|line| line[name.len()..].trim_start())
+                .filter(|line| !line.is_empty())
+                .map(String::from),
+        );
+        lines.push(String::from("HELP"));
+        lines.push(String::from("    Print this help."));
+        Some(Ok(RedisType::from(
+            lines.into_iter().map(RedisType::from).collect::<Vec<_>>(),
+        )))
+    }
+
+    // Reply for COMMAND INFO, in the Redis 7 format
+    fn info(&self, name: &str) -> RedisType {
+        let string = |value: &str| RedisType::from(String::from(value));
+
+        let (first, last, step) = match self.keys {
+            KeySpec::Range { first, last, step } => (first, last, step),
+            _ => (0, 0, 0),
+        };
+
+        let mut flags = self
+            .flags
+            .iter()
+            .map(|flag| string(flag))
+            .collect::<Vec<_>>();
+        if matches!(self.keys, KeySpec::Custom(_)) {
+            flags.push(string("movablekeys"));
+        }
+
+        let key_specs = if let KeySpec::Range { .. } = self.keys {
+            // Redis 7 describes the last key relative to the first
+            let lastkey = if last < 0 { last } else { last - first };
+
+            vec![RedisType::from(vec![
+                (
+                    string("begin_search"),
+                    RedisType::from(vec![
+                        (string("type"), string("index")),
+                        (
+                            string("spec"),
+                            RedisType::from(vec![(string("index"), RedisType::from(first))]),
+                        ),
+                    ]),
+                ),
+                (
+                    string("find_keys"),
+                    RedisType::from(vec![
+                        (string("type"), string("range")),
+                        (
+                            string("spec"),
+                            RedisType::from(vec![
+                                (string("lastkey"), RedisType::from(lastkey)),
+                                (string("keystep"), RedisType::from(step)),
+                                (string("limit"), RedisType::from(0)),
+                            ]),
+                        ),
+                    ]),
+                ),
+            ])]
+        } else {
+            vec![]
+        };
+
+        RedisType::from(vec![
+            string(&name.to_ascii_lowercase()),
+            RedisType::from(self.arity),
+            RedisType::from(flags),
+            RedisType::from(first),
+            RedisType::from(last),
+            RedisType::from(step),
+            RedisType::Array { value: vec![] },
+            RedisType::Array { value: vec![] },
+            RedisType::from(key_specs),
+            RedisType::Array { value: vec![] },
+        ])
+    }
+
+    // Reply for COMMAND DOCS
+    fn docs(&self) -> RedisType {
+        let string = |value: &str| RedisType::from(String::from(value));
+
+        RedisType::from(vec![
+            (string("summary"), string(self.summary)),
+            (string("since"), string(self.since)),
+            (string("group"), string(self.group)),
+        ])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        command_docs, command_keys, execute, Client, CustomCommand, Db, KeySpec, Server,
+        ServerError, State, COMMANDS, FLAGS,
+    };
+    use crate::server::plugin::Commands;
+    use crate::value::Value;
+    use crate::RedisType;
+    use std::net::SocketAddr;
+    use std::sync::Arc;
+    use std::time::{Duration, SystemTime};
+    use tokio::sync::Mutex;
+
+    fn argv(args: &[&str]) -> Vec<RedisType> {
+        args.iter()
+            .map(|arg| RedisType::from(String::from(*arg)))
+            .collect()
+    }
+
+    #[test]
+    fn test_key_positions() {
+        let mset = KeySpec::Range {
+            first: 1,
+            last: -1,
+            step: 2,
+        };
+        assert_eq!(
+            mset.positions(&argv(&["MSET", "a", "1", "b", "2"])),
+            vec![1, 3]
+        );
+        assert_eq!(KeySpec::FIRST.positions(&argv(&["GET", "a"])), vec![1]);
+        assert!(KeySpec::None.positions(&argv(&["COMMAND"])).is_empty());
+    }
+
+    #[test]
+    fn test_command_flags() {
+        for (name, command) in COMMANDS.iter() {
+            for flag in command.flags {
+                assert!(FLAGS.contains(flag), "{name} has unknown flag {flag}");
+            }
+            let write = command.has_flag("write");
+            let readonly = command.has_flag("readonly");
+            assert!(!(write && readonly), "{name} is both write and readonly");
+            assert!(
+                !command.has_flag("denyoom") || write,
+                "{name} is denyoom but not write"
+            );
+            // Replicas and client side caching go by these, for every command with keys
+            if !matches!(command.keys, KeySpec::None) {
+                assert!(
+                    write || readonly,
+                    "{name} has keys but isn't write or readonly"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_check_arity() {
+        assert!(COMMANDS["GET"].check_arity(2));
+        assert!(!COMMANDS["GET"].check_arity(3));
+        assert!(COMMANDS["SET"].check_arity(5));
+        assert!(!COMMANDS["SET"].check_arity(2));
+    }
+
+    #[test]
+    fn test_subcommands() {
+        assert_eq!(
+            COMMANDS["OBJECT"].subcommands("OBJECT"),
+            vec!["ENCODING", "REFCOUNT", "IDLETIME"]
+        );
+        assert!(COMMANDS["CLIENT"]
+            .subcommands("CLIENT")
+            .contains(&"NO-EVICT"));
+        assert!(COMMANDS["SET"].subcommands("SET").is_empty());
+        assert!(COMMANDS["FAILOVER"].subcommands("FAILOVER").is_empty());
+        assert!(COMMANDS["REPLICAOF"].subcommands("REPLICAOF").is_empty());
+
+        let mut containers = COMMANDS
+            .iter()
+            .filter(|(name, command)| !command.subcommands(name).is_empty())
+            .map(|(name, _)| *name)
+            .collect::<Vec<_>>();
+        containers.sort();
+        assert_eq!(
+            containers,
+            vec![
+                "CLIENT", "CLUSTER", "COMMAND", "CONFIG", "DEBUG", "LATENCY", "MEMORY", "OBJECT",
+                "PUBSUB", "SENTINEL"
+            ]
+        );
+
+        let help = COMMANDS["OBJECT"]
+            .subcommand_help("OBJECT", &argv(&["help"]))
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            help,
+            RedisType::from(
+                [
+                    "OBJECT <subcommand> [<arg> [value] [opt] ...]. Subcommands are:",
+                    "ENCODING key",
+                    "REFCOUNT key",
+                    "IDLETIME key",
+                    "HELP",
+                    "    Print this help.",
+                ]
+                .into_iter()
+                .map(|line| RedisType::from(String::from(line)))
+                .collect::<Vec<_>>()
+            )
+        );
+        assert_eq!(
+            COMMANDS["OBJECT"].subcommand_help("OBJECT", &argv(&["HELP", "x"])),
+            Some(Err(ServerError::WrongArity(String::from("object|help"))))
+        );
+        assert!(COMMANDS["GET"]
+            .subcommand_help("GET", &argv(&["HELP"]))
+            .is_none());
+    }
+
+    #[test]
+    fn test_command_docs() {
+        let docs = command_docs();
+        assert_eq!(docs.len(), COMMANDS.len());
+        assert!(docs.windows(2).all(|pair| pair[0].name < pair[1].name));
+
+        let set = docs.iter().find(|docs| docs.name == "SET").unwrap();
+        assert_eq!(set.group, "string");
+        assert!(set.usage[0].starts_with("SET key value"));
+        assert!(set.subcommands.is_empty());
+        assert!(set.description.starts_with("Sets key to a given value."));
+
+        let object = docs.iter().find(|docs| docs.name == "OBJECT").unwrap();
+        assert_eq!(object.subcommands, vec!["ENCODING", "REFCOUNT", "IDLETIME"]);
+    }
+
+    #[test]
+    fn test_command_keys() {
+        assert_eq!(
+            command_keys(&argv(&["mset", "a", "1", "b", "2"])),
+            vec![b"a".to_vec(), b"b".to_vec()]
+        );
+        assert!(command_keys(&argv(&["GET"])).is_empty());
+        assert!(command_keys(&argv(&["NOPE", "a"])).is_empty());
+        assert!(command_keys(&[]).is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_custom_command_on_replica() {
+        let state = Arc::new(Mutex::new(State::default()));
+        let addr = SocketAddr::from(([127, 0, 0, 1], 1234));
+
+        // The master's DEL for an expired key arrives while the command's handler is running
+        let master = Arc::downgrade(&state);
+        let read = CustomCommand::new("READ", 2, move |db: Db, args: Vec<Vec<u8>>| {
+            let master = master.clone();
+            async move {
+                let state = master.upgrade().unwrap();
+                let mut client = Client::new(addr, addr);
+                client.master = true;
+                client.authenticated = true;
+                let del = RedisType::from(argv(&["DEL", "key"]));
+                execute(&state, &mut client, &del).await;
+
+                let value = db.get(&args[0]).await;
+                Ok(value.map_or(RedisType::NullString, RedisType::from))
+            }
+        })
+        .flags(&["readonly"])
+        .keys(1, 1, 1);
+
+        {
+            let mut state = state.lock().await;
+            state.commands = Commands::new(vec![read]).unwrap();
+            state
+                .replication
+                .replicate_from(String::from("localhost"), 6379);
+            state.keystore.insert(b"key".to_vec(), Value::from("value"));
+            let past = SystemTime::now() - Duration::from_secs(1);
+            state.ttl.push(b"key".to_vec(), past);
+        }
+
+        let mut client = Client::new(addr, addr);
+        client.authenticated = true;
+        let read = RedisType::from(argv(&["READ", "key"]));
+        assert_eq!(
+            execute(&state, &mut client, &read).await,
+            Some(RedisType::NullString)
+        );
+        // Deleted by the master, not brought back once the command is done
+        assert!(!state.lock().await.keystore.contains_key(&b"key"[..]));
+    }
+
+    #[tokio::test]
+    async fn test_shutdown_stops_loops() {
+        let server = Server::bind("127.0.0.1:0").spawn().await.unwrap();
+        let state = server.state.clone();
+        server.shutdown().await.unwrap();
+
+        // Nothing is left running with the state, such as the expire and cron loops
+        assert_eq!(Arc::strong_count(&state), 1);
+    }
+}
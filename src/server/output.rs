@@ -0,0 +1,231 @@
+use bytes::Bytes;
+use std::io::IoSlice;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::io::AsyncWriteExt;
+use tokio::net::tcp::OwnedWriteHalf;
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+
+use crate::server::config::BufferLimit;
+
+// Replies are queued here and written to the socket by a separate task, so a client that is
+// slow to read doesn't hold up command processing. Queued bytes are counted to enforce limits.
+// Replies are only handed to the writer on flush, once a whole batch of pipelined commands has
+// run, and it writes everything it has been handed with one vectored write where it can. Each
+// reply is a list of segments, so large values are written straight from the keystore's buffers.
+pub struct OutputBuffer {
+    sender: mpsc::UnboundedSender<Vec<Bytes>>,
+    batch: Vec<Bytes>,
+    pending: Arc<AtomicUsize>,
+    soft_limit_reached_at: Option<Instant>,
+    writer: JoinHandle<std::io::Result<()>>,
+}
+
+// What the writer task gets flushed replies from
+pub type Replies = mpsc::UnboundedReceiver<Vec<Bytes>>;
+
+impl OutputBuffer {
+    pub fn new(mut stream: OwnedWriteHalf) -> Self {
+        OutputBuffer::with_writer(|mut replies, pending| {
+            tokio::spawn(async move {
+                while let Some(batch) = next_batch(&mut replies).await {
+                    write_all_vectored(&mut stream, &batch).await?;
+                    let len = batch.iter().map(Bytes::len).sum::<usize>();
+                    pending.fetch_sub(len, Ordering::Relaxed);
+                }
+                stream.shutdown().await
+            })
+        })
+    }
+
+    // Start the task that writes replies to the socket, which is given the replies as they're
+    // flushed and the count of queued bytes to take them off once written
+    pub fn with_writer(
+        spawn: impl FnOnce(Replies, Arc<AtomicUsize>) -> JoinHandle<std::io::Result<()>>,
+    ) -> Self {
+        let (sender, receiver) = mpsc::unbounded_channel::<Vec<Bytes>>();
+        let pending = Arc::new(AtomicUsize::new(0));
+        let writer = spawn(receiver, pending.clone());
+
+        OutputBuffer {
+            sender,
+            batch: Vec::new(),
+            pending,
+            soft_limit_reached_at: None,
+            writer,
+        }
+    }
+
+    // Bytes queued but not yet written to the socket
+    pub fn pending(&self) -> usize {
+        self.pending.load(Ordering::Relaxed)
+    }
+
+    // Queue a reply until the next flush, failing if the client is over its limits and should be
+    // disconnected
+    pub fn push(&mut self, reply: Vec<Bytes>, limit: &BufferLimit) -> Result<(), String> {
+        let len = reply.iter().map(Bytes::len).sum::<usize>();
+        self.pending.fetch_add(len, Ordering::Relaxed);
+        self.batch.extend(reply);
+        self.check(limit)
+    }
+
+    // Whether what's queued is within the limits, failing as push does if not
+    // Also called once soft_limit_deadline passes, since a client that has stopped reading may not
+    // be sent anything more to push.
+    pub fn check(&mut self, limit: &BufferLimit) -> Result<(), String> {
+        let pending = self.pending();
+
+        if limit.hard > 0 && pending > limit.hard {
+            return Err(format!(
+                "output buffer of {pending} bytes is over the hard limit of {} bytes",
+                limit.hard
+            ));
+        }
+
+        if limit.soft > 0 && pending > limit.soft {
+            let since = *self.soft_limit_reached_at.get_or_insert_with(Instant::now);
+            if since.elapsed() >= Duration::from_secs(limit.soft_seconds) {
+                return Err(format!(
+                    "output buffer of {pending} bytes has been over the soft limit of {} bytes for {} seconds",
+                    limit.soft, limit.soft_seconds
+                ));
+            }
+        } else {
+            self.soft_limit_reached_at = None;
+        }
+
+        Ok(())
+    }
+
+    // When the client will have been over the soft limit for too long, if it's over it now
+    pub fn soft_limit_deadline(&self, limit: &BufferLimit) -> Option<Instant> {
+        if limit.soft == 0 {
+            return None;
+        }
+        let since = self.soft_limit_reached_at?;
+        Some(since + Duration::from_secs(limit.soft_seconds))
+    }
+
+    // Hand everything queued since the last flush to the writer
+    pub fn flush(&mut self) -> Result<(), String> {
+        if self.batch.is_empty() {
+            return Ok(());
+        }
+
+        let batch = std::mem::take(&mut self.batch);
+        self.sender
+            .send(batch)
+            .map_err(|_| String::from("connection writer has stopped"))
+    }
+
+    // Wait for everything queued so far to be written, then close the connection
+    pub async fn close(mut self) -> std::io::Result<()> {
+        // If the writer has already stopped, awaiting it below returns why
+        let _ = self.flush();
+        drop(self.sender);
+        match self.writer.await {
+            Ok(result) => result,
+            Err(e) => Err(std::io::Error::other(e)),
+        }
+    }
+
+    // As close, but giving up on anything that hasn't been written after timeout
+    pub async fn close_within(mut self, timeout: Duration) -> std::io::Result<()> {
+        let _ = self.flush();
+        drop(self.sender);
+        match tokio::time::timeout(timeout, &mut self.writer).await {
+            Ok(Ok(result)) => result,
+            Ok(Err(e)) => Err(std::io::Error::other(e)),
+            Err(_) => {
+                self.writer.abort();
+                Err(std::io::Error::new(
+                    std::io::ErrorKind::TimedOut,
+                    format!(
+                        "{} bytes of replies not sent in time",
+                        self.pending.load(Ordering::Relaxed)
+                    ),
+                ))
+            }
+        }
+    }
+
+    // Close the connection immediately, dropping anything still queued
+    pub fn abort(self) {
+        self.writer.abort();
+    }
+}
+
+// The next batch of replies to write, or None once the connection is closing
+// Anything flushed while the last write was in progress goes out with this one
+pub async fn next_batch(replies: &mut Replies) -> Option<Vec<Bytes>> {
+    let mut batch = replies.recv().await?;
+    while let Ok(more) = replies.try_recv() {
+        batch.extend(more);
+    }
+    Some(batch)
+}
+
+// Write all of replies, in as few system calls as the socket allows
+async fn write_all_vectored(stream: &mut OwnedWriteHalf, replies: &[Bytes]) -> std::io::Result<()> {
+    let mut slices = replies
+        .iter()
+        .map(|reply| IoSlice::new(reply))
+        .collect::<Vec<_>>();
+    let mut slices = &mut slices[..];
+    IoSlice::advance_slices(&mut slices, 0);
+
+    while !slices.is_empty() {
+        let written = stream.write_vectored(slices).await?;
+        if written == 0 {
+            return Err(std::io::ErrorKind::WriteZero.into());
+        }
+        IoSlice::advance_slices(&mut slices, written);
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::OutputBuffer;
+    use crate::server::config::BufferLimit;
+    use bytes::Bytes;
+    use std::time::{Duration, Instant};
+
+    #[tokio::test]
+    async fn test_soft_limit() {
+        // A client that never reads
+        let mut output = OutputBuffer::with_writer(|replies, _pending| {
+            tokio::spawn(async move {
+                let _replies = replies;
+                std::future::pending().await
+            })
+        });
+        let limit = BufferLimit {
+            hard: 100,
+            soft: 10,
+            soft_seconds: 60,
+        };
+
+        output
+            .push(vec![Bytes::from("0123456789")], &limit)
+            .unwrap();
+        assert_eq!(output.soft_limit_deadline(&limit), None);
+        output.push(vec![Bytes::from("x")], &limit).unwrap();
+        let deadline = output.soft_limit_deadline(&limit).unwrap();
+        assert!(deadline > Instant::now() + Duration::from_secs(59));
+
+        // Nothing more has to be pushed for it to be over the limit for too long
+        output.check(&limit).unwrap();
+        output.soft_limit_reached_at = Some(Instant::now() - Duration::from_secs(60));
+        assert!(output.check(&limit).is_err());
+
+        assert!(output
+            .push(vec![Bytes::from(vec![0; 100])], &limit)
+            .is_err());
+        output.abort();
+    }
+}
@@ -0,0 +1,331 @@
+use crate::server::commands::COMMANDS;
+use crate::server::{propagate, reads, tracking, Command, KeySpec, State, FLAGS};
+use crate::value::Value;
+use crate::RedisType;
+use std::collections::HashMap;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::time::SystemTime;
+use tokio::sync::Mutex;
+
+// A command added by the program embedding the server (with Server::command), for operations of
+// its own that should run next to the data rather than as several round trips
+// It goes through the same checks as any other command (arity, authentication, pauses, cluster
+// slots, read only replicas and maxmemory, going by its flags) and is counted in the stats and the
+// latency monitor the same way, but its handler runs without holding the state's lock. Instead it
+// reads and changes keys through Db, which takes the lock for each update. Each key an update
+// changes is passed on to the AOF and replicas as a SET or DEL, so neither needs the command.
+//
+//     let server = Server::bind("127.0.0.1:0")
+//         .command(
+//             CustomCommand::new("TAKE", 2, |db: Db, args: Vec<Vec<u8>>| async move {
+//                 db.update(|keys| {
+//                     let value = keys.get(&args[0]).map(|value| RedisType::from(value.to_vec()));
+//                     keys.del(&args[0]);
+//                     Ok(value.unwrap_or(RedisType::NullString))
+//                 })
+//                 .await
+//             })
+//             .flags(&["write", "fast"])
+//             .keys(1, 1, 1),
+//         )
+//         .spawn()
+//         .await?;
+pub struct CustomCommand {
+    name: String,
+    arity: i64,
+    flags: &'static [&'static str],
+    keys: KeySpec,
+    handler: Arc<dyn CommandHandler>,
+}
+
+impl CustomCommand {
+    // Arity counts the command name, a negative one means at least that many arguments
+    pub fn new(name: &str, arity: i64, handler: impl CommandHandler) -> CustomCommand {
+        CustomCommand {
+            name: name.to_ascii_uppercase(),
+            arity,
+            flags: &[],
+            keys: KeySpec::None,
+            handler: Arc::new(handler),
+        }
+    }
+
+    // Redis' command flags, of which write, readonly, denyoom, fast and no_auth change how the
+    // command is run
+    pub fn flags(mut self, flags: &'static [&'static str]) -> CustomCommand {
+        self.flags = flags;
+        self
+    }
+
+    // Where the keys are in the arguments, counting the command name as 0 (a negative last counts
+    // back from the end), for cluster slots, client side caching and key sampling
+    pub fn keys(mut self, first: i64, last: i64, step: i64) -> CustomCommand {
+        self.keys = KeySpec::Range { first, last, step };
+        self
+    }
+}
+
+// Runs a custom command, given its arguments (without the command name)
+// An Err is sent as an ERR reply with that message.
+pub trait CommandHandler: Send + Sync + 'static {
+    fn call(&self, db: Db, args: Vec<Vec<u8>>) -> Pin<Box<dyn Future<Output = Reply> + Send>>;
+}
+
+pub type Reply = Result<RedisType, String>;
+
+impl<F, Fut> CommandHandler for F
+where
+    F: Fn(Db, Vec<Vec<u8>>) -> Fut + Send + Sync + 'static,
+    Fut: Future<Output = Reply> + Send + 'static,
+{
+    fn call(&self, db: Db, args: Vec<Vec<u8>>) -> Pin<Box<dyn Future<Output = Reply> + Send>> {
+        Box::pin(self(db, args))
+    }
+}
+
+// The custom commands a server was given, as the dispatcher looks them up
+#[derive(Default)]
+pub struct Commands(HashMap<String, Arc<Registered>>);
+
+pub struct Registered {
+    // For the dispatcher's checks, it's never called
+    pub definition: Command,
+    handler: Arc<dyn CommandHandler>,
+}
+
+impl std::fmt::Debug for Commands {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_set().entries(self.0.keys()).finish()
+    }
+}
+
+impl Commands {
+    pub fn new(commands: Vec<CustomCommand>) -> Result<Commands, String> {
+        let mut registered = HashMap::new();
+        for command in commands {
+            let name = command.name;
+            if name.is_empty() || name.contains(|c: char| c.is_ascii_whitespace()) {
+                return Err(format!("Invalid command name '{name}'"));
+            }
+            if COMMANDS.contains_key(name.as_str()) || registered.contains_key(&name) {
+                return Err(format!("Command '{name}' already exists"));
+            }
+            if command.arity == 0 {
+                return Err(format!("Invalid arity for command '{name}'"));
+            }
+            if let Some(flag) = command.flags.iter().find(|flag| !FLAGS.contains(flag)) {
+                return Err(format!("Unknown flag '{flag}' for command '{name}'"));
+            }
+
+            let definition = Command {
+                summary: "",
+                group: "custom",
+                since: "",
+                arity: command.arity,
+                flags: command.flags,
+                keys: command.keys,
+                help: String::new(),
+                f: Box::new(|_, _, _| unreachable!("custom commands are run by their handler")),
+            };
+            let handler = command.handler;
+            registered.insert(
+                name,
+                Arc::new(Registered {
+                    definition,
+                    handler,
+                }),
+            );
+        }
+        Ok(Commands(registered))
+    }
+
+    pub fn get(&self, name: &str) -> Option<Arc<Registered>> {
+        self.0.get(name).cloned()
+    }
+}
+
+impl Registered {
+    pub async fn call(&self, db: Db, args: Vec<Vec<u8>>) -> Reply {
+        self.handler.call(db, args).await
+    }
+}
+
+// The keyspace, for a custom command's handler
+#[derive(Clone)]
+pub struct Db {
+    state: Arc<Mutex<State>>,
+    // The client running the command, which isn't told its own changes invalidate what it caches
+    client: u64,
+}
+
+impl Db {
+    pub(super) fn new(state: Arc<Mutex<State>>, client: u64) -> Db {
+        Db { state, client }
+    }
+
+    pub async fn get(&self, key: &[u8]) -> Option<Vec<u8>> {
+        self.update(|keys| keys.get(key).map(<[u8]>::to_vec)).await
+    }
+
+    // Replaces any value (and expiration time) key had
+    pub async fn set(&self, key: &[u8], value: &[u8]) {
+        self.update(|keys| keys.set(key, value)).await
+    }
+
+    // Returns if key was there to delete
+    pub async fn del(&self, key: &[u8]) -> bool {
+        self.update(|keys| keys.del(key)).await
+    }
+
+    // Read and change any number of keys at once, with no other command running in between
+    // Keep it quick, every other client waits for it.
+    pub async fn update<T>(&self, f: impl FnOnce(&mut Keys) -> T) -> T {
+        let mut state = self.state.lock().await;
+        let mut keys = Keys {
+            state: &mut state,
+            changed: Vec::new(),
+        };
+        let result = f(&mut keys);
+        let changed = keys.changed;
+        if !changed.is_empty() {
+            changes(&mut state, &changed, self.client);
+        }
+        result
+    }
+}
+
+// Keys as a custom command sees them, for the length of one Db::update
+pub struct Keys<'a> {
+    state: &'a mut State,
+    changed: Vec<Vec<u8>>,
+}
+
+impl Keys<'_> {
+    // Keys that have expired aren't there, even if they haven't been removed yet
+    pub fn get(&self, key: &[u8]) -> Option<&[u8]> {
+        if self
+            .state
+            .ttl
+            .get(key)
+            .is_some_and(|expires_at| *expires_at <= SystemTime::now())
+        {
+            return None;
+        }
+        self.state.keystore.get(key).map(|value| &value[..])
+    }
+
+    pub fn contains(&self, key: &[u8]) -> bool {
+        self.get(key).is_some()
+    }
+
+    pub fn set(&mut self, key: &[u8], value: &[u8]) {
+        self.state.ttl.remove(key);
+        self.state.keystore.insert(key.to_vec(), Value::from(value));
+        self.changed(key);
+    }
+
+    pub fn del(&mut self, key: &[u8]) -> bool {
+        let existed = self.contains(key);
+        if self.state.keystore.remove(key).is_some() {
+            self.state.ttl.remove(key);
+            self.state.last_access.remove(key);
+            self.changed(key);
+        }
+        existed
+    }
+
+    fn changed(&mut self, key: &[u8]) {
+        if !self.changed.iter().any(|changed| changed == key) {
+            self.changed.push(key.to_vec());
+        }
+    }
+}
+
+// Everything that follows from keys having changed, as it does for any other write
+fn changes(state: &mut State, keys: &[Vec<u8>], client: u64) {
+    for key in keys {
+        let argv = match state.keystore.get(key) {
+            Some(value) => vec![b"SET".to_vec(), key.clone(), value.to_vec()],
+            None => vec![b"DEL".to_vec(), key.clone()],
+        };
+        propagate(state, &argv);
+    }
+    state.saves.lock().unwrap().changes_since_save += keys.len() as u64;
+    state.waiters.signal_keys(keys);
+    tracking::invalidate(state, keys, Some(client));
+    reads::publish(state);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Commands, CustomCommand, Db};
+    use crate::server::State;
+    use crate::RedisType;
+    use std::sync::Arc;
+    use std::time::{Duration, SystemTime};
+    use tokio::sync::Mutex;
+
+    #[test]
+    fn test_register() {
+        let command = |name: &str| {
+            CustomCommand::new(name, 2, |_db: Db, _args: Vec<Vec<u8>>| async {
+                Ok(RedisType::NullString)
+            })
+        };
+
+        let commands = Commands::new(vec![command("mycommand").flags(&["write"])]).unwrap();
+        assert!(commands.get("MYCOMMAND").is_some());
+
+        for (commands, error) in [
+            (vec![command("GET")], "Command 'GET' already exists"),
+            (
+                vec![command("MINE"), command("mine")],
+                "Command 'MINE' already exists",
+            ),
+            (
+                vec![command("MY COMMAND")],
+                "Invalid command name 'MY COMMAND'",
+            ),
+            (
+                vec![command("MINE").flags(&["writes"])],
+                "Unknown flag 'writes' for command 'MINE'",
+            ),
+        ] {
+            assert_eq!(Commands::new(commands).unwrap_err(), error);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_update() {
+        let state = Arc::new(Mutex::new(State::default()));
+        let db = Db::new(state.clone(), 1);
+
+        db.set(b"key", b"value").await;
+        assert_eq!(db.get(b"key").await, Some(b"value".to_vec()));
+        assert_eq!(
+            state.lock().await.saves.lock().unwrap().changes_since_save,
+            1
+        );
+
+        // Expired keys aren't there for the command, even before they're removed
+        let past = SystemTime::now() - Duration::from_secs(1);
+        state.lock().await.ttl.push(b"key".to_vec(), past);
+        assert_eq!(db.get(b"key").await, None);
+        assert!(!db.del(b"key").await);
+        assert!(state.lock().await.keystore.is_empty());
+
+        let moved = db
+            .update(|keys| {
+                keys.set(b"a", b"1");
+                let value = keys.get(b"a").unwrap().to_vec();
+                keys.set(b"b", &value);
+                keys.del(b"a")
+            })
+            .await;
+        assert!(moved);
+        assert_eq!(db.get(b"a").await, None);
+        assert_eq!(db.get(b"b").await, Some(b"1".to_vec()));
+    }
+}
@@ -0,0 +1,284 @@
+// Pub/sub: PUBLISH sends a message to every connection subscribed to its channel, or to a pattern
+// matching it
+//
+// Messages go out through each subscriber's push channel, between replies like CLIENT TRACKING's
+// invalidations. Under RESP2 they're plain arrays that can't be told apart from replies, so a
+// connection with subscriptions can only run the commands in ALLOWED until it has none left. RESP3
+// sends them as push messages, so a connection using it can run anything.
+
+use crate::server::clients::Client;
+use crate::server::{glob, State};
+use crate::RedisType;
+use std::collections::{BTreeSet, HashMap};
+
+// What a RESP2 connection with subscriptions can still run
+pub const ALLOWED: &[&str] = &[
+    "SUBSCRIBE",
+    "UNSUBSCRIBE",
+    "PSUBSCRIBE",
+    "PUNSUBSCRIBE",
+    "PING",
+    "QUIT",
+    "RESET",
+];
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Kind {
+    Channel,
+    Pattern,
+}
+
+#[derive(Debug, Default)]
+struct Subscriptions {
+    channels: BTreeSet<Vec<u8>>,
+    patterns: BTreeSet<Vec<u8>>,
+}
+
+impl Subscriptions {
+    fn of(&mut self, kind: Kind) -> &mut BTreeSet<Vec<u8>> {
+        match kind {
+            Kind::Channel => &mut self.channels,
+            Kind::Pattern => &mut self.patterns,
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct PubSub {
+    // The clients subscribed to each channel, and to each pattern
+    channels: HashMap<Vec<u8>, BTreeSet<u64>>,
+    patterns: HashMap<Vec<u8>, BTreeSet<u64>>,
+    clients: HashMap<u64, Subscriptions>,
+}
+
+impl PubSub {
+    fn of(&mut self, kind: Kind) -> &mut HashMap<Vec<u8>, BTreeSet<u64>> {
+        match kind {
+            Kind::Channel => &mut self.channels,
+            Kind::Pattern => &mut self.patterns,
+        }
+    }
+
+    // Returns whether it wasn't already
+    pub fn subscribe(&mut self, client: u64, kind: Kind, name: &[u8]) -> bool {
+        let subscriptions = self.clients.entry(client).or_default();
+        if !subscriptions.of(kind).insert(name.to_vec()) {
+            return false;
+        }
+        self.of(kind)
+            .entry(name.to_vec())
+            .or_default()
+            .insert(client);
+        true
+    }
+
+    // Returns whether it was
+    pub fn unsubscribe(&mut self, client: u64, kind: Kind, name: &[u8]) -> bool {
+        let Some(subscriptions) = self.clients.get_mut(&client) else {
+            return false;
+        };
+        if !subscriptions.of(kind).remove(name) {
+            return false;
+        }
+        if subscriptions.channels.is_empty() && subscriptions.patterns.is_empty() {
+            self.clients.remove(&client);
+        }
+
+        let subscribers = self.of(kind);
+        if let Some(clients) = subscribers.get_mut(name) {
+            clients.remove(&client);
+            if clients.is_empty() {
+                subscribers.remove(name);
+            }
+        }
+        true
+    }
+
+    // Every channel (or pattern) client is subscribed to, in order
+    pub fn subscriptions(&self, client: u64, kind: Kind) -> Vec<Vec<u8>> {
+        let Some(subscriptions) = self.clients.get(&client) else {
+            return Vec::new();
+        };
+        let names = match kind {
+            Kind::Channel => &subscriptions.channels,
+            Kind::Pattern => &subscriptions.patterns,
+        };
+        names.iter().cloned().collect()
+    }
+
+    pub fn is_subscribed(&self, client: u64, channel: &[u8]) -> bool {
+        self.channels
+            .get(channel)
+            .is_some_and(|clients| clients.contains(&client))
+    }
+
+    // For a connection that's gone
+    pub fn remove(&mut self, client: u64) {
+        for kind in [Kind::Channel, Kind::Pattern] {
+            for name in self.subscriptions(client, kind) {
+                self.unsubscribe(client, kind, &name);
+            }
+        }
+    }
+
+    // The clients a message published to channel goes to, with the pattern that matched it for
+    // those subscribed to one (a client subscribed more than once is sent it more than once)
+    pub fn receivers(&self, channel: &[u8]) -> Vec<(u64, Option<&[u8]>)> {
+        let mut receivers = Vec::new();
+        if let Some(clients) = self.channels.get(channel) {
+            receivers.extend(clients.iter().map(|client| (*client, None)));
+        }
+
+        let channel = String::from_utf8_lossy(channel);
+        for (pattern, clients) in &self.patterns {
+            if glob::matches(&String::from_utf8_lossy(pattern), &channel, false) {
+                receivers.extend(clients.iter().map(|client| (*client, Some(&pattern[..]))));
+            }
+        }
+        receivers
+    }
+
+    // For PUBSUB: the channels with at least one subscriber, how many each has, and how many
+    // patterns there are subscriptions to
+    pub fn channels(&self) -> impl Iterator<Item = &Vec<u8>> {
+        self.channels.keys()
+    }
+
+    pub fn subscribers(&self, channel: &[u8]) -> usize {
+        self.channels.get(channel).map_or(0, BTreeSet::len)
+    }
+
+    pub fn patterns(&self) -> usize {
+        self.patterns.len()
+    }
+}
+
+// Subscribe client to each of names, returning the confirmation for each
+pub fn subscribe(
+    state: &mut State,
+    client: &mut Client,
+    kind: Kind,
+    names: Vec<Vec<u8>>,
+) -> Vec<RedisType> {
+    let reply = match kind {
+        Kind::Channel => "subscribe",
+        Kind::Pattern => "psubscribe",
+    };
+    names
+        .into_iter()
+        .map(|name| {
+            if state.pubsub.subscribe(client.id, kind, &name) {
+                count(client, kind, 1);
+            }
+            confirmation(client, reply, RedisType::from(name))
+        })
+        .collect()
+}
+
+// Unsubscribe client from each of names, or from everything it's subscribed to without any,
+// returning the confirmation for each (of which there's always at least one)
+pub fn unsubscribe(
+    state: &mut State,
+    client: &mut Client,
+    kind: Kind,
+    names: Vec<Vec<u8>>,
+) -> Vec<RedisType> {
+    let reply = match kind {
+        Kind::Channel => "unsubscribe",
+        Kind::Pattern => "punsubscribe",
+    };
+    let names = match names.is_empty() {
+        true => state.pubsub.subscriptions(client.id, kind),
+        false => names,
+    };
+    if names.is_empty() {
+        return vec![confirmation(client, reply, RedisType::NullString)];
+    }
+
+    names
+        .into_iter()
+        .map(|name| {
+            if state.pubsub.unsubscribe(client.id, kind, &name) {
+                count(client, kind, -1);
+            }
+            confirmation(client, reply, RedisType::from(name))
+        })
+        .collect()
+}
+
+// Send message to everyone subscribed to channel, returning how many it was sent to
+pub fn publish(state: &State, channel: &[u8], message: &[u8]) -> usize {
+    let mut sent = 0;
+    for (client, pattern) in state.pubsub.receivers(channel) {
+        let Some(info) = state.clients.get(&client) else {
+            continue;
+        };
+        let push = match pattern {
+            None => vec![
+                RedisType::from(String::from("message")),
+                RedisType::from(channel.to_vec()),
+                RedisType::from(message.to_vec()),
+            ],
+            Some(pattern) => vec![
+                RedisType::from(String::from("pmessage")),
+                RedisType::from(pattern.to_vec()),
+                RedisType::from(channel.to_vec()),
+                RedisType::from(message.to_vec()),
+            ],
+        };
+        // Closed if the connection is going away
+        let _ = info.pushes.send(RedisType::Push { value: push });
+        sent += 1;
+    }
+    sent
+}
+
+// Keep the counts the connection checks (and CLIENT LIST shows) up to date
+fn count(client: &mut Client, kind: Kind, change: isize) {
+    let count = match kind {
+        Kind::Channel => &mut client.channels,
+        Kind::Pattern => &mut client.patterns,
+    };
+    *count = count.saturating_add_signed(change);
+}
+
+// What each (un)subscription is answered with: which it was, and how many channels and patterns
+// the client is subscribed to afterwards
+fn confirmation(client: &Client, reply: &str, name: RedisType) -> RedisType {
+    RedisType::Push {
+        value: vec![
+            RedisType::from(String::from(reply)),
+            name,
+            RedisType::from((client.channels + client.patterns) as i64),
+        ],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Kind, PubSub};
+
+    #[test]
+    fn test_receivers() {
+        let mut pubsub = PubSub::default();
+        assert!(pubsub.subscribe(1, Kind::Channel, b"news"));
+        assert!(!pubsub.subscribe(1, Kind::Channel, b"news"));
+        pubsub.subscribe(2, Kind::Channel, b"news");
+        pubsub.subscribe(2, Kind::Pattern, b"n*");
+        pubsub.subscribe(3, Kind::Pattern, b"other*");
+
+        assert_eq!(
+            pubsub.receivers(b"news"),
+            [(1, None), (2, None), (2, Some(&b"n*"[..]))]
+        );
+        assert_eq!(pubsub.subscribers(b"news"), 2);
+        assert_eq!(pubsub.patterns(), 2);
+
+        assert!(pubsub.unsubscribe(1, Kind::Channel, b"news"));
+        assert!(!pubsub.unsubscribe(1, Kind::Channel, b"news"));
+        pubsub.remove(2);
+        assert!(pubsub.receivers(b"news").is_empty());
+        assert_eq!(pubsub.channels().count(), 0);
+        assert_eq!(pubsub.patterns(), 1);
+    }
+}
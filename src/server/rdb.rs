@@ -0,0 +1,488 @@
+use crate::rdb::{
+    crc64, parse, LoadedValue, JSON_MODULE_ID, MAGIC, MODULE_OPCODE_EOF, MODULE_OPCODE_STRING,
+    OPCODE_AUX, OPCODE_EOF, OPCODE_EXPIRETIME_MS, OPCODE_RESIZEDB, OPCODE_SELECTDB, TYPE_MODULE_2,
+    TYPE_STRING, VERSION,
+};
+use crate::server::config::SaveRule;
+use crate::server::{State, REDIS_VERSION};
+use crate::value::Value;
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+// A single key as it is written to a snapshot
+pub struct Entry<'a> {
+    pub key: &'a [u8],
+    pub value: &'a [u8],
+    // A JSON document, with value its JSON text
+    pub json: bool,
+    pub expires_at: Option<SystemTime>,
+}
+
+// The dataset as of some point in time, unaffected by writes made after it was taken
+// Both maps are persistent so copying them is cheap and shares structure with the live ones
+#[derive(Debug)]
+pub struct Snapshot {
+    keystore: im::HashMap<Vec<u8>, Value>,
+    expires: im::HashMap<Vec<u8>, SystemTime>,
+}
+
+impl Snapshot {
+    pub fn of(state: &State) -> Snapshot {
+        Snapshot {
+            keystore: state.keystore.clone(),
+            expires: state.ttl.expires().clone(),
+        }
+    }
+
+    // Every key along with its expiration time, if it has one
+    pub fn entries(&self) -> Vec<Entry<'_>> {
+        self.keystore
+            .iter()
+            .map(|(key, value)| Entry {
+                key,
+                value,
+                json: value.is_json(),
+                expires_at: self.expires.get(key).copied(),
+            })
+            .collect()
+    }
+}
+
+// Serialize the given keys (all in database 0) into a complete RDB file
+pub fn dump(entries: &[Entry]) -> Vec<u8> {
+    let mut out = Vec::new();
+    out.extend_from_slice(MAGIC);
+    out.extend_from_slice(format!("{VERSION:04}").as_bytes());
+
+    let ctime = unix_millis(SystemTime::now()) / 1000;
+    for (key, value) in [
+        ("redis-ver", REDIS_VERSION.to_string()),
+        ("redis-bits", (usize::BITS).to_string()),
+        ("ctime", ctime.to_string()),
+    ] {
+        out.push(OPCODE_AUX);
+        write_string(&mut out, key.as_bytes());
+        write_string(&mut out, value.as_bytes());
+    }
+
+    if !entries.is_empty() {
+        out.push(OPCODE_SELECTDB);
+        write_length(&mut out, 0);
+
+        let expires = entries.iter().filter(|e| e.expires_at.is_some()).count();
+        out.push(OPCODE_RESIZEDB);
+        write_length(&mut out, entries.len() as u64);
+        write_length(&mut out, expires as u64);
+
+        for entry in entries {
+            if let Some(expires_at) = entry.expires_at {
+                out.push(OPCODE_EXPIRETIME_MS);
+                out.extend_from_slice(&unix_millis(expires_at).to_le_bytes());
+            }
+
+            // As RedisJSON saves them, so either can load the other's snapshots
+            if entry.json {
+                out.push(TYPE_MODULE_2);
+                write_string(&mut out, entry.key);
+                write_length(&mut out, JSON_MODULE_ID);
+                write_length(&mut out, MODULE_OPCODE_STRING);
+                write_string(&mut out, entry.value);
+                write_length(&mut out, MODULE_OPCODE_EOF);
+                continue;
+            }
+            out.push(TYPE_STRING);
+            write_string(&mut out, entry.key);
+            write_string(&mut out, entry.value);
+        }
+    }
+
+    out.push(OPCODE_EOF);
+    let checksum = crc64(0, &out);
+    out.extend_from_slice(&checksum.to_le_bytes());
+    out
+}
+
+// Write a snapshot of the current keystore to path, replacing it atomically
+pub fn save(state: &State, path: &Path) -> std::io::Result<()> {
+    let changes = state.saves.lock().unwrap().changes_since_save;
+    write_atomically(path, &dump(&Snapshot::of(state).entries()))?;
+    state.saves.lock().unwrap().saved(changes);
+    Ok(())
+}
+
+// Progress of snapshots, shared with background saves so they can report back when done
+#[derive(Debug)]
+pub struct SaveStatus {
+    pub last_save: SystemTime,
+    pub changes_since_save: u64,
+    pub in_progress_since: Option<Instant>,
+    // Set by BGSAVE SCHEDULE, started once the current background save is done
+    pub scheduled: bool,
+    pub last_bgsave_ok: bool,
+    pub last_bgsave_duration: Option<Duration>,
+}
+
+impl Default for SaveStatus {
+    fn default() -> Self {
+        SaveStatus {
+            last_save: SystemTime::now(),
+            changes_since_save: 0,
+            in_progress_since: None,
+            scheduled: false,
+            last_bgsave_ok: true,
+            last_bgsave_duration: None,
+        }
+    }
+}
+
+impl SaveStatus {
+    // A save finished, it included the first `changes` writes since the previous one
+    fn saved(&mut self, changes: u64) {
+        self.last_save = SystemTime::now();
+        self.changes_since_save -= changes.min(self.changes_since_save);
+    }
+
+    // Whether any of the save points has been reached
+    pub fn save_point_reached(&self, save: &[SaveRule]) -> bool {
+        let elapsed = self.last_save.elapsed().unwrap_or_default().as_secs();
+        save.iter()
+            .any(|rule| self.changes_since_save >= rule.changes && elapsed >= rule.seconds)
+    }
+}
+
+// Start writing a snapshot in the background
+// Writes can continue while it is being written, they just won't be part of it
+pub fn start_bgsave(state: &State, path: PathBuf) -> Result<(), String> {
+    let saves = state.saves.clone();
+    let changes = {
+        let mut status = saves.lock().unwrap();
+        if status.in_progress_since.is_some() {
+            return Err(String::from("Background save already in progress"));
+        }
+        status.in_progress_since = Some(Instant::now());
+        status.scheduled = false;
+        status.changes_since_save
+    };
+
+    let snapshot = Snapshot::of(state);
+
+    tokio::task::spawn_blocking(move || {
+        let result = write_atomically(&path, &dump(&snapshot.entries()));
+
+        let mut status = saves.lock().unwrap();
+        let started = status.in_progress_since.take().unwrap_or_else(Instant::now);
+        status.last_bgsave_duration = Some(started.elapsed());
+        status.last_bgsave_ok = result.is_ok();
+
+        match result {
+            Ok(()) => {
+                status.saved(changes);
+                tracing::info!("Background saving terminated with success");
+            }
+            Err(e) => tracing::warn!("Background saving error: {e}"),
+        }
+    });
+
+    Ok(())
+}
+
+// Write to a temporary file first so a crash part way through never leaves a torn snapshot
+pub fn write_atomically(path: &Path, contents: &[u8]) -> std::io::Result<()> {
+    // Unique per save, a foreground save can run while a background one is still writing
+    static NEXT_TEMP: AtomicU64 = AtomicU64::new(0);
+    let temp = path.with_file_name(format!(
+        "temp-{}-{}.rdb",
+        std::process::id(),
+        NEXT_TEMP.fetch_add(1, Ordering::Relaxed)
+    ));
+
+    let result = (|| {
+        let mut file = fs::File::create(&temp)?;
+        file.write_all(contents)?;
+        file.sync_all()?;
+        fs::rename(&temp, path)
+    })();
+
+    if result.is_err() {
+        let _ = fs::remove_file(&temp);
+    }
+    result
+}
+
+fn unix_millis(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_millis() as u64)
+        .unwrap_or_default()
+}
+
+// Lengths use 1, 2, 5 or 9 bytes depending on their size, the top two bits say which
+fn write_length(out: &mut Vec<u8>, length: u64) {
+    if length < 1 << 6 {
+        out.push(length as u8);
+    } else if length < 1 << 14 {
+        out.push(0x40 | (length >> 8) as u8);
+        out.push(length as u8);
+    } else if length <= u32::MAX as u64 {
+        out.push(0x80);
+        out.extend_from_slice(&(length as u32).to_be_bytes());
+    } else {
+        out.push(0x81);
+        out.extend_from_slice(&length.to_be_bytes());
+    }
+}
+
+fn write_string(out: &mut Vec<u8>, value: &[u8]) {
+    write_length(out, value.len() as u64);
+    out.extend_from_slice(value);
+}
+// Load the snapshot at path into state, skipping keys that have expired since it was saved
+// Returns Ok(None) if there is no snapshot yet
+pub fn load(state: &mut State, path: &Path) -> Result<Option<usize>, String> {
+    let data = match fs::read(path) {
+        Ok(data) => data,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e.to_string()),
+    };
+
+    load_data(state, &data).map(Some)
+}
+
+// Add every key in a complete RDB file to state, as loaded at startup or sent by a master
+// Keys of types that can't be stored here refuse the whole file, unless rdb-skip-unsupported says
+// to leave them out, since the next save would lose them for good.
+pub fn load_data(state: &mut State, data: &[u8]) -> Result<usize, String> {
+    let entries = parse(data)?;
+
+    let mut skipped = BTreeMap::new();
+    for entry in &entries {
+        if !matches!(entry.value, LoadedValue::String(_) | LoadedValue::Json(_)) {
+            *skipped.entry(entry.value.type_name()).or_insert(0) += 1;
+        }
+    }
+    if !skipped.is_empty() && !state.config.rdb_skip_unsupported {
+        let skipped = skipped
+            .iter()
+            .map(|(kind, count)| format!("{count} {kind}"))
+            .collect::<Vec<_>>()
+            .join(", ");
+        return Err(format!(
+            "Unsupported keys ({skipped}), only strings and JSON documents can be loaded. \
+             Set rdb-skip-unsupported yes to load the rest without them"
+        ));
+    }
+
+    let now = SystemTime::now();
+    let mut loaded = 0;
+    for entry in entries {
+        let value = match entry.value {
+            LoadedValue::String(value) => Value::from(value),
+            LoadedValue::Json(text) => match serde_json::from_slice(&text) {
+                Ok(document) => Value::json(document),
+                Err(e) => return Err(format!("Invalid JSON document in RDB file: {e}")),
+            },
+            _ => continue,
+        };
+
+        match entry.expires_at {
+            Some(expires_at) if expires_at <= now => continue,
+            Some(expires_at) => {
+                state.ttl.push(entry.key.clone(), expires_at);
+            }
+            None => {}
+        }
+        state.keystore.insert(entry.key, value);
+        loaded += 1;
+    }
+
+    for (kind, count) in skipped {
+        tracing::warn!(
+            "Skipped {count} {kind} keys, only strings and JSON documents are supported"
+        );
+    }
+
+    Ok(loaded)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rdb::LoadedEntry;
+
+    #[test]
+    fn test_write_length() {
+        let encode = |length| {
+            let mut out = Vec::new();
+            write_length(&mut out, length);
+            out
+        };
+
+        assert_eq!(encode(10), vec![10]);
+        assert_eq!(encode(700), vec![0x42, 0xbc]);
+        assert_eq!(encode(17000), vec![0x80, 0, 0, 0x42, 0x68]);
+    }
+
+    #[test]
+    fn test_dump() {
+        let data = dump(&[Entry {
+            key: b"key",
+            value: b"value",
+            json: false,
+            expires_at: None,
+        }]);
+
+        assert!(data.starts_with(b"REDIS0009"));
+
+        let (body, checksum) = data.split_at(data.len() - 8);
+        assert!(body.ends_with(b"\x00\x03key\x05value\xff"));
+        assert_eq!(crc64(0, body).to_le_bytes(), checksum);
+    }
+
+    #[test]
+    fn test_save_points() {
+        let mut status = SaveStatus {
+            last_save: SystemTime::now() - Duration::from_secs(120),
+            changes_since_save: 5,
+            ..SaveStatus::default()
+        };
+
+        let rule = |seconds, changes| SaveRule { seconds, changes };
+        assert!(status.save_point_reached(&[rule(60, 5)]));
+        assert!(!status.save_point_reached(&[rule(60, 10), rule(300, 1)]));
+
+        // Writes that happened while saving still count towards the next save
+        status.changes_since_save = 8;
+        status.saved(5);
+        assert_eq!(status.changes_since_save, 3);
+        assert!(!status.save_point_reached(&[rule(60, 1)]));
+    }
+
+    #[test]
+    fn test_snapshot_isolation() {
+        let mut state = State::default();
+        state
+            .keystore
+            .insert(b"kept".to_vec(), Value::from("before"));
+        state
+            .keystore
+            .insert(b"deleted".to_vec(), Value::from("value"));
+
+        let snapshot = Snapshot::of(&state);
+        state
+            .keystore
+            .insert(b"kept".to_vec(), Value::from("after"));
+        state
+            .keystore
+            .insert(b"added".to_vec(), Value::from("value"));
+        state.keystore.remove(&b"deleted"[..]);
+
+        let mut entries = snapshot
+            .entries()
+            .iter()
+            .map(|entry| (entry.key, entry.value))
+            .collect::<Vec<_>>();
+        entries.sort();
+        assert_eq!(
+            entries,
+            vec![(&b"deleted"[..], &b"value"[..]), (b"kept", b"before")]
+        );
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let expires_at = UNIX_EPOCH + Duration::from_millis(4_000_000_000_123);
+        let long = "x".repeat(20000);
+        let data = dump(&[
+            Entry {
+                key: b"plain",
+                value: b"value",
+                json: false,
+                expires_at: None,
+            },
+            Entry {
+                key: b"binary\xff\x00",
+                value: b"\x00\r\n\xfe",
+                json: false,
+                expires_at: None,
+            },
+            Entry {
+                key: b"expiring",
+                value: long.as_bytes(),
+                json: false,
+                expires_at: Some(expires_at),
+            },
+            Entry {
+                key: b"document",
+                value: br#"{"a":[1,"x"]}"#,
+                json: true,
+                expires_at: None,
+            },
+        ]);
+
+        assert_eq!(
+            parse(&data).unwrap(),
+            vec![
+                LoadedEntry {
+                    key: b"plain".to_vec(),
+                    value: LoadedValue::String(b"value".to_vec()),
+                    expires_at: None,
+                },
+                LoadedEntry {
+                    key: b"binary\xff\x00".to_vec(),
+                    value: LoadedValue::String(b"\x00\r\n\xfe".to_vec()),
+                    expires_at: None,
+                },
+                LoadedEntry {
+                    key: b"expiring".to_vec(),
+                    value: LoadedValue::String(long.into_bytes()),
+                    expires_at: Some(expires_at),
+                },
+                LoadedEntry {
+                    key: b"document".to_vec(),
+                    value: LoadedValue::Json(br#"{"a":[1,"x"]}"#.to_vec()),
+                    expires_at: None,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let mut data = dump(&[Entry {
+            key: b"key",
+            value: b"value",
+            json: false,
+            expires_at: None,
+        }]);
+
+        assert!(parse(b"NOTREDIS").is_err());
+        assert!(parse(&data[..data.len() - 12]).is_err());
+
+        let last = data.len() - 1;
+        data[last] ^= 0xFF;
+        assert_eq!(parse(&data), Err(String::from("Wrong RDB checksum")));
+    }
+
+    #[test]
+    fn test_load_unsupported() {
+        // A string and a set (as an intset), as written by Redis with the checksum disabled
+        let mut data = b"REDIS0011\xFE\x00".to_vec();
+        data.extend_from_slice(b"\x00\x03str\x05value");
+        data.extend_from_slice(b"\x0B\x03set\x0A\x02\x00\x00\x00\x01\x00\x00\x00\x07\x00");
+        data.extend_from_slice(b"\xFF");
+        data.extend_from_slice(&[0; 8]);
+
+        // Refused as a whole, rather than loading what it can and saving over the rest later
+        let mut state = State::default();
+        let error = load_data(&mut state, &data).unwrap_err();
+        assert!(error.starts_with("Unsupported keys (1 set)"), "{error}");
+        assert!(state.keystore.is_empty());
+
+        state.config.rdb_skip_unsupported = true;
+        assert_eq!(load_data(&mut state, &data), Ok(1));
+        assert_eq!(state.keystore.get(&b"str"[..]), Some(&Value::from("value")));
+    }
+}
@@ -0,0 +1,344 @@
+use crate::server::clients::Client;
+use crate::server::config::BufferLimits;
+use crate::server::{LoggedKeys, State};
+use crate::value::Value;
+use crate::RedisType;
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, Instant, SystemTime};
+
+// Commands that can be answered from the published view without locking the state
+pub const COMMANDS: [&str; 3] = ["GET", "MGET", "EXISTS"];
+
+// How many reads can pile up before a connection tries to apply them itself instead of waiting
+// for the next locked command or the cron
+const MAX_PENDING: usize = 1024;
+
+// The settings each connection checks before reading more commands
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Limits {
+    pub output: BufferLimits,
+    pub query: usize,
+    pub bulk: usize,
+    pub timeout: u64,
+    pub drain_timeout: u64,
+}
+
+// The keys and settings as of the end of the last command that held the state's lock
+#[derive(Debug, Default)]
+pub struct View {
+    keystore: im::HashMap<Vec<u8>, Value>,
+    expires: im::HashMap<Vec<u8>, SystemTime>,
+    // Off whenever reads still have to go through the lock, for example during CLIENT PAUSE ALL
+    enabled: bool,
+    slow_command_threshold: i64,
+    limits: Limits,
+}
+
+impl View {
+    // Expired keys are treated as missing, the sweep or the next locked command deletes them
+    fn get(&self, key: &[u8], now: SystemTime) -> Option<&Value> {
+        match self.expires.get(key) {
+            Some(expires_at) if *expires_at <= now => None,
+            _ => self.keystore.get(key),
+        }
+    }
+
+    // None when the command has to take the lock after all, to reply with an error
+    fn reply(&self, command: &str, keys: &[&[u8]]) -> Option<RedisType> {
+        let now = SystemTime::now();
+        Some(match command {
+            "GET" => match self.get(keys[0], now) {
+                Some(value) if value.is_json() => return None,
+                Some(value) => RedisType::Bulk {
+                    value: value.clone(),
+                },
+                None => RedisType::NullString,
+            },
+            "MGET" => RedisType::Array {
+                value: keys
+                    .iter()
+                    .map(|key| match self.get(key, now) {
+                        Some(value) if !value.is_json() => RedisType::Bulk {
+                            value: value.clone(),
+                        },
+                        _ => RedisType::NullString,
+                    })
+                    .collect(),
+            },
+            _ => {
+                let count = keys
+                    .iter()
+                    .filter(|key| self.get(key, now).is_some())
+                    .count();
+                RedisType::from(count as i64)
+            }
+        })
+    }
+}
+
+// What reads did that the state has to know about, applied the next time it's locked
+#[derive(Debug, Default)]
+struct Pending {
+    calls: Vec<(&'static str, Duration)>,
+    touched: Vec<(Vec<u8>, Instant)>,
+    // The client's last read, its other details only change in commands that take the lock
+    clients: BTreeMap<u64, (Instant, &'static str)>,
+}
+
+// Shared between the state and every connection
+// Commands that hold the state's lock publish a new view when they change the keys. Since both
+// maps are persistent that only copies a pointer, and readers only hold the RwLock long enough to
+// clone the Arc, so GET, MGET and EXISTS never wait for the state's lock (or for each other).
+#[derive(Debug, Default)]
+pub struct Reads {
+    view: RwLock<Arc<View>>,
+    pending: Mutex<Pending>,
+}
+
+impl Reads {
+    // Answer a read from the current view, or None if it has to run as a normal command
+    pub fn execute(&self, client: &mut Client, command: &RedisType) -> Option<RedisType> {
+        let argv = match command {
+            RedisType::Array { value } => value,
+            _ => return None,
+        };
+        let command = match argv.first() {
+            Some(RedisType::String { value }) => *COMMANDS
+                .iter()
+                .find(|name| name.eq_ignore_ascii_case(value))?,
+            _ => return None,
+        };
+        // Tracked reads have to be remembered, which needs the lock, and a subscribed connection
+        // may not be allowed to read at all
+        if !client.authenticated
+            || client.tracking
+            || client.subscribed()
+            || !crate::server::COMMANDS[command].check_arity(argv.len())
+        {
+            return None;
+        }
+        let keys = argv[1..]
+            .iter()
+            .map(|arg| match arg {
+                RedisType::String { value } => Some(value.as_bytes()),
+                RedisType::Bulk { value } => Some(&value[..]),
+                _ => None,
+            })
+            .collect::<Option<Vec<_>>>()?;
+
+        let view = self.view.read().unwrap().clone();
+        if !view.enabled {
+            return None;
+        }
+
+        let addr = client.addr;
+        let span = tracing::info_span!(
+            "command",
+            client = client.id,
+            %addr,
+            name = client.name.as_deref().unwrap_or(""),
+            %command,
+            keys = ?LoggedKeys(&keys),
+            duration_us = tracing::field::Empty,
+            outcome = tracing::field::Empty,
+        );
+        let _entered = span.enter();
+        tracing::debug!("Received: {:?}", &argv[1..]);
+
+        let start = Instant::now();
+        let reply = view.reply(command, &keys)?;
+        let elapsed = start.elapsed();
+
+        span.record("duration_us", elapsed.as_micros() as u64);
+        span.record("outcome", "ok");
+        let slow = view.slow_command_threshold;
+        if slow >= 0 && elapsed.as_micros() > slow as u128 {
+            tracing::warn!("Slow command");
+        } else {
+            tracing::debug!("Command finished");
+        }
+
+        let now = Instant::now();
+        client.last_interaction = now;
+        if !client
+            .last_command
+            .as_deref()
+            .is_some_and(|last| last.eq_ignore_ascii_case(command))
+        {
+            client.last_command = Some(command.to_ascii_lowercase());
+        }
+
+        let mut pending = self.pending.lock().unwrap();
+        pending.calls.push((command, elapsed));
+        if !client.no_touch {
+            let now_system = SystemTime::now();
+            for key in keys {
+                if view.get(key, now_system).is_some() {
+                    pending.touched.push((key.to_vec(), now));
+                }
+            }
+        }
+        pending.clients.insert(client.id, (now, command));
+
+        Some(reply)
+    }
+
+    pub fn limits(&self) -> Limits {
+        self.view.read().unwrap().limits
+    }
+
+    fn is_full(&self) -> bool {
+        self.pending.lock().unwrap().calls.len() >= MAX_PENDING
+    }
+}
+
+// Apply what reads have done since the last time, as if they had run while holding the lock
+pub fn flush(state: &mut State) {
+    let pending = std::mem::take(&mut *state.reads.pending.lock().unwrap());
+
+    let threshold = state.config.latency_monitor_threshold;
+    for (command, elapsed) in pending.calls {
+        state.stats.record_call(command, elapsed, false);
+        state.latency.record("fast-command", elapsed, threshold);
+    }
+    let rate = state.config.hotkeys_sample_rate;
+    for (key, at) in pending.touched {
+        if let Some(value) = state.keystore.get(&key) {
+            state.hotkeys.record(&key, value, rate, at);
+            state.last_access.insert(key, at);
+        }
+    }
+    // Clients that have disconnected since stay gone
+    for (id, (last_interaction, command)) in pending.clients {
+        if let Some(info) = state.clients.get_mut(&id) {
+            info.last_interaction = last_interaction;
+            info.last_command = Some(command.to_ascii_lowercase());
+        }
+    }
+}
+
+// Apply pending reads if there are a lot of them and the state isn't busy
+pub fn flush_if_full(state: &tokio::sync::Mutex<State>, reads: &Reads) {
+    if reads.is_full() {
+        if let Ok(mut state) = state.try_lock() {
+            flush(&mut state);
+        }
+    }
+}
+
+// Make the state's current keys visible to reads, called with the state locked after changes
+pub fn publish(state: &State) {
+    let enabled = !state.config.cluster_enabled
+        && state.sentinel.is_none()
+        && state
+            .pause
+            .is_none_or(|pause| pause.remaining(false).is_none());
+    let slow_command_threshold = state.config.slow_command_threshold;
+    let limits = Limits {
+        output: state.config.client_output_buffer_limit,
+        query: state.config.client_query_buffer_limit,
+        bulk: state.config.proto_max_bulk_len,
+        timeout: state.config.timeout,
+        drain_timeout: state.config.shutdown_drain_timeout,
+    };
+
+    let current = state.reads.view.read().unwrap().clone();
+    if current.enabled == enabled
+        && current.slow_command_threshold == slow_command_threshold
+        && current.limits == limits
+        && current.keystore.ptr_eq(&state.keystore)
+        && current.expires.ptr_eq(state.ttl.expires())
+    {
+        return;
+    }
+
+    *state.reads.view.write().unwrap() = Arc::new(View {
+        keystore: state.keystore.clone(),
+        expires: state.ttl.expires().clone(),
+        enabled,
+        slow_command_threshold,
+        limits,
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::SocketAddr;
+
+    fn command(argv: &[&str]) -> RedisType {
+        RedisType::Array {
+            value: argv
+                .iter()
+                .map(|arg| RedisType::from(arg.to_string()))
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn test_reads() {
+        let mut state = State::default();
+        let addr = SocketAddr::from(([127, 0, 0, 1], 1234));
+        let mut client = Client::new(addr, addr);
+        client.authenticated = true;
+        state.clients.insert(client.id, client.info());
+        let reads = state.reads.clone();
+
+        // Nothing is served until the state publishes a view
+        assert_eq!(reads.execute(&mut client, &command(&["GET", "a"])), None);
+
+        state.keystore.insert(b"a".to_vec(), Value::from("1"));
+        state.keystore.insert(b"old".to_vec(), Value::from("2"));
+        let past = SystemTime::now() - Duration::from_secs(1);
+        state.ttl.push(b"old".to_vec(), past);
+        publish(&state);
+
+        assert_eq!(
+            reads.execute(&mut client, &command(&["get", "a"])),
+            Some(RedisType::from(Value::from("1")))
+        );
+        assert_eq!(
+            reads.execute(&mut client, &command(&["GET", "old"])),
+            Some(RedisType::NullString)
+        );
+        assert_eq!(
+            reads.execute(&mut client, &command(&["MGET", "a", "b"])),
+            Some(RedisType::Array {
+                value: vec![RedisType::from(Value::from("1")), RedisType::NullString]
+            })
+        );
+        assert_eq!(
+            reads.execute(&mut client, &command(&["EXISTS", "a", "a", "old"])),
+            Some(RedisType::from(2))
+        );
+
+        // Anything else, including wrong arity, runs as a normal command
+        assert_eq!(reads.execute(&mut client, &command(&["GET"])), None);
+        assert_eq!(
+            reads.execute(&mut client, &command(&["SET", "a", "2"])),
+            None
+        );
+
+        // Later writes aren't visible until published
+        state.keystore.insert(b"b".to_vec(), Value::from("3"));
+        assert_eq!(
+            reads.execute(&mut client, &command(&["EXISTS", "b"])),
+            Some(RedisType::from(0))
+        );
+        publish(&state);
+        assert_eq!(
+            reads.execute(&mut client, &command(&["EXISTS", "b"])),
+            Some(RedisType::from(1))
+        );
+
+        flush(&mut state);
+        assert_eq!(state.stats.total_commands_processed, 6);
+        assert!(state.last_access.contains_key(&b"a"[..]));
+        assert!(!state.last_access.contains_key(&b"old"[..]));
+        assert_eq!(
+            state.clients[&client.id].last_command.as_deref(),
+            Some("exists")
+        );
+    }
+}
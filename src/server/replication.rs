@@ -0,0 +1,883 @@
+use crate::server::clients::Client;
+use crate::server::lifecycle::Shutdown;
+use crate::server::rdb::{self, Snapshot};
+use crate::server::{aof, State};
+use crate::{RedisType, RedisTypeParseError};
+use std::collections::hash_map::RandomState;
+use std::collections::BTreeMap;
+use std::fmt::Display;
+use std::hash::{BuildHasher, Hasher};
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tokio::sync::{mpsc, Mutex};
+
+// Replicas get a PING this often, so they can tell the link is up even without any writes
+const PING_PERIOD: Duration = Duration::from_secs(10);
+
+// A master that hasn't sent anything (not even a PING) for this long is considered gone
+const REPL_TIMEOUT: Duration = Duration::from_secs(60);
+
+// How often a replica tells its master how much of the stream it has applied
+const ACK_PERIOD: Duration = Duration::from_secs(1);
+
+// The master side of replication: every write is streamed to the connected replicas, the offset
+// counts bytes of that stream so that replicas can report back how much of it they've applied
+// As a replica, master is set and the offset is how much of the master's stream has been applied
+#[derive(Debug)]
+pub struct Replication {
+    pub replid: String,
+    pub offset: u64,
+    pub replicas: BTreeMap<u64, Replica>,
+    pub master: Option<MasterLink>,
+    pub failover: Option<Failover>,
+    last_ping: Instant,
+    // The offset when replicas were last asked to acknowledge it with REPLCONF GETACK
+    getack_offset: u64,
+}
+
+// Set by REPLICAOF, the connection itself is made (and remade) by the cron
+#[derive(Debug)]
+pub struct MasterLink {
+    pub host: String,
+    pub port: u16,
+    pub status: LinkStatus,
+    pub last_io: Instant,
+    // When a working link was lost, None if it has never been up
+    pub down_since: Option<Instant>,
+    // Set when we are handing over to this master with FAILOVER, which PSYNC asks it to finish
+    pub failover: bool,
+    // Triggered to drop the connection when the master changes or we're promoted
+    cancel: Arc<Shutdown>,
+}
+
+// As reported by ROLE
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum LinkStatus {
+    // Waiting for the cron to connect, initially and after the link is lost
+    Connect,
+    Connecting,
+    // Receiving the snapshot
+    Sync,
+    Connected,
+}
+
+impl Display for LinkStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            LinkStatus::Connect => "connect",
+            LinkStatus::Connecting => "connecting",
+            LinkStatus::Sync => "sync",
+            LinkStatus::Connected => "connected",
+        };
+        write!(f, "{name}")
+    }
+}
+
+impl Default for Replication {
+    fn default() -> Self {
+        Replication {
+            replid: new_replid(),
+            offset: 0,
+            replicas: BTreeMap::new(),
+            master: None,
+            failover: None,
+            last_ping: Instant::now(),
+            getack_offset: 0,
+        }
+    }
+}
+
+// A connection that has become a replica with PSYNC or SYNC, keyed by its client id
+#[derive(Debug)]
+pub struct Replica {
+    pub addr: SocketAddr,
+    // Sent with REPLCONF listening-port, where the replica accepts its own clients
+    pub listening_port: Option<u16>,
+    // Set once the snapshot has been sent, until then writes are only queued
+    pub online: bool,
+    pub ack_offset: u64,
+    pub last_ack: Instant,
+    stream: mpsc::UnboundedSender<Vec<u8>>,
+}
+
+// A FAILOVER in progress, writes are paused until it finishes or is aborted
+#[derive(Debug)]
+pub struct Failover {
+    // The ip and port of the replica to hand over to, otherwise the first one to catch up
+    pub target: Option<(String, u16)>,
+    pub deadline: Option<Instant>,
+    // Hand over to the target at the deadline even if it hasn't caught up
+    pub force: bool,
+    pub state: FailoverState,
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum FailoverState {
+    WaitingForSync,
+    // We're now a replica of the target, waiting for it to accept PSYNC FAILOVER
+    InProgress,
+}
+
+impl Display for FailoverState {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            FailoverState::WaitingForSync => "waiting-for-sync",
+            FailoverState::InProgress => "failover-in-progress",
+        };
+        write!(f, "{name}")
+    }
+}
+
+impl Replica {
+    // Where the replica accepts its own clients, as shown by ROLE and INFO and used by FAILOVER
+    pub fn endpoint(&self) -> (String, u16) {
+        (
+            self.addr.ip().to_string(),
+            self.listening_port.unwrap_or(self.addr.port()),
+        )
+    }
+}
+
+// Everything a connection needs to start acting as a replica, handed over by PSYNC and SYNC
+#[derive(Debug)]
+pub struct FullSync {
+    pub replid: String,
+    pub offset: u64,
+    // SYNC predates PSYNC and doesn't get the +FULLRESYNC line
+    pub psync: bool,
+    pub snapshot: Snapshot,
+    pub stream: mpsc::UnboundedReceiver<Vec<u8>>,
+}
+
+impl Replication {
+    // Start replicating to a client, writes from now on are queued until it has the snapshot
+    pub fn attach(
+        &mut self,
+        id: u64,
+        addr: SocketAddr,
+        listening_port: Option<u16>,
+        snapshot: Snapshot,
+        psync: bool,
+    ) -> FullSync {
+        let (sender, receiver) = mpsc::unbounded_channel();
+        self.replicas.insert(
+            id,
+            Replica {
+                addr,
+                listening_port,
+                online: false,
+                ack_offset: 0,
+                last_ack: Instant::now(),
+                stream: sender,
+            },
+        );
+
+        FullSync {
+            replid: self.replid.clone(),
+            offset: self.offset,
+            psync,
+            snapshot,
+            stream: receiver,
+        }
+    }
+
+    pub fn detach(&mut self, id: u64) {
+        self.replicas.remove(&id);
+    }
+
+    pub fn online(&mut self, id: u64) {
+        if let Some(replica) = self.replicas.get_mut(&id) {
+            replica.online = true;
+        }
+    }
+
+    // REPLCONF ACK, how much of the stream a replica has processed
+    pub fn ack(&mut self, id: u64, offset: u64) {
+        if let Some(replica) = self.replicas.get_mut(&id) {
+            replica.ack_offset = replica.ack_offset.max(offset);
+            replica.last_ack = Instant::now();
+        }
+    }
+
+    pub fn is_streaming(&self) -> bool {
+        !self.replicas.is_empty()
+    }
+
+    // Send a write to every replica, advancing the offset by its size
+    pub fn propagate(&mut self, argv: &[Vec<u8>]) {
+        if self.replicas.is_empty() {
+            return;
+        }
+
+        let data = aof::encode(argv);
+        self.offset += data.len() as u64;
+        self.replicas
+            .retain(|_, replica| replica.stream.send(data.clone()).is_ok());
+    }
+
+    // Ask replicas to acknowledge the stream so far now rather than in their own time, for WAIT
+    // Only once per offset, otherwise each ack would be answered with another GETACK.
+    pub fn request_acks(&mut self) {
+        if self.getack_offset != self.offset {
+            self.propagate(&[b"REPLCONF".to_vec(), b"GETACK".to_vec(), b"*".to_vec()]);
+            self.getack_offset = self.offset;
+        }
+    }
+
+    // How many replicas have acknowledged the stream up to offset
+    pub fn acknowledged(&self, offset: u64) -> usize {
+        self.replicas
+            .values()
+            .filter(|replica| replica.online && replica.ack_offset >= offset)
+            .count()
+    }
+
+    // Called every second
+    pub fn cron(&mut self) {
+        if self.last_ping.elapsed() >= PING_PERIOD {
+            self.propagate(&[b"PING".to_vec()]);
+            self.last_ping = Instant::now();
+        }
+    }
+
+    // REPLICAOF host port, our own replicas are dropped since our data is about to be replaced
+    pub fn replicate_from(&mut self, host: String, port: u16) {
+        if let Some(link) = self.master.take() {
+            link.cancel.trigger();
+        }
+        self.replicas.clear();
+        self.master = Some(MasterLink {
+            host,
+            port,
+            status: LinkStatus::Connect,
+            last_io: Instant::now(),
+            down_since: None,
+            failover: false,
+            cancel: Arc::default(),
+        });
+    }
+
+    // REPLICAOF NO ONE, keeping the data but starting a history of our own
+    pub fn promote(&mut self) {
+        if let Some(link) = self.master.take() {
+            link.cancel.trigger();
+            self.replid = new_replid();
+        }
+    }
+
+    // An online replica that has acknowledged everything we've sent, limited to target if given
+    fn caught_up(&self, target: Option<&(String, u16)>) -> Option<(String, u16)> {
+        self.replicas
+            .values()
+            .filter(|replica| replica.online && replica.ack_offset >= self.offset)
+            .map(Replica::endpoint)
+            .find(|endpoint| target.is_none_or(|target| target == endpoint))
+    }
+
+    // A link waiting to connect, which is then marked as connecting
+    pub fn connect(&mut self) -> Option<(String, u16, Arc<Shutdown>)> {
+        let link = self.master.as_mut()?;
+        if link.status != LinkStatus::Connect {
+            return None;
+        }
+
+        link.status = LinkStatus::Connecting;
+        Some((link.host.clone(), link.port, link.cancel.clone()))
+    }
+
+    // The master link, as long as it is still the one cancel belongs to
+    fn link(&mut self, cancel: &Arc<Shutdown>) -> Option<&mut MasterLink> {
+        self.master
+            .as_mut()
+            .filter(|link| Arc::ptr_eq(&link.cancel, cancel))
+    }
+}
+
+// Move a FAILOVER along, called every second and whenever a replica acknowledges
+pub fn update_failover(state: &mut State) {
+    let failover = match &state.replication.failover {
+        Some(failover) if failover.state == FailoverState::WaitingForSync => failover,
+        _ => return,
+    };
+
+    let timed_out = failover
+        .deadline
+        .is_some_and(|deadline| deadline <= Instant::now());
+    let target = match state.replication.caught_up(failover.target.as_ref()) {
+        Some(target) => target,
+        None if timed_out && failover.force => failover.target.clone().unwrap(),
+        None if timed_out => {
+            tracing::warn!("FAILOVER timed out waiting for a replica to catch up, aborting");
+            end_failover(state);
+            return;
+        }
+        None => return,
+    };
+
+    // Handing over is finished by the link, once the target accepts PSYNC FAILOVER
+    let (host, port) = target;
+    tracing::info!("Failover target {host}:{port} is synced, failing over");
+    state.replication.replicate_from(host.clone(), port);
+    state.replication.master.as_mut().unwrap().failover = true;
+    state.replication.failover.as_mut().unwrap().state = FailoverState::InProgress;
+    state.config.replicaof = Some((host, port));
+}
+
+// Let paused writes through once a FAILOVER is over, however it ended
+pub fn end_failover(state: &mut State) {
+    state.replication.failover = None;
+    state.unpaused.notify_waiters();
+}
+
+// FAILOVER ABORT, or the target refusing to take over
+pub fn abort_failover(state: &mut State) {
+    let in_progress = state
+        .replication
+        .failover
+        .as_ref()
+        .is_some_and(|failover| failover.state == FailoverState::InProgress);
+    if in_progress {
+        state.replication.promote();
+        state.config.replicaof = None;
+    }
+    end_failover(state);
+}
+
+// Replicate from a master until REPLICAOF changes it or the server shuts down
+// If the connection is lost, the link goes back to waiting for the cron to reconnect
+pub async fn run_link(state: Arc<Mutex<State>>, host: String, port: u16, cancel: Arc<Shutdown>) {
+    let mut cancelled = cancel.subscribe();
+    let mut shutdown = state.lock().await.shutdown.subscribe();
+
+    let result = tokio::select! {
+        result = sync_with_master(&state, &host, port, &cancel) => result,
+        _ = cancelled.wait() => return,
+        _ = shutdown.wait() => return,
+    };
+    if let Err(e) = result {
+        tracing::warn!("Replication with master {host}:{port} failed: {e}");
+    }
+
+    let mut state = state.lock().await;
+    if let Some(link) = state.replication.link(&cancel) {
+        if link.failover {
+            tracing::warn!("FAILOVER to {host}:{port} failed, staying a master");
+            abort_failover(&mut state);
+            return;
+        }
+
+        if link.status == LinkStatus::Connected {
+            link.down_since = Some(Instant::now());
+        }
+        link.status = LinkStatus::Connect;
+    }
+}
+
+// Handshake with the master, load its snapshot and then apply its writes as they arrive
+async fn sync_with_master(
+    state: &Arc<Mutex<State>>,
+    host: &str,
+    port: u16,
+    cancel: &Arc<Shutdown>,
+) -> Result<(), String> {
+    let (masterauth, listening_port, failover, our_replid, our_offset) = {
+        let mut state = state.lock().await;
+        let replication = &mut state.replication;
+        let failover = replication.link(cancel).is_some_and(|link| link.failover);
+        let (replid, offset) = (replication.replid.clone(), replication.offset);
+        (
+            state.config.masterauth.clone(),
+            state.config.port,
+            failover,
+            replid,
+            offset,
+        )
+    };
+
+    tracing::info!("Connecting to MASTER {host}:{port}");
+    let stream = tokio::time::timeout(REPL_TIMEOUT, TcpStream::connect((host, port)))
+        .await
+        .map_err(|_| String::from("Timeout connecting to the MASTER"))?
+        .map_err(|e| format!("Error connecting to the MASTER: {e}"))?;
+    let mut master = MasterConnection::new(stream);
+    tracing::info!("MASTER <-> REPLICA sync started");
+
+    if let Some(password) = masterauth {
+        master
+            .command(&["AUTH", &password])
+            .await
+            .map_err(|e| format!("Unable to AUTH to MASTER: {e}"))?;
+    }
+    master
+        .command(&["PING"])
+        .await
+        .map_err(|e| format!("Error reply to PING from master: {e}"))?;
+
+    // Older masters don't know these, which isn't a reason to give up
+    let listening_port = listening_port.to_string();
+    for argv in [
+        ["REPLCONF", "listening-port", listening_port.as_str()],
+        ["REPLCONF", "capa", "psync2"],
+    ] {
+        if let Err(e) = master.command(&argv).await {
+            tracing::warn!("(Non critical) Master does not understand {argv:?}: {e}");
+        }
+    }
+
+    // Without a previous replid this is always a full resync, but when failing over the new master
+    // checks that it was replicating from us
+    if failover {
+        let offset = our_offset.to_string();
+        master
+            .send(&["PSYNC", &our_replid, &offset, "FAILOVER"])
+            .await?;
+    } else {
+        master.send(&["PSYNC", "?", "-1"]).await?;
+    }
+    let reply = master.read_line().await?;
+    let (replid, offset) = reply
+        .strip_prefix('+')
+        .and_then(
+            |reply| match reply.split_ascii_whitespace().collect::<Vec<_>>()[..] {
+                ["FULLRESYNC", replid, offset] => {
+                    Some((replid.to_owned(), offset.parse::<u64>().ok()?))
+                }
+                _ => None,
+            },
+        )
+        .ok_or_else(|| format!("Unexpected reply to PSYNC from master: {reply}"))?;
+    tracing::info!("Full resync from master: {replid}:{offset}");
+
+    {
+        let mut state = state.lock().await;
+        match state.replication.link(cancel) {
+            Some(link) => {
+                link.status = LinkStatus::Sync;
+                link.failover = false;
+            }
+            None => return Ok(()),
+        }
+        if failover {
+            tracing::info!("Failover to {host}:{port} complete");
+            end_failover(&mut state);
+        }
+    }
+
+    let header = master.read_line().await?;
+    let len = header
+        .strip_prefix('$')
+        .and_then(|len| len.parse::<usize>().ok())
+        .ok_or_else(|| {
+            format!("Bad protocol from MASTER, expected the snapshot length: {header}")
+        })?;
+    tracing::info!("MASTER <-> REPLICA sync: receiving {len} bytes from master");
+    let data = master.read_exact(len).await?;
+
+    {
+        let mut state = state.lock().await;
+        let state = &mut *state;
+        if state.replication.link(cancel).is_none() {
+            return Ok(());
+        }
+
+        tracing::info!("MASTER <-> REPLICA sync: Flushing old data");
+        state.keystore.clear();
+        state.ttl.clear();
+        state.last_access.clear();
+        crate::server::tracking::invalidate_all(state);
+
+        let keys = rdb::load_data(state, &data)
+            .map_err(|e| format!("Failed trying to load the MASTER synchronization DB: {e}"))?;
+        tracing::info!("MASTER <-> REPLICA sync: Loaded {keys} keys");
+        crate::server::reads::publish(state);
+
+        // The old file describes data we no longer have
+        if state.aof.is_some() {
+            let path = PathBuf::from(&state.config.appendfilename);
+            match aof::start(state, &path, state.config.appendfsync) {
+                Ok(aof) => state.aof = Some(aof),
+                Err(e) => tracing::error!("Unable to restart the append only file: {e}"),
+            }
+        }
+
+        state.replication.replid = replid;
+        state.replication.offset = offset;
+        let link = state.replication.link(cancel).unwrap();
+        link.status = LinkStatus::Connected;
+        link.last_io = Instant::now();
+    }
+    tracing::info!("MASTER <-> REPLICA sync: Finished with success");
+
+    // The master shows up in CLIENT LIST like any other client
+    let mut client = Client::new(master.peer_addr, master.local_addr);
+    client.authenticated = true;
+    client.master = true;
+    state.lock().await.clients.insert(client.id, client.info());
+
+    let result = apply_stream(state, &mut master, &mut client, cancel, offset).await;
+
+    state.lock().await.clients.remove(&client.id);
+    result
+}
+
+// Run every write the master sends, acknowledging how far we've got
+async fn apply_stream(
+    state: &Arc<Mutex<State>>,
+    master: &mut MasterConnection,
+    client: &mut Client,
+    cancel: &Arc<Shutdown>,
+    mut offset: u64,
+) -> Result<(), String> {
+    let mut ack = tokio::time::interval(ACK_PERIOD);
+    let mut last_io = Instant::now();
+
+    loop {
+        while let Some((command, len)) = next_command(&master.buffer)? {
+            master.buffer.drain(..len);
+
+            // Commands about the link itself rather than writes to apply
+            let words = match &command {
+                RedisType::Array { value } => value
+                    .iter()
+                    .take(2)
+                    .map(|arg| match arg {
+                        RedisType::String { value } => value.to_ascii_uppercase(),
+                        _ => String::new(),
+                    })
+                    .collect::<Vec<_>>(),
+                _ => vec![],
+            };
+            match words.iter().map(String::as_str).collect::<Vec<_>>()[..] {
+                // The reply doesn't include the GETACK itself
+                ["REPLCONF", "GETACK"] => {
+                    master
+                        .send(&["REPLCONF", "ACK", &offset.to_string()])
+                        .await?
+                }
+                // Only database 0 exists
+                ["SELECT", _] => {}
+                _ => {
+                    crate::server::execute(state, client, &command).await;
+                }
+            }
+            offset += len as u64;
+
+            let mut state = state.lock().await;
+            state.replication.offset = offset;
+            if state.replication.link(cancel).is_none() {
+                return Ok(());
+            }
+        }
+
+        tokio::select! {
+            result = master.fill() => {
+                result?;
+                last_io = Instant::now();
+                if let Some(link) = state.lock().await.replication.link(cancel) {
+                    link.last_io = last_io;
+                }
+            }
+            _ = ack.tick() => {
+                if last_io.elapsed() > REPL_TIMEOUT {
+                    return Err(String::from("MASTER timeout, no data nor PING received"));
+                }
+                master.send(&["REPLCONF", "ACK", &offset.to_string()]).await?;
+            }
+        }
+    }
+}
+
+// Split the next complete command off the start of the stream, with how many bytes it took
+fn next_command(input: &[u8]) -> Result<Option<(RedisType, usize)>, String> {
+    match RedisType::parse_prefix(input) {
+        _ if input.is_empty() => Ok(None),
+        Ok(parsed) => Ok(Some(parsed)),
+        Err(RedisTypeParseError::Incomplete) => Ok(None),
+        Err(e) => Err(format!("Protocol error in the replication stream ({e:?})")),
+    }
+}
+
+// The connection to the master, buffering anything read past what has been asked for
+// Sentinels use it to talk to the servers they monitor as well
+pub struct MasterConnection {
+    stream: TcpStream,
+    buffer: Vec<u8>,
+    peer_addr: SocketAddr,
+    local_addr: SocketAddr,
+}
+
+impl MasterConnection {
+    pub fn new(stream: TcpStream) -> Self {
+        let unspecified = SocketAddr::from(([0, 0, 0, 0], 0));
+        MasterConnection {
+            peer_addr: stream.peer_addr().unwrap_or(unspecified),
+            local_addr: stream.local_addr().unwrap_or(unspecified),
+            stream,
+            buffer: Vec::new(),
+        }
+    }
+
+    async fn fill(&mut self) -> Result<(), String> {
+        let mut buf = [0; 4096];
+        let bytes_read = tokio::time::timeout(REPL_TIMEOUT, self.stream.read(&mut buf))
+            .await
+            .map_err(|_| String::from("Timeout reading from MASTER"))?
+            .map_err(|e| format!("Error reading from MASTER: {e}"))?;
+        if bytes_read == 0 {
+            return Err(String::from("Connection closed by MASTER"));
+        }
+
+        self.buffer.extend_from_slice(&buf[..bytes_read]);
+        Ok(())
+    }
+
+    async fn send(&mut self, argv: &[&str]) -> Result<(), String> {
+        let argv = argv
+            .iter()
+            .map(|arg| String::from(*arg))
+            .collect::<Vec<_>>();
+        self.stream
+            .write_all(&aof::encode(&argv))
+            .await
+            .map_err(|e| format!("Error writing to MASTER: {e}"))
+    }
+
+    // A line without the \r\n, skipping the newlines a master sends to keep the link alive
+    // while it prepares the snapshot
+    async fn read_line(&mut self) -> Result<String, String> {
+        loop {
+            let keepalives = self.buffer.iter().take_while(|b| **b == b'\n').count();
+            self.buffer.drain(..keepalives);
+
+            if let Some(end) = self.buffer.windows(2).position(|w| w == b"\r\n") {
+                let line = String::from_utf8_lossy(&self.buffer[..end]).into_owned();
+                self.buffer.drain(..end + 2);
+                return Ok(line);
+            }
+            self.fill().await?;
+        }
+    }
+
+    async fn read_exact(&mut self, len: usize) -> Result<Vec<u8>, String> {
+        while self.buffer.len() < len {
+            self.fill().await?;
+        }
+        Ok(self.buffer.drain(..len).collect())
+    }
+
+    // Send a command and read a single line reply, which may be a bulk string
+    pub async fn command(&mut self, argv: &[&str]) -> Result<String, String> {
+        self.send(argv).await?;
+
+        let line = self.read_line().await?;
+        match line.chars().next() {
+            Some('-') => Err(line[1..].to_owned()),
+            Some('+') | Some(':') => Ok(line[1..].to_owned()),
+            Some('$') => {
+                let len = line[1..]
+                    .parse::<i64>()
+                    .map_err(|_| format!("Bad bulk length: {line}"))?;
+                if len < 0 {
+                    return Ok(String::new());
+                }
+                let data = self.read_exact(len as usize + 2).await?;
+                Ok(String::from_utf8_lossy(&data[..len as usize]).into_owned())
+            }
+            _ => Err(format!("Unexpected reply: {line}")),
+        }
+    }
+}
+
+// 40 random hex characters, like Redis, RandomState is randomly seeded without needing a crate
+pub fn new_replid() -> String {
+    let state = RandomState::new();
+    let mut replid = (0..3)
+        .map(|i| {
+            let mut hasher = state.build_hasher();
+            hasher.write_u64(i);
+            format!("{:016x}", hasher.finish())
+        })
+        .collect::<String>();
+    replid.truncate(40);
+    replid
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_new_replid() {
+        let replid = new_replid();
+        assert_eq!(replid.len(), 40);
+        assert!(replid.chars().all(|c| c.is_ascii_hexdigit()));
+        assert_ne!(replid, new_replid());
+    }
+
+    #[test]
+    fn test_propagate() {
+        let mut replication = Replication::default();
+        let addr = SocketAddr::from(([127, 0, 0, 1], 1234));
+
+        // Nothing is counted without replicas to send to
+        replication.propagate(&[b"PING".to_vec()]);
+        assert_eq!(replication.offset, 0);
+
+        let snapshot = Snapshot::of(&crate::server::State::default());
+        let mut sync = replication.attach(1, addr, Some(6380), snapshot, true);
+        assert_eq!(sync.offset, 0);
+
+        replication.propagate(&[b"DEL".to_vec(), b"key".to_vec()]);
+        let data = sync.stream.try_recv().unwrap();
+        assert_eq!(data, b"*2\r\n$3\r\nDEL\r\n$3\r\nkey\r\n");
+        assert_eq!(replication.offset, data.len() as u64);
+
+        replication.ack(1, 10);
+        replication.ack(1, 5);
+        assert_eq!(replication.replicas[&1].ack_offset, 10);
+
+        // Replicas whose connection has gone away are dropped
+        drop(sync);
+        replication.propagate(&[b"PING".to_vec()]);
+        assert!(!replication.is_streaming());
+    }
+
+    #[test]
+    fn test_master_link() {
+        let mut replication = Replication::default();
+        let replid = replication.replid.clone();
+
+        replication.replicate_from(String::from("localhost"), 6379);
+        let (host, port, cancel) = replication.connect().unwrap();
+        assert_eq!((host.as_str(), port), ("localhost", 6379));
+        assert_eq!(
+            replication.master.as_ref().unwrap().status,
+            LinkStatus::Connecting
+        );
+        assert!(replication.connect().is_none());
+
+        // Switching masters cancels the old link, which can no longer update the new one
+        replication.replicate_from(String::from("localhost"), 6380);
+        assert!(cancel.subscribe().is_triggered());
+        assert!(replication.link(&cancel).is_none());
+
+        replication.promote();
+        assert!(replication.master.is_none());
+        assert_ne!(replication.replid, replid);
+    }
+
+    #[test]
+    fn test_failover() {
+        let mut state = State::default();
+        let addr = SocketAddr::from(([127, 0, 0, 1], 1234));
+        let snapshot = Snapshot::of(&state);
+        let _sync = state
+            .replication
+            .attach(1, addr, Some(6380), snapshot, true);
+        state.replication.online(1);
+        state.replication.propagate(&[b"PING".to_vec()]);
+
+        // Nothing happens until the replica has acknowledged everything
+        state.replication.failover = Some(Failover {
+            target: Some((String::from("127.0.0.1"), 6380)),
+            deadline: None,
+            force: false,
+            state: FailoverState::WaitingForSync,
+        });
+        update_failover(&mut state);
+        assert!(state.replication.master.is_none());
+
+        let offset = state.replication.offset;
+        state.replication.ack(1, offset);
+        update_failover(&mut state);
+        let link = state.replication.master.as_ref().unwrap();
+        assert_eq!((link.host.as_str(), link.port), ("127.0.0.1", 6380));
+        assert!(link.failover);
+        assert_eq!(
+            state.replication.failover.as_ref().unwrap().state,
+            FailoverState::InProgress
+        );
+
+        // Aborting goes back to being a master
+        abort_failover(&mut state);
+        assert!(state.replication.master.is_none());
+        assert!(state.replication.failover.is_none());
+        assert!(state.config.replicaof.is_none());
+    }
+
+    #[test]
+    fn test_next_command() {
+        let data = b"*1\r\n$4\r\nPING\r\n*2\r\n$3\r\nDEL";
+        let (command, len) = next_command(data).unwrap().unwrap();
+        assert_eq!(len, 14);
+        assert_eq!(
+            command.to_string(),
+            RedisType::from(vec![RedisType::from(String::from("PING"))]).to_string()
+        );
+
+        assert_eq!(next_command(&data[len..]), Ok(None));
+        assert_eq!(next_command(b""), Ok(None));
+        let (command, _) = next_command(b"*1\r\n$4\r\nP\xFFNG\r\n").unwrap().unwrap();
+        assert_eq!(
+            command,
+            RedisType::from(vec![RedisType::from(b"P\xFFNG".to_vec())])
+        );
+        assert!(next_command(b"PING\r\n").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_read_only_replica() {
+        use crate::server::{execute, ServerError};
+
+        let state = Arc::new(Mutex::new(State::default()));
+        state
+            .lock()
+            .await
+            .replication
+            .replicate_from(String::from("localhost"), 6379);
+        let addr = SocketAddr::from(([127, 0, 0, 1], 1234));
+        let command = |args: &[&str]| {
+            RedisType::from(
+                args.iter()
+                    .map(|arg| RedisType::from(String::from(*arg)))
+                    .collect::<Vec<_>>(),
+            )
+        };
+        let set = command(&["SET", "key", "value"]);
+        let get = command(&["GET", "key"]);
+
+        // A normal client can read, but its writes are refused
+        let mut client = Client::new(addr, addr);
+        client.authenticated = true;
+        assert_eq!(
+            execute(&state, &mut client, &set).await,
+            Some(ServerError::ReadOnly.into())
+        );
+        assert_eq!(
+            execute(&state, &mut client, &get).await,
+            Some(RedisType::NullString)
+        );
+
+        // While the link to the master can still write
+        let mut master = Client::new(addr, addr);
+        master.authenticated = true;
+        master.master = true;
+        execute(&state, &mut master, &set).await;
+        assert_eq!(
+            execute(&state, &mut client, &get).await,
+            Some(RedisType::from(crate::value::Value::from("value")))
+        );
+
+        // Unless replica-read-only is turned off
+        state.lock().await.config.replica_read_only = false;
+        let del = command(&["DEL", "key"]);
+        assert_eq!(
+            execute(&state, &mut client, &del).await,
+            Some(RedisType::from(1))
+        );
+    }
+}
@@ -0,0 +1,303 @@
+use crate::{split_args_bytes, RedisType, RedisTypeParseError};
+use std::fmt;
+
+// The longest an inline command's line, or a request's count or length line, can get without
+// ending, as in Redis
+const MAX_INLINE: usize = 64 * 1024;
+
+// How big a request from a client can be, checked as its headers arrive so one that's too big is
+// refused before its arguments have been buffered
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Limits {
+    // proto-max-bulk-len, for each argument
+    pub bulk: usize,
+    // client-query-buffer-limit, for the whole request
+    pub request: usize,
+}
+
+impl Limits {
+    // For input that's trusted, or only parsed to be refused
+    pub const NONE: Limits = Limits {
+        bulk: usize::MAX,
+        request: usize::MAX,
+    };
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ProtocolError {
+    // Anything else the parser refused
+    Parse(RedisTypeParseError),
+    InvalidMultibulkLength,
+    TooBigMultibulkCount,
+    // A request's argument that isn't a bulk string, with the type byte it has instead
+    ExpectedBulk(u8),
+    InvalidBulkLength,
+    TooBigBulkCount,
+    TooBig,
+    UnbalancedQuotes,
+    TooBigInline,
+}
+
+// As Redis words them after "Protocol error: "
+impl fmt::Display for ProtocolError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ProtocolError::Parse(e) => match e {
+                RedisTypeParseError::InvalidArrayLength => write!(f, "invalid multibulk length"),
+                RedisTypeParseError::InvalidBulkLength => write!(f, "invalid bulk length"),
+                RedisTypeParseError::InvalidSuffix => write!(f, "expected CRLF after bulk string"),
+                RedisTypeParseError::InvalidInteger => write!(f, "invalid integer"),
+                RedisTypeParseError::InvalidBoolean => write!(f, "invalid boolean"),
+                RedisTypeParseError::NestingTooDeep => write!(f, "too deeply nested"),
+                _ => write!(f, "invalid request"),
+            },
+            ProtocolError::InvalidMultibulkLength => write!(f, "invalid multibulk length"),
+            ProtocolError::TooBigMultibulkCount => write!(f, "too big mbulk count string"),
+            ProtocolError::ExpectedBulk(got) => {
+                write!(f, "expected '$', got '{}'", [*got].escape_ascii())
+            }
+            ProtocolError::InvalidBulkLength => write!(f, "invalid bulk length"),
+            ProtocolError::TooBigBulkCount => write!(f, "too big bulk count string"),
+            ProtocolError::TooBig => write!(f, "too big request"),
+            ProtocolError::UnbalancedQuotes => write!(f, "unbalanced quotes in request"),
+            ProtocolError::TooBigInline => write!(f, "too big inline request"),
+        }
+    }
+}
+
+// Remove as many complete commands from the start of input as possible, and why the rest were
+// refused if they were
+// Anything left over is the start of a command that hasn't been fully received yet
+pub fn parse(input: &mut Vec<u8>, limits: &Limits) -> (Vec<RedisType>, Option<ProtocolError>) {
+    let mut commands = Vec::new();
+    let mut consumed = 0;
+    let error = loop {
+        if consumed == input.len() {
+            break None;
+        }
+        // As in Redis, anything that isn't an array is an inline command, even if it starts with
+        // another RESP type's byte
+        if input[consumed] != b'*' {
+            match inline(&input[consumed..]) {
+                Ok(Some((command, len))) => {
+                    commands.extend(command);
+                    consumed += len;
+                    continue;
+                }
+                Ok(None) => break None,
+                Err(e) => break Some(e),
+            }
+        }
+        if let Err(e) = check(&input[consumed..], limits) {
+            break Some(e);
+        }
+
+        match RedisType::parse_prefix(&input[consumed..]) {
+            // As in Redis, a count of 0 or less is skipped, without a command
+            Ok((RedisType::NullArray, len)) => consumed += len,
+            Ok((RedisType::Array { value }, len)) if value.is_empty() => consumed += len,
+            Ok((command, len)) => {
+                commands.push(command);
+                consumed += len;
+            }
+            Err(RedisTypeParseError::Incomplete) => break None,
+            Err(e) => break Some(ProtocolError::Parse(e)),
+        }
+    };
+
+    match error {
+        Some(_) => input.clear(),
+        None => {
+            input.drain(..consumed);
+        }
+    }
+    (commands, error)
+}
+
+// A command typed as a line of text, such as GET foo (from telnet, say), split into arguments the
+// way redis-cli does, and how long the line was, or None if the whole line hasn't arrived yet
+// Empty lines are skipped, without a command.
+fn inline(data: &[u8]) -> Result<Option<(Option<RedisType>, usize)>, ProtocolError> {
+    let Some(end) = data.iter().position(|byte| *byte == b'\n') else {
+        if data.len() > MAX_INLINE {
+            return Err(ProtocolError::TooBigInline);
+        }
+        return Ok(None);
+    };
+
+    let line = data[..end].strip_suffix(b"\r").unwrap_or(&data[..end]);
+    let args = split_args_bytes(line).map_err(|_| ProtocolError::UnbalancedQuotes)?;
+    let command = match args.is_empty() {
+        true => None,
+        false => Some(RedisType::Array {
+            value: args.into_iter().map(RedisType::from).collect(),
+        }),
+    };
+    Ok(Some((command, end + 1)))
+}
+
+// Check the framing of a request (an array of bulk strings, starting at its *) and the lengths it
+// declares against limits, as far as it has been received
+fn check(data: &[u8], limits: &Limits) -> Result<(), ProtocolError> {
+    let Some((count, mut rest)) = line(data, ProtocolError::TooBigMultibulkCount)? else {
+        return Ok(());
+    };
+    let count = count
+        .filter(|count| *count <= i32::MAX as i64)
+        .ok_or(ProtocolError::InvalidMultibulkLength)?;
+
+    let mut size = data.len() - rest.len();
+    for _ in 0..count {
+        match rest.first() {
+            None => return Ok(()),
+            Some(b'$') => {}
+            Some(got) => return Err(ProtocolError::ExpectedBulk(*got)),
+        }
+        let Some((len, after)) = line(rest, ProtocolError::TooBigBulkCount)? else {
+            return Ok(());
+        };
+        let len = len
+            .and_then(|len| usize::try_from(len).ok())
+            .filter(|len| *len <= limits.bulk)
+            .ok_or(ProtocolError::InvalidBulkLength)?;
+
+        size += rest.len() - after.len() + len + 2;
+        if size > limits.request {
+            return Err(ProtocolError::TooBig);
+        }
+        if after.len() < len + 2 {
+            return Ok(());
+        }
+        rest = &after[len + 2..];
+    }
+    Ok(())
+}
+
+// The number on the line that starts data after its type byte (None if it isn't a number) and
+// what follows the line, or None if the whole line hasn't arrived yet
+// A line that goes on past MAX_INLINE is refused with too_big.
+type Line<'a> = (Option<i64>, &'a [u8]);
+
+fn line(data: &[u8], too_big: ProtocolError) -> Result<Option<Line<'_>>, ProtocolError> {
+    let searched = &data[..data.len().min(MAX_INLINE + 2)];
+    let Some(end) = searched.windows(2).position(|window| window == b"\r\n") else {
+        return match data.len() >= MAX_INLINE + 2 {
+            true => Err(too_big),
+            false => Ok(None),
+        };
+    };
+    let number = std::str::from_utf8(&data[1..end])
+        .ok()
+        .and_then(|number| number.parse().ok());
+    Ok(Some((number, &data[end + 2..])))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse, Limits, ProtocolError};
+
+    const LIMITS: Limits = Limits {
+        bulk: 10,
+        request: 64,
+    };
+
+    #[test]
+    fn test_limits() {
+        let mut input = b"*2\r\n$3\r\nGET\r\n$3\r\nfoo\r\n*2\r\n$3\r\nGET\r\n$2".to_vec();
+        let (commands, error) = parse(&mut input, &LIMITS);
+        assert_eq!((commands.len(), error), (1, None));
+        assert_eq!(input, b"*2\r\n$3\r\nGET\r\n$2");
+
+        // Refused as soon as the length arrives, before the argument itself
+        let mut input = b"*2\r\n$3\r\nGET\r\n$3\r\nfoo\r\n*2\r\n$3\r\nSET\r\n$11\r\n".to_vec();
+        let (commands, error) = parse(&mut input, &LIMITS);
+        assert_eq!(commands.len(), 1);
+        assert_eq!(error, Some(ProtocolError::InvalidBulkLength));
+        assert!(input.is_empty());
+
+        let mut input = b"*10\r\n".to_vec();
+        for _ in 0..10 {
+            input.extend(b"$10\r\n");
+            input.extend([b'x'; 10]);
+            input.extend(b"\r\n");
+        }
+        assert_eq!(parse(&mut input, &LIMITS).1, Some(ProtocolError::TooBig));
+
+        let mut input = b"*1\r\n$-5\r\n".to_vec();
+        assert_eq!(
+            parse(&mut input, &LIMITS).1,
+            Some(ProtocolError::InvalidBulkLength)
+        );
+
+        // Count and length lines are refused once they're too long, before they end
+        let mut input = b"*".to_vec();
+        input.extend([b'1'; super::MAX_INLINE + 1]);
+        assert_eq!(
+            parse(&mut input, &LIMITS).1,
+            Some(ProtocolError::TooBigMultibulkCount)
+        );
+        let mut input = b"*1\r\n$".to_vec();
+        input.extend([b'1'; super::MAX_INLINE + 1]);
+        assert_eq!(
+            parse(&mut input, &LIMITS).1,
+            Some(ProtocolError::TooBigBulkCount)
+        );
+        let mut input = b"*1\r\n$".to_vec();
+        input.extend([b'1'; super::MAX_INLINE - 1]);
+        assert_eq!(parse(&mut input, &LIMITS), (vec![], None));
+    }
+
+    #[test]
+    fn test_inline() {
+        let mut input = b"SET key \"hello world\"\r\n\r\nGET key\n*1\r\n$4\r\nPING\r\nGET".to_vec();
+        let (commands, error) = parse(&mut input, &LIMITS);
+        assert_eq!(error, None);
+        // The same as if they had been sent as RESP
+        let mut resp = b"*3\r\n$3\r\nSET\r\n$3\r\nkey\r\n$11\r\nhello world\r\n\
+            *2\r\n$3\r\nGET\r\n$3\r\nkey\r\n*1\r\n$4\r\nPING\r\n"
+            .to_vec();
+        assert_eq!(commands, parse(&mut resp, &Limits::NONE).0);
+        assert_eq!(input, b"GET");
+
+        // Lines starting with another RESP type's byte are inline commands too
+        let mut input = b"$4\r\n:1 +x\n-\r\n_\r\n,#!=(%~>|\r\n".to_vec();
+        let (commands, error) = parse(&mut input, &LIMITS);
+        assert_eq!(error, None);
+        let mut resp = b"*1\r\n$2\r\n$4\r\n*2\r\n$2\r\n:1\r\n$2\r\n+x\r\n*1\r\n$1\r\n-\r\n\
+            *1\r\n$1\r\n_\r\n*1\r\n$9\r\n,#!=(%~>|\r\n"
+            .to_vec();
+        assert_eq!(commands, parse(&mut resp, &Limits::NONE).0);
+        assert!(input.is_empty());
+
+        let mut input = b"GET \"key\r\n".to_vec();
+        assert_eq!(
+            parse(&mut input, &LIMITS).1,
+            Some(ProtocolError::UnbalancedQuotes)
+        );
+        let mut input = vec![b'x'; super::MAX_INLINE + 1];
+        assert_eq!(
+            parse(&mut input, &LIMITS).1,
+            Some(ProtocolError::TooBigInline)
+        );
+    }
+
+    #[test]
+    fn test_protocol_errors() {
+        for (input, error) in [
+            (&b"*x\r\n"[..], "invalid multibulk length"),
+            (b"*2\r\n$3\r\nGET\r\n:1\r\n", "expected '$', got ':'"),
+            (b"*1\r\n$three\r\n", "invalid bulk length"),
+            (b"*1\r\n$3\r\nGETX\r\n", "expected CRLF after bulk string"),
+        ] {
+            let (commands, found) = parse(&mut input.to_vec(), &LIMITS);
+            assert!(commands.is_empty());
+            assert_eq!(found.map(|found| found.to_string()).as_deref(), Some(error));
+        }
+
+        // Not an error, nor a command
+        let mut input = b"*-1\r\n*0\r\n*-5\r\n*1\r\n$4\r\nPING\r\n".to_vec();
+        let (commands, error) = parse(&mut input, &LIMITS);
+        assert_eq!((commands.len(), error), (1, None));
+        assert!(input.is_empty());
+    }
+}
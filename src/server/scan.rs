@@ -0,0 +1,191 @@
+use crate::value::Value;
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+// Scans that are never finished would keep their snapshots forever, so only this many of the most
+// recently used are kept, and none that has gone this long without being continued
+const MAX_SNAPSHOTS: usize = 64;
+const SNAPSHOT_IDLE: Duration = Duration::from_secs(60);
+
+// The keyspace as it was when each SCAN started, so that a scan returns every key that is there
+// for the whole of it however much the keyspace grows, shrinks or is reorganised in the meantime
+// A snapshot is the list of keys at the time, so each call picks up at its position directly
+// rather than walking the keyspace up to it. Cursors hold the snapshot's generation in their top
+// 32 bits and the position in it in the rest. A cursor whose snapshot has been dropped carries on
+// from the same position in a new snapshot of the live keyspace, which can skip or repeat keys.
+#[derive(Debug, Default)]
+pub struct Scans {
+    last_generation: u32,
+    snapshots: HashMap<u32, Snapshot>,
+}
+
+#[derive(Debug)]
+struct Snapshot {
+    keys: Vec<Vec<u8>>,
+    last_used: Instant,
+}
+
+impl Scans {
+    // Up to count keys from cursor on (0 to start a new scan) that are still in keystore and that
+    // keep accepts, and the cursor to carry on from, which is 0 once every key has been looked at
+    pub fn scan(
+        &mut self,
+        keystore: &im::HashMap<Vec<u8>, Value>,
+        cursor: u64,
+        count: usize,
+        keep: impl Fn(&[u8]) -> bool,
+    ) -> (u64, Vec<Vec<u8>>) {
+        let now = Instant::now();
+        self.snapshots
+            .retain(|_, snapshot| now.duration_since(snapshot.last_used) < SNAPSHOT_IDLE);
+
+        let (generation, position) = match cursor {
+            0 => (self.start(keystore, now), 0),
+            cursor => ((cursor >> 32) as u32, (cursor & u32::MAX as u64) as usize),
+        };
+        let generation = match self.snapshots.contains_key(&generation) {
+            true => generation,
+            false => self.start(keystore, now),
+        };
+        let snapshot = self.snapshots.get_mut(&generation).unwrap();
+        snapshot.last_used = now;
+
+        let end = snapshot.keys.len().min(position.saturating_add(count));
+        let keys = snapshot.keys.get(position..end).unwrap_or_default();
+        // Leaving out keys deleted since the scan started
+        let keys = keys
+            .iter()
+            .filter(|key| keystore.contains_key(*key) && keep(key))
+            .cloned()
+            .collect();
+
+        if end >= snapshot.keys.len() {
+            self.snapshots.remove(&generation);
+            return (0, keys);
+        }
+        (((generation as u64) << 32) | end as u64, keys)
+    }
+
+    // Snapshot keystore for a new scan, returning its generation
+    fn start(&mut self, keystore: &im::HashMap<Vec<u8>, Value>, now: Instant) -> u32 {
+        if self.snapshots.len() >= MAX_SNAPSHOTS {
+            let oldest = self
+                .snapshots
+                .iter()
+                .min_by_key(|(_, snapshot)| snapshot.last_used)
+                .map(|(generation, _)| *generation);
+            if let Some(oldest) = oldest {
+                self.snapshots.remove(&oldest);
+            }
+        }
+
+        // Never 0, so the first cursor can't be mistaken for the end of the scan
+        self.last_generation = self.last_generation.checked_add(1).unwrap_or(1);
+        self.snapshots.insert(
+            self.last_generation,
+            Snapshot {
+                keys: keystore.keys().cloned().collect(),
+                last_used: now,
+            },
+        );
+        self.last_generation
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Scans;
+    use crate::value::Value;
+    use std::collections::HashSet;
+    use std::time::{Duration, Instant};
+
+    fn key(n: usize) -> Vec<u8> {
+        format!("key:{n}").into_bytes()
+    }
+
+    #[test]
+    fn test_scan_while_changing() {
+        let mut keystore = im::HashMap::new();
+        for n in 0..1000 {
+            keystore.insert(key(n), Value::from(b"value".to_vec()));
+        }
+
+        // Grow the keyspace tenfold and delete every other original key part way through
+        let mut scans = Scans::default();
+        let mut seen = HashSet::new();
+        let mut cursor = 0;
+        let mut calls = 0;
+        loop {
+            let (next, keys) = scans.scan(&keystore, cursor, 50, |_| true);
+            seen.extend(keys);
+            calls += 1;
+            if calls == 5 {
+                for n in 1000..10000 {
+                    keystore.insert(key(n), Value::from(b"value".to_vec()));
+                }
+                for n in (0..1000).step_by(2) {
+                    keystore.remove(&key(n));
+                }
+            }
+            if next == 0 {
+                break;
+            }
+            cursor = next;
+        }
+
+        assert_eq!(calls, 20);
+        for n in (1..1000).step_by(2) {
+            assert!(seen.contains(&key(n)), "key:{n} wasn't returned");
+        }
+        // Keys added after the scan started aren't returned
+        assert!(seen.iter().all(|seen| (0..1000).any(|n| *seen == key(n))));
+        assert!(scans.snapshots.is_empty());
+    }
+
+    #[test]
+    fn test_scan_dropped_snapshots() {
+        let mut keystore = im::HashMap::new();
+        for n in 0..10 {
+            keystore.insert(key(n), Value::from(b"value".to_vec()));
+        }
+        let mut scans = Scans::default();
+
+        let (first, _) = scans.scan(&keystore, 0, 1, |_| true);
+        for _ in 0..super::MAX_SNAPSHOTS {
+            scans.scan(&keystore, 0, 1, |_| true);
+        }
+        assert_eq!(scans.snapshots.len(), super::MAX_SNAPSHOTS);
+
+        // Carries on through the live keyspace instead
+        let (next, keys) = scans.scan(&keystore, first, 100, |_| true);
+        assert_eq!((next, keys.len()), (0, 9));
+    }
+
+    #[test]
+    fn test_scan_cost() {
+        let mut keystore = im::HashMap::new();
+        for n in 0..20000 {
+            keystore.insert(key(n), Value::from(b"value".to_vec()));
+        }
+        let mut scans = Scans::default();
+
+        // Each call picks up where the last one left off, rather than walking up to its cursor,
+        // so the calls at the end of the scan take no longer than those at the start
+        let (mut cursor, _) = scans.scan(&keystore, 0, 10, |_| true);
+        let mut timed = |calls: usize, cursor: &mut u64| {
+            let start = Instant::now();
+            for _ in 0..calls {
+                *cursor = scans.scan(&keystore, *cursor, 10, |_| true).0;
+            }
+            start.elapsed()
+        };
+        let early = timed(100, &mut cursor);
+        timed(1799, &mut cursor);
+        let late = timed(100, &mut cursor);
+        assert_eq!(cursor, 0);
+        assert!(
+            late <= early * 4 + Duration::from_millis(2),
+            "{late:?} at the end against {early:?} at the start"
+        );
+    }
+}
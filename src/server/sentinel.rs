@@ -0,0 +1,767 @@
+use crate::server::lifecycle::Shutdown;
+use crate::server::replication::{new_replid, MasterConnection};
+use crate::server::State;
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::net::TcpStream;
+use tokio::sync::Mutex;
+
+// Sentinels listen here unless a port is configured
+pub const DEFAULT_PORT: u16 = 26379;
+
+// How often masters are pinged, and asked for INFO to find their replicas
+const PING_PERIOD: Duration = Duration::from_secs(1);
+const INFO_PERIOD: Duration = Duration::from_secs(10);
+
+const DEFAULT_DOWN_AFTER: Duration = Duration::from_secs(30);
+const DEFAULT_FAILOVER_TIMEOUT: Duration = Duration::from_secs(180);
+
+// The only commands a sentinel answers, it doesn't hold any data
+pub const COMMANDS: &[&str] = &[
+    "AUTH", "CLIENT", "COMMAND", "HELLO", "HELP", "INFO", "PING", "QUIT", "ROLE", "SENTINEL",
+    "SHUTDOWN",
+];
+
+// The masters this sentinel watches, from the sentinel directives and SENTINEL MONITOR
+#[derive(Debug)]
+pub struct Sentinel {
+    pub myid: String,
+    pub masters: BTreeMap<String, Master>,
+}
+
+#[derive(Debug)]
+pub struct Master {
+    pub name: String,
+    pub host: String,
+    pub port: u16,
+    // How many sentinels have to agree the master is down, there are no others yet so only 1 works
+    pub quorum: u32,
+    pub down_after: Duration,
+    pub failover_timeout: Duration,
+    pub auth_pass: Option<String>,
+    // Incremented by each failover
+    pub config_epoch: u64,
+    pub last_ok_ping: Instant,
+    pub last_info: Option<Instant>,
+    pub role_reported: String,
+    pub replicas: Vec<ReplicaInfo>,
+    // Set once the master hasn't answered for down_after (subjectively down)
+    pub sdown_since: Option<Instant>,
+    // Set by SENTINEL FAILOVER, picked up by the monitor
+    pub failover_requested: bool,
+    pub failover_since: Option<Instant>,
+    pub last_failover: Option<Instant>,
+    // Replicas (and former masters) still to be pointed at this master after a failover
+    pub reconfigure: Vec<(String, u16)>,
+    // Whether a monitor task has been started, and how to stop it
+    started: bool,
+    cancel: Arc<Shutdown>,
+}
+
+// A replica as reported by its master in INFO replication
+#[derive(Clone, Debug, PartialEq)]
+pub struct ReplicaInfo {
+    pub ip: String,
+    pub port: u16,
+    pub online: bool,
+    pub offset: u64,
+    pub lag: u64,
+}
+
+impl Sentinel {
+    // Build from the sentinel directives in the config file
+    pub fn from_directives(directives: &[Vec<String>]) -> Result<Sentinel, String> {
+        let mut sentinel = Sentinel {
+            myid: new_replid(),
+            masters: BTreeMap::new(),
+        };
+
+        for args in directives {
+            let error = |message: &str| format!("{message}: sentinel {}", args.join(" "));
+            let directive = args.first().map(|arg| arg.to_ascii_lowercase());
+
+            match (directive.as_deref(), args.len()) {
+                (Some("myid"), 2) if args[1].len() == 40 => sentinel.myid = args[1].clone(),
+                (Some("monitor"), 5) => {
+                    let port = args[3].parse().map_err(|_| error("Invalid port"))?;
+                    let quorum = args[4].parse().map_err(|_| error("Invalid quorum"))?;
+                    sentinel
+                        .monitor(&args[1], &args[2], port, quorum)
+                        .map_err(|e| error(&e))?;
+                }
+                (Some("config-epoch"), 3) => {
+                    let master = sentinel
+                        .masters
+                        .get_mut(&args[1])
+                        .ok_or_else(|| error("No such master with specified name"))?;
+                    master.config_epoch = args[2].parse().map_err(|_| error("Invalid epoch"))?;
+                }
+                (Some(option), 3) => {
+                    let master = sentinel
+                        .masters
+                        .get_mut(&args[1])
+                        .ok_or_else(|| error("No such master with specified name"))?;
+                    master.set(option, &args[2]).map_err(|e| error(&e))?;
+                }
+                _ => return Err(error("Unknown sentinel directive")),
+            }
+        }
+
+        Ok(sentinel)
+    }
+
+    // The sentinel directives to save, so that failovers survive a restart
+    pub fn directives(&self) -> Vec<Vec<String>> {
+        let mut directives = vec![vec![String::from("myid"), self.myid.clone()]];
+        for master in self.masters.values() {
+            let name = master.name.clone();
+            directives.push(vec![
+                String::from("monitor"),
+                name.clone(),
+                master.host.clone(),
+                master.port.to_string(),
+                master.quorum.to_string(),
+            ]);
+            if master.down_after != DEFAULT_DOWN_AFTER {
+                directives.push(vec![
+                    String::from("down-after-milliseconds"),
+                    name.clone(),
+                    master.down_after.as_millis().to_string(),
+                ]);
+            }
+            if master.failover_timeout != DEFAULT_FAILOVER_TIMEOUT {
+                directives.push(vec![
+                    String::from("failover-timeout"),
+                    name.clone(),
+                    master.failover_timeout.as_millis().to_string(),
+                ]);
+            }
+            if let Some(auth_pass) = &master.auth_pass {
+                directives.push(vec![
+                    String::from("auth-pass"),
+                    name.clone(),
+                    auth_pass.clone(),
+                ]);
+            }
+            if master.config_epoch > 0 {
+                directives.push(vec![
+                    String::from("config-epoch"),
+                    name,
+                    master.config_epoch.to_string(),
+                ]);
+            }
+        }
+        directives
+    }
+
+    // SENTINEL MONITOR, the cron starts watching it
+    pub fn monitor(
+        &mut self,
+        name: &str,
+        host: &str,
+        port: u16,
+        quorum: u32,
+    ) -> Result<(), String> {
+        if self.masters.contains_key(name) {
+            return Err(String::from("Duplicated master name"));
+        }
+        if quorum == 0 {
+            return Err(String::from("Quorum must be 1 or greater."));
+        }
+
+        self.masters.insert(
+            name.to_owned(),
+            Master {
+                name: name.to_owned(),
+                host: host.to_owned(),
+                port,
+                quorum,
+                down_after: DEFAULT_DOWN_AFTER,
+                failover_timeout: DEFAULT_FAILOVER_TIMEOUT,
+                auth_pass: None,
+                config_epoch: 0,
+                last_ok_ping: Instant::now(),
+                last_info: None,
+                role_reported: String::from("master"),
+                replicas: Vec::new(),
+                sdown_since: None,
+                failover_requested: false,
+                failover_since: None,
+                last_failover: None,
+                reconfigure: Vec::new(),
+                started: false,
+                cancel: Arc::default(),
+            },
+        );
+        Ok(())
+    }
+
+    // SENTINEL REMOVE, stopping its monitor
+    pub fn remove(&mut self, name: &str) -> bool {
+        match self.masters.remove(name) {
+            Some(master) => {
+                master.cancel.trigger();
+                true
+            }
+            None => false,
+        }
+    }
+
+    // Masters without a monitor yet, which the cron starts
+    pub fn unstarted(&mut self) -> Vec<(String, Arc<Shutdown>)> {
+        self.masters
+            .values_mut()
+            .filter_map(|master| {
+                let started = std::mem::replace(&mut master.started, true);
+                (!started).then(|| (master.name.clone(), master.cancel.clone()))
+            })
+            .collect()
+    }
+
+    // The master name is watched by, as long as it is still the one cancel belongs to
+    fn master(&mut self, name: &str, cancel: &Arc<Shutdown>) -> Option<&mut Master> {
+        self.masters
+            .get_mut(name)
+            .filter(|master| Arc::ptr_eq(&master.cancel, cancel))
+    }
+}
+
+impl Master {
+    // SENTINEL SET and the per master sentinel directives
+    pub fn set(&mut self, option: &str, value: &str) -> Result<(), String> {
+        let milliseconds = || match value.parse::<u64>() {
+            Ok(milliseconds) if milliseconds > 0 => Ok(Duration::from_millis(milliseconds)),
+            _ => Err(format!(
+                "Invalid argument '{value}' for SENTINEL SET '{option}'"
+            )),
+        };
+
+        match option.to_ascii_lowercase().as_str() {
+            "down-after-milliseconds" => self.down_after = milliseconds()?,
+            "failover-timeout" => self.failover_timeout = milliseconds()?,
+            "quorum" => match value.parse::<u32>() {
+                Ok(quorum) if quorum > 0 => self.quorum = quorum,
+                _ => {
+                    return Err(format!(
+                        "Invalid argument '{value}' for SENTINEL SET 'quorum'"
+                    ))
+                }
+            },
+            "auth-pass" => self.auth_pass = (!value.is_empty()).then(|| value.to_owned()),
+            _ => return Err(format!("Invalid argument '{option}' to SENTINEL SET")),
+        }
+        Ok(())
+    }
+
+    // With no other sentinels to ask, the master is objectively down when we alone are a quorum
+    pub fn odown(&self) -> bool {
+        self.sdown_since.is_some() && self.quorum <= 1
+    }
+
+    pub fn flags(&self) -> String {
+        let mut flags = String::from("master");
+        if self.sdown_since.is_some() {
+            flags.push_str(",s_down");
+        }
+        if self.odown() {
+            flags.push_str(",o_down");
+        }
+        if self.failover_since.is_some() {
+            flags.push_str(",failover_in_progress");
+        }
+        flags
+    }
+
+    // As listed by SENTINEL MASTERS, with Redis' field names
+    pub fn fields(&self) -> Vec<(String, String)> {
+        let millis = |since: Instant| since.elapsed().as_millis().to_string();
+        let mut fields = vec![
+            ("name", self.name.clone()),
+            ("ip", self.host.clone()),
+            ("port", self.port.to_string()),
+            ("flags", self.flags()),
+            ("last-ok-ping-reply", millis(self.last_ok_ping)),
+            (
+                "down-after-milliseconds",
+                self.down_after.as_millis().to_string(),
+            ),
+            (
+                "info-refresh",
+                self.last_info.map_or(String::from("0"), millis),
+            ),
+            ("role-reported", self.role_reported.clone()),
+            ("config-epoch", self.config_epoch.to_string()),
+            ("num-slaves", self.replicas.len().to_string()),
+            ("num-other-sentinels", String::from("0")),
+            ("quorum", self.quorum.to_string()),
+            (
+                "failover-timeout",
+                self.failover_timeout.as_millis().to_string(),
+            ),
+            ("parallel-syncs", String::from("1")),
+        ];
+        if let Some(since) = self.sdown_since {
+            fields.push(("s-down-time", millis(since)));
+        }
+        fields
+            .into_iter()
+            .map(|(field, value)| (String::from(field), value))
+            .collect()
+    }
+
+    // The online replica furthest along, to be promoted by a failover
+    pub fn choose_replica(&self) -> Option<&ReplicaInfo> {
+        self.replicas
+            .iter()
+            .filter(|replica| replica.online)
+            .max_by_key(|replica| (replica.offset, std::cmp::Reverse(replica.lag)))
+    }
+
+    // Record the result of a PING (and INFO), noticing when the master has been down too long
+    fn checked(&mut self, result: Result<Option<String>, String>) {
+        match result {
+            Ok(info) => {
+                self.last_ok_ping = Instant::now();
+                if let Some(info) = info {
+                    let (role, replicas) = parse_info(&info);
+                    self.role_reported = role;
+                    self.replicas = replicas;
+                    self.last_info = Some(Instant::now());
+                }
+                if self.sdown_since.take().is_some() {
+                    tracing::warn!("-sdown master {} {} {}", self.name, self.host, self.port);
+                }
+            }
+            Err(e) => {
+                tracing::debug!("Master {} {}:{}: {e}", self.name, self.host, self.port);
+            }
+        }
+
+        if self.sdown_since.is_none() && self.last_ok_ping.elapsed() > self.down_after {
+            self.sdown_since = Some(Instant::now());
+            tracing::warn!("+sdown master {} {} {}", self.name, self.host, self.port);
+            if self.odown() {
+                tracing::warn!(
+                    "+odown master {} {} {} #quorum 1/{}",
+                    self.name,
+                    self.host,
+                    self.port,
+                    self.quorum
+                );
+            }
+        }
+    }
+
+    // Whether to fail over now, either because it was asked for or because the master is down
+    // and the last attempt (if any) was long enough ago
+    fn failover_due(&self) -> bool {
+        if self.failover_since.is_some() {
+            return false;
+        }
+        self.failover_requested
+            || (self.odown()
+                && self
+                    .last_failover
+                    .is_none_or(|at| at.elapsed() > self.failover_timeout * 2))
+    }
+}
+
+// The role and replicas from INFO replication
+fn parse_info(info: &str) -> (String, Vec<ReplicaInfo>) {
+    let mut role = String::new();
+    let mut replicas = Vec::new();
+
+    for line in info.lines() {
+        let (key, value) = match line.trim_end().split_once(':') {
+            Some(pair) => pair,
+            None => continue,
+        };
+
+        if key == "role" {
+            role = value.to_owned();
+        } else if key.starts_with("slave") && key[5..].chars().all(|c| c.is_ascii_digit()) {
+            let fields = value
+                .split(',')
+                .filter_map(|field| field.split_once('='))
+                .collect::<BTreeMap<_, _>>();
+            let replica = (|| {
+                Some(ReplicaInfo {
+                    ip: fields.get("ip")?.to_string(),
+                    port: fields.get("port")?.parse().ok()?,
+                    online: *fields.get("state")? == "online",
+                    offset: fields.get("offset")?.parse().ok()?,
+                    lag: fields.get("lag")?.parse().ok()?,
+                })
+            })();
+            replicas.extend(replica);
+        }
+    }
+
+    (role, replicas)
+}
+
+// Save the sentinel state to the config file, if there is one
+pub fn save(state: &mut State) {
+    if let Some(sentinel) = &state.sentinel {
+        state.config.sentinel = sentinel.directives();
+    }
+    if state.config.config_file.is_some() {
+        if let Err(e) = state.config.rewrite() {
+            tracing::warn!("Unable to save the sentinel config: {e}");
+        }
+    }
+}
+
+async fn connect(
+    host: &str,
+    port: u16,
+    auth_pass: Option<&str>,
+) -> Result<MasterConnection, String> {
+    let stream = TcpStream::connect((host, port))
+        .await
+        .map_err(|e| e.to_string())?;
+    let mut connection = MasterConnection::new(stream);
+    if let Some(auth_pass) = auth_pass {
+        connection.command(&["AUTH", auth_pass]).await?;
+    }
+    Ok(connection)
+}
+
+// Send a single command on a new connection
+async fn command(
+    host: &str,
+    port: u16,
+    auth_pass: Option<&str>,
+    argv: &[&str],
+) -> Result<String, String> {
+    connect(host, port, auth_pass).await?.command(argv).await
+}
+
+// PING the master, and ask for INFO replication if info is set
+async fn check(
+    link: &mut Option<MasterConnection>,
+    host: &str,
+    port: u16,
+    auth_pass: Option<&str>,
+    info: bool,
+) -> Result<Option<String>, String> {
+    if link.is_none() {
+        *link = Some(connect(host, port, auth_pass).await?);
+    }
+    let connection = link.as_mut().unwrap();
+
+    connection.command(&["PING"]).await?;
+    if info {
+        Ok(Some(connection.command(&["INFO", "replication"]).await?))
+    } else {
+        Ok(None)
+    }
+}
+
+// Watch a master until SENTINEL REMOVE, failing over to one of its replicas when it goes down
+pub async fn monitor(state: Arc<Mutex<State>>, name: String, cancel: Arc<Shutdown>) {
+    let mut cancelled = cancel.subscribe();
+    let mut link = None;
+    let mut linked_to = (String::new(), 0);
+
+    loop {
+        let (host, port, auth_pass, down_after, info_due) = {
+            let mut state = state.lock().await;
+            let master = match state
+                .sentinel
+                .as_mut()
+                .and_then(|s| s.master(&name, &cancel))
+            {
+                Some(master) => master,
+                None => return,
+            };
+            let info_due = master
+                .last_info
+                .is_none_or(|at| at.elapsed() >= INFO_PERIOD);
+            (
+                master.host.clone(),
+                master.port,
+                master.auth_pass.clone(),
+                master.down_after,
+                info_due,
+            )
+        };
+
+        // The address changes after a failover
+        if linked_to != (host.clone(), port) {
+            link = None;
+            linked_to = (host.clone(), port);
+        }
+
+        let checked = check(&mut link, &host, port, auth_pass.as_deref(), info_due);
+        let result = tokio::select! {
+            result = tokio::time::timeout(down_after, checked) => {
+                result.unwrap_or_else(|_| Err(String::from("Timed out")))
+            }
+            _ = cancelled.wait() => return,
+        };
+        if result.is_err() {
+            link = None;
+        }
+
+        let (failover, reconfigure) = {
+            let mut state = state.lock().await;
+            let master = match state
+                .sentinel
+                .as_mut()
+                .and_then(|s| s.master(&name, &cancel))
+            {
+                Some(master) => master,
+                None => return,
+            };
+            let up = result.is_ok();
+            master.checked(result);
+
+            let failover = master.failover_due();
+            if failover {
+                master.failover_requested = false;
+                master.failover_since = Some(Instant::now());
+                master.last_failover = Some(Instant::now());
+            }
+            let reconfigure = if up && info_due {
+                std::mem::take(&mut master.reconfigure)
+            } else {
+                Vec::new()
+            };
+            (failover, reconfigure)
+        };
+
+        if failover {
+            run_failover(&state, &name, &cancel).await;
+        }
+
+        // Point replicas left over from a failover (including the old master, once it is back)
+        // at the new master, retrying with each INFO until it works
+        for (replica_host, replica_port) in reconfigure {
+            let argv = ["REPLICAOF", host.as_str(), &port.to_string()];
+            let sent = command(&replica_host, replica_port, auth_pass.as_deref(), &argv);
+            let result = tokio::time::timeout(PING_PERIOD, sent).await;
+
+            let mut state = state.lock().await;
+            let master = match state
+                .sentinel
+                .as_mut()
+                .and_then(|s| s.master(&name, &cancel))
+            {
+                Some(master) => master,
+                None => return,
+            };
+            match result {
+                Ok(Ok(_)) => tracing::info!(
+                    "+convert-to-slave slave {replica_host}:{replica_port} {replica_host} {replica_port} @ {name} {host} {port}"
+                ),
+                _ => master.reconfigure.push((replica_host, replica_port)),
+            }
+        }
+
+        tokio::select! {
+            _ = tokio::time::sleep(PING_PERIOD) => {}
+            _ = cancelled.wait() => return,
+        }
+    }
+}
+
+// Promote the best replica of a master: with FAILOVER if the master is still up (so no writes
+// are lost), otherwise with REPLICAOF NO ONE. The other replicas follow it afterwards.
+async fn run_failover(state: &Arc<Mutex<State>>, name: &str, cancel: &Arc<Shutdown>) {
+    let (host, port, auth_pass, timeout, graceful, promoted) = {
+        let mut state = state.lock().await;
+        let master = match state.sentinel.as_mut().and_then(|s| s.master(name, cancel)) {
+            Some(master) => master,
+            None => return,
+        };
+        tracing::warn!(
+            "+try-failover master {name} {} {}",
+            master.host,
+            master.port
+        );
+
+        let promoted = match master.choose_replica() {
+            Some(replica) => (replica.ip.clone(), replica.port),
+            None => {
+                tracing::warn!(
+                    "-failover-abort-no-good-slave master {name} {} {}",
+                    master.host,
+                    master.port
+                );
+                master.failover_since = None;
+                return;
+            }
+        };
+        tracing::warn!(
+            "+selected-slave slave {0}:{1} {0} {1} @ {name} {2} {3}",
+            promoted.0,
+            promoted.1,
+            master.host,
+            master.port
+        );
+
+        (
+            master.host.clone(),
+            master.port,
+            master.auth_pass.clone(),
+            master.failover_timeout,
+            master.sdown_since.is_none(),
+            promoted,
+        )
+    };
+
+    let (promoted_host, promoted_port) = promoted.clone();
+    let promoted_port_arg = promoted_port.to_string();
+    let timeout_arg = timeout.as_millis().to_string();
+    let result = if graceful {
+        let argv = [
+            "FAILOVER",
+            "TO",
+            promoted_host.as_str(),
+            &promoted_port_arg,
+            "TIMEOUT",
+            &timeout_arg,
+        ];
+        let sent = command(&host, port, auth_pass.as_deref(), &argv);
+        tokio::time::timeout(timeout, sent).await
+    } else {
+        let argv = ["REPLICAOF", "NO", "ONE"];
+        let sent = command(&promoted_host, promoted_port, auth_pass.as_deref(), &argv);
+        tokio::time::timeout(timeout, sent).await
+    };
+
+    let mut state = state.lock().await;
+    let master = match state.sentinel.as_mut().and_then(|s| s.master(name, cancel)) {
+        Some(master) => master,
+        None => return,
+    };
+    master.failover_since = None;
+
+    if let Err(e) = result
+        .map_err(|_| String::from("Timed out"))
+        .and_then(|result| result)
+    {
+        tracing::warn!("-failover-abort master {name} {host} {port}: {e}");
+        return;
+    }
+
+    // A graceful FAILOVER turns the old master into a replica by itself
+    let mut reconfigure = master
+        .replicas
+        .iter()
+        .map(|replica| (replica.ip.clone(), replica.port))
+        .filter(|replica| *replica != promoted)
+        .collect::<Vec<_>>();
+    if !graceful {
+        reconfigure.push((host.clone(), port));
+    }
+
+    master.host = promoted_host;
+    master.port = promoted_port;
+    master.config_epoch += 1;
+    master.replicas.clear();
+    master.reconfigure = reconfigure;
+    master.sdown_since = None;
+    master.last_ok_ping = Instant::now();
+    master.last_info = None;
+    master.role_reported = String::from("master");
+    tracing::warn!(
+        "+switch-master {name} {host} {port} {} {}",
+        master.host,
+        master.port
+    );
+
+    save(&mut state);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn directive(line: &str) -> Vec<String> {
+        line.split(' ').map(String::from).collect()
+    }
+
+    #[test]
+    fn test_directives() {
+        let directives = [
+            directive("monitor mymaster 127.0.0.1 6379 1"),
+            directive("down-after-milliseconds mymaster 5000"),
+            directive("auth-pass mymaster secret"),
+            directive("config-epoch mymaster 2"),
+        ];
+        let sentinel = Sentinel::from_directives(&directives).unwrap();
+        let master = &sentinel.masters["mymaster"];
+        assert_eq!(master.down_after, Duration::from_millis(5000));
+        assert_eq!(master.auth_pass.as_deref(), Some("secret"));
+        assert_eq!(master.config_epoch, 2);
+
+        // Written back with our id first, so it stays the same after a restart
+        let mut expected = vec![vec![String::from("myid"), sentinel.myid.clone()]];
+        expected.extend(directives);
+        assert_eq!(sentinel.directives(), expected);
+        let reloaded = Sentinel::from_directives(&sentinel.directives()).unwrap();
+        assert_eq!(reloaded.myid, sentinel.myid);
+
+        assert!(Sentinel::from_directives(&[directive("quorum mymaster 1")]).is_err());
+        assert!(
+            Sentinel::from_directives(&[directive("monitor mymaster 127.0.0.1 6379 0")]).is_err()
+        );
+        assert!(Sentinel::from_directives(&[directive("parallel-syncs 1")]).is_err());
+    }
+
+    #[test]
+    fn test_parse_info() {
+        let info = "# Replication\r\nrole:master\r\nconnected_slaves:2\r\n\
+                    slave0:ip=10.0.0.2,port=6380,state=online,offset=100,lag=0\r\n\
+                    slave1:ip=10.0.0.3,port=6381,state=send_bulk,offset=0,lag=1\r\n\
+                    master_failover_state:no-failover\r\n";
+        let (role, replicas) = parse_info(info);
+        assert_eq!(role, "master");
+        assert_eq!(
+            replicas,
+            vec![
+                ReplicaInfo {
+                    ip: String::from("10.0.0.2"),
+                    port: 6380,
+                    online: true,
+                    offset: 100,
+                    lag: 0,
+                },
+                ReplicaInfo {
+                    ip: String::from("10.0.0.3"),
+                    port: 6381,
+                    online: false,
+                    offset: 0,
+                    lag: 1,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_failover_due() {
+        let mut sentinel = Sentinel::from_directives(&[]).unwrap();
+        sentinel.monitor("mymaster", "127.0.0.1", 6379, 1).unwrap();
+        let master = sentinel.masters.get_mut("mymaster").unwrap();
+        master.down_after = Duration::from_millis(1);
+
+        master.checked(Ok(Some(String::from(
+            "role:master\r\nslave0:ip=10.0.0.2,port=6380,state=online,offset=5,lag=0\r\n\
+             slave1:ip=10.0.0.3,port=6381,state=online,offset=9,lag=0\r\n",
+        ))));
+        assert!(!master.failover_due());
+        assert_eq!(master.choose_replica().unwrap().port, 6381);
+
+        std::thread::sleep(Duration::from_millis(5));
+        master.checked(Err(String::from("Connection refused")));
+        assert_eq!(master.flags(), "master,s_down,o_down");
+        assert!(master.failover_due());
+
+        // Without other sentinels a larger quorum can never be reached
+        master.quorum = 2;
+        assert!(!master.failover_due());
+    }
+}
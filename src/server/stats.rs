@@ -0,0 +1,149 @@
+use std::collections::BTreeMap;
+use std::time::{Duration, Instant};
+
+// Server wide counters, reported by INFO and cleared by CONFIG RESETSTAT
+#[derive(Debug)]
+pub struct Stats {
+    pub started: Instant,
+    pub total_connections_received: u64,
+    pub total_commands_processed: u64,
+    pub expired_keys: u64,
+    // Active expire cycles that stopped at their time limit with expired keys left to find
+    pub expired_time_cap_reached_count: u64,
+    pub commands: BTreeMap<String, CommandStats>,
+}
+
+impl Default for Stats {
+    fn default() -> Self {
+        Stats {
+            started: Instant::now(),
+            total_connections_received: 0,
+            total_commands_processed: 0,
+            expired_keys: 0,
+            expired_time_cap_reached_count: 0,
+            commands: BTreeMap::new(),
+        }
+    }
+}
+
+impl Stats {
+    // Record a command that ran, successfully or not
+    pub fn record_call(&mut self, command: &str, duration: Duration, failed: bool) {
+        self.total_commands_processed += 1;
+
+        let stats = self.command(command);
+        stats.calls += 1;
+        stats.usec += duration.as_micros() as u64;
+        stats.latency.record(duration);
+        if failed {
+            stats.failed_calls += 1;
+        }
+    }
+
+    // Record a command that was refused before running, for example for NOAUTH
+    pub fn record_rejected(&mut self, command: &str) {
+        self.command(command).rejected_calls += 1;
+    }
+
+    // Everything except the uptime
+    pub fn reset(&mut self) {
+        *self = Stats {
+            started: self.started,
+            ..Stats::default()
+        };
+    }
+
+    fn command(&mut self, command: &str) -> &mut CommandStats {
+        self.commands
+            .entry(command.to_ascii_lowercase())
+            .or_default()
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct CommandStats {
+    pub calls: u64,
+    pub usec: u64,
+    pub rejected_calls: u64,
+    pub failed_calls: u64,
+    pub latency: Histogram,
+}
+
+// Command durations in power of two buckets of microseconds, bucket i holds durations below 2^i
+#[derive(Debug)]
+pub struct Histogram {
+    buckets: [u64; 65],
+    count: u64,
+}
+
+impl Default for Histogram {
+    fn default() -> Self {
+        Histogram {
+            buckets: [0; 65],
+            count: 0,
+        }
+    }
+}
+
+impl Histogram {
+    pub fn record(&mut self, duration: Duration) {
+        let usec = duration.as_micros().min(u64::MAX as u128) as u64;
+        self.buckets[(u64::BITS - usec.leading_zeros()) as usize] += 1;
+        self.count += 1;
+    }
+
+    // Upper bound in microseconds of the bucket containing the given percentile (0-100)
+    pub fn percentile(&self, percentile: f64) -> u64 {
+        let target = ((percentile / 100.0) * self.count as f64).ceil().max(1.0) as u64;
+
+        let mut seen = 0;
+        for (i, count) in self.buckets.iter().enumerate() {
+            seen += count;
+            if seen >= target {
+                return 1u64.checked_shl(i as u32).unwrap_or(u64::MAX);
+            }
+        }
+        0
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.count == 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_record_call() {
+        let mut stats = Stats::default();
+        stats.record_call("GET", Duration::from_micros(10), false);
+        stats.record_call("get", Duration::from_micros(20), true);
+        stats.record_rejected("get");
+
+        let get = &stats.commands["get"];
+        assert_eq!(get.calls, 2);
+        assert_eq!(get.usec, 30);
+        assert_eq!(get.failed_calls, 1);
+        assert_eq!(get.rejected_calls, 1);
+        assert_eq!(stats.total_commands_processed, 2);
+
+        stats.reset();
+        assert!(stats.commands.is_empty());
+        assert_eq!(stats.total_commands_processed, 0);
+    }
+
+    #[test]
+    fn test_percentiles() {
+        let mut histogram = Histogram::default();
+        for _ in 0..99 {
+            histogram.record(Duration::from_micros(3));
+        }
+        histogram.record(Duration::from_micros(1000));
+
+        assert_eq!(histogram.percentile(50.0), 4);
+        assert_eq!(histogram.percentile(99.0), 4);
+        assert_eq!(histogram.percentile(99.9), 1024);
+    }
+}
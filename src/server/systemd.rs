@@ -0,0 +1,206 @@
+use std::env;
+use std::ffi::OsStr;
+use std::io;
+use std::net::TcpListener;
+use std::ops::Range;
+use std::time::Duration;
+
+// Sockets passed by systemd start at this descriptor, after stdin, stdout and stderr
+const LISTEN_FDS_START: i32 = 3;
+
+// More than any unit would reasonably list, and a guard against a bogus LISTEN_FDS
+const LISTEN_FDS_MAX: i32 = 1024;
+
+// The listening sockets systemd passed with socket activation, to serve on instead of binding,
+// or none if it didn't pass any (or they were meant for another process). This clears LISTEN_PID
+// and LISTEN_FDS, so it has to be called before any other threads are started.
+pub fn take_listeners() -> io::Result<Vec<TcpListener>> {
+    let fds = passed_fds(
+        env::var("LISTEN_PID").ok().as_deref(),
+        env::var("LISTEN_FDS").ok().as_deref(),
+        std::process::id(),
+    );
+    // So that anything started from here doesn't think they're for it as well
+    env::remove_var("LISTEN_PID");
+    env::remove_var("LISTEN_FDS");
+    env::remove_var("LISTEN_FDNAMES");
+    let fds = match fds.map_err(io::Error::other)? {
+        Some(fds) => fds,
+        None => return Ok(Vec::new()),
+    };
+
+    #[cfg(unix)]
+    {
+        use std::os::fd::{BorrowedFd, FromRawFd};
+
+        let mut listeners = Vec::new();
+        for fd in fds {
+            // Safety: only borrowed while it's checked, which fails if it isn't open
+            let borrowed = unsafe { BorrowedFd::borrow_raw(fd) };
+            check_listener(borrowed).map_err(|e| {
+                io::Error::other(format!("Socket {fd} passed by systemd can't be used: {e}"))
+            })?;
+            // Safety: systemd hands these descriptors to this process, nothing else uses them,
+            // and it's been checked to be a listening TCP socket
+            let listener = unsafe { TcpListener::from_raw_fd(fd) };
+            listener.set_nonblocking(true)?;
+            listeners.push(listener);
+        }
+        Ok(listeners)
+    }
+
+    #[cfg(not(unix))]
+    {
+        let _ = fds;
+        Err(io::Error::other(
+            "Socket activation is only supported on unix",
+        ))
+    }
+}
+
+// Whether fd is an open TCP socket that's listening, rather than anything else the process has
+#[cfg(unix)]
+fn check_listener(fd: std::os::fd::BorrowedFd) -> io::Result<()> {
+    use socket2::{SockRef, Type};
+
+    let socket = SockRef::from(&fd);
+    if socket.r#type()? != Type::STREAM || socket.local_addr()?.as_socket().is_none() {
+        return Err(io::Error::other("not a TCP socket"));
+    }
+    #[cfg(any(target_os = "android", target_os = "freebsd", target_os = "linux"))]
+    if !socket.is_listener()? {
+        return Err(io::Error::other("not listening"));
+    }
+    Ok(())
+}
+
+// The descriptors passed by LISTEN_FDS, if LISTEN_PID says they're for the process pid
+fn passed_fds(
+    listen_pid: Option<&str>,
+    listen_fds: Option<&str>,
+    pid: u32,
+) -> Result<Option<Range<i32>>, String> {
+    let (Some(listen_pid), Some(listen_fds)) = (listen_pid, listen_fds) else {
+        return Ok(None);
+    };
+    if listen_pid.parse::<u32>().ok() != Some(pid) {
+        return Ok(None);
+    }
+    match listen_fds.parse::<i32>() {
+        Ok(count @ 0..=LISTEN_FDS_MAX) => Ok(Some(LISTEN_FDS_START..LISTEN_FDS_START + count)),
+        _ => Err(format!("Invalid LISTEN_FDS from systemd: {listen_fds:?}")),
+    }
+}
+
+// Tell systemd about a change in state, such as READY=1, if it's waiting to hear
+pub fn notify(state: &str) {
+    let Some(path) = env::var_os("NOTIFY_SOCKET") else {
+        return;
+    };
+    if let Err(e) = send(&path, state) {
+        tracing::warn!("Unable to notify systemd of {state:?}: {e}");
+    }
+}
+
+#[cfg(unix)]
+fn send(path: &OsStr, state: &str) -> io::Result<()> {
+    use std::os::unix::ffi::OsStrExt;
+    use std::os::unix::net::UnixDatagram;
+
+    let socket = UnixDatagram::unbound()?;
+    // Names starting with @ are in the abstract namespace, rather than files
+    if let Some(name) = path.as_bytes().strip_prefix(b"@") {
+        #[cfg(target_os = "linux")]
+        {
+            use std::os::linux::net::SocketAddrExt;
+
+            let addr = std::os::unix::net::SocketAddr::from_abstract_name(name)?;
+            socket.send_to_addr(state.as_bytes(), &addr)?;
+            return Ok(());
+        }
+        #[cfg(not(target_os = "linux"))]
+        {
+            let _ = name;
+            return Err(io::Error::other(
+                "abstract sockets are only supported on Linux",
+            ));
+        }
+    }
+    socket.send_to(state.as_bytes(), path)?;
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn send(_path: &OsStr, _state: &str) -> io::Result<()> {
+    Err(io::Error::other("only supported on unix"))
+}
+
+// How often systemd's watchdog expects to hear WATCHDOG=1, if it's watching this process
+pub fn watchdog_interval() -> Option<Duration> {
+    if let Ok(pid) = env::var("WATCHDOG_PID") {
+        if pid.parse::<u32>().ok()? != std::process::id() {
+            return None;
+        }
+    }
+    match env::var("WATCHDOG_USEC").ok()?.parse::<u64>().ok()? {
+        0 => None,
+        usec => Some(Duration::from_micros(usec)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[cfg(unix)]
+    use super::check_listener;
+    use super::{passed_fds, send};
+
+    #[test]
+    fn test_passed_fds() {
+        assert_eq!(passed_fds(Some("100"), Some("2"), 100), Ok(Some(3..5)));
+        assert_eq!(passed_fds(Some("100"), Some("0"), 100), Ok(Some(3..3)));
+        assert_eq!(passed_fds(Some("100"), Some("2"), 101), Ok(None));
+        assert_eq!(passed_fds(None, Some("2"), 100), Ok(None));
+        assert_eq!(passed_fds(Some("100"), None, 100), Ok(None));
+        assert!(passed_fds(Some("100"), Some("many"), 100).is_err());
+        assert!(passed_fds(Some("100"), Some("-1"), 100).is_err());
+        assert!(passed_fds(Some("100"), Some("2147483647"), 100).is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_check_listener() {
+        use std::os::fd::AsFd;
+
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        check_listener(listener.as_fd()).unwrap();
+
+        let socket = std::net::UdpSocket::bind("127.0.0.1:0").unwrap();
+        assert!(check_listener(socket.as_fd()).is_err());
+
+        let file = std::fs::File::open("Cargo.toml").unwrap();
+        assert!(check_listener(file.as_fd()).is_err());
+
+        // Connected rather than listening
+        let stream = std::net::TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        #[cfg(any(target_os = "android", target_os = "freebsd", target_os = "linux"))]
+        assert!(check_listener(stream.as_fd()).is_err());
+        drop(stream);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_send() {
+        use std::os::unix::net::UnixDatagram;
+
+        let path = std::env::temp_dir().join(format!("redis-rs-notify-{}", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let socket = UnixDatagram::bind(&path).unwrap();
+
+        send(path.as_os_str(), "READY=1").unwrap();
+        let mut buffer = [0; 64];
+        let len = socket.recv(&mut buffer).unwrap();
+        assert_eq!(&buffer[..len], b"READY=1");
+
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,309 @@
+// Server-assisted client-side caching, turned on with CLIENT TRACKING
+//
+// A tracking client is sent an invalidate push naming the keys it may have cached whenever they're
+// written or expire. Normally that's the keys it has read: each is remembered until it changes,
+// and then forgotten until it's read again, so a client is told at most once per read. In
+// broadcasting mode (BCAST) nothing is remembered and the client is told about every key written
+// that starts with one of its prefixes instead (or every key, without any).
+//
+// Invalidations go to the client itself, or to another connection given with REDIRECT. Only RESP3
+// has push messages, so as in Redis a RESP2 connection is only sent them as messages on the
+// __redis__:invalidate channel, if it's subscribed to it (which it would be redirected to).
+
+use crate::server::State;
+use crate::{Protocol, RedisType};
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+const INVALIDATE_CHANNEL: &[u8] = b"__redis__:invalidate";
+
+// How a client asked to be told about changes
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Options {
+    // The client that's sent invalidations instead of this one
+    pub redirect: Option<u64>,
+    pub bcast: bool,
+    pub prefixes: Vec<Vec<u8>>,
+    // Not told about its own writes
+    pub noloop: bool,
+}
+
+#[derive(Debug, Default)]
+pub struct Tracking {
+    clients: HashMap<u64, Options>,
+    // The clients that have read each key since it last changed, for those not broadcasting
+    // Clients that have since turned tracking off are only dropped once the key changes.
+    keys: HashMap<Vec<u8>, HashSet<u64>>,
+}
+
+impl Tracking {
+    // Turn tracking on, or change how it's done if it already is
+    // The mode can't change while it's on and prefixes are added to those already given, as in
+    // Redis.
+    pub fn enable(&mut self, client: u64, options: Options) -> Result<(), String> {
+        if !options.bcast && !options.prefixes.is_empty() {
+            return Err(String::from(
+                "PREFIX option requires BCAST mode to be enabled",
+            ));
+        }
+
+        let mut prefixes = Vec::new();
+        if let Some(existing) = self.clients.get(&client) {
+            if existing.bcast != options.bcast {
+                return Err(String::from("You can't switch BCAST mode on/off before disabling tracking for this client, and then re-enabling it with a different mode."));
+            }
+            prefixes = existing.prefixes.clone();
+        }
+
+        // Otherwise a single write could be sent twice
+        for (i, prefix) in options.prefixes.iter().enumerate() {
+            let others = prefixes.iter().chain(&options.prefixes[i + 1..]);
+            for other in others {
+                if prefix.starts_with(other) || other.starts_with(prefix) {
+                    return Err(format!(
+                        "Prefix '{}' overlaps with an existing prefix '{}'. Prefixes for a single client must not overlap.",
+                        prefix.escape_ascii(),
+                        other.escape_ascii()
+                    ));
+                }
+            }
+        }
+
+        prefixes.extend(options.prefixes);
+        self.clients.insert(
+            client,
+            Options {
+                prefixes,
+                ..options
+            },
+        );
+        Ok(())
+    }
+
+    pub fn disable(&mut self, client: u64) {
+        self.clients.remove(&client);
+    }
+
+    pub fn options(&self, client: u64) -> Option<&Options> {
+        self.clients.get(&client)
+    }
+
+    // After client has read keys, unless it's broadcasting (and so told about every write anyway)
+    pub fn remember(&mut self, client: u64, keys: &[Vec<u8>]) {
+        if self
+            .clients
+            .get(&client)
+            .is_none_or(|options| options.bcast)
+        {
+            return;
+        }
+        for key in keys {
+            self.keys.entry(key.clone()).or_default().insert(client);
+        }
+    }
+
+    // Which clients have to be told that keys changed, and which of them each has to be told
+    // about, given who wrote them (None if they expired)
+    pub fn invalidate(
+        &mut self,
+        keys: &[Vec<u8>],
+        writer: Option<u64>,
+    ) -> BTreeMap<u64, Vec<Vec<u8>>> {
+        let mut invalidated = BTreeMap::<u64, Vec<Vec<u8>>>::new();
+        if self.clients.is_empty() {
+            return invalidated;
+        }
+
+        let skip = |id: u64, options: &Options| options.noloop && writer == Some(id);
+        for key in keys {
+            let Some(readers) = self.keys.remove(key) else {
+                continue;
+            };
+            for id in readers {
+                match self.clients.get(&id) {
+                    Some(options) if !options.bcast && !skip(id, options) => {
+                        invalidated.entry(id).or_default().push(key.clone())
+                    }
+                    _ => {}
+                }
+            }
+        }
+
+        for (id, options) in &self.clients {
+            if !options.bcast || skip(*id, options) {
+                continue;
+            }
+            let matching = keys.iter().filter(|key| {
+                options.prefixes.is_empty()
+                    || options
+                        .prefixes
+                        .iter()
+                        .any(|prefix| key.starts_with(prefix))
+            });
+            invalidated
+                .entry(*id)
+                .or_default()
+                .extend(matching.cloned());
+        }
+
+        invalidated.retain(|_, keys| !keys.is_empty());
+        invalidated
+    }
+
+    // Every key changed at once, such as when a replica loads its master's snapshot
+    // Returns every tracking client, which all have to drop everything they've cached.
+    pub fn invalidate_all(&mut self) -> Vec<u64> {
+        self.keys.clear();
+        self.clients.keys().copied().collect()
+    }
+
+    // For INFO
+    pub fn clients(&self) -> usize {
+        self.clients.len()
+    }
+
+    pub fn keys(&self) -> usize {
+        self.keys.len()
+    }
+
+    pub fn prefixes(&self) -> usize {
+        self.clients
+            .values()
+            .map(|options| options.prefixes.len())
+            .sum()
+    }
+}
+
+// Tell the clients tracking keys that they've changed, after a write by writer (or after they
+// expired, with None)
+pub fn invalidate(state: &mut State, keys: &[Vec<u8>], writer: Option<u64>) {
+    for (client, keys) in state.tracking.invalidate(keys, writer) {
+        let keys = keys.into_iter().map(RedisType::from).collect::<Vec<_>>();
+        send(state, client, RedisType::from(keys));
+    }
+}
+
+// Tell every tracking client to drop everything it has cached
+pub fn invalidate_all(state: &mut State) {
+    for client in state.tracking.invalidate_all() {
+        send(state, client, RedisType::NullArray);
+    }
+}
+
+// Send an invalidate push for client, to whichever connection it redirects them to
+fn send(state: &State, client: u64, keys: RedisType) {
+    let Some(options) = state.tracking.options(client) else {
+        return;
+    };
+    let target = options.redirect.unwrap_or(client);
+
+    let (info, push) = match state.clients.get(&target) {
+        Some(info) => (
+            info,
+            vec![RedisType::from(String::from("invalidate")), keys],
+        ),
+        // The client it redirects to has gone, so it can't trust its cache any more
+        None => match state.clients.get(&client) {
+            Some(info) => (
+                info,
+                vec![
+                    RedisType::from(String::from("tracking-redir-broken")),
+                    RedisType::from(target as i64),
+                ],
+            ),
+            None => return,
+        },
+    };
+
+    // Closed if the connection is going away
+    if info.protocol == Protocol::Resp3 {
+        let _ = info.pushes.send(RedisType::Push { value: push });
+    } else if info.id == target && state.pubsub.is_subscribed(target, INVALIDATE_CHANNEL) {
+        let mut message = vec![
+            RedisType::from(String::from("message")),
+            RedisType::from(INVALIDATE_CHANNEL.to_vec()),
+        ];
+        message.extend(push.into_iter().skip(1));
+        let _ = info.pushes.send(RedisType::Push { value: message });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Options, Tracking};
+
+    fn keys(keys: &[&str]) -> Vec<Vec<u8>> {
+        keys.iter().map(|key| key.as_bytes().to_vec()).collect()
+    }
+
+    #[test]
+    fn test_invalidate_once_per_read() {
+        let mut tracking = Tracking::default();
+        tracking.enable(1, Options::default()).unwrap();
+        tracking.enable(2, Options::default()).unwrap();
+        tracking.remember(1, &keys(&["a", "b"]));
+        tracking.remember(2, &keys(&["a"]));
+        // Not tracking, so never remembered
+        tracking.remember(3, &keys(&["a"]));
+
+        let invalidated = tracking.invalidate(&keys(&["a", "c"]), Some(3));
+        assert_eq!(invalidated.len(), 2);
+        assert_eq!(invalidated[&1], keys(&["a"]));
+        assert_eq!(invalidated[&2], keys(&["a"]));
+
+        // Forgotten until it's read again
+        assert!(tracking.invalidate(&keys(&["a"]), None).is_empty());
+        assert_eq!(tracking.keys(), 1);
+
+        // Turning tracking off stops invalidations for keys already read
+        tracking.disable(1);
+        assert!(tracking.invalidate(&keys(&["b"]), None).is_empty());
+    }
+
+    #[test]
+    fn test_bcast() {
+        let mut tracking = Tracking::default();
+        let prefixes = |prefixes: &[&str]| Options {
+            bcast: true,
+            prefixes: keys(prefixes),
+            ..Options::default()
+        };
+        tracking.enable(1, prefixes(&["user:"])).unwrap();
+        tracking.enable(1, prefixes(&["post:"])).unwrap();
+        tracking.enable(2, prefixes(&[])).unwrap();
+
+        let invalidated = tracking.invalidate(&keys(&["user:1", "post:1", "other"]), None);
+        assert_eq!(invalidated[&1], keys(&["user:1", "post:1"]));
+        assert_eq!(invalidated[&2], keys(&["user:1", "post:1", "other"]));
+        assert_eq!(tracking.prefixes(), 2);
+
+        assert!(tracking.enable(1, prefixes(&["user:1"])).is_err());
+        assert!(tracking.enable(3, prefixes(&["a", "ab"])).is_err());
+        assert!(tracking.enable(1, Options::default()).is_err());
+        assert!(tracking
+            .enable(
+                4,
+                Options {
+                    prefixes: keys(&["a"]),
+                    ..Options::default()
+                }
+            )
+            .is_err());
+    }
+
+    #[test]
+    fn test_noloop() {
+        let mut tracking = Tracking::default();
+        let noloop = Options {
+            noloop: true,
+            ..Options::default()
+        };
+        tracking.enable(1, noloop).unwrap();
+        tracking.remember(1, &keys(&["a", "b"]));
+
+        assert!(tracking.invalidate(&keys(&["a"]), Some(1)).is_empty());
+        assert_eq!(
+            tracking.invalidate(&keys(&["b"]), Some(2))[&1],
+            keys(&["b"])
+        );
+    }
+}
@@ -0,0 +1,224 @@
+use crate::server::connection::{Reader, Stream, READ_SIZE};
+use crate::server::output::{self, OutputBuffer};
+use crate::server::State;
+use bytes::{Buf, Bytes};
+use std::future::Future;
+use std::io;
+use std::net::{SocketAddr, ToSocketAddrs};
+use std::os::fd::{AsRawFd, RawFd};
+use std::pin::Pin;
+use std::rc::Rc;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use tokio::sync::{mpsc, oneshot, Mutex};
+use tokio::task::JoinHandle;
+use tokio_uring::net::{TcpListener, TcpStream};
+
+// The most buffers a single writev can be given (IOV_MAX on Linux)
+const MAX_IOVECS: usize = 1024;
+
+// Serve connections with io_uring, one thread (and ring) per io thread
+// Each thread binds every address itself with SO_REUSEPORT, so the kernel spreads new connections
+// across them and none of the reads or writes for a connection ever leave its thread. Commands
+// still run against the same state as with epoll. Returns a task per thread that finishes once
+// the thread stops accepting connections, as with the epoll listeners.
+pub async fn listen(
+    state: &Arc<Mutex<State>>,
+    connections: &mpsc::Sender<()>,
+) -> io::Result<Vec<JoinHandle<io::Result<()>>>> {
+    let (binds, port, threads) = {
+        let state = state.lock().await;
+        let config = &state.config;
+        (config.bind.clone(), config.port, config.worker_threads())
+    };
+    let mut addrs = Vec::new();
+    for bind in binds {
+        addrs.extend((bind.as_str(), port).to_socket_addrs()?);
+    }
+
+    let mut accept_tasks = Vec::new();
+    for thread in 0..threads {
+        let addrs = addrs.clone();
+        let state = state.clone();
+        let connections = connections.clone();
+        let (accepting_tx, accepting_rx) = oneshot::channel();
+
+        std::thread::Builder::new()
+            .name(format!("io-uring-{thread}"))
+            .spawn(move || {
+                tokio_uring::start(serve_thread(
+                    thread,
+                    addrs,
+                    state,
+                    connections,
+                    accepting_tx,
+                ))
+            })?;
+
+        accept_tasks.push(tokio::spawn(
+            async move { accepting_rx.await.unwrap_or(Ok(())) },
+        ));
+    }
+
+    Ok(accept_tasks)
+}
+
+// Accept connections until shutdown, then let this thread's connections finish before its ring
+// (and everything running on it) goes away
+async fn serve_thread(
+    thread: usize,
+    addrs: Vec<SocketAddr>,
+    state: Arc<Mutex<State>>,
+    connections: mpsc::Sender<()>,
+    accepting: oneshot::Sender<io::Result<()>>,
+) {
+    let (local, mut local_done) = mpsc::channel::<()>(1);
+
+    let mut listeners = Vec::new();
+    for addr in addrs {
+        match TcpListener::bind(addr) {
+            Ok(listener) => {
+                if thread == 0 {
+                    tracing::info!(
+                        "Listening on {} with io_uring",
+                        listener.local_addr().unwrap_or(addr)
+                    );
+                }
+                listeners.push(tokio_uring::spawn(accept(
+                    listener,
+                    state.clone(),
+                    (connections.clone(), local.clone()),
+                )));
+            }
+            Err(e) => {
+                let _ = accepting.send(Err(e));
+                return;
+            }
+        }
+    }
+    drop((connections, local));
+
+    let mut result = Ok(());
+    for listener in listeners {
+        if let Ok(Err(e)) = listener.await {
+            result = Err(e);
+        }
+    }
+    let _ = accepting.send(result);
+
+    // Once every connection has dropped its sender
+    local_done.recv().await;
+}
+
+async fn accept(
+    listener: TcpListener,
+    state: Arc<Mutex<State>>,
+    connections: (mpsc::Sender<()>, mpsc::Sender<()>),
+) -> io::Result<()> {
+    let mut shutdown = state.lock().await.shutdown.subscribe();
+
+    loop {
+        let (stream, addr) = tokio::select! {
+            result = listener.accept() => result?,
+            _ = shutdown.wait() => return Ok(()),
+        };
+        let thread_state = state.clone();
+        let connection = connections.clone();
+
+        tracing::debug!("Accepted connection from {addr:?}");
+        tokio_uring::spawn(async move {
+            if let Err(e) =
+                crate::server::handle(UringStream(Rc::new(stream)), addr, thread_state).await
+            {
+                tracing::warn!("An error occurred: {e:?}");
+            }
+            drop(connection);
+        });
+    }
+}
+
+pub struct UringStream(Rc<TcpStream>);
+
+impl AsRawFd for UringStream {
+    fn as_raw_fd(&self) -> RawFd {
+        self.0.as_raw_fd()
+    }
+}
+
+impl Stream for UringStream {
+    type Reader = UringReader;
+
+    // Reads and writes are separate operations on the ring, so both halves share the socket
+    fn split(self) -> (UringReader, OutputBuffer) {
+        let stream = self.0.clone();
+        let output = OutputBuffer::with_writer(|mut replies, pending| {
+            tokio_uring::spawn(async move {
+                while let Some(batch) = output::next_batch(&mut replies).await {
+                    let len = batch.iter().map(Bytes::len).sum::<usize>();
+                    write_all_vectored(&stream, batch).await?;
+                    pending.fetch_sub(len, Ordering::Relaxed);
+                }
+                stream.shutdown(std::net::Shutdown::Write)
+            })
+        });
+
+        let reader = UringReader {
+            stream: self.0,
+            read: None,
+        };
+        (reader, output)
+    }
+}
+
+type Read = Pin<Box<dyn Future<Output = tokio_uring::BufResult<usize, Vec<u8>>>>>;
+
+pub struct UringReader {
+    stream: Rc<TcpStream>,
+    // A read that has been submitted to the ring, kept if the caller stops waiting for it so
+    // that whatever it reads is returned next time instead of lost
+    read: Option<Read>,
+}
+
+impl Reader for UringReader {
+    async fn read(&mut self, input: &mut Vec<u8>) -> io::Result<usize> {
+        let read = self.read.get_or_insert_with(|| {
+            let stream = self.stream.clone();
+            Box::pin(async move { stream.read(Vec::with_capacity(READ_SIZE)).await })
+        });
+        let (result, buf) = read.await;
+        self.read = None;
+
+        let bytes_read = result?;
+        input.extend_from_slice(&buf[..bytes_read]);
+        Ok(bytes_read)
+    }
+}
+
+// Write all of replies, taking them back from the ring after each writev to skip what was written
+async fn write_all_vectored(stream: &TcpStream, mut replies: Vec<Bytes>) -> io::Result<()> {
+    replies.retain(|reply| !reply.is_empty());
+
+    while !replies.is_empty() {
+        let rest = replies.split_off(replies.len().min(MAX_IOVECS));
+        let (result, mut written) = stream.writev(replies).await;
+        let mut len = result?;
+        if len == 0 {
+            return Err(io::ErrorKind::WriteZero.into());
+        }
+
+        // Drop the replies that went out in full and the start of the one that didn't
+        let mut done = 0;
+        while done < written.len() && len >= written[done].len() {
+            len -= written[done].len();
+            done += 1;
+        }
+        written.drain(..done);
+        if len > 0 {
+            written[0].advance(len);
+        }
+        written.extend(rest);
+        replies = written;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,330 @@
+use bytes::Bytes;
+use std::fmt::Debug;
+use std::hash::{Hash, Hasher};
+use std::ops::{Deref, RangeBounds};
+use std::sync::{Arc, OnceLock};
+
+// Strings up to this long are kept inline, in the same space a pointer to a buffer would take
+pub const INLINE_CAPACITY: usize = 22;
+
+// Integers from 0 up to (but not including) this are shared by every value holding them
+pub const SHARED_INTEGERS: i64 = 10000;
+
+// A stored value, a string or a JSON document
+// Like Redis' embstr, short strings are kept inline rather than in an allocation of their own,
+// and like its shared integers, small integers all point at the same static buffer. Anything
+// else is a reference counted buffer, so replies can send it without copying.
+#[derive(Clone)]
+pub enum Value {
+    Small(Small),
+    Shared(Bytes),
+}
+
+// Values that take less space than a Shared one, nested so that there's only the one place to
+// tell them apart from it and a Value is no bigger than the Bytes it can hold
+#[derive(Clone)]
+pub enum Small {
+    Inline {
+        len: u8,
+        data: [u8; INLINE_CAPACITY],
+    },
+    Json(Arc<Json>),
+}
+
+// A JSON document, as stored by JSON.SET, along with its serialized form, which is what the value
+// holds as bytes (for snapshots and MEMORY USAGE, say)
+#[derive(Debug)]
+pub struct Json {
+    document: serde_json::Value,
+    text: String,
+}
+
+impl Value {
+    // The value holding n, without formatting it into an allocation first
+    pub fn integer(n: i64) -> Value {
+        if (0..SHARED_INTEGERS).contains(&n) {
+            return Value::Shared(shared_integer(n as usize));
+        }
+
+        let mut data = [0; INLINE_CAPACITY];
+        let mut digits = n.unsigned_abs();
+        let mut start = INLINE_CAPACITY;
+        loop {
+            start -= 1;
+            data[start] = b'0' + (digits % 10) as u8;
+            digits /= 10;
+            if digits == 0 {
+                break;
+            }
+        }
+        if n < 0 {
+            start -= 1;
+            data[start] = b'-';
+        }
+        Value::from(&data[start..])
+    }
+
+    pub fn json(document: serde_json::Value) -> Value {
+        let text = document.to_string();
+        Value::Small(Small::Json(Arc::new(Json { document, text })))
+    }
+
+    pub fn as_json(&self) -> Option<&serde_json::Value> {
+        match self {
+            Value::Small(Small::Json(json)) => Some(&json.document),
+            _ => None,
+        }
+    }
+
+    pub fn is_json(&self) -> bool {
+        self.as_json().is_some()
+    }
+
+    // As reported by TYPE, JSON documents have the RedisJSON module's type
+    pub fn type_name(&self) -> &'static str {
+        match self.is_json() {
+            true => "ReJSON-RL",
+            false => "string",
+        }
+    }
+
+    // Share the value's buffer, copying it only if it doesn't have one
+    pub fn to_bytes(&self) -> Bytes {
+        match self {
+            Value::Small(_) => Bytes::copy_from_slice(self),
+            Value::Shared(bytes) => bytes.clone(),
+        }
+    }
+
+    pub fn slice(&self, range: impl RangeBounds<usize>) -> Value {
+        match self {
+            Value::Small(_) => {
+                Value::from(&self[(range.start_bound().cloned(), range.end_bound().cloned())])
+            }
+            Value::Shared(bytes) => Value::from(bytes.slice(range)),
+        }
+    }
+
+    // Shared integers are never freed, which Redis reports as an effectively infinite refcount
+    pub fn is_shared_integer(&self) -> bool {
+        match self {
+            Value::Shared(bytes) => {
+                let integers = shared_integers();
+                integers.as_ptr_range().contains(&bytes.as_ptr())
+            }
+            Value::Small(_) => false,
+        }
+    }
+
+    // How the value is stored, as reported by OBJECT ENCODING
+    pub fn encoding(&self) -> &'static str {
+        let integer = self.len() <= 20
+            && std::str::from_utf8(self).is_ok_and(|value| value.parse::<i64>().is_ok());
+        match self {
+            Value::Small(Small::Json(_)) => "raw",
+            _ if integer => "int",
+            Value::Small(Small::Inline { .. }) => "embstr",
+            Value::Shared(_) => "raw",
+        }
+    }
+}
+
+// All of the shared integers one after the other, each found from its number of digits
+fn shared_integers() -> &'static [u8] {
+    static INTEGERS: OnceLock<&'static [u8]> = OnceLock::new();
+    INTEGERS.get_or_init(|| {
+        let integers = (0..SHARED_INTEGERS)
+            .map(|n| n.to_string())
+            .collect::<String>();
+        Box::leak(integers.into_boxed_str()).as_bytes()
+    })
+}
+
+fn shared_integer(n: usize) -> Bytes {
+    let (start, len) = match n {
+        0..=9 => (n, 1),
+        10..=99 => (10 + (n - 10) * 2, 2),
+        100..=999 => (190 + (n - 100) * 3, 3),
+        _ => (2890 + (n - 1000) * 4, 4),
+    };
+    Bytes::from_static(&shared_integers()[start..start + len])
+}
+
+// The shared integer with the same contents, if there is one
+fn as_shared_integer(value: &[u8]) -> Option<Value> {
+    let canonical = !value.is_empty()
+        && value.len() <= 4
+        && value.iter().all(u8::is_ascii_digit)
+        && (value[0] != b'0' || value.len() == 1);
+    if !canonical {
+        return None;
+    }
+
+    let n = value
+        .iter()
+        .fold(0, |n, digit| n * 10 + (digit - b'0') as usize);
+    Some(Value::Shared(shared_integer(n)))
+}
+
+impl Deref for Value {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        match self {
+            Value::Small(Small::Inline { len, data }) => &data[..*len as usize],
+            Value::Small(Small::Json(json)) => json.text.as_bytes(),
+            Value::Shared(bytes) => bytes,
+        }
+    }
+}
+
+impl AsRef<[u8]> for Value {
+    fn as_ref(&self) -> &[u8] {
+        self
+    }
+}
+
+impl From<&[u8]> for Value {
+    fn from(value: &[u8]) -> Self {
+        if let Some(shared) = as_shared_integer(value) {
+            shared
+        } else if value.len() <= INLINE_CAPACITY {
+            let mut data = [0; INLINE_CAPACITY];
+            data[..value.len()].copy_from_slice(value);
+            Value::Small(Small::Inline {
+                len: value.len() as u8,
+                data,
+            })
+        } else {
+            Value::Shared(Bytes::copy_from_slice(value))
+        }
+    }
+}
+
+impl From<&str> for Value {
+    fn from(value: &str) -> Self {
+        Value::from(value.as_bytes())
+    }
+}
+
+impl From<Vec<u8>> for Value {
+    fn from(value: Vec<u8>) -> Self {
+        if value.len() <= INLINE_CAPACITY {
+            Value::from(value.as_slice())
+        } else {
+            Value::Shared(Bytes::from(value))
+        }
+    }
+}
+
+impl From<String> for Value {
+    fn from(value: String) -> Self {
+        Value::from(value.into_bytes())
+    }
+}
+
+impl From<Bytes> for Value {
+    fn from(value: Bytes) -> Self {
+        if value.len() <= INLINE_CAPACITY {
+            Value::from(&value[..])
+        } else {
+            Value::Shared(value)
+        }
+    }
+}
+
+#[cfg(feature = "fuzz")]
+impl<'a> arbitrary::Arbitrary<'a> for Value {
+    fn arbitrary(u: &mut arbitrary::Unstructured<'a>) -> arbitrary::Result<Self> {
+        Ok(Value::from(<&[u8]>::arbitrary(u)?))
+    }
+}
+
+// Compared by contents, however they're stored (so a JSON document is equal to its serialized form)
+impl PartialEq for Value {
+    fn eq(&self, other: &Self) -> bool {
+        **self == **other
+    }
+}
+
+impl Eq for Value {}
+
+impl Hash for Value {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        (**self).hash(state)
+    }
+}
+
+impl Debug for Value {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "b\"{}\"", self.escape_ascii())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encodings() {
+        assert_eq!(std::mem::size_of::<Value>(), std::mem::size_of::<Bytes>());
+
+        let shared = Value::from("1234");
+        assert!(shared.is_shared_integer());
+        assert_eq!(shared.encoding(), "int");
+        assert_eq!(Value::integer(1234).as_ptr(), shared.as_ptr());
+
+        for value in ["0123", "-1", "12345", "1.5"] {
+            assert!(!Value::from(value).is_shared_integer(), "{value}");
+        }
+        assert_eq!(Value::from("12345").encoding(), "int");
+        assert_eq!(Value::from("hello").encoding(), "embstr");
+        assert_eq!(
+            Value::from("x".repeat(INLINE_CAPACITY + 1)).encoding(),
+            "raw"
+        );
+    }
+
+    #[test]
+    fn test_integer() {
+        for n in [
+            0,
+            9,
+            10,
+            99,
+            100,
+            999,
+            1000,
+            9999,
+            10000,
+            -1,
+            i64::MIN,
+            i64::MAX,
+        ] {
+            assert_eq!(&*Value::integer(n), n.to_string().as_bytes());
+        }
+    }
+
+    #[test]
+    fn test_slice() {
+        let short = Value::from("hello");
+        assert_eq!(short.slice(1..3), Value::from("el"));
+
+        let long = Value::from("x".repeat(100));
+        assert_eq!(long.slice(..50).len(), 50);
+        assert_eq!(long.slice(..50).encoding(), "raw");
+        assert_eq!(long.slice(..5).encoding(), "embstr");
+    }
+
+    #[test]
+    fn test_json() {
+        let json = Value::json(serde_json::json!({"a": [1, "two"]}));
+        assert_eq!(&*json, br#"{"a":[1,"two"]}"#);
+        assert_eq!(json.as_json().unwrap()["a"][1], "two");
+        assert_eq!((json.type_name(), json.encoding()), ("ReJSON-RL", "raw"));
+        assert_eq!(Value::json(serde_json::json!(1)).encoding(), "raw");
+
+        assert!(!Value::from("{}").is_json());
+        assert_eq!(Value::from("{}").type_name(), "string");
+    }
+}
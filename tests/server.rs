@@ -0,0 +1,645 @@
+use redis_rs::server::{CustomCommand, Db, Server};
+use redis_rs::RedisType;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+// Send a command and read back its reply, which is small enough to arrive in one read
+async fn command(stream: &mut TcpStream, args: &[&str]) -> String {
+    let mut request = format!("*{}\r\n", args.len());
+    for arg in args {
+        request.push_str(&format!("${}\r\n{arg}\r\n", arg.len()));
+    }
+    stream.write_all(request.as_bytes()).await.unwrap();
+
+    let mut reply = vec![0; 1024];
+    let len = stream.read(&mut reply).await.unwrap();
+    String::from_utf8_lossy(&reply[..len]).into_owned()
+}
+
+// command, for arguments and replies that can be any bytes
+async fn command_bytes(stream: &mut TcpStream, args: &[&[u8]]) -> Vec<u8> {
+    let mut request = format!("*{}\r\n", args.len()).into_bytes();
+    for arg in args {
+        request.extend_from_slice(format!("${}\r\n", arg.len()).as_bytes());
+        request.extend_from_slice(arg);
+        request.extend_from_slice(b"\r\n");
+    }
+    stream.write_all(&request).await.unwrap();
+
+    let mut reply = vec![0; 1024];
+    let len = stream.read(&mut reply).await.unwrap();
+    reply.truncate(len);
+    reply
+}
+
+// Read replies (or messages) that come to len bytes in all, however many reads they arrive in
+async fn read_exactly(stream: &mut TcpStream, len: usize) -> String {
+    let mut reply = vec![0; len];
+    stream.read_exact(&mut reply).await.unwrap();
+    String::from_utf8_lossy(&reply).into_owned()
+}
+
+// Send a line of input as is and read back its reply
+async fn command_inline(stream: &mut TcpStream, line: &[u8]) -> String {
+    stream.write_all(line).await.unwrap();
+    let mut reply = vec![0; 1024];
+    let len = stream.read(&mut reply).await.unwrap();
+    String::from_utf8_lossy(&reply[..len]).into_owned()
+}
+
+#[tokio::test]
+async fn test_embedded_server() {
+    let server = Server::bind("127.0.0.1:0")
+        .config("maxmemory", "10mb")
+        .spawn()
+        .await
+        .unwrap();
+    assert_ne!(server.port(), 0);
+
+    let mut stream = TcpStream::connect(server.addr()).await.unwrap();
+    assert_eq!(command(&mut stream, &["PING"]).await, "$4\r\nPONG\r\n");
+    command(&mut stream, &["SET", "key", "value"]).await;
+    assert_eq!(
+        command(&mut stream, &["GET", "key"]).await,
+        "$5\r\nvalue\r\n"
+    );
+    assert!(command(&mut stream, &["CONFIG", "GET", "port"])
+        .await
+        .contains(&server.port().to_string()));
+
+    let addr = server.addr();
+    drop(stream);
+    server.shutdown().await.unwrap();
+    assert!(TcpStream::connect(addr).await.is_err());
+}
+
+#[tokio::test]
+async fn test_embedded_server_config() {
+    let result = Server::bind("127.0.0.1:0")
+        .config("no-such-parameter", "yes")
+        .spawn()
+        .await;
+    assert!(result.is_err());
+}
+
+#[tokio::test]
+async fn test_maxmemory() {
+    let server = Server::bind("127.0.0.1:0").spawn().await.unwrap();
+    let mut stream = TcpStream::connect(server.addr()).await.unwrap();
+    command(&mut stream, &["SET", "key", "value"]).await;
+
+    // Anything is over a limit of one byte, so only commands that can't use more memory run
+    command(&mut stream, &["CONFIG", "SET", "maxmemory", "1"]).await;
+    assert!(command(&mut stream, &["SET", "other", "value"])
+        .await
+        .starts_with("-OOM "));
+    assert_eq!(
+        command(&mut stream, &["GET", "key"]).await,
+        "$5\r\nvalue\r\n"
+    );
+    assert_eq!(command(&mut stream, &["DEL", "key"]).await, ":1\r\n");
+
+    command(&mut stream, &["CONFIG", "SET", "maxmemory", "0"]).await;
+    assert_eq!(
+        command(&mut stream, &["SET", "other", "value"]).await,
+        "$2\r\nOK\r\n"
+    );
+}
+
+#[tokio::test]
+async fn test_hotkeys() {
+    let server = Server::bind("127.0.0.1:0").spawn().await.unwrap();
+    let mut stream = TcpStream::connect(server.addr()).await.unwrap();
+    assert!(command(&mut stream, &["DEBUG", "HOTKEYS"])
+        .await
+        .starts_with("-ERR Key sampling is disabled"));
+
+    command(&mut stream, &["CONFIG", "SET", "hotkeys-sample-rate", "1"]).await;
+    command(&mut stream, &["SET", "hot", "value"]).await;
+    for _ in 0..3 {
+        command(&mut stream, &["GET", "hot"]).await;
+    }
+    command(&mut stream, &["SET", "big", &"x".repeat(500)]).await;
+
+    assert_eq!(
+        command(&mut stream, &["DEBUG", "HOTKEYS", "1"]).await,
+        "*2\r\n$3\r\nhot\r\n:4\r\n"
+    );
+    assert!(command(&mut stream, &["DEBUG", "BIGKEYS", "1"])
+        .await
+        .starts_with("*2\r\n$3\r\nbig\r\n:"));
+    assert!(command(&mut stream, &["INFO", "hotkeys"])
+        .await
+        .contains("hotkey_0:key=hot,accesses=4\r\n"));
+}
+
+#[tokio::test]
+async fn test_shutdown_drain() {
+    let server = Server::bind("127.0.0.1:0").spawn().await.unwrap();
+    let mut idle = TcpStream::connect(server.addr()).await.unwrap();
+    let mut waiting = TcpStream::connect(server.addr()).await.unwrap();
+    assert_eq!(command(&mut idle, &["PING"]).await, "$4\r\nPONG\r\n");
+
+    // Blocked until there's a replica, which there never will be
+    waiting
+        .write_all(b"*3\r\n$4\r\nWAIT\r\n$1\r\n1\r\n$1\r\n0\r\n")
+        .await
+        .unwrap();
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    let shutdown = tokio::spawn(server.shutdown());
+    let mut reply = vec![0; 1024];
+    let len = waiting.read(&mut reply).await.unwrap();
+    assert_eq!(&reply[..len], b"-ERR The server is shutting down\r\n");
+    assert_eq!(waiting.read(&mut reply).await.unwrap(), 0);
+    assert_eq!(idle.read(&mut reply).await.unwrap(), 0);
+    shutdown.await.unwrap().unwrap();
+}
+
+#[tokio::test]
+async fn test_request_limits() {
+    let server = Server::bind("127.0.0.1:0")
+        .config("proto-max-bulk-len", "16")
+        .spawn()
+        .await
+        .unwrap();
+    let mut stream = TcpStream::connect(server.addr()).await.unwrap();
+
+    // Refused from the length alone, without waiting for the value
+    stream
+        .write_all(b"*1\r\n$4\r\nPING\r\n*3\r\n$3\r\nSET\r\n$3\r\nkey\r\n$100\r\n")
+        .await
+        .unwrap();
+    let mut reply = Vec::new();
+    stream.read_to_end(&mut reply).await.unwrap();
+    assert_eq!(
+        reply,
+        b"$4\r\nPONG\r\n-ERR Protocol error: invalid bulk length\r\n"
+    );
+}
+
+#[tokio::test]
+async fn test_protocol_error() {
+    let server = Server::bind("127.0.0.1:0").spawn().await.unwrap();
+    let mut stream = TcpStream::connect(server.addr()).await.unwrap();
+
+    stream
+        .write_all(b"*1\r\n$4\r\nPING\r\n*2\r\n$3\r\nGET\r\n+key\r\n*1\r\n$4\r\nPING\r\n")
+        .await
+        .unwrap();
+    let mut reply = Vec::new();
+    stream.read_to_end(&mut reply).await.unwrap();
+    assert_eq!(
+        reply,
+        b"$4\r\nPONG\r\n-ERR Protocol error: expected '$', got '+'\r\n"
+    );
+
+    // A request whose name isn't text is refused and closed rather than left waiting for a reply
+    let mut stream = TcpStream::connect(server.addr()).await.unwrap();
+    stream.write_all(b"*1\r\n$2\r\n\xff\xfe\r\n").await.unwrap();
+    let mut reply = Vec::new();
+    stream.read_to_end(&mut reply).await.unwrap();
+    assert_eq!(reply, b"-ERR Protocol error: invalid command name\r\n");
+
+    // Requests with a count of 0 or less are skipped, as in Redis
+    let mut stream = TcpStream::connect(server.addr()).await.unwrap();
+    stream.write_all(b"*0\r\n*-1\r\n").await.unwrap();
+    assert_eq!(command(&mut stream, &["PING"]).await, "$4\r\nPONG\r\n");
+}
+
+#[tokio::test]
+async fn test_inline_commands() {
+    let server = Server::bind("127.0.0.1:0").spawn().await.unwrap();
+    let mut stream = TcpStream::connect(server.addr()).await.unwrap();
+
+    stream
+        .write_all(b"PING\r\nSET foo \"hello world\"\nGET foo\r\nGET \"foo\r\n")
+        .await
+        .unwrap();
+    let mut reply = Vec::new();
+    stream.read_to_end(&mut reply).await.unwrap();
+    assert_eq!(
+        reply,
+        b"$4\r\nPONG\r\n$2\r\nOK\r\n$11\r\nhello world\r\n\
+        -ERR Protocol error: unbalanced quotes in request\r\n"
+    );
+
+    // Anything that doesn't start with * is inline, even RESP that isn't an array
+    let mut stream = TcpStream::connect(server.addr()).await.unwrap();
+    assert_eq!(
+        command_inline(&mut stream, b"$4\r\n").await,
+        "-ERR unknown command '$4', with args beginning with: \r\n"
+    );
+    assert_eq!(
+        command_inline(&mut stream, b":1 +PING\r\n").await,
+        "-ERR unknown command ':1', with args beginning with: '+PING' \r\n"
+    );
+    assert_eq!(
+        command_inline(&mut stream, b"PING\r\n").await,
+        "$4\r\nPONG\r\n"
+    );
+}
+
+#[tokio::test]
+async fn test_binary_safe() {
+    let server = Server::bind("127.0.0.1:0").spawn().await.unwrap();
+    let mut stream = TcpStream::connect(server.addr()).await.unwrap();
+
+    // Line breaks, NUL and bytes that aren't UTF-8 in both keys and values
+    let key: &[u8] = b"key\r\n\x00\xff";
+    let value: &[u8] = b"\xfe\r\n\x00value\r\n";
+    assert_eq!(
+        command_bytes(&mut stream, &[b"SET", key, value]).await,
+        b"$2\r\nOK\r\n"
+    );
+    assert_eq!(
+        command_bytes(&mut stream, &[b"GET", key]).await,
+        b"$11\r\n\xfe\r\n\x00value\r\n\r\n"
+    );
+    assert_eq!(
+        command_bytes(&mut stream, &[b"GET", b"key\r\n\x00\xfe"]).await,
+        b"$-1\r\n"
+    );
+    assert_eq!(
+        command_bytes(&mut stream, &[b"MGET", key, b"missing", key]).await,
+        b"*3\r\n$11\r\n\xfe\r\n\x00value\r\n\r\n$-1\r\n$11\r\n\xfe\r\n\x00value\r\n\r\n"
+    );
+
+    // Inline, as escapes in quotes and as bytes that aren't UTF-8 as they are
+    assert_eq!(
+        command_inline(&mut stream, b"SET \"in\\r\\n\\x00\\xff\" \xfe\x01\xff\r\n").await,
+        "$2\r\nOK\r\n"
+    );
+    assert_eq!(
+        command_bytes(&mut stream, &[b"GET", b"in\r\n\x00\xff"]).await,
+        b"$3\r\n\xfe\x01\xff\r\n"
+    );
+    assert_eq!(
+        command_inline(&mut stream, b"SET raw\xff \"\\x00\\r\\n\"\r\n").await,
+        "$2\r\nOK\r\n"
+    );
+    assert_eq!(
+        command_bytes(&mut stream, &[b"MGET", b"raw\xff", b"in\r\n\x00\xff"]).await,
+        b"*2\r\n$3\r\n\x00\r\n\r\n$3\r\n\xfe\x01\xff\r\n"
+    );
+}
+
+#[tokio::test]
+async fn test_custom_commands() {
+    let take = CustomCommand::new("TAKE", 2, |db: Db, args: Vec<Vec<u8>>| async move {
+        db.update(|keys| {
+            let value = keys
+                .get(&args[0])
+                .map(|value| RedisType::from(value.to_vec()));
+            keys.del(&args[0]);
+            Ok(value.unwrap_or(RedisType::NullString))
+        })
+        .await
+    });
+    let fail = CustomCommand::new("FAIL", -1, |_db: Db, _args: Vec<Vec<u8>>| async {
+        Err(String::from("it failed"))
+    });
+    let server = Server::bind("127.0.0.1:0")
+        .command(take.flags(&["write", "fast"]).keys(1, 1, 1))
+        .command(fail)
+        .spawn()
+        .await
+        .unwrap();
+    let mut stream = TcpStream::connect(server.addr()).await.unwrap();
+
+    assert_eq!(
+        command(&mut stream, &["SET", "key", "value"]).await,
+        "$2\r\nOK\r\n"
+    );
+    assert_eq!(
+        command(&mut stream, &["take", "key"]).await,
+        "$5\r\nvalue\r\n"
+    );
+    assert_eq!(command(&mut stream, &["TAKE", "key"]).await, "$-1\r\n");
+    assert_eq!(command(&mut stream, &["GET", "key"]).await, "$-1\r\n");
+    assert_eq!(
+        command(&mut stream, &["TAKE"]).await,
+        "-ERR wrong number of arguments for 'take' command\r\n"
+    );
+    assert_eq!(
+        command(&mut stream, &["FAIL", "now"]).await,
+        "-ERR it failed\r\n"
+    );
+
+    let result = Server::bind("127.0.0.1:0")
+        .command(CustomCommand::new(
+            "GET",
+            2,
+            |_db: Db, _args: Vec<Vec<u8>>| async { Ok(RedisType::NullString) },
+        ))
+        .spawn()
+        .await;
+    assert_eq!(
+        result.err().unwrap().to_string(),
+        "Command 'GET' already exists"
+    );
+}
+
+#[tokio::test]
+async fn test_json() {
+    let server = Server::bind("127.0.0.1:0").spawn().await.unwrap();
+    let mut stream = TcpStream::connect(server.addr()).await.unwrap();
+    let bulk = |value: &str| format!("${}\r\n{value}\r\n", value.len());
+
+    assert_eq!(
+        command(
+            &mut stream,
+            &["JSON.SET", "doc", "$", r#"{"a":{"n":1},"b":[1,2]}"#]
+        )
+        .await,
+        "$2\r\nOK\r\n"
+    );
+    assert_eq!(
+        command(&mut stream, &["JSON.SET", "doc", "$.a.s", r#""x""#]).await,
+        "$2\r\nOK\r\n"
+    );
+    assert_eq!(
+        command(&mut stream, &["JSON.GET", "doc", "$..a"]).await,
+        "-ERR Invalid or unsupported path '$..a'\r\n"
+    );
+    assert_eq!(
+        command(&mut stream, &["JSON.GET", "doc", "$.a"]).await,
+        bulk(r#"[{"n":1,"s":"x"}]"#)
+    );
+    assert_eq!(
+        command(&mut stream, &["JSON.GET", "doc", ".a.n", "$.b[-1]"]).await,
+        bulk(r#"{".a.n":[1],"$.b[-1]":[2]}"#)
+    );
+    assert_eq!(
+        command(&mut stream, &["JSON.NUMINCRBY", "doc", "$.b[*]", "2"]).await,
+        bulk("[3,4]")
+    );
+    assert_eq!(
+        command(&mut stream, &["JSON.DEL", "doc", "$.a"]).await,
+        ":1\r\n"
+    );
+    assert_eq!(
+        command(&mut stream, &["JSON.GET", "doc", "SPACE", " "]).await,
+        bulk(r#"{"b": [3,4]}"#)
+    );
+
+    // Strings and JSON documents are different types
+    assert_eq!(
+        command(&mut stream, &["TYPE", "doc"]).await,
+        bulk("ReJSON-RL")
+    );
+    assert!(command(&mut stream, &["GET", "doc"])
+        .await
+        .starts_with("-WRONGTYPE "));
+    command(&mut stream, &["SET", "string", "value"]).await;
+    assert!(command(&mut stream, &["JSON.GET", "string"])
+        .await
+        .starts_with("-WRONGTYPE "));
+    assert_eq!(
+        command(&mut stream, &["MGET", "doc", "string"]).await,
+        "*2\r\n$-1\r\n$5\r\nvalue\r\n"
+    );
+
+    assert_eq!(command(&mut stream, &["JSON.DEL", "doc"]).await, ":1\r\n");
+    assert_eq!(command(&mut stream, &["EXISTS", "doc"]).await, ":0\r\n");
+}
+
+#[tokio::test]
+async fn test_pubsub() {
+    let server = Server::bind("127.0.0.1:0").spawn().await.unwrap();
+    let mut subscriber = TcpStream::connect(server.addr()).await.unwrap();
+    let mut publisher = TcpStream::connect(server.addr()).await.unwrap();
+
+    // A confirmation for each channel, with how many subscriptions there are so far
+    subscriber
+        .write_all(b"*3\r\n$9\r\nSUBSCRIBE\r\n$4\r\nnews\r\n$5\r\nother\r\n")
+        .await
+        .unwrap();
+    let confirmations = "*3\r\n$9\r\nsubscribe\r\n$4\r\nnews\r\n:1\r\n\
+        *3\r\n$9\r\nsubscribe\r\n$5\r\nother\r\n:2\r\n";
+    assert_eq!(
+        read_exactly(&mut subscriber, confirmations.len()).await,
+        confirmations
+    );
+    assert_eq!(
+        command(&mut subscriber, &["PSUBSCRIBE", "n*"]).await,
+        "*3\r\n$10\r\npsubscribe\r\n$2\r\nn*\r\n:3\r\n"
+    );
+
+    // Only commands that can't be mistaken for messages are allowed under RESP2
+    assert_eq!(
+        command(&mut subscriber, &["GET", "key"]).await,
+        "-ERR Can't execute 'get': only (P|S)SUBSCRIBE / (P|S)UNSUBSCRIBE / PING / QUIT / RESET are allowed in this context\r\n"
+    );
+    assert_eq!(
+        command(&mut subscriber, &["PING"]).await,
+        "*2\r\n$4\r\npong\r\n$0\r\n\r\n"
+    );
+
+    // Once for the channel and once for the pattern
+    assert_eq!(
+        command(&mut publisher, &["PUBLISH", "news", "hello"]).await,
+        ":2\r\n"
+    );
+    let messages = "*3\r\n$7\r\nmessage\r\n$4\r\nnews\r\n$5\r\nhello\r\n\
+        *4\r\n$8\r\npmessage\r\n$2\r\nn*\r\n$4\r\nnews\r\n$5\r\nhello\r\n";
+    assert_eq!(
+        read_exactly(&mut subscriber, messages.len()).await,
+        messages
+    );
+    assert_eq!(
+        command(&mut publisher, &["PUBSUB", "NUMSUB", "news", "none"]).await,
+        "*4\r\n$4\r\nnews\r\n:1\r\n$4\r\nnone\r\n:0\r\n"
+    );
+    assert_eq!(
+        command(&mut publisher, &["PUBSUB", "NUMPAT"]).await,
+        ":1\r\n"
+    );
+
+    // Unsubscribing from everything leaves subscriber mode
+    subscriber
+        .write_all(b"*1\r\n$11\r\nUNSUBSCRIBE\r\n*1\r\n$12\r\nPUNSUBSCRIBE\r\n")
+        .await
+        .unwrap();
+    let confirmations = "*3\r\n$11\r\nunsubscribe\r\n$4\r\nnews\r\n:2\r\n\
+        *3\r\n$11\r\nunsubscribe\r\n$5\r\nother\r\n:1\r\n\
+        *3\r\n$12\r\npunsubscribe\r\n$2\r\nn*\r\n:0\r\n";
+    assert_eq!(
+        read_exactly(&mut subscriber, confirmations.len()).await,
+        confirmations
+    );
+    assert_eq!(command(&mut subscriber, &["GET", "key"]).await, "$-1\r\n");
+    assert_eq!(
+        command(&mut publisher, &["PUBLISH", "news", "hello"]).await,
+        ":0\r\n"
+    );
+
+    // RESP3 has push messages, so a subscribed connection can run anything
+    command(&mut subscriber, &["HELLO", "3"]).await;
+    assert_eq!(
+        command(&mut subscriber, &["SUBSCRIBE", "news"]).await,
+        ">3\r\n$9\r\nsubscribe\r\n$4\r\nnews\r\n:1\r\n"
+    );
+    assert_eq!(command(&mut subscriber, &["GET", "key"]).await, "_\r\n");
+    assert_eq!(
+        command(&mut subscriber, &["RESET"]).await,
+        "$5\r\nRESET\r\n"
+    );
+    assert_eq!(
+        command(&mut publisher, &["PUBLISH", "news", "hello"]).await,
+        ":0\r\n"
+    );
+}
+
+// Whether the server has left the connection open, reading (and dropping) anything it sends until
+// it goes quiet
+async fn is_open(stream: &mut TcpStream) -> bool {
+    let mut buffer = vec![0; 64 * 1024];
+    loop {
+        match tokio::time::timeout(Duration::from_millis(100), stream.read(&mut buffer)).await {
+            Err(_) => return true,
+            Ok(Ok(0) | Err(_)) => return false,
+            Ok(Ok(_)) => {}
+        }
+    }
+}
+
+#[tokio::test]
+async fn test_idle_timeout() {
+    let server = Server::bind("127.0.0.1:0")
+        .config("timeout", "1")
+        .spawn()
+        .await
+        .unwrap();
+    let mut idle = TcpStream::connect(server.addr()).await.unwrap();
+    let mut blocked = TcpStream::connect(server.addr()).await.unwrap();
+    let mut subscriber = TcpStream::connect(server.addr()).await.unwrap();
+    let mut replica = TcpStream::connect(server.addr()).await.unwrap();
+
+    assert_eq!(command(&mut idle, &["PING"]).await, "$4\r\nPONG\r\n");
+    command(&mut subscriber, &["SUBSCRIBE", "news"]).await;
+    replica
+        .write_all(b"*3\r\n$5\r\nPSYNC\r\n$1\r\n?\r\n$2\r\n-1\r\n")
+        .await
+        .unwrap();
+    // More replicas than there are, so it waits for as long as the connection is open
+    blocked
+        .write_all(b"*3\r\n$4\r\nWAIT\r\n$1\r\n5\r\n$1\r\n0\r\n")
+        .await
+        .unwrap();
+
+    tokio::time::sleep(Duration::from_millis(2500)).await;
+    assert!(!is_open(&mut idle).await);
+    assert!(is_open(&mut blocked).await);
+    assert!(is_open(&mut replica).await);
+
+    // Still subscribed, so it's sent what's published
+    let mut publisher = TcpStream::connect(server.addr()).await.unwrap();
+    assert_eq!(
+        command(&mut publisher, &["PUBLISH", "news", "hello"]).await,
+        ":1\r\n"
+    );
+    let message = "*3\r\n$7\r\nmessage\r\n$4\r\nnews\r\n$5\r\nhello\r\n";
+    assert_eq!(read_exactly(&mut subscriber, message.len()).await, message);
+}
+
+#[tokio::test]
+async fn test_shutdown_save() {
+    // Snapshots go in dir, which the server moves the whole process into
+    let dir = std::env::temp_dir().join(format!("redis-rs-test-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+
+    // NOSAVE skips the save points, SAVE saves without any
+    for (option, save, saved) in [("NOSAVE", "3600 1", false), ("SAVE", "", true)] {
+        let dbfilename = format!("shutdown-{}.rdb", option.to_ascii_lowercase());
+        let server = Server::bind("127.0.0.1:0")
+            .config("dir", dir.to_str().unwrap())
+            .config("dbfilename", &dbfilename)
+            .config("save", save)
+            .spawn()
+            .await
+            .unwrap();
+        let mut stream = TcpStream::connect(server.addr()).await.unwrap();
+        command(&mut stream, &["SET", "key", "value"]).await;
+
+        assert_eq!(
+            command(&mut stream, &["SHUTDOWN", option]).await,
+            "$2\r\nOK\r\n"
+        );
+        server.wait().await.unwrap();
+        let path = dir.join(&dbfilename);
+        assert_eq!(path.exists(), saved, "SHUTDOWN {option}");
+        let _ = std::fs::remove_file(path);
+    }
+}
+
+#[tokio::test]
+async fn test_shutdown_drain_timeout() {
+    let server = Server::bind("127.0.0.1:0")
+        .config("shutdown-drain-timeout", "1")
+        .spawn()
+        .await
+        .unwrap();
+    let mut stream = TcpStream::connect(server.addr()).await.unwrap();
+    let value = "x".repeat(1024 * 1024);
+    command(&mut stream, &["SET", "big", &value]).await;
+
+    // Far more in replies than the socket's buffers hold, none of it read
+    let gets = b"*2\r\n$3\r\nGET\r\n$3\r\nbig\r\n".repeat(64);
+    stream.write_all(&gets).await.unwrap();
+    tokio::time::sleep(Duration::from_millis(200)).await;
+
+    // The replies that can't be sent are dropped once the drain timeout is up
+    tokio::time::timeout(Duration::from_secs(5), server.shutdown())
+        .await
+        .expect("shutdown waited for the client")
+        .unwrap();
+    let mut reply = Vec::new();
+    let _ = stream.read_to_end(&mut reply).await;
+    assert!(reply.len() < 64 * value.len());
+}
+
+#[cfg(feature = "io-uring")]
+#[tokio::test]
+async fn test_io_uring() {
+    // Each io_uring thread binds for itself, so it needs a port picked beforehand
+    let port = std::net::TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port();
+    let server = Server::bind(("127.0.0.1", port))
+        .config("io-uring", "yes")
+        .config("io-threads", "2")
+        .spawn()
+        .await
+        .unwrap();
+
+    let mut stream = TcpStream::connect(server.addr()).await.unwrap();
+    assert_eq!(
+        command(&mut stream, &["SET", "key", "value"]).await,
+        "$2\r\nOK\r\n"
+    );
+    stream
+        .write_all(b"*2\r\n$3\r\nGET\r\n$3\r\nkey\r\n*1\r\n$4\r\nPING\r\n")
+        .await
+        .unwrap();
+    let replies = "$5\r\nvalue\r\n$4\r\nPONG\r\n";
+    assert_eq!(read_exactly(&mut stream, replies.len()).await, replies);
+
+    // Large replies take more than one write
+    let value = "x".repeat(1024 * 1024);
+    command(&mut stream, &["SET", "big", &value]).await;
+    stream
+        .write_all(b"*2\r\n$3\r\nGET\r\n$3\r\nbig\r\n")
+        .await
+        .unwrap();
+    let reply = format!("${}\r\n{value}\r\n", value.len());
+    assert_eq!(read_exactly(&mut stream, reply.len()).await, reply);
+
+    server.shutdown().await.unwrap();
+    let mut rest = Vec::new();
+    stream.read_to_end(&mut rest).await.unwrap();
+    assert!(rest.is_empty());
+}